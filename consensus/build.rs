@@ -1,4 +1,10 @@
+use std::{env, path::PathBuf};
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-	tonic_build::compile_protos("../proto/streams.proto")?;
+	// The descriptors of the services, served through gRPC reflection
+	let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+	tonic_build::configure()
+		.file_descriptor_set_path(out_dir.join("validated_streams_descriptor.bin"))
+		.compile(&["../proto/streams.proto"], &["../proto"])?;
 	Ok(())
 }
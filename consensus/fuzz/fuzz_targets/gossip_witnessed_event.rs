@@ -0,0 +1,13 @@
+//! Feeds arbitrary bytes through the gossip ingress path, the way a message of another peer would
+//! be: decoding followed by verification against the current authorities.
+#![no_main]
+
+use consensus_validated_streams::{events::AuthoritiesList, test_utils::TestValidators};
+use libfuzzer_sys::fuzz_target;
+use once_cell::sync::Lazy;
+
+static AUTHORITIES: Lazy<AuthoritiesList> = Lazy::new(|| TestValidators::new(4).authorities());
+
+fuzz_target!(|message: &[u8]| {
+	let _ = AUTHORITIES.decode_witnessed_event(message);
+});
@@ -0,0 +1,39 @@
+//! Feeds well-formed messages with arbitrary keys and signatures through the gossip ingress path,
+//! reaching the signature parsing and verification which random bytes rarely get past decoding to.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use consensus_validated_streams::{proofs::WitnessedEvent, test_utils::TestValidators};
+use libfuzzer_sys::fuzz_target;
+use once_cell::sync::Lazy;
+use sp_core::{sr25519, H256};
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+
+static VALIDATORS: Lazy<TestValidators> = Lazy::new(|| TestValidators::new(4));
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+	/// Which validator to claim to be, unless `key` is set
+	validator: u8,
+	/// A raw sr25519 key to use instead of a validator's
+	key: Option<Vec<u8>>,
+	signature: Vec<u8>,
+	event_id: [u8; 32],
+	session: u32,
+}
+
+fuzz_target!(|input: Input| {
+	let pub_key = match input.key {
+		Some(key) => CryptoTypePublicPair(sr25519::CRYPTO_ID, key),
+		None => VALIDATORS.pub_key(input.validator as usize % VALIDATORS.len()),
+	};
+	let witnessed_event = WitnessedEvent {
+		signature: input.signature,
+		pub_key,
+		event_id: H256(input.event_id),
+		session: input.session,
+	};
+	if let Ok(message) = witnessed_event.to_bytes() {
+		let _ = VALIDATORS.authorities().decode_witnessed_event(&message);
+	}
+});
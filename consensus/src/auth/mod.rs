@@ -0,0 +1,202 @@
+//! Authentication of the trusted clients of the gRPC server, for nodes started with
+//! `--grpc-api-key` or `--grpc-api-keys-file`. Every request to the `Streams` and `Admin` services
+//! must then carry one of the keys as a bearer token, in an `authorization: Bearer <key>` header,
+//! or is refused with `UNAUTHENTICATED` before it is handled; without keys, any client which can
+//! reach the server is served, except that the `Admin` service only serves clients connecting from
+//! a loopback address, refusing the others with `PERMISSION_DENIED`. Giving each client a key of
+//! its own lets one of them be revoked without the others. Only the blake2-256 hashes of the keys are kept, and the presented tokens are hashed
+//! likewise before being looked up, so that the time the check takes tells nothing of the keys.
+//!
+//! For consortium deployments, where each member runs clients of its own, nodes started with
+//! `--grpc-tls-client-ca` can also allow only some of the certificates signed by the CA, listed
+//! with `--grpc-tls-client-allow` by their SHA-256 fingerprints: the requests of the other clients
+//! are refused with `PERMISSION_DENIED` by the [ClientCertificateInterceptor], before any service
+//! handles them.
+
+use crate::{errors::StartupError, logging::GRPC};
+use sp_core::hashing::{blake2_256, sha2_256};
+use std::{collections::HashSet, path::Path, sync::Arc};
+use tonic::{service::Interceptor, Request, Status};
+
+#[cfg(test)]
+pub mod tests;
+
+/// The metadata key the bearer token is read from.
+pub const AUTHORIZATION: &str = "authorization";
+
+/// The keys the trusted clients authenticate with. Cloning it gives another handle to the same
+/// keys.
+#[derive(Clone, Default)]
+pub struct ApiKeys {
+	hashes: Arc<HashSet<[u8; 32]>>,
+}
+
+impl ApiKeys {
+	/// The given keys, along with those of the file, if any: one key per line, ignoring blank
+	/// lines and those starting with `#`.
+	pub fn load(keys: &[String], file: Option<&Path>) -> Result<Self, StartupError> {
+		let contents = match file {
+			Some(file) => std::fs::read_to_string(file)
+				.map_err(|e| StartupError::ApiKeys(format!("{}: {e}", file.display())))?,
+			None => String::new(),
+		};
+		let lines = contents.lines().map(str::trim);
+		let file_keys = lines.filter(|line| !line.is_empty() && !line.starts_with('#'));
+		Ok(Self::new(keys.iter().map(String::as_str).chain(file_keys)))
+	}
+
+	/// The given keys.
+	pub fn new<'a>(keys: impl IntoIterator<Item = &'a str>) -> Self {
+		let hashes = keys.into_iter().map(|key| blake2_256(key.as_bytes())).collect();
+		Self { hashes: Arc::new(hashes) }
+	}
+
+	/// Whether clients need to authenticate at all.
+	pub fn is_enabled(&self) -> bool {
+		!self.hashes.is_empty()
+	}
+
+	/// Whether the key is one of the keys.
+	pub fn contains(&self, key: &str) -> bool {
+		self.hashes.contains(&blake2_256(key.as_bytes()))
+	}
+}
+
+/// Refuses the requests which do not carry one of the [ApiKeys], if there are any.
+#[derive(Clone)]
+pub struct ApiKeyInterceptor {
+	keys: ApiKeys,
+}
+
+impl ApiKeyInterceptor {
+	/// Creates a new interceptor checking requests against the keys.
+	pub fn new(keys: ApiKeys) -> Self {
+		Self { keys }
+	}
+}
+
+impl Interceptor for ApiKeyInterceptor {
+	fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+		if !self.keys.is_enabled() {
+			return Ok(request)
+		}
+		let authorization = request.metadata().get(AUTHORIZATION).map(|value| value.to_str());
+		let key = match authorization {
+			Some(Ok(value)) => value.strip_prefix("Bearer "),
+			_ => None,
+		};
+		match key {
+			Some(key) if self.keys.contains(key.trim()) => Ok(request),
+			Some(_) => {
+				tracing::debug!(target: GRPC, "Refused a request with an unknown API key");
+				Err(Status::unauthenticated("unknown API key"))
+			},
+			None => {
+				tracing::debug!(target: GRPC, "Refused a request without an API key");
+				Err(Status::unauthenticated("missing an `authorization: Bearer <key>` header"))
+			},
+		}
+	}
+}
+
+/// Refuses the requests to the `Admin` service as [ApiKeyInterceptor] does if there are [ApiKeys],
+/// and those of clients not connecting from a loopback address otherwise, so that a node without
+/// keys can only be administered from its own host.
+#[derive(Clone)]
+pub struct AdminInterceptor {
+	keys: ApiKeyInterceptor,
+}
+
+impl AdminInterceptor {
+	/// Creates a new interceptor checking requests against the keys, if there are any.
+	pub fn new(keys: ApiKeys) -> Self {
+		Self { keys: ApiKeyInterceptor::new(keys) }
+	}
+}
+
+impl Interceptor for AdminInterceptor {
+	fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+		if self.keys.keys.is_enabled() {
+			return self.keys.call(request)
+		}
+		match request.remote_addr() {
+			Some(address) if address.ip().is_loopback() => Ok(request),
+			address => {
+				tracing::debug!(target: GRPC, ?address, "Refused a remote admin request");
+				Err(Status::permission_denied(
+					"the admin service is only served to remote clients with --grpc-api-key",
+				))
+			},
+		}
+	}
+}
+
+/// The client certificates allowed to call the gRPC server, by their SHA-256 fingerprints. Cloning
+/// it gives another handle to the same fingerprints.
+#[derive(Clone, Default)]
+pub struct ClientCertificates {
+	fingerprints: Arc<HashSet<[u8; 32]>>,
+}
+
+impl ClientCertificates {
+	/// The certificates of the given fingerprints, in hex, as `openssl x509 -fingerprint -sha256`
+	/// prints them, with or without the colons.
+	pub fn parse(fingerprints: &[String]) -> Result<Self, StartupError> {
+		let parse = |fingerprint: &String| {
+			let digits = fingerprint.trim().replace(':', "");
+			let mut bytes = [0; 32];
+			hex::decode_to_slice(&digits, &mut bytes).map(|()| bytes).map_err(|e| {
+				let reason = format!("invalid client certificate fingerprint {fingerprint}: {e}");
+				StartupError::Tls(reason)
+			})
+		};
+		let fingerprints = fingerprints.iter().map(parse).collect::<Result<_, _>>()?;
+		Ok(Self { fingerprints: Arc::new(fingerprints) })
+	}
+
+	/// Whether only some of the clients are allowed at all.
+	pub fn is_enabled(&self) -> bool {
+		!self.fingerprints.is_empty()
+	}
+
+	/// Whether the certificate, DER-encoded, is one of the allowed ones.
+	pub fn allows(&self, certificate: &[u8]) -> bool {
+		self.fingerprints.contains(&sha2_256(certificate))
+	}
+}
+
+/// Refuses the requests of the clients whose certificate is not one of the [ClientCertificates], if
+/// there are any, as well as those of clients presenting no certificate at all.
+#[derive(Clone)]
+pub struct ClientCertificateInterceptor {
+	certificates: ClientCertificates,
+}
+
+impl ClientCertificateInterceptor {
+	/// Creates a new interceptor checking the certificates of the clients against the allowed ones.
+	pub fn new(certificates: ClientCertificates) -> Self {
+		Self { certificates }
+	}
+}
+
+impl Interceptor for ClientCertificateInterceptor {
+	fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+		if !self.certificates.is_enabled() {
+			return Ok(request)
+		}
+		// The first certificate of the chain is that of the client itself
+		let certificates = request.peer_certs();
+		let certificate = certificates.as_ref().and_then(|certificates| certificates.first());
+		match certificate {
+			Some(certificate) if self.certificates.allows(certificate.get_ref()) => Ok(request),
+			Some(_) => {
+				tracing::debug!(target: GRPC, "Refused a client with a certificate not allowed");
+				Err(Status::permission_denied("client certificate not allowed"))
+			},
+			None => {
+				tracing::debug!(target: GRPC, "Refused a client without a certificate");
+				Err(Status::permission_denied("missing a client certificate"))
+			},
+		}
+	}
+}
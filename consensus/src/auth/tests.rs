@@ -0,0 +1,112 @@
+use super::{
+	AdminInterceptor, ApiKeyInterceptor, ApiKeys, ClientCertificateInterceptor, ClientCertificates,
+	AUTHORIZATION,
+};
+use crate::errors::StartupError;
+use sp_core::hashing::sha2_256;
+use std::net::SocketAddr;
+use tonic::{service::Interceptor, transport::server::TcpConnectInfo, Code, Request};
+
+#[test]
+fn test_keys_loaded_from_the_flags_and_the_file() {
+	let path = std::env::temp_dir().join(format!("streams-api-keys-{}", std::process::id()));
+	std::fs::write(&path, "# The indexer\n  indexer-key  \n\nauditor-key").unwrap();
+	let keys = ApiKeys::load(&["cli-key".to_string()], Some(&path));
+	std::fs::remove_file(&path).unwrap();
+	let keys = keys.unwrap();
+	assert!(keys.is_enabled());
+	for key in ["cli-key", "indexer-key", "auditor-key"] {
+		assert!(keys.contains(key), "{key}");
+	}
+	for key in ["# The indexer", "", "indexer", "auditor-key "] {
+		assert!(!keys.contains(key), "{key}");
+	}
+
+	assert!(!ApiKeys::load(&[], None).unwrap().is_enabled());
+	match ApiKeys::load(&[], Some(&path)).err() {
+		Some(e @ StartupError::ApiKeys(_)) => {
+			assert!(e.to_string().contains(&path.display().to_string()), "{e}");
+			assert!(e.is_fatal());
+		},
+		other => panic!("expected an API keys error, got {other:?}"),
+	}
+}
+
+/// Passes a request with the given `authorization` header, if any, through the interceptor.
+fn intercept(keys: &ApiKeys, authorization: Option<&str>) -> Result<(), Code> {
+	let mut request = Request::new(());
+	if let Some(authorization) = authorization {
+		request.metadata_mut().insert(AUTHORIZATION, authorization.parse().unwrap());
+	}
+	let result = ApiKeyInterceptor::new(keys.clone()).call(request);
+	result.map(|_| ()).map_err(|status| status.code())
+}
+
+#[test]
+fn test_interceptor_requires_a_known_key() {
+	let keys = ApiKeys::new(["first", "second"]);
+	assert_eq!(intercept(&keys, Some("Bearer first")), Ok(()));
+	assert_eq!(intercept(&keys, Some("Bearer second")), Ok(()));
+	for refused in [None, Some("Bearer third"), Some("first"), Some("Basic first"), Some("Bearer ")]
+	{
+		assert_eq!(intercept(&keys, refused), Err(Code::Unauthenticated), "{refused:?}");
+	}
+
+	// Without keys, every request goes through
+	assert_eq!(intercept(&ApiKeys::default(), None), Ok(()));
+	assert_eq!(intercept(&ApiKeys::default(), Some("Bearer first")), Ok(()));
+}
+
+/// Passes a request from the given address, if any, through the admin interceptor.
+fn intercept_admin(keys: &ApiKeys, remote: Option<&str>, key: Option<&str>) -> Result<(), Code> {
+	let mut request = Request::new(());
+	let remote_addr = remote.map(|remote| remote.parse::<SocketAddr>().unwrap());
+	request.extensions_mut().insert(TcpConnectInfo { local_addr: None, remote_addr });
+	if let Some(key) = key {
+		request.metadata_mut().insert(AUTHORIZATION, format!("Bearer {key}").parse().unwrap());
+	}
+	let result = AdminInterceptor::new(keys.clone()).call(request);
+	result.map(|_| ()).map_err(|status| status.code())
+}
+
+#[test]
+fn test_admin_served_locally_without_keys() {
+	let none = ApiKeys::default();
+	assert_eq!(intercept_admin(&none, Some("127.0.0.1:5000"), None), Ok(()));
+	assert_eq!(intercept_admin(&none, Some("[::1]:5000"), None), Ok(()));
+	for remote in [Some("10.0.0.2:5000"), Some("[2001:db8::1]:5000"), None] {
+		let refused = intercept_admin(&none, remote, None);
+		assert_eq!(refused, Err(Code::PermissionDenied), "{remote:?}");
+	}
+
+	// With keys, remote clients are served as well, if they present one
+	let keys = ApiKeys::new(["first"]);
+	assert_eq!(intercept_admin(&keys, Some("10.0.0.2:5000"), Some("first")), Ok(()));
+	let refused = intercept_admin(&keys, Some("127.0.0.1:5000"), None);
+	assert_eq!(refused, Err(Code::Unauthenticated));
+}
+
+#[test]
+fn test_client_certificates_allowed_by_fingerprint() {
+	let fingerprint = hex::encode(sha2_256(b"certificate"));
+	// As `openssl x509 -fingerprint -sha256` prints it
+	let bytes = (0..32).map(|i| fingerprint[2 * i..2 * i + 2].to_uppercase());
+	let with_colons = bytes.collect::<Vec<_>>().join(":");
+	for fingerprint in [fingerprint, with_colons] {
+		let certificates = ClientCertificates::parse(&[fingerprint.clone()]).unwrap();
+		assert!(certificates.is_enabled());
+		assert!(certificates.allows(b"certificate"), "{fingerprint}");
+		assert!(!certificates.allows(b"other certificate"), "{fingerprint}");
+	}
+	for invalid in ["36:62", "not a fingerprint"] {
+		let result = ClientCertificates::parse(&[invalid.to_string()]);
+		assert!(matches!(result, Err(StartupError::Tls(_))), "{invalid}");
+	}
+
+	// Requests not made over TLS carry no certificate
+	let certificates = ClientCertificates::parse(&[hex::encode([1; 32])]).unwrap();
+	let refused = ClientCertificateInterceptor::new(certificates).call(Request::new(()));
+	assert_eq!(refused.map(|_| ()).map_err(|status| status.code()), Err(Code::PermissionDenied));
+	let mut disabled = ClientCertificateInterceptor::new(ClientCertificates::default());
+	assert!(disabled.call(Request::new(())).is_ok());
+}
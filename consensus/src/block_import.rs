@@ -2,23 +2,18 @@
 #![cfg(feature = "off-chain-proofs")]
 
 use crate::{
-	events::{verify_events_validity, AuthoritiesList},
+	events::{verify_events_validity, ValidatorSetHandle},
+	logging::PROOFS,
 	proofs::EventProofsTrait,
+	traits::ChainAccess,
 };
-use codec::Codec;
 use futures::{future::Shared, FutureExt};
-use lru::LruCache;
-use pallet_validated_streams::ValidatedStreamsApi;
 use sc_consensus::{BlockCheckParams, BlockImport, BlockImportParams, ImportResult};
 
-use sp_api::{HeaderT, ProvideRuntimeApi};
+use sp_api::HeaderT;
 use sp_consensus::{Error as ConsensusError, SyncOracle};
-use sp_consensus_aura::AuraApi;
-use sp_runtime::{app_crypto::CryptoTypePublicPair, traits::Block as BlockT};
-use std::{
-	marker::PhantomData,
-	sync::{Arc, Mutex},
-};
+use sp_runtime::traits::Block as BlockT;
+use std::{marker::PhantomData, sync::Arc};
 use tokio::sync::oneshot;
 
 /// Wrapper around a [BlockImport] which expects all events in the block to be witnessed in an
@@ -40,7 +35,7 @@ pub struct ValidatedStreamsBlockImport<
 	client: Arc<Client>,
 	event_proofs: Arc<EventProofs>,
 	sync_service: Shared<oneshot::Receiver<Arc<SyncingService>>>,
-	block_state: Arc<Mutex<LruCache<<Block as BlockT>::Hash, AuthoritiesList>>>,
+	validator_set: ValidatorSetHandle<Block>,
 	phantom: std::marker::PhantomData<(Block, AuthorityId)>,
 }
 
@@ -52,7 +47,7 @@ impl<Block: BlockT, I, Client, EventProofs, SyncingService, AuthorityId>
 		parent_block_import: I,
 		client: Arc<Client>,
 		event_proofs: Arc<EventProofs>,
-		block_state: Arc<Mutex<LruCache<<Block as BlockT>::Hash, AuthoritiesList>>>,
+		validator_set: ValidatorSetHandle<Block>,
 	) -> (Self, impl FnOnce(Arc<SyncingService>)) {
 		let (sync_service_sender, sync_service_receiver) = oneshot::channel();
 
@@ -62,7 +57,7 @@ impl<Block: BlockT, I, Client, EventProofs, SyncingService, AuthorityId>
 				client,
 				event_proofs,
 				sync_service: sync_service_receiver.shared(),
-				block_state,
+				validator_set,
 				phantom: PhantomData,
 			},
 			move |sync_service| {
@@ -81,7 +76,7 @@ impl<Block: BlockT, I: Clone, Client, EventProofs, SyncingService, AuthorityId>
 			client: self.client.clone(),
 			event_proofs: self.event_proofs.clone(),
 			sync_service: self.sync_service.clone(),
-			block_state: self.block_state.clone(),
+			validator_set: self.validator_set.clone(),
 			phantom: PhantomData,
 		}
 	}
@@ -92,14 +87,11 @@ impl<
 		Block: BlockT,
 		I: BlockImport<Block, Error = ConsensusError> + Send + Sync,
 		EventProofs: EventProofsTrait + Send + Sync,
-		Client: ProvideRuntimeApi<Block> + Send + Sync + 'static,
+		Client: ChainAccess<Block, AuthorityId>,
 		SyncingService: SyncOracle + Send + Sync,
-		AuthorityId: Codec + Send + Sync + 'static,
+		AuthorityId: Send + Sync + 'static,
 	> BlockImport<Block>
 	for ValidatedStreamsBlockImport<Block, I, Client, EventProofs, SyncingService, AuthorityId>
-where
-	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
-	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
 {
 	type Error = ConsensusError;
 	type Transaction = I::Transaction;
@@ -117,7 +109,7 @@ where
 	) -> Result<ImportResult, Self::Error> {
 		let sync_service = self.sync_service.clone().await.unwrap();
 		if sync_service.is_major_syncing() {
-			log::info!("🔁 Node is Syncing");
+			tracing::info!(target: PROOFS, "🔁 Node is Syncing");
 			return self.parent_block_import.import_block(block).await
 		}
 
@@ -125,12 +117,11 @@ where
 			let parent_block_id = *block.header.parent_hash();
 			let extrinsic_ids = self
 				.client
-				.runtime_api()
-				.get_extrinsic_ids(parent_block_id, block_extrinsics)
+				.extrinsic_ids(parent_block_id, block_extrinsics)
 				.ok()
 				.unwrap_or_default();
 			match verify_events_validity(
-				self.block_state.clone(),
+				&self.validator_set,
 				self.client.clone(),
 				parent_block_id,
 				self.event_proofs.clone(),
@@ -138,7 +129,17 @@ where
 			) {
 				Ok(unwitnessed_ids) =>
 					if !unwitnessed_ids.is_empty() {
-						log::info!(
+						for event_id in &unwitnessed_ids {
+							tracing::debug!(
+								target: PROOFS,
+								event_id = %event_id,
+								block = %block.post_hash(),
+								"Event of block has not been witnessed"
+							);
+						}
+						tracing::info!(
+							target: PROOFS,
+							block = %block.post_hash(),
 							"❌ Block rejeceted containing {} unwitnessed events",
 							unwitnessed_ids.len()
 						);
@@ -146,14 +147,20 @@ where
 							"Block contains unwitnessed events".to_string(),
 						))
 					} else if !extrinsic_ids.is_empty() {
-						log::info!(
-							"👌 block {} contains {} events, All have been witnessed",
-							block.post_hash(),
+						tracing::info!(
+							target: PROOFS,
+							block = %block.post_hash(),
+							"👌 block contains {} events, All have been witnessed",
 							extrinsic_ids.len()
 						);
 					},
 				Err(e) => {
-					log::error!("the following Error happened while verifying block events in the event_proofs:{}",e);
+					tracing::error!(
+						target: PROOFS,
+						block = %block.post_hash(),
+						error = %e,
+						"Failed verifying the events of block against the event proofs"
+					);
 					return Err(ConsensusError::ClientImport(e.to_string()))
 				},
 			}
@@ -1,8 +1,20 @@
 //! Configurations needed by the Validated Streams node
 
-use libp2p::{core::multiaddr::Protocol, Multiaddr};
+use libp2p::{core::multiaddr::Protocol, Multiaddr, PeerId};
 
-use std::{fmt, net::SocketAddr, str::FromStr};
+use crate::{
+	events::DEFAULT_LIVENESS_INTERVAL,
+	gossip::{
+		bans::{DEFAULT_BAN_DURATION, DEFAULT_BAN_THRESHOLD, DEFAULT_FLOOD_LIMIT},
+		Backpressure, GossipBackend, GossipCompression, OrderQueueConfig,
+		DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_HISTORY_LENGTH, DEFAULT_MAX_CONNECTIONS_PER_PEER,
+		DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_MESH_N, DEFAULT_TRANSPORT_TIMEOUT,
+	},
+	payloads::DEFAULT_MAX_PAYLOAD_SIZE,
+	streams::StreamId,
+	tunables::WitnessMode,
+};
+use std::{fmt, net::SocketAddr, num::NonZeroUsize, path::PathBuf, str::FromStr};
 
 /// Network configuration for the Validated Streams node
 /// Currently this is a type alias to [ValidatedStreamsNetworkParams], but would be changed to its
@@ -12,13 +24,84 @@ pub type ValidatedStreamsNetworkConfiguration = ValidatedStreamsNetworkParams;
 /// Command-line parameters for the network configuration of the Validated Streams node
 #[derive(Debug, Clone, clap::Args)]
 pub struct ValidatedStreamsNetworkParams {
-	/// Address to listen to GRPC calls from Validated Streams trusted clients
-	/// Do not expose to external machines or public-facing addresses as doing so is extremely
-	/// insecure and would result in anyone being able to trick this node into witnessing arbitrary
-	/// events.
+	/// Address to listen to GRPC calls from Validated Streams trusted clients. Repeat the flag to
+	/// listen on several addresses, e.g. on both IPv4 and IPv6 loopbacks.
+	/// Do not expose to external machines or public-facing addresses without authenticating the
+	/// clients (see `--grpc-tls-client-ca`), as doing so is extremely insecure and would result in
+	/// anyone being able to trick this node into witnessing arbitrary events.
 	#[clap(long, default_value = "127.0.0.1:6000")]
 	pub grpc_addr: Vec<SocketAddr>,
 
+	/// A PEM file of the certificate chain the GRPC server presents, to serve over TLS rather than
+	/// plaintext, e.g. when the trusted client is not on the same private network. Requires
+	/// `--grpc-tls-key`.
+	#[clap(long, requires = "grpc_tls_key")]
+	pub grpc_tls_cert: Option<PathBuf>,
+
+	/// A PEM file of the private key of the `--grpc-tls-cert` certificate.
+	#[clap(long, requires = "grpc_tls_cert")]
+	pub grpc_tls_key: Option<PathBuf>,
+
+	/// A PEM file of the CA certificates the GRPC server accepts client certificates from. When
+	/// set, only clients presenting a certificate signed by one of them can connect; otherwise TLS
+	/// only encrypts the channel, and does not keep anyone from witnessing events through it.
+	/// Requires `--grpc-tls-cert`.
+	#[clap(long, requires = "grpc_tls_cert")]
+	pub grpc_tls_client_ca: Option<PathBuf>,
+
+	/// The SHA-256 fingerprint of a certificate signed by the `--grpc-tls-client-ca` CAs which may
+	/// call the GRPC server, as `openssl x509 -noout -fingerprint -sha256` prints it. Repeat the
+	/// flag to allow several clients. With any, the other clients are refused with
+	/// `PERMISSION_DENIED` before their requests are handled, even with a certificate of the CA.
+	/// Requires `--grpc-tls-client-ca`.
+	#[clap(long, requires = "grpc_tls_client_ca")]
+	pub grpc_tls_client_allow: Vec<String>,
+
+	/// A key the trusted clients must present, as an `authorization: Bearer <key>` header, to be
+	/// served by the GRPC server. Repeat the flag to give each client a key of its own. Without
+	/// any key, every client is served. Prefer `--grpc-api-keys-file`, since the keys passed on
+	/// the command line are visible to the other users of the machine.
+	#[clap(long)]
+	pub grpc_api_key: Vec<String>,
+
+	/// A file of keys the trusted clients must present, one per line, in addition to those of
+	/// `--grpc-api-key`. Blank lines and lines starting with `#` are ignored.
+	#[clap(long)]
+	pub grpc_api_keys_file: Option<PathBuf>,
+
+	/// An origin (e.g. `https://dashboard.example.com`) whose pages may call the streams service
+	/// of the GRPC server from a browser, over gRPC-web. Repeat the flag for several origins, or
+	/// pass `*` for any. Without any, gRPC-web is not served. The API keys apply alike.
+	#[clap(long)]
+	pub grpc_web_origin: Vec<String>,
+
+	/// The requests to the streams service each trusted client may make per second, told apart by
+	/// their API key, or by their IP address without one. Those making more are refused with
+	/// `RESOURCE_EXHAUSTED`. 0 for no limit.
+	#[clap(long, default_value_t = 0)]
+	pub grpc_client_rate_limit: u32,
+
+	/// The connections the GRPC server keeps open at once on each address; further clients wait
+	/// for one of them to close. 0 for no limit.
+	#[clap(long, default_value_t = 0)]
+	pub grpc_max_connections: usize,
+
+	/// How often the GRPC server pings its clients over HTTP/2, in seconds, so that the NATs and
+	/// load balancers in between keep the connections of long-lived streaming clients open. 0 for
+	/// no pings.
+	#[clap(long, default_value_t = 0)]
+	pub grpc_keepalive_interval: u64,
+
+	/// How long the GRPC server waits for a client to answer a ping, in seconds, before closing
+	/// its connection.
+	#[clap(long, default_value_t = 20)]
+	pub grpc_keepalive_timeout: u64,
+
+	/// How long the GRPC server keeps a connection open, in seconds, before closing it along with
+	/// the calls still open on it, so that clients reconnect. 0 for no limit.
+	#[clap(long, default_value_t = 0)]
+	pub grpc_max_connection_age: u64,
+
 	/// Port used for libp2p gossipsub by the Validated Streams consensus. The same addresses will
 	/// be used as those passed to the Substrate network (--listen-addr, --bootnodes) Can be either
 	/// a fixed port value (a number) or an offset from the default Substrate post (a sign-prefixed
@@ -26,9 +109,220 @@ pub struct ValidatedStreamsNetworkParams {
 	#[clap(long, default_value_t = PortOrOffset::Offset(10))]
 	pub gossip_port: PortOrOffset,
 
-	/// Override for the bootnodes used for gossiping by the Validated Streams consensus.
+	/// An address for the gossip to listen on, in place of those of the Substrate network with
+	/// `--gossip-port` applied, e.g. `/ip4/0.0.0.0/tcp/30343` or `/ip6/::/tcp/30344/ws` for
+	/// WebSocket peers. Repeat the flag to listen on several addresses, e.g. transports or
+	/// interfaces, such as both IPv4 and IPv6 ones on dual-stack hosts; the gossip runs as long as
+	/// it listens on one of them.
+	#[clap(long, visible_alias = "streams-listen-addr")]
+	pub gossip_listen_addr: Vec<Multiaddr>,
+
+	/// What the gossip runs on: `swarm`, a libp2p swarm of its own on `--gossip-port`, or
+	/// `network`, the `/validated-streams/1` notification protocol of the Substrate network of the
+	/// node, sharing its connections and peers. The transport and discovery settings of the gossip
+	/// only apply to `swarm`.
+	#[clap(long, default_value_t = GossipBackend::Swarm)]
+	pub gossip_backend: GossipBackend,
+
+	/// Override for the bootnodes used for gossiping by the Validated Streams consensus, in place
+	/// of the peers listed in the chain spec, or else the Substrate bootnodes.
 	#[clap(long)]
 	pub gossip_bootnodes: Vec<Multiaddr>,
+
+	/// How the witnesses the node gossips are compressed: `none`, `snappy` or `zstd`. Witnesses
+	/// received are decompressed whatever the setting; the nodes of a network should only compress
+	/// theirs once all of them run a version which decompresses them.
+	#[clap(long, default_value_t = GossipCompression::None)]
+	pub gossip_compression: GossipCompression,
+
+	/// How often the gossip looks up more peers, in seconds, through a Kademlia DHT of its own,
+	/// dialing the validators it learns of, so that the gossip bootnodes need only be a few of
+	/// them. 0 to only connect to the bootnodes and the peers dialing the node.
+	#[clap(long, default_value_t = 30)]
+	pub gossip_discovery_interval: u64,
+
+	/// Discover the other validators on the local network through mDNS, dialing them as they are
+	/// found, so that development networks on a single LAN or docker network need no bootnodes.
+	#[clap(long)]
+	pub gossip_mdns: bool,
+
+	/// How long dialing a gossip peer, and the noise and yamux handshakes of a new connection,
+	/// may take, in seconds, before the connection is given up on.
+	#[clap(long, default_value_t = DEFAULT_TRANSPORT_TIMEOUT.as_secs())]
+	pub gossip_transport_timeout: u64,
+
+	/// The connections the gossip keeps established with peers which dialed the node. 0 for no
+	/// limit.
+	#[clap(long, default_value_t = 0)]
+	pub gossip_max_incoming_connections: u32,
+
+	/// The connections the gossip keeps established with a single peer. 0 for no limit.
+	#[clap(long, default_value_t = DEFAULT_MAX_CONNECTIONS_PER_PEER)]
+	pub gossip_max_connections_per_peer: u32,
+
+	/// Let gossip peers connect over QUIC as well, listening on the UDP port of each TCP gossip
+	/// address, e.g. on `/ip4/0.0.0.0/udp/10000/quic` along with `/ip4/0.0.0.0/tcp/10000`. QUIC
+	/// connections are set up faster, and do not hold back every witness behind a lost packet.
+	#[clap(long)]
+	pub gossip_quic: bool,
+
+	/// How often gossipsub maintains its mesh and tells peers of the witnesses it saw, in
+	/// milliseconds. Shorter intervals repair the mesh and recover lost witnesses sooner.
+	#[clap(long, default_value_t = DEFAULT_HEARTBEAT_INTERVAL.as_millis() as u64)]
+	pub gossip_heartbeat_interval: u64,
+
+	/// How many peers gossipsub keeps in the mesh of each topic. Small validator sets can do with
+	/// fewer than the default, so that each witness is sent fewer times.
+	#[clap(long, default_value_t = DEFAULT_MESH_N)]
+	pub gossip_mesh_n: usize,
+
+	/// How many heartbeats gossipsub keeps the witnesses it saw for, to send them to the peers
+	/// that missed them.
+	#[clap(long, default_value_t = DEFAULT_HISTORY_LENGTH)]
+	pub gossip_history_length: usize,
+
+	/// Whether gossipsub sends the witnesses of the node to every connected peer subscribed to
+	/// their topic, rather than to the peers of its mesh only, so that they reach every validator
+	/// connected to in one hop, as suits small validator sets. `false` leaves them to the mesh,
+	/// sending each witness fewer times in large validator sets.
+	#[clap(long, default_value_t = true, action = clap::ArgAction::Set)]
+	pub gossip_flood_publish: bool,
+
+	/// The largest message gossipsub sends or accepts, in bytes.
+	#[clap(long, default_value_t = DEFAULT_MAX_MESSAGE_SIZE)]
+	pub gossip_max_message_size: usize,
+
+	/// Only gossip with the peers proving to be validators of the current set, by signing their
+	/// gossip peer ID with their validator key, and with those of `--gossip-allowlist`. The
+	/// witnesses of other peers are ignored, and they are disconnected if they fail to prove it
+	/// within 10 seconds. Needs the `swarm` gossip backend.
+	#[clap(long)]
+	pub gossip_validators_only: bool,
+
+	/// The gossip peer IDs admitted by `--gossip-validators-only` without proving to be
+	/// validators, e.g. those of observer nodes.
+	#[clap(long, requires = "gossip_validators_only")]
+	pub gossip_allowlist: Vec<PeerId>,
+
+	/// How many protocol violations of a gossip peer within a minute get it banned: witnesses
+	/// which fail to decode or verify, and messages past `--gossip-flood-limit`. 0 to never ban
+	/// peers.
+	#[clap(long, default_value_t = DEFAULT_BAN_THRESHOLD)]
+	pub gossip_ban_threshold: u32,
+
+	/// How long a banned gossip peer stays disconnected, in seconds.
+	#[clap(long, default_value_t = DEFAULT_BAN_DURATION.as_secs())]
+	pub gossip_ban_duration: u64,
+
+	/// How many messages per second a gossip peer may send; each further one is a violation. 0
+	/// for no limit.
+	#[clap(long, default_value_t = DEFAULT_FLOOD_LIMIT)]
+	pub gossip_flood_limit: u32,
+
+	/// How many gossip peers the node waits for before witnessing events, so that their witnesses
+	/// reach someone; only admitted ones count with `--gossip-validators-only`.
+	#[clap(long, default_value_t = 0)]
+	pub gossip_min_peers: usize,
+
+	/// How many orders, such as witnesses to publish, are queued for the gossip at most, so that
+	/// a stalled gossip does not grow the queue without bound.
+	#[clap(long, default_value_t = OrderQueueConfig::default().capacity)]
+	pub gossip_order_capacity: NonZeroUsize,
+
+	/// What publishing a witness does while the gossip order queue is full: `block` waits for
+	/// room, `drop-oldest` drops the oldest witness queued, failing its publication, and `fail`
+	/// fails the publication right away, answering the client with `UNAVAILABLE`.
+	#[clap(long, default_value_t = Backpressure::Block)]
+	pub gossip_backpressure: Backpressure,
+
+	/// How often a validator gossips a liveness beat, in seconds, telling the other nodes that it
+	/// is online and how many events it witnessed; validators silent for three intervals are
+	/// reported offline. 0 to neither send nor follow the beats.
+	#[clap(long, default_value_t = DEFAULT_LIVENESS_INTERVAL.as_secs())]
+	pub gossip_liveness_interval: u64,
+
+	/// Probe whether the node is publicly reachable through its gossip peers with AutoNAT, and
+	/// answer their probes; confirmed addresses are advertised to the peers.
+	#[clap(long)]
+	pub gossip_autonat: bool,
+
+	/// A circuit relay to listen through, for a node behind a NAT to be reachable by its gossip
+	/// peers, e.g. `/ip4/198.51.100.1/tcp/4001/p2p/<relay peer id>`. Can be given several times.
+	#[clap(long = "gossip-relay")]
+	pub gossip_relays: Vec<Multiaddr>,
+
+	/// An address the node is reachable at from outside its network, e.g. that of a port
+	/// forwarded by its NAT, advertised to its gossip peers. Can be given several times.
+	#[clap(long = "gossip-external-address")]
+	pub gossip_external_addresses: Vec<Multiaddr>,
+
+	/// A file holding the ed25519 secret key the gossip identifies the node with, 32 bytes raw or
+	/// in hex, as written by `subkey generate-node-key`, so that its gossip peer ID is known ahead,
+	/// e.g. for firewall rules. Derived from the node key otherwise.
+	#[clap(long, conflicts_with = "streams_node_key_seed")]
+	pub streams_node_key_file: Option<PathBuf>,
+
+	/// A seed the gossip key of the node is derived from, so that the same seed always gives the
+	/// same gossip peer ID, as in reproducible test networks. Only for testing: anyone knowing the
+	/// seed can impersonate the node.
+	#[clap(long)]
+	pub streams_node_key_seed: Option<String>,
+
+	/// Export OpenTelemetry traces of the witnessing pipeline to the OTLP (gRPC) collector at this
+	/// endpoint, e.g. `http://localhost:4317`. Nothing is traced when not set.
+	#[clap(long)]
+	pub otlp_endpoint: Option<String>,
+
+	/// Keep a node started with `--validator` running even if its keystore holds no key of the
+	/// validator set, e.g. while bootstrapping a network whose keys get inserted later. Such a node
+	/// does not witness any events until it has a key.
+	#[clap(long)]
+	pub streams_allow_missing_key: bool,
+
+	/// Run the tasks of the Validated Streams subsystem (the gossip and the handling of witnesses,
+	/// and the gRPC server) on a tokio runtime of their own, with this many worker threads, rather
+	/// than on the executor of the node. Keeps bursts of gossip from delaying block authorship on
+	/// busy validators.
+	#[clap(long)]
+	pub streams_runtime_threads: Option<NonZeroUsize>,
+
+	/// Whether the events submitted by the trusted client are witnessed (`active`), or refused, as
+	/// during maintenance (`paused`). Tunable at runtime.
+	#[clap(long, default_value_t = WitnessMode::Active)]
+	pub streams_witness_mode: WitnessMode,
+
+	/// How many witnessing requests of the trusted client are accepted per second; those past the
+	/// limit are refused. 0 for no limit. Tunable at runtime.
+	#[clap(long, default_value_t = 0)]
+	pub streams_witness_rate_limit: u32,
+
+	/// A file of `name = value` lines setting the parameters tunable at runtime, named after their
+	/// flags, e.g. `streams-witness-rate-limit = 100`. Applied at startup over the flags, and
+	/// reloaded whenever the node receives SIGHUP.
+	#[clap(long)]
+	pub streams_config: Option<PathBuf>,
+
+	/// Store the payloads the trusted client submits along with its events, checked to hash to the
+	/// event ids with blake2-256, and serve them through the `GetEventPayload` RPC. They are kept
+	/// in the database of the node apart from the proofs, and never gossiped nor put on chain.
+	#[clap(long)]
+	pub streams_event_payloads: bool,
+
+	/// The largest event payload stored or hashed, in bytes; larger ones are refused.
+	#[clap(long, default_value_t = DEFAULT_MAX_PAYLOAD_SIZE)]
+	pub streams_payload_max_size: usize,
+
+	/// How long event payloads are kept, in seconds, before they are deleted.
+	#[clap(long, default_value_t = 7 * 24 * 60 * 60)]
+	pub streams_payload_retention: u64,
+
+	/// A stream of events validated along with the default one, for another application
+	/// submitting events to the node: the trusted clients name it in the `stream_id` of their
+	/// requests, and its witnesses are gossiped on a topic of its own. Repeat the flag for several
+	/// streams; every validator must list the same ones. Up to 64 ASCII letters, digits, `-`, `_`
+	/// or `.`.
+	#[clap(long)]
+	pub streams_id: Vec<StreamId>,
 }
 
 /// A specific port number or an offset from the base port number. Used to subtly adjust an address
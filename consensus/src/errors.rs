@@ -1,6 +1,8 @@
 //! Error types for the Validated Streams library.
 
-use std::{error::Error as E, fmt};
+use pallet_validated_streams::payload::SessionIndex;
+use std::{error::Error as E, fmt, net::SocketAddr};
+use tonic::Status;
 
 /// An error which has occurred during Validated Streams operation.
 #[derive(Debug, PartialEq)]
@@ -9,14 +11,60 @@ pub enum Error {
 	LockFail(String),
 	/// The client submitted an incorrect signature
 	BadWitnessedEventSignature(String),
+	/// A witness was made in a session which is neither the current one nor within its grace
+	/// window
+	StaleSession {
+		/// The session the witness claims
+		session: SessionIndex,
+		/// The current session
+		current: SessionIndex,
+	},
 	/// We failed to serialize a message
 	SerilizationFailure(String),
+	/// A gossiped witness is of a version of the format the node does not support
+	UnsupportedVersion(u8),
 	/// We failed to sign a message
 	SigningFailure(String),
 	/// A database-related error
 	Database(String),
 	/// The current node is not a validator
 	NotAValidator,
+	/// The node is shutting down, and witnesses no more events
+	ShuttingDown,
+	/// Witnessing was paused through the tunable parameters
+	WitnessingPaused,
+	/// The client cancelled the event, which is not witnessed again
+	EventCancelled,
+	/// A received witness is a copy of one collected recently, and was dropped unverified
+	DuplicateWitness,
+	/// The gossip failed, or is not running, before a message could be published
+	GossipUnavailable(String),
+	/// The gossip had too many orders queued to publish a message
+	GossipOverloaded(String),
+	/// The payload submitted along with an event does not hash to its id
+	PayloadMismatch,
+	/// The payload submitted along with an event is larger than stored
+	PayloadTooLarge {
+		/// The size of the payload, in bytes
+		size: usize,
+		/// The largest size stored
+		max: usize,
+	},
+	/// The node does not store event payloads
+	PayloadsDisabled,
+	/// The node does not validate the events of the stream
+	UnknownStream(String),
+	/// Some blocks of the requested range are not in the event index
+	NotIndexed {
+		/// The first block of the range
+		from: u32,
+		/// The last block of the range
+		to: u32,
+		/// The first and the last indexed blocks, if any
+		indexed: Option<(u32, u32)>,
+	},
+	/// The continuation token of a listing is not one of its range
+	InvalidPageToken,
 	/// Any other error
 	Other(String),
 }
@@ -26,15 +74,161 @@ impl fmt::Display for Error {
 			Error::LockFail(r) => write!(f, "Failed locking resource {r}"),
 			Error::BadWitnessedEventSignature(source) =>
 				write!(f, "Received bad witnessed event signature from {source}"),
+			Error::StaleSession { session, current } =>
+				write!(f, "Witness of session {session} is not accepted in session {current}"),
 			Error::SerilizationFailure(reason) => write!(f, "Serialization failed due to {reason}"),
+			Error::UnsupportedVersion(version) =>
+				write!(f, "Version {version} of the witness format is not supported"),
 			Error::SigningFailure(reason) => write!(f, "Signing failed due to {reason}"),
 			Error::Database(reason) => write!(f, "Database error, {reason}"),
 			Error::NotAValidator => write!(f, "Not a validator"),
+			Error::ShuttingDown => write!(f, "Shutting down"),
+			Error::WitnessingPaused => write!(f, "Witnessing is paused"),
+			Error::EventCancelled => write!(f, "The event was cancelled"),
+			Error::DuplicateWitness => write!(f, "The witness was already collected"),
+			Error::GossipUnavailable(reason) => write!(f, "Gossip unavailable: {reason}"),
+			Error::GossipOverloaded(reason) => write!(f, "Gossip overloaded: {reason}"),
+			Error::PayloadMismatch => write!(f, "Payload does not hash to the event id"),
+			Error::PayloadTooLarge { size, max } =>
+				write!(f, "Payload of {size} bytes is larger than the maximum of {max} bytes"),
+			Error::PayloadsDisabled => write!(f, "Event payloads are not stored by this node"),
+			Error::UnknownStream(stream_id) =>
+				write!(f, "Stream {stream_id:?} is not validated by this node"),
+			Error::NotIndexed { from, to, indexed: Some((first, last)) } => write!(
+				f,
+				"Blocks {from} to {to} are not all indexed; the index holds blocks {first} to \
+				 {last}"
+			),
+			Error::NotIndexed { from, to, indexed: None } =>
+				write!(f, "Blocks {from} to {to} are not indexed; nothing is indexed yet"),
+			Error::InvalidPageToken => write!(f, "The page token is not one of this range"),
 			Error::Other(reason) => write!(f, "{reason}"),
 		}
 	}
 }
 impl E for Error {}
+impl From<Error> for Status {
+	/// The status the gRPC server answers with when failing with the error, telling the clients
+	/// whether to fix their request, wait for the node, or give up.
+	fn from(e: Error) -> Status {
+		let message = e.to_string();
+		match e {
+			Error::BadWitnessedEventSignature(_) |
+			Error::StaleSession { .. } |
+			Error::DuplicateWitness |
+			Error::PayloadMismatch |
+			Error::PayloadTooLarge { .. } |
+			Error::InvalidPageToken => Status::invalid_argument(message),
+			Error::NotAValidator |
+			Error::WitnessingPaused |
+			Error::EventCancelled |
+			Error::PayloadsDisabled |
+			Error::UnknownStream(_) => Status::failed_precondition(message),
+			Error::ShuttingDown | Error::GossipUnavailable(_) => Status::unavailable(message),
+			Error::GossipOverloaded(_) => Status::resource_exhausted(message),
+			Error::NotIndexed { .. } => Status::out_of_range(message),
+			Error::LockFail(_) |
+			Error::SerilizationFailure(_) |
+			Error::UnsupportedVersion(_) |
+			Error::SigningFailure(_) |
+			Error::Database(_) |
+			Error::Other(_) => Status::internal(message),
+		}
+	}
+}
+
+/// An error which stops a part of the Validated Streams subsystem of a node from starting. The
+/// tasks spawned by [crate::node::start] log it, and shut the node down if it is
+/// [fatal](StartupError::is_fatal).
+#[derive(Debug, PartialEq)]
+pub enum StartupError {
+	/// We failed resolving the witnessing key of the node from its keystore
+	Keystore(String),
+	/// We failed setting up the gossip, or listening on any of its addresses
+	Gossip(String),
+	/// We failed serving the gRPC server on an address
+	Server(SocketAddr, String),
+	/// We failed reading the TLS certificates or key of the gRPC server
+	Tls(String),
+	/// We failed reading the API keys of the gRPC server
+	ApiKeys(String),
+	/// A validator's keystore holds no key of the validator set
+	MissingKey {
+		/// The type of the key looked for
+		key_type: String,
+		/// The path of the keystore, or [None] for an in-memory keystore
+		keystore_path: Option<String>,
+	},
+}
+impl StartupError {
+	/// Whether the node should shut down. A node whose keystore could not be read keeps following
+	/// the chain; the others cannot do their part in witnessing events at all.
+	pub fn is_fatal(&self) -> bool {
+		match self {
+			StartupError::Keystore(_) => false,
+			StartupError::Gossip(_) |
+			StartupError::Server(..) |
+			StartupError::Tls(_) |
+			StartupError::ApiKeys(_) |
+			StartupError::MissingKey { .. } => true,
+		}
+	}
+}
+impl fmt::Display for StartupError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			StartupError::Keystore(reason) =>
+				write!(f, "Failed resolving the witnessing key from the keystore: {reason}"),
+			StartupError::Gossip(reason) => write!(f, "Failed starting the gossip: {reason}"),
+			StartupError::Server(address, reason) =>
+				write!(f, "Failed starting the gRPC server on {address}: {reason}"),
+			StartupError::Tls(reason) =>
+				write!(f, "Failed reading the TLS configuration of the gRPC server: {reason}"),
+			StartupError::ApiKeys(reason) =>
+				write!(f, "Failed reading the API keys of the gRPC server: {reason}"),
+			StartupError::MissingKey { key_type, keystore_path } => {
+				let keystore = keystore_path.as_deref().unwrap_or("<in memory>");
+				write!(
+					f,
+					"No {key_type} key of the validator set in the keystore at {keystore}; insert \
+					 one, or pass --streams-allow-missing-key to start without witnessing events"
+				)
+			},
+		}
+	}
+}
+impl E for StartupError {}
+
+/// An error which stops a change of the [tunable parameters](crate::tunables) from being applied.
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+	/// No parameter has the given name
+	Unknown(String),
+	/// The parameter of the given name only takes effect at startup
+	NotTunable(String),
+	/// The value is not one the parameter can take
+	Invalid {
+		/// The name of the parameter
+		name: String,
+		/// The rejected value
+		value: String,
+	},
+	/// The config file could not be read, or has a malformed line
+	File(String),
+}
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ConfigError::Unknown(name) => write!(f, "Unknown parameter {name}"),
+			ConfigError::NotTunable(name) =>
+				write!(f, "Parameter {name} cannot be changed without a restart"),
+			ConfigError::Invalid { name, value } =>
+				write!(f, "Invalid value {value:?} for parameter {name}"),
+			ConfigError::File(reason) => write!(f, "Failed reading the config file: {reason}"),
+		}
+	}
+}
+impl E for ConfigError {}
 
 #[doc(hidden)] // Enable use of `?` operator.
 impl From<Box<bincode::ErrorKind>> for Error {
@@ -43,6 +237,13 @@ impl From<Box<bincode::ErrorKind>> for Error {
 	}
 }
 
+#[doc(hidden)] // Enable use of `?` operator.
+impl From<codec::Error> for Error {
+	fn from(e: codec::Error) -> Error {
+		Error::SerilizationFailure(format!("{e}"))
+	}
+}
+
 #[doc(hidden)] // Enable use of `?` operator.
 impl From<sp_keystore::Error> for Error {
 	fn from(e: sp_keystore::Error) -> Error {
@@ -1,22 +1,32 @@
 //! Service which processes all the incoming events
 
-use super::{get_latest_authorities_list, BlockStateCache};
+use super::{
+	get_latest_authorities_list, unix_millis, AuthoritiesList, LivenessBeat, ValidatorLiveness,
+	ValidatorSetHandle, LIVENESS_TOPIC,
+};
 use crate::{
 	errors::Error,
 	gossip::GossipHandler,
-	proofs::{EventProofsTrait, WitnessedEvent},
+	logging::{rate_limited, LogRateLimiter, SERVICE},
+	metrics::{Metrics, SubmissionOutcome},
+	notifications::{EventNotification, EventNotifications, EventStage},
+	proofs::{
+		EventProof, EventProofsTrait, WitnessedEvent, MAX_WITNESSED_EVENT_SIZE,
+		WITNESSED_EVENT_VERSION,
+	},
+	status::{EventStatus, EventStatuses},
+	streams::EventStreams,
+	traces::Traces,
+	traits::ChainAccess,
 };
 use async_trait::async_trait;
-use codec::Codec;
-use libp2p::gossipsub::IdentTopic;
-use pallet_validated_streams::ValidatedStreamsApi;
-use sc_client_api::{BlockchainEvents, HeaderBackend};
+use libp2p::gossipsub::{IdentTopic, MessageAcceptance};
+use lru::LruCache;
 use sc_transaction_pool_api::{
 	error::{Error as PoolError, IntoPoolError},
 	LocalTransactionPool,
 };
-use sp_api::{BlockT, ProvideRuntimeApi};
-use sp_consensus_aura::AuraApi;
+use sp_api::BlockT;
 use sp_core::{
 	sr25519::{Public, Signature},
 	ByteArray, H256,
@@ -24,18 +34,270 @@ use sp_core::{
 use sp_runtime::{
 	app_crypto::CryptoTypePublicPair, generic::BlockId, transaction_validity::InvalidTransaction,
 };
-use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+use std::{
+	collections::HashMap,
+	marker::PhantomData,
+	num::NonZeroUsize,
+	slice,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+use tracing::Instrument;
 
 /// The topic on which the [EventGossipHandler] listens.
 pub const WITNESSED_EVENTS_TOPIC: &str = "WitnessedEvent";
 
+/// How many submitted events an [EventProofsCollector] remembers, so as not to submit them again.
+const SUBMITTED_EVENTS_CAPACITY: usize = 4096;
+/// How many collected witnesses an [EventProofsCollector] remembers, so as to drop their copies.
+const SEEN_WITNESSES_CAPACITY: usize = 16384;
+/// How long an [EventProofsCollector] drops the copies of a witness it collected.
+const SEEN_WITNESS_TTL: Duration = Duration::from_secs(300);
+
+/// The witnesses collected recently, by event and validator, along with their signature: the
+/// copies of a witness, gossiped again or by several peers, are dropped before being verified and
+/// stored again. Witnesses with another signature, e.g. forged ones, are not copies, and are
+/// verified as usual.
+pub struct SeenWitnesses {
+	seen: LruCache<(H256, CryptoTypePublicPair), (Vec<u8>, Instant)>,
+	ttl: Duration,
+}
+
+impl SeenWitnesses {
+	/// Remembers up to `capacity` witnesses, each for `ttl` after it was collected.
+	pub fn new(capacity: NonZeroUsize, ttl: Duration) -> Self {
+		Self { seen: LruCache::new(capacity), ttl }
+	}
+
+	/// Whether the witness is a copy of one collected within the TTL of `now`.
+	pub fn contains(&mut self, witnessed_event: &WitnessedEvent, now: Instant) -> bool {
+		let key = (witnessed_event.event_id, witnessed_event.pub_key.clone());
+		let Some((signature, at)) = self.seen.get(&key) else { return false };
+		if now.saturating_duration_since(*at) >= self.ttl {
+			self.seen.pop(&key);
+			return false
+		}
+		*signature == witnessed_event.signature
+	}
+
+	/// Records that the witness was collected at `now`.
+	pub fn insert(&mut self, witnessed_event: &WitnessedEvent, now: Instant) {
+		let key = (witnessed_event.event_id, witnessed_event.pub_key.clone());
+		self.seen.put(key, (witnessed_event.signature.clone(), now));
+	}
+}
+
+/// The chain-independent part of the [EventGossipHandler]: stores the proofs from gossiped
+/// [crate::proofs::WitnessedEvent]-s, and decides when an event has gathered enough of them to
+/// be submitted.
+pub struct EventProofsCollector<EventProofs> {
+	event_proofs: Arc<EventProofs>,
+	submitted: Mutex<LruCache<H256, ()>>,
+	pending: Mutex<LruCache<H256, ()>>,
+	seen: Mutex<SeenWitnesses>,
+	metrics: Metrics,
+	log_limiter: LogRateLimiter,
+}
+
+impl<EventProofs: EventProofsTrait> EventProofsCollector<EventProofs> {
+	/// Creates a new EventProofsCollector
+	pub fn new(event_proofs: Arc<EventProofs>, metrics: Metrics) -> Self {
+		let capacity = NonZeroUsize::new(SUBMITTED_EVENTS_CAPACITY).expect("capacity is not zero");
+		let seen = NonZeroUsize::new(SEEN_WITNESSES_CAPACITY).expect("capacity is not zero");
+		Self {
+			event_proofs,
+			submitted: Mutex::new(LruCache::new(capacity)),
+			pending: Mutex::new(LruCache::new(capacity)),
+			seen: Mutex::new(SeenWitnesses::new(seen, SEEN_WITNESS_TTL)),
+			log_limiter: LogRateLimiter::new(metrics.clone()),
+			metrics,
+		}
+	}
+
+	/// Decodes and verifies a gossip message, adds the proof it contains to the EventProofs, and
+	/// returns the id of the event if it has now reached the target number of proofs and has not
+	/// been submitted yet. Copies of the witnesses collected recently fail as
+	/// [Error::DuplicateWitness], unverified, and witnesses of another version of the format as
+	/// [Error::UnsupportedVersion].
+	pub fn collect(
+		&self,
+		block_state: &AuthoritiesList,
+		message: &[u8],
+	) -> Result<Option<H256>, Error> {
+		self.metrics.on_witness_received();
+		let rejected = |e: Error| {
+			rate_limited!(
+				self.log_limiter,
+				"rejected_witnessed_event",
+				debug,
+				target: SERVICE,
+				error = %e,
+				"Rejected witnessed event"
+			);
+			self.metrics.on_witness_rejected();
+			e
+		};
+		let witnessed_event = match WitnessedEvent::from_bytes(message) {
+			// Not malformed, just of a release the node does not understand
+			Err(Error::UnsupportedVersion(version)) => {
+				self.metrics.on_gossip_incompatible("witness");
+				return Err(Error::UnsupportedVersion(version))
+			},
+			decoded => decoded.map_err(rejected)?,
+		};
+		if self.seen.lock()?.contains(&witnessed_event, Instant::now()) {
+			tracing::trace!(
+				target: SERVICE,
+				event_id = %witnessed_event.event_id,
+				"Dropped a copy of a collected witness"
+			);
+			self.metrics.on_witness_duplicate();
+			return Err(Error::DuplicateWitness)
+		}
+		let witnessed_event =
+			block_state.verify_witnessed_event_origin(witnessed_event).map_err(rejected)?;
+		let event_id = witnessed_event.event_id;
+		// The id is only known once the message is decoded; fill it in the enclosing span, if any
+		tracing::Span::current().record("event_id", tracing::field::display(event_id));
+		let validator = block_state.position(&witnessed_event.pub_key);
+		if let Some(validator) = validator {
+			self.metrics.on_witness_verified(event_id, validator);
+		}
+		self.check_equivocation(&witnessed_event, validator)?;
+
+		self.event_proofs.add_event_proof(&witnessed_event)?;
+
+		self.event_proofs.purge_event_stale_signatures(
+			&event_id,
+			&block_state.authorities,
+			block_state.oldest_session(),
+		)?;
+		self.seen.lock()?.insert(&witnessed_event, Instant::now());
+
+		// Counted under the lock of the pending events, so that a stale count cannot mark as
+		// pending an event which another handler has just found to have enough proofs
+		let proof_count = {
+			let mut pending = self.pending.lock()?;
+			let proof_count =
+				self.event_proofs.get_event_proof_count(&event_id, &block_state.authorities)?;
+			let changed = if proof_count < block_state.target() {
+				pending.put(event_id, ()).is_none()
+			} else {
+				pending.pop(&event_id).is_some()
+			};
+			if changed {
+				self.metrics.set_pending_events(pending.len());
+			}
+			proof_count
+		};
+
+		if proof_count < block_state.target() {
+			tracing::debug!(
+				target: SERVICE,
+				event_id = %event_id,
+				proof_count,
+				"Added a proof of the event"
+			);
+			return Ok(None)
+		}
+
+		if self.submitted.lock()?.contains(&event_id) {
+			tracing::trace!(target: SERVICE, event_id = %event_id, "Event was already submitted");
+			return Ok(None)
+		}
+
+		tracing::debug!(
+			target: SERVICE,
+			event_id = %event_id,
+			proof_count,
+			"Event was witnessed by enough validators"
+		);
+		Ok(Some(event_id))
+	}
+
+	/// Records an equivocation if the verified witness conflicts with the proof held from the same
+	/// validator for the same event, having been made in another session: an honest validator
+	/// vouches for an event in a single session at a time. Witnesses signed again in the same
+	/// session, e.g. when a client retries, do not conflict; either way, the new proof replaces
+	/// the one held, counting once.
+	fn check_equivocation(
+		&self,
+		witnessed_event: &WitnessedEvent,
+		validator: Option<usize>,
+	) -> Result<(), Error> {
+		let WitnessedEvent { event_id, pub_key, session, .. } = witnessed_event;
+		let held = self.event_proofs.get_event_proofs(event_id, slice::from_ref(pub_key))?;
+		let Some(held) = held.get(pub_key).filter(|held| held.session != *session) else {
+			return Ok(())
+		};
+		rate_limited!(
+			self.log_limiter,
+			"equivocation",
+			warn,
+			target: SERVICE,
+			event_id = %event_id,
+			validator = ?validator,
+			key = ?pub_key,
+			session,
+			held_session = held.session,
+			"Validator equivocated, witnessing the event in two sessions"
+		);
+		if let Some(validator) = validator {
+			self.metrics.on_equivocation(validator);
+		}
+		Ok(())
+	}
+
+	/// Records that the event has been submitted, so that further proofs for it do not cause it to
+	/// be submitted again.
+	pub fn mark_submitted(&self, event_id: H256) -> Result<(), Error> {
+		self.submitted.lock()?.put(event_id, ());
+		Ok(())
+	}
+
+	/// The number of proofs of the event from the given validators.
+	pub fn proof_count(
+		&self,
+		event_id: &H256,
+		validators: &[CryptoTypePublicPair],
+	) -> Result<u16, Error> {
+		self.event_proofs.get_event_proof_count(event_id, validators)
+	}
+
+	/// The witnesses of the given events held from the given validators, encoded as gossiped.
+	pub fn witnesses(
+		&self,
+		event_ids: &[H256],
+		validators: &[CryptoTypePublicPair],
+	) -> Result<Vec<Vec<u8>>, Error> {
+		let mut witnesses = Vec::new();
+		for event_id in event_ids {
+			for (pub_key, proof) in self.event_proofs.get_event_proofs(event_id, validators)? {
+				let EventProof { session, signature } = proof;
+				let witness = WitnessedEvent { signature, pub_key, event_id: *event_id, session };
+				witnesses.push(witness.to_bytes()?);
+			}
+		}
+		Ok(witnesses)
+	}
+}
+
 /// Service that handles incoming gossip, maintains the [EventProofs] storage,
 /// and submits extrinsics for proofs that we have collected the necessary signatures for.
 pub struct EventGossipHandler<TxPool, Client, EventProofs, AuthorityId, Block: BlockT> {
+	collector: EventProofsCollector<EventProofs>,
+	#[cfg(not(feature = "off-chain-proofs"))]
 	event_proofs: Arc<EventProofs>,
 	tx_pool: Arc<TxPool>,
 	client: Arc<Client>,
-	block_state: BlockStateCache<Block>,
+	validator_set: ValidatorSetHandle<Block>,
+	metrics: Metrics,
+	log_limiter: LogRateLimiter,
+	traces: Traces,
+	notifications: EventNotifications,
+	statuses: EventStatuses,
+	streams: EventStreams,
+	liveness: Option<ValidatorLiveness>,
 	phantom: PhantomData<AuthorityId>,
 }
 
@@ -43,17 +305,10 @@ impl<TxPool, Client, EventProofs, AuthorityId, Block>
 	EventGossipHandler<TxPool, Client, EventProofs, AuthorityId, Block>
 where
 	TxPool: LocalTransactionPool + LocalTransactionPool<Block = Block>,
-	Client: ProvideRuntimeApi<Block>
-		+ HeaderBackend<Block>
-		+ BlockchainEvents<Block>
-		+ Send
-		+ Sync
-		+ 'static,
-	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+	Client: ChainAccess<Block, AuthorityId>,
 	EventProofs: EventProofsTrait + Send + Sync + 'static,
-	AuthorityId: Codec + Send + Sync + 'static,
+	AuthorityId: Send + Sync + 'static,
 	Block: BlockT,
-	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
 {
 	/// The topic on which the [EventGossipHandler] listens.
 	pub const WITNESSED_EVENTS_TOPIC: &str = "WitnessedEvent";
@@ -63,51 +318,91 @@ where
 		client: Arc<Client>,
 		event_proofs: Arc<EventProofs>,
 		tx_pool: Arc<TxPool>,
-		block_state: BlockStateCache<Block>,
+		validator_set: ValidatorSetHandle<Block>,
+		metrics: Metrics,
+		traces: Traces,
 	) -> Self {
-		Self { client, event_proofs, tx_pool, phantom: PhantomData, block_state }
+		Self {
+			client,
+			#[cfg(not(feature = "off-chain-proofs"))]
+			event_proofs: event_proofs.clone(),
+			collector: EventProofsCollector::new(event_proofs, metrics.clone()),
+			tx_pool,
+			phantom: PhantomData,
+			validator_set,
+			log_limiter: LogRateLimiter::new(metrics.clone()),
+			metrics,
+			traces,
+			notifications: EventNotifications::default(),
+			statuses: EventStatuses::default(),
+			streams: EventStreams::default(),
+			liveness: None,
+		}
 	}
 
-	/// every incoming WitnessedEvent event should go through this function for processing the
-	/// message outcome, it verifies the WitnessedEvent than it tries to add it to the EventProofs,
-	/// and if its not already added it checks whether it reached the required target or not, if it
-	/// did it submits it to the transaction pool
-	async fn handle_witnessed_event(&self, witnessed_event: WitnessedEvent) -> Result<bool, Error> {
-		let block_state =
-			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
-		let witnessed_event = block_state.verify_witnessed_event_origin(witnessed_event)?;
+	/// Makes the handler notify every event it submits to the subscribers of the notifications.
+	pub fn with_notifications(mut self, notifications: EventNotifications) -> Self {
+		self.notifications = notifications;
+		self
+	}
 
-		self.event_proofs.add_event_proof(&witnessed_event)?;
+	/// Makes the handler record the events it finds witnessed enough, and those it submits, in the
+	/// given statuses.
+	pub fn with_statuses(mut self, statuses: EventStatuses) -> Self {
+		self.statuses = statuses;
+		self
+	}
 
-		self.event_proofs
-			.purge_event_stale_signatures(&witnessed_event.event_id, &block_state.authorities)?;
+	/// Makes the handler listen to the topics of the given streams, along with that of the default
+	/// one. Their witnesses are handled alike, being of
+	/// [stream event ids](crate::streams::stream_event_id).
+	pub fn with_streams(mut self, streams: EventStreams) -> Self {
+		self.streams = streams;
+		self
+	}
+
+	/// Makes the handler listen to the [LIVENESS_TOPIC] as well, and record the beats gossiped on
+	/// it in the given liveness.
+	pub fn with_liveness(mut self, liveness: ValidatorLiveness) -> Self {
+		self.liveness = Some(liveness);
+		self
+	}
 
-		let proof_count = self
-			.event_proofs
-			.get_event_proof_count(&witnessed_event.event_id, &block_state.authorities)?;
+	/// every incoming WitnessedEvent message should go through this function for processing the
+	/// message outcome, it hands the message to the [EventProofsCollector], and if the event
+	/// reached the required target it submits it to the transaction pool
+	async fn handle_witnessed_event(&self, message: &[u8]) -> Result<bool, Error> {
+		let block_state = get_latest_authorities_list(&self.validator_set, self.client.as_ref())?;
 
-		if proof_count >= block_state.target() {
+		if let Some(event_id) = self.collector.collect(&block_state, message)? {
+			self.statuses.advance(event_id, EventStatus::ThresholdReached);
+			// Left for the other validators to submit, if they witnessed it
+			if self.statuses.get(&event_id) == EventStatus::Cancelled {
+				tracing::debug!(target: SERVICE, event_id = %event_id, "Skipped cancelled event");
+				return Ok(true)
+			}
+			let quorum = self.traces.quorum_reached(event_id);
 			#[cfg(feature = "off-chain-proofs")]
 			let proofs = None;
 			#[cfg(not(feature = "off-chain-proofs"))]
-			let proofs = Some(
-				self.event_proofs
-					.get_event_proofs(&witnessed_event.event_id, &block_state.authorities)?,
-			);
+			let proofs =
+				Some(self.event_proofs.get_event_proofs(&event_id, &block_state.authorities)?);
 
-			log::debug!(
-				"Event:{} has been witnessed by a majority of validators and will be added to TxPool, Current Proof count:{}",
-				witnessed_event.event_id,
-				proof_count
-			);
-
-			self.submit_event_extrinsic(witnessed_event.event_id, proofs).await?;
-		} else {
-			log::debug!(
-				"Event:{} has been added to the event proofs, Current Proof Count:{}",
-				witnessed_event.event_id,
-				proof_count
-			);
+			let _submit = self.traces.stage(&quorum, "submit_extrinsic");
+			self.submit_event_extrinsic(event_id, proofs).await?;
+			self.collector.mark_submitted(event_id)?;
+			self.statuses.advance(event_id, EventStatus::InPool);
+			tracing::debug!(target: SERVICE, event_id = %event_id, "Submitted event extrinsic");
+			if self.notifications.has_subscribers() {
+				let witness_count =
+					self.collector.proof_count(&event_id, &block_state.authorities)?;
+				self.notifications.notify(EventNotification {
+					event_id,
+					stage: EventStage::Submitted,
+					block_hash: None,
+					witness_count,
+				});
+			}
 		}
 
 		Ok(true)
@@ -118,32 +413,59 @@ where
 	async fn submit_event_extrinsic(
 		&self,
 		event_id: H256,
-		event_proofs: Option<HashMap<CryptoTypePublicPair, Vec<u8>>>,
+		event_proofs: Option<HashMap<CryptoTypePublicPair, EventProof>>,
 	) -> Result<(), Error> {
-		let proofs = event_proofs.map(|x| {
-			x.iter()
-				.map(|(k, v)| {
-					let pubkey = Public::from_slice(k.1.as_slice()).unwrap();
-					let signature = Signature::from_slice(v.clone().as_slice()).unwrap();
-					(pubkey, signature)
-				})
-				.collect()
-		});
-		let best_hash = self.client.info().best_hash;
-		let unsigned_extrinsic = self
-			.client
-			.runtime_api()
-			.create_unsigned_extrinsic(best_hash, event_id, proofs)?;
-
-		match self.tx_pool.submit_local(&BlockId::hash(best_hash), unsigned_extrinsic) {
-			Ok(_) => Ok(()),
+		let proofs = event_proofs
+			.map(|x| {
+				x.iter()
+					.map(|(k, v)| {
+						let pubkey = Public::from_slice(k.1.as_slice()).map_err(|_| {
+							Error::BadWitnessedEventSignature(
+								"Can't retrieve sr25519 keys from event proofs".to_string(),
+							)
+						})?;
+						let signature =
+							Signature::from_slice(v.signature.as_slice()).ok_or_else(|| {
+								Error::BadWitnessedEventSignature(
+									"Can't create sr25519 signature from event proofs".to_string(),
+								)
+							})?;
+						Ok((pubkey, (v.session, signature)))
+					})
+					.collect::<Result<_, Error>>()
+			})
+			.transpose()?;
+		let (_, best_hash) = self.client.best_block();
+		let unsigned_extrinsic =
+			self.client.create_unsigned_extrinsic(best_hash, event_id, proofs)?;
+
+		let started = Instant::now();
+		let submitted = self.tx_pool.submit_local(&BlockId::hash(best_hash), unsigned_extrinsic);
+		let elapsed = started.elapsed();
+		let (outcome, result) = match submitted {
+			Ok(_) => (SubmissionOutcome::Submitted, Ok(())),
 			Err(x) => match x.into_pool_error() {
-				Ok(PoolError::AlreadyImported(_)) => Ok(()),
-				Ok(PoolError::InvalidTransaction(InvalidTransaction::Stale)) => Ok(()),
-				Ok(e) => Err(Error::Other(e.to_string())),
-				Err(e) => Err(Error::Other(e.to_string())),
+				Ok(PoolError::AlreadyImported(_)) => (SubmissionOutcome::AlreadyImported, Ok(())),
+				Ok(PoolError::InvalidTransaction(InvalidTransaction::Stale)) =>
+					(SubmissionOutcome::AlreadyImported, Ok(())),
+				Ok(e) => (submission_failure(&e), Err(Error::Other(e.to_string()))),
+				Err(e) => (SubmissionOutcome::Invalid, Err(Error::Other(e.to_string()))),
 			},
-		}
+		};
+		self.metrics.on_extrinsic_submitted(event_id, outcome, elapsed);
+		result
+	}
+}
+
+/// Classifies a transaction pool error rejecting the extrinsic of an event.
+fn submission_failure(error: &PoolError) -> SubmissionOutcome {
+	match error {
+		PoolError::ImmediatelyDropped |
+		PoolError::TooLowPriority { .. } |
+		PoolError::TemporarilyBanned => SubmissionOutcome::Dropped,
+		PoolError::InvalidTransaction(InvalidTransaction::AncientBirthBlock) =>
+			SubmissionOutcome::MortalityExpired,
+		_ => SubmissionOutcome::Invalid,
 	}
 }
 
@@ -152,30 +474,96 @@ impl<TxPool, Client, EventProofs, AuthorityId, Block> GossipHandler
 	for EventGossipHandler<TxPool, Client, EventProofs, AuthorityId, Block>
 where
 	TxPool: LocalTransactionPool + LocalTransactionPool<Block = Block>,
-	Client: ProvideRuntimeApi<Block>
-		+ HeaderBackend<Block>
-		+ BlockchainEvents<Block>
-		+ Send
-		+ Sync
-		+ 'static,
+	Client: ChainAccess<Block, AuthorityId>,
 	EventProofs: EventProofsTrait + Send + Sync + 'static,
-	AuthorityId: Codec + Send + Sync + 'static,
-	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
+	AuthorityId: Send + Sync + 'static,
 	Block: BlockT,
-	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
 {
-	fn get_topics() -> Vec<IdentTopic> {
-		vec![IdentTopic::new(Self::WITNESSED_EVENTS_TOPIC)]
+	fn get_topics(&self) -> Vec<IdentTopic> {
+		let mut topics = self.streams.topics();
+		if self.liveness.is_some() {
+			topics.push(IdentTopic::new(LIVENESS_TOPIC));
+		}
+		topics
 	}
 
-	async fn handle(&self, message_data: Vec<u8>) {
-		match bincode::deserialize::<WitnessedEvent>(message_data.as_slice()) {
-			Ok(witnessed_event) => {
-				if let Err(e) = self.handle_witnessed_event(witnessed_event).await {
-					log::error!("failed processing message: {:?}", e)
-				}
-			},
-			Err(e) => log::error!("failed deserilizing message data due to error:{:?}", e),
+	async fn handle(&self, message_data: &[u8]) {
+		self.handle_received(message_data).await;
+	}
+
+	/// Witnesses which fail to decode, or are not signed by a validator of an accepted session,
+	/// are rejected, and so not relayed; so are those of stale sessions or of unsupported
+	/// versions, and those which failed to be collected, without penalizing the peer. Liveness
+	/// beats are left to the [ValidatorLiveness], if the handler listens to them.
+	async fn handle_received(&self, message_data: &[u8]) -> MessageAcceptance {
+		// Beats are told apart before the version of the witnesses, so that they are never taken
+		// for witnesses of an unsupported version; handlers not following them leave them be
+		if LivenessBeat::is_beat(message_data) {
+			let Some(liveness) = &self.liveness else { return MessageAcceptance::Ignore };
+			return match get_latest_authorities_list(&self.validator_set, self.client.as_ref()) {
+				Ok(block_state) =>
+					liveness.on_beat(&block_state, message_data, Instant::now(), unix_millis()),
+				Err(_) => MessageAcceptance::Ignore,
+			}
+		}
+		let span = tracing::debug_span!(
+			target: SERVICE,
+			"handle_witnessed_event",
+			event_id = tracing::field::Empty
+		);
+		let result = self.handle_witnessed_event(message_data).instrument(span).await;
+		let Err(e) = result else { return MessageAcceptance::Accept };
+		// Copies are neither relayed nor held against the peer
+		if e == Error::DuplicateWitness {
+			return MessageAcceptance::Ignore
+		}
+		// Nor are witnesses of another version, which the peer may only have relayed
+		if let Error::UnsupportedVersion(version) = e {
+			rate_limited!(
+				self.log_limiter,
+				"unsupported_witness_version",
+				warn,
+				target: SERVICE,
+				version,
+				supported = WITNESSED_EVENT_VERSION,
+				"Ignored a witness of an unsupported version; validators run incompatible releases"
+			);
+			return MessageAcceptance::Ignore
+		}
+		rate_limited!(
+			self.log_limiter,
+			"failed_witnessed_event",
+			error,
+			target: SERVICE,
+			error = %e,
+			"Failed processing witnessed event"
+		);
+		match e {
+			Error::SerilizationFailure(_) | Error::BadWitnessedEventSignature(_) =>
+				MessageAcceptance::Reject,
+			_ => MessageAcceptance::Ignore,
+		}
+	}
+
+	/// The witnesses held of the events from the latest validators, for the peers which missed
+	/// their gossip. None if they cannot be read.
+	fn held_messages(&self, event_ids: &[H256]) -> Vec<Vec<u8>> {
+		let witnesses = get_latest_authorities_list(&self.validator_set, self.client.as_ref())
+			.and_then(|block_state| {
+				self.collector.witnesses(event_ids, &block_state.authorities)
+			});
+		witnesses.unwrap_or_else(|e| {
+			tracing::debug!(target: SERVICE, error = %e, "Failed reading held witnesses");
+			Vec::new()
+		})
+	}
+
+	/// Witnesses of the same event are collected in order. Oversized ones are dropped right away;
+	/// other malformed ones are left for [Self::handle] to reject.
+	fn ordering_key(&self, message: &[u8]) -> Option<u64> {
+		if message.len() as u64 > MAX_WITNESSED_EVENT_SIZE {
+			return None
 		}
+		Some(WitnessedEvent::from_bytes(message).map_or(0, |event| event.event_id.to_low_u64_be()))
 	}
 }
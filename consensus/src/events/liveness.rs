@@ -0,0 +1,316 @@
+//! Liveness of the validators. Every validator publishes a [LivenessBeat] on the [LIVENESS_TOPIC]
+//! at an interval, signed with the key it witnesses events with, and carrying how many events it
+//! witnessed since it started. The nodes verify the beats against the latest finalized validator
+//! set, and count the validators heard from within the last [SILENCE_INTERVALS] intervals as
+//! online: they log those coming online and going silent, and report them in the
+//! `streams_validator_online` metric, along with their witnessed counts.
+
+use super::{get_latest_authorities_list, AuthoritiesList, ValidatorSetHandle};
+use crate::{
+	errors::Error,
+	gossip::GossipTrait,
+	logging::SERVICE,
+	metrics::Metrics,
+	role::LocalRole,
+	telemetry::NodeRole,
+	traits::ChainAccess,
+	watchdog::{Heartbeat, HEARTBEAT_INTERVAL},
+};
+use codec::{Decode, DecodeAll, Encode};
+use libp2p::gossipsub::{IdentTopic, MessageAcceptance};
+use sp_api::BlockT;
+use sp_core::{
+	sr25519::{Public, Signature},
+	ByteArray,
+};
+use sp_keystore::CryptoStore;
+use sp_runtime::{
+	app_crypto::{CryptoTypePublicPair, RuntimePublic},
+	key_types::AURA,
+};
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The topic the beats are published on.
+pub const LIVENESS_TOPIC: &str = "ValidatorLiveness";
+/// How often validators publish a beat, by default.
+pub const DEFAULT_LIVENESS_INTERVAL: Duration = Duration::from_secs(30);
+/// How many intervals a validator stays online without a beat.
+pub const SILENCE_INTERVALS: u32 = 3;
+/// The first byte of the beats, telling them apart from witnesses, which start with their version.
+pub const LIVENESS_BEAT_TAG: u8 = 0x80;
+/// What the signature of a beat is bound to, along with its fields.
+const LIVENESS_CONTEXT: &[u8] = b"validated-streams/liveness";
+/// The largest beat, in bytes: its tag, a key, a signature, their lengths, and the counters.
+const MAX_BEAT_SIZE: usize = 256;
+
+/// The beat of a validator, telling that it is online.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct LivenessBeat {
+	/// The validator key which made the signature
+	pub pub_key: CryptoTypePublicPair,
+	/// How many events the validator witnessed since it started
+	pub witnessed: u64,
+	/// When the beat was sent, in milliseconds since the Unix epoch; each beat of a validator is
+	/// sent after the previous one
+	pub sent_at: u64,
+	/// The signature of the [payload](Self::payload)
+	pub signature: Vec<u8>,
+}
+
+impl LivenessBeat {
+	/// What the validator signs: the fields of the beat, bound to the liveness context.
+	pub fn payload(pub_key: &CryptoTypePublicPair, witnessed: u64, sent_at: u64) -> Vec<u8> {
+		[LIVENESS_CONTEXT, &(pub_key, witnessed, sent_at).encode()].concat()
+	}
+
+	/// Encodes the beat as gossiped: the [LIVENESS_BEAT_TAG], followed by its SCALE encoding.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = vec![LIVENESS_BEAT_TAG];
+		self.encode_to(&mut bytes);
+		bytes
+	}
+
+	/// Decodes a beat, as encoded by [Self::to_bytes].
+	pub fn from_bytes(message: &[u8]) -> Result<Self, Error> {
+		if message.len() > MAX_BEAT_SIZE {
+			return Err(Error::SerilizationFailure("Liveness beat is too large".to_string()))
+		}
+		match message.split_first() {
+			Some((&LIVENESS_BEAT_TAG, mut encoded)) => Ok(Self::decode_all(&mut encoded)?),
+			_ => Err(Error::SerilizationFailure("Not a liveness beat".to_string())),
+		}
+	}
+
+	/// Whether the gossip message is a beat, rather than a witness.
+	pub fn is_beat(message: &[u8]) -> bool {
+		message.first() == Some(&LIVENESS_BEAT_TAG)
+	}
+
+	/// Whether the signature is that of the key of the beat.
+	pub fn verify(&self) -> bool {
+		let (Ok(pub_key), Some(signature)) = (
+			Public::from_slice(self.pub_key.1.as_slice()),
+			Signature::from_slice(self.signature.as_slice()),
+		) else {
+			return false
+		};
+		pub_key.verify(&Self::payload(&self.pub_key, self.witnessed, self.sent_at), &signature)
+	}
+}
+
+/// The last beat heard from a validator.
+struct Heard {
+	/// The index of the validator in the set it was heard in
+	validator: usize,
+	sent_at: u64,
+	received: Instant,
+	witnessed: u64,
+	online: bool,
+}
+
+struct Inner {
+	interval: Duration,
+	witnessed: AtomicU64,
+	heard: Mutex<HashMap<CryptoTypePublicPair, Heard>>,
+	metrics: Metrics,
+}
+
+/// The liveness of the validators, as told by their beats, along with the count of events the
+/// node witnessed itself, for its own beats. Cheap to clone; the clones share the same state.
+#[derive(Clone)]
+pub struct ValidatorLiveness {
+	inner: Arc<Inner>,
+}
+
+impl ValidatorLiveness {
+	/// Tracks validators beating every `interval`, reporting them to the metrics.
+	pub fn new(interval: Duration, metrics: Metrics) -> Self {
+		let heard = Mutex::new(HashMap::new());
+		Self { inner: Arc::new(Inner { interval, witnessed: AtomicU64::new(0), heard, metrics }) }
+	}
+
+	/// How often validators beat.
+	pub fn interval(&self) -> Duration {
+		self.inner.interval
+	}
+
+	/// How long a validator stays online without a beat.
+	fn silence(&self) -> Duration {
+		self.inner.interval * SILENCE_INTERVALS
+	}
+
+	/// Records that the node witnessed an event.
+	pub fn on_witnessed(&self) {
+		self.inner.witnessed.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// How many events the node witnessed since it started.
+	pub fn witnessed(&self) -> u64 {
+		self.inner.witnessed.load(Ordering::Relaxed)
+	}
+
+	/// The beat of the node, signed with the first key of the validator set found in the keystore,
+	/// as witnesses are. None if there is no such key.
+	pub async fn sign(
+		&self,
+		keystore: &dyn CryptoStore,
+		authorities: &AuthoritiesList,
+		sent_at: u64,
+	) -> Result<Option<LivenessBeat>, Error> {
+		let keys = keystore.supported_keys(AURA, authorities.authorities.to_vec()).await?;
+		let Some(pub_key) = keys.into_iter().next() else { return Ok(None) };
+		let witnessed = self.witnessed();
+		let payload = LivenessBeat::payload(&pub_key, witnessed, sent_at);
+		let signature = keystore
+			.sign_with(AURA, &pub_key, &payload)
+			.await?
+			.ok_or_else(|| Error::SigningFailure("Failed getting a signature".to_string()))?;
+		Ok(Some(LivenessBeat { pub_key, witnessed, sent_at, signature }))
+	}
+
+	/// Handles a gossiped beat, received at `now`, or `now_ms` since the Unix epoch. Beats which
+	/// fail to decode or verify are rejected; those of keys outside the set, and those older than
+	/// the last beat of their validator or sent too far from `now_ms`, e.g. replayed ones, are
+	/// ignored without penalizing the peer.
+	pub fn on_beat(
+		&self,
+		authorities: &AuthoritiesList,
+		message: &[u8],
+		now: Instant,
+		now_ms: u64,
+	) -> MessageAcceptance {
+		let beat = match LivenessBeat::from_bytes(message) {
+			Ok(beat) => beat,
+			Err(e) => {
+				tracing::debug!(target: SERVICE, error = %e, "Rejected liveness beat");
+				return MessageAcceptance::Reject
+			},
+		};
+		let Some(validator) = authorities.position(&beat.pub_key) else {
+			return MessageAcceptance::Ignore
+		};
+		if !beat.verify() {
+			tracing::debug!(target: SERVICE, validator, "Rejected forged liveness beat");
+			return MessageAcceptance::Reject
+		}
+		if now_ms.abs_diff(beat.sent_at) > self.silence().as_millis() as u64 {
+			return MessageAcceptance::Ignore
+		}
+		let mut heard = self.inner.heard.lock().unwrap();
+		if heard.get(&beat.pub_key).map_or(false, |last| last.sent_at >= beat.sent_at) {
+			return MessageAcceptance::Ignore
+		}
+		let was_online = heard.get(&beat.pub_key).map_or(false, |last| last.online);
+		if !was_online {
+			tracing::info!(
+				target: SERVICE,
+				validator,
+				key = ?beat.pub_key,
+				witnessed = beat.witnessed,
+				"Validator online"
+			);
+		}
+		self.inner.metrics.set_validator_liveness(validator, true, beat.witnessed);
+		let last = Heard {
+			validator,
+			sent_at: beat.sent_at,
+			received: now,
+			witnessed: beat.witnessed,
+			online: true,
+		};
+		heard.insert(beat.pub_key, last);
+		MessageAcceptance::Accept
+	}
+
+	/// Marks the validators last heard from longer than [SILENCE_INTERVALS] intervals before `now`
+	/// as silent, and forgets those which left the set.
+	pub fn check(&self, authorities: &AuthoritiesList, now: Instant) {
+		let silence = self.silence();
+		let mut heard = self.inner.heard.lock().unwrap();
+		heard.retain(|pub_key, last| {
+			let member = authorities.contains(pub_key);
+			let silent = now.saturating_duration_since(last.received) >= silence;
+			if last.online && (silent || !member) {
+				last.online = false;
+				tracing::warn!(
+					target: SERVICE,
+					validator = last.validator,
+					key = ?pub_key,
+					silent_for = ?now.saturating_duration_since(last.received),
+					"Validator went silent"
+				);
+				self.inner.metrics.set_validator_liveness(last.validator, false, last.witnessed);
+			}
+			member
+		});
+	}
+
+	/// The validators online, with the count of events each witnessed as of its last beat, in
+	/// index order.
+	pub fn online(&self) -> Vec<(CryptoTypePublicPair, u64)> {
+		let heard = self.inner.heard.lock().unwrap();
+		let mut online: Vec<_> = heard.iter().filter(|(_, last)| last.online).collect();
+		online.sort_by_key(|(_, last)| last.validator);
+		online.into_iter().map(|(pub_key, last)| (pub_key.clone(), last.witnessed)).collect()
+	}
+}
+
+/// The current time in milliseconds since the Unix epoch, as beats are timed.
+pub fn unix_millis() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Publishes the beat of the node every interval while it is a validator, and checks which
+/// validators went silent meanwhile, forever.
+pub async fn run_liveness<Block, Client, AuthorityId, G>(
+	liveness: ValidatorLiveness,
+	client: Arc<Client>,
+	validator_set: ValidatorSetHandle<Block>,
+	keystore: Arc<dyn CryptoStore>,
+	mut gossip: G,
+	role: LocalRole,
+	heartbeat: Heartbeat,
+) where
+	Block: BlockT,
+	Client: ChainAccess<Block, AuthorityId>,
+	G: GossipTrait,
+{
+	// Ticking often enough for the watchdog, however long the interval
+	let mut ticks = tokio::time::interval(liveness.interval().min(HEARTBEAT_INTERVAL));
+	let mut next_beat = Instant::now();
+	loop {
+		ticks.tick().await;
+		heartbeat.bump();
+		let authorities = match get_latest_authorities_list(&validator_set, client.as_ref()) {
+			Ok(authorities) => authorities,
+			Err(e) => {
+				tracing::warn!(target: SERVICE, error = %e, "Failed reading the validator set");
+				continue
+			},
+		};
+		let now = Instant::now();
+		liveness.check(&authorities, now);
+		if role.get() != NodeRole::Validator || now < next_beat {
+			continue
+		}
+		next_beat = now + liveness.interval();
+		let beat = match liveness.sign(keystore.as_ref(), &authorities, unix_millis()).await {
+			Ok(Some(beat)) => beat,
+			Ok(None) => continue,
+			Err(e) => {
+				tracing::debug!(target: SERVICE, error = %e, "Failed signing a liveness beat");
+				continue
+			},
+		};
+		if let Err(e) = gossip.publish(IdentTopic::new(LIVENESS_TOPIC), beat.to_bytes()).await {
+			tracing::debug!(target: SERVICE, error = %e, "Failed publishing a liveness beat");
+		}
+	}
+}
@@ -0,0 +1,80 @@
+//! Proofs that the gossip peers of a node belong to the validator set
+
+use super::{get_latest_authorities_list, ValidatorSetHandle};
+use crate::{
+	gossip::{
+		admission::{membership_payload, MembershipProof},
+		Membership,
+	},
+	logging::GOSSIP,
+	traits::ChainAccess,
+};
+use async_trait::async_trait;
+use libp2p::PeerId;
+use sp_api::BlockT;
+use sp_core::{
+	sr25519::{Public, Signature},
+	ByteArray,
+};
+use sp_keystore::CryptoStore;
+use sp_runtime::{app_crypto::RuntimePublic, key_types::AURA};
+use std::{marker::PhantomData, sync::Arc};
+
+/// Proves the membership of the node with the key it witnesses events with, and verifies that of
+/// its peers against the latest finalized validator set.
+pub struct ValidatorMembership<Block: BlockT, Client, AuthorityId> {
+	client: Arc<Client>,
+	keystore: Arc<dyn CryptoStore>,
+	validator_set: ValidatorSetHandle<Block>,
+	phantom: PhantomData<AuthorityId>,
+}
+
+impl<Block: BlockT, Client, AuthorityId> ValidatorMembership<Block, Client, AuthorityId> {
+	/// Creates a new ValidatorMembership
+	pub fn new(
+		client: Arc<Client>,
+		keystore: Arc<dyn CryptoStore>,
+		validator_set: ValidatorSetHandle<Block>,
+	) -> Self {
+		Self { client, keystore, validator_set, phantom: PhantomData }
+	}
+}
+
+#[async_trait]
+impl<Block, Client, AuthorityId> Membership for ValidatorMembership<Block, Client, AuthorityId>
+where
+	Block: BlockT,
+	Client: ChainAccess<Block, AuthorityId>,
+	AuthorityId: Send + Sync + 'static,
+{
+	/// Signed with the first key of the validator set found in the keystore, as witnesses are.
+	async fn prove(&self, local: PeerId) -> Option<MembershipProof> {
+		let block_state =
+			get_latest_authorities_list(&self.validator_set, self.client.as_ref()).ok()?;
+		let keys = self.keystore.supported_keys(AURA, block_state.authorities.to_vec()).await;
+		let pub_key = keys.ok()?.into_iter().next()?;
+		let signed = self.keystore.sign_with(AURA, &pub_key, &membership_payload(&local)).await;
+		match signed {
+			Ok(Some(signature)) => Some(MembershipProof { pub_key, signature }),
+			Ok(None) => None,
+			Err(e) => {
+				tracing::debug!(target: GOSSIP, error = %e, "Failed signing a membership proof");
+				None
+			},
+		}
+	}
+
+	fn verify(&self, peer: &PeerId, proof: &MembershipProof) -> bool {
+		let block_state = get_latest_authorities_list(&self.validator_set, self.client.as_ref());
+		if !block_state.map_or(false, |block_state| block_state.contains(&proof.pub_key)) {
+			return false
+		}
+		let (Ok(pub_key), Some(signature)) = (
+			Public::from_slice(proof.pub_key.1.as_slice()),
+			Signature::from_slice(proof.signature.as_slice()),
+		) else {
+			return false
+		};
+		pub_key.verify(&membership_payload(peer), &signature)
+	}
+}
@@ -3,52 +3,156 @@
 use crate::{
 	errors::Error,
 	proofs::{EventProofsTrait, WitnessedEvent},
+	traits::ChainAccess,
 };
-use codec::Codec;
 use lru::LruCache;
-use pallet_validated_streams::ValidatedStreamsApi;
-use sc_client_api::HeaderBackend;
-use sp_api::{BlockT, ProvideRuntimeApi};
-use sp_consensus_aura::AuraApi;
+use pallet_validated_streams::payload::{witness_payload, SessionIndex};
+use sp_api::BlockT;
 use sp_core::{
 	sr25519::{Public, Signature},
 	ByteArray, H256,
 };
 use sp_runtime::app_crypto::{CryptoTypePublicPair, RuntimePublic};
-use std::sync::{Arc, Mutex};
+use std::{
+	cmp::Ordering,
+	collections::{BTreeMap, HashMap, HashSet},
+	num::NonZeroUsize,
+	sync::{Arc, Mutex, RwLock},
+};
 #[cfg(test)]
 pub mod tests;
 
 mod gossip;
+mod liveness;
+mod membership;
+mod reader;
 mod validate;
 mod witness;
 
-pub use gossip::EventGossipHandler;
+pub use gossip::{EventGossipHandler, EventProofsCollector, SeenWitnesses, WITNESSED_EVENTS_TOPIC};
+pub use liveness::{
+	run_liveness, unix_millis, LivenessBeat, ValidatorLiveness, DEFAULT_LIVENESS_INTERVAL,
+	LIVENESS_BEAT_TAG, LIVENESS_TOPIC, SILENCE_INTERVALS,
+};
+pub use membership::ValidatorMembership;
+pub use reader::EventProofReader;
 pub use validate::EventValidator;
 pub use witness::EventWitnesser;
 
-/// A cache for the list of authorities in a block.
-pub type BlockStateCache<Block> = Arc<Mutex<LruCache<<Block as BlockT>::Hash, AuthoritiesList>>>;
+/// The validator sets of the chain, shared by everything which checks witnesses against them: the
+/// verification of gossiped witnesses, the threshold those are counted against, and the quorum
+/// expected of the gossip mesh, so that none of them can disagree about the current set. Cloning
+/// it is cheap and shares the same sets.
+///
+/// The set at the last finalized block is kept apart and replaced as a whole once another block
+/// is finalized, so that reading it takes neither a copy of the set nor the lock of the cache; the
+/// sets at other blocks are cached per block.
+pub struct ValidatorSetHandle<Block: BlockT> {
+	latest: Arc<RwLock<Option<(Block::Hash, AuthoritiesList)>>>,
+	cache: Arc<Mutex<LruCache<Block::Hash, AuthoritiesList>>>,
+}
+
+impl<Block: BlockT> Clone for ValidatorSetHandle<Block> {
+	fn clone(&self) -> Self {
+		Self { latest: self.latest.clone(), cache: self.cache.clone() }
+	}
+}
+
+impl<Block: BlockT> ValidatorSetHandle<Block> {
+	/// Creates a handle caching the sets at up to `capacity` blocks.
+	pub fn new(capacity: NonZeroUsize) -> Self {
+		Self { latest: Default::default(), cache: Arc::new(Mutex::new(LruCache::new(capacity))) }
+	}
 
-/// Internal struct holding the list of the authorities at a particular block.
+	/// The set at the last finalized block, as last swapped in, if any.
+	pub fn current(&self) -> Result<Option<AuthoritiesList>, Error> {
+		Ok(self.latest.read()?.as_ref().map(|(_, set)| set.clone()))
+	}
+
+	/// Swaps in the set at a newly finalized block. Readers see either the previous set or this
+	/// one, never a mix of both.
+	pub(crate) fn replace(&self, at: Block::Hash, set: AuthoritiesList) -> Result<(), Error> {
+		*self.latest.write()? = Some((at, set));
+		Ok(())
+	}
+}
+
+/// The list of the authorities at a particular block, along with its session. Cloning it is cheap,
+/// and shares the list.
+///
+/// Witnesses are accepted from the authorities of the session they claim, as long as it is the
+/// session of the list or one of the previous sessions still within the grace window of the chain,
+/// whose authorities are kept along with the list.
 #[derive(Clone, Debug)]
 pub struct AuthoritiesList {
 	/// The list of authorities at the block.
-	pub authorities: Vec<CryptoTypePublicPair>,
+	pub authorities: Arc<[CryptoTypePublicPair]>,
+	/// The position of each authority in the list
+	positions: Arc<HashMap<CryptoTypePublicPair, usize>>,
+	/// The session of the authorities at the block.
+	pub session: SessionIndex,
+	/// The authorities of the previous sessions whose witnesses are still accepted
+	previous: Arc<BTreeMap<SessionIndex, HashSet<CryptoTypePublicPair>>>,
 }
 impl AuthoritiesList {
-	/// Creates a new [AuthoritiesList]
+	/// Creates a new [AuthoritiesList], of session 0 and accepting no previous sessions
 	pub fn new(authorities: Vec<CryptoTypePublicPair>) -> Self {
-		Self { authorities }
+		// Reversed, so that a key listed twice keeps its first position
+		let positions =
+			authorities.iter().enumerate().map(|(i, key)| (key.clone(), i)).rev().collect();
+		Self {
+			authorities: authorities.into(),
+			positions: Arc::new(positions),
+			session: 0,
+			previous: Default::default(),
+		}
 	}
 
-	/// Verifies that the witnessed event was signed by one of the authorities
-	/// than proceeds to check the signature
+	/// The same list, as the authorities of the given session, and accepting witnesses of the
+	/// given previous sessions from their authorities.
+	pub fn in_session(
+		mut self,
+		session: SessionIndex,
+		previous: impl IntoIterator<Item = (SessionIndex, Vec<CryptoTypePublicPair>)>,
+	) -> Self {
+		self.session = session;
+		let previous = previous.into_iter().filter(|(previous, _)| *previous < session);
+		let previous = previous.map(|(previous, keys)| (previous, keys.into_iter().collect()));
+		self.previous = Arc::new(previous.collect());
+		self
+	}
+
+	/// The oldest session whose witnesses are accepted.
+	pub fn oldest_session(&self) -> SessionIndex {
+		self.previous.keys().next().copied().unwrap_or(self.session)
+	}
+
+	/// Whether the key is one of the authorities.
+	pub fn contains(&self, key: &CryptoTypePublicPair) -> bool {
+		self.positions.contains_key(key)
+	}
+
+	/// The position of the key in the list of authorities, if it is one of them.
+	pub fn position(&self, key: &CryptoTypePublicPair) -> Option<usize> {
+		self.positions.get(key).copied()
+	}
+
+	/// Verifies that the witnessed event was signed by one of the authorities of the session it
+	/// claims, which must still be accepted, than proceeds to check the signature
 	pub fn verify_witnessed_event_origin(
 		&self,
 		witnessed_event: WitnessedEvent,
 	) -> Result<WitnessedEvent, Error> {
-		if self.authorities.contains(&witnessed_event.pub_key) {
+		let session = witnessed_event.session;
+		let signer = match session.cmp(&self.session) {
+			Ordering::Equal => self.contains(&witnessed_event.pub_key),
+			Ordering::Less => match self.previous.get(&session) {
+				Some(authorities) => authorities.contains(&witnessed_event.pub_key),
+				None => return Err(Error::StaleSession { session, current: self.session }),
+			},
+			Ordering::Greater => return Err(Error::StaleSession { session, current: self.session }),
+		};
+		if signer {
 			let pubkey =
 				Public::from_slice(witnessed_event.pub_key.1.as_slice()).map_err(|_| {
 					Error::BadWitnessedEventSignature(
@@ -62,7 +166,7 @@ impl AuthoritiesList {
 					)
 				})?;
 
-			if pubkey.verify(&witnessed_event.event_id, &signature) {
+			if pubkey.verify(&witness_payload(&witnessed_event.event_id, session), &signature) {
 				Ok(witnessed_event)
 			} else {
 				Err(Error::BadWitnessedEventSignature(
@@ -76,6 +180,12 @@ impl AuthoritiesList {
 		}
 	}
 
+	/// Decodes a gossiped [WitnessedEvent] and verifies its origin. This is everything done with
+	/// the untrusted bytes of a gossip message before they reach the event proofs.
+	pub fn decode_witnessed_event(&self, message: &[u8]) -> Result<WitnessedEvent, Error> {
+		self.verify_witnessed_event_origin(WitnessedEvent::from_bytes(message)?)
+	}
+
 	/// Calcultes the minimum number of authorities to witness an event in order for it to be valid.
 	/// --
 	/// Currently, this uses the formula floor(n * 2 / 3) + 1; the logic for that is slightly
@@ -94,25 +204,36 @@ impl AuthoritiesList {
 	}
 }
 
+/// The validators witnessing the events as of the last finalized block, as told to clients.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WitnessingSet {
+	/// The session of the validators
+	pub session: SessionIndex,
+	/// The validators, in the order of the chain
+	pub validators: Vec<CryptoTypePublicPair>,
+	/// How many of the validators must witness an event for it to be validated
+	pub target: u16,
+	/// Whether this node witnesses events as one of the validators
+	pub witnessing: bool,
+}
+
 /// Returns the list of events that we do not have enough witnesses for, using the authorities in
-/// the given block.
-pub(crate) fn verify_events_validity<Block, EventProofs, Client, AuthorityId>(
-	block_state: BlockStateCache<Block>,
-	client: Arc<Client>,
+/// the given block. As on chain, only the witnesses of those authorities count towards the target,
+/// whichever accepted session they were made in.
+pub(crate) fn verify_events_validity<Block, EventProofs, Chain, AuthorityId>(
+	validator_set: &ValidatorSetHandle<Block>,
+	chain: Arc<Chain>,
 	authorities_block_id: <Block as BlockT>::Hash,
 	event_proofs: Arc<EventProofs>,
 	ids: Vec<H256>,
 ) -> Result<Vec<H256>, Error>
 where
 	Block: BlockT,
-	Client: ProvideRuntimeApi<Block> + Send + Sync + 'static,
-	AuthorityId: Codec + Send + Sync + 'static,
+	Chain: ChainAccess<Block, AuthorityId>,
 	EventProofs: EventProofsTrait + Send + Sync,
-	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
-	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
 {
 	let authorities_list =
-		get_authorities_list(block_state, client.as_ref(), authorities_block_id)?;
+		get_authorities_list(validator_set, chain.as_ref(), authorities_block_id)?;
 	let target = authorities_list.target();
 	let mut unprepared_ids = Vec::new();
 	for id in ids {
@@ -125,46 +246,45 @@ where
 	Ok(unprepared_ids)
 }
 
-/// Reads the latest finalized list of authorities. For use when pruining event proofs.
-pub(crate) fn get_latest_authorities_list<Block, Client, AuthorityId>(
-	block_state: BlockStateCache<Block>,
-	client: &Client,
+/// Reads the latest finalized list of authorities, swapping it into the handle if another block
+/// got finalized since it was last read.
+pub(crate) fn get_latest_authorities_list<Block, Chain, AuthorityId>(
+	validator_set: &ValidatorSetHandle<Block>,
+	chain: &Chain,
 ) -> Result<AuthoritiesList, Error>
 where
 	Block: BlockT,
-	Client: HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync + 'static,
-	AuthorityId: Codec + Send + Sync + 'static,
-	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
-	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+	Chain: ChainAccess<Block, AuthorityId>,
 {
-	get_authorities_list(block_state, client, client.info().finalized_hash)
+	let (_, finalized_hash) = chain.finalized_block();
+	if let Some((at, set)) = validator_set.latest.read()?.as_ref() {
+		if *at == finalized_hash {
+			return Ok(set.clone())
+		}
+	}
+	let set = get_authorities_list(validator_set, chain, finalized_hash)?;
+	validator_set.replace(finalized_hash, set.clone())?;
+	Ok(set)
 }
 
 /// Reads the list of authorities from a block.
-pub(crate) fn get_authorities_list<Block, Client, AuthorityId>(
-	block_state: BlockStateCache<Block>,
-	client: &Client,
+pub(crate) fn get_authorities_list<Block, Chain, AuthorityId>(
+	validator_set: &ValidatorSetHandle<Block>,
+	chain: &Chain,
 	authorities_block_id: <Block as BlockT>::Hash,
 ) -> Result<AuthoritiesList, Error>
 where
 	Block: BlockT,
-	Client: ProvideRuntimeApi<Block> + Send + Sync + 'static,
-	AuthorityId: Codec + Send + Sync + 'static,
-	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
-	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+	Chain: ChainAccess<Block, AuthorityId>,
 {
-	if let Some(block_state) = block_state.lock()?.get(&authorities_block_id) {
-		return Ok(block_state.clone())
+	if let Some(set) = validator_set.cache.lock()?.get(&authorities_block_id) {
+		return Ok(set.clone())
 	}
-	let public_keys = client
-		.runtime_api()
-		.authorities(authorities_block_id)
-		.map_err(|e| Error::Other(e.to_string()))?
-		.iter()
-		.map(CryptoTypePublicPair::from)
-		.collect();
-	let new_block_state = AuthoritiesList::new(public_keys);
-	block_state.lock()?.put(authorities_block_id, new_block_state.clone());
-
-	Ok(new_block_state)
+	let authorities = chain.authorities(authorities_block_id)?;
+	let session = chain.session(authorities_block_id)?;
+	let previous = chain.previous_sessions(authorities_block_id)?;
+	let new_set = AuthoritiesList::new(authorities).in_session(session, previous);
+	validator_set.cache.lock()?.put(authorities_block_id, new_set.clone());
+
+	Ok(new_set)
 }
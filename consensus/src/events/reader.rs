@@ -0,0 +1,54 @@
+//! Service which reads the witnesses of events for the trusted client
+
+use super::{get_latest_authorities_list, ValidatorSetHandle};
+use crate::{
+	errors::Error,
+	proofs::{EventProofsTrait, ValidatorProofs},
+	traits::{ChainAccess, EventProofReaderTrait},
+};
+use sp_api::BlockT;
+use sp_core::H256;
+use std::{marker::PhantomData, sync::Arc};
+
+/// A utility which reads the proofs of events held in the [EventProofsTrait], from the validators
+/// the node currently counts witnesses of.
+pub struct EventProofReader<Block: BlockT, Client, AuthorityId, EventProofs> {
+	client: Arc<Client>,
+	event_proofs: Arc<EventProofs>,
+	validator_set: ValidatorSetHandle<Block>,
+	phantom: PhantomData<AuthorityId>,
+}
+
+impl<Block: BlockT, Client, AuthorityId, EventProofs>
+	EventProofReader<Block, Client, AuthorityId, EventProofs>
+{
+	/// Creates a new EventProofReader
+	pub fn new(
+		client: Arc<Client>,
+		event_proofs: Arc<EventProofs>,
+		validator_set: ValidatorSetHandle<Block>,
+	) -> Self {
+		Self { client, event_proofs, validator_set, phantom: PhantomData }
+	}
+}
+
+impl<Block, Client, AuthorityId, EventProofs> EventProofReaderTrait
+	for EventProofReader<Block, Client, AuthorityId, EventProofs>
+where
+	Block: BlockT,
+	Client: ChainAccess<Block, AuthorityId>,
+	EventProofs: EventProofsTrait,
+{
+	fn get_latest_event_proofs(&self, event_id: &H256) -> Result<ValidatorProofs, Error> {
+		let block_state = get_latest_authorities_list(&self.validator_set, self.client.as_ref())?;
+		let proofs = self.event_proofs.get_event_proofs(event_id, &block_state.authorities)?;
+		let mut proofs: Vec<_> = proofs.into_iter().collect();
+		proofs.sort_by_key(|(pub_key, _)| block_state.position(pub_key));
+		Ok(ValidatorProofs {
+			session: block_state.session,
+			validators: block_state.authorities.to_vec(),
+			target: block_state.target(),
+			proofs,
+		})
+	}
+}
@@ -1,26 +1,65 @@
-use super::AuthoritiesList;
-use crate::proofs::WitnessedEvent;
+use super::{
+	get_latest_authorities_list, unix_millis, verify_events_validity, AuthoritiesList,
+	EventGossipHandler, EventProofReader, EventProofsCollector, EventWitnesser, LivenessBeat,
+	SeenWitnesses, ValidatorLiveness, ValidatorMembership, LIVENESS_TOPIC, WITNESSED_EVENTS_TOPIC,
+};
+use crate::{
+	errors::Error,
+	gossip::{GossipHandler, GossipTrait, Membership},
+	metrics::Metrics,
+	proofs::{
+		EventProofsTrait, InMemoryEventProofs, WitnessedEvent, MAX_WITNESSED_EVENT_SIZE,
+		WITNESSED_EVENT_VERSION,
+	},
+	server::{
+		validated_streams_proto::{
+			streams_server::Streams, GetEventProofRequest, WitnessEventRequest,
+		},
+		ValidatedStreamsGrpc,
+	},
+	role::LocalRole,
+	shutdown::{ShutdownSignal, ShutdownStage},
+	telemetry::NodeRole,
+	test_utils::{
+		single_node, validator_set, FakeChain, Fault, LinkConfig, SimulatedNetwork, SimulatedNode,
+		SimulatedValidator, TestBlock, TestPool, TestProofs, TestValidators,
+	},
+	traces::Traces,
+	traits::{EventProofReaderTrait, EventWitnesserTrait},
+};
+use libp2p::{
+	gossipsub::{IdentTopic, MessageAcceptance},
+	PeerId,
+};
+use pallet_validated_streams::payload::witness_payload;
+use prometheus_endpoint::Registry;
 use rstest::rstest;
-use sc_keystore::LocalKeystore;
-use sp_core::{sr25519::Public, H256};
-use sp_keystore::CryptoStore;
-use sp_runtime::{app_crypto::CryptoTypePublicPair, key_types::AURA};
+use sc_transaction_pool_api::error::Error as PoolError;
+use sp_core::{
+	sr25519::{Pair, Public, Signature},
+	ByteArray, Pair as _, H256,
+};
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::{
+	collections::BTreeSet,
+	num::NonZeroUsize,
+	sync::{mpsc, Arc},
+	time::{Duration, Instant},
+};
+use tonic::{Code, Request, Status};
 
-#[tokio::test]
-async fn test_verify_events() {
+#[test]
+fn test_verify_events() {
 	// simple witnessed event
-	let keystore = LocalKeystore::in_memory();
+	let validators = TestValidators::new(1);
 	let event_id = H256::repeat_byte(0);
-	let key = keystore.sr25519_generate_new(AURA, None).await.unwrap();
-	let witnessed_event = create_witnessed_event(event_id, &keystore, key).await;
-	let validators_list = vec![CryptoTypePublicPair::from(key)];
-	let block_state = AuthoritiesList::new(validators_list);
+	let witnessed_event = validators.witness(0, event_id).build();
+	let block_state = validators.authorities();
 
 	let result = block_state.verify_witnessed_event_origin(witnessed_event.clone());
 	assert_eq!(result.unwrap(), witnessed_event);
 
-	let mut empty_sig_event = witnessed_event.clone();
-	empty_sig_event.signature = vec![];
+	let empty_sig_event = validators.witness(0, event_id).empty_signature().build();
 	let result = block_state.verify_witnessed_event_origin(empty_sig_event);
 	assert!(result.is_err());
 
@@ -30,8 +69,7 @@ async fn test_verify_events() {
 	let result = block_state.verify_witnessed_event_origin(invalid_sig_event);
 	assert!(result.is_err());
 
-	let mut bad_sig_event = witnessed_event.clone();
-	*bad_sig_event.signature.get_mut(8).unwrap() += 1;
+	let bad_sig_event = validators.witness(0, event_id).corrupt_signature().build();
 	let result = block_state.verify_witnessed_event_origin(bad_sig_event);
 	assert!(result.is_err());
 
@@ -46,30 +84,986 @@ async fn test_verify_events() {
 	assert!(result.is_err());
 }
 
+#[test]
+fn test_verify_events_wrong_signer() {
+	// validator 0 claims an event signed by validator 1
+	let validators = TestValidators::new(2);
+	let event_id = H256::repeat_byte(0);
+	let block_state = validators.authorities();
+
+	let forged_event = validators.witness(0, event_id).signed_by(1).build();
+	assert!(block_state.verify_witnessed_event_origin(forged_event).is_err());
+}
+
+#[test]
+fn test_decode_witnessed_event() {
+	let validators = TestValidators::new(1);
+	let witnessed_event = validators.witness(0, H256::repeat_byte(1)).build();
+	let message = witnessed_event.to_bytes().unwrap();
+	let block_state = validators.authorities();
+
+	assert_eq!(block_state.decode_witnessed_event(&message), Ok(witnessed_event));
+	assert!(block_state.decode_witnessed_event(&message[..message.len() - 1]).is_err());
+	assert!(AuthoritiesList::new(vec![]).decode_witnessed_event(&message).is_err());
+}
+
+/// Hostile gossip messages, each of which must be rejected without panicking or allocating based
+/// on its length fields.
+#[rstest]
+#[case::empty(vec![])]
+#[case::unknown_version([&[WITNESSED_EVENT_VERSION + 1][..], &[0; 42]].concat())]
+#[case::huge_signature_length([WITNESSED_EVENT_VERSION, 0x03, 0xff, 0xff, 0xff, 0xff].to_vec())]
+#[case::signature_length_past_end([&[WITNESSED_EVENT_VERSION, 0x01, 0x01][..], &[0; 8]].concat())]
+#[case::huge_key_length(
+	[&[WITNESSED_EVENT_VERSION, 0][..], b"sr25", &[0x03, 0xff, 0xff, 0xff, 0xff]].concat()
+)]
+#[case::short_event_id([&[WITNESSED_EVENT_VERSION, 0][..], b"sr25", &[0], &[0; 4]].concat())]
+#[case::trailing_bytes([&[WITNESSED_EVENT_VERSION, 0][..], b"sr25", &[0], &[0; 37]].concat())]
+#[case::oversized(vec![0; MAX_WITNESSED_EVENT_SIZE as usize + 1])]
+fn test_decode_hostile_witnessed_event(#[case] message: Vec<u8>) {
+	let block_state = TestValidators::new(1).authorities();
+	assert!(block_state.decode_witnessed_event(&message).is_err());
+}
+
+/// A short run of the fuzz targets, for environments without cargo-fuzz.
+#[cfg(feature = "fuzz-smoke")]
+mod fuzz_smoke {
+	use crate::test_utils::TestValidators;
+	use proptest::{prelude::*, test_runner::TestRunner};
+	use sp_core::H256;
+
+	const CASES: u32 = 10_000;
+
+	#[test]
+	fn fuzz_smoke_decode_witnessed_event() {
+		let block_state = TestValidators::new(1).authorities();
+		TestRunner::new(ProptestConfig::with_cases(CASES))
+			.run(&prop::collection::vec(any::<u8>(), 0..512), |message| {
+				let _ = block_state.decode_witnessed_event(&message);
+				Ok(())
+			})
+			.unwrap();
+	}
+
+	#[test]
+	fn fuzz_smoke_decode_mutated_witnessed_event() {
+		let validators = TestValidators::new(1);
+		let block_state = validators.authorities();
+		let message = validators.witness(0, H256::repeat_byte(1)).build().to_bytes().unwrap();
+		let mutations = prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..8);
+		TestRunner::new(ProptestConfig::with_cases(CASES))
+			.run(&mutations, |mutations| {
+				let mut message = message.clone();
+				for (index, byte) in mutations {
+					message[index.index(message.len())] ^= byte;
+				}
+				let _ = block_state.decode_witnessed_event(&message);
+				Ok(())
+			})
+			.unwrap();
+	}
+}
+
 #[rstest]
 #[case(3, 3)]
 #[case(4, 3)]
 #[case(5, 4)]
 #[case(6, 5)]
 #[case(10, 7)]
-fn test_calculate_target(#[case] validator_count: u8, #[case] target: u16) {
-	let validators_list = (0..validator_count)
-		.map(|x| CryptoTypePublicPair::from(Public::from_h256(H256::repeat_byte(x))))
-		.collect();
-	let block_state = AuthoritiesList::new(validators_list);
+fn test_calculate_target(#[case] validator_count: usize, #[case] target: u16) {
+	let block_state = TestValidators::new(validator_count).authorities();
 
 	assert_eq!(block_state.target(), target);
 }
 
-async fn create_witnessed_event(
+fn simulated_network(seed: u64, validators: &TestValidators) -> SimulatedNetwork<SimulatedNode> {
+	let nodes = (0..validators.len())
+		.map(|_| Arc::new(SimulatedNode::new(validators.authorities())))
+		.collect();
+	SimulatedNetwork::new(seed, nodes)
+}
+
+/// Make validator `i` witness the event, as EventWitnesser does
+async fn witness(
+	network: &SimulatedNetwork<SimulatedNode>,
+	validators: &TestValidators,
+	i: usize,
 	event_id: H256,
-	keystore: &LocalKeystore,
-	key: Public,
-) -> WitnessedEvent {
-	let signature = keystore
-		.sign_with(AURA, keystore.keys(AURA).await.unwrap().get(0).unwrap(), event_id.as_bytes())
-		.await
-		.unwrap()
+) {
+	let message = validators.witness(i, event_id).build().to_bytes().unwrap();
+	network.gossip(i).publish(IdentTopic::new(WITNESSED_EVENTS_TOPIC), message).await.unwrap();
+}
+
+async fn witness_by_all(
+	network: &SimulatedNetwork<SimulatedNode>,
+	validators: &TestValidators,
+	event_id: H256,
+) {
+	for i in 0..validators.len() {
+		witness(network, validators, i, event_id).await;
+	}
+}
+
+fn submissions(network: &SimulatedNetwork<SimulatedNode>) -> Vec<Vec<H256>> {
+	(0..network.len()).map(|i| network.handler(i).submitted()).collect()
+}
+
+#[tokio::test]
+async fn test_simulated_quorum_despite_loss() {
+	let validators = TestValidators::new(4);
+	let event_id = H256::repeat_byte(1);
+	for seed in 0..20 {
+		let network = simulated_network(seed, &validators);
+		network.set_default_link(LinkConfig { loss: 0.2, ..Default::default() });
+
+		witness_by_all(&network, &validators, event_id).await;
+		network.run_until_idle().await;
+
+		assert_eq!(submissions(&network), vec![vec![event_id]; 4], "seed {seed}");
+	}
+}
+
+#[tokio::test]
+async fn test_simulated_simultaneous_threshold_is_submitted_once() {
+	// With no latency, every proof reaches every node at the same time, so all nodes cross the
+	// threshold together and keep receiving proofs after it.
+	let validators = TestValidators::new(7);
+	let event_id = H256::repeat_byte(1);
+	let network = simulated_network(0, &validators);
+	network.set_default_link(LinkConfig { latency: 0..=0, loss: 0.0 });
+
+	witness_by_all(&network, &validators, event_id).await;
+	network.run_until_idle().await;
+
+	assert_eq!(submissions(&network), vec![vec![event_id]; 7]);
+}
+
+#[tokio::test]
+async fn test_simulated_partition_healed_within_retransmissions() {
+	let validators = TestValidators::new(4);
+	let event_id = H256::repeat_byte(1);
+	let network = simulated_network(0, &validators);
+	network.set_retransmissions(3, 100);
+	network.partition(&[&[0, 1], &[2, 3]]);
+
+	witness_by_all(&network, &validators, event_id).await;
+	network.run_for(150).await;
+	assert_eq!(submissions(&network), vec![Vec::<H256>::new(); 4]);
+
+	network.heal();
+	network.run_until_idle().await;
+	assert_eq!(submissions(&network), vec![vec![event_id]; 4]);
+}
+
+#[tokio::test]
+async fn test_simulated_partition_healed_after_retransmissions() {
+	let validators = TestValidators::new(4);
+	let event_id = H256::repeat_byte(1);
+	let network = simulated_network(0, &validators);
+	network.set_retransmissions(3, 100);
+	network.partition(&[&[0, 1], &[2, 3]]);
+
+	witness_by_all(&network, &validators, event_id).await;
+	network.run_until_idle().await;
+	assert_eq!(submissions(&network), vec![Vec::<H256>::new(); 4]);
+
+	// the proofs are lost for good, until the clients witness the event again
+	network.heal();
+	witness_by_all(&network, &validators, event_id).await;
+	network.run_until_idle().await;
+	assert_eq!(submissions(&network), vec![vec![event_id]; 4]);
+}
+
+#[tokio::test]
+async fn test_simulation_is_deterministic() {
+	let validators = TestValidators::new(4);
+	let events = [H256::repeat_byte(1), H256::repeat_byte(2)];
+	let run = |seed| {
+		let validators = &validators;
+		async move {
+			let network = simulated_network(seed, validators);
+			network.set_default_link(LinkConfig { latency: 0..=100, loss: 0.3 });
+			for event_id in events {
+				witness_by_all(&network, validators, event_id).await;
+				network.run_for(20).await;
+			}
+			network.run_until_idle().await;
+			(network.trace(), submissions(&network))
+		}
+	};
+
+	assert_eq!(run(42).await, run(42).await);
+	assert_ne!(run(42).await.0, run(43).await.0);
+}
+
+/// The validators in session 1, still accepting the witnesses of session 0, so that there is a
+/// session to equivocate across.
+fn byzantine_authorities(validators: &TestValidators) -> AuthoritiesList {
+	validators.authorities().in_session(1, [(0, validators.pubkeys())])
+}
+
+fn byzantine_network(
+	validators: &TestValidators,
+	faults: &[(usize, Fault)],
+	metrics: &Metrics,
+) -> SimulatedNetwork<SimulatedValidator> {
+	let nodes = (0..validators.len())
+		.map(|i| {
+			let fault = faults.iter().find(|(j, _)| *j == i).map(|(_, fault)| *fault);
+			let authorities = byzantine_authorities(validators);
+			Arc::new(SimulatedValidator::with_metrics(i, authorities, fault, metrics.clone()))
+		})
+		.collect();
+	SimulatedNetwork::new(0, nodes)
+}
+
+/// Ask every validator to witness the event, and deliver everything they publish
+async fn witness_byzantine(
+	network: &SimulatedNetwork<SimulatedValidator>,
+	validators: &TestValidators,
+	event_id: H256,
+) {
+	for i in 0..network.len() {
+		for message in network.handler(i).witness(validators, event_id) {
+			let topic = IdentTopic::new(WITNESSED_EVENTS_TOPIC);
+			network.gossip(i).publish(topic, message).await.unwrap();
+		}
+	}
+	network.run_until_idle().await;
+}
+
+fn honest_nodes(network: &SimulatedNetwork<SimulatedValidator>) -> Vec<&SimulatedNode> {
+	(0..network.len())
+		.map(|i| network.handler(i))
+		.filter(|validator| validator.fault().is_none())
+		.map(|validator| validator.node())
+		.collect()
+}
+
+#[rstest]
+#[case::signs_garbage(Fault::SignsGarbage)]
+#[case::equivocates(Fault::Equivocates)]
+#[case::withholds(Fault::Withholds)]
+#[case::replays(Fault::Replays)]
+#[tokio::test]
+async fn test_byzantine_minority(#[case] fault: Fault) {
+	// one faulty validator out of four is the most a target of three tolerates
+	let validators = TestValidators::new(4);
+	let authorities = byzantine_authorities(&validators);
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let network = byzantine_network(&validators, &[(3, fault)], &metrics);
+	let events = [H256::repeat_byte(1), H256::repeat_byte(2)];
+
+	for event_id in events {
+		witness_byzantine(&network, &validators, event_id).await;
+	}
+
+	for node in honest_nodes(&network) {
+		assert_eq!(node.submitted(), events.to_vec());
+		for proof in node.stored_proofs() {
+			assert!(authorities.verify_witnessed_event_origin(proof).is_ok());
+		}
+		let rejects_messages = matches!(fault, Fault::SignsGarbage | Fault::Replays);
+		assert_eq!(!node.take_rejected().is_empty(), rejects_messages);
+	}
+	// Every node, the faulty one included, records the equivocation of validator 3 over each
+	// event, and nothing else is taken for one
+	let equivocations: Vec<_> = registry
+		.gather()
+		.iter()
+		.filter(|family| family.get_name() == "streams_validator_equivocations_total")
+		.flat_map(|family| family.get_metric().to_vec())
+		.map(|metric| {
+			(metric.get_label()[0].get_value().to_string(), metric.get_counter().get_value())
+		})
+		.collect();
+	let expected = match fault {
+		Fault::Equivocates => vec![("3".to_string(), 8.0)],
+		_ => vec![],
+	};
+	assert_eq!(equivocations, expected);
+}
+
+#[rstest]
+#[case::withholds(&[(2, Fault::Withholds), (3, Fault::Withholds)])]
+#[case::signs_garbage(&[(2, Fault::SignsGarbage), (3, Fault::SignsGarbage)])]
+#[case::replays(&[(1, Fault::Withholds), (2, Fault::Withholds), (3, Fault::Replays)])]
+#[case::equivocates(&[(1, Fault::Withholds), (2, Fault::Withholds), (3, Fault::Equivocates)])]
+#[tokio::test]
+async fn test_byzantine_majority(#[case] faults: &[(usize, Fault)]) {
+	let validators = TestValidators::new(4);
+	let network = byzantine_network(&validators, faults, &Metrics::default());
+
+	for event_id in [H256::repeat_byte(1), H256::repeat_byte(2)] {
+		witness_byzantine(&network, &validators, event_id).await;
+	}
+
+	for node in honest_nodes(&network) {
+		assert_eq!(node.submitted(), Vec::<H256>::new());
+	}
+}
+
+#[test]
+fn test_latest_authorities_follow_finality() {
+	let validators = TestValidators::new(4);
+	let chain = FakeChain::new(validators.pubkeys());
+	let validator_set = validator_set();
+	let latest = || {
+		get_latest_authorities_list::<TestBlock, _, Public>(&validator_set, &chain)
+			.unwrap()
+			.authorities
+			.to_vec()
+	};
+
+	chain.rotate_authorities(validators.pubkeys()[..3].to_vec());
+	// the rotation only takes effect once finalized
+	assert_eq!(latest(), validators.pubkeys());
+	chain.finalize_best();
+	assert_eq!(latest(), validators.pubkeys()[..3].to_vec());
+	chain.set_finalized(0);
+	assert_eq!(latest(), validators.pubkeys());
+}
+
+#[test]
+fn test_witnesses_of_stale_sessions_rejected() {
+	let validators = TestValidators::new(4);
+	let chain = FakeChain::new(validators.pubkeys());
+	chain.set_session_grace(2);
+	// Sessions 1 to 5, the last one without validator 3
+	for _ in 1..5 {
+		chain.rotate_authorities(validators.pubkeys());
+	}
+	chain.rotate_authorities(validators.pubkeys()[..3].to_vec());
+	chain.finalize_best();
+	let set =
+		get_latest_authorities_list::<TestBlock, _, Public>(&validator_set(), &chain).unwrap();
+	assert_eq!((set.session, set.oldest_session()), (5, 3));
+	let event_id = H256::repeat_byte(1);
+	let decode = |i, session| {
+		let witness = validators.witness(i, event_id).in_session(session).build();
+		set.decode_witnessed_event(&witness.to_bytes().unwrap())
+	};
+
+	// The previous session N-1 is within the grace window, even for the rotated out validator 3
+	assert!(decode(0, 4).is_ok());
+	assert!(decode(3, 4).is_ok());
+	assert!(decode(0, 5).is_ok());
+	assert!(matches!(decode(3, 5), Err(Error::BadWitnessedEventSignature(_))));
+	// While N-5 is long past it, and the future is not known yet
+	assert_eq!(decode(0, 0), Err(Error::StaleSession { session: 0, current: 5 }));
+	assert_eq!(decode(0, 2), Err(Error::StaleSession { session: 2, current: 5 }));
+	assert_eq!(decode(0, 6), Err(Error::StaleSession { session: 6, current: 5 }));
+	// A signature of another session cannot be passed off as one of the current session
+	let replayed = validators.witness(0, event_id).in_session(5);
+	let replayed = replayed.signing(&witness_payload(&event_id, 0)).build();
+	assert!(set.verify_witnessed_event_origin(replayed).is_err());
+}
+
+#[test]
+fn test_authorities_membership() {
+	let validators = TestValidators::new(4);
+	let mut keys = validators.pubkeys()[..3].to_vec();
+	keys.push(validators.pub_key(0));
+	let authorities = AuthoritiesList::new(keys);
+
+	assert!(authorities.contains(&validators.pub_key(2)));
+	assert!(!authorities.contains(&validators.pub_key(3)));
+	assert_eq!(authorities.position(&validators.pub_key(2)), Some(2));
+	// A key listed twice keeps its first position
+	assert_eq!(authorities.position(&validators.pub_key(0)), Some(0));
+	assert_eq!(authorities.position(&validators.pub_key(3)), None);
+	// Clones share the list instead of copying it
+	assert!(Arc::ptr_eq(&authorities.authorities, &authorities.clone().authorities));
+}
+
+#[test]
+fn test_latest_set_swapped_on_finality() {
+	let validators = TestValidators::new(4);
+	let chain = FakeChain::new(validators.pubkeys());
+	let validator_set = validator_set();
+	let shared = validator_set.clone();
+	let latest = || get_latest_authorities_list::<TestBlock, _, Public>(&validator_set, &chain);
+	assert!(shared.current().unwrap().is_none());
+
+	let first = latest().unwrap();
+	let current = shared.current().unwrap().unwrap();
+	assert!(Arc::ptr_eq(&first.authorities, &current.authorities));
+	// Until another block gets finalized, every read shares the same set
+	assert!(Arc::ptr_eq(&first.authorities, &latest().unwrap().authorities));
+
+	chain.rotate_authorities(validators.pubkeys()[..3].to_vec());
+	chain.finalize_best();
+	assert_eq!(latest().unwrap().authorities.len(), 3);
+	assert_eq!(shared.current().unwrap().unwrap().authorities.len(), 3);
+}
+
+#[test]
+fn test_set_swapped_while_verifying() {
+	const THREADS: usize = 4;
+	const ROUNDS: u64 = 1000;
+	let validators = TestValidators::new(7);
+	let chain = FakeChain::new(validators.pubkeys());
+	chain.rotate_authorities(validators.pubkeys()[..4].to_vec());
+	let validator_set = validator_set();
+	// Signed by a validator which is only part of the set at genesis
+	let rotated_out = validators.pub_key(6);
+	let message = validators.witness(6, H256::repeat_byte(1)).build().to_bytes().unwrap();
+
+	let (done, finished) = mpsc::channel();
+	std::thread::spawn(move || {
+		let (chain, validator_set) = (&chain, &validator_set);
+		let (rotated_out, message) = (&rotated_out, &message);
+		std::thread::scope(|scope| {
+			// Finality going back and forth between the two sets
+			scope.spawn(move || (0..ROUNDS).for_each(|round| chain.set_finalized(round % 2)));
+			let verifiers: Vec<_> = (0..THREADS)
+				.map(|_| {
+					scope.spawn(move || {
+						for _ in 0..ROUNDS {
+							let set = get_latest_authorities_list::<TestBlock, _, Public>(
+								validator_set,
+								chain,
+							)
+							.unwrap();
+							// The list, the membership, the threshold and the verification all
+							// agree on a single set
+							let member = set.contains(rotated_out);
+							assert_eq!(set.authorities.len(), if member { 7 } else { 4 });
+							assert_eq!(set.target(), if member { 5 } else { 3 });
+							assert_eq!(set.decode_witnessed_event(message).is_ok(), member);
+						}
+					})
+				})
+				.collect();
+			verifiers.into_iter().for_each(|verifier| verifier.join().unwrap());
+		});
+		// Once the swaps are over, the handle holds the set at the last finalized block
+		get_latest_authorities_list::<TestBlock, _, Public>(validator_set, chain).unwrap();
+		done.send(validator_set.current().unwrap().unwrap().authorities.len()).unwrap();
+	});
+	let latest = finished.recv_timeout(Duration::from_secs(30)).expect("verifiers failed");
+	assert_eq!(latest, 4);
+}
+
+#[tokio::test]
+async fn test_witness_after_session_change() {
+	let validators = TestValidators::new(4);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let network = simulated_network(0, &validators);
+	let witnesser = EventWitnesser::<TestBlock, _, Public, _>::new(
+		chain.clone(),
+		network.gossip(3),
+		validators.keystore(3),
+		validator_set(),
+		Metrics::default(),
+		Traces::default(),
+	);
+
+	witnesser.witness_event(H256::repeat_byte(1)).await.unwrap();
+	network.run_until_idle().await;
+	let proofs = network.handler(0).stored_proofs();
+	assert!(proofs.iter().any(|proof| proof.pub_key == validators.pub_key(3)));
+
+	// validator 3 is rotated out
+	chain.rotate_authorities(validators.pubkeys()[..3].to_vec());
+	chain.finalize_best();
+	assert_eq!(witnesser.witness_event(H256::repeat_byte(2)).await, Err(Error::NotAValidator));
+}
+
+#[tokio::test]
+async fn test_submission_through_pool() {
+	// A single validator reaches the target with its own witness
+	let validators = TestValidators::new(1);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let pool = Arc::new(TestPool::default());
+	let handler = EventGossipHandler::<_, _, _, Public, TestBlock>::new(
+		chain.clone(),
+		Arc::new(TestProofs::new()),
+		pool.clone(),
+		validator_set(),
+		Metrics::default(),
+		Traces::default(),
+	);
+	let witness = |event| validators.witness(0, H256::repeat_byte(event)).build().to_bytes();
+
+	// A dropped extrinsic is submitted again once the event is witnessed again
+	pool.fail_next(PoolError::ImmediatelyDropped);
+	handler.handle(&witness(1).unwrap()).await;
+	assert_eq!(pool.submitted(), Vec::<H256>::new());
+	handler.handle(&witness(1).unwrap()).await;
+	assert_eq!(pool.submitted(), vec![H256::repeat_byte(1)]);
+
+	// Accepted and already-imported extrinsics are not submitted again
+	handler.handle(&witness(1).unwrap()).await;
+	pool.fail_next(PoolError::AlreadyImported(Box::new(())));
+	handler.handle(&witness(2).unwrap()).await;
+	handler.handle(&witness(2).unwrap()).await;
+	assert_eq!(pool.submitted(), vec![H256::repeat_byte(1)]);
+
+	let created = chain.created_extrinsics();
+	assert_eq!(created.len(), 3);
+	assert!(created.iter().all(|extrinsic| extrinsic.at == FakeChain::hash(0)));
+}
+
+#[test]
+fn test_seen_witnesses_expire_and_match_signatures() {
+	let validators = TestValidators::new(2);
+	let event_id = H256::repeat_byte(1);
+	let witness = validators.witness(0, event_id).build();
+	let mut seen = SeenWitnesses::new(NonZeroUsize::new(2).unwrap(), Duration::from_secs(10));
+	let now = Instant::now();
+	assert!(!seen.contains(&witness, now));
+
+	seen.insert(&witness, now);
+	assert!(seen.contains(&witness, now + Duration::from_secs(9)));
+	// Another signature of the same validator, or another validator, is not a copy
+	assert!(!seen.contains(&validators.witness(0, event_id).build(), now));
+	assert!(!seen.contains(&validators.witness(1, event_id).build(), now));
+	let retargeted = WitnessedEvent { event_id: H256::repeat_byte(2), ..witness.clone() };
+	assert!(!seen.contains(&retargeted, now));
+	// Expired, and forgotten
+	assert!(!seen.contains(&witness, now + Duration::from_secs(10)));
+	assert!(!seen.contains(&witness, now));
+}
+
+#[test]
+fn test_collected_witness_copies_dropped_unverified() {
+	let validators = TestValidators::new(4);
+	let authorities = validators.authorities();
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let collector = EventProofsCollector::new(Arc::new(TestProofs::new()), metrics);
+	let event_id = H256::repeat_byte(1);
+	let message = validators.witness(0, event_id).build().to_bytes().unwrap();
+
+	assert_eq!(collector.collect(&authorities, &message), Ok(None));
+	assert_eq!(collector.collect(&authorities, &message), Err(Error::DuplicateWitness));
+	// A forged copy is verified, and rejected
+	let forged = validators.witness(0, event_id).signed_by(1).build().to_bytes().unwrap();
+	assert!(matches!(
+		collector.collect(&authorities, &forged),
+		Err(Error::BadWitnessedEventSignature(_))
+	));
+	let families = registry.gather();
+	let counter = |name: &str| {
+		let family = families.iter().find(|family| family.get_name() == name).unwrap();
+		family.get_metric()[0].get_counter().get_value()
+	};
+	assert_eq!(counter("streams_witnesses_duplicate_total"), 1.0);
+	assert_eq!(counter("streams_witnesses_rejected_total"), 1.0);
+}
+
+#[test]
+fn test_equivocations_recorded() {
+	let validators = TestValidators::new(4);
+	let authorities = validators.authorities().in_session(1, [(0, validators.pubkeys())]);
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let collector = EventProofsCollector::new(Arc::new(TestProofs::new()), metrics);
+	let event_id = H256::repeat_byte(1);
+	let witness = |session| validators.witness(2, event_id).in_session(session).build();
+
+	assert_eq!(collector.collect(&authorities, &witness(1).to_bytes().unwrap()), Ok(None));
+	// Signed again in the same session, e.g. for a client retrying, without conflicting
+	assert_eq!(collector.collect(&authorities, &witness(1).to_bytes().unwrap()), Ok(None));
+	// Vouched for in another session, still accepted, and counted once
+	assert_eq!(collector.collect(&authorities, &witness(0).to_bytes().unwrap()), Ok(None));
+	assert_eq!(collector.proof_count(&event_id, &validators.pubkeys()), Ok(1));
+	let families = registry.gather();
+	let family = families
+		.iter()
+		.find(|family| family.get_name() == "streams_validator_equivocations_total")
 		.unwrap();
-	WitnessedEvent { event_id, pub_key: CryptoTypePublicPair::from(key), signature }
+	let metric = &family.get_metric()[0];
+	assert_eq!(metric.get_label()[0].get_value(), "2");
+	assert_eq!(metric.get_counter().get_value(), 1.0);
+}
+
+/// Many threads collecting the same witnesses at once, as the gossip handlers of a busy node would,
+/// through the collector's pending and submitted events and the metrics' bookkeeping.
+#[test]
+fn test_collector_under_contention() {
+	const THREADS: usize = 8;
+	let validators = TestValidators::new(4);
+	let authorities = validators.authorities();
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let collector = EventProofsCollector::new(Arc::new(TestProofs::new()), metrics);
+	let events: Vec<_> = (0..64).map(H256::from_low_u64_be).collect();
+	let messages: Vec<_> = events
+		.iter()
+		.flat_map(|event_id| (0..4).map(|i| validators.witness(i, *event_id).build()))
+		.map(|witness| witness.to_bytes().unwrap())
+		.collect();
+
+	let (done, finished) = mpsc::channel();
+	std::thread::spawn(move || {
+		let (collector, authorities, messages) = (&collector, &authorities, &messages);
+		let collected: Vec<H256> = std::thread::scope(|scope| {
+			let threads: Vec<_> = (0..THREADS)
+				.map(|thread| {
+					scope.spawn(move || {
+						// Every thread goes through all messages, each from a different offset
+						let offset = thread * messages.len() / THREADS;
+						let mut collected = Vec::new();
+						for message in messages.iter().cycle().skip(offset).take(messages.len()) {
+							match collector.collect(authorities, message) {
+								Ok(Some(event_id)) => {
+									collector.mark_submitted(event_id).unwrap();
+									collected.push(event_id);
+								},
+								Ok(None) | Err(Error::DuplicateWitness) => {},
+								Err(e) => panic!("rejected a witness: {e}"),
+							}
+						}
+						collected
+					})
+				})
+				.collect();
+			threads.into_iter().flat_map(|thread| thread.join().unwrap()).collect()
+		});
+		done.send(collected).unwrap();
+	});
+	let collected = finished.recv_timeout(Duration::from_secs(30)).expect("collectors deadlocked");
+
+	// Every event reached the target, and none of them is left pending
+	let collected: BTreeSet<_> = collected.into_iter().collect();
+	assert_eq!(collected, events.into_iter().collect());
+	let pending = registry
+		.gather()
+		.into_iter()
+		.find(|family| family.get_name() == "streams_pending_events")
+		.map(|family| family.get_metric()[0].get_gauge().get_value());
+	assert_eq!(pending, Some(0.0));
+}
+
+#[test]
+fn test_verify_events_validity_at_block() {
+	let validators = TestValidators::new(4);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let proofs = Arc::new(TestProofs::new());
+	let event_id = H256::repeat_byte(1);
+	for i in 0..3 {
+		proofs.add_event_proof(&validators.witness(i, event_id).build()).unwrap();
+	}
+	let rotated = chain.rotate_authorities(validators.pubkeys()[1..].to_vec());
+
+	let unwitnessed = |at| {
+		verify_events_validity::<TestBlock, _, _, Public>(
+			&validator_set(),
+			chain.clone(),
+			at,
+			proofs.clone(),
+			vec![event_id],
+		)
+		.unwrap()
+	};
+	// three out of four authorities at genesis, but only two out of three after the rotation
+	assert_eq!(unwitnessed(FakeChain::hash(0)), Vec::<H256>::new());
+	assert_eq!(unwitnessed(rotated), vec![event_id]);
+}
+
+#[test]
+fn test_proof_reader_reads_the_latest_validators() {
+	let validators = TestValidators::new(4);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let proofs = Arc::new(InMemoryEventProofs::new());
+	let event_id = H256::repeat_byte(1);
+	for i in [3, 0, 1] {
+		proofs.add_event_proof(&validators.witness(i, event_id).build()).unwrap();
+	}
+	let reader = EventProofReader::<TestBlock, _, Public, _>::new(
+		chain.clone(),
+		proofs.clone(),
+		validator_set(),
+	);
+
+	let read = reader.get_latest_event_proofs(&event_id).unwrap();
+	assert_eq!((read.session, read.validators.clone(), read.target), (0, validators.pubkeys(), 3));
+	let signers: Vec<_> = read.proofs.iter().map(|(pub_key, _)| pub_key.clone()).collect();
+	assert_eq!(signers, [0, 1, 3].map(|i| validators.pub_key(i)));
+	let (_, proof) = &read.proofs[0];
+	assert_eq!(*proof, validators.witness(0, event_id).build().proof());
+
+	// Only the proofs of the validators at the last finalized block
+	chain.rotate_authorities(validators.pubkeys()[1..].to_vec());
+	chain.finalize_best();
+	let read = reader.get_latest_event_proofs(&event_id).unwrap();
+	assert_eq!((read.session, read.validators.len(), read.target), (1, 3, 3));
+	let signers: Vec<_> = read.proofs.iter().map(|(pub_key, _)| pub_key.clone()).collect();
+	assert_eq!(signers, [1, 3].map(|i| validators.pub_key(i)));
+	assert!(reader.get_latest_event_proofs(&H256::repeat_byte(2)).unwrap().proofs.is_empty());
+}
+
+#[tokio::test]
+async fn test_membership_proven_by_current_validators() {
+	let validators = TestValidators::new(5);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()[..4].to_vec()));
+	let validator_set = validator_set();
+	let membership = |i| {
+		let (chain, keystore) = (chain.clone(), validators.keystore(i));
+		ValidatorMembership::<TestBlock, _, Public>::new(chain, keystore, validator_set.clone())
+	};
+	let (peer, other_peer) = (PeerId::random(), PeerId::random());
+
+	let proof = membership(0).prove(peer).await.unwrap();
+	assert_eq!(proof.pub_key, validators.pub_key(0));
+	assert!(membership(1).verify(&peer, &proof));
+	// Bound to the peer ID, so that another peer cannot pass it on as its own
+	assert!(!membership(1).verify(&other_peer, &proof));
+	let mut forged = proof.clone();
+	forged.pub_key = validators.pub_key(1);
+	assert!(!membership(1).verify(&peer, &forged));
+	assert_eq!(membership(4).prove(peer).await, None);
+
+	// validator 0 is rotated out
+	chain.rotate_authorities(validators.pubkeys()[1..4].to_vec());
+	chain.finalize_best();
+	assert!(!membership(1).verify(&peer, &proof));
+	assert_eq!(membership(0).prove(peer).await, None);
+}
+
+#[tokio::test]
+async fn test_grpc_event_proofs_verify_independently() {
+	let validators = TestValidators::new(3);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let proofs = Arc::new(InMemoryEventProofs::new());
+	let event_id = H256::repeat_byte(1);
+	for i in 0..3 {
+		proofs.add_event_proof(&validators.witness(i, event_id).build()).unwrap();
+	}
+	let (witnesser, _network) = single_node(&validators, chain.clone());
+	let grpc = ValidatedStreamsGrpc {
+		event_proofs: Arc::new(EventProofReader::<TestBlock, _, Public, _>::new(
+			chain,
+			proofs,
+			validator_set(),
+		)),
+		..ValidatedStreamsGrpc::for_tests(witnesser)
+	};
+
+	let request = Request::new(GetEventProofRequest { event_id: event_id.0.to_vec() });
+	let response = grpc.get_event_proof(request).await.unwrap().into_inner();
+	let expected: Vec<_> = (0..3).map(|i| validators.public(i).to_raw_vec()).collect();
+	assert_eq!((response.session, response.validators, response.target), (0, expected, 3));
+	// Every signature checks out against the witness payload alone
+	assert_eq!(response.signatures.len(), 3);
+	for signature in response.signatures {
+		let public = Public::from_slice(&signature.public_key).unwrap();
+		let payload = witness_payload(&event_id, signature.session);
+		let signature = Signature::from_slice(&signature.signature).unwrap();
+		assert!(Pair::verify(&signature, payload, &public));
+	}
+
+	let request = Request::new(GetEventProofRequest { event_id: vec![1; 31] });
+	let status = grpc.get_event_proof(request).await.unwrap_err();
+	assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_grpc_failures_answered_with_their_codes() {
+	let validators = TestValidators::new(2);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let proofs = Arc::new(TestProofs::new());
+	let shutdown = ShutdownSignal::default();
+	let (witnesser, _network) = single_node(&validators, chain.clone());
+	let grpc = ValidatedStreamsGrpc {
+		event_proofs: Arc::new(EventProofReader::<TestBlock, _, Public, _>::new(
+			chain.clone(),
+			proofs.clone(),
+			validator_set(),
+		)),
+		..ValidatedStreamsGrpc::for_tests(witnesser.with_shutdown(shutdown.clone()))
+	};
+	let event_id = vec![1; 32];
+
+	// Failures of the node itself
+	proofs.fail_next(Error::Database("store closed".to_string()));
+	let request = Request::new(GetEventProofRequest { event_id: event_id.clone() });
+	let status = grpc.get_event_proof(request).await.unwrap_err();
+	assert_eq!(status.code(), Code::Internal, "{status}");
+
+	// Witnessing refused until the node is a validator again
+	chain.rotate_authorities(validators.pubkeys()[1..].to_vec());
+	chain.finalize_best();
+	let event = WitnessEventRequest { event_id, ..Default::default() };
+	let witness = || Request::new(event.clone());
+	let status = grpc.witness_event(witness()).await.unwrap_err();
+	assert_eq!(status.code(), Code::FailedPrecondition, "{status}");
+
+	// Witnessing refused for good, to be retried on another node
+	shutdown.advance(ShutdownStage::StoppingWitnessing);
+	let status = grpc.witness_event(witness()).await.unwrap_err();
+	assert_eq!(status.code(), Code::Unavailable, "{status}");
+
+	// Publications refused while the gossip queue is full, to be retried once it drains
+	let status = Status::from(Error::GossipOverloaded("the order queue is full".to_string()));
+	assert_eq!(status.code(), Code::ResourceExhausted, "{status}");
+	let status = Status::from(Error::GossipUnavailable("the gossip is not running".to_string()));
+	assert_eq!(status.code(), Code::Unavailable, "{status}");
+}
+
+#[tokio::test]
+async fn test_witnessing_set_follows_the_session() {
+	let validators = TestValidators::new(4);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let network = simulated_network(0, &validators);
+	let witnesser = |role| {
+		EventWitnesser::<TestBlock, _, Public, _>::new(
+			chain.clone(),
+			network.gossip(3),
+			validators.keystore(3),
+			validator_set(),
+			Metrics::default(),
+			Traces::default(),
+		)
+		.with_role(LocalRole::new(role, Metrics::default()))
+	};
+
+	let set = witnesser(NodeRole::Validator).witnessing_set().await.unwrap();
+	assert_eq!((set.session, set.validators, set.target), (0, validators.pubkeys(), 3));
+	assert!(set.witnessing);
+	assert!(!witnesser(NodeRole::Observer).witnessing_set().await.unwrap().witnessing);
+
+	// validator 3 is rotated out
+	chain.rotate_authorities(validators.pubkeys()[..3].to_vec());
+	chain.finalize_best();
+	let set = witnesser(NodeRole::Validator).witnessing_set().await.unwrap();
+	assert_eq!((set.session, set.validators.len(), set.target), (1, 3, 3));
+	assert!(!set.witnessing);
+}
+
+#[tokio::test]
+async fn test_liveness_beats_mark_validators_online_until_silent() {
+	let validators = TestValidators::new(3);
+	let authorities = AuthoritiesList::new(validators.pubkeys()[..2].to_vec());
+	let liveness = ValidatorLiveness::new(Duration::from_secs(30), Metrics::default());
+	let beat = |i: usize, witnessed, sent_at| {
+		let pub_key = validators.pub_key(i);
+		let payload = LivenessBeat::payload(&pub_key, witnessed, sent_at);
+		let signature = validators.pair(i).sign(&payload).0.to_vec();
+		LivenessBeat { pub_key, witnessed, sent_at, signature }
+	};
+	let (now, now_ms) = (Instant::now(), 1_000_000);
+	let on_beat = |beat: &LivenessBeat, now, now_ms| {
+		liveness.on_beat(&authorities, &beat.to_bytes(), now, now_ms)
+	};
+
+	let signed =
+		liveness.sign(validators.keystore(0).as_ref(), &authorities, now_ms).await.unwrap();
+	assert_eq!(signed.as_ref().map(LivenessBeat::verify), Some(true));
+	assert_eq!(on_beat(&signed.unwrap(), now, now_ms), MessageAcceptance::Accept);
+	assert_eq!(on_beat(&beat(1, 5, now_ms), now, now_ms), MessageAcceptance::Accept);
+	assert_eq!(liveness.online(), vec![(validators.pub_key(0), 0), (validators.pub_key(1), 5)]);
+
+	// Replayed, forged, undecodable, or from outside the set
+	assert_eq!(on_beat(&beat(1, 5, now_ms), now, now_ms), MessageAcceptance::Ignore);
+	let mut forged = beat(1, 5, now_ms + 1);
+	forged.witnessed = 50;
+	assert_eq!(on_beat(&forged, now, now_ms), MessageAcceptance::Reject);
+	let undecodable = &beat(1, 5, now_ms + 1).to_bytes()[..40];
+	assert_eq!(liveness.on_beat(&authorities, undecodable, now, now_ms), MessageAcceptance::Reject);
+	assert_eq!(on_beat(&beat(2, 5, now_ms), now, now_ms), MessageAcceptance::Ignore);
+	let outsider = liveness.sign(validators.keystore(2).as_ref(), &authorities, now_ms).await;
+	assert_eq!(outsider, Ok(None));
+	// Sent too long ago to tell anything
+	let late = now + Duration::from_secs(100);
+	assert_eq!(on_beat(&beat(1, 6, now_ms), late, now_ms + 100_000), MessageAcceptance::Ignore);
+
+	// validator 0 goes silent for three intervals, while validator 1 keeps beating
+	let later_ms = now_ms + 60_000;
+	let later = now + Duration::from_secs(60);
+	assert_eq!(on_beat(&beat(1, 7, later_ms), later, later_ms), MessageAcceptance::Accept);
+	liveness.check(&authorities, now + Duration::from_secs(89));
+	assert_eq!(liveness.online().len(), 2);
+	liveness.check(&authorities, now + Duration::from_secs(90));
+	assert_eq!(liveness.online(), vec![(validators.pub_key(1), 7)]);
+
+	// validator 1 is rotated out
+	liveness.check(&AuthoritiesList::new(validators.pubkeys()[..1].to_vec()), later);
+	assert!(liveness.online().is_empty());
+}
+
+#[tokio::test]
+async fn test_liveness_beats_gossiped_with_witnessed_counts() {
+	let validators = TestValidators::new(1);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let liveness = ValidatorLiveness::new(Duration::from_secs(30), Metrics::default());
+	let handler = EventGossipHandler::<_, _, _, Public, TestBlock>::new(
+		chain.clone(),
+		Arc::new(TestProofs::new()),
+		Arc::new(TestPool::default()),
+		validator_set(),
+		Metrics::default(),
+		Traces::default(),
+	)
+	.with_liveness(liveness.clone());
+	assert!(handler.get_topics().contains(&IdentTopic::new(LIVENESS_TOPIC)));
+	let (witnesser, _network) = single_node(&validators, chain.clone());
+	let witnesser = witnesser.with_liveness(liveness.clone());
+
+	witnesser.witness_event(H256::repeat_byte(1)).await.unwrap();
+	witnesser.witness_event(H256::repeat_byte(2)).await.unwrap();
+	assert_eq!(liveness.witnessed(), 2);
+	let authorities = validators.authorities();
+	let sent_at = unix_millis();
+	let beat = liveness.sign(validators.keystore(0).as_ref(), &authorities, sent_at).await;
+	let message = beat.unwrap().unwrap().to_bytes();
+	assert_eq!(handler.handle_received(&message).await, MessageAcceptance::Accept);
+	assert_eq!(liveness.online(), vec![(validators.pub_key(0), 2)]);
+	assert_eq!(handler.handle_received(&message).await, MessageAcceptance::Ignore);
+
+	// Handlers not following the beats ignore them, without taking them for witnesses of an
+	// unsupported version
+	let registry = Registry::new();
+	let handler = EventGossipHandler::<_, _, _, Public, TestBlock>::new(
+		chain,
+		Arc::new(TestProofs::new()),
+		Arc::new(TestPool::default()),
+		validator_set(),
+		Metrics::register(Some(&registry)).unwrap(),
+		Traces::default(),
+	);
+	assert!(!handler.get_topics().contains(&IdentTopic::new(LIVENESS_TOPIC)));
+	assert_eq!(handler.handle_received(&message).await, MessageAcceptance::Ignore);
+	let families = registry.gather();
+	let incompatible = families
+		.iter()
+		.find(|family| family.get_name() == "streams_gossip_incompatible_total")
+		.map_or(0, |family| family.get_metric().len());
+	assert_eq!(incompatible, 0);
+}
+
+#[tokio::test]
+async fn test_witnesses_of_unsupported_versions_ignored() {
+	let validators = TestValidators::new(1);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let pool = Arc::new(TestPool::default());
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let handler = EventGossipHandler::<_, _, _, Public, TestBlock>::new(
+		chain,
+		Arc::new(TestProofs::new()),
+		pool.clone(),
+		validator_set(),
+		metrics,
+		Traces::default(),
+	);
+	let mut message = validators.witness(0, H256::repeat_byte(1)).build().to_bytes().unwrap();
+
+	// Not held against the peer, which may run a compatible release and only relay it
+	message[0] = WITNESSED_EVENT_VERSION + 1;
+	assert_eq!(handler.handle_received(&message).await, MessageAcceptance::Ignore);
+	message[0] = WITNESSED_EVENT_VERSION;
+	message.push(0);
+	assert_eq!(handler.handle_received(&message).await, MessageAcceptance::Reject);
+	assert_eq!(pool.submitted(), Vec::<H256>::new());
+	let families = registry.gather();
+	let counter = |name: &str| {
+		let family = families.iter().find(|family| family.get_name() == name).unwrap();
+		family.get_metric()[0].get_counter().get_value()
+	};
+	assert_eq!(counter("streams_gossip_incompatible_total"), 1.0);
+	assert_eq!(counter("streams_witnesses_rejected_total"), 1.0);
 }
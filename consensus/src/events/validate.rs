@@ -23,21 +23,17 @@ impl<Client: ProvideRuntimeApi<Block>, Block: BlockT> EventValidator<Client, Blo
 	}
 }
 
-#[async_trait]
-impl<Client, Block: BlockT> EventValidatorTrait for EventValidator<Client, Block>
+impl<Client, Block: BlockT> EventValidator<Client, Block>
 where
 	Client: HeaderBackend<Block>
 		+ HeaderMetadata<Block>
 		+ BlockBackend<Block>
 		+ BlockchainEvents<Block>
-		+ ProvideRuntimeApi<Block>
-		+ Sync
-		+ Send
-		+ 'static,
-	Client::Api: ValidatedStreamsApi<Block>,
+		+ ProvideRuntimeApi<Block>,
 	<<Block as BlockT>::Header as HeaderT>::Number: Into<u32>,
 {
-	async fn get_finalized_block_events(&self, block_num: u32) -> Result<Vec<H256>, Error> {
+	/// The hash and the extrinsics of the block at block_num, once it is finalized.
+	async fn wait_finalized_block(&self, block_num: u32) -> (Block::Hash, Vec<Block::Extrinsic>) {
 		let mut last_finalized = self.client.info().finalized_hash;
 
 		let block_id = loop {
@@ -60,6 +56,26 @@ where
 		};
 
 		let block_extrinsics = self.client.block_body(block_id).ok().flatten().unwrap_or_default();
+		(block_id, block_extrinsics)
+	}
+}
+
+#[async_trait]
+impl<Client, Block: BlockT> EventValidatorTrait for EventValidator<Client, Block>
+where
+	Client: HeaderBackend<Block>
+		+ HeaderMetadata<Block>
+		+ BlockBackend<Block>
+		+ BlockchainEvents<Block>
+		+ ProvideRuntimeApi<Block>
+		+ Sync
+		+ Send
+		+ 'static,
+	Client::Api: ValidatedStreamsApi<Block>,
+	<<Block as BlockT>::Header as HeaderT>::Number: Into<u32>,
+{
+	async fn get_finalized_block_events(&self, block_num: u32) -> Result<Vec<H256>, Error> {
+		let (block_id, block_extrinsics) = self.wait_finalized_block(block_num).await;
 
 		Ok(self
 			.client
@@ -68,6 +84,22 @@ where
 			.unwrap_or_default())
 	}
 
+	async fn get_finalized_block_indexed_events(
+		&self,
+		block_num: u32,
+	) -> Result<Vec<(u32, H256)>, Error> {
+		let (block_id, block_extrinsics) = self.wait_finalized_block(block_num).await;
+
+		// The runtime only tells which events a list of extrinsics validates, so ask it about each
+		// extrinsic on its own to learn where the events are
+		let mut events = Vec::new();
+		for (extrinsic_index, extrinsic) in block_extrinsics.into_iter().enumerate() {
+			let ids = self.client.runtime_api().get_extrinsic_ids(block_id, &vec![extrinsic])?;
+			events.extend(ids.into_iter().map(|id| (extrinsic_index as u32, id)));
+		}
+		Ok(events)
+	}
+
 	async fn get_latest_finalized_block(&self) -> Result<u32, Error> {
 		Ok(self.client.info().finalized_number.into())
 	}
@@ -1,85 +1,191 @@
 //! Service which witnesses events from the trusted client
 
-use super::{get_latest_authorities_list, gossip::WITNESSED_EVENTS_TOPIC, AuthoritiesList};
-use crate::{errors::Error, gossip::Gossip, proofs::WitnessedEvent, traits::EventWitnesserTrait};
+use super::{get_latest_authorities_list, ValidatorLiveness, ValidatorSetHandle, WitnessingSet};
+use crate::{
+	errors::Error,
+	gossip::{Gossip, GossipTrait},
+	logging::SERVICE,
+	metrics::Metrics,
+	proofs::WitnessedEvent,
+	role::LocalRole,
+	shutdown::{ShutdownSignal, ShutdownStage},
+	status::{EventStatus, EventStatuses},
+	streams::{stream_topic, EventStreams},
+	telemetry::NodeRole,
+	traces::Traces,
+	traits::{ChainAccess, EventWitnesserTrait},
+	tunables::{Tunables, WitnessMode},
+};
 use async_trait::async_trait;
-use codec::Codec;
-use libp2p::gossipsub::IdentTopic;
-use lru::LruCache;
-use pallet_validated_streams::ValidatedStreamsApi;
-use sc_client_api::HeaderBackend;
-use sp_api::{BlockT, ProvideRuntimeApi};
-use sp_consensus_aura::AuraApi;
+use opentelemetry::Context;
+use pallet_validated_streams::payload::witness_payload;
+use sp_api::BlockT;
 use sp_core::H256;
 use sp_keystore::CryptoStore;
-use sp_runtime::{app_crypto::CryptoTypePublicPair, key_types::AURA};
-use std::{
-	marker::PhantomData,
-	sync::{Arc, Mutex},
-};
+use sp_runtime::key_types::AURA;
+use std::{marker::PhantomData, sync::Arc};
 
 /// A utility which signs and submits proofs for events we have witnessed.
-pub struct EventWitnesser<Block: BlockT, Client, AuthorityId> {
+pub struct EventWitnesser<Block: BlockT, Client, AuthorityId, G = Gossip> {
 	client: Arc<Client>,
-	gossip: Gossip,
+	gossip: G,
 	keystore: Arc<dyn CryptoStore>,
-	block_state: Arc<Mutex<LruCache<<Block as BlockT>::Hash, AuthoritiesList>>>,
+	validator_set: ValidatorSetHandle<Block>,
+	metrics: Metrics,
+	traces: Traces,
+	/// Whether the node currently witnesses events
+	role: LocalRole,
+	tunables: Tunables,
+	shutdown: ShutdownSignal,
+	statuses: EventStatuses,
+	streams: EventStreams,
+	liveness: Option<ValidatorLiveness>,
 	phantom: PhantomData<(Block, AuthorityId)>,
 }
 
-impl<Block, Client, AuthorityId> EventWitnesser<Block, Client, AuthorityId>
+impl<Block, Client, AuthorityId, G> EventWitnesser<Block, Client, AuthorityId, G>
 where
 	Block: BlockT,
+	G: GossipTrait,
 {
 	/// Creates a new EventService
 	pub fn new(
 		client: Arc<Client>,
-		gossip: Gossip,
+		gossip: G,
 		keystore: Arc<dyn CryptoStore>,
-		block_state: Arc<Mutex<LruCache<<Block as BlockT>::Hash, AuthoritiesList>>>,
+		validator_set: ValidatorSetHandle<Block>,
+		metrics: Metrics,
+		traces: Traces,
 	) -> Self {
-		Self { client, gossip, keystore, phantom: PhantomData, block_state }
+		Self {
+			client,
+			gossip,
+			keystore,
+			phantom: PhantomData,
+			validator_set,
+			metrics,
+			traces,
+			role: LocalRole::default(),
+			tunables: Tunables::default(),
+			shutdown: ShutdownSignal::default(),
+			statuses: EventStatuses::default(),
+			streams: EventStreams::default(),
+			liveness: None,
+		}
+	}
+
+	/// Makes the witnesser refuse to witness any event while the node observes, as it does when its
+	/// key is not in the validator set.
+	pub fn with_role(mut self, role: LocalRole) -> Self {
+		self.role = role;
+		self
+	}
+
+	/// Makes the witnesser refuse to witness any event while the [WitnessMode] of the tunables is
+	/// [WitnessMode::Paused].
+	pub fn with_tunables(mut self, tunables: Tunables) -> Self {
+		self.tunables = tunables;
+		self
+	}
+
+	/// Makes the witnesser refuse to witness any event once the shutdown reaches
+	/// [ShutdownStage::StoppingWitnessing].
+	pub fn with_shutdown(mut self, shutdown: ShutdownSignal) -> Self {
+		self.shutdown = shutdown;
+		self
+	}
+
+	/// Makes the witnesser record the events it witnesses in the given statuses.
+	pub fn with_statuses(mut self, statuses: EventStatuses) -> Self {
+		self.statuses = statuses;
+		self
+	}
+
+	/// Makes the witnesser witness the events of the given streams, along with those of the
+	/// default one, and refuse those of the others.
+	pub fn with_streams(mut self, streams: EventStreams) -> Self {
+		self.streams = streams;
+		self
+	}
+
+	/// Makes the witnesser count the events it witnesses in the given liveness, for the beats of
+	/// the node.
+	pub fn with_liveness(mut self, liveness: ValidatorLiveness) -> Self {
+		self.liveness = Some(liveness);
+		self
 	}
 }
 
 #[async_trait]
-impl<Block, Client, AuthorityId> EventWitnesserTrait for EventWitnesser<Block, Client, AuthorityId>
+impl<Block, Client, AuthorityId, G> EventWitnesserTrait
+	for EventWitnesser<Block, Client, AuthorityId, G>
 where
 	Block: BlockT,
-	Client: HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync + 'static,
-	AuthorityId: Codec + Send + Sync + 'static,
-	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
-	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+	G: GossipTrait + 'static,
+	Client: ChainAccess<Block, AuthorityId>,
+	AuthorityId: Send + Sync + 'static,
 {
-	/// Witnesses an event by signing and sending it to the [Gossip].
-	/// [EventGossipHandler] will then proceed to add the event to the [EventProofsTrait].
-	async fn witness_event(&self, event_id: H256) -> Result<(), Error> {
-		let block_state =
-			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
+	/// Witnesses an event by signing and sending it to the [GossipTrait], on the topic of its
+	/// stream. [EventGossipHandler] will then proceed to add the event to the [EventProofsTrait].
+	async fn witness_stream_event(&self, stream_id: &str, event_id: H256) -> Result<(), Error> {
+		self.streams.check(stream_id)?;
+		if self.role.get() == NodeRole::Observer {
+			return Err(Error::NotAValidator)
+		}
+		if self.shutdown.has_reached(ShutdownStage::StoppingWitnessing) {
+			return Err(Error::ShuttingDown)
+		}
+		if self.tunables.current().witness_mode == WitnessMode::Paused {
+			return Err(Error::WitnessingPaused)
+		}
+		let block_state = get_latest_authorities_list(&self.validator_set, self.client.as_ref())?;
 
-		log::trace!("To witness event {event_id} {}", event_id);
+		tracing::trace!(target: SERVICE, event_id = %event_id, stream_id, "Witnessing event");
 
-		let supported_keys = self.keystore.supported_keys(AURA, block_state.authorities).await?;
+		let supported_keys =
+			self.keystore.supported_keys(AURA, block_state.authorities.to_vec()).await?;
 
-		let pub_key = supported_keys.get(0).ok_or(Error::NotAValidator)?;
+		let pub_key = supported_keys.into_iter().next().ok_or(Error::NotAValidator)?;
+		let sign = self.traces.stage(&Context::current(), "sign");
 		let signature = self
 			.keystore
-			.sign_with(AURA, pub_key, event_id.as_bytes())
+			.sign_with(AURA, &pub_key, &witness_payload(&event_id, block_state.session))
 			.await?
 			.ok_or_else(|| Error::SigningFailure("Failed getting a signature".to_string()))?;
+		drop(sign);
 
-		log::trace!("Signed event {event_id} {}", event_id);
-
-		let witnessed_event = WitnessedEvent { signature, pub_key: pub_key.clone(), event_id };
+		// The key might have left the validator set while signing, getting the witness rejected
+		if self.role.get() == NodeRole::Observer {
+			return Err(Error::NotAValidator)
+		}
+		let witnessed_event =
+			WitnessedEvent { signature, pub_key, event_id, session: block_state.session };
 
-		let serilized_event = bincode::serialize(&witnessed_event)
-			.map_err(|e| Error::SerilizationFailure(e.to_string()))?;
+		let serilized_event = witnessed_event.to_bytes()?;
 
-		self.gossip
-			.clone()
-			.publish(IdentTopic::new(WITNESSED_EVENTS_TOPIC), serilized_event)
-			.await;
+		let _publish = self.traces.stage(&Context::current(), "gossip_publish");
+		self.gossip.clone().publish(stream_topic(stream_id), serilized_event).await?;
+		self.metrics.on_witness_sent();
+		if let Some(liveness) = &self.liveness {
+			liveness.on_witnessed();
+		}
+		self.statuses.advance(event_id, EventStatus::WitnessedBySelf);
+		tracing::debug!(target: SERVICE, event_id = %event_id, "Published witnessed event");
 
 		Ok(())
 	}
+
+	async fn witnessing_set(&self) -> Result<WitnessingSet, Error> {
+		let block_state = get_latest_authorities_list(&self.validator_set, self.client.as_ref())?;
+		let validators = block_state.authorities.to_vec();
+		// As decided when witnessing an event, short of signing one
+		let witnessing = self.role.get() == NodeRole::Validator &&
+			!self.keystore.supported_keys(AURA, validators.clone()).await?.is_empty();
+		Ok(WitnessingSet {
+			session: block_state.session,
+			target: block_state.target(),
+			validators,
+			witnessing,
+		})
+	}
 }
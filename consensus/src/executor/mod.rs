@@ -0,0 +1,161 @@
+//! Where the tasks of the subsystem run. By default they share the executor of the node with block
+//! import, networking and RPC; passing `--streams-runtime-threads` moves them to a [StreamsRuntime]
+//! of their own, so that a burst of gossip cannot hold up block authorship.
+//!
+//! Either way the tasks are spawned through the task manager of the node, under the same names: on
+//! a dedicated runtime, the task manager runs a proxy for each of them, which is all the node's
+//! executor ever polls. The subsystem only talks to the rest of the node through channels and
+//! synchronous calls, neither of which cares what runtime it is used from: transactions are
+//! submitted to the pool with the synchronous `submit_local`, notifications of the client arrive on
+//! its unbounded channels, and the [crate::shutdown::StreamsShutdown] drives the gossip through its
+//! channel from the main runtime.
+//!
+//! Neither runtime outlives the node: once the task manager drops the proxies, as it does when the
+//! node exits, their tasks are aborted, and the dedicated runtime is shut down with the last one.
+
+use futures::{future::BoxFuture, Future};
+use sc_service::{SpawnEssentialTaskHandle, SpawnTaskHandle};
+use sp_core::traits::SpawnNamed;
+use std::{io, num::NonZeroUsize, panic, sync::Arc};
+use tokio::{
+	runtime::{Handle, Runtime},
+	task::JoinHandle,
+};
+#[cfg(test)]
+pub mod tests;
+
+/// The name of the worker threads of a [StreamsRuntime].
+pub const STREAMS_THREAD_NAME: &str = "validated-streams";
+
+/// A multi-threaded tokio runtime dedicated to the tasks of the subsystem.
+pub struct StreamsRuntime {
+	runtime: Option<Runtime>,
+}
+
+impl StreamsRuntime {
+	/// Starts a runtime with the given number of worker threads.
+	pub fn dedicated(worker_threads: NonZeroUsize) -> io::Result<Self> {
+		let runtime = tokio::runtime::Builder::new_multi_thread()
+			.worker_threads(worker_threads.get())
+			.thread_name(STREAMS_THREAD_NAME)
+			.enable_all()
+			.build()?;
+		Ok(Self { runtime: Some(runtime) })
+	}
+
+	/// A handle to the runtime.
+	pub fn handle(&self) -> &Handle {
+		self.runtime.as_ref().expect("only taken when dropped").handle()
+	}
+
+	/// Runs the given future on the runtime, resolving to its output from whichever runtime polls
+	/// the returned future. Dropping the returned future aborts it, and a panic of the future is
+	/// resumed by the returned one. The runtime is kept alive until then.
+	pub fn run<F>(self: Arc<Self>, future: F) -> impl Future<Output = F::Output> + Send + 'static
+	where
+		F: Future + Send + 'static,
+		F::Output: Send + 'static,
+	{
+		let mut task = AbortOnDrop(self.handle().spawn(future));
+		async move {
+			let joined = (&mut task.0).await;
+			// Drops the runtime after the task, if this was its last user
+			drop(task);
+			drop(self);
+			match joined {
+				Ok(output) => output,
+				Err(e) => match e.try_into_panic() {
+					Ok(payload) => panic::resume_unwind(payload),
+					// Only the runtime shutting down cancels its tasks, and the node is exiting
+					// then
+					Err(_) => futures::future::pending().await,
+				},
+			}
+		}
+	}
+}
+
+impl Drop for StreamsRuntime {
+	fn drop(&mut self) {
+		// Unlike dropping it, this does not block, so it is fine within the node's runtime
+		if let Some(runtime) = self.runtime.take() {
+			runtime.shutdown_background();
+		}
+	}
+}
+
+/// Aborts the task of a [StreamsRuntime] when the future waiting on it is dropped.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+	fn drop(&mut self) {
+		self.0.abort();
+	}
+}
+
+/// A spawn handle of the node which runs the spawned futures on the dedicated [StreamsRuntime] if
+/// there is one, and on the node's executor otherwise.
+#[derive(Clone)]
+pub(crate) struct StreamsSpawner<S> {
+	spawner: S,
+	runtime: Option<Arc<StreamsRuntime>>,
+}
+
+impl<S> StreamsSpawner<S> {
+	pub fn new(spawner: S, runtime: Option<Arc<StreamsRuntime>>) -> Self {
+		Self { spawner, runtime }
+	}
+
+	/// The future the task manager polls for the given one.
+	fn proxy(&self, future: impl Future<Output = ()> + Send + 'static) -> BoxFuture<'static, ()> {
+		match &self.runtime {
+			Some(runtime) => Box::pin(runtime.clone().run(future)),
+			None => Box::pin(future),
+		}
+	}
+}
+
+impl StreamsSpawner<SpawnTaskHandle> {
+	/// Spawns a task, like [SpawnTaskHandle::spawn].
+	pub fn spawn(
+		&self,
+		name: &'static str,
+		group: Option<&'static str>,
+		future: impl Future<Output = ()> + Send + 'static,
+	) {
+		self.spawner.spawn(name, group, self.proxy(future))
+	}
+}
+
+impl StreamsSpawner<SpawnEssentialTaskHandle> {
+	/// Spawns an essential task, like [SpawnEssentialTaskHandle::spawn].
+	pub fn spawn(
+		&self,
+		name: &'static str,
+		group: Option<&'static str>,
+		future: impl Future<Output = ()> + Send + 'static,
+	) {
+		self.spawner.spawn(name, group, self.proxy(future))
+	}
+}
+
+impl SpawnNamed for StreamsSpawner<SpawnTaskHandle> {
+	fn spawn_blocking(
+		&self,
+		name: &'static str,
+		group: Option<&'static str>,
+		future: BoxFuture<'static, ()>,
+	) {
+		// Blocking tasks get threads of their own either way
+		self.spawner.spawn_blocking(name, group, future)
+	}
+
+	fn spawn(
+		&self,
+		name: &'static str,
+		group: Option<&'static str>,
+		future: BoxFuture<'static, ()>,
+	) {
+		self.spawner.spawn(name, group, self.proxy(future))
+	}
+}
@@ -0,0 +1,51 @@
+use super::{StreamsRuntime, STREAMS_THREAD_NAME};
+use futures::{channel::oneshot, FutureExt};
+use std::{
+	num::NonZeroUsize,
+	panic::AssertUnwindSafe,
+	sync::{Arc, Weak},
+	time::Duration,
+};
+
+fn runtime() -> Arc<StreamsRuntime> {
+	Arc::new(StreamsRuntime::dedicated(NonZeroUsize::new(2).unwrap()).unwrap())
+}
+
+fn thread_name() -> Option<String> {
+	std::thread::current().name().map(str::to_string)
+}
+
+#[tokio::test]
+async fn test_futures_run_on_the_dedicated_runtime() {
+	let runtime = runtime();
+	let name = tokio::spawn(runtime.clone().run(async { thread_name() })).await.unwrap();
+	assert_eq!(name.as_deref(), Some(STREAMS_THREAD_NAME));
+
+	// Its panics reach whoever waits on it
+	let panicked = AssertUnwindSafe(runtime.run(async { panic!("task panicked") }))
+		.catch_unwind()
+		.await
+		.unwrap_err();
+	assert_eq!(panicked.downcast_ref::<&str>(), Some(&"task panicked"));
+}
+
+#[tokio::test]
+async fn test_dropping_the_proxy_aborts_the_task_and_the_runtime() {
+	let runtime = runtime();
+	let weak: Weak<StreamsRuntime> = Arc::downgrade(&runtime);
+	let (dropped_tx, dropped_rx) = oneshot::channel::<()>();
+	let (started_tx, started_rx) = oneshot::channel();
+	let proxy = tokio::spawn(runtime.run(async move {
+		// Completes once the task is dropped, as it never is otherwise
+		let _dropped = dropped_tx;
+		started_tx.send(()).unwrap();
+		futures::future::pending::<()>().await
+	}));
+	started_rx.await.unwrap();
+
+	proxy.abort();
+	assert!(proxy.await.unwrap_err().is_cancelled());
+	let aborted = tokio::time::timeout(Duration::from_secs(5), dropped_rx).await;
+	assert!(aborted.is_ok(), "the task kept running");
+	assert!(weak.upgrade().is_none(), "the runtime outlived its tasks");
+}
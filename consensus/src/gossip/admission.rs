@@ -0,0 +1,213 @@
+//! Admission of gossip peers, keeping arbitrary peers out of the witness mesh. Once connected,
+//! peers prove over the [MEMBERSHIP_PROTOCOL] that they belong to the current validator set, by
+//! signing their gossip peer ID with their validator key. A node restricting its peers ignores the
+//! messages of those which have not proven so, and are not on its allowlist, and drops their
+//! connections after [ADMISSION_TIMEOUT].
+
+use super::sync::decode;
+use async_trait::async_trait;
+use codec::{Decode, Encode};
+use futures::{
+	io::{AsyncRead, AsyncWrite},
+	AsyncWriteExt,
+};
+use libp2p::{
+	core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName},
+	request_response::{
+		ProtocolSupport, RequestResponse, RequestResponseCodec, RequestResponseConfig,
+	},
+	PeerId,
+};
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::{
+	collections::{HashMap, HashSet},
+	io, iter,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+/// The name of the protocol.
+pub const MEMBERSHIP_PROTOCOL: &str = "/validated-streams/membership/1";
+/// How long peers get to prove their membership once connected.
+pub const ADMISSION_TIMEOUT: Duration = Duration::from_secs(10);
+/// What the signature of a membership proof is bound to, along with the peer ID.
+const MEMBERSHIP_CONTEXT: &[u8] = b"validated-streams/membership";
+/// The largest proof, in bytes: a key, a signature, and their lengths.
+const MAX_PROOF_SIZE: usize = 256;
+
+/// The proof that a gossip peer belongs to the validator set: the signature of its
+/// [membership_payload] by one of the validator keys.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct MembershipProof {
+	/// The validator key which made the signature
+	pub pub_key: CryptoTypePublicPair,
+	/// The signature of the payload
+	pub signature: Vec<u8>,
+}
+
+/// What a validator signs to prove that the gossip peer ID is its own.
+pub fn membership_payload(peer: &PeerId) -> Vec<u8> {
+	[MEMBERSHIP_CONTEXT, &peer.to_bytes()].concat()
+}
+
+/// Proves the membership of the node in the validator set, and verifies that of its peers.
+#[async_trait]
+pub trait Membership: Send + Sync {
+	/// The proof that the node, of the given gossip peer ID, is a validator, if it is one.
+	async fn prove(&self, local: PeerId) -> Option<MembershipProof>;
+
+	/// Whether the proof is that of a validator of the current set, for the given peer.
+	fn verify(&self, peer: &PeerId, proof: &MembershipProof) -> bool;
+}
+
+/// The peers a node restricting its gossip peers admits: those on the allowlist, and those which
+/// proved to be validators, as long as they still are one.
+pub(crate) struct Admissions {
+	membership: Option<Arc<dyn Membership>>,
+	allowlist: HashSet<PeerId>,
+	/// The admitted peers, along with their proof, unless they are on the allowlist
+	admitted: HashMap<PeerId, Option<MembershipProof>>,
+	/// The peers yet to prove their membership, by when they must
+	pending: HashMap<PeerId, Instant>,
+}
+
+impl Admissions {
+	/// Admits the peers on the allowlist, and those proving their membership, if any can.
+	pub fn new(membership: Option<Arc<dyn Membership>>, allowlist: &[PeerId]) -> Self {
+		let allowlist = allowlist.iter().copied().collect();
+		Self { membership, allowlist, admitted: HashMap::new(), pending: HashMap::new() }
+	}
+
+	/// Records a new peer. Returns whether it is yet to be admitted, and has until
+	/// [ADMISSION_TIMEOUT] after `now` for it.
+	pub fn on_connected(&mut self, peer: PeerId, now: Instant) -> bool {
+		if self.allowlist.contains(&peer) {
+			self.admitted.insert(peer, None);
+			return false
+		}
+		if self.admitted.contains_key(&peer) {
+			return false
+		}
+		self.pending.insert(peer, now + ADMISSION_TIMEOUT);
+		true
+	}
+
+	/// Forgets a peer once its last connection is closed; it proves its membership again if it
+	/// connects back.
+	pub fn on_disconnected(&mut self, peer: &PeerId) {
+		self.admitted.remove(peer);
+		self.pending.remove(peer);
+	}
+
+	/// Admits the peer if the proof is that of a validator. Returns whether it was admitted.
+	pub fn on_proof(&mut self, peer: PeerId, proof: MembershipProof) -> bool {
+		if self.allowlist.contains(&peer) {
+			return true
+		}
+		let Some(membership) = &self.membership else { return false };
+		if !membership.verify(&peer, &proof) {
+			return false
+		}
+		self.pending.remove(&peer);
+		self.admitted.insert(peer, Some(proof));
+		true
+	}
+
+	/// Whether the peer is admitted.
+	pub fn is_admitted(&self, peer: &PeerId) -> bool {
+		self.admitted.contains_key(peer)
+	}
+
+	/// The peers to disconnect: those which did not prove their membership in time, and those
+	/// whose proof no longer holds, e.g. as they left the validator set.
+	pub fn take_expelled(&mut self, now: Instant) -> Vec<PeerId> {
+		let mut expelled: Vec<_> =
+			self.pending.iter().filter(|(_, due)| **due <= now).map(|(peer, _)| *peer).collect();
+		for peer in &expelled {
+			self.pending.remove(peer);
+		}
+		let membership = self.membership.as_ref();
+		self.admitted.retain(|peer, proof| {
+			let Some(proof) = proof else { return true };
+			let holds = membership.map_or(false, |membership| membership.verify(peer, proof));
+			if !holds {
+				expelled.push(*peer);
+			}
+			holds
+		});
+		expelled
+	}
+}
+
+/// The [MEMBERSHIP_PROTOCOL], as negotiated with peers.
+#[derive(Clone)]
+pub struct MembershipProtocol;
+
+impl ProtocolName for MembershipProtocol {
+	fn protocol_name(&self) -> &[u8] {
+		MEMBERSHIP_PROTOCOL.as_bytes()
+	}
+}
+
+/// Reads and writes membership proofs, and whether they admitted the peer, as length-prefixed
+/// SCALE encodings.
+#[derive(Clone, Default)]
+pub struct MembershipCodec;
+
+#[async_trait]
+impl RequestResponseCodec for MembershipCodec {
+	type Protocol = MembershipProtocol;
+	type Request = MembershipProof;
+	type Response = bool;
+
+	async fn read_request<T>(
+		&mut self,
+		_: &MembershipProtocol,
+		io: &mut T,
+	) -> io::Result<MembershipProof>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		decode(read_length_prefixed(io, MAX_PROOF_SIZE).await?)
+	}
+
+	async fn read_response<T>(&mut self, _: &MembershipProtocol, io: &mut T) -> io::Result<bool>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		decode(read_length_prefixed(io, 1).await?)
+	}
+
+	async fn write_request<T>(
+		&mut self,
+		_: &MembershipProtocol,
+		io: &mut T,
+		proof: MembershipProof,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		write_length_prefixed(io, proof.encode()).await?;
+		io.close().await
+	}
+
+	async fn write_response<T>(
+		&mut self,
+		_: &MembershipProtocol,
+		io: &mut T,
+		admitted: bool,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		write_length_prefixed(io, admitted.encode()).await?;
+		io.close().await
+	}
+}
+
+/// The behaviour of the protocol, both proving the membership of the node and verifying that of
+/// its peers.
+pub(super) fn behaviour() -> RequestResponse<MembershipCodec> {
+	let protocols = iter::once((MembershipProtocol, ProtocolSupport::Full));
+	RequestResponse::new(MembershipCodec, protocols, RequestResponseConfig::default())
+}
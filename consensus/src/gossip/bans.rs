@@ -0,0 +1,146 @@
+//! Temporary bans of the gossip peers which keep violating the protocol, by sending messages the
+//! handler rejects, e.g. undecodable ones or ones with forged signatures, or by flooding the node
+
+use crate::logging::GOSSIP;
+use libp2p::PeerId;
+use std::{
+	collections::{HashMap, VecDeque},
+	time::Duration,
+};
+use tokio::time::Instant;
+
+/// The violations after which a peer is banned, by default.
+pub const DEFAULT_BAN_THRESHOLD: u32 = 10;
+/// How long a peer stays banned, by default.
+pub const DEFAULT_BAN_DURATION: Duration = Duration::from_secs(600);
+/// The messages a peer may send per second before each further one is a violation, by default.
+pub const DEFAULT_FLOOD_LIMIT: u32 = 1000;
+/// How long a violation counts towards a ban.
+pub const VIOLATION_WINDOW: Duration = Duration::from_secs(60);
+/// The window the messages of a peer are counted over, against the flood limit.
+const FLOOD_WINDOW: Duration = Duration::from_secs(1);
+
+/// When peers get banned, and for how long.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BanConfig {
+	/// The violations within the [VIOLATION_WINDOW] after which a peer is banned. 0 to never ban
+	/// peers.
+	pub threshold: u32,
+	/// How long a peer stays banned
+	pub duration: Duration,
+	/// The messages a peer may send per second; each one past it is a violation. 0 for no limit.
+	pub flood_limit: u32,
+}
+
+impl Default for BanConfig {
+	fn default() -> Self {
+		Self {
+			threshold: DEFAULT_BAN_THRESHOLD,
+			duration: DEFAULT_BAN_DURATION,
+			flood_limit: DEFAULT_FLOOD_LIMIT,
+		}
+	}
+}
+
+/// What a peer did wrong.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+	/// Sent a message the handler rejected, e.g. one that failed to decode or verify
+	Rejected,
+	/// Sent more messages than the flood limit allows
+	Flooding,
+}
+
+/// The violations of a peer, along with its messages of the current flood window.
+#[derive(Default)]
+struct Offender {
+	violations: VecDeque<Instant>,
+	flood_window: Option<Instant>,
+	messages: u32,
+}
+
+/// Counts the violations of the peers, banning them once they reach the threshold of the
+/// [BanConfig], and unbanning them once their ban ran out.
+pub(crate) struct Bans {
+	config: BanConfig,
+	offenders: HashMap<PeerId, Offender>,
+	banned: HashMap<PeerId, Instant>,
+}
+
+impl Bans {
+	/// Bans peers as configured.
+	pub fn new(config: BanConfig) -> Self {
+		Self { config, offenders: HashMap::new(), banned: HashMap::new() }
+	}
+
+	/// Records a message of a peer. Returns whether the peer is to be banned, for flooding.
+	pub fn on_message(&mut self, peer: PeerId, now: Instant) -> bool {
+		if self.config.flood_limit == 0 || self.config.threshold == 0 {
+			return false
+		}
+		let offender = self.offenders.entry(peer).or_default();
+		match offender.flood_window {
+			Some(start) if now < start + FLOOD_WINDOW => offender.messages += 1,
+			_ => {
+				offender.flood_window = Some(now);
+				offender.messages = 1;
+			},
+		}
+		if offender.messages <= self.config.flood_limit {
+			return false
+		}
+		self.on_violation(peer, Violation::Flooding, now)
+	}
+
+	/// Records a violation of a peer. Returns whether the peer is to be banned, having reached the
+	/// threshold within the [VIOLATION_WINDOW]; it is then counted as banned until the ban runs
+	/// out.
+	pub fn on_violation(&mut self, peer: PeerId, violation: Violation, now: Instant) -> bool {
+		if self.config.threshold == 0 || self.banned.contains_key(&peer) {
+			return false
+		}
+		let offender = self.offenders.entry(peer).or_default();
+		while offender.violations.front().map_or(false, |at| *at + VIOLATION_WINDOW <= now) {
+			offender.violations.pop_front();
+		}
+		offender.violations.push_back(now);
+		let violations = offender.violations.len();
+		tracing::debug!(target: GOSSIP, peer = %peer, ?violation, violations, "Protocol violation");
+		if violations < self.config.threshold as usize {
+			return false
+		}
+		self.offenders.remove(&peer);
+		self.banned.insert(peer, now + self.config.duration);
+		true
+	}
+
+	/// Forgets the messages of a peer once disconnected, keeping the violations which still count.
+	pub fn on_disconnected(&mut self, peer: &PeerId, now: Instant) {
+		let Some(offender) = self.offenders.get_mut(peer) else { return };
+		offender.violations.retain(|at| *at + VIOLATION_WINDOW > now);
+		offender.flood_window = None;
+		if offender.violations.is_empty() {
+			self.offenders.remove(peer);
+		}
+	}
+
+	/// The peers banned at the moment.
+	pub fn banned(&self) -> impl Iterator<Item = &PeerId> {
+		self.banned.keys()
+	}
+
+	/// When the next ban runs out, if any peer is banned.
+	pub fn next_unban(&self) -> Option<Instant> {
+		self.banned.values().min().copied()
+	}
+
+	/// The peers whose ban ran out, which are no longer counted as banned.
+	pub fn take_unbanned(&mut self, now: Instant) -> Vec<PeerId> {
+		let unbanned: Vec<_> =
+			self.banned.iter().filter(|(_, until)| **until <= now).map(|(peer, _)| *peer).collect();
+		for peer in &unbanned {
+			self.banned.remove(peer);
+		}
+		unbanned
+	}
+}
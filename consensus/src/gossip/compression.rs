@@ -0,0 +1,99 @@
+//! Compression of the messages the gossip publishes. Compressed messages are told apart from the
+//! others by the magic number their frame starts with, so that every message received is handed
+//! to the handler as published, whatever the compression its publisher chose, if any.
+
+use crate::logging::GOSSIP;
+use std::{
+	fmt,
+	io::{Read, Write},
+	str::FromStr,
+};
+
+/// The most a received message may decompress to, in bytes, so that small messages cannot blow up
+/// in memory.
+pub const MAX_DECOMPRESSED_SIZE: usize = 1024 * 1024;
+/// The level messages are compressed with by zstd, favoring speed as small messages compress
+/// little better at higher levels.
+const ZSTD_LEVEL: i32 = 3;
+/// The magic number zstd frames start with.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// The stream identifier chunk snappy frames start with.
+const SNAPPY_MAGIC: [u8; 10] = [0xff, 0x06, 0x00, 0x00, b's', b'N', b'a', b'P', b'p', b'Y'];
+
+/// How the messages published by the gossip are compressed, as chosen with
+/// `--gossip-compression`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GossipCompression {
+	/// Published as given
+	#[default]
+	None,
+	/// Compressed in the snappy frame format: fast, for a modest gain
+	Snappy,
+	/// Compressed by zstd: slower, for a larger gain
+	Zstd,
+}
+
+impl GossipCompression {
+	/// Compresses a message to publish. Failing to compress leaves it as given.
+	pub fn compress(&self, message: Vec<u8>) -> Vec<u8> {
+		let compressed = match self {
+			Self::None => return message,
+			Self::Snappy => snappy(&message),
+			Self::Zstd => zstd::bulk::compress(&message, ZSTD_LEVEL).map_err(|e| e.to_string()),
+		};
+		compressed.unwrap_or_else(|e| {
+			tracing::debug!(target: GOSSIP, error = %e, "Failed compressing a message");
+			message
+		})
+	}
+}
+
+/// Compresses a message in the snappy frame format.
+fn snappy(message: &[u8]) -> Result<Vec<u8>, String> {
+	let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+	encoder.write_all(message).map_err(|e| e.to_string())?;
+	encoder.into_inner().map_err(|e| e.to_string())
+}
+
+/// The message as published, decompressing it if it was compressed. Fails on messages which do
+/// not decompress, or to more than [MAX_DECOMPRESSED_SIZE].
+pub fn decompress(message: Vec<u8>) -> Result<Vec<u8>, String> {
+	if message.starts_with(&ZSTD_MAGIC) {
+		return zstd::bulk::decompress(&message, MAX_DECOMPRESSED_SIZE).map_err(|e| e.to_string())
+	}
+	if !message.starts_with(&SNAPPY_MAGIC) {
+		return Ok(message)
+	}
+	let mut decompressed = Vec::new();
+	snap::read::FrameDecoder::new(message.as_slice())
+		.take(MAX_DECOMPRESSED_SIZE as u64 + 1)
+		.read_to_end(&mut decompressed)
+		.map_err(|e| e.to_string())?;
+	if decompressed.len() > MAX_DECOMPRESSED_SIZE {
+		return Err(format!("decompresses to more than {MAX_DECOMPRESSED_SIZE} bytes"))
+	}
+	Ok(decompressed)
+}
+
+impl fmt::Display for GossipCompression {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			Self::None => "none",
+			Self::Snappy => "snappy",
+			Self::Zstd => "zstd",
+		})
+	}
+}
+
+impl FromStr for GossipCompression {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"none" => Ok(Self::None),
+			"snappy" => Ok(Self::Snappy),
+			"zstd" => Ok(Self::Zstd),
+			_ => Err(format!("expected none, snappy or zstd, got {s}")),
+		}
+	}
+}
@@ -0,0 +1,359 @@
+//! The hand-off between the event loop of the gossip and its [GossipHandler]. The event loop only
+//! runs the cheap checks on incoming messages (dropping duplicates, and those the handler rejects
+//! by their [ordering key](GossipHandler::ordering_key)) before queueing them; workers running as
+//! tasks of their own then pass them to the handler, so that slow handling never holds up the
+//! swarm. Each ordering key always goes to the same worker, which handles its messages in order.
+//! The verdicts of the handler on the messages awaiting [validation](Validation) are sent back to
+//! the event loop, for gossipsub to relay the accepted ones only; the messages dropped before
+//! reaching the handler are not relayed either.
+
+use super::GossipHandler;
+use crate::{errors::Error, logging::GOSSIP, metrics::Metrics};
+use futures::{
+	channel::{mpsc::UnboundedSender, oneshot},
+	future::BoxFuture,
+	FutureExt,
+};
+use libp2p::{
+	gossipsub::{MessageAcceptance, MessageId},
+	PeerId,
+};
+use lru::LruCache;
+use sp_core::{hashing::blake2_128, traits::SpawnNamed};
+use std::{
+	any::Any,
+	collections::VecDeque,
+	num::NonZeroUsize,
+	panic::AssertUnwindSafe,
+	sync::{
+		atomic::{AtomicBool, AtomicUsize, Ordering},
+		Arc, Mutex, MutexGuard,
+	},
+};
+use tokio::sync::Notify;
+use tracing::Instrument;
+
+/// How many workers pass the incoming messages to the handler.
+pub const INGRESS_WORKERS: usize = 4;
+/// How many messages the queue of a worker holds before dropping the oldest ones received from
+/// peers. Messages we publish ourselves are never dropped.
+pub const INGRESS_CAPACITY: usize = 256;
+/// How many recently received messages are remembered, so as to drop their duplicates.
+const SEEN_CAPACITY: usize = 4096;
+
+/// Why an incoming message was dropped before reaching the handler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IngressDrop {
+	/// The same message was received recently
+	Duplicate,
+	/// The handler rejected it without handling it
+	Rejected,
+	/// The queue of its worker was full, and it was the oldest message received from a peer
+	Overflow,
+}
+
+impl IngressDrop {
+	/// The label of the reason in the metrics.
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Duplicate => "duplicate",
+			Self::Rejected => "rejected",
+			Self::Overflow => "overflow",
+		}
+	}
+}
+
+/// The verdict of the handler on a received message, as reported to gossipsub.
+pub(crate) type Verdict = (MessageId, PeerId, MessageAcceptance);
+
+/// A received message held back by gossipsub until the handler tells whether to relay it.
+pub(crate) struct Validation {
+	pub id: MessageId,
+	pub source: PeerId,
+	pub verdicts: UnboundedSender<Verdict>,
+}
+
+impl Validation {
+	/// Reports the verdict on the message, if it awaits one.
+	fn report(validation: Option<Self>, acceptance: MessageAcceptance) {
+		if let Some(Self { id, source, verdicts }) = validation {
+			verdicts.unbounded_send((id, source, acceptance)).ok();
+		}
+	}
+}
+
+/// A queued message, or a marker among them.
+enum Item {
+	/// Received from a peer, within the span of its propagation, and awaiting validation if given
+	Received(Vec<u8>, tracing::Span, Option<Validation>),
+	/// Published by us, acknowledged once handled
+	Published(Vec<u8>, oneshot::Sender<Result<(), Error>>),
+	/// Reached once every message queued before it has been handled
+	Barrier(Arc<Barrier>),
+}
+
+/// Acknowledges a flush once every worker has reached it.
+struct Barrier {
+	remaining: AtomicUsize,
+	flushed: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl Barrier {
+	fn reach(&self) {
+		if self.remaining.fetch_sub(1, Ordering::SeqCst) == 1 {
+			if let Some(flushed) = self.flushed.lock().unwrap().take() {
+				flushed.send(()).ok();
+			}
+		}
+	}
+}
+
+/// The queue of one worker.
+#[derive(Default)]
+struct Shard {
+	items: Mutex<VecDeque<Item>>,
+	ready: Notify,
+	/// Whether a worker is running for the queue
+	staffed: AtomicBool,
+}
+
+struct Inner {
+	shards: Vec<Shard>,
+	queued: AtomicUsize,
+	closed: AtomicBool,
+	metrics: Metrics,
+}
+
+impl Inner {
+	fn on_queued(&self) {
+		let queued = self.queued.fetch_add(1, Ordering::SeqCst) + 1;
+		self.metrics.set_gossip_ingress_queued(queued);
+	}
+
+	fn on_dequeued(&self) {
+		let queued = self.queued.fetch_sub(1, Ordering::SeqCst) - 1;
+		self.metrics.set_gossip_ingress_queued(queued);
+	}
+}
+
+/// Spawns the tasks of the gossip through the spawner of the node, so that they run under its
+/// task manager: the workers, and the connections of the swarm. Cheap to clone.
+#[derive(Clone)]
+pub(crate) struct GossipSpawner {
+	spawner: Arc<dyn SpawnNamed>,
+	worker_task: &'static str,
+	connection_task: &'static str,
+	group: Option<&'static str>,
+}
+
+impl GossipSpawner {
+	/// Spawns the workers and the connections as tasks of the given names, in the given group.
+	pub fn new(
+		spawner: impl SpawnNamed + 'static,
+		worker_task: &'static str,
+		connection_task: &'static str,
+		group: Option<&'static str>,
+	) -> Self {
+		Self { spawner: Arc::new(spawner), worker_task, connection_task, group }
+	}
+
+	fn spawn(&self, worker: BoxFuture<'static, ()>) {
+		self.spawner.spawn(self.worker_task, self.group, worker)
+	}
+
+	/// Spawns a task driving a connection of the swarm.
+	pub fn spawn_connection(&self, connection: BoxFuture<'static, ()>) {
+		self.spawner.spawn(self.connection_task, self.group, connection)
+	}
+}
+
+/// Gossip services started by the tests without a spawner spawn their tasks with [tokio::spawn].
+#[cfg(test)]
+impl Default for GossipSpawner {
+	fn default() -> Self {
+		#[derive(Clone)]
+		struct TokioSpawner;
+
+		impl SpawnNamed for TokioSpawner {
+			fn spawn_blocking(
+				&self,
+				_: &'static str,
+				_: Option<&'static str>,
+				task: BoxFuture<'static, ()>,
+			) {
+				tokio::task::spawn_blocking(move || futures::executor::block_on(task));
+			}
+
+			fn spawn(
+				&self,
+				_: &'static str,
+				_: Option<&'static str>,
+				task: BoxFuture<'static, ()>,
+			) {
+				tokio::spawn(task);
+			}
+		}
+
+		Self::new(TokioSpawner, "gossip-ingress", "gossip-connection", None)
+	}
+}
+
+/// The queues between the event loop and the workers. They outlive restarts of the event loop,
+/// and are closed once dropped, the workers ending once they have handled what is left.
+pub(crate) struct Ingress {
+	inner: Arc<Inner>,
+	seen: Mutex<LruCache<[u8; 16], ()>>,
+}
+
+impl Ingress {
+	pub fn new(metrics: Metrics) -> Self {
+		let seen = NonZeroUsize::new(SEEN_CAPACITY).expect("capacity is not zero");
+		let shards = (0..INGRESS_WORKERS).map(|_| Shard::default()).collect();
+		let inner = Inner {
+			shards,
+			queued: AtomicUsize::new(0),
+			closed: AtomicBool::new(false),
+			metrics,
+		};
+		Self { inner: Arc::new(inner), seen: Mutex::new(LruCache::new(seen)) }
+	}
+
+	/// Spawns a worker for every queue without one: all of them at first, and those whose worker
+	/// panicked afterwards. The panics of the workers are sent to `failures`.
+	pub fn spawn_workers<H: GossipHandler + Send + Sync + 'static>(
+		&self,
+		handler: &Arc<H>,
+		spawner: &GossipSpawner,
+		failures: &UnboundedSender<Box<dyn Any + Send>>,
+	) {
+		for index in 0..self.inner.shards.len() {
+			if self.inner.shards[index].staffed.swap(true, Ordering::SeqCst) {
+				continue
+			}
+			let (inner, handler) = (self.inner.clone(), handler.clone());
+			let failures = failures.clone();
+			spawner.spawn(Box::pin(async move {
+				let worked = AssertUnwindSafe(work(&inner, index, handler.as_ref()))
+					.catch_unwind()
+					.await;
+				inner.shards[index].staffed.store(false, Ordering::SeqCst);
+				if let Err(panic) = worked {
+					failures.unbounded_send(panic).ok();
+				}
+			}));
+		}
+	}
+
+	/// Queues a message received from a peer, unless it is a duplicate or the handler rejects it.
+	/// The dropped messages awaiting validation are ignored, or rejected by the handler.
+	pub fn receive<H: GossipHandler>(
+		&self,
+		handler: &H,
+		message: Vec<u8>,
+		span: tracing::Span,
+		validation: Option<Validation>,
+	) {
+		if self.seen.lock().unwrap().put(blake2_128(&message), ()).is_some() {
+			Validation::report(validation, MessageAcceptance::Ignore);
+			return self.drop_message(IngressDrop::Duplicate)
+		}
+		let Some(key) = handler.ordering_key(&message) else {
+			Validation::report(validation, MessageAcceptance::Reject);
+			return self.drop_message(IngressDrop::Rejected)
+		};
+		let mut items = self.shard(key).items.lock().unwrap();
+		if items.len() >= INGRESS_CAPACITY {
+			match items.iter().position(|item| matches!(item, Item::Received(..))) {
+				Some(oldest) => {
+					if let Some(Item::Received(_, _, validation)) = items.remove(oldest) {
+						Validation::report(validation, MessageAcceptance::Ignore);
+					}
+					self.inner.on_dequeued();
+				},
+				None => {
+					Validation::report(validation, MessageAcceptance::Ignore);
+					return self.drop_message(IngressDrop::Overflow)
+				},
+			}
+			self.drop_message(IngressDrop::Overflow);
+		}
+		self.queue(key, items, Item::Received(message, span, validation));
+	}
+
+	/// Queues a message we publish, to be acknowledged through `handled` once handled.
+	pub fn publish<H: GossipHandler>(
+		&self,
+		handler: &H,
+		message: Vec<u8>,
+		handled: oneshot::Sender<Result<(), Error>>,
+	) {
+		let key = handler.ordering_key(&message).unwrap_or_default();
+		let items = self.shard(key).items.lock().unwrap();
+		self.queue(key, items, Item::Published(message, handled));
+	}
+
+	/// Acknowledges through `flushed` once every message queued so far has been handled.
+	pub fn flush(&self, flushed: oneshot::Sender<()>) {
+		let barrier = Arc::new(Barrier {
+			remaining: AtomicUsize::new(self.inner.shards.len()),
+			flushed: Mutex::new(Some(flushed)),
+		});
+		for key in 0..self.inner.shards.len() {
+			let items = self.inner.shards[key].items.lock().unwrap();
+			self.queue(key as u64, items, Item::Barrier(barrier.clone()));
+		}
+	}
+
+	fn shard(&self, key: u64) -> &Shard {
+		&self.inner.shards[(key % self.inner.shards.len() as u64) as usize]
+	}
+
+	/// Queues an item under the lock of its queue, so that it is counted before being handled.
+	fn queue(&self, key: u64, mut items: MutexGuard<VecDeque<Item>>, item: Item) {
+		items.push_back(item);
+		self.inner.on_queued();
+		drop(items);
+		self.shard(key).ready.notify_one();
+	}
+
+	fn drop_message(&self, reason: IngressDrop) {
+		tracing::trace!(target: GOSSIP, reason = reason.as_str(), "Dropped an incoming message");
+		self.inner.metrics.on_gossip_ingress_dropped(reason);
+	}
+}
+
+impl Drop for Ingress {
+	fn drop(&mut self) {
+		self.inner.closed.store(true, Ordering::SeqCst);
+		for shard in &self.inner.shards {
+			shard.ready.notify_one();
+		}
+	}
+}
+
+/// Handles the messages of one queue until it is closed and empty.
+async fn work<H: GossipHandler + Sync>(inner: &Inner, index: usize, handler: &H) {
+	let shard = &inner.shards[index];
+	loop {
+		let next = shard.items.lock().unwrap().pop_front();
+		let Some(item) = next else {
+			if inner.closed.load(Ordering::SeqCst) {
+				return
+			}
+			shard.ready.notified().await;
+			continue
+		};
+		inner.on_dequeued();
+		match item {
+			Item::Received(message, span, validation) => {
+				let acceptance = handler.handle_received(&message).instrument(span).await;
+				Validation::report(validation, acceptance);
+			},
+			Item::Published(message, handled) => {
+				handler.handle(&message).await;
+				handled.send(Ok(())).ok();
+			},
+			Item::Barrier(barrier) => barrier.reach(),
+		}
+	}
+}
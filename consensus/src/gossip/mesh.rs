@@ -0,0 +1,202 @@
+//! Tracking of the gossip peers a validator needs for its events to reach quorum
+
+use crate::{logging::GOSSIP, metrics::Metrics, telemetry::StreamsTelemetry};
+use libp2p::{core::multiaddr::Protocol, Multiaddr, PeerId};
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+/// How often a degraded mesh is warned about, at most, while it stays degraded.
+pub const MESH_WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What the gossip mesh of a validator is expected to look like. Cheap to clone; the [Default]
+/// expectations require no peers, so that the mesh is never degraded.
+#[derive(Clone, Debug, Default)]
+pub struct MeshExpectations {
+	inner: Arc<Mutex<Expectations>>,
+}
+
+#[derive(Debug, Default)]
+struct Expectations {
+	/// The number of validators needed to witness an event
+	quorum: usize,
+	/// The gossip addresses of the other validators, as configured
+	addresses: Vec<Multiaddr>,
+}
+
+impl MeshExpectations {
+	/// Sets the gossip addresses the other validators are expected at.
+	pub fn set_addresses(&self, addresses: Vec<Multiaddr>) {
+		self.inner.lock().unwrap().addresses = addresses.into_iter().map(without_peer_id).collect();
+	}
+
+	/// Sets the number of validators needed to witness an event, including ourselves.
+	pub fn set_quorum(&self, quorum: usize) {
+		self.inner.lock().unwrap().quorum = quorum;
+	}
+
+	/// The number of peers needed for our events to ever reach quorum.
+	pub fn required_peers(&self) -> usize {
+		self.inner.lock().unwrap().quorum.saturating_sub(1)
+	}
+
+	fn addresses(&self) -> Vec<Multiaddr> {
+		self.inner.lock().unwrap().addresses.clone()
+	}
+}
+
+/// A connected gossip peer.
+#[derive(Default)]
+struct Peer {
+	/// The addresses the peer is known by: the one we dialed, and the ones it listens on
+	addresses: Vec<Multiaddr>,
+	/// Whether the peer has subscribed to a topic of ours, completing the handshake
+	subscribed: bool,
+}
+
+impl Peer {
+	fn add_addresses(&mut self, addresses: impl IntoIterator<Item = Multiaddr>) {
+		for address in addresses {
+			if !self.addresses.contains(&address) {
+				self.addresses.push(address);
+			}
+		}
+	}
+}
+
+/// A connected gossip peer, as listed by [Gossip::peers](super::Gossip::peers).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GossipPeer {
+	pub peer_id: PeerId,
+	/// The addresses the peer is known by: the one we dialed, and the ones it listens on
+	pub addresses: Vec<Multiaddr>,
+	/// Whether the peer has subscribed to a topic of ours
+	pub subscribed: bool,
+}
+
+/// Compares the connected gossip peers against the [MeshExpectations], reporting the health of the
+/// mesh to the metrics and the logs.
+pub(crate) struct MeshMonitor {
+	expectations: MeshExpectations,
+	metrics: Metrics,
+	telemetry: StreamsTelemetry,
+	peers: HashMap<PeerId, Peer>,
+	degraded: bool,
+	last_warning: Option<Instant>,
+}
+
+impl MeshMonitor {
+	pub fn new(
+		expectations: MeshExpectations,
+		metrics: Metrics,
+		telemetry: StreamsTelemetry,
+	) -> Self {
+		Self {
+			expectations,
+			metrics,
+			telemetry,
+			peers: HashMap::new(),
+			degraded: false,
+			last_warning: None,
+		}
+	}
+
+	/// Records a connection to a peer, dialed at the given address, if any.
+	pub fn on_connected(&mut self, peer: PeerId, dialed: Option<Multiaddr>) {
+		self.peers.entry(peer).or_default().add_addresses(dialed.map(without_peer_id));
+	}
+
+	/// Records the addresses a peer listens on.
+	pub fn on_identified(&mut self, peer: PeerId, listen_addresses: Vec<Multiaddr>) {
+		let peer = self.peers.entry(peer).or_default();
+		// Peers identify themselves again every so often
+		peer.add_addresses(listen_addresses.into_iter().map(without_peer_id));
+	}
+
+	/// Records that a peer subscribed to one of our topics.
+	pub fn on_subscribed(&mut self, peer: PeerId) {
+		self.peers.entry(peer).or_default().subscribed = true;
+	}
+
+	/// Records that the last connection to a peer was closed.
+	pub fn on_disconnected(&mut self, peer: &PeerId) {
+		self.peers.remove(peer);
+	}
+
+	/// The connected peers, by peer ID.
+	pub fn peers(&self) -> Vec<GossipPeer> {
+		let mut peers: Vec<_> = self
+			.peers
+			.iter()
+			.map(|(peer_id, peer)| GossipPeer {
+				peer_id: *peer_id,
+				addresses: peer.addresses.clone(),
+				subscribed: peer.subscribed,
+			})
+			.collect();
+		peers.sort_by_key(|peer| peer.peer_id.to_bytes());
+		peers
+	}
+
+	/// The connected peers which subscribed to one of our topics.
+	pub fn subscribed(&self) -> impl Iterator<Item = &PeerId> {
+		self.peers.iter().filter(|(_, peer)| peer.subscribed).map(|(peer_id, _)| peer_id)
+	}
+
+	/// The addresses a connected peer is known by.
+	pub fn addresses_of(&self, peer: &PeerId) -> Vec<Multiaddr> {
+		self.peers.get(peer).map(|peer| peer.addresses.clone()).unwrap_or_default()
+	}
+
+	/// Reports the health of the mesh. Warns when it becomes degraded, and then at most once per
+	/// [MESH_WARNING_INTERVAL] while it stays so; notes when it recovers.
+	pub fn check(&mut self, now: Instant) {
+		let required = self.expectations.required_peers();
+		let connected = self.peers.values().filter(|peer| peer.subscribed).count();
+		let health =
+			if required == 0 { 1.0 } else { (connected as f64 / required as f64).min(1.0) };
+		let degraded = connected < required;
+
+		if degraded {
+			let due = match self.last_warning {
+				Some(last_warning) if self.degraded =>
+					now.saturating_duration_since(last_warning) >= MESH_WARNING_INTERVAL,
+				_ => true,
+			};
+			if due {
+				tracing::warn!(
+					target: GOSSIP,
+					connected,
+					required,
+					missing = ?self.missing(),
+					"Gossip mesh is degraded; events cannot reach quorum"
+				);
+				self.telemetry.on_quorum_stall(connected, required);
+				self.last_warning = Some(now);
+			}
+		} else if self.degraded {
+			tracing::info!(target: GOSSIP, connected, required, "Gossip mesh recovered");
+		}
+		self.degraded = degraded;
+		self.metrics.set_mesh_health(health, degraded);
+	}
+
+	/// The expected addresses none of the handshaked peers is known by.
+	fn missing(&self) -> Vec<Multiaddr> {
+		let mut missing = self.expectations.addresses();
+		missing.retain(|address| {
+			!self.peers.values().any(|peer| peer.subscribed && peer.addresses.contains(address))
+		});
+		missing
+	}
+}
+
+/// Strips a trailing `/p2p/..` from an address, as peers are told apart by address alone.
+pub(crate) fn without_peer_id(mut address: Multiaddr) -> Multiaddr {
+	if let Some(Protocol::P2p(_)) = address.iter().last() {
+		address.pop();
+	}
+	address
+}
@@ -1,41 +1,233 @@
 //! A module for gossiping messages with a swarm of peers.
 
+use crate::{
+	errors::{Error, StartupError},
+	logging::{rate_limited, LogRateLimiter, GOSSIP},
+	metrics::Metrics,
+	startup::{StartupSignals, StartupStep},
+	telemetry::StreamsTelemetry,
+	watchdog::Heartbeat,
+};
+use admission::{Admissions, MembershipCodec, MembershipProof};
+use bans::{Bans, Violation};
+use ingress::{Ingress, Validation, Verdict, GossipSpawner};
+use mesh::{without_peer_id, MeshMonitor};
+use orders::{order_queue, OrderSender, Orders};
+use redial::Redials;
+use scoring::{peer_score_params, peer_score_thresholds};
+use sync::{ProofsRequest, ProofsResponse, SyncCodec};
 use async_trait::async_trait;
 use futures::{
-	channel::mpsc::{channel, Receiver, Sender},
+	channel::{
+		mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+		oneshot,
+	},
+	future::BoxFuture,
 	prelude::*,
 	select,
+	stream::FuturesUnordered,
 };
 use libp2p::{
-	core::{muxing::StreamMuxerBox, transport::Boxed, upgrade},
-	gossipsub::{self, Gossipsub, GossipsubEvent, IdentTopic, MessageAuthenticity},
+	autonat::Behaviour as Autonat,
+	core::{
+		either::EitherOutput, multiaddr::Protocol, muxing::StreamMuxerBox, transport::Boxed,
+		upgrade, ConnectedPoint,
+	},
+	gossipsub::{
+		self, Gossipsub, GossipsubEvent, IdentTopic, MessageAcceptance, MessageAuthenticity,
+		ValidationMode,
+	},
 	identify::{Behaviour as Identify, Event as IdentifyEvent},
 	identity::{self, Keypair},
 	kad::{record::store::MemoryStore, Kademlia},
-	mdns::tokio::Behaviour as MDns,
-	mplex,
-	swarm::{NetworkBehaviour, SwarmEvent},
-	tcp, tls, Multiaddr, PeerId, Swarm, Transport,
+	mdns::{tokio::Behaviour as MDns, Event as MdnsEvent},
+	dns, noise, quic,
+	relay::v2::client::{transport::ClientTransport, Client as RelayClient},
+	request_response::{
+		RequestId, RequestResponse, RequestResponseEvent, RequestResponseMessage,
+	},
+	swarm::{
+		behaviour::toggle::Toggle,
+		dial_opts::{DialOpts, PeerCondition},
+		ConnectionLimits, NetworkBehaviour, SwarmBuilder, SwarmEvent,
+	},
+	tcp, websocket, yamux, Multiaddr, PeerId, Swarm, Transport,
 };
 
-use std::sync::Arc;
+use sp_core::{hashing::blake2_256, traits::SpawnNamed, H256};
+use std::{
+	any::Any,
+	collections::{HashMap, VecDeque},
+	panic::{self, AssertUnwindSafe},
+	path::Path,
+	pin::Pin,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+pub mod admission;
+pub mod bans;
+pub mod compression;
+pub mod ingress;
+pub mod mesh;
+pub mod nat;
+pub mod network;
+pub mod orders;
+pub mod redial;
+pub mod scoring;
+pub mod sync;
+pub mod version;
 #[cfg(test)]
 pub mod tests;
 
+pub use admission::{Membership, MEMBERSHIP_PROTOCOL};
+pub use bans::BanConfig;
+pub use compression::GossipCompression;
+pub use mesh::{GossipPeer, MeshExpectations};
+pub use nat::NatConfig;
+pub use network::{pending_network, GossipBackend, GossipNetwork, PendingNetwork, PROTOCOL_NAME};
+pub use orders::{Backpressure, OrderQueueConfig};
+pub use version::PROTOCOL_VERSION;
+
+/// How often the health of the mesh is checked, on top of whenever a peer comes or goes.
+const MESH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// What the secret of the gossip key is derived from, along with the node key.
+const GOSSIP_KEY_DOMAIN: &[u8] = b"validated-streams/gossip-key";
+/// How long the peers get to close their connections once the gossip is closed.
+const CLOSE_GRACE: Duration = Duration::from_secs(2);
+/// How many times the event loop may fail within [RESTART_WINDOW] before the gossip gives up.
+pub const MAX_RESTARTS: usize = 5;
+/// The window [MAX_RESTARTS] is counted over.
+pub const RESTART_WINDOW: Duration = Duration::from_secs(300);
+/// How long the gossip waits before its first restart; each restart within the window waits twice
+/// as long as the one before.
+pub const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+/// How long dialing a peer, and the handshakes of a new connection, may take by default.
+pub const DEFAULT_TRANSPORT_TIMEOUT: Duration = Duration::from_secs(20);
+/// How many connections may be established with a single peer by default, leaving room for two
+/// peers dialing each other at once.
+pub const DEFAULT_MAX_CONNECTIONS_PER_PEER: u32 = 2;
+
+/// The settings of the transport of the gossip, and the limits on its connections, 0 for none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransportConfig {
+	/// How long dialing a peer, and the security and multiplexing handshakes of a connection,
+	/// may take before the connection is given up on
+	pub timeout: Duration,
+	/// The connections established with the peers which dialed this node
+	pub max_incoming: u32,
+	/// The connections established with a single peer
+	pub max_per_peer: u32,
+	/// Whether peers can also connect over QUIC, on the UDP port of each TCP address listened on
+	pub quic: bool,
+}
+
+impl Default for TransportConfig {
+	fn default() -> Self {
+		Self {
+			timeout: DEFAULT_TRANSPORT_TIMEOUT,
+			max_incoming: 0,
+			max_per_peer: DEFAULT_MAX_CONNECTIONS_PER_PEER,
+			quic: false,
+		}
+	}
+}
+
+/// How often gossipsub maintains its mesh and gossips about the messages it saw, by default.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// How many peers gossipsub keeps in the mesh of each topic by default.
+pub const DEFAULT_MESH_N: usize = 6;
+/// How many heartbeats gossipsub remembers the messages it saw for by default.
+pub const DEFAULT_HISTORY_LENGTH: usize = 5;
+/// The largest message gossipsub sends or accepts by default, in bytes.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 65536;
+
+/// The settings of the gossipsub mesh. The defaults are those of gossipsub, meant for large
+/// networks; a small, fixed validator set can do with a smaller mesh and more frequent heartbeats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshConfig {
+	/// How often the mesh is maintained, and peers told of the messages seen
+	pub heartbeat_interval: Duration,
+	/// How many peers the mesh of each topic is kept at; it is allowed to shrink to five sixths of
+	/// that, and to grow to twice that, before peers are grafted or pruned
+	pub mesh_n: usize,
+	/// How many heartbeats the messages seen are kept for, to be sent to the peers asking for them
+	pub history_length: usize,
+	/// The largest message sent or accepted, in bytes
+	pub max_message_size: usize,
+	/// Whether our own messages are sent to every connected peer subscribed to their topic, rather
+	/// than to the mesh only, reaching all of those peers in one hop
+	pub flood_publish: bool,
+}
+
+impl Default for MeshConfig {
+	fn default() -> Self {
+		Self {
+			heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+			mesh_n: DEFAULT_MESH_N,
+			history_length: DEFAULT_HISTORY_LENGTH,
+			max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+			flood_publish: true,
+		}
+	}
+}
+
+impl MeshConfig {
+	/// The gossipsub configuration of these settings, relaying messages only once validated.
+	fn gossipsub_config(&self) -> Result<gossipsub::GossipsubConfig, StartupError> {
+		let invalid = |e: &str| StartupError::Gossip(format!("invalid gossipsub config: {e}"));
+		if self.heartbeat_interval.is_zero() {
+			return Err(invalid("the heartbeat interval must not be zero"))
+		}
+		let mesh_n_low = (self.mesh_n * 5 / 6).max(1);
+		gossipsub::GossipsubConfigBuilder::default()
+			.heartbeat_interval(self.heartbeat_interval)
+			.mesh_n(self.mesh_n)
+			.mesh_n_low(mesh_n_low)
+			.mesh_n_high(self.mesh_n * 2)
+			.mesh_outbound_min((self.mesh_n / 2).min(mesh_n_low).min(2))
+			.history_length(self.history_length)
+			.history_gossip(self.history_length.min(3))
+			.max_transmit_size(self.max_message_size)
+			.flood_publish(self.flood_publish)
+			.validation_mode(ValidationMode::Strict)
+			.validate_messages()
+			.build()
+			.map_err(invalid)
+	}
+}
+
 #[derive(NetworkBehaviour)]
 struct GossipNetworkBehavior {
 	gossipsub: Gossipsub,
+	proof_sync: RequestResponse<SyncCodec>,
+	membership: RequestResponse<MembershipCodec>,
 	kademlia: Kademlia<MemoryStore>,
-	mdns: MDns,
+	mdns: Toggle<MDns>,
 	identify: Identify,
+	autonat: Toggle<Autonat>,
+	relay: RelayClient,
 }
 
 /// Represents an internal message passed between the public Gossip interface and the
 /// internal GossipService handler
 enum GossipOrder {
-	SendMessage(IdentTopic, Vec<u8>),
+	/// Publish a message, ordered at the given time, acknowledging once handled, or failing once
+	/// dropped from the queue
+	SendMessage(IdentTopic, Vec<u8>, Instant, oneshot::Sender<Result<(), Error>>),
 	DialPeers(Vec<Multiaddr>),
+	/// Disconnect from a peer and stop gossiping with it, sending whether it was connected
+	RemovePeer(PeerId, oneshot::Sender<bool>),
+	/// Send the connected peers
+	ListPeers(oneshot::Sender<Vec<GossipPeer>>),
+	/// Send the addresses listened on
+	ListenAddresses(oneshot::Sender<Vec<Multiaddr>>),
 	Listen(Multiaddr),
+	/// Ask a peer for the witnesses it holds of the given events, sending how many it sent back
+	RequestProofs(PeerId, Vec<H256>, oneshot::Sender<Result<usize, Error>>),
+	/// Acknowledge once all earlier orders have been handled
+	Flush(oneshot::Sender<()>),
+	/// Leave the gossip and stop the service, acknowledging once done
+	Close(oneshot::Sender<()>),
 }
 
 /// A struct which can be used to send messages to a libp2p gossipsub(+kademlia) network.
@@ -45,35 +237,57 @@ enum GossipOrder {
 /// # use consensus_validated_streams::gossip::{Gossip, GossipHandler};
 /// # use std::sync::Arc;
 /// # use async_trait::async_trait;
+/// # use sp_core::traits::SpawnNamed;
 /// use libp2p::gossipsub::IdentTopic;
 /// struct ExampleHandler {}
 /// #[async_trait]
 /// impl GossipHandler for ExampleHandler {
-///     fn get_topics() -> Vec<IdentTopic> { vec!(IdentTopic::new("some_topic")) }
-///     async fn handle(&self, message: Vec<u8>) {
+///     fn get_topics(&self) -> Vec<IdentTopic> { vec!(IdentTopic::new("some_topic")) }
+///     async fn handle(&self, message: &[u8]) {
 ///         println!("Received message! {:?}", message);
 ///     }
 /// }
-/// # async fn async_stuff() { // Only doctest compilation, as actual usage blocks forever
+/// # async fn async_stuff(spawner: impl SpawnNamed + Clone + 'static) { // Only doctest compilation
 /// let (gossip, service) = Gossip::create();
+/// let service = service.with_spawner(spawner.clone(), "ingress", "connection", None);
 /// gossip.clone().listen("/ip4/0.0.0.0/tcp/10000".parse().unwrap());
 /// gossip.clone().connect_to(vec![ "/ip4/0.0.0.0/tcp/10001".parse().unwrap() ]);
-/// tokio::spawn(async move {
-///     service.run(Arc::new(ExampleHandler {})).await;
-/// });
+/// spawner.spawn("gossip", None, Box::pin(async move {
+///     service.run(Arc::new(ExampleHandler {})).await.unwrap();
+/// }));
 /// // Later...
-/// gossip.clone().publish(IdentTopic::new("some_topic"), vec!(0, 1, 2, 3)).await;
+/// gossip.clone().publish(IdentTopic::new("some_topic"), vec!(0, 1, 2, 3)).await.unwrap();
 /// # }
 /// ```
 #[derive(Clone)]
 pub struct Gossip {
-	tx: Sender<GossipOrder>,
+	tx: OrderSender,
 }
 
 /// A handle used to start the networking code of a [Gossip].
 #[must_use]
 pub struct GossipService {
-	rc: Receiver<GossipOrder>,
+	rc: Orders,
+	metrics: Metrics,
+	mesh_expectations: MeshExpectations,
+	telemetry: StreamsTelemetry,
+	log_limiter: LogRateLimiter,
+	heartbeat: Option<Heartbeat>,
+	listen_addresses: Vec<Multiaddr>,
+	spawner: Option<GossipSpawner>,
+	startup: StartupSignals,
+	key: Keypair,
+	transport: TransportConfig,
+	discovery_interval: Duration,
+	mdns: bool,
+	network: Option<PendingNetwork>,
+	compression: GossipCompression,
+	mesh_config: MeshConfig,
+	membership: Option<Arc<dyn Membership>>,
+	allowlist: Option<Vec<PeerId>>,
+	bans: BanConfig,
+	min_peers: usize,
+	nat: NatConfig,
 }
 
 /// A handler for all messages received or sent by a [Gossip]
@@ -81,25 +295,122 @@ pub struct GossipService {
 pub trait GossipHandler {
 	/// Returns the list of topics the [GossipHandler] is interested in. Note that changes in the
 	/// output of this function will not be picked up.
-	fn get_topics() -> Vec<IdentTopic>;
+	fn get_topics(&self) -> Vec<IdentTopic>;
 
 	/// Handles a message received on any of the topics this [GossipHandler] is subscribed to,
 	/// *or* a message sent by the [Gossip] to other peers.
 	/// Currently, messages are not differentiated by topic or origin.
-	async fn handle(&self, message: Vec<u8>);
+	async fn handle(&self, message: &[u8]);
+
+	/// Handles a message received from a peer like [Self::handle], telling whether it is valid:
+	/// gossipsub only relays the accepted messages to the other peers, and penalizes the peers
+	/// sending rejected ones. Every message is accepted by default.
+	async fn handle_received(&self, message: &[u8]) -> MessageAcceptance {
+		self.handle(message).await;
+		MessageAcceptance::Accept
+	}
+
+	/// The messages this handler holds of the given events, encoded as gossiped, for the peers
+	/// asking for them through the [sync] protocol. None by default.
+	fn held_messages(&self, _event_ids: &[H256]) -> Vec<Vec<u8>> {
+		Vec::new()
+	}
+
+	/// The key messages are handled in order by: messages of the same key are handled one after
+	/// the other, while those of different keys may be handled concurrently. Returning [None]
+	/// drops a received message without handling it, so it should only be done for messages
+	/// which can be told invalid cheaply. All messages share the same key by default.
+	fn ordering_key(&self, _message: &[u8]) -> Option<u64> {
+		Some(0)
+	}
+}
+
+/// The sending side of a gossip network. Implemented by [Gossip]; tests substitute a simulated
+/// network through it.
+#[async_trait]
+pub trait GossipTrait: Clone + Send + Sync {
+	/// Publishes a message to peers subscribed to a specific topic, and to the local
+	/// [GossipHandler].
+	async fn publish(&mut self, topic: IdentTopic, message: Vec<u8>) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl GossipTrait for Gossip {
+	async fn publish(&mut self, topic: IdentTopic, message: Vec<u8>) -> Result<(), Error> {
+		Gossip::publish(self, topic, message).await
+	}
 }
 
 impl Gossip {
 	/// Creates a new [Gossip] and a [GossipService] that can be used to start it.
 	pub fn create() -> (Self, GossipService) {
-		let (tx, rc) = channel(64); // TODO: make inbox size configurable?
+		Self::create_with_metrics(Metrics::default())
+	}
+
+	/// Like [Gossip::create], but the [GossipService] reports to the given metrics.
+	pub fn create_with_metrics(metrics: Metrics) -> (Self, GossipService) {
+		let expectations = MeshExpectations::default();
+		Self::create_with_mesh_expectations(metrics, expectations, StreamsTelemetry::default())
+	}
+
+	/// Like [Gossip::create_with_metrics], but the [GossipService] also reports the health of the
+	/// mesh against the given expectations, to the metrics and the telemetry.
+	pub fn create_with_mesh_expectations(
+		metrics: Metrics,
+		mesh_expectations: MeshExpectations,
+		telemetry: StreamsTelemetry,
+	) -> (Self, GossipService) {
+		let orders = OrderQueueConfig::default();
+		Self::create_with_order_queue(metrics, mesh_expectations, telemetry, orders)
+	}
+
+	/// Like [Gossip::create_with_mesh_expectations], with the order queue between the [Gossip] and
+	/// the [GossipService] holding as many orders as configured, and publishing as its
+	/// [Backpressure] policy says once it is full.
+	pub fn create_with_order_queue(
+		metrics: Metrics,
+		mesh_expectations: MeshExpectations,
+		telemetry: StreamsTelemetry,
+		orders: OrderQueueConfig,
+	) -> (Self, GossipService) {
+		let (tx, rc) = order_queue(orders, metrics.clone());
 
-		(Self { tx }, GossipService { rc })
+		let log_limiter = LogRateLimiter::new(metrics.clone());
+		(Self { tx }, GossipService {
+			rc,
+			metrics,
+			mesh_expectations,
+			telemetry,
+			log_limiter,
+			heartbeat: None,
+			listen_addresses: Vec::new(),
+			spawner: None,
+			startup: StartupSignals::default(),
+			key: identity::Keypair::generate_ed25519(),
+			transport: TransportConfig::default(),
+			discovery_interval: Duration::ZERO,
+			mdns: false,
+			network: None,
+			compression: GossipCompression::None,
+			mesh_config: MeshConfig::default(),
+			membership: None,
+			allowlist: None,
+			bans: BanConfig::default(),
+			min_peers: 0,
+			nat: NatConfig::default(),
+		})
 	}
 
-	/// Publishes a message to peers subscribed to a specific topic
-	pub async fn publish(&mut self, topic: IdentTopic, message: Vec<u8>) {
-		self.send_order(GossipOrder::SendMessage(topic, message)).await;
+	/// Publishes a message to peers subscribed to a specific topic, returning once the
+	/// [GossipService] has handled it. Fails if the service is not running, or if its event loop
+	/// failed before handling the message, as well as with [Error::GossipOverloaded] if the order
+	/// queue is full, or the message was dropped from it, as the [Backpressure] policy says.
+	pub async fn publish(&mut self, topic: IdentTopic, message: Vec<u8>) -> Result<(), Error> {
+		let (handled, done) = oneshot::channel();
+		self.tx.publish(GossipOrder::SendMessage(topic, message, Instant::now(), handled)).await?;
+		done.await.map_err(|_| {
+			Error::GossipUnavailable("the gossip stopped before handling the message".to_string())
+		})?
 	}
 
 	/// Connects to a list of peers
@@ -107,155 +418,1273 @@ impl Gossip {
 		self.send_order(GossipOrder::DialPeers(peers)).await;
 	}
 
+	/// Disconnects from a peer, and stops gossiping with it, as well as redialing it after a
+	/// restart, until it is dialed again with [Gossip::connect_to]. Returns whether it was
+	/// connected; fails if the service is not running.
+	pub async fn remove_peer(&mut self, peer: PeerId) -> Result<bool, Error> {
+		let (removed, done) = oneshot::channel();
+		self.send_order(GossipOrder::RemovePeer(peer, removed)).await;
+		done.await.map_err(|_| Error::GossipUnavailable("the gossip is not running".to_string()))
+	}
+
+	/// Asks a peer for the messages it holds of the given events, up to [sync::MAX_SYNC_EVENTS]
+	/// of them, e.g. after missing their gossip, and hands them to the handler as if gossiped.
+	/// Returns how many the peer sent; fails if the service is not running, or if the request
+	/// fails.
+	pub async fn request_proofs(
+		&mut self,
+		peer: PeerId,
+		event_ids: Vec<H256>,
+	) -> Result<usize, Error> {
+		let (received, done) = oneshot::channel();
+		self.send_order(GossipOrder::RequestProofs(peer, event_ids, received)).await;
+		done.await.map_err(|_| Error::GossipUnavailable("the gossip is not running".to_string()))?
+	}
+
+	/// The connected peers. Fails if the service is not running.
+	pub async fn peers(&mut self) -> Result<Vec<GossipPeer>, Error> {
+		let (listed, done) = oneshot::channel();
+		self.send_order(GossipOrder::ListPeers(listed)).await;
+		done.await.map_err(|_| Error::GossipUnavailable("the gossip is not running".to_string()))
+	}
+
+	/// The addresses the service listens on, every one of them once the listener is up, e.g. both
+	/// its TCP and WebSocket addresses. None on the network backend, where the network of the node
+	/// listens instead. Fails if the service is not running.
+	pub async fn listen_addresses(&mut self) -> Result<Vec<Multiaddr>, Error> {
+		let (listed, done) = oneshot::channel();
+		self.send_order(GossipOrder::ListenAddresses(listed)).await;
+		done.await.map_err(|_| Error::GossipUnavailable("the gossip is not running".to_string()))
+	}
+
 	/// Listen on an address
 	pub async fn listen(&mut self, address: Multiaddr) {
 		self.send_order(GossipOrder::Listen(address)).await;
 	}
 
+	/// Waits until every order sent before has been handled; in particular, until every message
+	/// published or received before has been handled by the [GossipHandler].
+	pub async fn flush(&mut self) {
+		let (flushed, done) = oneshot::channel();
+		self.send_order(GossipOrder::Flush(flushed)).await;
+		done.await.ok();
+	}
+
+	/// Leaves the topics and disconnects from all peers, once every order sent before has been
+	/// handled, and stops the [GossipService].
+	pub async fn close(&mut self) {
+		let (closed, done) = oneshot::channel();
+		self.send_order(GossipOrder::Close(closed)).await;
+		done.await.ok();
+	}
+
 	/// Send an order to the internal channel between the Gossip and
 	/// GossipService::run -- creating an "Actor" model out of the two.
 	async fn send_order(&mut self, order: GossipOrder) {
-		self.tx
-			.send(order)
-			.await
-			.unwrap_or_else(|e| log::error!("could not send order due to error:{:?}", e));
+		self.tx.send(order).await
 	}
 }
 
 impl GossipService {
-	/// Starts the gossip service. This function never returns, so make sure to spawn it as a
-	/// separate task.
-	pub async fn run<H: GossipHandler + Send + Sync + 'static>(self, handler: Arc<H>) -> ! {
-		let mut swarm = Self::create_swarm();
+	/// Makes the service bump the given heartbeat every time around its loop.
+	pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+		self.heartbeat = Some(heartbeat);
+		self
+	}
+
+	/// Makes the service identify itself to its peers with the given key, rather than with a new
+	/// ed25519 key, so that its [PeerId] stays the same across restarts (see [derive_gossip_key],
+	/// [gossip_key_from_seed] and [load_gossip_key]).
+	pub fn with_key(mut self, key: Keypair) -> Self {
+		self.key = key;
+		self
+	}
+
+	/// Makes the service connect to its peers with the given transport settings and limits, rather
+	/// than with the [default](TransportConfig::default) ones.
+	pub fn with_transport(mut self, transport: TransportConfig) -> Self {
+		self.transport = transport;
+		self
+	}
 
-		for topic in H::get_topics() {
-			swarm.behaviour_mut().gossipsub.subscribe(&topic).ok();
+	/// Makes the service keep its gossipsub mesh with the given settings, rather than with the
+	/// [default](MeshConfig::default) ones.
+	pub fn with_mesh(mut self, mesh: MeshConfig) -> Self {
+		self.mesh_config = mesh;
+		self
+	}
+
+	/// Makes the service discover more peers through the Kademlia DHT of the gossip every
+	/// `interval`, dialing the peers it learns of, so that it only needs a few peers to start
+	/// from, e.g. the bootnodes. Without it, the service only connects to the peers it is told to
+	/// dial, and to those which dial it.
+	pub fn with_discovery_interval(mut self, interval: Duration) -> Self {
+		self.discovery_interval = interval;
+		self
+	}
+
+	/// Makes the service discover the peers on its local network through mDNS, dialing them as
+	/// they are found, as for development networks on a single LAN or docker network.
+	pub fn with_mdns(mut self, mdns: bool) -> Self {
+		self.mdns = mdns;
+		self
+	}
+
+	/// Makes the service gossip over the [PROTOCOL_NAME] notification protocol of the network
+	/// service of the node, once provided, rather than over a swarm of its own. The listen
+	/// addresses, key, transport, discovery and NAT settings of the service are then left unused.
+	pub fn with_network(mut self, network: PendingNetwork) -> Self {
+		self.network = Some(network);
+		self
+	}
+
+	/// Makes the service compress the messages it publishes, which peers running a version of the
+	/// service that cannot decompress them fail to handle. Received messages are decompressed
+	/// however they were compressed, whatever the setting.
+	pub fn with_compression(mut self, compression: GossipCompression) -> Self {
+		self.compression = compression;
+		self
+	}
+
+	/// Makes the service prove to its peers that it belongs to the validator set, and verify that
+	/// they do, as described in [admission].
+	pub fn with_membership(mut self, membership: Arc<dyn Membership>) -> Self {
+		self.membership = Some(membership);
+		self
+	}
+
+	/// Makes the service only gossip with the given peers, and those proving they belong to the
+	/// validator set through its [membership](Self::with_membership), disconnecting the others.
+	/// Only the swarm backend restricts its peers; running on a network fails.
+	pub fn with_allowlist(mut self, allowlist: Vec<PeerId>) -> Self {
+		self.allowlist = Some(allowlist);
+		self
+	}
+
+	/// Makes the service ban the peers which keep sending messages the handler rejects, or more
+	/// messages than it can take, as configured. Peers are banned by default with a
+	/// [BanConfig::default]; only the swarm backend bans them.
+	pub fn with_bans(mut self, bans: BanConfig) -> Self {
+		self.bans = bans;
+		self
+	}
+
+	/// Makes the service mark the [StartupStep::PeersConnected] step of its startup only once
+	/// that many peers subscribed to its topics, rather than right away, so that the node does not
+	/// witness events before anyone can receive them. With [restricted](Self::with_allowlist)
+	/// peers, only the admitted ones count.
+	pub fn with_min_peers(mut self, min_peers: usize) -> Self {
+		self.min_peers = min_peers;
+		self
+	}
+
+	/// Makes the service reachable from behind a NAT as configured, as described in [nat]. The
+	/// relays are kept connected even with [restricted](Self::with_allowlist) peers.
+	pub fn with_nat(mut self, nat: NatConfig) -> Self {
+		self.nat = nat;
+		self
+	}
+
+	/// The peer ID the service identifies itself to its peers with.
+	pub fn peer_id(&self) -> PeerId {
+		PeerId::from(self.key.public())
+	}
+
+	/// Makes the service listen on the given addresses as soon as it runs. Unlike
+	/// [Gossip::listen], failing to listen on all of them stops the service from running.
+	pub fn with_listen_addresses(mut self, addresses: Vec<Multiaddr>) -> Self {
+		self.listen_addresses = addresses;
+		self
+	}
+
+	/// Makes the service spawn the workers handling its messages, and the tasks driving the
+	/// connections of its swarm, through the given spawner, as tasks of the given names and group.
+	/// The service does not run without one.
+	pub fn with_spawner(
+		mut self,
+		spawner: impl SpawnNamed + 'static,
+		worker_task: &'static str,
+		connection_task: &'static str,
+		group: Option<&'static str>,
+	) -> Self {
+		self.spawner = Some(GossipSpawner::new(spawner, worker_task, connection_task, group));
+		self
+	}
+
+	/// The spawner the service was given, which it needs to run.
+	#[cfg(not(test))]
+	fn required_spawner(spawner: Option<GossipSpawner>) -> Result<GossipSpawner, StartupError> {
+		spawner.ok_or_else(|| StartupError::Gossip("the gossip was given no spawner".to_string()))
+	}
+
+	/// The spawner the service was given, with tests falling back to [tokio::spawn].
+	#[cfg(test)]
+	fn required_spawner(spawner: Option<GossipSpawner>) -> Result<GossipSpawner, StartupError> {
+		Ok(spawner.unwrap_or_default())
+	}
+
+	/// Makes the service mark the [StartupStep::GossipListening] step of the given startup once it
+	/// listens on an address, the [StartupStep::TopicsSubscribed] one once it has subscribed, and
+	/// the [StartupStep::PeersConnected] one once it has [enough peers](Self::with_min_peers).
+	pub fn with_startup(mut self, startup: StartupSignals) -> Self {
+		self.startup = startup;
+		self
+	}
+
+	/// Starts the gossip service. This function only returns if the service fails to start, or
+	/// once the gossip is [closed](Gossip::close), so make sure to spawn it as a separate task.
+	/// Messages are handed over to the handler through the [ingress] queues, by
+	/// [ingress::INGRESS_WORKERS] workers spawned the first time around.
+	///
+	/// If the event loop fails, e.g. on a panic in a behaviour or in the handler, the swarm is
+	/// rebuilt with the same key, and set back up with the addresses listened on and the peers
+	/// dialed so far, after a [backoff](RESTART_BACKOFF). Orders still queued survive the restart,
+	/// while a message being published at the time fails. Failing more than [MAX_RESTARTS] times
+	/// within the [RESTART_WINDOW] is a fatal error.
+	///
+	/// Given a [network](GossipService::with_network), the service runs on it instead, as
+	/// described in [network].
+	pub async fn run<H: GossipHandler + Send + Sync + 'static>(
+		mut self,
+		handler: Arc<H>,
+	) -> Result<(), StartupError> {
+		if let Some(network) = self.network.take() {
+			return self.run_on_network(network, handler).await
 		}
+		let Self {
+			rc,
+			metrics,
+			mesh_expectations,
+			telemetry,
+			log_limiter,
+			heartbeat,
+			mut listen_addresses,
+			spawner,
+			startup,
+			key,
+			transport,
+			discovery_interval,
+			mdns,
+			network: _,
+			compression,
+			mesh_config,
+			membership,
+			allowlist,
+			bans,
+			min_peers,
+			nat,
+		} = self;
+		nat.validate()?;
+		let spawner = Self::required_spawner(spawner)?;
+		let allowlist = allowlist.map(|allowlist| {
+			allowlist.into_iter().chain(nat.relay_peers()).collect::<Vec<_>>()
+		});
+		let ingress = Ingress::new(metrics.clone());
+		let (worker_failures, mut failed_workers) = unbounded();
+		let (verdicts, mut verdicts_rc) = unbounded();
+		if transport.quic {
+			let quic = listen_addresses.iter().filter_map(quic_address).collect::<Vec<_>>();
+			listen_addresses.extend(quic);
+		}
+		let mut setup = SwarmSetup { listen_addresses, peers: Vec::new(), removed: Vec::new() };
+		let mut failures = VecDeque::new();
+		let mut bans = Bans::new(bans);
+		loop {
+			let mut swarm =
+				Self::create_swarm(&key, &transport, mdns, &mesh_config, nat.autonat, &spawner)?;
+			Self::listen_on_all(&mut swarm, &setup.listen_addresses)?;
+			nat::set_up(&mut swarm, &nat);
+			Self::dial_peers(&mut swarm, &setup.peers);
+			for (peer, _) in &setup.removed {
+				swarm.behaviour_mut().gossipsub.blacklist_peer(peer);
+			}
+			for peer in bans.banned() {
+				swarm.ban_peer_id(*peer);
+			}
+			let topics = handler.get_topics();
+			for topic in &topics {
+				swarm.behaviour_mut().gossipsub.subscribe(topic).ok();
+			}
+			swarm
+				.behaviour_mut()
+				.gossipsub
+				.with_peer_score(peer_score_params(&topics), peer_score_thresholds())
+				.map_err(|e| StartupError::Gossip(format!("invalid peer scoring: {e}")))?;
+			startup.mark_done(StartupStep::TopicsSubscribed);
 
-		Self::run_loop(&mut swarm, self.rc, handler.as_ref()).await
+			ingress.spawn_workers(&handler, &spawner, &worker_failures);
+
+			let mut mesh =
+				MeshMonitor::new(mesh_expectations.clone(), metrics.clone(), telemetry.clone());
+			let mut admissions =
+				allowlist.as_ref().map(|allowlist| Admissions::new(membership.clone(), allowlist));
+			let run_loop = Self::run_loop(&mut swarm, RunLoopParams {
+				rc: &rc,
+				handler: handler.as_ref(),
+				ingress: &ingress,
+				failed_workers: &mut failed_workers,
+				verdicts: &verdicts,
+				verdicts_rc: &mut verdicts_rc,
+				metrics: &metrics,
+				startup: &startup,
+				log_limiter: &log_limiter,
+				mesh: &mut mesh,
+				heartbeat: heartbeat.as_ref(),
+				setup: &mut setup,
+				discovery_interval,
+				compression,
+				membership: membership.as_ref(),
+				admissions: admissions.as_mut(),
+				bans: &mut bans,
+				min_peers,
+			});
+			let Err(panic) = AssertUnwindSafe(run_loop).catch_unwind().await else {
+				return Ok(())
+			};
+			drop(swarm);
+			metrics.set_gossip_peers(0);
+			tokio::time::sleep(restart_backoff(&mut failures, panic.as_ref(), &metrics)?).await;
+		}
 	}
 
-	/// Runs a select loop that handles events from the network and from orders
+	/// Runs a select loop that handles events from the network and from orders, until ordered to
+	/// close. A panic of a worker is resumed here, failing the loop.
 	async fn run_loop<H: GossipHandler + Send + Sync>(
 		swarm: &mut Swarm<GossipNetworkBehavior>,
-		mut rc: Receiver<GossipOrder>,
-		handler: &H,
-	) -> ! {
+		params: RunLoopParams<'_, H>,
+	) {
+		let RunLoopParams {
+			rc,
+			handler,
+			ingress,
+			failed_workers,
+			verdicts,
+			verdicts_rc,
+			metrics,
+			startup,
+			log_limiter,
+			mesh,
+			heartbeat,
+			setup,
+			discovery_interval,
+			compression,
+			membership,
+			mut admissions,
+			bans,
+			min_peers,
+		} = params;
+		// The mesh checks also keep the heartbeat going while the network is quiet
+		let mut mesh_checks = tokio::time::interval(MESH_CHECK_INTERVAL);
+		let mut discoveries =
+			(!discovery_interval.is_zero()).then(|| tokio::time::interval(discovery_interval));
+		let context =
+			LoopContext { handler, ingress, verdicts, metrics, startup, log_limiter, compression };
+		let mut redials = Redials::default();
+		let mut proof_requests = HashMap::new();
+		let mut proving = FuturesUnordered::<BoxFuture<'static, _>>::new();
 		loop {
+			if let Some(heartbeat) = heartbeat {
+				heartbeat.bump();
+			}
+			redials.sync(&setup.peers, tokio::time::Instant::now());
+			if !startup.is_done(StartupStep::PeersConnected) {
+				let admitted = |peer: &&PeerId| {
+					admissions.as_ref().map_or(true, |admissions| admissions.is_admitted(peer))
+				};
+				let connected = mesh.subscribed().filter(admitted).count();
+				mark_peers_connected(startup, min_peers, connected);
+			}
+			let discovery = async {
+				match &mut discoveries {
+					Some(discoveries) => discoveries.tick().await,
+					None => future::pending().await,
+				}
+			};
+			let next_redial = redials.next_due();
+			let redial = async move {
+				match next_redial {
+					Some(due) => tokio::time::sleep_until(due).await,
+					None => future::pending().await,
+				}
+			};
+			let next_unban = bans.next_unban();
+			let unban = async move {
+				match next_unban {
+					Some(due) => tokio::time::sleep_until(due).await,
+					None => future::pending().await,
+				}
+			};
 			select! {
-				order = rc.select_next_some() => Self::handle_incoming_order(swarm, order, handler).await,
-				event = swarm.select_next_some() => Self::handle_incoming_event(swarm, event, handler).await,
+				order = rc.next().fuse() => match order {
+					GossipOrder::Close(closed) => {
+						Self::close(swarm, handler, metrics).await;
+						closed.send(()).ok();
+						return
+					},
+					GossipOrder::ListPeers(listed) => {
+						listed.send(mesh.peers()).ok();
+					},
+					GossipOrder::ListenAddresses(listed) => {
+						listed.send(swarm.listeners().cloned().collect()).ok();
+					},
+					GossipOrder::RemovePeer(peer, removed) => {
+						setup.forget(peer, mesh.addresses_of(&peer));
+						swarm.behaviour_mut().gossipsub.blacklist_peer(&peer);
+						swarm.behaviour_mut().kademlia.remove_peer(&peer);
+						let connected = swarm.disconnect_peer_id(peer).is_ok();
+						tracing::info!(target: GOSSIP, peer = %peer, connected, "Removed peer");
+						removed.send(connected).ok();
+					},
+					GossipOrder::RequestProofs(peer, mut event_ids, received) => {
+						event_ids.truncate(sync::MAX_SYNC_EVENTS);
+						let proof_sync = &mut swarm.behaviour_mut().proof_sync;
+						let request = proof_sync.send_request(&peer, ProofsRequest { event_ids });
+						proof_requests.insert(request, received);
+					},
+					order => {
+						for peer in setup.record(&order) {
+							swarm.behaviour_mut().gossipsub.remove_blacklisted_peer(&peer);
+						}
+						Self::handle_incoming_order(swarm, order, &context)
+					},
+				},
+				event = swarm.select_next_some() => match event {
+					SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::ProofSync(event)) =>
+						Self::handle_sync_event(
+							swarm,
+							event,
+							handler,
+							ingress,
+							setup,
+							&mut proof_requests,
+						),
+					SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Membership(event)) => {
+						let admissions = admissions.as_deref_mut();
+						Self::handle_membership_event(swarm, event, admissions, setup)
+					},
+					SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Gossipsub(
+						GossipsubEvent::Message { propagation_source, message_id, .. },
+					)) if admissions.as_ref().map_or(false, |admissions| {
+						!admissions.is_admitted(&propagation_source)
+					}) => {
+						let ignored = (message_id, propagation_source, MessageAcceptance::Ignore);
+						verdicts.unbounded_send(ignored).ok();
+					},
+					event => {
+						if let SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Gossipsub(
+							GossipsubEvent::Message { propagation_source, .. },
+						)) = &event
+						{
+							let now = tokio::time::Instant::now();
+							if bans.on_message(*propagation_source, now) {
+								Self::ban_peer(swarm, *propagation_source, Violation::Flooding);
+							}
+						}
+						if let SwarmEvent::ConnectionEstablished { peer_id, num_established, .. } =
+							&event
+						{
+							if num_established.get() == 1 {
+								let admissions = admissions.as_deref_mut();
+								Self::admit(swarm, *peer_id, membership, admissions, &mut proving);
+							}
+						}
+						if let SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } =
+							&event
+						{
+							if let Some(admissions) = admissions.as_deref_mut() {
+								admissions.on_disconnected(peer_id);
+							}
+							bans.on_disconnected(peer_id, tokio::time::Instant::now());
+						}
+						let redials = &mut redials;
+						Self::handle_incoming_event(swarm, event, &context, mesh, setup, redials)
+					},
+				},
+				(peer, proof) = proving.select_next_some() => match proof {
+					Some(proof) if swarm.is_connected(&peer) => {
+						swarm.behaviour_mut().membership.send_request(&peer, proof);
+					},
+					Some(_) => {},
+					None => tracing::debug!(
+						target: GOSSIP,
+						peer = %peer,
+						"Not a validator; cannot prove membership to the peer"
+					),
+				},
+				(id, source, acceptance) = verdicts_rc.select_next_some() => {
+					tracing::trace!(target: GOSSIP, peer = %source, ?acceptance, "Validated");
+					let rejected = matches!(acceptance, MessageAcceptance::Reject);
+					let now = tokio::time::Instant::now();
+					if rejected && bans.on_violation(source, Violation::Rejected, now) {
+						Self::ban_peer(swarm, source, Violation::Rejected);
+					}
+					// Fails only for the messages no longer held back, e.g. before a restart
+					let gossipsub = &mut swarm.behaviour_mut().gossipsub;
+					gossipsub.report_message_validation_result(&id, &source, acceptance).ok();
+				},
+				_ = mesh_checks.tick().fuse() => {
+					mesh.check(Instant::now());
+					if let Some(admissions) = admissions.as_deref_mut() {
+						Self::expel_unproven(swarm, admissions);
+					}
+				},
+				_ = discovery.fuse() => Self::discover_peers(swarm, setup),
+				_ = redial.fuse() => Self::redial_peers(swarm, &mut redials),
+				_ = unban.fuse() => for peer in bans.take_unbanned(tokio::time::Instant::now()) {
+					tracing::info!(target: GOSSIP, peer = %peer, "Unbanned peer");
+					swarm.unban_peer_id(peer);
+				},
+				panic = failed_workers.select_next_some() => panic::resume_unwind(panic),
 			}
 		}
 	}
 
 	/// Handles an incoming channel order
-	async fn handle_incoming_order<H: GossipHandler + Send>(
+	fn handle_incoming_order<H: GossipHandler>(
 		swarm: &mut Swarm<GossipNetworkBehavior>,
 		order: GossipOrder,
-		handler: &H,
+		context: &LoopContext<'_, H>,
 	) {
+		let LoopContext { handler, ingress, metrics, log_limiter, compression, .. } = *context;
 		match order {
-			GossipOrder::SendMessage(topic, message) => {
-				if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, message.clone()) {
-					log::info!("Failed Gossiping message with Error: {:?}", e);
+			GossipOrder::SendMessage(topic, message, ordered, handled) => {
+				ingress.publish(handler, message.clone(), handled);
+				let message = compression.compress(message);
+				match swarm.behaviour_mut().gossipsub.publish(topic, message) {
+					Ok(_) => metrics.on_gossip_published(ordered.elapsed()),
+					Err(e) => rate_limited!(
+						log_limiter,
+						"failed_gossip_publish",
+						info,
+						target: GOSSIP,
+						error = ?e,
+						"Failed gossiping message"
+					),
 				}
-				handler.handle(message).await;
-				log::trace!("Gossiped a message!");
+				tracing::trace!(target: GOSSIP, "Gossiped a message");
 			},
 			GossipOrder::DialPeers(peers) => {
 				Self::dial_peers(swarm, &peers);
 			},
+			GossipOrder::Flush(flushed) => {
+				ingress.flush(flushed);
+			},
+			GossipOrder::Close(_) |
+			GossipOrder::RemovePeer(..) |
+			GossipOrder::ListPeers(_) |
+			GossipOrder::ListenAddresses(_) |
+			GossipOrder::RequestProofs(..) => unreachable!("handled by the run loop"),
 			GossipOrder::Listen(listen_addr) => {
-				log::info!("Listening on {:?}", listen_addr);
+				tracing::info!(target: GOSSIP, "Listening on {:?}", listen_addr);
 				if let Err(e) = swarm.listen_on(listen_addr) {
-					log::info!("Failed listening on provided Address: {:?}", e);
+					tracing::info!(
+						target: GOSSIP,
+						error = ?e,
+						"Failed listening on provided address"
+					);
 				}
 			},
 		}
 	}
 
-	/// Handles an incoming swarm event, passing message data to the handler
-	async fn handle_incoming_event<H: GossipHandler + Send>(
+	/// Handles an incoming swarm event, queueing message data for the handler
+	fn handle_incoming_event<H: GossipHandler>(
 		swarm: &mut Swarm<GossipNetworkBehavior>,
 		event: SwarmEvent<GossipNetworkBehaviorEvent, impl std::fmt::Display>,
-		handler: &H,
+		context: &LoopContext<'_, H>,
+		mesh: &mut MeshMonitor,
+		setup: &SwarmSetup,
+		redials: &mut Redials,
 	) {
+		let LoopContext { handler, ingress, verdicts, metrics, startup, .. } = *context;
 		match event {
-			SwarmEvent::NewListenAddr { address, .. } => log::info!("Listening on {:?}", address),
+			SwarmEvent::NewListenAddr { address, .. } => {
+				tracing::info!(target: GOSSIP, "Listening on {:?}", address);
+				startup.mark_done(StartupStep::GossipListening);
+			},
+			SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+				tracing::debug!(target: GOSSIP, peer = %peer_id, "Connection established");
+				metrics.set_gossip_peers(swarm.connected_peers().count());
+				let dialed = match endpoint {
+					ConnectedPoint::Dialer { address, .. } => Some(address),
+					ConnectedPoint::Listener { .. } => None,
+				};
+				redials.on_connected(peer_id, dialed.iter());
+				mesh.on_connected(peer_id, dialed);
+			},
+			SwarmEvent::ConnectionClosed { peer_id, num_established, .. } => {
+				tracing::debug!(target: GOSSIP, peer = %peer_id, "Connection closed");
+				metrics.set_gossip_peers(swarm.connected_peers().count());
+				if num_established == 0 {
+					redials.on_disconnected(&peer_id, tokio::time::Instant::now());
+					mesh.on_disconnected(&peer_id);
+					mesh.check(Instant::now());
+				}
+			},
 			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Gossipsub(
 				GossipsubEvent::Subscribed { peer_id, topic },
 			)) => {
-				log::info!("{:?} subscribed to topic {:?}", peer_id, topic);
+				tracing::info!(
+					target: GOSSIP,
+					peer = %peer_id,
+					"Peer subscribed to topic {:?}",
+					topic
+				);
+				mesh.on_subscribed(peer_id);
+				mesh.check(Instant::now());
 			},
 			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Gossipsub(
-				GossipsubEvent::Message { message, .. },
+				GossipsubEvent::Message { propagation_source, message_id, message },
 			)) => {
-				handler.handle(message.data).await;
+				let span = tracing::debug_span!(
+					target: GOSSIP,
+					"gossip_message",
+					peer = %propagation_source
+				);
+				let data = match compression::decompress(message.data) {
+					Ok(data) => data,
+					Err(e) => {
+						tracing::debug!(
+							target: GOSSIP,
+							peer = %propagation_source,
+							error = %e,
+							"Failed decompressing a message"
+						);
+						let rejected = (message_id, propagation_source, MessageAcceptance::Reject);
+						verdicts.unbounded_send(rejected).ok();
+						return
+					},
+				};
+				let validation = Validation {
+					id: message_id,
+					source: propagation_source,
+					verdicts: verdicts.clone(),
+				};
+				ingress.receive(handler, data, span, Some(validation));
 			},
 			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Identify(
 				IdentifyEvent::Received { info, peer_id },
-			)) =>
-				for addr in info.listen_addrs {
+			)) => {
+				if version::is_incompatible(&info.protocol_version) {
+					tracing::warn!(
+						target: GOSSIP,
+						peer = %peer_id,
+						version = %info.protocol_version,
+						ours = PROTOCOL_VERSION,
+						"Disconnecting a peer of an incompatible protocol version"
+					);
+					metrics.on_gossip_incompatible("peer");
+					swarm.disconnect_peer_id(peer_id).ok();
+					return
+				}
+				for addr in &info.listen_addrs {
 					swarm.behaviour_mut().kademlia.add_address(&peer_id, addr.clone());
+				}
+				// Configured peers may have dialed us first
+				redials.on_connected(peer_id, info.listen_addrs.iter());
+				mesh.on_identified(peer_id, info.listen_addrs);
+			},
+			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Mdns(MdnsEvent::Discovered(
+				discovered,
+			))) =>
+				for (peer_id, address) in discovered {
+					if swarm.is_connected(&peer_id) || setup.is_removed(&peer_id) {
+						continue
+					}
+					tracing::debug!(
+						target: GOSSIP, peer = %peer_id, %address, "Found a peer through mDNS"
+					);
+					swarm.behaviour_mut().kademlia.add_address(&peer_id, address.clone());
+					let dial = DialOpts::peer_id(peer_id)
+						.addresses(vec![address])
+						.condition(PeerCondition::Disconnected)
+						.build();
+					if let Err(e) = swarm.dial(dial) {
+						tracing::debug!(
+							target: GOSSIP, peer = %peer_id, error = %e, "Failed dialing a peer"
+						);
+					}
 				},
+			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Autonat(event)) =>
+				nat::on_autonat_event(event),
+			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Relay(event)) =>
+				nat::on_relay_event(event),
+			_ => {},
+		}
+	}
+
+	/// Handles an event of the [sync] protocol: answers the requests of peers with the messages
+	/// the handler holds, and hands those of the responses to the handler, unvalidated by
+	/// gossipsub as they are not relayed.
+	fn handle_sync_event<H: GossipHandler>(
+		swarm: &mut Swarm<GossipNetworkBehavior>,
+		event: RequestResponseEvent<ProofsRequest, ProofsResponse>,
+		handler: &H,
+		ingress: &Ingress,
+		setup: &SwarmSetup,
+		proof_requests: &mut HashMap<RequestId, oneshot::Sender<Result<usize, Error>>>,
+	) {
+		match event {
+			RequestResponseEvent::Message {
+				peer,
+				message: RequestResponseMessage::Request { request, channel, .. },
+			} => {
+				if setup.is_removed(&peer) {
+					// Dropping the channel fails the request
+					return
+				}
+				let response = ProofsResponse::new(handler.held_messages(&request.event_ids));
+				tracing::debug!(
+					target: GOSSIP,
+					peer = %peer,
+					requested = request.event_ids.len(),
+					sent = response.witnesses.len(),
+					"Answered a proofs request"
+				);
+				swarm.behaviour_mut().proof_sync.send_response(channel, response).ok();
+			},
+			RequestResponseEvent::Message {
+				peer,
+				message: RequestResponseMessage::Response { request_id, response },
+			} => {
+				let received = response.witnesses.len();
+				for message in response.witnesses {
+					let span = tracing::debug_span!(target: GOSSIP, "synced_message", peer = %peer);
+					ingress.receive(handler, message, span, None);
+				}
+				if let Some(requested) = proof_requests.remove(&request_id) {
+					requested.send(Ok(received)).ok();
+				}
+			},
+			RequestResponseEvent::OutboundFailure { peer, request_id, error } => {
+				tracing::debug!(
+					target: GOSSIP,
+					peer = %peer,
+					error = %error,
+					"Failed requesting proofs"
+				);
+				if let Some(requested) = proof_requests.remove(&request_id) {
+					let error = format!("failed requesting proofs of {peer}: {error}");
+					requested.send(Err(Error::GossipUnavailable(error))).ok();
+				}
+			},
+			RequestResponseEvent::InboundFailure { peer, error, .. } => {
+				tracing::debug!(
+					target: GOSSIP,
+					peer = %peer,
+					error = %error,
+					"Failed answering a proofs request"
+				);
+			},
+			RequestResponseEvent::ResponseSent { .. } => {},
+		}
+	}
+
+	/// Proves the membership of the node to a new peer, if it can, and gives the peer until the
+	/// [admission::ADMISSION_TIMEOUT] to prove its own, if the peers are restricted.
+	fn admit(
+		swarm: &mut Swarm<GossipNetworkBehavior>,
+		peer: PeerId,
+		membership: Option<&Arc<dyn Membership>>,
+		admissions: Option<&mut Admissions>,
+		proving: &mut FuturesUnordered<BoxFuture<'static, (PeerId, Option<MembershipProof>)>>,
+	) {
+		if let Some(membership) = membership {
+			let (membership, local) = (membership.clone(), *swarm.local_peer_id());
+			proving.push(async move { (peer, membership.prove(local).await) }.boxed());
+		}
+		let Some(admissions) = admissions else { return };
+		if admissions.on_connected(peer, Instant::now()) {
+			tracing::debug!(target: GOSSIP, peer = %peer, "Waiting for a membership proof");
+		}
+	}
+
+	/// Handles an event of the [admission] protocol: admits the peers proving their membership,
+	/// if they are restricted, and disconnects those failing to. The messages of the peers are
+	/// ignored until they are admitted.
+	fn handle_membership_event(
+		swarm: &mut Swarm<GossipNetworkBehavior>,
+		event: RequestResponseEvent<MembershipProof, bool>,
+		admissions: Option<&mut Admissions>,
+		setup: &SwarmSetup,
+	) {
+		match event {
+			RequestResponseEvent::Message {
+				peer,
+				message: RequestResponseMessage::Request { request, channel, .. },
+			} => {
+				if setup.is_removed(&peer) {
+					return
+				}
+				let Some(admissions) = admissions else {
+					swarm.behaviour_mut().membership.send_response(channel, true).ok();
+					return
+				};
+				let was_admitted = admissions.is_admitted(&peer);
+				let admitted = admissions.on_proof(peer, request);
+				swarm.behaviour_mut().membership.send_response(channel, admitted).ok();
+				if !admitted {
+					tracing::info!(target: GOSSIP, peer = %peer, "Disconnecting a non-validator");
+					swarm.disconnect_peer_id(peer).ok();
+				} else if !was_admitted {
+					tracing::info!(target: GOSSIP, peer = %peer, "Admitted validator peer");
+				}
+			},
+			RequestResponseEvent::Message {
+				peer,
+				message: RequestResponseMessage::Response { response: false, .. },
+			} => {
+				tracing::info!(target: GOSSIP, peer = %peer, "Peer refused our membership proof");
+			},
+			RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+				tracing::debug!(
+					target: GOSSIP,
+					peer = %peer,
+					error = %error,
+					"Failed proving membership to the peer"
+				);
+			},
 			_ => {},
 		}
 	}
 
+	/// Bans a peer which reached the violation threshold, disconnecting it and refusing its
+	/// connections until the ban runs out.
+	fn ban_peer(swarm: &mut Swarm<GossipNetworkBehavior>, peer: PeerId, violation: Violation) {
+		tracing::warn!(target: GOSSIP, peer = %peer, ?violation, "Banned peer for violations");
+		swarm.ban_peer_id(peer);
+	}
+
+	/// Disconnects the restricted peers which did not prove their membership in time, or no
+	/// longer belong to the validator set.
+	fn expel_unproven(swarm: &mut Swarm<GossipNetworkBehavior>, admissions: &mut Admissions) {
+		for peer in admissions.take_expelled(Instant::now()) {
+			tracing::info!(target: GOSSIP, peer = %peer, "Disconnecting peer without membership");
+			swarm.disconnect_peer_id(peer).ok();
+		}
+	}
+
+	/// Leaves the topics of the handler and disconnects from every peer, waiting up to
+	/// [CLOSE_GRACE] for the connections to close. Messages received meanwhile are ignored.
+	async fn close<H: GossipHandler>(
+		swarm: &mut Swarm<GossipNetworkBehavior>,
+		handler: &H,
+		metrics: &Metrics,
+	) {
+		for topic in handler.get_topics() {
+			swarm.behaviour_mut().gossipsub.unsubscribe(&topic).ok();
+		}
+		let peers: Vec<PeerId> = swarm.connected_peers().copied().collect();
+		tracing::info!(target: GOSSIP, peers = peers.len(), "Disconnecting from gossip peers");
+		for peer in peers {
+			swarm.disconnect_peer_id(peer).ok();
+		}
+		let closing = async {
+			while swarm.connected_peers().next().is_some() {
+				swarm.select_next_some().await;
+			}
+		};
+		if tokio::time::timeout(CLOSE_GRACE, closing).await.is_err() {
+			tracing::warn!(target: GOSSIP, "Gossip connections still open after the grace period");
+		}
+		metrics.set_gossip_peers(swarm.connected_peers().count());
+		tracing::info!(target: GOSSIP, "Gossip closed");
+	}
+
+	/// Dials the peers in the routing table of the DHT which are not connected, leaving out the
+	/// removed ones, and looks up more peers from the known ones, which are dialed in turn once
+	/// the lookup adds them to the routing table.
+	fn discover_peers(swarm: &mut Swarm<GossipNetworkBehavior>, setup: &SwarmSetup) {
+		let known: Vec<(PeerId, Vec<Multiaddr>)> = swarm
+			.behaviour_mut()
+			.kademlia
+			.kbuckets()
+			.flat_map(|bucket| {
+				let entries = bucket.iter().map(|entry| {
+					(*entry.node.key.preimage(), entry.node.value.iter().cloned().collect())
+				});
+				entries.collect::<Vec<_>>()
+			})
+			.collect();
+		for (peer, addresses) in known {
+			if swarm.is_connected(&peer) || setup.is_removed(&peer) {
+				continue
+			}
+			tracing::debug!(target: GOSSIP, peer = %peer, ?addresses, "Dialing a discovered peer");
+			let dial = DialOpts::peer_id(peer)
+				.addresses(addresses)
+				.condition(PeerCondition::Disconnected)
+				.build();
+			if let Err(e) = swarm.dial(dial) {
+				tracing::debug!(target: GOSSIP, peer = %peer, error = %e, "Failed dialing a peer");
+			}
+		}
+		// Fails only while no peer is known, until the first one connects
+		if let Err(e) = swarm.behaviour_mut().kademlia.bootstrap() {
+			tracing::trace!(target: GOSSIP, error = ?e, "Not looking up peers");
+		}
+	}
+
+	/// Dials the configured peers whose redial is due, by peer ID once known, so that peers which
+	/// connected meanwhile are not dialed again.
+	fn redial_peers(swarm: &mut Swarm<GossipNetworkBehavior>, redials: &mut Redials) {
+		for (address, peer) in redials.take_due(tokio::time::Instant::now()) {
+			let dial = match peer {
+				Some(peer) => DialOpts::peer_id(peer)
+					.addresses(vec![address.clone()])
+					.condition(PeerCondition::Disconnected)
+					.build(),
+				None => DialOpts::unknown_peer_id().address(address.clone()).build(),
+			};
+			if let Err(e) = swarm.dial(dial) {
+				tracing::debug!(target: GOSSIP, %address, error = %e, "Failed redialing a peer");
+			}
+		}
+	}
+
 	/// Connects to a slice of peers
 	fn dial_peers(swarm: &mut Swarm<GossipNetworkBehavior>, peers: &[Multiaddr]) {
 		for peer in peers {
-			log::trace!("Dialing peer {:?}", peer);
+			tracing::trace!(target: GOSSIP, address = %peer, "Dialing peer");
 			match swarm.dial(peer.clone()) {
 				Err(e) => {
-					log::info!("Error dialing peer {:?}", e);
+					tracing::info!(
+						target: GOSSIP,
+						address = %peer,
+						error = ?e,
+						"Error dialing peer"
+					);
 				},
 				Ok(_) => {
-					log::info!("🤜🤛 Dialed Succefully");
+					tracing::info!(target: GOSSIP, address = %peer, "🤜🤛 Dialed Succefully");
 				},
 			}
 		}
 	}
 
-	/// Creates a new gossipsub swarm
-	fn create_swarm() -> Swarm<GossipNetworkBehavior> {
-		let key = Self::create_keys();
-		let transport = Self::get_transport(key.clone());
-		let behaviour = Self::get_behaviour(key.clone());
-		let peer_id = PeerId::from(key.public());
-		log::info!("Validated Streams Gossip peer ID: {:?}", peer_id);
-		libp2p::Swarm::with_threadpool_executor(transport, behaviour, peer_id)
+	/// Listens on the given addresses, failing only if there are some but none of them works.
+	fn listen_on_all(
+		swarm: &mut Swarm<GossipNetworkBehavior>,
+		addresses: &[Multiaddr],
+	) -> Result<(), StartupError> {
+		let mut failures = Vec::new();
+		for address in addresses {
+			tracing::info!(target: GOSSIP, "Listening on {:?}", address);
+			if let Err(e) = swarm.listen_on(address.clone()) {
+				tracing::warn!(
+					target: GOSSIP,
+					address = %address,
+					error = ?e,
+					"Failed listening on provided address"
+				);
+				failures.push(format!("{address}: {e}"));
+			}
+		}
+		if !addresses.is_empty() && failures.len() == addresses.len() {
+			return Err(StartupError::Gossip(format!(
+				"could not listen on any address ({})",
+				failures.join(", ")
+			)))
+		}
+		Ok(())
 	}
 
-	/// Creates a ed255519 nodekey for the swarm
-	fn create_keys() -> Keypair {
-		identity::Keypair::generate_ed25519()
+	/// Creates a new gossipsub swarm, whose connections are driven by tasks of the given spawner,
+	/// with the limits of the config.
+	fn create_swarm(
+		key: &Keypair,
+		config: &TransportConfig,
+		mdns: bool,
+		mesh: &MeshConfig,
+		autonat: bool,
+		spawner: &GossipSpawner,
+	) -> Result<Swarm<GossipNetworkBehavior>, StartupError> {
+		let peer_id = PeerId::from(key.public());
+		let (relay_transport, relay) = RelayClient::new_transport_and_behaviour(peer_id);
+		let transport = Self::get_transport(key.clone(), config, relay_transport)?;
+		let behaviour = Self::get_behaviour(key.clone(), mdns, mesh, autonat, relay)?;
+		tracing::info!(target: GOSSIP, "Validated Streams Gossip peer ID: {:?}", peer_id);
+		let spawner = spawner.clone();
+		let executor = move |task: Pin<Box<dyn Future<Output = ()> + Send>>| {
+			spawner.spawn_connection(task);
+		};
+		let limit = |limit: u32| (limit > 0).then_some(limit);
+		let limits = ConnectionLimits::default()
+			.with_max_established_incoming(limit(config.max_incoming))
+			.with_max_established_per_peer(limit(config.max_per_peer));
+		Ok(SwarmBuilder::with_executor(transport, behaviour, peer_id, executor)
+			.connection_limits(limits)
+			.build())
 	}
 
-	/// Creates a tcp transport over mplex and tls
-	fn get_transport(key: Keypair) -> Boxed<(PeerId, StreamMuxerBox)> {
-		tcp::async_io::Transport::new(tcp::Config::default())
+	/// Creates a tcp transport secured with noise and multiplexed with yamux, giving up on
+	/// connections not set up within the timeout of the config, along with a QUIC transport if the
+	/// config says so. WebSocket connections, over tcp, and those relayed by the circuit relays
+	/// are secured and multiplexed as tcp ones. Every transport dials and listens on IPv4 and IPv6
+	/// addresses, and `/dns`, `/dns4` and `/dns6` ones are resolved before dialing.
+	fn get_transport(
+		key: Keypair,
+		config: &TransportConfig,
+		relay: ClientTransport,
+	) -> Result<Boxed<(PeerId, StreamMuxerBox)>, StartupError> {
+		let noise = noise::NoiseAuthenticated::xx(&key)
+			.map_err(|e| StartupError::Gossip(format!("failed using noise keys: {e}")))?;
+		let tcp_config = || tcp::Config::default().nodelay(true);
+		// WebSocket addresses are tcp ones as well, so they are dialed and listened on first
+		let websocket = websocket::WsConfig::new(tcp::async_io::Transport::new(tcp_config()));
+		let tcp = relay
+			.or_transport(websocket)
+			.or_transport(tcp::async_io::Transport::new(tcp_config()))
 			.upgrade(upgrade::Version::V1)
-			.authenticate(tls::Config::new(&key).expect("Failed using tls keys"))
-			.multiplex(mplex::MplexConfig::new())
-			.boxed()
+			.authenticate(noise)
+			.multiplex(yamux::YamuxConfig::default())
+			.timeout(config.timeout)
+			.boxed();
+		let transport = match config.quic {
+			false => tcp,
+			true => {
+				let mut quic_config = quic::Config::new(&key);
+				quic_config.handshake_timeout = config.timeout;
+				let quic = quic::async_std::Transport::new(quic_config)
+					.map(|(peer_id, connection), _| (peer_id, StreamMuxerBox::new(connection)));
+				// Each address is dialed and listened on by the transport supporting it
+				quic.or_transport(tcp)
+					.map(|output, _| match output {
+						EitherOutput::First(output) | EitherOutput::Second(output) => output,
+					})
+					.boxed()
+			},
+		};
+		// Peers listed by name are dialed at the IPv4 and IPv6 addresses it resolves to alike
+		let dns = dns::TokioDnsConfig::system(transport)
+			.map_err(|e| StartupError::Gossip(format!("failed reading the DNS config: {e}")))?;
+		Ok(dns.boxed())
 	}
 
-	/// Assembles a gossipsub behaviour
-	fn get_behaviour(key: Keypair) -> GossipNetworkBehavior {
+	/// Assembles a gossipsub behaviour, along with mDNS and AutoNAT if enabled
+	fn get_behaviour(
+		key: Keypair,
+		mdns: bool,
+		mesh: &MeshConfig,
+		autonat: bool,
+		relay: RelayClient,
+	) -> Result<GossipNetworkBehavior, StartupError> {
 		let peer_id = PeerId::from(key.public());
-		let gossipsub_config = gossipsub::GossipsubConfig::default();
+		// Messages are only relayed once the handler accepts them
+		let gossipsub_config = mesh.gossipsub_config()?;
 		let mdns_config = libp2p::mdns::Config::default();
 		let identify_config =
-			libp2p::identify::Config::new("vstreams/1.0.0".to_string(), key.public());
+			libp2p::identify::Config::new(PROTOCOL_VERSION.to_string(), key.public());
+		// Signing with the gossip key only ties messages to a peer ID; the witnesses they carry are
+		// signed with the validator key, which the handler verifies against the validator set
 		let message_authenticity = MessageAuthenticity::Signed(key);
 
-		GossipNetworkBehavior {
-			gossipsub: gossipsub::Gossipsub::new(message_authenticity, gossipsub_config).unwrap(),
+		let gossipsub = gossipsub::Gossipsub::new(message_authenticity, gossipsub_config)
+			.map_err(|e| StartupError::Gossip(format!("failed setting up gossipsub: {e}")))?;
+		let mdns = mdns
+			.then(|| MDns::new(mdns_config))
+			.transpose()
+			.map_err(|e| StartupError::Gossip(format!("failed initializing mDNS: {e}")))?;
+
+		Ok(GossipNetworkBehavior {
+			gossipsub,
+			proof_sync: sync::behaviour(),
+			membership: admission::behaviour(),
 			identify: Identify::new(identify_config),
 			kademlia: Kademlia::new(peer_id, MemoryStore::new(peer_id)),
-			mdns: MDns::new(mdns_config).expect("Failed to initialize mDNS"),
+			mdns: Toggle::from(mdns),
+			autonat: Toggle::from(autonat.then(|| nat::autonat_behaviour(peer_id))),
+			relay,
+		})
+	}
+}
+
+/// The key of the gossip of a node, derived from the key of its Substrate network (`--node-key` or
+/// the key file in its base path), so that its [PeerId] stays the same across restarts, and peers
+/// can keep listing it statically. The ed25519 secret is the blake2-256 hash of a domain separator
+/// and the encoded node key, which keeps the two peer IDs apart, as they identify the node on
+/// networks of their own.
+pub fn derive_gossip_key(node_key: &Keypair) -> Result<Keypair, StartupError> {
+	let encoded = node_key
+		.to_protobuf_encoding()
+		.map_err(|e| StartupError::Gossip(format!("failed encoding the node key: {e}")))?;
+	ed25519_key(blake2_256(&[GOSSIP_KEY_DOMAIN, encoded.as_slice()].concat()))
+}
+
+/// The gossip key of `--streams-node-key-seed`, for reproducible test networks: the ed25519 secret
+/// is the blake2-256 hash of a domain separator and the seed, so that the same seed always gives
+/// the same [PeerId]. Anyone knowing the seed can impersonate the node.
+pub fn gossip_key_from_seed(seed: &str) -> Result<Keypair, StartupError> {
+	ed25519_key(blake2_256(&[GOSSIP_KEY_DOMAIN, seed.as_bytes()].concat()))
+}
+
+/// The gossip key of `--streams-node-key-file`: an ed25519 secret of 32 bytes, raw or in hex, as
+/// in the node key files of Substrate, e.g. those of `subkey generate-node-key`.
+pub fn load_gossip_key(path: &Path) -> Result<Keypair, StartupError> {
+	let failed = |reason: String| StartupError::Gossip(format!("{}: {reason}", path.display()));
+	let contents = std::fs::read(path).map_err(|e| failed(e.to_string()))?;
+	let mut secret = [0; 32];
+	match std::str::from_utf8(&contents).map(str::trim) {
+		Ok(digits) if digits.len() == 64 =>
+			hex::decode_to_slice(digits, &mut secret).map_err(|e| failed(e.to_string()))?,
+		_ if contents.len() == 32 => secret.copy_from_slice(&contents),
+		_ => return Err(failed("not an ed25519 secret key of 32 bytes, raw or in hex".to_string())),
+	}
+	ed25519_key(secret)
+}
+
+/// The ed25519 key of the secret.
+fn ed25519_key(mut secret: [u8; 32]) -> Result<Keypair, StartupError> {
+	let secret = identity::ed25519::SecretKey::from_bytes(&mut secret)
+		.map_err(|e| StartupError::Gossip(format!("invalid gossip key: {e}")))?;
+	Ok(Keypair::Ed25519(secret.into()))
+}
+
+/// The QUIC address on the UDP port of a TCP address, e.g. `/ip4/127.0.0.1/udp/10000/quic` for
+/// `/ip4/127.0.0.1/tcp/10000`; [None] for the other addresses.
+pub fn quic_address(address: &Multiaddr) -> Option<Multiaddr> {
+	let mut protocols = address.iter();
+	let ip = protocols.next().filter(|ip| matches!(ip, Protocol::Ip4(_) | Protocol::Ip6(_)))?;
+	let (Some(Protocol::Tcp(port)), None) = (protocols.next(), protocols.next()) else {
+		return None
+	};
+	Some(Multiaddr::empty().with(ip).with(Protocol::Udp(port)).with(Protocol::Quic))
+}
+
+/// Parameters for [GossipService::run_loop], borrowed from the run of the service for the time of
+/// one event loop.
+struct RunLoopParams<'a, H> {
+	rc: &'a Orders,
+	handler: &'a H,
+	ingress: &'a Ingress,
+	failed_workers: &'a mut UnboundedReceiver<Box<dyn Any + Send>>,
+	verdicts: &'a UnboundedSender<Verdict>,
+	verdicts_rc: &'a mut UnboundedReceiver<Verdict>,
+	metrics: &'a Metrics,
+	startup: &'a StartupSignals,
+	log_limiter: &'a LogRateLimiter,
+	mesh: &'a mut MeshMonitor,
+	heartbeat: Option<&'a Heartbeat>,
+	setup: &'a mut SwarmSetup,
+	discovery_interval: Duration,
+	compression: GossipCompression,
+	membership: Option<&'a Arc<dyn Membership>>,
+	admissions: Option<&'a mut Admissions>,
+	bans: &'a mut Bans,
+	min_peers: usize,
+}
+
+/// What the handling of the orders and events of the swarm shares with its event loop.
+struct LoopContext<'a, H> {
+	handler: &'a H,
+	ingress: &'a Ingress,
+	verdicts: &'a UnboundedSender<Verdict>,
+	metrics: &'a Metrics,
+	startup: &'a StartupSignals,
+	log_limiter: &'a LogRateLimiter,
+	compression: GossipCompression,
+}
+
+/// What a rebuilt swarm gets set back up with: the addresses listened on and the peers dialed
+/// before its event loop failed, and the peers removed since, along with their addresses.
+struct SwarmSetup {
+	listen_addresses: Vec<Multiaddr>,
+	peers: Vec<Multiaddr>,
+	removed: Vec<(PeerId, Vec<Multiaddr>)>,
+}
+
+impl SwarmSetup {
+	/// Remembers the addresses of an order, if any. Returns the removed peers it dials again.
+	fn record(&mut self, order: &GossipOrder) -> Vec<PeerId> {
+		let mut restored = Vec::new();
+		match order {
+			GossipOrder::DialPeers(peers) =>
+				for peer in peers {
+					if !self.peers.contains(peer) {
+						self.peers.push(peer.clone());
+					}
+					let (id, address) = (peer_id_of(peer), without_peer_id(peer.clone()));
+					self.removed.retain(|(removed, addresses)| {
+						let dialed = id == Some(*removed) || addresses.contains(&address);
+						if dialed {
+							restored.push(*removed);
+						}
+						!dialed
+					});
+				},
+			GossipOrder::Listen(address) if !self.listen_addresses.contains(address) =>
+				self.listen_addresses.push(address.clone()),
+			_ => {},
 		}
+		restored
+	}
+
+	/// Whether the peer was removed, and not dialed again since.
+	fn is_removed(&self, peer: &PeerId) -> bool {
+		self.removed.iter().any(|(removed, _)| removed == peer)
+	}
+
+	/// Stops redialing a removed peer, known by the given addresses.
+	fn forget(&mut self, peer: PeerId, addresses: Vec<Multiaddr>) {
+		self.peers.retain(|dialed| {
+			let address = without_peer_id(dialed.clone());
+			peer_id_of(dialed) != Some(peer) && !addresses.contains(&address)
+		});
+		self.removed.retain(|(removed, _)| *removed != peer);
+		self.removed.push((peer, addresses));
+	}
+}
+
+/// Marks the [StartupStep::PeersConnected] step once the given peers are at least the minimum.
+fn mark_peers_connected(startup: &StartupSignals, min_peers: usize, connected: usize) {
+	if connected >= min_peers {
+		tracing::info!(target: GOSSIP, connected, min_peers, "Connected to enough peers");
+		startup.mark_done(StartupStep::PeersConnected);
+	}
+}
+
+/// The peer ID an address ends with, if any.
+fn peer_id_of(address: &Multiaddr) -> Option<PeerId> {
+	match address.iter().last() {
+		Some(Protocol::P2p(hash)) => PeerId::from_multihash(hash).ok(),
+		_ => None,
+	}
+}
+
+/// Counts a failure of the event loop, returning how long to wait before restarting it, or the
+/// fatal error once it failed more than [MAX_RESTARTS] times within the [RESTART_WINDOW].
+fn restart_backoff(
+	failures: &mut VecDeque<tokio::time::Instant>,
+	panic: &(dyn Any + Send),
+	metrics: &Metrics,
+) -> Result<Duration, StartupError> {
+	let now = tokio::time::Instant::now();
+	failures.retain(|failed| now - *failed < RESTART_WINDOW);
+	failures.push_back(now);
+	let cause = panic_cause(panic);
+	if failures.len() > MAX_RESTARTS {
+		tracing::error!(target: GOSSIP, cause, "Gossip failed too often; giving up");
+		return Err(StartupError::Gossip(format!(
+			"the event loop failed {} times within {:?}, last with: {cause}",
+			failures.len(),
+			RESTART_WINDOW
+		)))
+	}
+	let backoff = RESTART_BACKOFF * 2u32.pow(failures.len() as u32 - 1);
+	tracing::error!(
+		target: GOSSIP,
+		cause,
+		backoff_ms = backoff.as_millis() as u64,
+		"Gossip failed; restarting"
+	);
+	metrics.on_gossip_restart();
+	Ok(backoff)
+}
+
+/// The message of a caught panic.
+fn panic_cause(panic: &(dyn Any + Send)) -> &str {
+	match panic.downcast_ref::<&str>() {
+		Some(message) => message,
+		None => panic.downcast_ref::<String>().map(String::as_str).unwrap_or("unknown panic"),
 	}
 }
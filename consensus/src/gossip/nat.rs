@@ -0,0 +1,95 @@
+//! Reachability of the nodes behind a NAT. With AutoNAT, a node asks its peers to dial it back on
+//! the addresses it listens on, or is observed at, to learn whether it is publicly reachable; the
+//! addresses confirmed so are advertised to its peers, through identify and Kademlia, along with
+//! the external addresses it is configured with. A node which is not reachable listens through
+//! circuit relays instead, reserving a slot on each of them, and its peers reach it over the
+//! relayed address. Every node can dial peers through their relays.
+
+use super::{peer_id_of, GossipNetworkBehavior};
+use crate::{errors::StartupError, logging::GOSSIP};
+use libp2p::{
+	autonat::{self, Event as AutonatEvent, NatStatus},
+	core::multiaddr::Protocol,
+	relay::v2::client::Event as RelayEvent,
+	swarm::AddressScore,
+	Multiaddr, PeerId, Swarm,
+};
+
+/// How the node makes itself reachable by peers which cannot dial it directly.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NatConfig {
+	/// Whether the node probes its reachability through its peers with AutoNAT, and answers
+	/// their probes
+	pub autonat: bool,
+	/// The circuit relays the node listens through, each ending with the peer ID of the relay
+	pub relays: Vec<Multiaddr>,
+	/// The addresses the node is reachable at from outside its network, advertised to its peers
+	pub external_addresses: Vec<Multiaddr>,
+}
+
+impl NatConfig {
+	/// Checks that every relay is known by its peer ID, as reservations are made with a peer.
+	pub(super) fn validate(&self) -> Result<(), StartupError> {
+		match self.relays.iter().find(|relay| peer_id_of(relay).is_none()) {
+			Some(relay) => Err(StartupError::Gossip(format!(
+				"the relay address {relay} does not end with the peer ID of the relay"
+			))),
+			None => Ok(()),
+		}
+	}
+
+	/// The peer IDs of the relays, which are kept connected whether they are validators or not.
+	pub(super) fn relay_peers(&self) -> impl Iterator<Item = PeerId> + '_ {
+		self.relays.iter().filter_map(peer_id_of)
+	}
+}
+
+/// The address the node listens on through a relay, as dialed by its peers.
+pub fn circuit_address(relay: &Multiaddr) -> Multiaddr {
+	relay.clone().with(Protocol::P2pCircuit)
+}
+
+/// The AutoNAT behaviour of the node, probing through the connected peers.
+pub(super) fn autonat_behaviour(peer_id: PeerId) -> autonat::Behaviour {
+	autonat::Behaviour::new(peer_id, autonat::Config::default())
+}
+
+/// Advertises the external addresses of the node, and listens through its relays.
+pub(super) fn set_up(swarm: &mut Swarm<GossipNetworkBehavior>, config: &NatConfig) {
+	for address in &config.external_addresses {
+		swarm.add_external_address(address.clone(), AddressScore::Infinite);
+	}
+	for relay in &config.relays {
+		if let Err(e) = swarm.listen_on(circuit_address(relay)) {
+			tracing::warn!(target: GOSSIP, %relay, error = ?e, "Failed listening through a relay");
+		}
+	}
+}
+
+/// Logs the changes of the reachability of the node.
+pub(super) fn on_autonat_event(event: AutonatEvent) {
+	match event {
+		AutonatEvent::StatusChanged { new: NatStatus::Public(address), .. } =>
+			tracing::info!(target: GOSSIP, %address, "Publicly reachable"),
+		AutonatEvent::StatusChanged { new: NatStatus::Private, .. } => tracing::warn!(
+			target: GOSSIP,
+			"Not publicly reachable; peers can only reach the node through its relays, if any"
+		),
+		event => tracing::trace!(target: GOSSIP, ?event, "AutoNAT event"),
+	}
+}
+
+/// Logs the reservations made on the relays, and the circuits through them.
+pub(super) fn on_relay_event(event: RelayEvent) {
+	match event {
+		RelayEvent::ReservationReqAccepted { relay_peer_id, renewal: false, .. } =>
+			tracing::info!(target: GOSSIP, relay = %relay_peer_id, "Listening through a relay"),
+		RelayEvent::ReservationReqFailed { relay_peer_id, error, .. } => tracing::warn!(
+			target: GOSSIP,
+			relay = %relay_peer_id,
+			error = ?error,
+			"Failed reserving a slot on a relay"
+		),
+		event => tracing::debug!(target: GOSSIP, ?event, "Relay event"),
+	}
+}
@@ -0,0 +1,466 @@
+//! A backend of the [Gossip](super::Gossip) running on the network service of the node, rather
+//! than on a libp2p swarm of its own, so that the node keeps a single set of connections, behind
+//! the same NAT handling and peer set as the rest of its protocols.
+//!
+//! Messages are sent as notifications of the [PROTOCOL_NAME] protocol, registered on the network
+//! through [peers_set_config] before it is built, to every peer with which the protocol is open.
+//! Each peer relays the messages of the topics of its handler it sees for the first time to its
+//! other peers, so that the messages reach the validators it is not connected to, as over
+//! gossipsub. The peers are those of the peer set of the protocol, as the network finds them, and
+//! those [dialed](super::Gossip::connect_to) by addresses ending with their peer ID.
+
+use super::{
+	compression::{self, GossipCompression},
+	ingress::Ingress,
+	mesh::MeshMonitor,
+	orders::Orders,
+	mark_peers_connected, peer_id_of, restart_backoff, GossipHandler, GossipOrder, GossipService,
+	SwarmSetup, MESH_CHECK_INTERVAL,
+};
+use crate::{
+	errors::{Error, StartupError},
+	logging::{rate_limited, LogRateLimiter, GOSSIP},
+	metrics::Metrics,
+	startup::{StartupSignals, StartupStep},
+	watchdog::Heartbeat,
+};
+use codec::{Decode, Encode};
+use futures::{
+	channel::{
+		mpsc::{unbounded, UnboundedReceiver},
+		oneshot,
+	},
+	prelude::*,
+	select,
+	stream::BoxStream,
+};
+use libp2p::{gossipsub::TopicHash, Multiaddr, PeerId};
+use lru::LruCache;
+use sc_network::{
+	config::NonDefaultSetConfig, Event, NetworkEventStream, NetworkNotification, NetworkPeers,
+};
+use sp_core::hashing::blake2_128;
+use std::{
+	any::Any,
+	collections::{HashSet, VecDeque},
+	fmt,
+	num::NonZeroUsize,
+	panic::{self, AssertUnwindSafe},
+	str::FromStr,
+	sync::Arc,
+	time::Instant,
+};
+
+/// The name of the notification protocol the gossip runs on.
+pub const PROTOCOL_NAME: &str = "/validated-streams/1";
+/// The largest notification of the protocol, in bytes.
+pub const MAX_NOTIFICATION_SIZE: u64 = 64 * 1024;
+/// How many of the messages seen last are remembered, so as not to relay them again.
+const SEEN_CAPACITY: usize = 16384;
+/// The name the event stream of the network is opened under.
+const EVENT_STREAM: &str = "validated-streams-gossip";
+
+/// What the gossip runs on, as chosen with `--gossip-backend`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GossipBackend {
+	/// A libp2p swarm of its own, on the gossip port
+	#[default]
+	Swarm,
+	/// The [PROTOCOL_NAME] notification protocol of the network service of the node
+	Network,
+}
+
+impl fmt::Display for GossipBackend {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			Self::Swarm => "swarm",
+			Self::Network => "network",
+		})
+	}
+}
+
+impl FromStr for GossipBackend {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"swarm" => Ok(Self::Swarm),
+			"network" => Ok(Self::Network),
+			_ => Err(format!("expected swarm or network, got {s}")),
+		}
+	}
+}
+
+/// The set of the [PROTOCOL_NAME] protocol, to add to the extra sets of the network configuration
+/// before the network is built. Its peers are found as those of the default set are.
+pub fn peers_set_config() -> NonDefaultSetConfig {
+	NonDefaultSetConfig::new(PROTOCOL_NAME.into(), MAX_NOTIFICATION_SIZE)
+}
+
+/// An event of the [PROTOCOL_NAME] protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetworkEvent {
+	/// The protocol was opened with a peer
+	Opened(PeerId),
+	/// The protocol was closed with a peer
+	Closed(PeerId),
+	/// A peer sent a notification
+	Received(PeerId, Vec<u8>),
+}
+
+/// What the gossip needs of the network service of the node. Implemented by the network services
+/// of Substrate; tests substitute a simulated network through it.
+pub trait GossipNetwork: Send + Sync {
+	/// The events of the [PROTOCOL_NAME] protocol from now on.
+	fn events(&self) -> BoxStream<'static, NetworkEvent>;
+
+	/// Sends a notification to a peer with which the protocol is open.
+	fn notify(&self, peer: PeerId, message: Vec<u8>);
+
+	/// Connects to peers, known by addresses ending with their peer ID, as reserved peers of the
+	/// protocol.
+	fn add_peers(&self, peers: HashSet<Multiaddr>) -> Result<(), String>;
+
+	/// Closes the protocol with a peer, and stops reserving a slot for it.
+	fn remove_peer(&self, peer: PeerId);
+}
+
+impl<T: NetworkEventStream + NetworkNotification + NetworkPeers + Send + Sync> GossipNetwork for T {
+	fn events(&self) -> BoxStream<'static, NetworkEvent> {
+		let ours = |protocol: &str| protocol == PROTOCOL_NAME;
+		self.event_stream(EVENT_STREAM)
+			.flat_map(move |event| {
+				stream::iter(match event {
+					Event::NotificationStreamOpened { remote, protocol, .. } if ours(&protocol) =>
+						vec![NetworkEvent::Opened(remote)],
+					Event::NotificationStreamClosed { remote, protocol } if ours(&protocol) =>
+						vec![NetworkEvent::Closed(remote)],
+					Event::NotificationsReceived { remote, messages } => messages
+						.into_iter()
+						.filter(|(protocol, _)| ours(protocol))
+						.map(|(_, message)| NetworkEvent::Received(remote, message.to_vec()))
+						.collect(),
+					_ => Vec::new(),
+				})
+			})
+			.boxed()
+	}
+
+	fn notify(&self, peer: PeerId, message: Vec<u8>) {
+		self.write_notification(peer, PROTOCOL_NAME.into(), message);
+	}
+
+	fn add_peers(&self, peers: HashSet<Multiaddr>) -> Result<(), String> {
+		self.add_peers_to_reserved_set(PROTOCOL_NAME.into(), peers)
+	}
+
+	fn remove_peer(&self, peer: PeerId) {
+		self.remove_peers_from_reserved_set(PROTOCOL_NAME.into(), vec![peer]);
+		self.disconnect_peer(peer, PROTOCOL_NAME.into());
+	}
+}
+
+/// The network the gossip runs on, provided once the node has built it.
+pub struct PendingNetwork(oneshot::Receiver<Arc<dyn GossipNetwork>>);
+
+/// A [PendingNetwork], and the function providing it with the network.
+pub fn pending_network() -> (PendingNetwork, impl FnOnce(Arc<dyn GossipNetwork>)) {
+	let (provide, provided) = oneshot::channel();
+	(PendingNetwork(provided), move |network| {
+		let _ = provide.send(network);
+	})
+}
+
+/// A message as sent over the protocol: the topic it was published on, and its data.
+#[derive(Encode, Decode)]
+struct Notification {
+	topic: String,
+	data: Vec<u8>,
+}
+
+/// The peers the gossip runs with on the network.
+struct NetworkPeerSet {
+	/// The peers with which the protocol is open
+	open: HashSet<PeerId>,
+	/// The blake2-128 hashes of the messages seen last
+	seen: LruCache<[u8; 16], ()>,
+	/// The peers dialed and removed so far
+	setup: SwarmSetup,
+}
+
+impl NetworkPeerSet {
+	/// Whether the message is seen for the first time.
+	fn first_seen(&mut self, notification: &[u8]) -> bool {
+		self.seen.put(blake2_128(notification), ()).is_none()
+	}
+
+	/// Sends a notification to every open peer but the one it came from, if any.
+	fn relay(&self, network: &dyn GossipNetwork, notification: &[u8], from: Option<PeerId>) {
+		for peer in self.open.iter().filter(|peer| Some(**peer) != from) {
+			network.notify(*peer, notification.to_vec());
+		}
+	}
+}
+
+/// Parameters for [run_loop], borrowed from the run of the service for the time of one event
+/// loop.
+struct NetworkLoopParams<'a, H> {
+	rc: &'a Orders,
+	handler: &'a H,
+	topics: &'a HashSet<TopicHash>,
+	ingress: &'a Ingress,
+	failed_workers: &'a mut UnboundedReceiver<Box<dyn Any + Send>>,
+	metrics: &'a Metrics,
+	log_limiter: &'a LogRateLimiter,
+	mesh: &'a mut MeshMonitor,
+	heartbeat: Option<&'a Heartbeat>,
+	peers: &'a mut NetworkPeerSet,
+	compression: GossipCompression,
+	startup: &'a StartupSignals,
+	min_peers: usize,
+}
+
+impl GossipService {
+	/// Runs the gossip on the network once provided, restarting its event loop on failures as
+	/// [GossipService::run] does. The peers dialed so far are added again after a restart.
+	pub(super) async fn run_on_network<H: GossipHandler + Send + Sync + 'static>(
+		self,
+		network: PendingNetwork,
+		handler: Arc<H>,
+	) -> Result<(), StartupError> {
+		let Self {
+			rc,
+			metrics,
+			mesh_expectations,
+			telemetry,
+			log_limiter,
+			heartbeat,
+			spawner,
+			startup,
+			compression,
+			allowlist,
+			min_peers,
+			..
+		} = self;
+		let spawner = Self::required_spawner(spawner)?;
+		if allowlist.is_some() {
+			let error = "restricting the gossip peers needs the swarm backend";
+			return Err(StartupError::Gossip(error.to_string()))
+		}
+		let network = network.0.await.map_err(|_| {
+			StartupError::Gossip("the network service was never provided".to_string())
+		})?;
+		tracing::info!(target: GOSSIP, protocol = PROTOCOL_NAME, "Gossiping over the network");
+		// The network listens on the addresses of the node on its own
+		startup.mark_done(StartupStep::GossipListening);
+		let topics: HashSet<TopicHash> =
+			handler.get_topics().into_iter().map(|topic| topic.hash()).collect();
+		startup.mark_done(StartupStep::TopicsSubscribed);
+
+		let ingress = Ingress::new(metrics.clone());
+		let (worker_failures, mut failed_workers) = unbounded();
+		let seen = NonZeroUsize::new(SEEN_CAPACITY).expect("capacity is not zero");
+		let setup =
+			SwarmSetup { listen_addresses: Vec::new(), peers: Vec::new(), removed: Vec::new() };
+		let mut peers = NetworkPeerSet { open: HashSet::new(), seen: LruCache::new(seen), setup };
+		let mut failures = VecDeque::new();
+		loop {
+			add_peers(network.as_ref(), &peers.setup.peers);
+			ingress.spawn_workers(&handler, &spawner, &worker_failures);
+			let mut mesh =
+				MeshMonitor::new(mesh_expectations.clone(), metrics.clone(), telemetry.clone());
+			let run_loop = run_loop(network.as_ref(), NetworkLoopParams {
+				rc: &rc,
+				handler: handler.as_ref(),
+				topics: &topics,
+				ingress: &ingress,
+				failed_workers: &mut failed_workers,
+				metrics: &metrics,
+				log_limiter: &log_limiter,
+				mesh: &mut mesh,
+				heartbeat: heartbeat.as_ref(),
+				peers: &mut peers,
+				compression,
+				startup: &startup,
+				min_peers,
+			});
+			let Err(panic) = AssertUnwindSafe(run_loop).catch_unwind().await else {
+				return Ok(())
+			};
+			// The protocol is opened again with the peers still connected, as new events
+			peers.open.clear();
+			metrics.set_gossip_peers(0);
+			tokio::time::sleep(restart_backoff(&mut failures, panic.as_ref(), &metrics)?).await;
+		}
+	}
+}
+
+/// Handles the events of the protocol and the orders, until ordered to close. A panic of a worker
+/// is resumed here, failing the loop.
+async fn run_loop<H: GossipHandler + Send + Sync>(
+	network: &dyn GossipNetwork,
+	params: NetworkLoopParams<'_, H>,
+) {
+	let NetworkLoopParams {
+		rc,
+		handler,
+		topics,
+		ingress,
+		failed_workers,
+		metrics,
+		log_limiter,
+		mesh,
+		heartbeat,
+		peers,
+		compression,
+		startup,
+		min_peers,
+	} = params;
+	let mut events = network.events().fuse();
+	// The mesh checks also keep the heartbeat going while the network is quiet
+	let mut mesh_checks = tokio::time::interval(MESH_CHECK_INTERVAL);
+	loop {
+		if let Some(heartbeat) = heartbeat {
+			heartbeat.bump();
+		}
+		if !startup.is_done(StartupStep::PeersConnected) {
+			// Peers subscribe to every topic of the protocol as they open it
+			mark_peers_connected(startup, min_peers, peers.open.len());
+		}
+		select! {
+			order = rc.next().fuse() => match order {
+				GossipOrder::SendMessage(topic, message, ordered, handled) => {
+					ingress.publish(handler, message.clone(), handled);
+					let topic = topic.hash().into_string();
+					let data = compression.compress(message);
+					let notification = Notification { topic, data }.encode();
+					peers.first_seen(&notification);
+					if peers.open.is_empty() {
+						rate_limited!(
+							log_limiter,
+							"failed_gossip_publish",
+							info,
+							target: GOSSIP,
+							"Failed gossiping message: no peers"
+						);
+						continue
+					}
+					peers.relay(network, &notification, None);
+					metrics.on_gossip_published(ordered.elapsed());
+					tracing::trace!(target: GOSSIP, "Gossiped a message");
+				},
+				order @ GossipOrder::DialPeers(_) => {
+					// Lets the removed peers dialed again back in
+					peers.setup.record(&order);
+					add_peers(network, &peers.setup.peers);
+				},
+				GossipOrder::RemovePeer(peer, removed) => {
+					peers.setup.forget(peer, mesh.addresses_of(&peer));
+					network.remove_peer(peer);
+					let connected = peers.open.remove(&peer);
+					metrics.set_gossip_peers(peers.open.len());
+					mesh.on_disconnected(&peer);
+					tracing::info!(target: GOSSIP, peer = %peer, connected, "Removed peer");
+					removed.send(connected).ok();
+				},
+				GossipOrder::ListPeers(listed) => {
+					listed.send(mesh.peers()).ok();
+				},
+				GossipOrder::ListenAddresses(listed) => {
+					listed.send(Vec::new()).ok();
+				},
+				GossipOrder::RequestProofs(peer, _, received) => {
+					let error = format!("cannot request proofs of {peer} on the network backend");
+					received.send(Err(Error::GossipUnavailable(error))).ok();
+				},
+				GossipOrder::Listen(address) => {
+					tracing::info!(
+						target: GOSSIP,
+						%address,
+						"Not listening; the network listens on the addresses of the node"
+					);
+				},
+				GossipOrder::Flush(flushed) => {
+					ingress.flush(flushed);
+				},
+				GossipOrder::Close(closed) => {
+					for peer in peers.open.drain() {
+						network.remove_peer(peer);
+					}
+					metrics.set_gossip_peers(0);
+					tracing::info!(target: GOSSIP, "Gossip closed");
+					closed.send(()).ok();
+					return
+				},
+			},
+			event = events.select_next_some() => match event {
+				NetworkEvent::Opened(peer) => {
+					if peers.setup.is_removed(&peer) {
+						network.remove_peer(peer);
+						continue
+					}
+					tracing::debug!(target: GOSSIP, peer = %peer, "Protocol opened");
+					peers.open.insert(peer);
+					metrics.set_gossip_peers(peers.open.len());
+					mesh.on_connected(peer, None);
+					mesh.on_subscribed(peer);
+					mesh.check(Instant::now());
+				},
+				NetworkEvent::Closed(peer) => {
+					tracing::debug!(target: GOSSIP, peer = %peer, "Protocol closed");
+					if peers.open.remove(&peer) {
+						metrics.set_gossip_peers(peers.open.len());
+						mesh.on_disconnected(&peer);
+						mesh.check(Instant::now());
+					}
+				},
+				NetworkEvent::Received(peer, notification) => {
+					if !peers.open.contains(&peer) || !peers.first_seen(&notification) {
+						continue
+					}
+					let decoded = Notification::decode(&mut &notification[..]);
+					let Ok(Notification { topic, data }) = decoded else {
+						tracing::debug!(target: GOSSIP, peer = %peer, "Undecodable notification");
+						continue
+					};
+					if !topics.contains(&TopicHash::from_raw(topic)) {
+						continue
+					}
+					let data = match compression::decompress(data) {
+						Ok(data) => data,
+						Err(e) => {
+							tracing::debug!(
+								target: GOSSIP,
+								peer = %peer,
+								error = %e,
+								"Failed decompressing a message"
+							);
+							continue
+						},
+					};
+					peers.relay(network, &notification, Some(peer));
+					let span = tracing::debug_span!(target: GOSSIP, "gossip_message", peer = %peer);
+					ingress.receive(handler, data, span, None);
+				},
+			},
+			_ = mesh_checks.tick().fuse() => mesh.check(Instant::now()),
+			panic = failed_workers.select_next_some() => panic::resume_unwind(panic),
+		}
+	}
+}
+
+/// Adds the peers of the addresses ending with a peer ID to the network; the others cannot be
+/// told apart from the peers the network finds on its own.
+fn add_peers(network: &dyn GossipNetwork, addresses: &[Multiaddr]) {
+	let (known, unknown): (Vec<_>, Vec<_>) =
+		addresses.iter().cloned().partition(|address| peer_id_of(address).is_some());
+	if !unknown.is_empty() {
+		tracing::debug!(target: GOSSIP, ?unknown, "Not dialing peers without a peer ID");
+	}
+	if known.is_empty() {
+		return
+	}
+	if let Err(e) = network.add_peers(known.into_iter().collect()) {
+		tracing::info!(target: GOSSIP, error = %e, "Failed adding gossip peers");
+	}
+}
@@ -0,0 +1,211 @@
+//! The queue of orders between the [Gossip](super::Gossip) handles and their service. It holds a
+//! bounded number of orders, so that a stalled event loop never makes it grow without bound; once
+//! it is full, publishing a message waits for room, drops the oldest message queued, or fails, as
+//! its [Backpressure] policy says, while the other orders always wait for room. Orders still
+//! queued when the event loop fails are handled by the next one; those queued once the service is
+//! gone are dropped, along with their acknowledgements.
+
+use super::GossipOrder;
+use crate::{errors::Error, logging::GOSSIP, metrics::Metrics};
+use std::{
+	collections::VecDeque,
+	fmt,
+	num::NonZeroUsize,
+	str::FromStr,
+	sync::{Arc, Mutex},
+};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore, TryAcquireError};
+
+/// How many orders the queue holds, by default.
+pub const DEFAULT_ORDER_CAPACITY: usize = 64;
+
+/// What publishing a message does while the order queue is full, as chosen with
+/// `--gossip-backpressure`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backpressure {
+	/// Waits for room in the queue
+	#[default]
+	Block,
+	/// Drops the oldest message queued to make room, failing its publication
+	DropOldest,
+	/// Fails the publication right away, leaving it to the caller to retry
+	Fail,
+}
+
+impl fmt::Display for Backpressure {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			Self::Block => "block",
+			Self::DropOldest => "drop-oldest",
+			Self::Fail => "fail",
+		})
+	}
+}
+
+impl FromStr for Backpressure {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"block" => Ok(Self::Block),
+			"drop-oldest" => Ok(Self::DropOldest),
+			"fail" => Ok(Self::Fail),
+			_ => Err(format!("expected block, drop-oldest or fail, got {s}")),
+		}
+	}
+}
+
+/// How many orders the queue holds, and what publishing does once it is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OrderQueueConfig {
+	/// How many orders are queued at most
+	pub capacity: NonZeroUsize,
+	/// What publishing does while the queue is full
+	pub backpressure: Backpressure,
+}
+
+impl Default for OrderQueueConfig {
+	fn default() -> Self {
+		let capacity = NonZeroUsize::new(DEFAULT_ORDER_CAPACITY).expect("capacity is not zero");
+		Self { capacity, backpressure: Backpressure::default() }
+	}
+}
+
+/// A queued order, holding its room in the queue until taken.
+struct Queued {
+	order: GossipOrder,
+	_room: OwnedSemaphorePermit,
+}
+
+struct Shared {
+	orders: Mutex<VecDeque<Queued>>,
+	ready: Notify,
+	room: Arc<Semaphore>,
+}
+
+impl Shared {
+	/// Queues an order, unless the service is gone.
+	fn push(&self, order: GossipOrder, room: OwnedSemaphorePermit) {
+		let mut orders = self.orders.lock().unwrap();
+		if self.room.is_closed() {
+			return gone()
+		}
+		orders.push_back(Queued { order, _room: room });
+		drop(orders);
+		self.ready.notify_one();
+	}
+
+	/// Takes the oldest message queued for publishing out of the queue, failing its publication,
+	/// and hands over its room.
+	fn drop_oldest_publication(&self) -> Option<OwnedSemaphorePermit> {
+		let mut orders = self.orders.lock().unwrap();
+		let oldest = orders
+			.iter()
+			.position(|queued| matches!(queued.order, GossipOrder::SendMessage(..)))?;
+		let Queued { order, _room: room } = orders.remove(oldest)?;
+		if let GossipOrder::SendMessage(_, _, _, handled) = order {
+			let error = "dropped from the full order queue for a newer message".to_string();
+			handled.send(Err(Error::GossipOverloaded(error))).ok();
+		}
+		Some(room)
+	}
+}
+
+fn gone() {
+	tracing::error!(target: GOSSIP, "Could not send order: the gossip service is gone");
+}
+
+/// Creates a queue as configured, reporting the publications it refuses or drops to the metrics.
+pub(super) fn order_queue(config: OrderQueueConfig, metrics: Metrics) -> (OrderSender, Orders) {
+	let shared = Arc::new(Shared {
+		orders: Mutex::new(VecDeque::new()),
+		ready: Notify::new(),
+		room: Arc::new(Semaphore::new(config.capacity.get())),
+	});
+	let sender = OrderSender { shared: shared.clone(), backpressure: config.backpressure, metrics };
+	(sender, Orders { shared })
+}
+
+/// The sending side of the queue, shared by the clones of a [Gossip](super::Gossip).
+#[derive(Clone)]
+pub(super) struct OrderSender {
+	shared: Arc<Shared>,
+	backpressure: Backpressure,
+	metrics: Metrics,
+}
+
+impl OrderSender {
+	/// Queues an order, once there is room for it.
+	pub async fn send(&self, order: GossipOrder) {
+		match self.shared.room.clone().acquire_owned().await {
+			Ok(room) => self.shared.push(order, room),
+			Err(_) => gone(),
+		}
+	}
+
+	/// Queues a message to publish, as the [Backpressure] policy says if the queue is full. With
+	/// [Backpressure::DropOldest], it still waits for room if no message is queued, only orders of
+	/// other kinds.
+	pub async fn publish(&self, order: GossipOrder) -> Result<(), Error> {
+		let room = match self.shared.room.clone().try_acquire_owned() {
+			Ok(room) => room,
+			Err(TryAcquireError::Closed) =>
+				return Err(Error::GossipUnavailable("the gossip is not running".to_string())),
+			Err(TryAcquireError::NoPermits) => match self.backpressure {
+				Backpressure::Block => {
+					self.send(order).await;
+					return Ok(())
+				},
+				Backpressure::Fail => {
+					tracing::debug!(target: GOSSIP, "Refused a message: the order queue is full");
+					self.metrics.on_gossip_publish_refused();
+					return Err(Error::GossipOverloaded("the order queue is full".to_string()))
+				},
+				Backpressure::DropOldest => match self.shared.drop_oldest_publication() {
+					Some(room) => {
+						tracing::debug!(
+							target: GOSSIP,
+							"Dropped the oldest message queued: the order queue is full"
+						);
+						self.metrics.on_gossip_publish_refused();
+						room
+					},
+					None => {
+						self.send(order).await;
+						return Ok(())
+					},
+				},
+			},
+		};
+		self.shared.push(order, room);
+		Ok(())
+	}
+}
+
+/// The receiving side of the queue, held by the [GossipService](super::GossipService).
+pub(super) struct Orders {
+	shared: Arc<Shared>,
+}
+
+impl Orders {
+	/// The next order, once one is queued. Taking it makes room for another.
+	pub async fn next(&self) -> GossipOrder {
+		loop {
+			let next = self.shared.orders.lock().unwrap().pop_front();
+			if let Some(queued) = next {
+				return queued.order
+			}
+			// Orders queued meanwhile are not missed: the notification is kept until awaited
+			self.shared.ready.notified().await;
+		}
+	}
+}
+
+impl Drop for Orders {
+	/// Fails the orders still queued, and those queued from now on, as the service is gone.
+	fn drop(&mut self) {
+		let mut orders = self.shared.orders.lock().unwrap();
+		self.shared.room.close();
+		orders.clear();
+	}
+}
@@ -0,0 +1,120 @@
+//! Redialing of the configured gossip peers once their connection is lost, or could not be made
+
+use super::{mesh::without_peer_id, peer_id_of};
+use crate::logging::GOSSIP;
+use libp2p::{Multiaddr, PeerId};
+use std::{
+	collections::hash_map::RandomState,
+	hash::{BuildHasher, Hasher},
+	time::Duration,
+};
+use tokio::time::Instant;
+
+/// How long to wait before the first redial of a peer.
+pub const REDIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// The longest wait between two redials of a peer, however many failed before.
+pub const MAX_REDIAL_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A configured peer, by the address it was dialed at.
+struct Redial {
+	address: Multiaddr,
+	/// The peer found at the address, once it connected
+	peer: Option<PeerId>,
+	connected: bool,
+	/// The redials since the peer was last connected
+	attempts: u32,
+	/// When to redial the peer next, unless it connects meanwhile
+	due: Option<Instant>,
+}
+
+/// Schedules the redials of the configured peers which are not connected: REDIAL_BACKOFF after
+/// they are dialed or disconnected, and twice as long after each redial that fails, up to
+/// MAX_REDIAL_BACKOFF. Up to a quarter of each wait is added at random, so that the validators
+/// do not all redial a peer coming back at once.
+#[derive(Default)]
+pub(crate) struct Redials {
+	redials: Vec<Redial>,
+}
+
+impl Redials {
+	/// Tracks the given peers, dropping those no longer among them. The new ones were just
+	/// dialed, and are redialed unless they connect in time.
+	pub fn sync(&mut self, peers: &[Multiaddr], now: Instant) {
+		self.redials.retain(|redial| peers.contains(&redial.address));
+		for address in peers {
+			if self.redials.iter().any(|redial| redial.address == *address) {
+				continue
+			}
+			let due = Some(now + backoff(0));
+			let peer = peer_id_of(address);
+			let address = address.clone();
+			self.redials.push(Redial { address, peer, connected: false, attempts: 0, due });
+		}
+	}
+
+	/// Records a connection to a peer, dialed at the given address, or listening on it.
+	pub fn on_connected<'a>(
+		&mut self,
+		peer: PeerId,
+		addresses: impl Iterator<Item = &'a Multiaddr>,
+	) {
+		let addresses: Vec<_> = addresses.cloned().map(without_peer_id).collect();
+		for redial in &mut self.redials {
+			let found = match redial.peer {
+				Some(known) => known == peer,
+				None => addresses.contains(&redial.address),
+			};
+			if found {
+				redial.peer = Some(peer);
+				redial.connected = true;
+				redial.attempts = 0;
+				redial.due = None;
+			}
+		}
+	}
+
+	/// Records that the last connection to a peer was closed, scheduling its redial.
+	pub fn on_disconnected(&mut self, peer: &PeerId, now: Instant) {
+		for redial in &mut self.redials {
+			if redial.peer == Some(*peer) && redial.connected {
+				redial.connected = false;
+				redial.due = Some(now + backoff(0));
+			}
+		}
+	}
+
+	/// When the next redial is due, if any is scheduled.
+	pub fn next_due(&self) -> Option<Instant> {
+		self.redials.iter().filter_map(|redial| redial.due).min()
+	}
+
+	/// The addresses of the peers to redial now, scheduling their next redial for in case this
+	/// one fails.
+	pub fn take_due(&mut self, now: Instant) -> Vec<(Multiaddr, Option<PeerId>)> {
+		let mut due = Vec::new();
+		for redial in &mut self.redials {
+			if redial.connected || redial.due.map_or(true, |at| at > now) {
+				continue
+			}
+			redial.attempts = redial.attempts.saturating_add(1);
+			let wait = backoff(redial.attempts);
+			tracing::debug!(
+				target: GOSSIP,
+				address = %redial.address,
+				attempts = redial.attempts,
+				next_ms = wait.as_millis() as u64,
+				"Redialing peer"
+			);
+			redial.due = Some(now + wait);
+			due.push((redial.address.clone(), redial.peer));
+		}
+		due
+	}
+}
+
+/// The wait before the redial following the given number of them, with its jitter.
+pub fn backoff(attempts: u32) -> Duration {
+	let wait = REDIAL_BACKOFF.saturating_mul(2u32.saturating_pow(attempts)).min(MAX_REDIAL_BACKOFF);
+	let jitter = RandomState::new().build_hasher().finish() % (wait.as_millis() as u64 / 4 + 1);
+	wait + Duration::from_millis(jitter)
+}
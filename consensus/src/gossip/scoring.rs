@@ -0,0 +1,45 @@
+//! Gossipsub peer scoring, pruning the peers that flood the mesh with witnesses it rejects
+
+use libp2p::gossipsub::{IdentTopic, PeerScoreParams, PeerScoreThresholds, TopicScoreParams};
+use std::time::Duration;
+
+/// The penalty of the witnesses a peer sent that were rejected, times their count squared, before
+/// decay. A few of them get the peer pruned from the mesh, and four of them graylisted.
+pub const INVALID_WITNESS_WEIGHT: f64 = -20.0;
+
+/// The scoring of the peers of a node subscribed to the given witness topics.
+pub fn peer_score_params(topics: &[IdentTopic]) -> PeerScoreParams {
+	let topics = topics.iter().map(|topic| (topic.hash(), witness_topic_params())).collect();
+	PeerScoreParams { topics, topic_score_cap: 50.0, ..Default::default() }
+}
+
+/// The scores below which peers are no longer gossiped with, published to, or listened to at all.
+pub fn peer_score_thresholds() -> PeerScoreThresholds {
+	PeerScoreThresholds {
+		gossip_threshold: -10.0,
+		publish_threshold: -50.0,
+		graylist_threshold: -200.0,
+		accept_px_threshold: 10.0,
+		opportunistic_graft_threshold: 5.0,
+	}
+}
+
+/// Witnesses come in bursts, as events are submitted, and honest peers can stay quiet for long:
+/// peers are rewarded for being in the mesh and for delivering witnesses first, and penalized for
+/// delivering rejected ones, but never for delivering few. Duplicates earn nothing.
+fn witness_topic_params() -> TopicScoreParams {
+	TopicScoreParams {
+		topic_weight: 1.0,
+		time_in_mesh_weight: 0.01,
+		time_in_mesh_quantum: Duration::from_secs(1),
+		time_in_mesh_cap: 3600.0,
+		first_message_deliveries_weight: 1.0,
+		first_message_deliveries_decay: 0.9,
+		first_message_deliveries_cap: 20.0,
+		mesh_message_deliveries_weight: 0.0,
+		mesh_failure_penalty_weight: 0.0,
+		invalid_message_deliveries_weight: INVALID_WITNESS_WEIGHT,
+		invalid_message_deliveries_decay: 0.99,
+		..Default::default()
+	}
+}
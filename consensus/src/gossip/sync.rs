@@ -0,0 +1,138 @@
+//! The [SYNC_PROTOCOL] request-response protocol, through which a node asks a peer for the
+//! witnesses it holds of some events, e.g. after missing their gossip. The witnesses sent back
+//! are handed to the [GossipHandler](super::GossipHandler) as if gossiped, and so verified as
+//! such; they are not relayed further.
+
+use async_trait::async_trait;
+use codec::{Decode, DecodeAll, Encode};
+use futures::{
+	io::{AsyncRead, AsyncWrite},
+	AsyncWriteExt,
+};
+use libp2p::{
+	core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName},
+	request_response::{
+		ProtocolSupport, RequestResponse, RequestResponseCodec, RequestResponseConfig,
+	},
+};
+use sp_core::H256;
+use std::{io, iter};
+
+/// The name of the protocol.
+pub const SYNC_PROTOCOL: &str = "/validated-streams/sync/1";
+/// The most events a request may ask the witnesses of.
+pub const MAX_SYNC_EVENTS: usize = 64;
+/// The largest response, in bytes. The witnesses past it are left out.
+pub const MAX_SYNC_RESPONSE_SIZE: usize = 1024 * 1024;
+/// The largest request, in bytes: [MAX_SYNC_EVENTS] ids, and their length.
+const MAX_SYNC_REQUEST_SIZE: usize = MAX_SYNC_EVENTS * 32 + 8;
+
+/// A request for the witnesses a peer holds of the given events.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct ProofsRequest {
+	pub event_ids: Vec<H256>,
+}
+
+/// The witnesses a peer holds of the events of a [ProofsRequest], each encoded as gossiped.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub struct ProofsResponse {
+	pub witnesses: Vec<Vec<u8>>,
+}
+
+impl ProofsResponse {
+	/// A response of the given witnesses, leaving out those past [MAX_SYNC_RESPONSE_SIZE].
+	pub fn new(witnesses: Vec<Vec<u8>>) -> Self {
+		let mut size = 0;
+		let witnesses = witnesses
+			.into_iter()
+			.take_while(|witness| {
+				// The length prefix of each witness takes up to 4 bytes
+				size += witness.len() + 4;
+				size < MAX_SYNC_RESPONSE_SIZE
+			})
+			.collect();
+		Self { witnesses }
+	}
+}
+
+/// The [SYNC_PROTOCOL], as negotiated with peers.
+#[derive(Clone)]
+pub struct SyncProtocol;
+
+impl ProtocolName for SyncProtocol {
+	fn protocol_name(&self) -> &[u8] {
+		SYNC_PROTOCOL.as_bytes()
+	}
+}
+
+/// Reads and writes the messages of the protocol as length-prefixed SCALE encodings.
+#[derive(Clone, Default)]
+pub struct SyncCodec;
+
+#[async_trait]
+impl RequestResponseCodec for SyncCodec {
+	type Protocol = SyncProtocol;
+	type Request = ProofsRequest;
+	type Response = ProofsResponse;
+
+	async fn read_request<T>(&mut self, _: &SyncProtocol, io: &mut T) -> io::Result<ProofsRequest>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		let request: ProofsRequest =
+			decode(read_length_prefixed(io, MAX_SYNC_REQUEST_SIZE).await?)?;
+		if request.event_ids.len() > MAX_SYNC_EVENTS {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "too many events requested"))
+		}
+		Ok(request)
+	}
+
+	async fn read_response<T>(
+		&mut self,
+		_: &SyncProtocol,
+		io: &mut T,
+	) -> io::Result<ProofsResponse>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		decode(read_length_prefixed(io, MAX_SYNC_RESPONSE_SIZE).await?)
+	}
+
+	async fn write_request<T>(
+		&mut self,
+		_: &SyncProtocol,
+		io: &mut T,
+		request: ProofsRequest,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		write_length_prefixed(io, request.encode()).await?;
+		io.close().await
+	}
+
+	async fn write_response<T>(
+		&mut self,
+		_: &SyncProtocol,
+		io: &mut T,
+		response: ProofsResponse,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		write_length_prefixed(io, response.encode()).await?;
+		io.close().await
+	}
+}
+
+/// Decodes a message, failing on bytes left over.
+pub(super) fn decode<M: Decode>(bytes: Vec<u8>) -> io::Result<M> {
+	M::decode_all(&mut bytes.as_slice())
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// The behaviour of the protocol, both asking peers and answering them.
+pub(super) fn behaviour() -> RequestResponse<SyncCodec> {
+	let protocols = iter::once((SyncProtocol, ProtocolSupport::Full));
+	RequestResponse::new(SyncCodec, protocols, RequestResponseConfig::default())
+}
@@ -1,30 +1,87 @@
-use super::{Gossip, GossipHandler};
-use crate::proofs::WitnessedEvent;
+use super::{
+	admission::{membership_payload, Admissions, MembershipProof, ADMISSION_TIMEOUT},
+	bans::{Bans, Violation, VIOLATION_WINDOW},
+	compression::{decompress, MAX_DECOMPRESSED_SIZE},
+	ingress::{Ingress, Validation, GossipSpawner, INGRESS_CAPACITY},
+	mesh::{MeshMonitor, MESH_WARNING_INTERVAL},
+	nat::circuit_address,
+	network::NetworkEvent,
+	redial::{backoff, Redials, MAX_REDIAL_BACKOFF, REDIAL_BACKOFF},
+	scoring::{peer_score_params, peer_score_thresholds, INVALID_WITNESS_WEIGHT},
+	sync::{ProofsResponse, MAX_SYNC_RESPONSE_SIZE},
+	version::{is_incompatible, major_version},
+	derive_gossip_key, gossip_key_from_seed, load_gossip_key, pending_network, Backpressure,
+	Gossip, BanConfig, GossipCompression, GossipHandler, GossipNetwork, GossipService,
+	quic_address, Membership, MeshConfig, MeshExpectations, NatConfig, OrderQueueConfig,
+	TransportConfig, MAX_RESTARTS, PROTOCOL_VERSION,
+};
+use crate::{
+	errors::{Error, StartupError},
+	logging::GOSSIP,
+	metrics::Metrics,
+	proofs::{ValidatorProofs, WitnessedEvent, WITNESSED_EVENT_VERSION},
+	server::{
+		validated_streams_proto::{
+			admin_server::Admin, AddPeersRequest, ListPeersRequest, RegossipProofsRequest,
+			RemovePeerRequest,
+		},
+		AdminGrpc,
+	},
+	startup::{StartupSignals, StartupStep},
+	telemetry::StreamsTelemetry,
+	test_utils::{CapturedLogs, TestValidators},
+	traits::EventProofReaderTrait,
+	tunables::Tunables,
+};
 use async_trait::async_trait;
-use libp2p::{gossipsub::IdentTopic, Multiaddr};
-use sp_core::sr25519::Public;
+use futures::{
+	channel::{mpsc, oneshot},
+	stream::BoxStream,
+	StreamExt,
+};
+use libp2p::{
+	gossipsub::{IdentTopic, MessageAcceptance, MessageId},
+	identity::Keypair,
+	multiaddr::Protocol,
+	relay::v2::client::Client as RelayClient,
+	Multiaddr, PeerId,
+};
+use prometheus_endpoint::Registry;
+use sp_core::{sr25519, H256};
 use sp_runtime::app_crypto::CryptoTypePublicPair;
 use std::{
-	sync::{Arc, Mutex},
-	time::Duration,
+	collections::{HashMap, HashSet},
+	num::NonZeroUsize,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant},
 };
+use tonic::{Code, Request};
 pub struct MockGossipHandler {
 	messages: Mutex<Vec<WitnessedEvent>>,
 }
 #[async_trait]
 impl GossipHandler for MockGossipHandler {
-	fn get_topics() -> Vec<libp2p::gossipsub::IdentTopic> {
+	fn get_topics(&self) -> Vec<libp2p::gossipsub::IdentTopic> {
 		vec![IdentTopic::new("WitnessedEvent")]
 	}
 
-	async fn handle(&self, message: Vec<u8>) {
-		match bincode::deserialize::<WitnessedEvent>(message.as_slice()) {
+	async fn handle(&self, message: &[u8]) {
+		match WitnessedEvent::from_bytes(message) {
 			Ok(witnessed_event) => {
 				self.messages.lock().unwrap().push(witnessed_event);
 			},
 			Err(e) => log::error!("failed deserilizing message data due to error:{:?}", e),
 		}
 	}
+
+	fn held_messages(&self, event_ids: &[H256]) -> Vec<Vec<u8>> {
+		let messages = self.messages.lock().unwrap();
+		let held = messages.iter().filter(|event| event_ids.contains(&event.event_id));
+		held.map(|event| event.to_bytes().unwrap()).collect()
+	}
 }
 /// test receiving messages from other peers by creating a mock service that listens on a different
 /// Multiaddr and test that messages sent from self should not be received
@@ -44,12 +101,12 @@ pub async fn test_self_message() {
 	streams_gossip.connect_to(vec![self_addr.clone()]).await;
 	let handler_self_c = handler_self.clone();
 	tokio::spawn(async move {
-		service.run(handler_self_c).await;
+		service.run(handler_self_c).await.unwrap();
 	});
 	mock_peer_gossip.listen(peer_mock_addr.clone()).await;
 	let handler_peer_mock_c = handler_peer_mock.clone();
 	tokio::spawn(async move {
-		mock_peer_service.run(handler_peer_mock_c).await;
+		mock_peer_service.run(handler_peer_mock_c).await.unwrap();
 	});
 
 	// wait for the two peers to start
@@ -59,8 +116,9 @@ pub async fn test_self_message() {
 	//wait for connection to be established between peers
 	tokio::time::sleep(Duration::from_millis(1000)).await;
 	streams_gossip
-		.publish(IdentTopic::new("WitnessedEvent"), bincode::serialize(&witnessed_event).unwrap())
-		.await;
+		.publish(IdentTopic::new("WitnessedEvent"), witnessed_event.to_bytes().unwrap())
+		.await
+		.unwrap();
 
 	//wait for message to be received by the other peer
 	tokio::time::sleep(Duration::from_millis(1000)).await;
@@ -71,9 +129,1285 @@ pub async fn test_self_message() {
 }
 
 fn create_witnessed_event() -> WitnessedEvent {
-	WitnessedEvent {
-		event_id: sp_core::H256::repeat_byte(0),
-		pub_key: CryptoTypePublicPair::from(Public::from_h256(sp_core::H256::repeat_byte(0))),
-		signature: vec![],
+	TestValidators::new(1).witness(0, sp_core::H256::repeat_byte(0)).build()
+}
+
+fn address(port: u16) -> Multiaddr {
+	format!("/ip4/127.0.0.1/tcp/{port}").parse().unwrap()
+}
+
+/// The value of a gauge without labels in the registry.
+fn gauge(registry: &Registry, name: &str) -> Option<f64> {
+	let families = registry.gather();
+	let family = families.iter().find(|family| family.get_name() == name)?;
+	Some(family.get_metric()[0].get_gauge().get_value())
+}
+
+#[test]
+fn test_mesh_health_follows_handshaked_peers() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let registry = Registry::new();
+	let expectations = MeshExpectations::default();
+	expectations.set_addresses(vec![address(1), address(2)]);
+	// Three validators out of four, ourselves included
+	expectations.set_quorum(3);
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let mut mesh = MeshMonitor::new(expectations, metrics, StreamsTelemetry::default());
+	let (first, second) = (PeerId::random(), PeerId::random());
+	let now = Instant::now();
+	let warnings = || logs.find(GOSSIP, "Gossip mesh is degraded; events cannot reach quorum");
+	let last_missing = || Some(warnings().last()?.field("missing")?.to_string());
+
+	mesh.check(now);
+	assert_eq!(gauge(&registry, "streams_mesh_health"), Some(0.0));
+	assert_eq!(gauge(&registry, "streams_mesh_degraded"), Some(1.0));
+	assert_eq!(warnings().len(), 1);
+	assert_eq!(last_missing(), Some(format!("{:?}", [address(1), address(2)])));
+
+	// Connected peers only count once they subscribe; warnings are not repeated right away
+	let dialed = format!("{}/p2p/{first}", address(1)).parse().unwrap();
+	mesh.on_connected(first, Some(dialed));
+	mesh.on_connected(second, None);
+	mesh.check(now);
+	assert_eq!(gauge(&registry, "streams_mesh_health"), Some(0.0));
+	mesh.on_subscribed(first);
+	mesh.check(now + Duration::from_secs(1));
+	assert_eq!(gauge(&registry, "streams_mesh_health"), Some(0.5));
+	assert_eq!(warnings().len(), 1);
+	mesh.check(now + MESH_WARNING_INTERVAL);
+	assert_eq!(warnings().len(), 2);
+	assert_eq!(last_missing(), Some(format!("{:?}", [address(2)])));
+
+	// Peers we did not dial are recognized by the addresses they listen on
+	mesh.on_identified(second, vec![address(2)]);
+	mesh.on_subscribed(second);
+	mesh.check(now + MESH_WARNING_INTERVAL);
+	assert_eq!(gauge(&registry, "streams_mesh_health"), Some(1.0));
+	assert_eq!(gauge(&registry, "streams_mesh_degraded"), Some(0.0));
+	assert_eq!(logs.find(GOSSIP, "Gossip mesh recovered").len(), 1);
+
+	// Degrading again is warned about straight away
+	mesh.on_disconnected(&first);
+	mesh.check(now + MESH_WARNING_INTERVAL);
+	assert_eq!(gauge(&registry, "streams_mesh_degraded"), Some(1.0));
+	assert_eq!(warnings().len(), 3);
+	assert_eq!(last_missing(), Some(format!("{:?}", [address(1)])));
+}
+
+#[test]
+fn test_default_mesh_expectations_are_never_degraded() {
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let mut mesh =
+		MeshMonitor::new(MeshExpectations::default(), metrics, StreamsTelemetry::default());
+
+	mesh.check(Instant::now());
+	assert_eq!(gauge(&registry, "streams_mesh_health"), Some(1.0));
+	assert_eq!(gauge(&registry, "streams_mesh_degraded"), Some(0.0));
+}
+
+#[test]
+fn test_gossip_key_derived_from_the_node_key() {
+	let (node_key, other_key) = (Keypair::generate_ed25519(), Keypair::generate_ed25519());
+	let peer_id = |key: &Keypair| PeerId::from(key.public());
+	let gossip_key = derive_gossip_key(&node_key).unwrap();
+	// The same on every start, yet apart from the node's own
+	assert_eq!(peer_id(&derive_gossip_key(&node_key).unwrap()), peer_id(&gossip_key));
+	assert_ne!(peer_id(&gossip_key), peer_id(&node_key));
+	assert_ne!(peer_id(&derive_gossip_key(&other_key).unwrap()), peer_id(&gossip_key));
+
+	let (_gossip, service) = Gossip::create();
+	assert_ne!(service.peer_id(), peer_id(&gossip_key));
+	assert_eq!(service.with_key(gossip_key.clone()).peer_id(), peer_id(&gossip_key));
+}
+
+#[test]
+fn test_gossip_key_from_a_seed_or_a_file() {
+	let peer_id = |key: Keypair| PeerId::from(key.public());
+	let seeded = peer_id(gossip_key_from_seed("validator-1").unwrap());
+	assert_eq!(peer_id(gossip_key_from_seed("validator-1").unwrap()), seeded);
+	assert_ne!(peer_id(gossip_key_from_seed("validator-2").unwrap()), seeded);
+
+	let secret = [7; 32];
+	let path = std::env::temp_dir().join(format!("streams-gossip-key-{}", std::process::id()));
+	let load = |contents: &[u8]| {
+		std::fs::write(&path, contents).unwrap();
+		load_gossip_key(&path).map(peer_id)
+	};
+	let raw = load(&secret).unwrap();
+	assert_eq!(load(format!("{}\n", hex::encode(secret)).as_bytes()).unwrap(), raw);
+	assert_ne!(raw, seeded);
+	for invalid in [&b"7777"[..], &[7; 31], &[b'z'; 64]] {
+		match load(invalid) {
+			Err(e @ StartupError::Gossip(_)) =>
+				assert!(e.to_string().contains(&path.display().to_string()), "{e}"),
+			other => panic!("expected a gossip error, got {other:?}"),
+		}
+	}
+	std::fs::remove_file(&path).unwrap();
+	assert!(load_gossip_key(&path).is_err());
+}
+
+#[tokio::test]
+async fn test_gossip_fails_without_listen_address() {
+	let handler = || Arc::new(MockGossipHandler { messages: Mutex::new(Vec::new()) });
+	let occupied = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let occupied = address(occupied.local_addr().unwrap().port());
+	let unsupported: Multiaddr = "/memory/1".parse().unwrap();
+
+	for addresses in [vec![occupied.clone()], vec![unsupported.clone()]] {
+		let (_gossip, service) = Gossip::create();
+		let result = service.with_listen_addresses(addresses).run(handler()).await;
+		let error = result.unwrap_err();
+		assert!(matches!(error, StartupError::Gossip(_)));
+		assert!(error.is_fatal());
+	}
+
+	// One working address is enough to run
+	let (_gossip, service) = Gossip::create();
+	let service = service.with_listen_addresses(vec![occupied, unsupported, address(0)]);
+	let run = service.run(handler());
+	assert!(tokio::time::timeout(Duration::from_millis(500), run).await.is_err());
+}
+
+/// Panics on every message reading "panic", and counts the others.
+#[derive(Default)]
+struct PanickingHandler(AtomicUsize);
+
+#[async_trait]
+impl GossipHandler for PanickingHandler {
+	fn get_topics(&self) -> Vec<IdentTopic> {
+		vec![IdentTopic::new("Panicking")]
+	}
+
+	async fn handle(&self, message: &[u8]) {
+		if message == b"panic" {
+			panic!("handler panicked");
+		}
+		self.0.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+#[tokio::test]
+async fn test_gossip_restarts_after_a_panic() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let (mut gossip, service) = Gossip::create_with_metrics(metrics);
+	let handler = Arc::new(PanickingHandler::default());
+	let service = service.with_listen_addresses(vec![address(0)]);
+	let service = tokio::spawn(service.run(handler.clone()));
+	let topic = IdentTopic::new("Panicking");
+
+	let failed = gossip.publish(topic.clone(), b"panic".to_vec()).await;
+	assert!(matches!(failed, Err(Error::GossipUnavailable(_))));
+	// Handled by the rebuilt swarm once the backoff is over
+	assert_eq!(gossip.publish(topic, b"event".to_vec()).await, Ok(()));
+	assert_eq!(handler.0.load(Ordering::SeqCst), 1);
+
+	let families = registry.gather();
+	let restarts = families.iter().find(|f| f.get_name() == "streams_gossip_restarts_total");
+	assert_eq!(restarts.unwrap().get_metric()[0].get_counter().get_value(), 1.0);
+	let lines = logs.find(GOSSIP, "Gossip failed; restarting");
+	assert_eq!(lines.len(), 1);
+	assert_eq!(lines[0].field("cause"), Some("handler panicked"));
+
+	gossip.close().await;
+	assert_eq!(service.await.unwrap(), Ok(()));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_gossip_gives_up_after_repeated_panics() {
+	let (mut gossip, service) = Gossip::create();
+	let service = tokio::spawn(service.run(Arc::new(PanickingHandler::default())));
+
+	for _ in 0..=MAX_RESTARTS {
+		let failed = gossip.publish(IdentTopic::new("Panicking"), b"panic".to_vec()).await;
+		assert!(matches!(failed, Err(Error::GossipUnavailable(_))));
+	}
+	let error = service.await.unwrap().unwrap_err();
+	assert!(matches!(error, StartupError::Gossip(_)));
+	assert!(error.is_fatal());
+}
+
+/// A gossip whose order queue holds a single order, publishing as the policy says once it is full.
+fn single_order_gossip(backpressure: Backpressure, metrics: Metrics) -> (Gossip, GossipService) {
+	let orders = OrderQueueConfig { capacity: NonZeroUsize::new(1).unwrap(), backpressure };
+	let (expectations, telemetry) = (MeshExpectations::default(), StreamsTelemetry::default());
+	Gossip::create_with_order_queue(metrics, expectations, telemetry, orders)
+}
+
+#[tokio::test]
+async fn test_publishing_fails_on_a_full_order_queue() {
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let (mut gossip, service) = single_order_gossip(Backpressure::Fail, metrics);
+	let topic = IdentTopic::new("Panicking");
+
+	// The service is not running yet, so the first message fills the queue
+	let mut queued = gossip.clone();
+	let mut first = Box::pin(queued.publish(topic.clone(), b"first".to_vec()));
+	assert!(futures::poll!(&mut first).is_pending());
+	let refused = gossip.publish(topic, b"second".to_vec()).await;
+	assert!(matches!(refused, Err(Error::GossipOverloaded(_))));
+
+	let handler = Arc::new(PanickingHandler::default());
+	let service = service.with_listen_addresses(vec![address(0)]);
+	let service = tokio::spawn(service.run(handler.clone()));
+	assert_eq!(first.await, Ok(()));
+	assert_eq!(handler.0.load(Ordering::SeqCst), 1);
+	let families = registry.gather();
+	let refused = families.iter().find(|f| f.get_name() == "streams_gossip_publish_refused_total");
+	assert_eq!(refused.unwrap().get_metric()[0].get_counter().get_value(), 1.0);
+
+	gossip.close().await;
+	assert_eq!(service.await.unwrap(), Ok(()));
+}
+
+#[tokio::test]
+async fn test_oldest_message_dropped_from_a_full_order_queue() {
+	let (mut gossip, service) = single_order_gossip(Backpressure::DropOldest, Metrics::default());
+	let topic = IdentTopic::new("Panicking");
+
+	let mut queued = gossip.clone();
+	let mut first = Box::pin(queued.publish(topic.clone(), b"first".to_vec()));
+	assert!(futures::poll!(&mut first).is_pending());
+	let mut newer = gossip.clone();
+	let mut second = Box::pin(newer.publish(topic, b"second".to_vec()));
+	assert!(futures::poll!(&mut second).is_pending());
+	assert!(matches!(first.await, Err(Error::GossipOverloaded(_))));
+
+	let handler = Arc::new(PanickingHandler::default());
+	let service = service.with_listen_addresses(vec![address(0)]);
+	let service = tokio::spawn(service.run(handler.clone()));
+	assert_eq!(second.await, Ok(()));
+	assert_eq!(handler.0.load(Ordering::SeqCst), 1);
+
+	gossip.close().await;
+	assert_eq!(service.await.unwrap(), Ok(()));
+}
+
+#[tokio::test]
+async fn test_publishing_fails_once_the_service_is_gone() {
+	let (mut gossip, service) = single_order_gossip(Backpressure::Block, Metrics::default());
+	drop(service);
+	for message in [b"first", b"second"] {
+		let failed = gossip.publish(IdentTopic::new("Panicking"), message.to_vec()).await;
+		assert!(matches!(failed, Err(Error::GossipUnavailable(_))));
+	}
+}
+
+/// Records the messages it handles, taking a while over those of [SlowHandler::SLOW_KEY] as a slow
+/// proofs store would, and blocking its worker meanwhile. Messages are keyed by their first byte.
+#[derive(Default)]
+struct SlowHandler {
+	delay: Duration,
+	handled: Mutex<Vec<Vec<u8>>>,
+}
+
+impl SlowHandler {
+	const SLOW_KEY: u8 = 0;
+	const DELAY: Duration = Duration::from_millis(100);
+
+	fn slow() -> Self {
+		Self { delay: Self::DELAY, ..Default::default() }
+	}
+
+	fn handled(&self) -> Vec<Vec<u8>> {
+		self.handled.lock().unwrap().clone()
+	}
+}
+
+#[async_trait]
+impl GossipHandler for SlowHandler {
+	fn get_topics(&self) -> Vec<IdentTopic> {
+		vec![IdentTopic::new("Slow")]
+	}
+
+	async fn handle(&self, message: &[u8]) {
+		if message[0] == Self::SLOW_KEY {
+			std::thread::sleep(self.delay);
+		}
+		self.handled.lock().unwrap().push(message.to_vec());
+	}
+
+	fn ordering_key(&self, message: &[u8]) -> Option<u64> {
+		message.first().map(|key| *key as u64)
+	}
+}
+
+#[tokio::test]
+async fn test_ingress_drops_oldest_received_messages() {
+	let registry = Registry::new();
+	let ingress = Ingress::new(Metrics::register(Some(&registry)).unwrap());
+	let handler = Arc::new(SlowHandler::default());
+	let received = |i: u16| [&[1], &i.to_be_bytes()[..]].concat();
+
+	// Queued while no worker runs yet
+	let (published, acknowledged) = oneshot::channel();
+	ingress.publish(handler.as_ref(), vec![1], published);
+	for i in 0..INGRESS_CAPACITY as u16 + 2 {
+		ingress.receive(handler.as_ref(), received(i), tracing::Span::none(), None);
+	}
+	let duplicate = received(INGRESS_CAPACITY as u16);
+	ingress.receive(handler.as_ref(), duplicate, tracing::Span::none(), None);
+	ingress.receive(handler.as_ref(), Vec::new(), tracing::Span::none(), None);
+	assert_eq!(gauge(&registry, "streams_gossip_ingress_queued"), Some(INGRESS_CAPACITY as f64));
+
+	let (failures, _failed) = mpsc::unbounded();
+	ingress.spawn_workers(&handler, &GossipSpawner::default(), &failures);
+	acknowledged.await.unwrap().unwrap();
+	let (flushed, done) = oneshot::channel();
+	ingress.flush(flushed);
+	done.await.unwrap();
+
+	// Our own message stays, and the three oldest received ones make room for the newer ones
+	let mut expected = vec![vec![1]];
+	expected.extend((3..INGRESS_CAPACITY as u16 + 2).map(received));
+	assert_eq!(handler.handled(), expected);
+	let families = registry.gather();
+	let dropped = families
+		.iter()
+		.find(|family| family.get_name() == "streams_gossip_ingress_dropped_total")
+		.unwrap();
+	let mut dropped: Vec<_> = dropped
+		.get_metric()
+		.iter()
+		.map(|metric| (metric.get_label()[0].get_value(), metric.get_counter().get_value()))
+		.collect();
+	dropped.sort_by(|a, b| a.0.cmp(b.0));
+	assert_eq!(dropped, [("duplicate", 1.0), ("overflow", 3.0), ("rejected", 1.0)]);
+	assert_eq!(gauge(&registry, "streams_gossip_ingress_queued"), Some(0.0));
+}
+
+#[tokio::test]
+async fn test_ingress_reports_verdicts_on_received_messages() {
+	let ingress = Ingress::new(Metrics::default());
+	let handler = Arc::new(SlowHandler::default());
+	let (verdicts, reported) = mpsc::unbounded();
+	let source = PeerId::random();
+	let validation = |id: &str| {
+		Some(Validation { id: MessageId::new(id.as_bytes()), source, verdicts: verdicts.clone() })
+	};
+
+	ingress.receive(handler.as_ref(), vec![1], tracing::Span::none(), validation("handled"));
+	ingress.receive(handler.as_ref(), vec![1], tracing::Span::none(), validation("duplicate"));
+	ingress.receive(handler.as_ref(), Vec::new(), tracing::Span::none(), validation("rejected"));
+	let (failures, _failed) = mpsc::unbounded();
+	ingress.spawn_workers(&handler, &GossipSpawner::default(), &failures);
+	drop(verdicts);
+
+	// Dropped right away, and handled once a worker runs
+	let reported: Vec<_> = reported
+		.take(3)
+		.map(|(id, peer, acceptance)| (String::from_utf8(id.0).unwrap(), peer, acceptance))
+		.collect()
+		.await;
+	assert_eq!(reported, [
+		("duplicate".to_string(), source, MessageAcceptance::Ignore),
+		("rejected".to_string(), source, MessageAcceptance::Reject),
+		("handled".to_string(), source, MessageAcceptance::Accept),
+	]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_slow_handler_does_not_stall_the_gossip() {
+	let topic = IdentTopic::new("Slow");
+	let (slow_address, peer_address) = (address(10021), address(10022));
+	let (mut slow, slow_service) = Gossip::create();
+	let slow_handler = Arc::new(SlowHandler::slow());
+	let slow_service = slow_service.with_listen_addresses(vec![slow_address.clone()]);
+	tokio::spawn(slow_service.run(slow_handler.clone()));
+	let (mut peer, peer_service) = Gossip::create();
+	let peer_handler = Arc::new(SlowHandler::default());
+	let peer_service = peer_service.with_listen_addresses(vec![peer_address]);
+	tokio::spawn(peer_service.run(peer_handler.clone()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	peer.connect_to(vec![slow_address]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+
+	// Seconds worth of slow messages for the handler of the slow node
+	const BURST: u8 = 30;
+	for i in 0..BURST {
+		peer.publish(topic.clone(), vec![SlowHandler::SLOW_KEY, i]).await.unwrap();
+	}
+	tokio::time::sleep(Duration::from_millis(200)).await;
+
+	// Meanwhile, the slow node keeps publishing, and handling other messages
+	let started = Instant::now();
+	slow.publish(topic.clone(), vec![1]).await.unwrap();
+	assert!(started.elapsed() < SlowHandler::DELAY * 2, "publishing took {:?}", started.elapsed());
+	tokio::time::sleep(Duration::from_millis(500)).await;
+	assert!(peer_handler.handled().contains(&vec![1]));
+	assert!(slow_handler.handled().len() < BURST as usize);
+
+	// None of the slow messages were lost, nor reordered
+	slow.flush().await;
+	let slow_messages: Vec<_> = slow_handler
+		.handled()
+		.into_iter()
+		.filter(|message| message[0] == SlowHandler::SLOW_KEY)
+		.collect();
+	let expected: Vec<_> = (0..BURST).map(|i| vec![SlowHandler::SLOW_KEY, i]).collect();
+	assert_eq!(slow_messages, expected);
+}
+
+impl MockGossipHandler {
+	fn new() -> Arc<Self> {
+		Arc::new(Self { messages: Mutex::new(Vec::new()) })
+	}
+
+	fn holding(events: Vec<WitnessedEvent>) -> Arc<Self> {
+		Arc::new(Self { messages: Mutex::new(events) })
+	}
+
+	fn received(&self) -> Vec<WitnessedEvent> {
+		self.messages.lock().unwrap().clone()
+	}
+}
+
+#[tokio::test]
+async fn test_removed_peers_are_not_gossiped_with() {
+	let (first_address, second_address) = (address(10031), address(10032));
+	let (mut first, first_service) = Gossip::create();
+	let first_handler = MockGossipHandler::new();
+	let first_service = first_service.with_listen_addresses(vec![first_address]);
+	tokio::spawn(first_service.run(first_handler.clone()));
+	let (mut second, second_service) = Gossip::create();
+	let second_service = second_service.with_listen_addresses(vec![second_address.clone()]);
+	tokio::spawn(second_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	first.connect_to(vec![second_address.clone()]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+
+	let peers = first.peers().await.unwrap();
+	assert_eq!(peers.len(), 1);
+	assert!(peers[0].addresses.contains(&second_address), "{:?}", peers[0].addresses);
+	assert!(peers[0].subscribed);
+	let peer = peers[0].peer_id;
+	assert_eq!(first.remove_peer(peer).await, Ok(true));
+	tokio::time::sleep(Duration::from_millis(500)).await;
+	assert_eq!(first.peers().await, Ok(Vec::new()));
+	assert_eq!(first.remove_peer(peer).await, Ok(false));
+
+	// Dialing the peer again restores it
+	first.connect_to(vec![second_address]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	let witnessed_event = create_witnessed_event();
+	let message = witnessed_event.to_bytes().unwrap();
+	second.publish(IdentTopic::new("WitnessedEvent"), message).await.unwrap();
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert_eq!(first.peers().await.unwrap()[0].peer_id, peer);
+	assert_eq!(first_handler.received(), vec![witnessed_event]);
+
+	let stopped = Gossip::create().0.peers().await;
+	assert!(matches!(stopped, Err(Error::GossipUnavailable(_))));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_redials_backed_off_until_connected() {
+	let (peer, configured) = (PeerId::random(), address(10000));
+	let mut redials = Redials::default();
+	let start = tokio::time::Instant::now();
+	redials.sync(&[configured.clone()], start);
+	assert!(redials.take_due(start).is_empty());
+
+	// Redialed twice as late after each failure, with up to a quarter more at random
+	let mut due = start;
+	for attempts in 0..10 {
+		let wait = (REDIAL_BACKOFF * 2u32.pow(attempts)).min(MAX_REDIAL_BACKOFF);
+		let next = redials.next_due().unwrap();
+		assert!(next >= due + wait && next <= due + wait + wait / 4, "{attempts}: {next:?}");
+		due = next;
+		assert_eq!(redials.take_due(due), vec![(configured.clone(), None)]);
+	}
+	assert!(backoff(u32::MAX) <= MAX_REDIAL_BACKOFF + MAX_REDIAL_BACKOFF / 4);
+
+	// Not redialed while connected, and from the first backoff again once disconnected
+	redials.on_connected(peer, [configured.clone()].iter());
+	assert_eq!(redials.next_due(), None);
+	redials.on_disconnected(&peer, due);
+	assert!(redials.next_due().unwrap() <= due + REDIAL_BACKOFF + REDIAL_BACKOFF / 4);
+	let next = redials.next_due().unwrap();
+	assert_eq!(redials.take_due(next), vec![(configured, Some(peer))]);
+
+	// Nor once no longer configured
+	redials.sync(&[], next);
+	assert_eq!(redials.next_due(), None);
+}
+
+#[tokio::test]
+async fn test_peers_redialed_once_back() {
+	let (first_address, second_address) = (address(10081), address(10082));
+	let (mut first, first_service) = Gossip::create();
+	let first_service = first_service.with_listen_addresses(vec![first_address]);
+	tokio::spawn(first_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(500)).await;
+	first.connect_to(vec![second_address.clone()]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert_eq!(first.peers().await, Ok(Vec::new()));
+
+	// Reached once up, and again once restarted
+	for _ in 0..2 {
+		let (mut second, second_service) = Gossip::create();
+		let second_service = second_service.with_listen_addresses(vec![second_address.clone()]);
+		tokio::spawn(second_service.run(MockGossipHandler::new()));
+		tokio::time::sleep(Duration::from_millis(5000)).await;
+		assert_eq!(first.peers().await.unwrap().len(), 1);
+		second.close().await;
+		tokio::time::sleep(Duration::from_millis(500)).await;
+		assert_eq!(first.peers().await, Ok(Vec::new()));
+	}
+}
+
+#[tokio::test]
+async fn test_incoming_connections_limited() {
+	let limited_address = address(10041);
+	let (mut limited, limited_service) = Gossip::create();
+	let transport = TransportConfig { max_incoming: 1, ..Default::default() };
+	let limited_service = limited_service
+		.with_listen_addresses(vec![limited_address.clone()])
+		.with_transport(transport);
+	tokio::spawn(limited_service.run(MockGossipHandler::new()));
+	let mut dialers = Vec::new();
+	for port in [10042, 10043] {
+		let (dialer, service) = Gossip::create();
+		let service = service.with_listen_addresses(vec![address(port)]);
+		tokio::spawn(service.run(MockGossipHandler::new()));
+		dialers.push(dialer);
+	}
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	for dialer in &mut dialers {
+		dialer.connect_to(vec![limited_address.clone()]).await;
+		tokio::time::sleep(Duration::from_millis(500)).await;
+	}
+	tokio::time::sleep(Duration::from_millis(500)).await;
+
+	// The second peer is refused once the first one is connected
+	assert_eq!(limited.peers().await.unwrap().len(), 1);
+	assert_eq!(dialers[0].peers().await.unwrap().len(), 1);
+	assert_eq!(dialers[1].peers().await, Ok(Vec::new()));
+}
+
+#[test]
+fn test_quic_addresses_of_tcp_addresses() {
+	let quic = |address: &str| quic_address(&address.parse().unwrap()).map(|a| a.to_string());
+	assert_eq!(quic("/ip4/0.0.0.0/tcp/10000").as_deref(), Some("/ip4/0.0.0.0/udp/10000/quic"));
+	assert_eq!(quic("/ip6/::1/tcp/10000").as_deref(), Some("/ip6/::1/udp/10000/quic"));
+	for other in ["/ip4/127.0.0.1/udp/10000/quic", "/dns4/localhost/tcp/10000", "/ip4/127.0.0.1"] {
+		assert_eq!(quic(other), None, "{other}");
+	}
+}
+
+#[tokio::test]
+async fn test_witnesses_gossiped_over_quic() {
+	let transport = TransportConfig { quic: true, ..Default::default() };
+	let (mut first, first_service) = Gossip::create();
+	let first_handler = MockGossipHandler::new();
+	let first_service =
+		first_service.with_listen_addresses(vec![address(10051)]).with_transport(transport);
+	tokio::spawn(first_service.run(first_handler.clone()));
+	let (mut second, second_service) = Gossip::create();
+	let second_service =
+		second_service.with_listen_addresses(vec![address(10052)]).with_transport(transport);
+	tokio::spawn(second_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	let second_quic = quic_address(&address(10052)).unwrap();
+	first.connect_to(vec![second_quic.clone()]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+
+	let peers = first.peers().await.unwrap();
+	assert_eq!(peers.len(), 1);
+	assert!(peers[0].addresses.contains(&second_quic), "{:?}", peers[0].addresses);
+	let witnessed_event = create_witnessed_event();
+	let message = witnessed_event.to_bytes().unwrap();
+	second.publish(IdentTopic::new("WitnessedEvent"), message).await.unwrap();
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert_eq!(first_handler.received(), vec![witnessed_event]);
+}
+
+#[test]
+fn test_messages_decompressed_however_compressed() {
+	let message = create_witnessed_event().to_bytes().unwrap();
+	let compressions =
+		[GossipCompression::None, GossipCompression::Snappy, GossipCompression::Zstd];
+	for compression in compressions {
+		assert_eq!(compression.to_string().parse(), Ok(compression));
+		let compressed = compression.compress(message.clone());
+		assert_eq!(compressed == message, compression == GossipCompression::None);
+		assert_eq!(decompress(compressed.clone()), Ok(message.clone()));
+		if compression != GossipCompression::None {
+			assert!(decompress(compressed[..compressed.len() / 2].to_vec()).is_err());
+		}
+	}
+	assert!("lz4".parse::<GossipCompression>().is_err());
+
+	// Messages decompressing to too much are dropped, however small
+	let bomb = vec![0; MAX_DECOMPRESSED_SIZE + 1];
+	for compression in &compressions[1..] {
+		let compressed = compression.compress(bomb.clone());
+		assert!(compressed.len() < 64 * 1024);
+		assert!(decompress(compressed).is_err());
+	}
+}
+
+#[tokio::test]
+async fn test_compressed_witnesses_gossiped_to_any_peer() {
+	let (_first, first_service) = Gossip::create();
+	let first_handler = MockGossipHandler::new();
+	let first_service = first_service.with_listen_addresses(vec![address(10091)]);
+	tokio::spawn(first_service.run(first_handler.clone()));
+	let (mut second, second_service) = Gossip::create();
+	let second_service = second_service
+		.with_listen_addresses(vec![address(10092)])
+		.with_compression(GossipCompression::Zstd);
+	tokio::spawn(second_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	second.connect_to(vec![address(10091)]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+
+	let witnessed_event = create_witnessed_event();
+	let message = witnessed_event.to_bytes().unwrap();
+	second.publish(IdentTopic::new("WitnessedEvent"), message).await.unwrap();
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert_eq!(first_handler.received(), vec![witnessed_event]);
+}
+
+#[tokio::test]
+async fn test_peers_discovered_through_a_bootnode() {
+	let (mut bootnode, bootnode_service) = Gossip::create();
+	let bootnode_address = address(10061);
+	let bootnode_service = bootnode_service.with_listen_addresses(vec![bootnode_address.clone()]);
+	tokio::spawn(bootnode_service.run(MockGossipHandler::new()));
+	let mut validators = Vec::new();
+	for port in [10062, 10063] {
+		let (validator, service) = Gossip::create();
+		let service = service
+			.with_listen_addresses(vec![address(port)])
+			.with_discovery_interval(Duration::from_millis(500));
+		tokio::spawn(service.run(MockGossipHandler::new()));
+		validators.push(validator);
+	}
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	for validator in &mut validators {
+		validator.connect_to(vec![bootnode_address.clone()]).await;
+	}
+	tokio::time::sleep(Duration::from_millis(3000)).await;
+
+	// Each validator found the other one through the bootnode
+	assert_eq!(bootnode.peers().await.unwrap().len(), 2);
+	for validator in &mut validators {
+		let peers = validator.peers().await.unwrap();
+		assert_eq!(peers.len(), 2, "{peers:?}");
+	}
+}
+
+#[tokio::test]
+async fn test_local_peers_found_through_mdns() {
+	let mut gossips = Vec::new();
+	for port in [10071, 10072] {
+		let (gossip, service) = Gossip::create();
+		let service = service.with_listen_addresses(vec![address(port)]).with_mdns(true);
+		tokio::spawn(service.run(MockGossipHandler::new()));
+		gossips.push(gossip);
+	}
+	tokio::time::sleep(Duration::from_millis(3000)).await;
+
+	// Connected without dialing one another
+	for gossip in &mut gossips {
+		assert_eq!(gossip.peers().await.unwrap().len(), 1);
+	}
+}
+
+/// Holds the proofs of a single event.
+struct HeldProofs(H256, ValidatorProofs);
+
+impl EventProofReaderTrait for HeldProofs {
+	fn get_latest_event_proofs(&self, event_id: &H256) -> Result<ValidatorProofs, Error> {
+		let mut proofs = self.1.clone();
+		if *event_id != self.0 {
+			proofs.proofs.clear();
+		}
+		Ok(proofs)
+	}
+}
+
+#[tokio::test]
+async fn test_admin_regossips_held_proofs() {
+	let (gossip, service) = Gossip::create();
+	let handler = MockGossipHandler::new();
+	tokio::spawn(service.run(handler.clone()));
+	let validators = TestValidators::new(1);
+	let witness = validators.witness(0, H256::repeat_byte(1)).build();
+	let proofs = ValidatorProofs {
+		session: witness.session,
+		validators: validators.pubkeys(),
+		target: 1,
+		proofs: vec![(witness.pub_key.clone(), witness.proof())],
+	};
+	let admin = AdminGrpc {
+		tunables: Tunables::default(),
+		metrics: Metrics::default(),
+		gossip,
+		event_proofs: Arc::new(HeldProofs(witness.event_id, proofs)),
+	};
+
+	let regossip = |event_id: H256| RegossipProofsRequest { event_id: event_id.0.to_vec() };
+	let response = admin.regossip_proofs(Request::new(regossip(witness.event_id))).await;
+	assert_eq!(response.unwrap().into_inner().witnesses, 1);
+	assert_eq!(handler.received(), vec![witness]);
+	let unknown = admin.regossip_proofs(Request::new(regossip(H256::repeat_byte(2)))).await;
+	assert_eq!(unknown.unwrap_err().code(), Code::NotFound);
+
+	// Malformed peers are refused
+	assert!(admin.list_peers(Request::new(ListPeersRequest {})).await.is_ok());
+	let add = AddPeersRequest { addresses: vec!["not an address".to_string()] };
+	assert_eq!(admin.add_peers(Request::new(add)).await.unwrap_err().code(), Code::InvalidArgument);
+	let remove = RemovePeerRequest { peer_id: "not a peer".to_string() };
+	let removed = admin.remove_peer(Request::new(remove)).await;
+	assert_eq!(removed.unwrap_err().code(), Code::InvalidArgument);
+}
+
+/// Peers of a simulated notification protocol, which is open between the linked ones.
+#[derive(Default)]
+struct SimulatedProtocol {
+	events: Mutex<HashMap<PeerId, mpsc::UnboundedSender<NetworkEvent>>>,
+	links: Mutex<HashSet<(PeerId, PeerId)>>,
+}
+
+impl SimulatedProtocol {
+	/// The network of a new peer.
+	fn join(self: &Arc<Self>, peer: PeerId) -> Arc<dyn GossipNetwork> {
+		let (sender, events) = mpsc::unbounded();
+		self.events.lock().unwrap().insert(peer, sender);
+		Arc::new(SimulatedPeer { peer, events: Mutex::new(Some(events)), protocol: self.clone() })
+	}
+
+	fn send(&self, to: PeerId, event: NetworkEvent) {
+		self.events.lock().unwrap()[&to].unbounded_send(event).ok();
+	}
+
+	/// Opens the protocol between two peers.
+	fn link(&self, first: PeerId, second: PeerId) {
+		self.links.lock().unwrap().extend([(first, second), (second, first)]);
+		self.send(first, NetworkEvent::Opened(second));
+		self.send(second, NetworkEvent::Opened(first));
+	}
+}
+
+struct SimulatedPeer {
+	peer: PeerId,
+	events: Mutex<Option<mpsc::UnboundedReceiver<NetworkEvent>>>,
+	protocol: Arc<SimulatedProtocol>,
+}
+
+impl GossipNetwork for SimulatedPeer {
+	fn events(&self) -> BoxStream<'static, NetworkEvent> {
+		self.events.lock().unwrap().take().expect("events taken once").boxed()
+	}
+
+	fn notify(&self, peer: PeerId, message: Vec<u8>) {
+		if self.protocol.links.lock().unwrap().contains(&(self.peer, peer)) {
+			self.protocol.send(peer, NetworkEvent::Received(self.peer, message));
+		}
+	}
+
+	fn add_peers(&self, _peers: HashSet<Multiaddr>) -> Result<(), String> {
+		Ok(())
+	}
+
+	fn remove_peer(&self, peer: PeerId) {
+		let mut links = self.protocol.links.lock().unwrap();
+		if links.remove(&(self.peer, peer)) && links.remove(&(peer, self.peer)) {
+			self.protocol.send(self.peer, NetworkEvent::Closed(peer));
+			self.protocol.send(peer, NetworkEvent::Closed(self.peer));
+		}
+	}
+}
+
+#[tokio::test]
+async fn test_witnesses_relayed_over_the_network_protocol() {
+	let protocol = Arc::new(SimulatedProtocol::default());
+	let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+	let (mut gossips, mut handlers) = (Vec::new(), Vec::new());
+	for peer in &peers {
+		let (gossip, service) = Gossip::create();
+		let (network, provide) = pending_network();
+		provide(protocol.join(*peer));
+		let handler = MockGossipHandler::new();
+		tokio::spawn(service.with_network(network).run(handler.clone()));
+		gossips.push(gossip);
+		handlers.push(handler);
+	}
+	// The first peer only reaches the last one through the second
+	protocol.link(peers[0], peers[1]);
+	protocol.link(peers[1], peers[2]);
+	tokio::time::sleep(Duration::from_millis(200)).await;
+	assert_eq!(gossips[1].peers().await.unwrap().len(), 2);
+
+	let witnessed_event = create_witnessed_event();
+	let message = witnessed_event.to_bytes().unwrap();
+	gossips[0].publish(IdentTopic::new("WitnessedEvent"), message.clone()).await.unwrap();
+	// Topics the other handlers do not listen to are left out
+	gossips[0].publish(IdentTopic::new("Other"), message).await.unwrap();
+	tokio::time::sleep(Duration::from_millis(200)).await;
+	for handler in &handlers[1..] {
+		assert_eq!(handler.received(), vec![witnessed_event.clone()]);
+	}
+
+	assert!(gossips[1].remove_peer(peers[2]).await.unwrap());
+	assert_eq!(gossips[1].peers().await.unwrap().len(), 1);
+	let other_event = TestValidators::new(1).witness(0, H256::repeat_byte(1)).build();
+	let message = other_event.to_bytes().unwrap();
+	gossips[0].publish(IdentTopic::new("WitnessedEvent"), message).await.unwrap();
+	tokio::time::sleep(Duration::from_millis(200)).await;
+	assert_eq!(handlers[1].received(), vec![witnessed_event.clone(), other_event]);
+	assert_eq!(handlers[2].received(), vec![witnessed_event]);
+}
+
+#[test]
+fn test_peers_graylisted_after_four_rejected_witnesses() {
+	let topics = [IdentTopic::new("WitnessedEvent"), IdentTopic::new("WitnessedEvent/other")];
+	let params = peer_score_params(&topics);
+	let thresholds = peer_score_thresholds();
+	assert!(params.validate().is_ok());
+	assert!(thresholds.validate().is_ok());
+	assert_eq!(params.topics.len(), 2);
+
+	// Even peers with the best score on the topics are, once their rejected witnesses add up
+	let score = |rejected: f64| params.topic_score_cap + INVALID_WITNESS_WEIGHT * rejected.powi(2);
+	assert!(score(3.0) > thresholds.graylist_threshold);
+	assert!(score(4.0) < thresholds.graylist_threshold);
+	assert!(score(2.0) < thresholds.gossip_threshold);
+
+	let key = Keypair::generate_ed25519();
+	let (_, relay) = RelayClient::new_transport_and_behaviour(PeerId::from(key.public()));
+	let mesh = MeshConfig::default();
+	let behaviour = GossipService::get_behaviour(key, false, &mesh, false, relay).unwrap();
+	let mut gossipsub = behaviour.gossipsub;
+	assert!(gossipsub.with_peer_score(params, thresholds).is_ok());
+}
+
+#[test]
+fn test_mesh_configured_for_any_mesh_size() {
+	for mesh_n in 1..=12 {
+		let mesh = MeshConfig { mesh_n, history_length: 1, ..Default::default() };
+		let config = mesh.gossipsub_config().unwrap();
+		assert_eq!(config.mesh_n(), mesh_n);
+		assert!(config.mesh_n_low() >= 1 && config.mesh_n_low() <= mesh_n);
+		assert_eq!(config.mesh_n_high(), mesh_n * 2);
+		assert_eq!(config.history_gossip(), 1);
+	}
+	let default = MeshConfig::default().gossipsub_config().unwrap();
+	assert_eq!((default.mesh_n_low(), default.mesh_n_high()), (5, 12));
+	assert_eq!(default.max_transmit_size(), 65536);
+	assert!(default.flood_publish());
+	let mesh = MeshConfig { flood_publish: false, ..Default::default() };
+	assert!(!mesh.gossipsub_config().unwrap().flood_publish());
+
+	let mesh = MeshConfig { heartbeat_interval: Duration::ZERO, ..Default::default() };
+	assert!(matches!(mesh.gossipsub_config(), Err(StartupError::Gossip(_))));
+	let mesh = MeshConfig { mesh_n: 0, ..Default::default() };
+	assert!(matches!(mesh.gossipsub_config(), Err(StartupError::Gossip(_))));
+}
+
+#[tokio::test]
+async fn test_witnesses_gossiped_over_a_tuned_mesh() {
+	let mesh = MeshConfig {
+		heartbeat_interval: Duration::from_millis(200),
+		mesh_n: 2,
+		history_length: 3,
+		max_message_size: 4096,
+		flood_publish: false,
+	};
+	let (_first, first_service) = Gossip::create();
+	let first_handler = MockGossipHandler::new();
+	let first_service = first_service.with_listen_addresses(vec![address(10101)]).with_mesh(mesh);
+	tokio::spawn(first_service.run(first_handler.clone()));
+	let (mut second, second_service) = Gossip::create();
+	let second_service = second_service.with_listen_addresses(vec![address(10102)]).with_mesh(mesh);
+	tokio::spawn(second_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	second.connect_to(vec![address(10101)]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+
+	let witnessed_event = create_witnessed_event();
+	let message = witnessed_event.to_bytes().unwrap();
+	second.publish(IdentTopic::new("WitnessedEvent"), message).await.unwrap();
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert_eq!(first_handler.received(), vec![witnessed_event]);
+}
+
+#[tokio::test]
+async fn test_missed_witnesses_pulled_from_a_peer() {
+	let witnessed_event = create_witnessed_event();
+	let (_first, first_service) = Gossip::create();
+	let first_handler = MockGossipHandler::holding(vec![witnessed_event.clone()]);
+	let first_service = first_service.with_listen_addresses(vec![address(10111)]);
+	let first_peer = first_service.peer_id();
+	tokio::spawn(first_service.run(first_handler));
+	let (mut second, second_service) = Gossip::create();
+	let second_handler = MockGossipHandler::new();
+	let second_service = second_service.with_listen_addresses(vec![address(10112)]);
+	tokio::spawn(second_service.run(second_handler.clone()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	second.connect_to(vec![address(10111)]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+
+	let unknown = H256::repeat_byte(7);
+	let event_ids = vec![witnessed_event.event_id, unknown];
+	assert_eq!(second.request_proofs(first_peer, event_ids).await.unwrap(), 1);
+	second.flush().await;
+	assert_eq!(second_handler.received(), vec![witnessed_event]);
+
+	let unconnected = PeerId::random();
+	let requested = second.request_proofs(unconnected, vec![unknown]).await;
+	assert!(matches!(requested, Err(Error::GossipUnavailable(_))));
+}
+
+#[test]
+fn test_proofs_responses_capped_in_size() {
+	let witness = vec![0; 1000];
+	let response = ProofsResponse::new(vec![witness; 2000]);
+	let size: usize = response.witnesses.iter().map(|witness| witness.len() + 4).sum();
+	assert!(size < MAX_SYNC_RESPONSE_SIZE);
+	assert_eq!(response.witnesses.len(), MAX_SYNC_RESPONSE_SIZE / 1004);
+	assert_eq!(ProofsResponse::new(vec![vec![1]]).witnesses, vec![vec![1]]);
+}
+
+/// Proves membership with a key of its own, if given one, and admits the peers proving it with
+/// one of the member keys. Its "signatures" are the payloads themselves.
+struct MockMembership {
+	key: Option<CryptoTypePublicPair>,
+	members: Mutex<HashSet<CryptoTypePublicPair>>,
+}
+
+impl MockMembership {
+	fn new(key: Option<u8>, members: &[u8]) -> Arc<Self> {
+		let members = Mutex::new(members.iter().map(|&key| mock_key(key)).collect());
+		Arc::new(Self { key: key.map(mock_key), members })
 	}
 }
+
+fn mock_key(key: u8) -> CryptoTypePublicPair {
+	CryptoTypePublicPair(sr25519::CRYPTO_ID, vec![key; 32])
+}
+
+#[async_trait]
+impl Membership for MockMembership {
+	async fn prove(&self, local: PeerId) -> Option<MembershipProof> {
+		let signature = membership_payload(&local);
+		Some(MembershipProof { pub_key: self.key.clone()?, signature })
+	}
+
+	fn verify(&self, peer: &PeerId, proof: &MembershipProof) -> bool {
+		self.members.lock().unwrap().contains(&proof.pub_key) &&
+			proof.signature == membership_payload(peer)
+	}
+}
+
+#[tokio::test]
+async fn test_only_validators_admitted_to_a_restricted_mesh() {
+	let (_first, first_service) = Gossip::create();
+	let first_handler = MockGossipHandler::new();
+	let first_service = first_service
+		.with_listen_addresses(vec![address(10121)])
+		.with_membership(MockMembership::new(Some(1), &[1, 2]))
+		.with_allowlist(Vec::new());
+	tokio::spawn(first_service.run(first_handler.clone()));
+	let mut peers = Vec::new();
+	for (port, key) in [(10122, Some(2)), (10123, Some(3)), (10124, None)] {
+		let (mut peer, peer_service) = Gossip::create();
+		let peer_service = peer_service
+			.with_listen_addresses(vec![address(port)])
+			.with_membership(MockMembership::new(key, &[1, 2]));
+		tokio::spawn(peer_service.run(MockGossipHandler::new()));
+		tokio::time::sleep(Duration::from_millis(500)).await;
+		peer.connect_to(vec![address(10121)]).await;
+		peers.push(peer);
+	}
+	tokio::time::sleep(Duration::from_millis(1500)).await;
+
+	let mut witnessed_events = Vec::new();
+	for (i, peer) in peers.iter_mut().enumerate() {
+		let mut witnessed_event = create_witnessed_event();
+		witnessed_event.event_id = H256::repeat_byte(i as u8);
+		let message = witnessed_event.to_bytes().unwrap();
+		peer.publish(IdentTopic::new("WitnessedEvent"), message).await.ok();
+		witnessed_events.push(witnessed_event);
+	}
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	// Only the witness of the validator gets through, those of the others being ignored
+	assert_eq!(first_handler.received(), vec![witnessed_events[0].clone()]);
+}
+
+#[test]
+fn test_admissions_expel_peers_without_membership() {
+	let membership = MockMembership::new(None, &[1]);
+	let allowed = PeerId::random();
+	let verifier: Arc<dyn Membership> = membership.clone();
+	let mut admissions = Admissions::new(Some(verifier), &[allowed]);
+	let (validator, stranger, late) = (PeerId::random(), PeerId::random(), PeerId::random());
+	let now = Instant::now();
+	let proof = |peer: &PeerId, key| MembershipProof {
+		pub_key: mock_key(key),
+		signature: membership_payload(peer),
+	};
+
+	assert!(!admissions.on_connected(allowed, now));
+	assert!(admissions.is_admitted(&allowed));
+	for peer in [validator, stranger, late] {
+		assert!(admissions.on_connected(peer, now));
+	}
+	assert!(admissions.on_proof(validator, proof(&validator, 1)));
+	assert!(!admissions.on_proof(stranger, proof(&stranger, 2)));
+	// The proof of another peer is no proof
+	assert!(!admissions.on_proof(late, proof(&validator, 1)));
+	assert!(admissions.is_admitted(&validator) && !admissions.is_admitted(&stranger));
+	assert!(admissions.take_expelled(now).is_empty());
+	assert!(admissions.on_proof(late, proof(&late, 1)));
+
+	let expelled = admissions.take_expelled(now + ADMISSION_TIMEOUT);
+	assert_eq!(expelled, vec![stranger]);
+	// Proofs no longer hold once their key leaves the validator set
+	membership.members.lock().unwrap().clear();
+	let mut expelled = admissions.take_expelled(now + ADMISSION_TIMEOUT);
+	expelled.sort();
+	let mut left = vec![validator, late];
+	left.sort();
+	assert_eq!(expelled, left);
+	assert!(admissions.is_admitted(&allowed));
+	admissions.on_disconnected(&allowed);
+	assert!(!admissions.is_admitted(&allowed));
+}
+
+/// Rejects every message, counting them.
+#[derive(Default)]
+struct RejectingHandler(AtomicUsize);
+
+#[async_trait]
+impl GossipHandler for RejectingHandler {
+	fn get_topics(&self) -> Vec<IdentTopic> {
+		vec![IdentTopic::new("WitnessedEvent")]
+	}
+
+	async fn handle(&self, _message: &[u8]) {
+		self.0.fetch_add(1, Ordering::SeqCst);
+	}
+
+	async fn handle_received(&self, message: &[u8]) -> MessageAcceptance {
+		self.handle(message).await;
+		MessageAcceptance::Reject
+	}
+}
+
+#[tokio::test]
+async fn test_peers_banned_after_repeated_violations() {
+	let bans = BanConfig { threshold: 2, ..Default::default() };
+	let (mut first, first_service) = Gossip::create();
+	let first_handler = Arc::new(RejectingHandler::default());
+	let first_service = first_service.with_listen_addresses(vec![address(10131)]).with_bans(bans);
+	tokio::spawn(first_service.run(first_handler.clone()));
+	let (mut second, second_service) = Gossip::create();
+	let second_service = second_service.with_listen_addresses(vec![address(10132)]);
+	let second_peer = second_service.peer_id();
+	tokio::spawn(second_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	second.connect_to(vec![address(10131)]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert_eq!(first.peers().await.unwrap()[0].peer_id, second_peer);
+
+	for garbage in [b"garbage".to_vec(), b"forged".to_vec()] {
+		second.publish(IdentTopic::new("WitnessedEvent"), garbage).await.ok();
+	}
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert_eq!(first_handler.0.load(Ordering::SeqCst), 2);
+	assert_eq!(first.peers().await, Ok(Vec::new()));
+
+	// Banned peers cannot connect back
+	second.connect_to(vec![address(10131)]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert_eq!(first.peers().await, Ok(Vec::new()));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_bans_follow_violations_within_the_window() {
+	let config = BanConfig { threshold: 3, duration: Duration::from_secs(30), flood_limit: 5 };
+	let mut bans = Bans::new(config);
+	let (peer, flooder) = (PeerId::random(), PeerId::random());
+	let now = tokio::time::Instant::now();
+
+	assert!(!bans.on_violation(peer, Violation::Rejected, now));
+	assert!(!bans.on_violation(peer, Violation::Rejected, now));
+	// The first violations no longer count once past the window
+	let later = now + VIOLATION_WINDOW;
+	assert!(!bans.on_violation(peer, Violation::Rejected, later));
+	assert!(!bans.on_violation(peer, Violation::Rejected, later));
+	assert!(bans.on_violation(peer, Violation::Rejected, later));
+	assert!(!bans.on_violation(peer, Violation::Rejected, later));
+	assert_eq!(bans.banned().collect::<Vec<_>>(), vec![&peer]);
+	assert_eq!(bans.next_unban(), Some(later + config.duration));
+
+	// Floods are violations message after message past the limit, per second
+	let flooded = (0..8).map(|_| bans.on_message(flooder, later)).collect::<Vec<_>>();
+	assert_eq!(flooded, [false, false, false, false, false, false, false, true]);
+	for i in 0..10 {
+		assert!(!bans.on_message(peer, later + Duration::from_secs(i)));
+	}
+
+	assert!(bans.take_unbanned(later + config.duration - Duration::from_secs(1)).is_empty());
+	let mut unbanned = bans.take_unbanned(later + config.duration);
+	unbanned.sort();
+	let mut banned = vec![peer, flooder];
+	banned.sort();
+	assert_eq!(unbanned, banned);
+	assert_eq!(bans.next_unban(), None);
+
+	let disabled = BanConfig { threshold: 0, ..config };
+	let mut bans = Bans::new(disabled);
+	assert!((0..10).all(|_| !bans.on_violation(peer, Violation::Rejected, now)));
+}
+
+#[tokio::test]
+async fn test_peers_connected_once_enough_peers_subscribed() {
+	let startup = StartupSignals::default();
+	let (_first, first_service) = Gossip::create();
+	let first_service = first_service
+		.with_listen_addresses(vec![address(10141)])
+		.with_startup(startup.clone())
+		.with_min_peers(1);
+	tokio::spawn(first_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert!(startup.is_done(StartupStep::TopicsSubscribed));
+	assert!(!startup.is_done(StartupStep::PeersConnected));
+
+	let (mut second, second_service) = Gossip::create();
+	let second_startup = StartupSignals::default();
+	let second_service = second_service
+		.with_listen_addresses(vec![address(10142)])
+		.with_startup(second_startup.clone());
+	tokio::spawn(second_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	// Without a minimum, the step is done as soon as the service runs
+	assert!(second_startup.is_done(StartupStep::PeersConnected));
+	second.connect_to(vec![address(10141)]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert!(startup.is_done(StartupStep::PeersConnected));
+}
+
+#[tokio::test]
+async fn test_external_addresses_advertised_to_peers() {
+	let external: Multiaddr = "/ip4/203.0.113.7/tcp/30333".parse().unwrap();
+	let nat = NatConfig {
+		autonat: true,
+		external_addresses: vec![external.clone()],
+		..Default::default()
+	};
+	let (_first, first_service) = Gossip::create();
+	let first_service = first_service.with_listen_addresses(vec![address(10151)]).with_nat(nat);
+	let first_peer = first_service.peer_id();
+	tokio::spawn(first_service.run(MockGossipHandler::new()));
+	let (mut second, second_service) = Gossip::create();
+	let second_service = second_service.with_listen_addresses(vec![address(10152)]);
+	tokio::spawn(second_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	second.connect_to(vec![address(10151)]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+
+	let peers = second.peers().await.unwrap();
+	assert_eq!(peers[0].peer_id, first_peer);
+	assert!(peers[0].addresses.contains(&external), "{:?}", peers[0].addresses);
+}
+
+#[tokio::test]
+async fn test_relays_known_by_their_peer_id() {
+	let relay = address(10153).with(Protocol::P2p(PeerId::random().into()));
+	assert_eq!(circuit_address(&relay).to_string(), format!("{relay}/p2p-circuit"));
+
+	let nat = NatConfig { relays: vec![address(10153)], ..Default::default() };
+	let (_gossip, service) = Gossip::create();
+	let service = service.with_listen_addresses(vec![address(10154)]).with_nat(nat);
+	let error = service.run(MockGossipHandler::new()).await.unwrap_err();
+	assert!(matches!(error, StartupError::Gossip(_)));
+	assert!(error.to_string().contains("peer ID of the relay"), "{error}");
+}
+
+#[tokio::test]
+async fn test_listening_on_tcp_and_websocket_addresses() {
+	let websocket = address(10162).with(Protocol::Ws("/".into()));
+	let (mut first, first_service) = Gossip::create();
+	let addresses = vec![address(10161), websocket.clone()];
+	let first_service = first_service.with_listen_addresses(addresses.clone());
+	let first_peer = first_service.peer_id();
+	tokio::spawn(first_service.run(MockGossipHandler::new()));
+	let (mut second, second_service) = Gossip::create();
+	let second_service = second_service.with_listen_addresses(vec![address(10163)]);
+	tokio::spawn(second_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+
+	let listened = first.listen_addresses().await.unwrap();
+	assert_eq!(listened.into_iter().collect::<HashSet<_>>(), addresses.into_iter().collect());
+
+	// Peers can connect over either
+	second.connect_to(vec![websocket]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert_eq!(second.peers().await.unwrap()[0].peer_id, first_peer);
+}
+
+#[tokio::test]
+async fn test_gossip_over_ipv4_and_ipv6() {
+	let ipv6 = |port: u16| format!("/ip6/::1/tcp/{port}").parse::<Multiaddr>().unwrap();
+	let (mut first, first_service) = Gossip::create();
+	// Both stacks on the same port
+	let addresses = vec![address(10171), ipv6(10171)];
+	let first_service = first_service.with_listen_addresses(addresses.clone());
+	let first_peer = first_service.peer_id();
+	tokio::spawn(first_service.run(MockGossipHandler::new()));
+	let (mut second, second_service) = Gossip::create();
+	let second_service = second_service.with_listen_addresses(vec![ipv6(10172)]);
+	tokio::spawn(second_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+
+	let listened = first.listen_addresses().await.unwrap();
+	assert_eq!(listened.into_iter().collect::<HashSet<_>>(), addresses.into_iter().collect());
+	second.connect_to(vec![ipv6(10171)]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert_eq!(second.peers().await.unwrap()[0].peer_id, first_peer);
+	assert_eq!(first.peers().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_peers_dialed_and_redialed_by_name() {
+	let named: Multiaddr = "/dns4/localhost/tcp/10182".parse().unwrap();
+	let (mut first, first_service) = Gossip::create();
+	let first_service = first_service.with_listen_addresses(vec![address(10181)]);
+	tokio::spawn(first_service.run(MockGossipHandler::new()));
+	tokio::time::sleep(Duration::from_millis(500)).await;
+	first.connect_to(vec![named]).await;
+
+	// The name is resolved again on every redial, wherever the peer is found meanwhile
+	for _ in 0..2 {
+		let (mut second, second_service) = Gossip::create();
+		let second_service = second_service.with_listen_addresses(vec![address(10182)]);
+		tokio::spawn(second_service.run(MockGossipHandler::new()));
+		tokio::time::sleep(Duration::from_millis(5000)).await;
+		assert_eq!(first.peers().await.unwrap().len(), 1);
+		second.close().await;
+		tokio::time::sleep(Duration::from_millis(500)).await;
+		assert_eq!(first.peers().await, Ok(Vec::new()));
+	}
+}
+
+#[test]
+fn test_peers_of_other_major_versions_incompatible() {
+	assert_eq!(major_version(PROTOCOL_VERSION), Some(WITNESSED_EVENT_VERSION.into()));
+	assert!(!is_incompatible(PROTOCOL_VERSION));
+	// Minor and patch versions keep the witness format
+	assert!(!is_incompatible("vstreams/1.4.2"));
+	assert!(is_incompatible("vstreams/2.0.0"));
+	assert!(is_incompatible("vstreams/0.9.0"));
+	// Peers of other protocols, such as relays, are left to the other rules
+	assert_eq!(major_version("/ipfs/0.1.0"), None);
+	assert!(!is_incompatible("/ipfs/0.1.0"));
+	assert!(!is_incompatible("vstreams/next"));
+}
@@ -0,0 +1,29 @@
+//! Versions of the gossip protocol, so that changes to the format of the witnesses can roll out
+//! across a validator set without silent decode failures. Nodes on the libp2p swarm identify
+//! themselves to their peers with the [PROTOCOL_VERSION], whose major version is that of the
+//! witnesses they gossip, and disconnect the peers of another major version; the notification
+//! protocol of the `network` backend carries its version in its
+//! [name](super::network::PROTOCOL_NAME) instead, so that peers of other versions never open it.
+//! Witnesses of a version the node does not support, e.g. relayed by peers which are compatible
+//! otherwise, are ignored with a warning rather than rejected as malformed.
+
+use crate::proofs::WITNESSED_EVENT_VERSION;
+
+/// The version nodes identify themselves with, `vstreams/<major>.<minor>.<patch>`; the major
+/// version is the [WITNESSED_EVENT_VERSION].
+pub const PROTOCOL_VERSION: &str = "vstreams/1.0.0";
+/// What the versions of the protocol start with.
+const PROTOCOL_PREFIX: &str = "vstreams/";
+
+/// The major version of the gossip protocol a peer identified with, unless it identified with
+/// another protocol.
+pub fn major_version(protocol_version: &str) -> Option<u64> {
+	let version = protocol_version.strip_prefix(PROTOCOL_PREFIX)?;
+	version.split('.').next()?.parse().ok()
+}
+
+/// Whether a peer identified with a version of the gossip protocol whose witnesses the node cannot
+/// decode. Peers of other protocols, such as circuit relays, are not.
+pub fn is_incompatible(protocol_version: &str) -> bool {
+	major_version(protocol_version).map_or(false, |major| major != WITNESSED_EVENT_VERSION.into())
+}
@@ -0,0 +1,85 @@
+//! The standard gRPC health checks (`grpc.health.v1.Health`) of the server, for load balancers and
+//! probes. The streams service, and the server as a whole (the empty service name), are reported
+//! serving once every [StartupStep] is done, for as long as the proofs can be read back, and not
+//! serving again from the start of the shutdown on. The health service is not behind the API keys,
+//! as probes usually cannot send any; it only tells whether the subsystem is ready.
+
+use crate::{
+	logging::GRPC,
+	shutdown::{ShutdownSignal, ShutdownStage},
+	startup::{StartupSignals, StartupStep},
+	traits::EventProofReaderTrait,
+};
+use sp_core::H256;
+use std::{sync::Arc, time::Duration};
+use tonic_health::{server::HealthReporter, ServingStatus};
+#[cfg(test)]
+pub mod tests;
+
+/// How often the readiness of the subsystem is checked again.
+pub const HEALTH_INTERVAL: Duration = Duration::from_secs(5);
+/// The services whose health is reported: the server as a whole, and the streams service, also
+/// under its name before the package was versioned, for the probes checking it by that name.
+pub const HEALTH_SERVICES: [&str; 3] =
+	["", "validated_streams.v1.Streams", "ValidatedStreams.Streams"];
+
+/// Whether the subsystem is ready to serve the streams, or why not.
+pub fn readiness(
+	startup: &StartupSignals,
+	shutdown: &ShutdownSignal,
+	event_proofs: &(dyn EventProofReaderTrait + Send + Sync),
+) -> Result<(), String> {
+	if shutdown.has_reached(ShutdownStage::DrainingRequests) {
+		return Err("shutting down".into())
+	}
+	let pending = startup.pending(&StartupStep::ALL);
+	if !pending.is_empty() {
+		let pending: Vec<_> = pending.iter().map(StartupStep::as_str).collect();
+		return Err(format!("startup steps pending: {}", pending.join(", ")))
+	}
+	// Reads the latest validators and their proofs of an event, from the chain and the store
+	event_proofs
+		.get_latest_event_proofs(&H256::zero())
+		.map(|_| ())
+		.map_err(|e| format!("proofs unreadable: {e}"))
+}
+
+/// Keeps the health of [HEALTH_SERVICES] up to date with the [readiness] of the subsystem, logging
+/// every change. Returns once the shutdown starts, having reported them not serving.
+pub async fn report_health(
+	mut reporter: HealthReporter,
+	startup: StartupSignals,
+	shutdown: ShutdownSignal,
+	event_proofs: Arc<dyn EventProofReaderTrait + Send + Sync>,
+) {
+	let mut reported = None;
+	loop {
+		let ready = readiness(&startup, &shutdown, event_proofs.as_ref());
+		if reported.as_ref() != Some(&ready) {
+			let status = match &ready {
+				Ok(()) => {
+					tracing::info!(target: GRPC, "Health reported as serving");
+					ServingStatus::Serving
+				},
+				Err(reason) => {
+					tracing::warn!(target: GRPC, %reason, "Health reported as not serving");
+					ServingStatus::NotServing
+				},
+			};
+			for service in HEALTH_SERVICES {
+				reporter.set_service_status(service, status).await;
+			}
+			reported = Some(ready);
+		}
+		if shutdown.has_reached(ShutdownStage::DrainingRequests) {
+			return
+		}
+		// Checked again as soon as the startup is done, rather than at the next interval
+		let starting = !startup.pending(&StartupStep::ALL).is_empty();
+		tokio::select! {
+			_ = tokio::time::sleep(HEALTH_INTERVAL) => {},
+			_ = startup.all_done(&StartupStep::ALL), if starting => {},
+			_ = shutdown.reached(ShutdownStage::DrainingRequests) => {},
+		}
+	}
+}
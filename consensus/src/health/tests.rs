@@ -0,0 +1,92 @@
+use super::{readiness, report_health, HEALTH_SERVICES};
+use crate::{
+	errors::Error,
+	proofs::ValidatorProofs,
+	shutdown::{ShutdownSignal, ShutdownStage},
+	startup::{StartupSignals, StartupStep},
+	test_utils::NoEventProofs,
+	traits::EventProofReaderTrait,
+};
+use futures::StreamExt;
+use sp_core::H256;
+use std::{net::TcpListener, sync::Arc, time::Duration};
+use tokio::net::TcpStream;
+use tonic::transport::{Channel, Server};
+use tonic_health::pb::{
+	health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+};
+
+/// Proofs which cannot be read, as with an unreachable store.
+struct UnreadableProofs;
+
+impl EventProofReaderTrait for UnreadableProofs {
+	fn get_latest_event_proofs(&self, _event_id: &H256) -> Result<ValidatorProofs, Error> {
+		Err(Error::Database("store closed".to_string()))
+	}
+}
+
+#[test]
+fn test_readiness() {
+	let (startup, shutdown) = (StartupSignals::default(), ShutdownSignal::default());
+	let reason = readiness(&startup, &shutdown, &NoEventProofs).unwrap_err();
+	assert!(reason.contains("proofs_store_open, keystore_resolved"), "{reason}");
+
+	for step in &StartupStep::ALL[..3] {
+		startup.mark_done(*step);
+	}
+	let reason = readiness(&startup, &shutdown, &NoEventProofs).unwrap_err();
+	assert_eq!(reason, "startup steps pending: topics_subscribed, peers_connected");
+	startup.mark_done(StartupStep::TopicsSubscribed);
+	let reason = readiness(&startup, &shutdown, &NoEventProofs).unwrap_err();
+	assert_eq!(reason, "startup steps pending: peers_connected");
+	startup.mark_done(StartupStep::PeersConnected);
+	assert_eq!(readiness(&startup, &shutdown, &NoEventProofs), Ok(()));
+
+	let reason = readiness(&startup, &shutdown, &UnreadableProofs).unwrap_err();
+	assert!(reason.contains("store closed"), "{reason}");
+	shutdown.advance(ShutdownStage::DrainingRequests);
+	assert_eq!(readiness(&startup, &shutdown, &NoEventProofs), Err("shutting down".into()));
+}
+
+#[tokio::test]
+async fn test_health_follows_the_startup_and_shutdown() {
+	let (reporter, health) = tonic_health::server::health_reporter();
+	let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+	let server = tokio::spawn(Server::builder().add_service(health).serve(address));
+	while TcpStream::connect(address).await.is_err() {
+		tokio::time::sleep(Duration::from_millis(10)).await;
+	}
+	let (startup, shutdown) = (StartupSignals::default(), ShutdownSignal::default());
+	let reporting = tokio::spawn(report_health(
+		reporter,
+		startup.clone(),
+		shutdown.clone(),
+		Arc::new(NoEventProofs),
+	));
+
+	let channel = Channel::from_shared(format!("http://{address}")).unwrap().connect();
+	let mut client = HealthClient::new(channel.await.unwrap());
+	let mut watches = Vec::new();
+	for service in HEALTH_SERVICES {
+		let request = HealthCheckRequest { service: service.to_string() };
+		let watch = client.watch(request).await.unwrap().into_inner();
+		watches.push(watch.map(|response| response.unwrap().status));
+	}
+	let not_serving = ServingStatus::NotServing as i32;
+	for watch in &mut watches {
+		assert_eq!(watch.next().await, Some(not_serving));
+	}
+
+	for step in StartupStep::ALL {
+		startup.mark_done(step);
+	}
+	for watch in &mut watches {
+		assert_eq!(watch.next().await, Some(ServingStatus::Serving as i32));
+	}
+	shutdown.advance(ShutdownStage::DrainingRequests);
+	for watch in &mut watches {
+		assert_eq!(watch.next().await, Some(not_serving));
+	}
+	reporting.await.unwrap();
+	server.abort();
+}
@@ -0,0 +1,53 @@
+//! Validated streams event index storage
+
+use super::EventIndexTrait;
+use crate::errors::Error;
+
+use sp_core::H256;
+use std::sync::Mutex;
+
+/// An in-memory index of the validated events.
+#[derive(Default)]
+pub struct InMemoryEventIndex {
+	/// The first indexed block, and the events of every indexed block from it on
+	blocks: Mutex<Option<(u32, Vec<Vec<(u32, H256)>>)>>,
+}
+
+impl InMemoryEventIndex {
+	/// Create an empty [InMemoryEventIndex] instance.
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl EventIndexTrait for InMemoryEventIndex {
+	fn add_block_events(&self, block_number: u32, events: &[(u32, H256)]) -> Result<(), Error> {
+		let mut blocks = self.blocks.lock()?;
+		let (first, indexed) = blocks.get_or_insert_with(|| (block_number, vec![]));
+		let next = *first + indexed.len() as u32;
+		if block_number != next {
+			return Err(Error::Database(format!(
+				"block {block_number} does not follow the last indexed block {}",
+				next - 1
+			)))
+		}
+		indexed.push(events.to_vec());
+		Ok(())
+	}
+
+	fn get_block_events(&self, block_number: u32) -> Result<Option<Vec<(u32, H256)>>, Error> {
+		let blocks = self.blocks.lock()?;
+		Ok(blocks.as_ref().and_then(|(first, indexed)| {
+			let offset = block_number.checked_sub(*first)?;
+			indexed.get(offset as usize).cloned()
+		}))
+	}
+
+	fn bounds(&self) -> Result<Option<(u32, u32)>, Error> {
+		let blocks = self.blocks.lock()?;
+		Ok(blocks.as_ref().and_then(|(first, indexed)| {
+			let count = indexed.len() as u32;
+			(count > 0).then(|| (*first, *first + count - 1))
+		}))
+	}
+}
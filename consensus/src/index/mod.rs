@@ -0,0 +1,141 @@
+//! The off-chain index of the validated events, by finalized block. The node indexes the events of
+//! every finalized block in order, starting from genesis, so that the `ListValidatedEvents` RPC
+//! can page through the events of any range of indexed blocks without walking the chain. Events
+//! are listed by block number, then by the index of the extrinsic which validated them; a page
+//! ends with a continuation token, the position of the next event, from which the next page of
+//! the same range starts.
+
+use crate::{
+	errors::Error,
+	logging::SERVICE,
+	traits::EventValidatorTrait,
+	watchdog::{next_beating, Heartbeat},
+};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use sp_core::H256;
+use std::sync::Arc;
+
+#[cfg(test)]
+pub mod tests;
+
+pub mod in_memory;
+pub use in_memory::InMemoryEventIndex;
+
+pub mod offchain;
+pub use offchain::OffchainStorageEventIndex;
+
+/// How many events a page holds if the request does not say.
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+/// The most events a page holds.
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+/// A validated event, as listed by the index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedEvent {
+	/// The finalized block the event was validated in
+	pub block_number: u32,
+	/// The index of the extrinsic which validated the event within its block
+	pub extrinsic_index: u32,
+	/// The id of the event
+	pub event_id: H256,
+}
+
+/// The position of the next event of a listing: the block number and the extrinsic index of the
+/// event.
+pub type PageToken = (u32, u32);
+
+/// A trait for storing the index of the validated events.
+pub trait EventIndexTrait {
+	/// Records the events of a finalized block, by the index of the extrinsic which validated
+	/// them, in order. The block must directly follow the last indexed one, unless the index is
+	/// empty.
+	fn add_block_events(&self, block_number: u32, events: &[(u32, H256)]) -> Result<(), Error>;
+	/// The events of an indexed block, by the index of their extrinsics, in order; [None] if the
+	/// block is not indexed.
+	fn get_block_events(&self, block_number: u32) -> Result<Option<Vec<(u32, H256)>>, Error>;
+	/// The first and the last indexed block; [None] if nothing is indexed yet.
+	fn bounds(&self) -> Result<Option<(u32, u32)>, Error>;
+}
+
+/// Lists up to `page_size` of the events validated from block `from` to block `to` included, which
+/// must not be before `from`, starting at the position of the `token` if given. Returns them along
+/// with the token of the next page, if any. Fails with [Error::NotIndexed] unless every block of
+/// the range is indexed.
+pub fn list_events(
+	index: &dyn EventIndexTrait,
+	from: u32,
+	to: u32,
+	page_size: u32,
+	token: Option<PageToken>,
+) -> Result<(Vec<IndexedEvent>, Option<PageToken>), Error> {
+	let indexed = index.bounds()?;
+	if !matches!(indexed, Some((first, last)) if first <= from && to <= last) {
+		return Err(Error::NotIndexed { from, to, indexed })
+	}
+	let (start_block, start_extrinsic) = match token {
+		Some((block, extrinsic)) if (from..=to).contains(&block) => (block, extrinsic),
+		Some(_) => return Err(Error::InvalidPageToken),
+		None => (from, 0),
+	};
+	let page_size = match page_size {
+		0 => DEFAULT_PAGE_SIZE,
+		size => size.min(MAX_PAGE_SIZE),
+	} as usize;
+
+	let mut events = Vec::new();
+	for block_number in start_block..=to {
+		let block_events = index
+			.get_block_events(block_number)?
+			.ok_or(Error::NotIndexed { from, to, indexed })?;
+		let skipped = if block_number == start_block { start_extrinsic } else { 0 };
+		for (extrinsic_index, event_id) in block_events {
+			if extrinsic_index < skipped {
+				continue
+			}
+			if events.len() == page_size {
+				return Ok((events, Some((block_number, extrinsic_index))))
+			}
+			events.push(IndexedEvent { block_number, extrinsic_index, event_id });
+		}
+	}
+	Ok((events, None))
+}
+
+/// Indexes the events of every finalized block after the last indexed one, or from genesis if
+/// nothing is indexed yet, once at the start and again on every finality notification, until the
+/// notifications end.
+pub(crate) async fn index_finalized_events<Validator, Notifications>(
+	index: Arc<dyn EventIndexTrait + Send + Sync>,
+	validator: Arc<Validator>,
+	mut finality_notifications: Notifications,
+	heartbeat: Heartbeat,
+) where
+	Validator: EventValidatorTrait + Send + Sync,
+	Notifications: Stream + Send + Unpin,
+{
+	loop {
+		if let Err(e) = catch_up(index.as_ref(), validator.as_ref(), &heartbeat).await {
+			tracing::warn!(target: SERVICE, error = %e, "Failed indexing the finalized events");
+		}
+		if next_beating(&mut finality_notifications, &heartbeat).await.is_none() {
+			return
+		}
+	}
+}
+
+async fn catch_up(
+	index: &(dyn EventIndexTrait + Send + Sync),
+	validator: &(impl EventValidatorTrait + Sync),
+	heartbeat: &Heartbeat,
+) -> Result<(), Error> {
+	let finalized = validator.get_latest_finalized_block().await?;
+	let mut next = index.bounds()?.map_or(0, |(_, last)| last + 1);
+	while next <= finalized {
+		let events = validator.get_finalized_block_indexed_events(next).await?;
+		index.add_block_events(next, &events)?;
+		heartbeat.bump();
+		next += 1;
+	}
+	Ok(())
+}
@@ -0,0 +1,67 @@
+//! Validated streams event index storage
+
+use super::EventIndexTrait;
+use crate::errors::Error;
+
+use sp_core::{offchain::OffchainStorage, H256};
+use std::sync::Mutex;
+
+/// A persistent index of the validated events in [OffchainStorage], apart from the proofs.
+pub struct OffchainStorageEventIndex<Storage: OffchainStorage> {
+	// key value format:
+	// b <block number (u32, big endian)> -> <serialized events of the block>
+	// bounds -> <serialized first and last indexed blocks>
+	storage: Storage,
+	/// Held while adding blocks, which moves the bounds
+	adding: Mutex<()>,
+}
+
+impl<Storage: OffchainStorage> OffchainStorageEventIndex<Storage> {
+	/// The prefix under which data is persisted in the OffchainStorage
+	pub const OFFCHAIN_PREFIX: &[u8] = b"EventIndex";
+	const BOUNDS: &[u8] = b"bounds";
+
+	/// Returns a OffchainStorageEventIndex instance that persists data in the provided
+	/// [OffchainStorage]
+	pub fn new(storage: Storage) -> Self {
+		Self { storage, adding: Mutex::new(()) }
+	}
+
+	fn block_key(block_number: u32) -> Vec<u8> {
+		[b"b".as_ref(), &block_number.to_be_bytes()].concat()
+	}
+}
+
+impl<Storage: OffchainStorage> EventIndexTrait for OffchainStorageEventIndex<Storage> {
+	fn add_block_events(&self, block_number: u32, events: &[(u32, H256)]) -> Result<(), Error> {
+		let _adding = self.adding.lock()?;
+		let first = match self.bounds()? {
+			None => block_number,
+			Some((first, last)) if block_number == last + 1 => first,
+			Some((_, last)) =>
+				return Err(Error::Database(format!(
+					"block {block_number} does not follow the last indexed block {last}"
+				))),
+		};
+		let mut storage = self.storage.clone();
+		let key = Self::block_key(block_number);
+		storage.set(Self::OFFCHAIN_PREFIX, &key, &bincode::serialize(events)?);
+		let bounds = bincode::serialize(&(first, block_number))?;
+		storage.set(Self::OFFCHAIN_PREFIX, Self::BOUNDS, &bounds);
+		Ok(())
+	}
+
+	fn get_block_events(&self, block_number: u32) -> Result<Option<Vec<(u32, H256)>>, Error> {
+		match self.storage.get(Self::OFFCHAIN_PREFIX, &Self::block_key(block_number)) {
+			Some(events) => Ok(Some(bincode::deserialize(&events)?)),
+			None => Ok(None),
+		}
+	}
+
+	fn bounds(&self) -> Result<Option<(u32, u32)>, Error> {
+		match self.storage.get(Self::OFFCHAIN_PREFIX, Self::BOUNDS) {
+			Some(bounds) => Ok(Some(bincode::deserialize(&bounds)?)),
+			None => Ok(None),
+		}
+	}
+}
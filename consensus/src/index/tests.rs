@@ -0,0 +1,233 @@
+use super::{
+	index_finalized_events, list_events, EventIndexTrait, InMemoryEventIndex, IndexedEvent,
+	OffchainStorageEventIndex, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+};
+use crate::{errors::Error, traits::EventValidatorTrait, watchdog::Heartbeats};
+use async_trait::async_trait;
+use futures::channel::mpsc;
+use rstest::rstest;
+use sp_core::H256;
+use sp_runtime::offchain::testing::TestPersistentOffchainDB;
+use std::sync::{Arc, Mutex};
+
+fn in_memory_index() -> InMemoryEventIndex {
+	InMemoryEventIndex::new()
+}
+
+fn offchain_index() -> OffchainStorageEventIndex<TestPersistentOffchainDB> {
+	OffchainStorageEventIndex::new(TestPersistentOffchainDB::new())
+}
+
+/// The events of a block with events in the given extrinsics, with ids telling them apart.
+fn block_events(block_number: u32, extrinsics: &[u32]) -> Vec<(u32, H256)> {
+	let event_id = |extrinsic| H256::from_low_u64_be(u64::from(block_number) << 32 | extrinsic);
+	extrinsics.iter().map(|&extrinsic| (extrinsic, event_id(u64::from(extrinsic)))).collect()
+}
+
+#[rstest]
+#[case(in_memory_index())]
+#[case(offchain_index())]
+fn test_add_and_get_block_events(#[case] index: impl EventIndexTrait) {
+	assert_eq!(index.bounds(), Ok(None));
+	assert_eq!(index.get_block_events(5), Ok(None));
+
+	assert_eq!(index.add_block_events(5, &block_events(5, &[1, 3])), Ok(()));
+	assert_eq!(index.add_block_events(6, &[]), Ok(()));
+	assert_eq!(index.bounds(), Ok(Some((5, 6))));
+	assert_eq!(index.get_block_events(5), Ok(Some(block_events(5, &[1, 3]))));
+	assert_eq!(index.get_block_events(6), Ok(Some(vec![])));
+	assert_eq!(index.get_block_events(4), Ok(None));
+	assert_eq!(index.get_block_events(7), Ok(None));
+
+	// Blocks are only added in order, without gaps
+	assert!(matches!(index.add_block_events(8, &[]), Err(Error::Database(_))));
+	assert!(matches!(index.add_block_events(6, &[]), Err(Error::Database(_))));
+	assert_eq!(index.bounds(), Ok(Some((5, 6))));
+}
+
+/// The extrinsics of the blocks of [indexed_blocks] with events: none in the even blocks, 1 to 3
+/// in the odd ones.
+fn indexed_extrinsics(block_number: u32) -> &'static [u32] {
+	if block_number % 2 == 0 {
+		&[]
+	} else {
+		&[1, 2, 3]
+	}
+}
+
+/// An index of blocks 0 to 9.
+fn indexed_blocks() -> InMemoryEventIndex {
+	let index = in_memory_index();
+	for block_number in 0..10 {
+		let events = block_events(block_number, indexed_extrinsics(block_number));
+		index.add_block_events(block_number, &events).unwrap();
+	}
+	index
+}
+
+/// Every event of the range, listed a page of `page_size` at a time, along with the number of
+/// pages.
+fn list_all(
+	index: &dyn EventIndexTrait,
+	from: u32,
+	to: u32,
+	page_size: u32,
+) -> (Vec<IndexedEvent>, usize) {
+	let (mut events, mut pages, mut token) = (vec![], 0, None);
+	loop {
+		let (page, next) = list_events(index, from, to, page_size, token).unwrap();
+		assert!(page.len() <= page_size as usize);
+		events.extend(page);
+		pages += 1;
+		match next {
+			Some(next) => token = Some(next),
+			None => return (events, pages),
+		}
+	}
+}
+
+#[test]
+fn test_list_events_pages_through_the_range() {
+	let index = indexed_blocks();
+	let expected: Vec<_> = (2..=7)
+		.flat_map(|block_number| {
+			let events = block_events(block_number, indexed_extrinsics(block_number));
+			events.into_iter().map(move |(extrinsic_index, event_id)| IndexedEvent {
+				block_number,
+				extrinsic_index,
+				event_id,
+			})
+		})
+		.collect();
+	assert_eq!(expected.len(), 9);
+
+	for (page_size, pages) in [(1, 9), (2, 5), (4, 3), (9, 1), (100, 1)] {
+		assert_eq!(list_all(&index, 2, 7, page_size), (expected.clone(), pages), "{page_size}");
+	}
+	// Ordered by block, then by extrinsic
+	let mut sorted = expected.clone();
+	sorted.sort_by_key(|event| (event.block_number, event.extrinsic_index));
+	assert_eq!(sorted, expected);
+
+	// A page ends with the position of the next event, even in a later block
+	let (page, next) = list_events(&index, 2, 7, 3, None).unwrap();
+	assert_eq!(page, expected[..3]);
+	assert_eq!(next, Some((5, 1)));
+	// Ranges without events
+	assert_eq!(list_events(&index, 4, 4, 3, None), Ok((vec![], None)));
+	assert_eq!(list_events(&index, 3, 3, 3, Some((3, 4))), Ok((vec![], None)));
+}
+
+#[test]
+fn test_list_events_page_sizes() {
+	let index = in_memory_index();
+	let extrinsics: Vec<u32> = (0..MAX_PAGE_SIZE + 10).collect();
+	index.add_block_events(0, &block_events(0, &extrinsics)).unwrap();
+
+	let (page, next) = list_events(&index, 0, 0, 0, None).unwrap();
+	assert_eq!((page.len(), next), (DEFAULT_PAGE_SIZE as usize, Some((0, DEFAULT_PAGE_SIZE))));
+	let (page, next) = list_events(&index, 0, 0, u32::MAX, None).unwrap();
+	assert_eq!((page.len(), next), (MAX_PAGE_SIZE as usize, Some((0, MAX_PAGE_SIZE))));
+}
+
+#[test]
+fn test_list_events_outside_the_index() {
+	let index = in_memory_index();
+	let not_indexed = Err(Error::NotIndexed { from: 0, to: 1, indexed: None });
+	assert_eq!(list_events(&index, 0, 1, 10, None), not_indexed);
+
+	let index = indexed_blocks();
+	let indexed = Some((0, 9));
+	// Wholly or partly past the last indexed block
+	for (from, to) in [(8, 10), (10, 12)] {
+		let not_indexed = Err(Error::NotIndexed { from, to, indexed });
+		assert_eq!(list_events(&index, from, to, 10, None), not_indexed);
+	}
+	let message = Error::NotIndexed { from: 8, to: 10, indexed }.to_string();
+	assert!(message.contains("0 to 9"), "{message}");
+
+	// Partly before the first one
+	let index = in_memory_index();
+	for block_number in 3..5 {
+		index.add_block_events(block_number, &[]).unwrap();
+	}
+	let not_indexed = Err(Error::NotIndexed { from: 2, to: 4, indexed: Some((3, 4)) });
+	assert_eq!(list_events(&index, 2, 4, 10, None), not_indexed);
+}
+
+#[test]
+fn test_list_events_refuses_tokens_of_other_ranges() {
+	let index = indexed_blocks();
+	let (_, next) = list_events(&index, 1, 9, 2, None).unwrap();
+	assert_eq!(next, Some((3, 1)));
+	assert_eq!(list_events(&index, 5, 9, 2, next), Err(Error::InvalidPageToken));
+	assert_eq!(list_events(&index, 1, 2, 2, next), Err(Error::InvalidPageToken));
+}
+
+/// A chain whose finalized blocks are those pushed so far, with their events.
+#[derive(Default)]
+struct ScriptedChain {
+	blocks: Mutex<Vec<Vec<(u32, H256)>>>,
+}
+
+impl ScriptedChain {
+	fn finalize(&self, extrinsics: &[u32]) {
+		let mut blocks = self.blocks.lock().unwrap();
+		let block_number = blocks.len() as u32;
+		blocks.push(block_events(block_number, extrinsics));
+	}
+}
+
+#[async_trait]
+impl EventValidatorTrait for ScriptedChain {
+	async fn get_finalized_block_events(&self, block_num: u32) -> Result<Vec<H256>, Error> {
+		let events = self.get_finalized_block_indexed_events(block_num).await?;
+		Ok(events.into_iter().map(|(_, event_id)| event_id).collect())
+	}
+
+	async fn get_finalized_block_indexed_events(
+		&self,
+		block_num: u32,
+	) -> Result<Vec<(u32, H256)>, Error> {
+		let blocks = self.blocks.lock()?;
+		let events = blocks.get(block_num as usize).cloned();
+		events.ok_or_else(|| Error::Other(format!("block {block_num} is not finalized")))
+	}
+
+	async fn get_latest_finalized_block(&self) -> Result<u32, Error> {
+		Ok(self.blocks.lock()?.len() as u32 - 1)
+	}
+}
+
+#[tokio::test]
+async fn test_indexer_catches_up_with_finality() {
+	let chain = Arc::new(ScriptedChain::default());
+	for extrinsics in [&[][..], &[1], &[]] {
+		chain.finalize(extrinsics);
+	}
+	// Resumed from the last indexed block
+	let index = Arc::new(in_memory_index());
+	index.add_block_events(0, &[]).unwrap();
+
+	let (notify, notifications) = mpsc::unbounded::<()>();
+	let indexer = tokio::spawn(index_finalized_events(
+		index.clone(),
+		chain.clone(),
+		notifications,
+		Heartbeats::default().register("index"),
+	));
+	chain.finalize(&[1, 2]);
+	chain.finalize(&[]);
+	notify.unbounded_send(()).unwrap();
+	drop(notify);
+	indexer.await.unwrap();
+
+	assert_eq!(index.bounds(), Ok(Some((0, 4))));
+	let expected = [block_events(1, &[1]), block_events(3, &[1, 2])].concat();
+	let (events, _) = list_all(index.as_ref(), 0, 4, 10);
+	let listed: Vec<_> =
+		events.iter().map(|event| (event.extrinsic_index, event.event_id)).collect();
+	assert_eq!(listed, expected);
+	let blocks: Vec<_> = events.iter().map(|event| event.block_number).collect();
+	assert_eq!(blocks, [1, 3, 3]);
+}
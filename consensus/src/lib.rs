@@ -4,18 +4,48 @@
 //! create on-chain applications that reactively source data from off-chain applications, while
 //! requiring confirmation of the occurrence of off-chain events from at least two-thirds of
 //! validators. See the README file (at <https://github.com/comrade-coop/validated-streams>) for more details on the architecture.
+//!
+//! ## Locking
+//! The gossip swarm is owned by the task running the [gossip::GossipService], and everything else
+//! talks to it through a channel; it hands messages over to the workers of the handler through the
+//! [gossip::ingress] queues. The remaining shared state (the validator sets, the pending and
+//! submitted events, the in-memory proofs, and the bookkeeping of the metrics and traces) sits
+//! behind `std::sync::Mutex`es, or an `RwLock` for the latest validator set, held only for short,
+//! synchronous sections: no guard is ever held across an `.await`. The only nesting is the proof
+//! count of an event being read under the lock of the pending events; the proofs never take that
+//! lock in turn.
 
 #![feature(async_closure)]
 #![warn(missing_docs)]
+pub mod auth;
 pub mod block_import;
 pub mod config;
 pub mod errors;
 pub mod events;
+pub mod executor;
 pub mod gossip;
+pub mod health;
+pub mod index;
+pub mod limits;
+pub mod logging;
+pub mod metrics;
 pub mod node;
+pub mod notifications;
+pub mod payloads;
 pub mod proofs;
+pub mod role;
 pub mod server;
+pub mod shutdown;
+pub mod startup;
+pub mod status;
+pub mod streams;
+pub mod telemetry;
+pub mod traces;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 pub mod traits;
+pub mod tunables;
+pub mod watchdog;
 
 #[cfg(feature = "off-chain-proofs")]
 pub use block_import::ValidatedStreamsBlockImport;
@@ -24,4 +54,4 @@ pub use config::{ValidatedStreamsNetworkConfiguration, ValidatedStreamsNetworkPa
 
 pub use node::{start, StartParams};
 
-pub use events::BlockStateCache;
+pub use events::ValidatorSetHandle;
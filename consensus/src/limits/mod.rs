@@ -0,0 +1,228 @@
+//! Limits on the trusted clients of the gRPC server, so that a misbehaving one cannot starve the
+//! others, nor the witnessing pipeline. With `--grpc-client-rate-limit`, each client gets that many
+//! requests to the `Streams` service per second, on top of the limit of all witnessing requests;
+//! clients are told apart by their API key if they present one, and by their IP address otherwise.
+//! With `--grpc-max-connections`, the server holds back new connections while that many are open,
+//! leaving them in the backlog of the listener until another one closes.
+//!
+//! So that long-lived streaming clients are not silently dropped by the NATs and load balancers in
+//! between, `--grpc-keepalive-interval` has the server ping its clients over HTTP/2 that often,
+//! closing the connections of those not answering within `--grpc-keepalive-timeout`. With
+//! `--grpc-max-connection-age`, connections are closed once that old, ending the calls still open
+//! on them, so that clients reconnect, e.g. to pick up another node behind a load balancer.
+
+use crate::{auth::AUTHORIZATION, logging::GRPC};
+use futures::{stream, Future, Stream};
+use lru::LruCache;
+use sp_core::hashing::blake2_256;
+use std::{
+	io,
+	net::IpAddr,
+	num::NonZeroUsize,
+	pin::Pin,
+	sync::{Arc, Mutex},
+	task::{Context, Poll},
+	time::Duration,
+};
+use tokio::{
+	io::{AsyncRead, AsyncWrite, ReadBuf},
+	net::{TcpListener, TcpStream},
+	sync::{OwnedSemaphorePermit, Semaphore},
+	time::{sleep, Instant, Sleep},
+};
+use tonic::{
+	service::Interceptor,
+	transport::server::{Connected, TcpConnectInfo},
+	Request, Status,
+};
+
+#[cfg(test)]
+pub mod tests;
+
+/// How many clients the rate limits are kept for; the least recent ones start over.
+pub const TRACKED_CLIENTS: usize = 4096;
+/// How long the server waits before accepting connections again after failing to.
+pub const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The limits on the clients of the gRPC server, and on their connections, 0 for none.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClientLimits {
+	/// The requests to the `Streams` service each client may make per second
+	pub rate_limit: u32,
+	/// The connections open at once
+	pub max_connections: usize,
+	/// How often clients are pinged over HTTP/2
+	pub keepalive_interval: Duration,
+	/// How long the server waits for clients to answer a ping before closing their connection; 0
+	/// for the default of tonic, 20 seconds
+	pub keepalive_timeout: Duration,
+	/// How long a connection is kept open
+	pub max_connection_age: Duration,
+}
+
+impl ClientLimits {
+	/// The interval and the timeout of the HTTP/2 pings, as the server builder takes them.
+	pub fn keepalive(&self) -> (Option<Duration>, Option<Duration>) {
+		let nonzero = |duration: Duration| (!duration.is_zero()).then_some(duration);
+		(nonzero(self.keepalive_interval), nonzero(self.keepalive_timeout))
+	}
+}
+
+/// A client, as told apart by the rate limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum ClientId {
+	/// The blake2-256 hash of the API key the client presents
+	Key([u8; 32]),
+	/// The IP address the client connects from
+	Address(IpAddr),
+	/// Clients with neither, as when not connected over TCP
+	Unknown,
+}
+
+impl ClientId {
+	fn of<T>(request: &Request<T>) -> Self {
+		let authorization = request.metadata().get(AUTHORIZATION).map(|value| value.to_str());
+		match (authorization, request.remote_addr()) {
+			(Some(Ok(value)), _) => Self::Key(blake2_256(value.trim().as_bytes())),
+			(_, Some(address)) => Self::Address(address.ip()),
+			_ => Self::Unknown,
+		}
+	}
+}
+
+/// Refuses the requests of the clients exceeding the rate limit, with `RESOURCE_EXHAUSTED`.
+/// Cloning it gives another handle to the same limits.
+#[derive(Clone)]
+pub struct ClientRateLimiter {
+	limit: u32,
+	/// The token bucket of each client
+	buckets: Arc<Mutex<LruCache<ClientId, (f64, Instant)>>>,
+}
+
+impl ClientRateLimiter {
+	/// Creates a new rate limiter, letting each client make `limit` requests per second, or any
+	/// number of them if 0.
+	pub fn new(limit: u32) -> Self {
+		let capacity = NonZeroUsize::new(TRACKED_CLIENTS).expect("capacity is not zero");
+		Self { limit, buckets: Arc::new(Mutex::new(LruCache::new(capacity))) }
+	}
+
+	/// Takes a token from the bucket of the client, if there is one left.
+	fn take_token(&self, client: ClientId) -> bool {
+		if self.limit == 0 {
+			return true
+		}
+		let (limit, now) = (self.limit as f64, Instant::now());
+		let mut buckets = self.buckets.lock().unwrap();
+		// Starting with a full second's worth of requests
+		if !buckets.contains(&client) {
+			buckets.put(client, (limit, now));
+		}
+		let (tokens, updated) = buckets.get_mut(&client).expect("just put");
+		let elapsed = now.saturating_duration_since(*updated).as_secs_f64();
+		*tokens = (*tokens + elapsed * limit).min(limit);
+		*updated = now;
+		if *tokens < 1.0 {
+			return false
+		}
+		*tokens -= 1.0;
+		true
+	}
+}
+
+impl Interceptor for ClientRateLimiter {
+	fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+		let client = ClientId::of(&request);
+		if self.take_token(client) {
+			return Ok(request)
+		}
+		tracing::debug!(target: GRPC, ?client, limit = self.limit, "Client rate limited");
+		Err(Status::resource_exhausted(format!(
+			"rate limit of {} requests per second per client exceeded",
+			self.limit
+		)))
+	}
+}
+
+/// A connection accepted by [incoming], holding its place among the connections open at once, and
+/// reading as ended once past its maximum age, if any.
+pub struct LimitedConnection {
+	stream: TcpStream,
+	_permit: Option<OwnedSemaphorePermit>,
+	expiry: Option<Pin<Box<Sleep>>>,
+}
+
+/// The connections accepted on the listener, holding back further ones while `max_connections`
+/// are open, and closing them once `max_age` old, if not 0. Failures to accept are retried after
+/// [ACCEPT_BACKOFF] rather than ending the stream, as they are usually temporary, e.g. when out of
+/// file descriptors.
+pub fn incoming(
+	listener: TcpListener,
+	max_connections: usize,
+	max_age: Duration,
+) -> impl Stream<Item = io::Result<LimitedConnection>> {
+	let permits = (max_connections > 0).then(|| Arc::new(Semaphore::new(max_connections)));
+	stream::unfold((listener, permits), |(listener, permits)| async move {
+		let permit = match &permits {
+			// The semaphore is never closed
+			Some(permits) => Some(permits.clone().acquire_owned().await.expect("not closed")),
+			None => None,
+		};
+		loop {
+			match listener.accept().await {
+				Ok((stream, _)) => {
+					stream.set_nodelay(true).ok();
+					let expiry = (!max_age.is_zero()).then(|| Box::pin(sleep(max_age)));
+					let connection = LimitedConnection { stream, _permit: permit, expiry };
+					return Some((Ok(connection), (listener, permits)))
+				},
+				Err(e) => {
+					tracing::warn!(target: GRPC, error = %e, "Failed accepting a connection");
+					sleep(ACCEPT_BACKOFF).await;
+				},
+			}
+		}
+	})
+}
+
+impl Connected for LimitedConnection {
+	type ConnectInfo = TcpConnectInfo;
+
+	fn connect_info(&self) -> Self::ConnectInfo {
+		self.stream.connect_info()
+	}
+}
+
+impl AsyncRead for LimitedConnection {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		// Read as ended by the client, so that the server closes the connection
+		if let Some(expiry) = &mut self.expiry {
+			if expiry.as_mut().poll(cx).is_ready() {
+				return Poll::Ready(Ok(()))
+			}
+		}
+		Pin::new(&mut self.stream).poll_read(cx, buf)
+	}
+}
+
+impl AsyncWrite for LimitedConnection {
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		Pin::new(&mut self.stream).poll_write(cx, buf)
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Pin::new(&mut self.stream).poll_flush(cx)
+	}
+
+	fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Pin::new(&mut self.stream).poll_shutdown(cx)
+	}
+}
@@ -0,0 +1,84 @@
+use super::{incoming, ClientRateLimiter};
+use crate::auth::AUTHORIZATION;
+use futures::{FutureExt, StreamExt};
+use std::time::Duration;
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::{TcpListener, TcpStream},
+};
+use tonic::{service::Interceptor, Code, Request};
+
+/// Passes a request with the given `authorization` header, if any, through the rate limiter.
+fn intercept(limiter: &mut ClientRateLimiter, authorization: Option<&str>) -> Result<(), Code> {
+	let mut request = Request::new(());
+	if let Some(authorization) = authorization {
+		request.metadata_mut().insert(AUTHORIZATION, authorization.parse().unwrap());
+	}
+	limiter.call(request).map(|_| ()).map_err(|status| status.code())
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_clients_rate_limited_apart() {
+	let mut limiter = ClientRateLimiter::new(4);
+	for _ in 0..4 {
+		assert_eq!(intercept(&mut limiter, Some("Bearer first")), Ok(()));
+	}
+	assert_eq!(intercept(&mut limiter, Some("Bearer first")), Err(Code::ResourceExhausted));
+	// Other keys, and clients without any, have buckets of their own
+	assert_eq!(intercept(&mut limiter, Some("Bearer second")), Ok(()));
+	assert_eq!(intercept(&mut limiter, None), Ok(()));
+
+	// Refilled at the rate of the limit, up to a second's worth
+	tokio::time::advance(Duration::from_millis(250)).await;
+	assert_eq!(intercept(&mut limiter, Some("Bearer first")), Ok(()));
+	assert_eq!(intercept(&mut limiter, Some("Bearer first")), Err(Code::ResourceExhausted));
+	tokio::time::advance(Duration::from_secs(10)).await;
+	let mut clone = limiter.clone();
+	for _ in 0..4 {
+		assert_eq!(intercept(&mut clone, Some("Bearer first")), Ok(()));
+	}
+	assert_eq!(intercept(&mut limiter, Some("Bearer first")), Err(Code::ResourceExhausted));
+}
+
+#[test]
+fn test_no_rate_limit() {
+	let mut limiter = ClientRateLimiter::new(0);
+	for _ in 0..1000 {
+		assert_eq!(intercept(&mut limiter, None), Ok(()));
+	}
+}
+
+#[tokio::test]
+async fn test_connections_held_back_at_the_limit() {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let address = listener.local_addr().unwrap();
+	let mut connections = Box::pin(incoming(listener, 2, Duration::ZERO));
+
+	let _clients = [TcpStream::connect(address).await, TcpStream::connect(address).await];
+	let first = connections.next().await.unwrap().unwrap();
+	let _second = connections.next().await.unwrap().unwrap();
+	let _third = TcpStream::connect(address).await.unwrap();
+	tokio::time::sleep(Duration::from_millis(100)).await;
+	assert!(connections.next().now_or_never().is_none());
+
+	drop(first);
+	let third = tokio::time::timeout(Duration::from_secs(5), connections.next()).await;
+	assert!(matches!(third, Ok(Some(Ok(_)))));
+}
+
+#[tokio::test]
+async fn test_connections_closed_at_their_maximum_age() {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let address = listener.local_addr().unwrap();
+	let mut connections = Box::pin(incoming(listener, 0, Duration::from_millis(200)));
+
+	let mut client = TcpStream::connect(address).await.unwrap();
+	let mut connection = connections.next().await.unwrap().unwrap();
+	client.write_all(b"ping").await.unwrap();
+	let mut buf = [0; 4];
+	connection.read_exact(&mut buf).await.unwrap();
+	assert_eq!(&buf, b"ping");
+	// Read as ended once old enough, while the client still has it open
+	let read = tokio::time::timeout(Duration::from_secs(5), connection.read(&mut buf)).await;
+	assert!(matches!(read, Ok(Ok(0))));
+}
@@ -0,0 +1,23 @@
+//! Log targets of the Validated Streams subsystem. All of them are under `validated_streams`, so
+//! `-l validated_streams=debug` enables the debug logs of the whole subsystem and nothing else.
+//!
+//! Log lines and spans about a particular event carry an `event_id` field, formatted as the short
+//! hex of the id (`0x1234…cdef`), and those about a particular gossip peer carry a `peer` field.
+//!
+//! Lines which a misbehaving peer can trigger at will go through a [LogRateLimiter].
+
+pub mod rate_limit;
+#[cfg(test)]
+pub mod tests;
+
+pub(crate) use rate_limit::rate_limited;
+pub use rate_limit::{LogRateLimiter, Suppressed};
+
+/// The gRPC server and the requests of the trusted client.
+pub const GRPC: &str = "validated_streams::grpc";
+/// The gossip network.
+pub const GOSSIP: &str = "validated_streams::gossip";
+/// Witnessing events and collecting the witnesses of other validators.
+pub const SERVICE: &str = "validated_streams::service";
+/// Storing and checking event proofs.
+pub const PROOFS: &str = "validated_streams::proofs";
@@ -0,0 +1,112 @@
+//! A token bucket per call site, for log lines which can fire many times a second
+
+use crate::metrics::Metrics;
+use std::{
+	collections::BTreeMap,
+	fmt,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+/// How many lines of a call site are logged in a burst, by default.
+pub const DEFAULT_BURST: u32 = 1;
+/// How often a call site gets to log another line once its burst is spent, by default.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Limits how often the log lines of each call site are emitted. Lines past the limit are counted
+/// in the metrics and summarized by the next line of the same call site which gets logged. Used
+/// through the `rate_limited!` macro.
+pub struct LogRateLimiter {
+	burst: u32,
+	interval: Duration,
+	metrics: Metrics,
+	buckets: Mutex<BTreeMap<&'static str, Bucket>>,
+}
+
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+	suppressed: u64,
+	suppressed_since: Option<Instant>,
+}
+
+/// The lines of a call site suppressed since its last logged line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Suppressed {
+	/// How many lines were suppressed.
+	pub count: u64,
+	/// How long ago the first of them was suppressed.
+	pub period: Duration,
+}
+
+impl fmt::Display for Suppressed {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let (count, seconds) = (self.count, self.period.as_secs());
+		write!(f, "Suppressed {count} similar messages in the last {seconds}s")
+	}
+}
+
+impl LogRateLimiter {
+	/// Creates a limiter with the [DEFAULT_BURST] and [DEFAULT_INTERVAL].
+	pub fn new(metrics: Metrics) -> Self {
+		Self::with_limits(DEFAULT_BURST, DEFAULT_INTERVAL, metrics)
+	}
+
+	/// Creates a limiter which logs `burst` lines of each call site at once, and then one more
+	/// every `interval`.
+	pub fn with_limits(burst: u32, interval: Duration, metrics: Metrics) -> Self {
+		Self { burst, interval, metrics, buckets: Mutex::new(BTreeMap::new()) }
+	}
+
+	/// Takes a token of the call site with the given key. Returns the lines suppressed since the
+	/// last one if the line should be logged, or [None] if it should be suppressed.
+	pub fn check(&self, key: &'static str) -> Option<Suppressed> {
+		self.check_at(key, Instant::now())
+	}
+
+	/// Like [LogRateLimiter::check], for a line at `now`.
+	pub(crate) fn check_at(&self, key: &'static str, now: Instant) -> Option<Suppressed> {
+		let mut buckets = self.buckets.lock().unwrap();
+		let burst = self.burst as f64;
+		let bucket = buckets.entry(key).or_insert(Bucket {
+			tokens: burst,
+			updated: now,
+			suppressed: 0,
+			suppressed_since: None,
+		});
+		let elapsed = now.saturating_duration_since(bucket.updated);
+		bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() / self.interval.as_secs_f64())
+			.min(burst);
+		bucket.updated = now;
+
+		if bucket.tokens < 1.0 {
+			bucket.suppressed += 1;
+			bucket.suppressed_since.get_or_insert(now);
+			self.metrics.on_log_suppressed(key);
+			return None
+		}
+		bucket.tokens -= 1.0;
+		let since = bucket.suppressed_since.take().unwrap_or(now);
+		Some(Suppressed {
+			count: std::mem::take(&mut bucket.suppressed),
+			period: now.saturating_duration_since(since),
+		})
+	}
+}
+
+/// Logs a line through a [LogRateLimiter] under the given call site key, preceded by a summary of
+/// the lines suppressed since the previous one, if any:
+/// ```ignore
+/// rate_limited!(self.log_limiter, "rejected_witness", debug, target: SERVICE, "Rejected");
+/// ```
+macro_rules! rate_limited {
+	($limiter:expr, $key:literal, $level:ident, target: $target:expr, $($line:tt)+) => {
+		if let Some(suppressed) = $limiter.check($key) {
+			if suppressed.count > 0 {
+				tracing::$level!(target: $target, key = $key, "{}", suppressed);
+			}
+			tracing::$level!(target: $target, $($line)+);
+		}
+	};
+}
+pub(crate) use rate_limited;
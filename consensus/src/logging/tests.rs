@@ -0,0 +1,171 @@
+use super::{rate_limited, LogRateLimiter, Suppressed, GRPC, SERVICE};
+use crate::{
+	events::{EventWitnesser, WITNESSED_EVENTS_TOPIC},
+	gossip::GossipTrait,
+	metrics::Metrics,
+	server::{
+		validated_streams_proto::{streams_server::Streams, WitnessEventRequest},
+		ValidatedStreamsGrpc,
+	},
+	test_utils::{
+		validator_set, CapturedLogs, FakeChain, SimulatedGossip, SimulatedNetwork, SimulatedNode,
+		TestGrpc, TestValidators,
+	},
+	traces::Traces,
+};
+use libp2p::gossipsub::IdentTopic;
+use prometheus_endpoint::Registry;
+use sp_core::H256;
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tonic::Request;
+
+/// A gRPC service witnessing events with the keys of the first validator, on a chain with the
+/// given authorities.
+fn grpc(
+	validators: &TestValidators,
+	authorities: Vec<CryptoTypePublicPair>,
+	gossip: SimulatedGossip,
+) -> TestGrpc {
+	ValidatedStreamsGrpc::for_tests(EventWitnesser::new(
+		Arc::new(FakeChain::new(authorities)),
+		gossip,
+		validators.keystore(0),
+		validator_set(),
+		Metrics::default(),
+		Traces::default(),
+	))
+}
+
+fn network(validators: &TestValidators) -> SimulatedNetwork<SimulatedNode> {
+	let nodes = (0..validators.len())
+		.map(|_| Arc::new(SimulatedNode::new(validators.authorities())))
+		.collect();
+	SimulatedNetwork::new(0, nodes)
+}
+
+#[tokio::test]
+async fn test_client_request_carries_event_id() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let validators = TestValidators::new(4);
+	let network = network(&validators);
+	let grpc = grpc(&validators, validators.pubkeys(), network.gossip(0));
+	let event_id = H256::repeat_byte(1);
+
+	let request = WitnessEventRequest { event_id: event_id.0.to_vec(), ..Default::default() };
+	grpc.witness_event(Request::new(request)).await.unwrap();
+
+	for (target, message) in
+		[(GRPC, "Received event from the client"), (SERVICE, "Published witnessed event")]
+	{
+		let lines = logs.find(target, message);
+		assert_eq!(lines.len(), 1, "{message}");
+		assert_eq!(lines[0].field("event_id"), Some("0x0101…0101"), "{message}");
+		assert!(lines[0].in_span("handle_client_request"), "{message}");
+	}
+}
+
+#[tokio::test]
+async fn test_failed_client_request_is_logged() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let validators = TestValidators::new(1);
+	let network = network(&validators);
+	// The only validator was removed from the authorities
+	let grpc = grpc(&validators, vec![], network.gossip(0));
+
+	let request = WitnessEventRequest { event_id: vec![2; 32], ..Default::default() };
+	assert!(grpc.witness_event(Request::new(request)).await.is_err());
+
+	let lines = logs.find(GRPC, "Failed witnessing event");
+	assert_eq!(lines.len(), 1);
+	assert_eq!(lines[0].field("event_id"), Some("0x0202…0202"));
+	assert!(lines[0].field("error").is_some());
+	assert!(logs.find(SERVICE, "Published witnessed event").is_empty());
+}
+
+#[tokio::test]
+async fn test_collected_proofs_carry_event_id() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let validators = TestValidators::new(4);
+	let network = network(&validators);
+	let event_id = H256::repeat_byte(3);
+	let topic = IdentTopic::new(WITNESSED_EVENTS_TOPIC);
+
+	for i in 0..validators.len() {
+		let message = validators.witness(i, event_id).build().to_bytes().unwrap();
+		network.gossip(i).publish(topic.clone(), message).await.unwrap();
+	}
+	let garbage = validators.witness(0, event_id).corrupt_signature().build();
+	network.gossip(0).publish(topic, garbage.to_bytes().unwrap()).await.unwrap();
+	network.run_until_idle().await;
+
+	// Every node adds the first two proofs it receives, then reaches the target with the third
+	let added = logs.find(SERVICE, "Added a proof of the event");
+	assert_eq!(added.len(), 2 * validators.len());
+	let witnessed = logs.find(SERVICE, "Event was witnessed by enough validators");
+	assert_eq!(witnessed.len(), validators.len());
+	for line in added.iter().chain(&witnessed) {
+		assert_eq!(line.field("event_id"), Some("0x0303…0303"));
+		assert!(line.field("proof_count").is_some());
+	}
+	let rejected = logs.find(SERVICE, "Rejected witnessed event");
+	assert_eq!(rejected.len(), validators.len());
+	assert!(rejected.iter().all(|line| line.field("error").is_some()));
+}
+
+#[test]
+fn test_rate_limiter_suppresses_bursts() {
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let limiter = LogRateLimiter::with_limits(2, Duration::from_secs(10), metrics);
+	let start = Instant::now();
+	let at = |millis| start + Duration::from_millis(millis);
+
+	// The burst is logged, the rest of the flood is not
+	let logged = (0..1000).filter(|&i| limiter.check_at("flood", at(i)).is_some()).count();
+	assert_eq!(logged, 2);
+	// Other call sites have buckets of their own
+	let nothing_suppressed = Suppressed { count: 0, period: Duration::ZERO };
+	assert_eq!(limiter.check_at("other", at(1000)), Some(nothing_suppressed));
+
+	// A token comes back every interval, and its line carries the summary of the suppressed ones
+	assert_eq!(limiter.check_at("flood", at(9_000)), None);
+	let summary = limiter.check_at("flood", at(11_000));
+	assert_eq!(summary, Some(Suppressed { count: 999, period: Duration::from_millis(10_998) }));
+	assert_eq!(summary.unwrap().to_string(), "Suppressed 999 similar messages in the last 10s");
+	assert_eq!(limiter.check_at("flood", at(11_001)), None);
+
+	let suppressed = registry
+		.gather()
+		.into_iter()
+		.find(|family| family.get_name() == "streams_suppressed_logs_total")
+		.map(|family| family.get_metric()[0].get_counter().get_value());
+	assert_eq!(suppressed, Some(1000.0));
+}
+
+#[test]
+fn test_rate_limited_logs_summaries() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let limiter = LogRateLimiter::with_limits(1, Duration::from_millis(50), Metrics::default());
+	let log = |i: usize| rate_limited!(limiter, "burst", warn, target: SERVICE, i, "Burst line");
+
+	for i in 0..100 {
+		log(i);
+	}
+	std::thread::sleep(Duration::from_millis(60));
+	log(100);
+
+	let lines = logs.find(SERVICE, "Burst line");
+	let numbers: Vec<_> = lines.iter().map(|line| line.field("i").unwrap().to_string()).collect();
+	assert_eq!(numbers, ["0", "100"]);
+	let summaries: Vec<_> = logs
+		.events()
+		.into_iter()
+		.filter(|line| line.message().starts_with("Suppressed 99 similar messages"))
+		.collect();
+	assert_eq!(summaries.len(), 1);
+	assert_eq!(summaries[0].field("key"), Some("burst"));
+}
@@ -0,0 +1,788 @@
+//! Prometheus metrics of the Validated Streams subsystem. All metrics are prefixed with `streams_`
+//! (after the prefix of the node's registry, if any).
+
+use crate::{
+	gossip::ingress::IngressDrop,
+	logging::SERVICE,
+	traits::ChainAccess,
+	watchdog::{next_beating, Heartbeat},
+};
+use lru::LruCache;
+use prometheus_endpoint::{
+	prometheus::core::Collector, register, Counter, CounterVec, Gauge, GaugeVec, Histogram,
+	HistogramOpts, HistogramVec, Opts, PrometheusError, Registry, F64, U64,
+};
+use sc_client_api::{BlockBackend, BlockchainEvents};
+use sp_api::BlockT;
+use sp_core::H256;
+use std::{
+	collections::HashMap,
+	num::NonZeroUsize,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+#[cfg(test)]
+pub mod tests;
+
+/// How long after being submitted by the trusted client an event which is still not finalized is
+/// considered expired (and is no longer waited for).
+pub const EVENT_EXPIRY: Duration = Duration::from_secs(600);
+
+/// The buckets of the end-to-end latency histogram, in seconds.
+const LATENCY_BUCKETS: [f64; 14] =
+	[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1200.0];
+
+/// The buckets of the witness delay histogram, in seconds.
+const DELAY_BUCKETS: [f64; 12] =
+	[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// The buckets of the histograms timing single calls, in seconds.
+const CALL_BUCKETS: [f64; 10] =
+	[0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1];
+
+/// Validators past this index share the `other` label of the per-validator metrics, keeping their
+/// cardinality bounded regardless of the size of the validator set.
+pub const MAX_LABELED_VALIDATORS: usize = 64;
+
+/// How many events the time their first witness was received is kept for.
+const FIRST_SEEN_CAPACITY: usize = 4096;
+
+/// How many events whose extrinsic could not be submitted are remembered, to tell retries apart.
+const FAILED_SUBMISSIONS_CAPACITY: usize = 4096;
+
+/// The outcome of submitting the extrinsic of an event to the transaction pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubmissionOutcome {
+	/// The pool accepted the extrinsic.
+	Submitted,
+	/// The extrinsic is already in the pool, or the event is already on chain.
+	AlreadyImported,
+	/// The pool dropped the extrinsic, e.g. because it is full or the extrinsic is banned.
+	Dropped,
+	/// The extrinsic's mortality period is over.
+	MortalityExpired,
+	/// The extrinsic was rejected for any other reason.
+	Invalid,
+}
+
+impl SubmissionOutcome {
+	fn label(self) -> &'static str {
+		match self {
+			Self::Submitted => "submitted",
+			Self::AlreadyImported => "already_imported",
+			Self::Dropped => "dropped",
+			Self::MortalityExpired => "mortality_expired",
+			Self::Invalid => "invalid",
+		}
+	}
+
+	/// Whether the extrinsic of the event is (or once was) in the pool.
+	fn is_success(self) -> bool {
+		matches!(self, Self::Submitted | Self::AlreadyImported)
+	}
+}
+
+/// The counts of events in each stage and of gossip peers, for status reports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatusCounts {
+	/// Events with proofs which have not yet reached the target number of them.
+	pub pending: u64,
+	/// Events which reached the target and whose extrinsic is in the pool, but not yet in a block.
+	pub at_quorum: u64,
+	/// Events finalized since the node started.
+	pub finalized: u64,
+	/// Peers connected to the gossip.
+	pub peers: u64,
+}
+
+/// A summary of the witnesses received from one validator, for per-validator statistics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidatorWitnessStats {
+	/// The index of the validator in the validator set, or `other` for those past
+	/// [MAX_LABELED_VALIDATORS].
+	pub validator: String,
+	/// The number of witnesses received from the validator.
+	pub witnesses: u64,
+	/// The mean delay between the first witness of an event and the validator's witness of it.
+	pub mean_delay: Duration,
+}
+
+/// A handle for updating the metrics of the subsystem. Cheap to clone; the [Default] handle is not
+/// registered anywhere and ignores all updates, for nodes without metrics and for tests.
+#[derive(Clone, Default)]
+pub struct Metrics {
+	inner: Option<Arc<Inner>>,
+}
+
+struct Inner {
+	client_requests: CounterVec<U64>,
+	witnesses_sent: Counter<U64>,
+	witnesses_received: Counter<U64>,
+	witnesses_rejected: Counter<U64>,
+	witnesses_duplicate: Counter<U64>,
+	pending_events: Gauge<U64>,
+	gossip_peers: Gauge<U64>,
+	gossip_restarts: Counter<U64>,
+	gossip_publish_refused: Counter<U64>,
+	gossip_ingress_queued: Gauge<U64>,
+	gossip_ingress_dropped: CounterVec<U64>,
+	gossip_incompatible: CounterVec<U64>,
+	mesh_health: Gauge<F64>,
+	mesh_degraded: Gauge<U64>,
+	event_latency: HistogramVec,
+	events_finalized: Counter<U64>,
+	validator_witnesses: CounterVec<U64>,
+	validator_equivocations: CounterVec<U64>,
+	witness_delay: HistogramVec,
+	gossip_publish: Histogram,
+	extrinsic_submissions: CounterVec<U64>,
+	extrinsic_submission_duration: Histogram,
+	awaiting_inclusion_count: Gauge<U64>,
+	suppressed_logs: CounterVec<U64>,
+	task_heartbeat_age: GaugeVec<F64>,
+	node_role: GaugeVec<U64>,
+	role_transitions: CounterVec<U64>,
+	validator_online: GaugeVec<U64>,
+	validator_witnessed: GaugeVec<U64>,
+	/// When each event submitted by the trusted client and not yet finalized was received
+	submitted: Mutex<HashMap<H256, Instant>>,
+	/// When the first witness of each recent event was received
+	first_seen: Mutex<LruCache<H256, Instant>>,
+	/// When the extrinsic of each event submitted to the pool and not yet in a block was submitted
+	awaiting_inclusion: Mutex<HashMap<H256, Instant>>,
+	/// Events whose last submission failed
+	failed_submissions: Mutex<LruCache<H256, ()>>,
+}
+
+impl Metrics {
+	/// Registers the metrics with the given registry, or returns no-op metrics if there is none.
+	pub fn register(registry: Option<&Registry>) -> Result<Self, PrometheusError> {
+		let Some(registry) = registry else { return Ok(Self::default()) };
+		let inner = Inner {
+			client_requests: register(
+				CounterVec::new(
+					Opts::new(
+						"streams_client_requests_total",
+						"Requests of trusted clients, by method and outcome",
+					),
+					&["method", "outcome"],
+				)?,
+				registry,
+			)?,
+			witnesses_sent: register(
+				Counter::new("streams_witnesses_sent_total", "Witnessed events we have signed")?,
+				registry,
+			)?,
+			witnesses_received: register(
+				Counter::new(
+					"streams_witnesses_received_total",
+					"Witnessed events received through gossip, including our own",
+				)?,
+				registry,
+			)?,
+			witnesses_rejected: register(
+				Counter::new(
+					"streams_witnesses_rejected_total",
+					"Received witnessed events which failed decoding or verification",
+				)?,
+				registry,
+			)?,
+			witnesses_duplicate: register(
+				Counter::new(
+					"streams_witnesses_duplicate_total",
+					"Received copies of witnessed events collected recently, dropped unverified",
+				)?,
+				registry,
+			)?,
+			pending_events: register(
+				Gauge::new(
+					"streams_pending_events",
+					"Events with proofs which have not yet reached the target number of them",
+				)?,
+				registry,
+			)?,
+			gossip_peers: register(
+				Gauge::new("streams_gossip_peers", "Peers connected to the gossip")?,
+				registry,
+			)?,
+			gossip_restarts: register(
+				Counter::new(
+					"streams_gossip_restarts_total",
+					"Times the gossip was restarted after its event loop failed",
+				)?,
+				registry,
+			)?,
+			gossip_publish_refused: register(
+				Counter::new(
+					"streams_gossip_publish_refused_total",
+					"Messages not published as the gossip order queue was full, refused or dropped",
+				)?,
+				registry,
+			)?,
+			gossip_ingress_queued: register(
+				Gauge::new(
+					"streams_gossip_ingress_queued",
+					"Gossip messages queued for the handler, received or published",
+				)?,
+				registry,
+			)?,
+			gossip_ingress_dropped: register(
+				CounterVec::new(
+					Opts::new(
+						"streams_gossip_ingress_dropped_total",
+						"Received gossip messages dropped before being handled, by reason",
+					),
+					&["reason"],
+				)?,
+				registry,
+			)?,
+			gossip_incompatible: register(
+				CounterVec::new(
+					Opts::new(
+						"streams_gossip_incompatible_total",
+						"Gossip peers disconnected and witnesses ignored for being of an \
+						 incompatible protocol version, by kind",
+					),
+					&["kind"],
+				)?,
+				registry,
+			)?,
+			mesh_health: register(
+				Gauge::new(
+					"streams_mesh_health",
+					"Handshaked gossip peers over the number needed to reach quorum, capped at 1",
+				)?,
+				registry,
+			)?,
+			mesh_degraded: register(
+				Gauge::new(
+					"streams_mesh_degraded",
+					"Whether there are too few gossip peers for our events to ever reach quorum",
+				)?,
+				registry,
+			)?,
+			event_latency: register(
+				HistogramVec::new(
+					HistogramOpts::new(
+						"streams_event_latency_seconds",
+						"Time from the trusted client submitting an event until it is finalized or \
+						 expires, by outcome",
+					)
+					.buckets(LATENCY_BUCKETS.to_vec()),
+					&["outcome"],
+				)?,
+				registry,
+			)?,
+			events_finalized: register(
+				Counter::new(
+					"streams_events_finalized_total",
+					"Events found in finalized blocks since the node started",
+				)?,
+				registry,
+			)?,
+			validator_witnesses: register(
+				CounterVec::new(
+					Opts::new(
+						"streams_validator_witnesses_total",
+						"Verified witnesses received through gossip, by validator index",
+					),
+					&["validator"],
+				)?,
+				registry,
+			)?,
+			validator_equivocations: register(
+				CounterVec::new(
+					Opts::new(
+						"streams_validator_equivocations_total",
+						"Witnesses of an event conflicting with the one held from the same \
+						 validator, by validator index",
+					),
+					&["validator"],
+				)?,
+				registry,
+			)?,
+			witness_delay: register(
+				HistogramVec::new(
+					HistogramOpts::new(
+						"streams_witness_delay_seconds",
+						"Time from receiving the first witness of an event until receiving the \
+						 witness of each validator, by validator index",
+					)
+					.buckets(DELAY_BUCKETS.to_vec()),
+					&["validator"],
+				)?,
+				registry,
+			)?,
+			gossip_publish: register(
+				Histogram::with_opts(
+					HistogramOpts::new(
+						"streams_gossip_publish_seconds",
+						"Time from ordering the gossip to publish a message until gossipsub \
+						 accepts it",
+					)
+					.buckets(CALL_BUCKETS.to_vec()),
+				)?,
+				registry,
+			)?,
+			extrinsic_submissions: register(
+				CounterVec::new(
+					Opts::new(
+						"streams_extrinsic_submissions_total",
+						"Submissions of event extrinsics to the transaction pool, by outcome; \
+						 `retried` counts the submissions following a failed one, on top of their \
+						 own outcome",
+					),
+					&["outcome"],
+				)?,
+				registry,
+			)?,
+			extrinsic_submission_duration: register(
+				Histogram::with_opts(
+					HistogramOpts::new(
+						"streams_extrinsic_submission_seconds",
+						"Time taken by the transaction pool to accept or reject an event extrinsic",
+					)
+					.buckets(CALL_BUCKETS.to_vec()),
+				)?,
+				registry,
+			)?,
+			awaiting_inclusion_count: register(
+				Gauge::new(
+					"streams_extrinsics_awaiting_inclusion",
+					"Event extrinsics in the transaction pool which are not yet in a block",
+				)?,
+				registry,
+			)?,
+			suppressed_logs: register(
+				CounterVec::new(
+					Opts::new(
+						"streams_suppressed_logs_total",
+						"Log lines suppressed by rate limiting, by call site",
+					),
+					&["key"],
+				)?,
+				registry,
+			)?,
+			task_heartbeat_age: register(
+				GaugeVec::new(
+					Opts::new(
+						"streams_task_heartbeat_age_seconds",
+						"Time since each long-lived task of the subsystem last made progress",
+					),
+					&["task"],
+				)?,
+				registry,
+			)?,
+			node_role: register(
+				GaugeVec::new(
+					Opts::new("streams_node_role", "The role the node runs the subsystem in"),
+					&["role"],
+				)?,
+				registry,
+			)?,
+			role_transitions: register(
+				CounterVec::new(
+					Opts::new(
+						"streams_role_transitions_total",
+						"Changes of the role the node runs the subsystem in, by the new role",
+					),
+					&["role"],
+				)?,
+				registry,
+			)?,
+			validator_online: register(
+				GaugeVec::new(
+					Opts::new(
+						"streams_validator_online",
+						"Whether each validator sent a liveness beat recently, by validator index",
+					),
+					&["validator"],
+				)?,
+				registry,
+			)?,
+			validator_witnessed: register(
+				GaugeVec::new(
+					Opts::new(
+						"streams_validator_witnessed",
+						"Events each validator witnessed since it started, as of its last liveness \
+						 beat, by validator index",
+					),
+					&["validator"],
+				)?,
+				registry,
+			)?,
+			submitted: Mutex::new(HashMap::new()),
+			first_seen: Mutex::new(LruCache::new(
+				NonZeroUsize::new(FIRST_SEEN_CAPACITY).expect("capacity is not zero"),
+			)),
+			awaiting_inclusion: Mutex::new(HashMap::new()),
+			failed_submissions: Mutex::new(LruCache::new(
+				NonZeroUsize::new(FAILED_SUBMISSIONS_CAPACITY).expect("capacity is not zero"),
+			)),
+		};
+		Ok(Self { inner: Some(Arc::new(inner)) })
+	}
+
+	/// Records a request of a trusted client.
+	pub fn on_client_request(&self, method: &str, outcome: &str) {
+		if let Some(inner) = &self.inner {
+			inner.client_requests.with_label_values(&[method, outcome]).inc();
+		}
+	}
+
+	/// Records that we have signed and published a witnessed event.
+	pub fn on_witness_sent(&self) {
+		if let Some(inner) = &self.inner {
+			inner.witnesses_sent.inc();
+		}
+	}
+
+	/// Records that a witnessed event was received.
+	pub fn on_witness_received(&self) {
+		if let Some(inner) = &self.inner {
+			inner.witnesses_received.inc();
+		}
+	}
+
+	/// Records that a received witnessed event was rejected.
+	pub fn on_witness_rejected(&self) {
+		if let Some(inner) = &self.inner {
+			inner.witnesses_rejected.inc();
+		}
+	}
+
+	/// Records that a received witnessed event was a copy of one collected recently.
+	pub fn on_witness_duplicate(&self) {
+		if let Some(inner) = &self.inner {
+			inner.witnesses_duplicate.inc();
+		}
+	}
+
+	/// Sets the number of events still gathering proofs.
+	pub fn set_pending_events(&self, count: usize) {
+		if let Some(inner) = &self.inner {
+			inner.pending_events.set(count as u64);
+		}
+	}
+
+	/// Sets the number of peers connected to the gossip.
+	pub fn set_gossip_peers(&self, count: usize) {
+		if let Some(inner) = &self.inner {
+			inner.gossip_peers.set(count as u64);
+		}
+	}
+
+	/// Records that the gossip was restarted after its event loop failed.
+	pub fn on_gossip_restart(&self) {
+		if let Some(inner) = &self.inner {
+			inner.gossip_restarts.inc();
+		}
+	}
+
+	/// Records that a message was not published as the gossip order queue was full.
+	pub fn on_gossip_publish_refused(&self) {
+		if let Some(inner) = &self.inner {
+			inner.gossip_publish_refused.inc();
+		}
+	}
+
+	/// Sets the number of gossip messages queued for the handler.
+	pub fn set_gossip_ingress_queued(&self, count: usize) {
+		if let Some(inner) = &self.inner {
+			inner.gossip_ingress_queued.set(count as u64);
+		}
+	}
+
+	/// Records that a received gossip message was dropped before being handled.
+	pub fn on_gossip_ingress_dropped(&self, reason: IngressDrop) {
+		if let Some(inner) = &self.inner {
+			inner.gossip_ingress_dropped.with_label_values(&[reason.as_str()]).inc();
+		}
+	}
+
+	/// Records that a gossip peer (`peer`) or a received witness (`witness`) was of an incompatible
+	/// protocol version.
+	pub fn on_gossip_incompatible(&self, kind: &str) {
+		if let Some(inner) = &self.inner {
+			inner.gossip_incompatible.with_label_values(&[kind]).inc();
+		}
+	}
+
+	/// Sets the health of the gossip mesh, and whether it is degraded.
+	pub fn set_mesh_health(&self, health: f64, degraded: bool) {
+		if let Some(inner) = &self.inner {
+			inner.mesh_health.set(health);
+			inner.mesh_degraded.set(degraded as u64);
+		}
+	}
+	/// Records that the trusted client submitted an event at the given time. Events submitted more
+	/// than once are timed from the first submission.
+	pub fn on_event_submitted(&self, event_id: H256, received: Instant) {
+		if let Some(inner) = &self.inner {
+			inner.submitted.lock().unwrap().entry(event_id).or_insert(received);
+		}
+	}
+
+	/// Records that the given events were finalized.
+	pub fn on_events_finalized(&self, event_ids: &[H256]) {
+		self.on_events_finalized_at(event_ids, Instant::now())
+	}
+
+	/// Records that the given events were finalized at `now`, and expires the submitted events
+	/// which have been waiting for longer than [EVENT_EXPIRY]. Events which were only seen through
+	/// gossip are not timed.
+	pub(crate) fn on_events_finalized_at(&self, event_ids: &[H256], now: Instant) {
+		let Some(inner) = &self.inner else { return };
+		inner.events_finalized.inc_by(event_ids.len() as u64);
+		let mut submitted = inner.submitted.lock().unwrap();
+		let finalized = inner.event_latency.with_label_values(&["finalized"]);
+		for event_id in event_ids {
+			if let Some(received) = submitted.remove(event_id) {
+				finalized.observe(now.saturating_duration_since(received).as_secs_f64());
+			}
+		}
+		let expired = inner.event_latency.with_label_values(&["expired"]);
+		submitted.retain(|_, received| {
+			let elapsed = now.saturating_duration_since(*received);
+			if elapsed < EVENT_EXPIRY {
+				return true
+			}
+			expired.observe(elapsed.as_secs_f64());
+			false
+		});
+	}
+
+	/// Records that a verified witness of the event was received from the validator with the given
+	/// index in the validator set.
+	pub fn on_witness_verified(&self, event_id: H256, validator: usize) {
+		self.on_witness_verified_at(event_id, validator, Instant::now())
+	}
+
+	/// Records that a verified witness of the event was received at `now`, timing it from the
+	/// first witness of the same event.
+	pub(crate) fn on_witness_verified_at(&self, event_id: H256, validator: usize, now: Instant) {
+		let Some(inner) = &self.inner else { return };
+		let first_seen = *inner.first_seen.lock().unwrap().get_or_insert(event_id, || now);
+		let label = validator_label(validator);
+		inner.validator_witnesses.with_label_values(&[&label]).inc();
+		inner
+			.witness_delay
+			.with_label_values(&[&label])
+			.observe(now.saturating_duration_since(first_seen).as_secs_f64());
+	}
+
+	/// Records that the validator with the given index in the validator set equivocated, sending a
+	/// witness of an event which conflicts with the one held from it.
+	pub fn on_equivocation(&self, validator: usize) {
+		if let Some(inner) = &self.inner {
+			inner.validator_equivocations.with_label_values(&[&validator_label(validator)]).inc();
+		}
+	}
+
+	/// Records the time between ordering the gossip to publish a message and publishing it.
+	pub fn on_gossip_published(&self, elapsed: Duration) {
+		if let Some(inner) = &self.inner {
+			inner.gossip_publish.observe(elapsed.as_secs_f64());
+		}
+	}
+
+	/// Records a submission of the extrinsic of an event to the transaction pool, and how long the
+	/// pool took to handle it.
+	pub fn on_extrinsic_submitted(
+		&self,
+		event_id: H256,
+		outcome: SubmissionOutcome,
+		elapsed: Duration,
+	) {
+		let Some(inner) = &self.inner else { return };
+		inner.extrinsic_submission_duration.observe(elapsed.as_secs_f64());
+		inner.extrinsic_submissions.with_label_values(&[outcome.label()]).inc();
+		let retried = {
+			let mut failed = inner.failed_submissions.lock().unwrap();
+			if outcome.is_success() {
+				failed.pop(&event_id).is_some()
+			} else {
+				failed.put(event_id, ()).is_some()
+			}
+		};
+		if retried {
+			inner.extrinsic_submissions.with_label_values(&["retried"]).inc();
+		}
+		if outcome == SubmissionOutcome::Submitted {
+			let mut awaiting = inner.awaiting_inclusion.lock().unwrap();
+			awaiting.insert(event_id, Instant::now());
+			inner.awaiting_inclusion_count.set(awaiting.len() as u64);
+		}
+	}
+
+	/// Records that the given events were included in a newly-imported block.
+	pub fn on_events_included(&self, event_ids: &[H256]) {
+		self.on_events_included_at(event_ids, Instant::now())
+	}
+
+	/// Records that the given events were included in a block at `now`, and stops waiting for the
+	/// inclusion of the extrinsics submitted longer than [EVENT_EXPIRY] ago, which the pool has
+	/// presumably dropped.
+	pub(crate) fn on_events_included_at(&self, event_ids: &[H256], now: Instant) {
+		let Some(inner) = &self.inner else { return };
+		let mut awaiting = inner.awaiting_inclusion.lock().unwrap();
+		for event_id in event_ids {
+			awaiting.remove(event_id);
+		}
+		awaiting.retain(|_, submitted| now.saturating_duration_since(*submitted) < EVENT_EXPIRY);
+		inner.awaiting_inclusion_count.set(awaiting.len() as u64);
+	}
+
+	/// Records that a log line of the given call site was suppressed.
+	pub fn on_log_suppressed(&self, key: &str) {
+		if let Some(inner) = &self.inner {
+			inner.suppressed_logs.with_label_values(&[key]).inc();
+		}
+	}
+
+	/// Sets the role the node runs the subsystem in.
+	pub fn set_node_role(&self, role: &str) {
+		if let Some(inner) = &self.inner {
+			inner.node_role.reset();
+			inner.node_role.with_label_values(&[role]).set(1);
+		}
+	}
+
+	/// Records that the node changed to the given role while running.
+	pub fn on_role_transition(&self, role: &str) {
+		if let Some(inner) = &self.inner {
+			inner.role_transitions.with_label_values(&[role]).inc();
+		}
+	}
+
+	/// Sets how long ago a task last made progress.
+	pub fn set_task_heartbeat_age(&self, task: &str, age: Duration) {
+		if let Some(inner) = &self.inner {
+			inner.task_heartbeat_age.with_label_values(&[task]).set(age.as_secs_f64());
+		}
+	}
+
+	/// Sets whether the validator with the given index in the validator set is online, and how many
+	/// events it witnessed as of its last liveness beat.
+	pub fn set_validator_liveness(&self, validator: usize, online: bool, witnessed: u64) {
+		if let Some(inner) = &self.inner {
+			let label = validator_label(validator);
+			inner.validator_online.with_label_values(&[&label]).set(online as u64);
+			inner.validator_witnessed.with_label_values(&[&label]).set(witnessed);
+		}
+	}
+
+	/// Summarizes the witnesses received from each validator so far, ordered by validator index.
+	pub fn validator_witness_stats(&self) -> Vec<ValidatorWitnessStats> {
+		let Some(inner) = &self.inner else { return Vec::new() };
+		let mut stats: Vec<_> = inner
+			.witness_delay
+			.collect()
+			.iter()
+			.flat_map(|family| family.get_metric())
+			.map(|metric| {
+				let histogram = metric.get_histogram();
+				let witnesses = histogram.get_sample_count();
+				let mean = histogram.get_sample_sum() / witnesses.max(1) as f64;
+				ValidatorWitnessStats {
+					validator: metric.get_label()[0].get_value().to_string(),
+					witnesses,
+					mean_delay: Duration::from_secs_f64(mean),
+				}
+			})
+			.collect();
+		stats.sort_by_key(|stats| stats.validator.parse::<usize>().unwrap_or(usize::MAX));
+		stats
+	}
+
+	/// The current counts of events and peers, or [None] if the metrics are not registered.
+	pub fn status_counts(&self) -> Option<StatusCounts> {
+		let inner = self.inner.as_ref()?;
+		Some(StatusCounts {
+			pending: inner.pending_events.get(),
+			at_quorum: inner.awaiting_inclusion_count.get(),
+			finalized: inner.events_finalized.get(),
+			peers: inner.gossip_peers.get(),
+		})
+	}
+
+	/// Whether the metrics are registered anywhere.
+	pub fn is_enabled(&self) -> bool {
+		self.inner.is_some()
+	}
+}
+
+/// The label of the validator with the given index in the per-validator metrics.
+fn validator_label(validator: usize) -> String {
+	if validator < MAX_LABELED_VALIDATORS {
+		validator.to_string()
+	} else {
+		"other".to_string()
+	}
+}
+
+/// Reports the events of every newly-finalized block to the metrics, until the client stops
+/// producing finality notifications. Returns immediately if the metrics are not enabled.
+pub async fn report_finalized_events<Block, Client, AuthorityId>(
+	client: Arc<Client>,
+	metrics: Metrics,
+	heartbeat: Heartbeat,
+) where
+	Block: BlockT,
+	Client: BlockchainEvents<Block> + BlockBackend<Block> + ChainAccess<Block, AuthorityId>,
+{
+	if !metrics.is_enabled() {
+		return
+	}
+	let mut finality_notifications = client.finality_notification_stream();
+	while let Some(notification) = next_beating(&mut finality_notifications, &heartbeat).await {
+		for hash in notification.tree_route.iter().chain([&notification.hash]) {
+			if let Some(event_ids) = block_event_ids(client.as_ref(), *hash) {
+				metrics.on_events_finalized(&event_ids);
+			}
+		}
+	}
+}
+
+/// Reports the events of every newly-imported block to the metrics, until the client stops
+/// producing import notifications. Returns immediately if the metrics are not enabled.
+pub async fn report_imported_events<Block, Client, AuthorityId>(
+	client: Arc<Client>,
+	metrics: Metrics,
+	heartbeat: Heartbeat,
+) where
+	Block: BlockT,
+	Client: BlockchainEvents<Block> + BlockBackend<Block> + ChainAccess<Block, AuthorityId>,
+{
+	if !metrics.is_enabled() {
+		return
+	}
+	let mut import_notifications = client.import_notification_stream();
+	while let Some(notification) = next_beating(&mut import_notifications, &heartbeat).await {
+		if let Some(event_ids) = block_event_ids(client.as_ref(), notification.hash) {
+			metrics.on_events_included(&event_ids);
+		}
+	}
+}
+
+/// The ids of the events validated in a block, or [None] if they could not be read.
+pub(crate) fn block_event_ids<Block, Client, AuthorityId>(
+	client: &Client,
+	hash: Block::Hash,
+) -> Option<Vec<H256>>
+where
+	Block: BlockT,
+	Client: BlockBackend<Block> + ChainAccess<Block, AuthorityId>,
+{
+	let extrinsics = client.block_body(hash).ok().flatten().unwrap_or_default();
+	client
+		.extrinsic_ids(hash, &extrinsics)
+		.map_err(|e| {
+			tracing::warn!(
+				target: SERVICE,
+				error = %e,
+				"Failed reading the events of block {hash:?}"
+			)
+		})
+		.ok()
+}
@@ -0,0 +1,277 @@
+use super::{
+	Metrics, SubmissionOutcome, ValidatorWitnessStats, EVENT_EXPIRY, MAX_LABELED_VALIDATORS,
+};
+use crate::{
+	events::{EventGossipHandler, EventWitnesser, WITNESSED_EVENTS_TOPIC},
+	gossip::{ingress::IngressDrop, GossipHandler, GossipTrait},
+	proofs::InMemoryEventProofs,
+	server::{
+		validated_streams_proto::{streams_server::Streams, WitnessEventRequest},
+		ValidatedStreamsGrpc,
+	},
+	test_utils::{
+		validator_set, FakeChain, SimulatedNetwork, SimulatedNode, TestBlock, TestPool,
+		TestValidators,
+	},
+	traces::Traces,
+	traits::EventWitnesserTrait,
+};
+use libp2p::gossipsub::IdentTopic;
+use prometheus_endpoint::Registry;
+use sc_transaction_pool_api::error::Error as PoolError;
+use sp_core::{sr25519::Public, H256};
+use sp_runtime::transaction_validity::InvalidTransaction;
+use std::{
+	collections::HashMap,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tonic::Request;
+
+/// The values of all series in the registry, keyed by `name{label=value,...}`. Histograms are
+/// reduced to their `_count` and `_sum` series.
+pub(crate) fn scrape(registry: &Registry) -> HashMap<String, f64> {
+	let mut series = HashMap::new();
+	for family in registry.gather() {
+		for metric in family.get_metric() {
+			let labels: Vec<_> = metric
+				.get_label()
+				.iter()
+				.map(|label| format!("{}={}", label.get_name(), label.get_value()))
+				.collect();
+			let labels = labels.join(",");
+			if metric.has_histogram() {
+				let histogram = metric.get_histogram();
+				let count = histogram.get_sample_count() as f64;
+				series.insert(format!("{}_count{{{labels}}}", family.get_name()), count);
+				let sum = histogram.get_sample_sum();
+				series.insert(format!("{}_sum{{{labels}}}", family.get_name()), sum);
+				continue
+			}
+			let value = if metric.has_counter() {
+				metric.get_counter().get_value()
+			} else {
+				metric.get_gauge().get_value()
+			};
+			series.insert(format!("{}{{{labels}}}", family.get_name()), value);
+		}
+	}
+	series
+}
+
+#[test]
+fn test_unregistered_metrics_are_no_op() {
+	for metrics in [Metrics::default(), Metrics::register(None).unwrap()] {
+		metrics.on_client_request("witness_event", "ok");
+		metrics.on_witness_sent();
+		metrics.on_witness_received();
+		metrics.on_witness_rejected();
+		metrics.on_witness_duplicate();
+		metrics.on_gossip_publish_refused();
+		metrics.set_pending_events(1);
+		metrics.set_gossip_peers(1);
+		metrics.set_gossip_ingress_queued(1);
+		metrics.on_gossip_ingress_dropped(IngressDrop::Overflow);
+		metrics.on_event_submitted(H256::zero(), Instant::now());
+		metrics.on_events_finalized(&[H256::zero()]);
+		metrics.on_witness_verified(H256::zero(), 0);
+		metrics.on_gossip_published(Duration::from_millis(1));
+		metrics.on_extrinsic_submitted(H256::zero(), SubmissionOutcome::Submitted, Duration::ZERO);
+		metrics.on_events_included(&[H256::zero()]);
+		assert_eq!(metrics.validator_witness_stats(), vec![]);
+	}
+}
+
+#[test]
+fn test_register_twice_fails() {
+	let registry = Registry::new();
+	assert!(Metrics::register(Some(&registry)).is_ok());
+	assert!(Metrics::register(Some(&registry)).is_err());
+}
+
+#[tokio::test]
+async fn test_metrics_after_activity() {
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let validators = TestValidators::new(4);
+	let nodes = (0..validators.len())
+		.map(|i| {
+			let metrics = if i == 0 { metrics.clone() } else { Metrics::default() };
+			Arc::new(SimulatedNode::with_metrics(validators.authorities(), metrics))
+		})
+		.collect();
+	let network = SimulatedNetwork::new(0, nodes);
+	let witnesser = EventWitnesser::<TestBlock, _, Public, _>::new(
+		Arc::new(FakeChain::new(validators.pubkeys())),
+		network.gossip(0),
+		validators.keystore(0),
+		validator_set(),
+		metrics.clone(),
+		Traces::default(),
+	);
+	let grpc = ValidatedStreamsGrpc { metrics, ..ValidatedStreamsGrpc::for_tests(witnesser) };
+
+	let valid = WitnessEventRequest { event_id: vec![1; 32], ..Default::default() };
+	assert!(grpc.witness_event(Request::new(valid)).await.is_ok());
+	let invalid = WitnessEventRequest { event_id: vec![1; 31], ..Default::default() };
+	assert!(grpc.witness_event(Request::new(invalid)).await.is_err());
+	grpc.event_witnesser.witness_event(H256::repeat_byte(2)).await.unwrap();
+	let garbage = validators.witness(1, H256::repeat_byte(3)).corrupt_signature().build();
+	network
+		.gossip(1)
+		.publish(IdentTopic::new(WITNESSED_EVENTS_TOPIC), garbage.to_bytes().unwrap())
+		.await
+		.unwrap();
+	network.run_until_idle().await;
+
+	let series = scrape(&registry);
+	let expected = [
+		("streams_client_requests_total{method=witness_event,outcome=ok}", 1.0),
+		("streams_client_requests_total{method=witness_event,outcome=invalid_argument}", 1.0),
+		("streams_witnesses_sent_total{}", 2.0),
+		("streams_witnesses_received_total{}", 3.0),
+		("streams_witnesses_rejected_total{}", 1.0),
+		("streams_pending_events{}", 2.0),
+		("streams_gossip_peers{}", 0.0),
+		("streams_gossip_restarts_total{}", 0.0),
+		("streams_gossip_ingress_queued{}", 0.0),
+	];
+	for (name, value) in expected {
+		assert_eq!(series.get(name), Some(&value), "{name} in {series:?}");
+	}
+}
+
+#[test]
+fn test_event_latency_by_outcome() {
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let submitted = Instant::now();
+	let (finalized, expired, gossiped) =
+		(H256::repeat_byte(1), H256::repeat_byte(2), H256::repeat_byte(3));
+
+	metrics.on_event_submitted(finalized, submitted);
+	metrics.on_event_submitted(expired, submitted);
+	// Resubmissions do not restart the clock
+	metrics.on_event_submitted(finalized, submitted + Duration::from_secs(1));
+	metrics.on_events_finalized_at(&[finalized, gossiped], submitted + Duration::from_secs(2));
+	let expiry = submitted + EVENT_EXPIRY + Duration::from_secs(1);
+	metrics.on_events_finalized_at(&[], expiry);
+	// Events are only observed once, whichever comes first
+	metrics.on_events_finalized_at(&[finalized, expired], expiry);
+
+	let series = scrape(&registry);
+	let expected = [
+		("streams_event_latency_seconds_count{outcome=finalized}", 1.0),
+		("streams_event_latency_seconds_sum{outcome=finalized}", 2.0),
+		("streams_event_latency_seconds_count{outcome=expired}", 1.0),
+		("streams_event_latency_seconds_sum{outcome=expired}", EVENT_EXPIRY.as_secs_f64() + 1.0),
+	];
+	for (name, value) in expected {
+		assert_eq!(series.get(name), Some(&value), "{name} in {series:?}");
+	}
+}
+
+#[test]
+fn test_witness_delay_by_validator() {
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let first_seen = Instant::now();
+	let delayed_event = |event_id, delays: &[(usize, u64)]| {
+		for &(validator, delay) in delays {
+			let received = first_seen + Duration::from_secs(delay);
+			metrics.on_witness_verified_at(event_id, validator, received);
+		}
+	};
+
+	// The first witness of an event starts its clock, whichever validator it is from
+	delayed_event(H256::repeat_byte(1), &[(0, 0), (1, 1), (2, 3)]);
+	delayed_event(H256::repeat_byte(2), &[(1, 2), (0, 3), (2, 7)]);
+	// Validators past the cap are counted together
+	delayed_event(H256::repeat_byte(3), &[(MAX_LABELED_VALIDATORS, 0)]);
+	metrics.on_gossip_published(Duration::from_millis(2));
+
+	let series = scrape(&registry);
+	let expected = [
+		("streams_validator_witnesses_total{validator=0}", 2.0),
+		("streams_validator_witnesses_total{validator=2}", 2.0),
+		("streams_validator_witnesses_total{validator=other}", 1.0),
+		("streams_witness_delay_seconds_sum{validator=0}", 1.0),
+		("streams_witness_delay_seconds_sum{validator=1}", 1.0),
+		("streams_witness_delay_seconds_sum{validator=2}", 8.0),
+		("streams_witness_delay_seconds_count{validator=other}", 1.0),
+		("streams_gossip_publish_seconds_count{}", 1.0),
+	];
+	for (name, value) in expected {
+		assert_eq!(series.get(name), Some(&value), "{name} in {series:?}");
+	}
+
+	let stats = |validator: &str, witnesses, mean_delay| ValidatorWitnessStats {
+		validator: validator.to_string(),
+		witnesses,
+		mean_delay: Duration::from_secs_f64(mean_delay),
+	};
+	assert_eq!(
+		metrics.validator_witness_stats(),
+		vec![stats("0", 2, 0.5), stats("1", 2, 0.5), stats("2", 2, 4.0), stats("other", 1, 0.0)]
+	);
+}
+
+#[tokio::test]
+async fn test_extrinsic_submission_outcomes() {
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	// A single validator reaches the target with its own witness
+	let validators = TestValidators::new(1);
+	let pool = Arc::new(TestPool::default());
+	let handler = EventGossipHandler::<_, _, _, Public, TestBlock>::new(
+		Arc::new(FakeChain::new(validators.pubkeys())),
+		Arc::new(InMemoryEventProofs::new()),
+		pool.clone(),
+		validator_set(),
+		metrics.clone(),
+		Traces::default(),
+	);
+	let witness = |event| validators.witness(0, H256::repeat_byte(event)).build().to_bytes();
+
+	// Dropped, and then accepted once the witness is received again
+	pool.fail_next(PoolError::ImmediatelyDropped);
+	handler.handle(&witness(1).unwrap()).await;
+	handler.handle(&witness(1).unwrap()).await;
+	pool.fail_next(PoolError::AlreadyImported(Box::new(())));
+	handler.handle(&witness(2).unwrap()).await;
+	pool.fail_next(PoolError::InvalidTransaction(InvalidTransaction::AncientBirthBlock));
+	handler.handle(&witness(3).unwrap()).await;
+	pool.fail_next(PoolError::InvalidTransaction(InvalidTransaction::Call));
+	handler.handle(&witness(4).unwrap()).await;
+	assert_eq!(pool.submitted(), vec![H256::repeat_byte(1)]);
+
+	let series = scrape(&registry);
+	let expected = [
+		("streams_extrinsic_submissions_total{outcome=submitted}", 1.0),
+		("streams_extrinsic_submissions_total{outcome=dropped}", 1.0),
+		("streams_extrinsic_submissions_total{outcome=retried}", 1.0),
+		("streams_extrinsic_submissions_total{outcome=already_imported}", 1.0),
+		("streams_extrinsic_submissions_total{outcome=mortality_expired}", 1.0),
+		("streams_extrinsic_submissions_total{outcome=invalid}", 1.0),
+		("streams_extrinsic_submission_seconds_count{}", 5.0),
+		("streams_extrinsics_awaiting_inclusion{}", 1.0),
+	];
+	for (name, value) in expected {
+		assert_eq!(series.get(name), Some(&value), "{name} in {series:?}");
+	}
+
+	metrics.on_events_included(&[H256::repeat_byte(1)]);
+	let series = scrape(&registry);
+	assert_eq!(series.get("streams_extrinsics_awaiting_inclusion{}"), Some(&0.0));
+}
+
+#[test]
+fn test_extrinsics_awaiting_inclusion_expire() {
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	metrics.on_extrinsic_submitted(H256::zero(), SubmissionOutcome::Submitted, Duration::ZERO);
+	metrics.on_events_included_at(&[], Instant::now() + EVENT_EXPIRY);
+
+	let series = scrape(&registry);
+	assert_eq!(series.get("streams_extrinsics_awaiting_inclusion{}"), Some(&0.0));
+}
@@ -0,0 +1,683 @@
+//! A helper for starting all the components needed to run a full Validated Streams node
+
+use crate::{
+	auth::{ApiKeys, ClientCertificates},
+	config::ValidatedStreamsNetworkConfiguration,
+	errors::StartupError,
+	events::{
+		get_latest_authorities_list, run_liveness, EventGossipHandler, EventProofReader,
+		EventValidator, EventWitnesser, ValidatorLiveness, ValidatorMembership, ValidatorSetHandle,
+	},
+	executor::{StreamsRuntime, StreamsSpawner},
+	gossip::{
+		derive_gossip_key, gossip_key_from_seed, load_gossip_key, BanConfig, Gossip,
+		GossipBackend, MeshConfig, MeshExpectations, NatConfig, OrderQueueConfig, PendingNetwork,
+		TransportConfig,
+	},
+	index::{index_finalized_events, EventIndexTrait},
+	limits::ClientLimits,
+	logging::{GOSSIP, SERVICE},
+	metrics::{report_finalized_events, report_imported_events, Metrics},
+	notifications::{notify_finalized_events, EventNotifications},
+	payloads::{purge_expired_payloads, EventPayloads, EventPayloadsTrait},
+	proofs::EventProofsTrait,
+	role::{track_role, LocalRole},
+	server::{self, ServerParams},
+	shutdown::{ShutdownSignal, StreamsShutdown},
+	startup::{StartupSignals, StartupStep},
+	status::{track_included_events, EventStatuses},
+	streams::EventStreams,
+	telemetry::{report_status, NodeRole, StreamsTelemetry, STATUS_INTERVAL},
+	traces::Traces,
+	traits::ChainAccess,
+	tunables::{TunableParams, Tunables},
+	watchdog::{
+		next_beating, run_watchdog, Heartbeat, Heartbeats, STALL_THRESHOLD, WATCHDOG_INTERVAL,
+	},
+};
+use codec::Codec;
+use futures::{channel::oneshot, future, Future};
+use libp2p::Multiaddr;
+
+use pallet_validated_streams::ValidatedStreamsApi;
+use prometheus_endpoint::Registry;
+use sc_client_api::{BlockBackend, BlockchainEvents, HeaderBackend};
+use sc_network::config::NetworkConfiguration;
+use sc_service::{error::Error as ServiceError, Role, SpawnEssentialTaskHandle, SpawnTaskHandle};
+use sc_telemetry::TelemetryHandle;
+use sc_transaction_pool_api::LocalTransactionPool;
+use sp_api::{BlockT, HeaderT, ProvideRuntimeApi};
+use sp_blockchain::HeaderMetadata;
+use sp_consensus_aura::AuraApi;
+use sp_keystore::CryptoStore;
+use sp_runtime::{app_crypto::CryptoTypePublicPair, key_types::AURA};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tokio::time::Instant;
+#[cfg(test)]
+pub mod tests;
+
+/// How long a validator waits for a key of the validator set to appear in its keystore before
+/// reporting it missing.
+pub const MISSING_KEY_DEADLINE: Duration = Duration::from_secs(30);
+/// How often the keystore is checked for a key until then.
+const MISSING_KEY_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The task group every task of the subsystem is spawned under. They are all async tasks of the
+/// task manager, whichever runtime they run on (see [crate::executor]); none of them does blocking
+/// work, so none takes a thread of the blocking pool.
+const TASK_GROUP: Option<&str> = Some("validated-streams");
+const FINALITY_METRICS_TASK: &str = "validated-streams-finality-metrics";
+const INCLUSION_METRICS_TASK: &str = "validated-streams-inclusion-metrics";
+const TELEMETRY_TASK: &str = "validated-streams-telemetry";
+const MESH_QUORUM_TASK: &str = "validated-streams-mesh-quorum";
+const GRPC_SERVER_TASK: &str = "validated-streams-grpc-server";
+const GOSSIP_TASK: &str = "validated-streams-gossip";
+const GOSSIP_INGRESS_TASK: &str = "validated-streams-gossip-ingress";
+const GOSSIP_CONNECTION_TASK: &str = "validated-streams-gossip-connection";
+const WATCHDOG_TASK: &str = "validated-streams-watchdog";
+const KEYSTORE_TASK: &str = "validated-streams-keystore";
+const ROLE_TASK: &str = "validated-streams-role";
+const PAYLOADS_TASK: &str = "validated-streams-payloads";
+const INDEX_TASK: &str = "validated-streams-index";
+const NOTIFICATIONS_TASK: &str = "validated-streams-notifications";
+const STATUS_TASK: &str = "validated-streams-status";
+const LIVENESS_TASK: &str = "validated-streams-liveness";
+#[cfg(unix)]
+const CONFIG_TASK: &str = "validated-streams-config";
+
+/// Parameters for the [start] function.
+pub struct StartParams<
+	Block: BlockT,
+	TxPool: LocalTransactionPool<Block = Block> + 'static,
+	Client: Sync + Send + 'static,
+	EventProofs: EventProofsTrait + Sync + Send + 'static,
+> {
+	/// The spawn handle to launch services under.
+	pub spawn_handle: SpawnTaskHandle,
+	/// The spawn handle to launch the services which shut the node down on fatal errors under.
+	pub essential_spawn_handle: SpawnEssentialTaskHandle,
+	/// A reference to an [EventProofsTrait] instance for storing events proofs.
+	pub event_proofs: Arc<EventProofs>,
+	/// The store of the event payloads, used if the node stores them.
+	pub event_payloads: Arc<dyn EventPayloadsTrait + Send + Sync>,
+	/// The index of the validated events, kept up to date with the finalized blocks.
+	pub event_index: Arc<dyn EventIndexTrait + Send + Sync>,
+	/// The client.
+	pub client: Arc<Client>,
+	/// A keystore for signing witnessed events.
+	pub keystore: Arc<dyn CryptoStore>,
+	/// A reference to the transaction pool where fully-witnessed events will be submitted.
+	pub transaction_pool: Arc<TxPool>,
+	/// The substrate network configuration.
+	pub network_configuration: NetworkConfiguration,
+	/// The gossip peers listed in the chain spec, dialed unless `--gossip-bootnodes` are given.
+	pub chain_spec_peers: Vec<Multiaddr>,
+	/// The network service of the node, once built, for the gossip to run on with
+	/// `--gossip-backend network`. Its set must then be in the extra sets of the network
+	/// configuration, as [crate::gossip::network::peers_set_config] makes it.
+	pub gossip_network: PendingNetwork,
+	/// The validated streams -specific network configuration.
+	pub validated_streams_network_config: ValidatedStreamsNetworkConfiguration,
+	/// The validator sets, shared by all the services of the subsystem.
+	pub validator_set: ValidatorSetHandle<Block>,
+	/// The registry to report metrics to, if metrics are enabled.
+	pub prometheus_registry: Option<Registry>,
+	/// The node's telemetry, if enabled.
+	pub telemetry: Option<TelemetryHandle>,
+	/// The role of the node.
+	pub role: Role,
+	/// The path of the keystore, or [None] if it is in memory. Only used for reporting a missing
+	/// key.
+	pub keystore_path: Option<PathBuf>,
+}
+
+/// Start all the services of the Validated Streams node.
+/// This functions starts the gossip, event service, and the gRPC server for the current node, and
+/// configures their ports using the passed configuration. The services wait on one another as
+/// described in [crate::startup]. Errors of these services once spawned
+/// are [StartupError]s; they are logged, and the fatal ones shut the node down. Returns the
+/// [StreamsShutdown] to run once the node is told to exit, before its tasks are stopped.
+pub fn start<
+	Block: BlockT,
+	TxPool: LocalTransactionPool<Block = Block> + 'static,
+	Client: Sync + Send + 'static,
+	EventProofs: EventProofsTrait + Sync + Send + 'static,
+	AuthorityId: Codec + Send + Sync + 'static,
+>(
+	params: StartParams<Block, TxPool, Client, EventProofs>,
+) -> Result<StreamsShutdown, ServiceError>
+where
+	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
+	Client: HeaderMetadata<Block>
+		+ BlockBackend<Block>
+		+ HeaderBackend<Block>
+		+ BlockchainEvents<Block>
+		+ ProvideRuntimeApi<Block>
+		+ ChainAccess<Block, AuthorityId>,
+	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+	<<Block as BlockT>::Header as HeaderT>::Number: Into<u32>,
+{
+	let StartParams {
+		spawn_handle,
+		essential_spawn_handle,
+		event_proofs,
+		event_payloads,
+		event_index,
+		client,
+		keystore,
+		transaction_pool: tx_pool,
+		validated_streams_network_config: vs_network_configuration,
+		network_configuration,
+		chain_spec_peers,
+		gossip_network,
+		validator_set,
+		prometheus_registry,
+		telemetry,
+		role,
+		keystore_path,
+	} = params;
+
+	let role = if role.is_authority() { NodeRole::Validator } else { NodeRole::Observer };
+	tracing::info!(target: SERVICE, role = role.as_str(), "Starting Validated Streams");
+
+	let telemetry = StreamsTelemetry::from_handle(telemetry);
+	// The status sent to the telemetry is read from the metrics, so keep them even without
+	// Prometheus
+	let prometheus_registry =
+		prometheus_registry.or_else(|| telemetry.is_enabled().then(Registry::new));
+	let metrics = Metrics::register(prometheus_registry.as_ref())?;
+	let local_role = LocalRole::new(role, metrics.clone());
+	let traces = match &vs_network_configuration.otlp_endpoint {
+		Some(endpoint) => Traces::otlp(endpoint).map_err(|e| ServiceError::Other(e.to_string()))?,
+		None => Traces::default(),
+	};
+	let grpc_tls = match (
+		&vs_network_configuration.grpc_tls_cert,
+		&vs_network_configuration.grpc_tls_key,
+	) {
+		(Some(cert), Some(key)) => {
+			let client_ca = vs_network_configuration.grpc_tls_client_ca.as_deref();
+			let tls = server::tls_config(cert, key, client_ca);
+			Some(tls.map_err(|e| ServiceError::Other(e.to_string()))?)
+		},
+		// Either both are given or neither is
+		_ => None,
+	};
+	let api_keys = ApiKeys::load(
+		&vs_network_configuration.grpc_api_key,
+		vs_network_configuration.grpc_api_keys_file.as_deref(),
+	)
+	.map_err(|e| ServiceError::Other(e.to_string()))?;
+	let client_certificates =
+		ClientCertificates::parse(&vs_network_configuration.grpc_tls_client_allow)
+			.map_err(|e| ServiceError::Other(e.to_string()))?;
+	let streams_runtime = match vs_network_configuration.streams_runtime_threads {
+		Some(threads) => {
+			let runtime = StreamsRuntime::dedicated(threads).map_err(|e| {
+				ServiceError::Other(format!("Failed starting the streams runtime: {e}"))
+			})?;
+			let threads = threads.get();
+			tracing::info!(target: SERVICE, threads, "Running on a dedicated runtime");
+			Some(Arc::new(runtime))
+		},
+		None => None,
+	};
+	let spawn_handle = StreamsSpawner::new(spawn_handle, streams_runtime.clone());
+	let essential_spawn_handle = StreamsSpawner::new(essential_spawn_handle, streams_runtime);
+
+	let tunables = Tunables::new(TunableParams::from_params(&vs_network_configuration));
+	if let Some(path) = vs_network_configuration.streams_config.clone() {
+		tunables.reload(&path).map_err(|e| ServiceError::Other(e.to_string()))?;
+		#[cfg(unix)]
+		spawn_handle.spawn(
+			CONFIG_TASK,
+			TASK_GROUP,
+			crate::tunables::reload_on_sighup(tunables.clone(), path),
+		);
+	}
+
+	let startup = StartupSignals::default();
+	// The node opens the proofs store before starting the subsystem
+	startup.mark_done(StartupStep::ProofsStoreOpen);
+
+	let gossip_key = match (
+		&vs_network_configuration.streams_node_key_file,
+		&vs_network_configuration.streams_node_key_seed,
+	) {
+		(Some(path), _) => load_gossip_key(path),
+		(None, Some(seed)) => gossip_key_from_seed(seed),
+		(None, None) => {
+			let node_key = network_configuration.node_key.clone().into_keypair().map_err(|e| {
+				ServiceError::Other(format!("Failed reading the node key: {e}"))
+			})?;
+			derive_gossip_key(&node_key)
+		},
+	}
+	.map_err(|e| ServiceError::Other(e.to_string()))?;
+	let mesh_expectations = MeshExpectations::default();
+	let (streams_gossip, streams_gossip_service) =
+		Gossip::create_with_order_queue(
+			metrics.clone(),
+			mesh_expectations.clone(),
+			telemetry.clone(),
+			OrderQueueConfig {
+				capacity: vs_network_configuration.gossip_order_capacity,
+				backpressure: vs_network_configuration.gossip_backpressure,
+			},
+		);
+
+	let shutdown_signal = ShutdownSignal::default();
+	let notifications = EventNotifications::default();
+	let statuses = EventStatuses::default();
+	let streams = EventStreams::new(vs_network_configuration.streams_id.clone());
+	let liveness_interval = Duration::from_secs(vs_network_configuration.gossip_liveness_interval);
+	let liveness = (!liveness_interval.is_zero())
+		.then(|| ValidatorLiveness::new(liveness_interval, metrics.clone()));
+	let mut event_gossip_handler = EventGossipHandler::new(
+		client.clone(),
+		event_proofs.clone(),
+		tx_pool,
+		validator_set.clone(),
+		metrics.clone(),
+		traces.clone(),
+	)
+	.with_notifications(notifications.clone())
+	.with_statuses(statuses.clone())
+	.with_streams(streams.clone());
+	let mut event_witnesser = EventWitnesser::new(
+		client.clone(),
+		streams_gossip.clone(),
+		keystore.clone(),
+		validator_set.clone(),
+		metrics.clone(),
+		traces.clone(),
+	)
+	.with_role(local_role.clone())
+	.with_tunables(tunables.clone())
+	.with_shutdown(shutdown_signal.clone())
+	.with_statuses(statuses.clone())
+	.with_streams(streams);
+	if let Some(liveness) = &liveness {
+		event_gossip_handler = event_gossip_handler.with_liveness(liveness.clone());
+		event_witnesser = event_witnesser.with_liveness(liveness.clone());
+	}
+	let event_gossip_handler = Arc::new(event_gossip_handler);
+	let event_witnesser = Arc::new(event_witnesser);
+	let membership = Arc::new(ValidatorMembership::<Block, _, AuthorityId>::new(
+		client.clone(),
+		keystore.clone(),
+		validator_set.clone(),
+	));
+	let event_validator = Arc::new(EventValidator::new(client.clone()));
+	let event_proof_reader = Arc::new(EventProofReader::<Block, _, AuthorityId, _>::new(
+		client.clone(),
+		event_proofs.clone(),
+		validator_set.clone(),
+	));
+
+	let heartbeats = Heartbeats::default();
+	let payloads = vs_network_configuration.streams_event_payloads.then(|| {
+		let retention = Duration::from_secs(vs_network_configuration.streams_payload_retention);
+		let max_size = vs_network_configuration.streams_payload_max_size;
+		EventPayloads::new(event_payloads, max_size, retention)
+	});
+	if let Some(payloads) = payloads.clone() {
+		let heartbeat = heartbeats.register(PAYLOADS_TASK);
+		spawn_handle.spawn(PAYLOADS_TASK, TASK_GROUP, purge_expired_payloads(payloads, heartbeat));
+	}
+	spawn_handle.spawn(
+		INDEX_TASK,
+		TASK_GROUP,
+		index_finalized_events(
+			event_index.clone(),
+			event_validator.clone(),
+			client.finality_notification_stream(),
+			heartbeats.register(INDEX_TASK),
+		),
+	);
+	spawn_handle.spawn(
+		NOTIFICATIONS_TASK,
+		TASK_GROUP,
+		notify_finalized_events::<Block, _, AuthorityId, _>(
+			client.clone(),
+			event_proofs.clone(),
+			notifications.clone(),
+			heartbeats.register(NOTIFICATIONS_TASK),
+		),
+	);
+	spawn_handle.spawn(
+		STATUS_TASK,
+		TASK_GROUP,
+		track_included_events::<Block, _, AuthorityId>(
+			client.clone(),
+			statuses.clone(),
+			heartbeats.register(STATUS_TASK),
+		),
+	);
+	spawn_handle.spawn(
+		FINALITY_METRICS_TASK,
+		TASK_GROUP,
+		report_finalized_events::<Block, _, AuthorityId>(
+			client.clone(),
+			metrics.clone(),
+			heartbeats.register(FINALITY_METRICS_TASK),
+		),
+	);
+	spawn_handle.spawn(
+		INCLUSION_METRICS_TASK,
+		TASK_GROUP,
+		report_imported_events::<Block, _, AuthorityId>(
+			client.clone(),
+			metrics.clone(),
+			heartbeats.register(INCLUSION_METRICS_TASK),
+		),
+	);
+	spawn_handle.spawn(
+		TELEMETRY_TASK,
+		TASK_GROUP,
+		report_status(
+			telemetry,
+			metrics.clone(),
+			local_role.clone(),
+			STATUS_INTERVAL,
+			heartbeats.register(TELEMETRY_TASK),
+		),
+	);
+	if let Some(liveness) = liveness {
+		spawn_handle.spawn(
+			LIVENESS_TASK,
+			TASK_GROUP,
+			run_liveness::<Block, _, AuthorityId, _>(
+				liveness,
+				client.clone(),
+				validator_set.clone(),
+				keystore.clone(),
+				streams_gossip.clone(),
+				local_role.clone(),
+				heartbeats.register(LIVENESS_TASK),
+			),
+		);
+	}
+	let missing_key = MissingKeyPolicy {
+		keystore_path,
+		allow: vs_network_configuration.streams_allow_missing_key,
+	};
+	let resolve_key = resolve_witnessing_key::<Block, _, AuthorityId>(
+		role,
+		keystore.clone(),
+		client.clone(),
+		validator_set.clone(),
+		missing_key,
+	);
+	let keystore_startup = startup.clone();
+	let resolve_key = async move {
+		// Witnessing gets attempted after a lookup error too, as it was before
+		let resolved = resolve_key.await;
+		keystore_startup.mark_done(StartupStep::KeystoreResolved);
+		resolved
+	};
+	essential_spawn_handle.spawn(KEYSTORE_TASK, TASK_GROUP, supervise(KEYSTORE_TASK, resolve_key));
+	if role == NodeRole::Validator {
+		let track = track_role::<Block, _, AuthorityId>(
+			local_role,
+			keystore,
+			client.clone(),
+			validator_set.clone(),
+			heartbeats.register(ROLE_TASK),
+		);
+		let role_startup = startup.clone();
+		spawn_handle.spawn(ROLE_TASK, TASK_GROUP, async move {
+			// Starting from the key resolved at startup
+			role_startup.done(StartupStep::KeystoreResolved).await;
+			track.await
+		});
+	}
+	spawn_handle.spawn(
+		MESH_QUORUM_TASK,
+		TASK_GROUP,
+		track_mesh_quorum::<Block, _, AuthorityId>(
+			client,
+			validator_set,
+			mesh_expectations.clone(),
+			heartbeats.register(MESH_QUORUM_TASK),
+		),
+	);
+	spawn_handle.spawn(
+		WATCHDOG_TASK,
+		TASK_GROUP,
+		run_watchdog(heartbeats.clone(), metrics.clone(), STALL_THRESHOLD, WATCHDOG_INTERVAL),
+	);
+
+	let client_limits = ClientLimits {
+		rate_limit: vs_network_configuration.grpc_client_rate_limit,
+		max_connections: vs_network_configuration.grpc_max_connections,
+		keepalive_interval: Duration::from_secs(vs_network_configuration.grpc_keepalive_interval),
+		keepalive_timeout: Duration::from_secs(vs_network_configuration.grpc_keepalive_timeout),
+		max_connection_age: Duration::from_secs(vs_network_configuration.grpc_max_connection_age),
+	};
+	let (grpc_stopped, grpc_drained) = oneshot::channel();
+	let grpc_server = server::run(ServerParams {
+		event_witnesser,
+		event_validator,
+		grpc_addrs: vs_network_configuration.grpc_addr,
+		tls: grpc_tls,
+		api_keys,
+		client_certificates,
+		web_origins: vs_network_configuration.grpc_web_origin,
+		limits: client_limits,
+		metrics,
+		traces,
+		startup: startup.clone(),
+		tunables,
+		payloads,
+		max_payload_size: vs_network_configuration.streams_payload_max_size,
+		event_index,
+		notifications,
+		statuses,
+		event_proofs: event_proof_reader,
+		gossip: streams_gossip.clone(),
+		shutdown: shutdown_signal.clone(),
+	});
+	let grpc = async move {
+		grpc_server.await?;
+		grpc_stopped.send(()).ok();
+		Ok(())
+	};
+	essential_spawn_handle.spawn(GRPC_SERVER_TASK, TASK_GROUP, supervise(GRPC_SERVER_TASK, grpc));
+
+	let gossip_listen_addresses = if !vs_network_configuration.gossip_listen_addr.is_empty() {
+		vs_network_configuration.gossip_listen_addr
+	} else {
+		network_configuration
+			.listen_addresses
+			.iter()
+			.map(|addr| vs_network_configuration.gossip_port.adjust_multiaddr(addr.clone()))
+			.collect::<Vec<_>>()
+	};
+
+	let gossip_peers = if !vs_network_configuration.gossip_bootnodes.is_empty() {
+		vs_network_configuration.gossip_bootnodes
+	} else if !chain_spec_peers.is_empty() {
+		chain_spec_peers
+	} else {
+		network_configuration
+			.boot_nodes
+			.iter()
+			.map(|addr| {
+				let mut addr =
+					vs_network_configuration.gossip_port.adjust_multiaddr(addr.multiaddr.clone());
+				// Remove the final /p2p/.. part as we are using different keys for gossip
+				match addr.pop() {
+					Some(libp2p::core::multiaddr::Protocol::P2p(_)) => {},
+					Some(x) => addr.push(x),
+					None => {},
+				}
+				addr
+			})
+			.collect()
+	};
+	tracing::info!(target: GOSSIP, "Gossip bootnodes: {:?}", gossip_peers);
+	mesh_expectations.set_addresses(gossip_peers.clone());
+
+	let streams_gossip_service = match vs_network_configuration.gossip_backend {
+		GossipBackend::Swarm => streams_gossip_service,
+		GossipBackend::Network => streams_gossip_service.with_network(gossip_network),
+	};
+	let streams_gossip_service = streams_gossip_service
+		.with_key(gossip_key)
+		.with_transport(TransportConfig {
+			timeout: Duration::from_secs(vs_network_configuration.gossip_transport_timeout),
+			max_incoming: vs_network_configuration.gossip_max_incoming_connections,
+			max_per_peer: vs_network_configuration.gossip_max_connections_per_peer,
+			quic: vs_network_configuration.gossip_quic,
+		})
+		.with_discovery_interval(Duration::from_secs(
+			vs_network_configuration.gossip_discovery_interval,
+		))
+		.with_mdns(vs_network_configuration.gossip_mdns)
+		.with_mesh(MeshConfig {
+			heartbeat_interval: Duration::from_millis(
+				vs_network_configuration.gossip_heartbeat_interval,
+			),
+			mesh_n: vs_network_configuration.gossip_mesh_n,
+			history_length: vs_network_configuration.gossip_history_length,
+			max_message_size: vs_network_configuration.gossip_max_message_size,
+			flood_publish: vs_network_configuration.gossip_flood_publish,
+		})
+		.with_compression(vs_network_configuration.gossip_compression)
+		.with_membership(membership)
+		.with_bans(BanConfig {
+			threshold: vs_network_configuration.gossip_ban_threshold,
+			duration: Duration::from_secs(vs_network_configuration.gossip_ban_duration),
+			flood_limit: vs_network_configuration.gossip_flood_limit,
+		})
+		.with_min_peers(vs_network_configuration.gossip_min_peers)
+		.with_nat(NatConfig {
+			autonat: vs_network_configuration.gossip_autonat,
+			relays: vs_network_configuration.gossip_relays,
+			external_addresses: vs_network_configuration.gossip_external_addresses,
+		})
+		.with_heartbeat(heartbeats.register(GOSSIP_TASK))
+		.with_listen_addresses(gossip_listen_addresses)
+		.with_spawner(spawn_handle, GOSSIP_INGRESS_TASK, GOSSIP_CONNECTION_TASK, TASK_GROUP)
+		.with_startup(startup.clone());
+	let streams_gossip_service = match vs_network_configuration.gossip_validators_only {
+		true => streams_gossip_service.with_allowlist(vs_network_configuration.gossip_allowlist),
+		false => streams_gossip_service,
+	};
+	let mut gossip_dial = streams_gossip.clone();
+	let gossip = async move {
+		// The handler stores the witnesses it receives
+		startup.done(StartupStep::ProofsStoreOpen).await;
+		// Dialed once the service runs, after it started listening
+		gossip_dial.connect_to(gossip_peers).await;
+
+		streams_gossip_service.run(event_gossip_handler).await
+	};
+	essential_spawn_handle.spawn(GOSSIP_TASK, TASK_GROUP, supervise(GOSSIP_TASK, gossip));
+
+	Ok(StreamsShutdown::new(shutdown_signal, event_proofs, streams_gossip, grpc_drained))
+}
+
+/// Runs a task which can fail to start, logging its error. Meant to be spawned as an essential
+/// task: it returns, shutting the node down, only if the error is fatal, and idles otherwise.
+pub(crate) async fn supervise(
+	task: &'static str,
+	future: impl Future<Output = Result<(), StartupError>>,
+) {
+	match future.await {
+		Ok(()) => {},
+		Err(e) if e.is_fatal() => {
+			tracing::error!(
+				target: SERVICE,
+				task,
+				error = %e,
+				"Fatal error; shutting the node down"
+			);
+			return
+		},
+		Err(e) => tracing::error!(target: SERVICE, task, error = %e, "Task failed"),
+	}
+	future::pending().await
+}
+
+/// What a validator does if its keystore holds no key of the validator set.
+pub(crate) struct MissingKeyPolicy {
+	/// The path of the keystore, for the error
+	pub keystore_path: Option<PathBuf>,
+	/// Whether to keep running without witnessing events rather than failing
+	pub allow: bool,
+}
+
+/// Resolves the key a validator witnesses events with, among the keys of the latest finalized
+/// validator set found in the keystore. As keys might get inserted right after startup, a missing
+/// key is only reported once it is still missing after the [MISSING_KEY_DEADLINE]. Observers
+/// resolve nothing.
+pub(crate) async fn resolve_witnessing_key<Block, Client, AuthorityId>(
+	role: NodeRole,
+	keystore: Arc<dyn CryptoStore>,
+	client: Arc<Client>,
+	validator_set: ValidatorSetHandle<Block>,
+	missing_key: MissingKeyPolicy,
+) -> Result<(), StartupError>
+where
+	Block: BlockT,
+	Client: ChainAccess<Block, AuthorityId>,
+{
+	if role == NodeRole::Observer {
+		tracing::info!(target: SERVICE, "Not a validator; observing events without witnessing");
+		return Ok(())
+	}
+	let deadline = Instant::now() + MISSING_KEY_DEADLINE;
+	loop {
+		let authorities = get_latest_authorities_list(&validator_set, client.as_ref())
+			.map_err(|e| StartupError::Keystore(e.to_string()))?;
+		let keys = keystore
+			.supported_keys(AURA, authorities.authorities.to_vec())
+			.await
+			.map_err(|e| StartupError::Keystore(e.to_string()))?;
+		if let Some(key) = keys.first() {
+			tracing::info!(target: SERVICE, key = ?key, "Witnessing events");
+			return Ok(())
+		}
+		if Instant::now() >= deadline {
+			break
+		}
+		tokio::time::sleep(MISSING_KEY_RETRY_INTERVAL).await;
+	}
+
+	let error = StartupError::MissingKey {
+		key_type: String::from_utf8_lossy(&AURA.0).into_owned(),
+		keystore_path: missing_key.keystore_path.map(|path| path.display().to_string()),
+	};
+	if !missing_key.allow {
+		return Err(error)
+	}
+	tracing::warn!(target: SERVICE, error = %error, "Running as a validator without a key");
+	Ok(())
+}
+
+/// Keeps the quorum the gossip mesh is expected to reach up to date with the latest finalized
+/// validator set, until the client stops producing finality notifications.
+async fn track_mesh_quorum<Block, Client, AuthorityId>(
+	client: Arc<Client>,
+	validator_set: ValidatorSetHandle<Block>,
+	mesh_expectations: MeshExpectations,
+	heartbeat: Heartbeat,
+) where
+	Block: BlockT,
+	Client: BlockchainEvents<Block> + ChainAccess<Block, AuthorityId>,
+{
+	let mut finality_notifications = client.finality_notification_stream();
+	loop {
+		match get_latest_authorities_list(&validator_set, client.as_ref()) {
+			Ok(authorities) => mesh_expectations.set_quorum(authorities.target().into()),
+			Err(e) => {
+				tracing::warn!(target: GOSSIP, error = %e, "Failed reading the validator set")
+			},
+		}
+		if next_beating(&mut finality_notifications, &heartbeat).await.is_none() {
+			return
+		}
+	}
+}
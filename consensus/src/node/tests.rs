@@ -0,0 +1,606 @@
+use super::{resolve_witnessing_key, supervise, MissingKeyPolicy, MISSING_KEY_DEADLINE};
+use crate::{
+	auth::{ApiKeys, ClientCertificates},
+	errors::{Error, StartupError},
+	gossip::Gossip,
+	health::HEALTH_SERVICES,
+	index::InMemoryEventIndex,
+	limits::ClientLimits,
+	logging::SERVICE,
+	metrics::Metrics,
+	notifications::EventNotifications,
+	role::LocalRole,
+	server::{
+		self,
+		validated_streams_proto::{
+			admin_client::AdminClient, get_event_status_response::Status as ProtoStatus,
+			streams_client::StreamsClient, GetApiVersionRequest, GetApiVersionResponse,
+			GetEventStatusRequest, GetValidatorInfoRequest, UpdateConfigRequest,
+			WitnessEventRequest,
+		},
+		ServerParams,
+	},
+	shutdown::{ShutdownSignal, ShutdownStage},
+	startup::StartupSignals,
+	status::EventStatuses,
+	telemetry::NodeRole,
+	test_utils::{
+		single_validator, validator_set, CapturedLogs, FakeChain, NoEventProofs, NoFinalizedEvents,
+		TestBlock, TestValidators,
+	},
+	traces::Traces,
+	traits::EventWitnesserTrait,
+	tunables::Tunables,
+};
+use futures::{stream, StreamExt};
+use prost::Message;
+use sc_keystore::LocalKeystore;
+use sp_core::{sr25519::Public, H256};
+use sp_keystore::SyncCryptoStore;
+use sp_runtime::key_types::AURA;
+use std::{
+	collections::HashMap,
+	net::{SocketAddr, TcpListener},
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::TcpStream,
+	time::Instant,
+};
+use tonic::{
+	client::Grpc,
+	codec::{CompressionEncoding, ProstCodec},
+	codegen::http::uri::PathAndQuery,
+	transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity, ServerTlsConfig},
+	Code, Request,
+};
+use tonic_health::pb::{
+	health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+};
+use tonic_reflection::pb::{
+	server_reflection_client::ServerReflectionClient, server_reflection_request::MessageRequest,
+	server_reflection_response::MessageResponse, ServerReflectionRequest,
+};
+
+#[tokio::test]
+async fn test_supervise_returns_only_on_fatal_errors() {
+	let (logs, _guard) = CapturedLogs::capture();
+
+	let fatal = StartupError::Gossip("no address".to_string());
+	supervise("fatal", async move { Err(fatal) }).await;
+	let lines = logs.find(SERVICE, "Fatal error; shutting the node down");
+	assert_eq!(lines.len(), 1);
+	assert_eq!(lines[0].field("task"), Some("fatal"));
+
+	// Returning would end the essential task, and with it the node
+	let not_fatal = StartupError::Keystore("unreadable".to_string());
+	let idle = supervise("not_fatal", async move { Err(not_fatal) });
+	assert!(tokio::time::timeout(Duration::from_millis(100), idle).await.is_err());
+	assert_eq!(logs.find(SERVICE, "Task failed").len(), 1);
+	let done = supervise("done", async { Ok(()) });
+	assert!(tokio::time::timeout(Duration::from_millis(100), done).await.is_err());
+}
+
+/// Runs the gRPC server on the address until the shutdown drains the requests, serving gRPC-web to
+/// the given origins.
+async fn run_server(
+	address: SocketAddr,
+	tls: Option<ServerTlsConfig>,
+	api_keys: ApiKeys,
+	web_origins: &[&str],
+	shutdown: ShutdownSignal,
+) -> Result<(), StartupError> {
+	let (certificates, limits) = (ClientCertificates::default(), ClientLimits::default());
+	run_limited_server(address, tls, api_keys, certificates, web_origins, limits, shutdown).await
+}
+
+/// Runs the gRPC server as [run_server] does, with limits on its clients and the certificates they
+/// must present.
+async fn run_limited_server(
+	address: SocketAddr,
+	tls: Option<ServerTlsConfig>,
+	api_keys: ApiKeys,
+	client_certificates: ClientCertificates,
+	web_origins: &[&str],
+	limits: ClientLimits,
+	shutdown: ShutdownSignal,
+) -> Result<(), StartupError> {
+	let (witnesser, _network) = single_validator();
+	server::run(ServerParams {
+		event_witnesser: Arc::new(witnesser),
+		event_validator: Arc::new(NoFinalizedEvents),
+		grpc_addrs: vec![address],
+		tls,
+		api_keys,
+		client_certificates,
+		web_origins: web_origins.iter().map(|origin| origin.to_string()).collect(),
+		limits,
+		metrics: Metrics::default(),
+		traces: Traces::default(),
+		startup: StartupSignals::ready(),
+		tunables: Tunables::default(),
+		payloads: None,
+		max_payload_size: 0,
+		event_index: Arc::new(InMemoryEventIndex::new()),
+		notifications: EventNotifications::default(),
+		statuses: EventStatuses::default(),
+		event_proofs: Arc::new(NoEventProofs),
+		gossip: Gossip::create().0,
+		shutdown,
+	})
+	.await
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_grpc_server_fails_on_occupied_port() {
+	let occupied = TcpListener::bind("127.0.0.1:0").unwrap();
+	let address = occupied.local_addr().unwrap();
+
+	match run_server(address, None, ApiKeys::default(), &[], ShutdownSignal::default()).await {
+		Err(e @ StartupError::Server(failed, _)) => {
+			assert_eq!(failed, address);
+			assert!(e.is_fatal());
+		},
+		other => panic!("expected a server startup error, got {other:?}"),
+	}
+}
+
+#[tokio::test]
+async fn test_grpc_server_waits_for_its_port() {
+	let occupied = TcpListener::bind("127.0.0.1:0").unwrap();
+	let (address, shutdown) = (occupied.local_addr().unwrap(), ShutdownSignal::default());
+	let server = tokio::spawn(run_server(address, None, ApiKeys::default(), &[], shutdown.clone()));
+	tokio::time::sleep(Duration::from_millis(300)).await;
+	assert!(!server.is_finished());
+
+	// As when the previous run of the node releases it
+	drop(occupied);
+	let started = async {
+		while TcpStream::connect(address).await.is_err() {
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+	};
+	assert!(tokio::time::timeout(Duration::from_secs(10), started).await.is_ok());
+	shutdown.advance(ShutdownStage::DrainingRequests);
+	assert!(matches!(server.await, Ok(Ok(()))));
+}
+
+#[tokio::test]
+async fn test_grpc_server_stops_with_the_shutdown() {
+	let (address, shutdown) = (free_address(), ShutdownSignal::default());
+	let server = tokio::spawn(run_server(address, None, ApiKeys::default(), &[], shutdown.clone()));
+	while TcpStream::connect(address).await.is_err() {
+		tokio::time::sleep(Duration::from_millis(10)).await;
+	}
+
+	shutdown.advance(ShutdownStage::DrainingRequests);
+	let stopped = tokio::time::timeout(Duration::from_secs(5), server).await;
+	assert!(matches!(stopped, Ok(Ok(Ok(())))), "{stopped:?}");
+	// Leaving the port to the next run of the node
+	assert!(TcpStream::connect(address).await.is_err());
+	let restarted = run_server(address, None, ApiKeys::default(), &[], shutdown.clone());
+	let restarted = tokio::time::timeout(Duration::from_secs(5), tokio::spawn(restarted)).await;
+	assert!(matches!(restarted, Ok(Ok(Ok(())))), "{restarted:?}");
+}
+
+/// A test certificate or key, issued by the test CA of `ca.pem`: `server` to `localhost`, and
+/// `client` to a trusted client.
+fn testdata(file: &str) -> PathBuf {
+	PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/node/testdata").join(file)
+}
+
+/// A local address nothing listens on.
+fn free_address() -> SocketAddr {
+	TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+/// Starts the gRPC server in the background, and waits for it to listen.
+async fn start_server(
+	tls: Option<ServerTlsConfig>,
+	api_keys: ApiKeys,
+) -> (SocketAddr, ShutdownSignal) {
+	let (address, shutdown) = (free_address(), ShutdownSignal::default());
+	tokio::spawn(run_server(address, tls, api_keys, &[], shutdown.clone()));
+	while TcpStream::connect(address).await.is_err() {
+		tokio::time::sleep(Duration::from_millis(10)).await;
+	}
+	(address, shutdown)
+}
+
+/// A TLS client trusting the test CA, with the given identity.
+fn tls_client(address: SocketAddr, identity: Option<Identity>) -> Endpoint {
+	let ca = Certificate::from_pem(std::fs::read(testdata("ca.pem")).unwrap());
+	let tls = ClientTlsConfig::new().ca_certificate(ca).domain_name("localhost");
+	let tls = match identity {
+		Some(identity) => tls.identity(identity),
+		None => tls,
+	};
+	Channel::from_shared(format!("https://{address}")).unwrap().tls_config(tls).unwrap()
+}
+
+/// Whether the client gets served a request.
+async fn served(endpoint: Endpoint) -> bool {
+	let Ok(channel) = endpoint.connect().await else { return false };
+	let request = GetEventStatusRequest { event_id: vec![1; 32] };
+	match StreamsClient::new(channel).get_event_status(request).await {
+		Ok(response) => response.into_inner().status == ProtoStatus::Unknown as i32,
+		Err(_) => false,
+	}
+}
+
+#[tokio::test]
+async fn test_grpc_server_over_tls() {
+	let tls = server::tls_config(&testdata("server.pem"), &testdata("server.key"), None);
+	let (address, shutdown) = start_server(Some(tls.unwrap()), ApiKeys::default()).await;
+
+	assert!(served(tls_client(address, None)).await);
+	// Plaintext clients are not
+	let plaintext = Channel::from_shared(format!("http://{address}")).unwrap();
+	assert!(!served(plaintext).await);
+	shutdown.advance(ShutdownStage::DrainingRequests);
+}
+
+#[tokio::test]
+async fn test_grpc_server_authenticates_clients() {
+	let (cert, key) = (testdata("server.pem"), testdata("server.key"));
+	let tls = server::tls_config(&cert, &key, Some(&testdata("ca.pem")));
+	let (address, shutdown) = start_server(Some(tls.unwrap()), ApiKeys::default()).await;
+
+	let read = |file| std::fs::read(testdata(file)).unwrap();
+	let identity = Identity::from_pem(read("client.pem"), read("client.key"));
+	assert!(served(tls_client(address, Some(identity))).await);
+	// Clients without a certificate are not
+	assert!(!served(tls_client(address, None)).await);
+	shutdown.advance(ShutdownStage::DrainingRequests);
+}
+
+#[tokio::test]
+async fn test_grpc_server_only_serves_allowed_certificates() {
+	// The SHA-256 fingerprints of the client and the server certificates
+	let client = "36:62:59:13:94:C1:93:CA:A3:44:32:55:D3:25:48:B3:\
+		42:CD:CE:E9:07:F8:4D:A9:C0:8C:0E:85:82:1A:8E:DF";
+	let other = "f3f9d2e23448c9deca7ba8eb2a4b653814be975ff39ac59cfe5cc7277ee06a44";
+	let read = |file| std::fs::read(testdata(file)).unwrap();
+	for (allowed, expected) in [(vec![client, other], Ok(())), (vec![other], Err(()))] {
+		let allowed: Vec<_> = allowed.into_iter().map(String::from).collect();
+		let certificates = ClientCertificates::parse(&allowed).unwrap();
+		let (cert, key) = (testdata("server.pem"), testdata("server.key"));
+		let tls = Some(server::tls_config(&cert, &key, Some(&testdata("ca.pem"))).unwrap());
+		let (address, signal) = (free_address(), ShutdownSignal::default());
+		let shutdown = signal.clone();
+		let (keys, limits) = (ApiKeys::default(), ClientLimits::default());
+		let server = run_limited_server(address, tls, keys, certificates, &[], limits, shutdown);
+		tokio::spawn(server);
+		while TcpStream::connect(address).await.is_err() {
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+
+		let identity = Identity::from_pem(read("client.pem"), read("client.key"));
+		let channel = tls_client(address, Some(identity)).connect().await.unwrap();
+		let request = GetEventStatusRequest { event_id: vec![1; 32] };
+		let result = StreamsClient::new(channel).get_event_status(request).await;
+		let result = result.map(|_| ()).map_err(|status| status.code());
+		assert_eq!(result, expected.map_err(|()| Code::PermissionDenied), "{allowed:?}");
+		signal.advance(ShutdownStage::DrainingRequests);
+	}
+}
+
+/// A request carrying the key as a bearer token, if any.
+fn with_key<T>(message: T, key: Option<&str>) -> Request<T> {
+	let mut request = Request::new(message);
+	if let Some(key) = key {
+		let value = format!("Bearer {key}").parse().unwrap();
+		request.metadata_mut().insert("authorization", value);
+	}
+	request
+}
+
+#[tokio::test]
+async fn test_grpc_server_tells_its_api_version_under_both_packages() {
+	let (address, shutdown) = start_server(None, ApiKeys::default()).await;
+	let channel = Channel::from_shared(format!("http://{address}")).unwrap().connect();
+	let channel = channel.await.unwrap();
+
+	let mut client = StreamsClient::new(channel.clone());
+	let version = client.get_api_version(GetApiVersionRequest {}).await.unwrap().into_inner();
+	assert_eq!(version.package, server::API_PACKAGE);
+	assert_eq!((version.major, version.minor), (1, server::API_MINOR_VERSION));
+	assert!(!version.node_version.is_empty());
+
+	// As a client compiled against the unversioned package calls it
+	let mut unversioned = Grpc::new(channel);
+	unversioned.ready().await.unwrap();
+	let path = PathAndQuery::from_static("/ValidatedStreams.Streams/GetApiVersion");
+	let request = Request::new(GetApiVersionRequest {});
+	let response: tonic::Response<GetApiVersionResponse> =
+		unversioned.unary(request, path, ProstCodec::default()).await.unwrap();
+	assert_eq!(response.into_inner(), version);
+	shutdown.advance(ShutdownStage::DrainingRequests);
+}
+
+#[tokio::test]
+async fn test_grpc_server_compresses_for_clients_accepting_it() {
+	let (address, shutdown) = start_server(None, ApiKeys::default()).await;
+	let channel = Channel::from_shared(format!("http://{address}")).unwrap().connect();
+	let client = StreamsClient::new(channel.await.unwrap());
+	let mut compressed = client
+		.clone()
+		.send_compressed(CompressionEncoding::Gzip)
+		.accept_compressed(CompressionEncoding::Gzip);
+
+	let response = compressed.get_validator_info(GetValidatorInfoRequest {}).await.unwrap();
+	assert_eq!(response.metadata().get("grpc-encoding").unwrap(), "gzip");
+	assert_eq!(response.into_inner().validators.len(), 1);
+	let response = client.clone().get_validator_info(GetValidatorInfoRequest {}).await.unwrap();
+	assert!(response.metadata().get("grpc-encoding").is_none());
+	shutdown.advance(ShutdownStage::DrainingRequests);
+}
+
+#[tokio::test]
+async fn test_grpc_server_tells_its_validators() {
+	let (address, shutdown) = start_server(None, ApiKeys::default()).await;
+	let channel = Channel::from_shared(format!("http://{address}")).unwrap().connect();
+	let mut client = StreamsClient::new(channel.await.unwrap());
+
+	let info = client.get_validator_info(GetValidatorInfoRequest {}).await.unwrap().into_inner();
+	let validators = vec![TestValidators::new(1).pub_key(0).1];
+	assert_eq!((info.session, info.validators, info.target), (0, validators, 1));
+	assert!(info.witnessing);
+	shutdown.advance(ShutdownStage::DrainingRequests);
+}
+
+#[tokio::test]
+async fn test_grpc_server_takes_streams_of_events() {
+	let (address, shutdown) = start_server(None, ApiKeys::default()).await;
+	let channel = Channel::from_shared(format!("http://{address}")).unwrap().connect();
+	let mut client = StreamsClient::new(channel.await.unwrap());
+
+	let event = |event_id: Vec<u8>| WitnessEventRequest { event_id, ..Default::default() };
+	let mut events: Vec<_> = (0..200).map(|byte| event(vec![byte; 32])).collect();
+	events.insert(10, event(vec![1; 31]));
+	// Payloads are refused by this node
+	events.insert(20, WitnessEventRequest { payload: vec![1], ..event(vec![0; 32]) });
+	let response = client.submit_events(stream::iter(events)).await.unwrap().into_inner();
+	assert_eq!(response.accepted, 200);
+	let expected = [(Code::InvalidArgument as u32, 1), (Code::FailedPrecondition as u32, 1)];
+	assert_eq!(response.rejected, HashMap::from(expected));
+
+	let events = stream::iter(Vec::<WitnessEventRequest>::new());
+	let response = client.submit_events(events).await.unwrap().into_inner();
+	assert_eq!((response.accepted, response.rejected.len()), (0, 0));
+	shutdown.advance(ShutdownStage::DrainingRequests);
+}
+
+/// The response head of a gRPC-web call of `GetEventStatus` from a page of the origin, over
+/// HTTP/1.1.
+async fn grpc_web_call(address: SocketAddr, origin: &str) -> String {
+	let message = GetEventStatusRequest { event_id: vec![1; 32] }.encode_to_vec();
+	let body = [&[0][..], &(message.len() as u32).to_be_bytes(), &message[..]].concat();
+	let head = format!(
+		"POST /validated_streams.v1.Streams/GetEventStatus HTTP/1.1\r\nHost: {address}\r\n\
+		 Origin: {origin}\r\nContent-Type: application/grpc-web+proto\r\nX-Grpc-Web: 1\r\n\
+		 Content-Length: {}\r\nConnection: close\r\n\r\n",
+		body.len()
+	);
+	let mut stream = TcpStream::connect(address).await.unwrap();
+	stream.write_all(&[head.as_bytes(), &body[..]].concat()).await.unwrap();
+	let mut response = Vec::new();
+	stream.read_to_end(&mut response).await.unwrap();
+	let response = String::from_utf8_lossy(&response);
+	response.split("\r\n\r\n").next().unwrap().to_lowercase()
+}
+
+#[tokio::test]
+async fn test_grpc_server_serves_grpc_web() {
+	let (address, shutdown) = (free_address(), ShutdownSignal::default());
+	let origins = &["https://dashboard.example"];
+	tokio::spawn(run_server(address, None, ApiKeys::default(), origins, shutdown.clone()));
+	while TcpStream::connect(address).await.is_err() {
+		tokio::time::sleep(Duration::from_millis(10)).await;
+	}
+
+	let head = grpc_web_call(address, "https://dashboard.example").await;
+	assert!(head.starts_with("http/1.1 200"), "{head}");
+	assert!(head.contains("content-type: application/grpc-web+proto"), "{head}");
+	assert!(head.contains("access-control-allow-origin: https://dashboard.example"), "{head}");
+	// Pages of other origins are refused
+	let head = grpc_web_call(address, "https://elsewhere.example").await;
+	assert!(head.starts_with("http/1.1 403"), "{head}");
+	shutdown.advance(ShutdownStage::DrainingRequests);
+}
+
+#[tokio::test]
+async fn test_grpc_server_requires_api_keys() {
+	let (address, shutdown) = start_server(None, ApiKeys::new(["first", "second"])).await;
+	let channel = Channel::from_shared(format!("http://{address}")).unwrap().connect();
+	let channel = channel.await.unwrap();
+	let (mut streams, mut admin) = (StreamsClient::new(channel.clone()), AdminClient::new(channel));
+
+	let status = |key| with_key(GetEventStatusRequest { event_id: vec![1; 32] }, key);
+	for key in ["first", "second"] {
+		assert!(streams.get_event_status(status(Some(key))).await.is_ok(), "{key}");
+	}
+	for key in [None, Some("third")] {
+		let code = streams.get_event_status(status(key)).await.unwrap_err().code();
+		assert_eq!(code, Code::Unauthenticated, "{key:?}");
+	}
+	// The admin service as well
+	let config = |key| with_key(UpdateConfigRequest { changes: Default::default() }, key);
+	assert!(admin.update_config(config(Some("first"))).await.is_ok());
+	let code = admin.update_config(config(None)).await.unwrap_err().code();
+	assert_eq!(code, Code::Unauthenticated);
+	shutdown.advance(ShutdownStage::DrainingRequests);
+}
+
+#[tokio::test]
+async fn test_grpc_server_limits_its_clients() {
+	let (address, shutdown) = (free_address(), ShutdownSignal::default());
+	let limits = ClientLimits { rate_limit: 2, max_connections: 1, ..Default::default() };
+	let keys = ApiKeys::new(["first", "second"]);
+	let certificates = ClientCertificates::default();
+	let signal = shutdown.clone();
+	let server = run_limited_server(address, None, keys, certificates, &[], limits, signal);
+	tokio::spawn(server);
+	while TcpStream::connect(address).await.is_err() {
+		tokio::time::sleep(Duration::from_millis(10)).await;
+	}
+	let endpoint = Channel::from_shared(format!("http://{address}")).unwrap();
+	let mut streams = StreamsClient::new(endpoint.connect().await.unwrap());
+
+	let status = |key| with_key(GetEventStatusRequest { event_id: vec![1; 32] }, Some(key));
+	for _ in 0..2 {
+		assert!(streams.get_event_status(status("first")).await.is_ok());
+	}
+	let code = streams.get_event_status(status("first")).await.unwrap_err().code();
+	assert_eq!(code, Code::ResourceExhausted);
+	// The other clients are not held back by it
+	assert!(streams.get_event_status(status("second")).await.is_ok());
+
+	// Further connections wait for the open one to close
+	let waiting = tokio::spawn(served(endpoint.clone()));
+	tokio::time::sleep(Duration::from_millis(200)).await;
+	assert!(!waiting.is_finished());
+	drop(streams);
+	assert!(tokio::time::timeout(Duration::from_secs(5), waiting).await.unwrap().unwrap());
+	shutdown.advance(ShutdownStage::DrainingRequests);
+}
+
+#[tokio::test]
+async fn test_grpc_server_reports_its_health() {
+	let (address, shutdown) = start_server(None, ApiKeys::new(["first"])).await;
+	let channel = Channel::from_shared(format!("http://{address}")).unwrap().connect();
+	let mut client = HealthClient::new(channel.await.unwrap());
+
+	// Without an API key, as probes cannot send one
+	for service in HEALTH_SERVICES {
+		let request = HealthCheckRequest { service: service.to_string() };
+		let response = client.check(request).await.unwrap().into_inner();
+		assert_eq!(response.status, ServingStatus::Serving as i32, "{service:?}");
+	}
+	let request = HealthCheckRequest { service: "validated_streams.v1.Unknown".to_string() };
+	assert_eq!(client.check(request).await.unwrap_err().code(), Code::NotFound);
+	shutdown.advance(ShutdownStage::DrainingRequests);
+}
+
+#[tokio::test]
+async fn test_grpc_server_reflects_its_services() {
+	let (address, shutdown) = start_server(None, ApiKeys::default()).await;
+	let channel = Channel::from_shared(format!("http://{address}")).unwrap().connect();
+	let mut client = ServerReflectionClient::new(channel.await.unwrap());
+
+	let request = ServerReflectionRequest {
+		host: String::new(),
+		message_request: Some(MessageRequest::ListServices(String::new())),
+	};
+	let response = client.server_reflection_info(stream::iter([request])).await.unwrap();
+	let response = response.into_inner().next().await.unwrap().unwrap();
+	let list = match response.message_response {
+		Some(MessageResponse::ListServicesResponse(list)) => list,
+		other => panic!("expected the list of services, got {other:?}"),
+	};
+	let mut services: Vec<_> = list.service.into_iter().map(|service| service.name).collect();
+	services.sort();
+	let expected = [
+		"grpc.reflection.v1alpha.ServerReflection",
+		"validated_streams.v1.Admin",
+		"validated_streams.v1.Streams",
+	];
+	assert_eq!(services, expected);
+	shutdown.advance(ShutdownStage::DrainingRequests);
+}
+
+#[tokio::test]
+async fn test_grpc_server_fails_on_bad_tls_files() {
+	let missing = server::tls_config(&testdata("missing.pem"), &testdata("server.key"), None);
+	match missing.err() {
+		Some(e @ StartupError::Tls(_)) => {
+			assert!(e.to_string().contains("missing.pem"), "{e}");
+			assert!(e.is_fatal());
+		},
+		other => panic!("expected a TLS error, got {other:?}"),
+	}
+
+	// Read, but not told apart until the server starts
+	let tls = server::tls_config(&testdata("server.key"), &testdata("server.pem"), None).unwrap();
+	let address = free_address();
+	let result =
+		run_server(address, Some(tls), ApiKeys::default(), &[], ShutdownSignal::default()).await;
+	assert!(matches!(result, Err(StartupError::Server(failed, _)) if failed == address));
+}
+
+fn policy(allow: bool) -> MissingKeyPolicy {
+	MissingKeyPolicy { keystore_path: Some(PathBuf::from("/tmp/keystore")), allow }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_witnessing_key_by_role() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let validators = TestValidators::new(2);
+	let chain = Arc::new(FakeChain::new(vec![validators.pub_key(0)]));
+	// Validator 0 holds a key of the validator set; validator 1 does not
+	let resolve = |role: NodeRole, i: usize, allow: bool| {
+		resolve_witnessing_key::<TestBlock, _, Public>(
+			role,
+			validators.keystore(i),
+			chain.clone(),
+			validator_set(),
+			policy(allow),
+		)
+	};
+
+	assert_eq!(resolve(NodeRole::Validator, 0, false).await, Ok(()));
+	assert_eq!(logs.find(SERVICE, "Witnessing events").len(), 1);
+
+	let started = Instant::now();
+	let error = resolve(NodeRole::Validator, 1, false).await.unwrap_err();
+	assert!(started.elapsed() >= MISSING_KEY_DEADLINE);
+	assert!(error.is_fatal());
+	let message = error.to_string();
+	assert!(message.contains("aura") && message.contains("/tmp/keystore"), "{message}");
+	assert_eq!(resolve(NodeRole::Validator, 1, true).await, Ok(()));
+	assert_eq!(logs.find(SERVICE, "Running as a validator without a key").len(), 1);
+
+	let observing = "Not a validator; observing events without witnessing";
+	assert_eq!(resolve(NodeRole::Observer, 0, false).await, Ok(()));
+	assert_eq!(resolve(NodeRole::Observer, 1, false).await, Ok(()));
+	assert_eq!(logs.find(SERVICE, observing).len(), 2);
+
+	chain.set_pruned(true);
+	let error = resolve(NodeRole::Validator, 0, false).await.unwrap_err();
+	assert!(matches!(error, StartupError::Keystore(_)));
+	assert!(!error.is_fatal());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_key_inserted_before_deadline() {
+	let validators = TestValidators::new(1);
+	let keystore = Arc::new(LocalKeystore::in_memory());
+	let resolve = resolve_witnessing_key::<TestBlock, _, Public>(
+		NodeRole::Validator,
+		keystore.clone(),
+		Arc::new(FakeChain::new(validators.pubkeys())),
+		validator_set(),
+		policy(false),
+	);
+	let insert = async {
+		tokio::time::sleep(MISSING_KEY_DEADLINE / 2).await;
+		// The seed of validator 0
+		SyncCryptoStore::sr25519_generate_new(&*keystore, AURA, Some("//Validator0")).unwrap();
+	};
+	let (resolved, ()) = futures::join!(resolve, insert);
+	assert_eq!(resolved, Ok(()));
+}
+
+#[tokio::test]
+async fn test_observer_refuses_to_witness() {
+	let (witnesser, network) = single_validator();
+	let role = LocalRole::new(NodeRole::Observer, Metrics::default());
+	let observer = witnesser.with_role(role);
+
+	let result = observer.witness_event(H256::repeat_byte(1)).await;
+	assert_eq!(result, Err(Error::NotAValidator));
+	assert!(network.trace().is_empty());
+}
@@ -0,0 +1,131 @@
+//! Push notifications of the progress of events, for the `SubscribeValidatedEvents` RPC. The
+//! gossip handler notifies every event it submits once it reaches the witnessing threshold, and a
+//! task following finality notifies every event of each newly finalized block. Notifications are
+//! broadcast to all subscribers; one which falls more than [NOTIFICATIONS_CAPACITY] notifications
+//! behind misses the oldest of them, and is told so.
+
+use crate::{
+	logging::SERVICE,
+	metrics::block_event_ids,
+	proofs::EventProofsTrait,
+	traits::ChainAccess,
+	watchdog::{next_beating, Heartbeat},
+};
+use sc_client_api::{BlockBackend, BlockchainEvents};
+use sp_api::BlockT;
+use sp_core::H256;
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+#[cfg(test)]
+pub mod tests;
+
+/// How many notifications a subscriber can fall behind before it misses some.
+pub const NOTIFICATIONS_CAPACITY: usize = 1024;
+
+/// How far an event has gone when it is notified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventStage {
+	/// Witnessed by enough validators, and submitted to the transaction pool by this node
+	Submitted,
+	/// Validated in a finalized block
+	Finalized,
+}
+
+/// A notification of the progress of an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EventNotification {
+	/// The id of the event
+	pub event_id: H256,
+	/// How far the event has gone
+	pub stage: EventStage,
+	/// The finalized block which validated the event; [None] if it is only submitted
+	pub block_hash: Option<H256>,
+	/// The number of witnesses of the event this node holds, from the validators of the block
+	pub witness_count: u16,
+}
+
+/// The channel through which notifications are broadcast to the subscribers. Cloning it gives
+/// another handle to the same channel.
+#[derive(Clone)]
+pub struct EventNotifications {
+	sender: broadcast::Sender<EventNotification>,
+}
+
+impl Default for EventNotifications {
+	fn default() -> Self {
+		Self { sender: broadcast::channel(NOTIFICATIONS_CAPACITY).0 }
+	}
+}
+
+impl EventNotifications {
+	/// Receives every notification from now on.
+	pub fn subscribe(&self) -> broadcast::Receiver<EventNotification> {
+		self.sender.subscribe()
+	}
+
+	/// Whether anyone is subscribed; notifications are dropped otherwise.
+	pub fn has_subscribers(&self) -> bool {
+		self.sender.receiver_count() > 0
+	}
+
+	/// Broadcasts a notification to the current subscribers.
+	pub fn notify(&self, notification: EventNotification) {
+		// Fails only without subscribers, who would not have received it anyway
+		self.sender.send(notification).ok();
+	}
+}
+
+/// The notifications of the events validated in a finalized block, with their witnesses among
+/// the given validators of the block.
+pub fn finalized_notifications(
+	event_proofs: &impl EventProofsTrait,
+	block_hash: H256,
+	event_ids: &[H256],
+	validators: &[CryptoTypePublicPair],
+) -> Vec<EventNotification> {
+	event_ids
+		.iter()
+		.map(|&event_id| EventNotification {
+			event_id,
+			stage: EventStage::Finalized,
+			block_hash: Some(block_hash),
+			witness_count: event_proofs.get_event_proof_count(&event_id, validators).unwrap_or(0),
+		})
+		.collect()
+}
+
+/// Notifies the events of every newly-finalized block, while anyone is subscribed, until the
+/// client stops producing finality notifications.
+pub(crate) async fn notify_finalized_events<Block, Client, AuthorityId, EventProofs>(
+	client: Arc<Client>,
+	event_proofs: Arc<EventProofs>,
+	notifications: EventNotifications,
+	heartbeat: Heartbeat,
+) where
+	Block: BlockT,
+	Client: BlockchainEvents<Block> + BlockBackend<Block> + ChainAccess<Block, AuthorityId>,
+	EventProofs: EventProofsTrait,
+{
+	let mut finality_notifications = client.finality_notification_stream();
+	while let Some(notification) = next_beating(&mut finality_notifications, &heartbeat).await {
+		if !notifications.has_subscribers() {
+			continue
+		}
+		for hash in notification.tree_route.iter().chain([&notification.hash]) {
+			let Some(event_ids) = block_event_ids(client.as_ref(), *hash) else { continue };
+			if event_ids.is_empty() {
+				continue
+			}
+			let validators = client.authorities(*hash).unwrap_or_else(|e| {
+				tracing::warn!(target: SERVICE, error = %e, "Failed reading the validators");
+				vec![]
+			});
+			let block_hash = H256::from_slice(hash.as_ref());
+			let finalized =
+				finalized_notifications(event_proofs.as_ref(), block_hash, &event_ids, &validators);
+			finalized.into_iter().for_each(|notification| notifications.notify(notification));
+		}
+	}
+}
@@ -0,0 +1,199 @@
+use super::{
+	finalized_notifications, EventNotification, EventNotifications, EventStage,
+	NOTIFICATIONS_CAPACITY,
+};
+use crate::{
+	events::EventGossipHandler,
+	gossip::GossipHandler,
+	metrics::Metrics,
+	proofs::{EventProofsTrait, InMemoryEventProofs},
+	server::{
+		validated_streams_proto::{
+			streams_server::Streams, validated_event_notification::Stage, EventConfirmation,
+			SubscribeValidatedEventsRequest, ValidatedEventNotification, WitnessEventRequest,
+		},
+		ValidatedStreamsGrpc,
+	},
+	status::EventStatus,
+	test_utils::{
+		single_validator, validator_set, FakeChain, TestBlock, TestGrpc, TestPool, TestValidators,
+	},
+	traces::Traces,
+};
+use futures::{stream, FutureExt, StreamExt};
+use sp_core::{sr25519::Public, H256};
+use std::sync::Arc;
+use tonic::{Code, Request};
+
+fn submitted(event_id: H256, witness_count: u16) -> EventNotification {
+	EventNotification { event_id, stage: EventStage::Submitted, block_hash: None, witness_count }
+}
+
+fn finalized(event_id: H256, block_hash: H256, witness_count: u16) -> EventNotification {
+	let block_hash = Some(block_hash);
+	EventNotification { event_id, stage: EventStage::Finalized, block_hash, witness_count }
+}
+
+#[test]
+fn test_notifications_broadcast_to_every_subscriber() {
+	let notifications = EventNotifications::default();
+	assert!(!notifications.has_subscribers());
+	// Dropped without subscribers
+	notifications.notify(submitted(H256::repeat_byte(1), 1));
+
+	let (mut first, mut second) = (notifications.subscribe(), notifications.clone().subscribe());
+	assert!(notifications.has_subscribers());
+	notifications.notify(submitted(H256::repeat_byte(2), 1));
+	for receiver in [&mut first, &mut second] {
+		assert_eq!(receiver.try_recv(), Ok(submitted(H256::repeat_byte(2), 1)));
+		assert!(receiver.try_recv().is_err());
+	}
+	drop((first, second));
+	assert!(!notifications.has_subscribers());
+}
+
+#[test]
+fn test_finalized_notifications_count_the_witnesses() {
+	let validators = TestValidators::new(3);
+	let event_proofs = InMemoryEventProofs::new();
+	let (witnessed, unknown) = (H256::repeat_byte(1), H256::repeat_byte(2));
+	for i in 0..2 {
+		event_proofs.add_event_proof(&validators.witness(i, witnessed).build()).unwrap();
+	}
+	let (block_hash, pubkeys) = (H256::repeat_byte(9), validators.pubkeys());
+
+	let notifications =
+		finalized_notifications(&event_proofs, block_hash, &[witnessed, unknown], &pubkeys);
+	let expected = [finalized(witnessed, block_hash, 2), finalized(unknown, block_hash, 0)];
+	assert_eq!(notifications, expected);
+	// Only witnesses of the given validators count
+	let notifications =
+		finalized_notifications(&event_proofs, block_hash, &[witnessed], &pubkeys[1..]);
+	assert_eq!(notifications, [finalized(witnessed, block_hash, 1)]);
+}
+
+#[tokio::test]
+async fn test_submitted_events_are_notified() {
+	// A single validator reaches the target with its own witness
+	let validators = TestValidators::new(1);
+	let notifications = EventNotifications::default();
+	let pool = Arc::new(TestPool::default());
+	let handler = EventGossipHandler::<_, _, _, Public, TestBlock>::new(
+		Arc::new(FakeChain::new(validators.pubkeys())),
+		Arc::new(InMemoryEventProofs::new()),
+		pool.clone(),
+		validator_set(),
+		Metrics::default(),
+		Traces::default(),
+	)
+	.with_notifications(notifications.clone());
+	let witness = |event| validators.witness(0, H256::repeat_byte(event)).build().to_bytes();
+
+	// Nothing is kept for later subscribers
+	handler.handle(&witness(1).unwrap()).await;
+	let mut receiver = notifications.subscribe();
+	handler.handle(&witness(2).unwrap()).await;
+	// Nor notified again when already submitted
+	handler.handle(&witness(2).unwrap()).await;
+
+	assert_eq!(pool.submitted(), [H256::repeat_byte(1), H256::repeat_byte(2)]);
+	assert_eq!(receiver.try_recv(), Ok(submitted(H256::repeat_byte(2), 1)));
+	assert!(receiver.try_recv().is_err());
+}
+
+/// A gRPC service pushing the given notifications.
+fn grpc(notifications: EventNotifications) -> TestGrpc {
+	let (witnesser, _network) = single_validator();
+	ValidatedStreamsGrpc { notifications, ..ValidatedStreamsGrpc::for_tests(witnesser) }
+}
+
+async fn subscribe(
+	grpc: &TestGrpc,
+	finalized_only: bool,
+) -> <TestGrpc as Streams>::SubscribeValidatedEventsStream {
+	let request = Request::new(SubscribeValidatedEventsRequest { finalized_only });
+	grpc.subscribe_validated_events(request).await.unwrap().into_inner()
+}
+
+#[tokio::test]
+async fn test_subscriptions_stream_the_notifications() {
+	let notifications = EventNotifications::default();
+	let grpc = grpc(notifications.clone());
+	let mut all = subscribe(&grpc, false).await;
+	let mut finalized_only = subscribe(&grpc, true).await;
+
+	let (event_id, block_hash) = (H256::repeat_byte(1), H256::repeat_byte(9));
+	notifications.notify(submitted(event_id, 3));
+	notifications.notify(finalized(event_id, block_hash, 4));
+
+	let expected_submitted = ValidatedEventNotification {
+		event_id: event_id.0.to_vec(),
+		stage: Stage::Submitted.into(),
+		block_hash: vec![],
+		witness_count: 3,
+	};
+	let expected_finalized = ValidatedEventNotification {
+		event_id: event_id.0.to_vec(),
+		stage: Stage::Finalized.into(),
+		block_hash: block_hash.0.to_vec(),
+		witness_count: 4,
+	};
+	assert_eq!(all.next().await.unwrap().unwrap(), expected_submitted);
+	assert_eq!(all.next().await.unwrap().unwrap(), expected_finalized);
+	assert_eq!(finalized_only.next().await.unwrap().unwrap(), expected_finalized);
+
+	// The streams end along with the notifications
+	drop((grpc, notifications));
+	assert!(all.next().await.is_none());
+	assert!(finalized_only.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_lagging_subscription_ends_with_data_loss() {
+	let notifications = EventNotifications::default();
+	let grpc = grpc(notifications.clone());
+	let mut subscription = subscribe(&grpc, false).await;
+
+	for i in 0..NOTIFICATIONS_CAPACITY as u64 + 1 {
+		notifications.notify(submitted(H256::from_low_u64_be(i), 1));
+	}
+	let status = subscription.next().await.unwrap().unwrap_err();
+	assert_eq!(status.code(), Code::DataLoss);
+	assert!(status.message().contains("missed 1 notifications"), "{}", status.message());
+	assert!(subscription.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_submitted_events_are_confirmed_as_they_reach_the_threshold() {
+	let notifications = EventNotifications::default();
+	let grpc = grpc(notifications.clone());
+	let (pending, confirmed) = (H256::repeat_byte(1), H256::repeat_byte(2));
+	grpc.statuses.advance(confirmed, EventStatus::ThresholdReached);
+	let event = |event_id: Vec<u8>| Ok(WitnessEventRequest { event_id, ..Default::default() });
+	let events = [event(pending.0.to_vec()), event(vec![3; 31]), event(confirmed.0.to_vec())];
+	let confirmations = grpc.handle_submit_and_confirm(stream::iter(events));
+	let mut confirmations = Box::pin(confirmations);
+
+	let confirmation = |event_id: H256| EventConfirmation {
+		event_id: event_id.0.to_vec(),
+		code: Code::Ok as u32,
+		message: String::new(),
+		witness_count: 0,
+	};
+	let mut answered = Vec::new();
+	for _ in 0..2 {
+		answered.push(confirmations.next().await.unwrap().unwrap());
+	}
+	answered.sort_by_key(|confirmation| confirmation.code);
+	assert_eq!(answered[0], confirmation(confirmed));
+	assert_eq!((answered[1].event_id.len(), answered[1].code), (31, Code::InvalidArgument as u32));
+	// Still waiting for the other one, past the end of the stream of the client
+	assert!(confirmations.next().now_or_never().is_none());
+
+	// As the gossip handler does, so that it is confirmed even if notified before witnessed
+	notifications.notify(submitted(H256::repeat_byte(4), 1));
+	grpc.statuses.advance(pending, EventStatus::ThresholdReached);
+	notifications.notify(submitted(pending, 1));
+	assert_eq!(confirmations.next().await.unwrap().unwrap(), confirmation(pending));
+	assert!(confirmations.next().await.is_none());
+}
@@ -0,0 +1,64 @@
+//! Validated streams event payload storage
+
+use super::EventPayloadsTrait;
+use crate::errors::Error;
+
+use sp_core::H256;
+use std::{
+	collections::{hash_map::Entry, HashMap, VecDeque},
+	sync::Mutex,
+};
+
+/// An in-memory store of event payloads.
+#[derive(Default)]
+pub struct InMemoryEventPayloads {
+	inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+	payloads: HashMap<H256, Vec<u8>>,
+	/// The stored events, in the order they were stored in, with the time they were stored at
+	stored: VecDeque<(u64, H256)>,
+}
+
+impl InMemoryEventPayloads {
+	/// Create an empty [InMemoryEventPayloads] instance.
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl EventPayloadsTrait for InMemoryEventPayloads {
+	fn add_event_payload(
+		&self,
+		event_id: &H256,
+		payload: &[u8],
+		stored_at: u64,
+	) -> Result<(), Error> {
+		let mut inner = self.inner.lock()?;
+		if let Entry::Vacant(e) = inner.payloads.entry(*event_id) {
+			e.insert(payload.to_vec());
+			inner.stored.push_back((stored_at, *event_id));
+		}
+		Ok(())
+	}
+
+	fn get_event_payload(&self, event_id: &H256) -> Result<Option<Vec<u8>>, Error> {
+		Ok(self.inner.lock()?.payloads.get(event_id).cloned())
+	}
+
+	fn purge_expired_payloads(&self, stored_before: u64) -> Result<usize, Error> {
+		let mut inner = self.inner.lock()?;
+		let mut deleted = 0;
+		while let Some(&(stored_at, event_id)) = inner.stored.front() {
+			if stored_at >= stored_before {
+				break
+			}
+			inner.stored.pop_front();
+			inner.payloads.remove(&event_id);
+			deleted += 1;
+		}
+		Ok(deleted)
+	}
+}
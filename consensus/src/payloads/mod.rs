@@ -0,0 +1,114 @@
+//! Optional storage of the payloads of events, for nodes started with `--streams-event-payloads`.
+//! The trusted client may submit the data of an event along with it; the node checks that the
+//! event id is the [canonical](pallet_validated_streams::payload::event_id) id of the data and that
+//! the data is no larger than `--streams-payload-max-size`, stores it once the event is witnessed,
+//! and serves it back through the `GetEventPayload` RPC, so that auditors can resolve an event id
+//! to its data without a blob store of their own. Payloads are kept apart from the proofs, for
+//! `--streams-payload-retention` seconds after they were stored, and are never gossiped nor put on
+//! chain.
+
+use crate::{errors::Error, logging::SERVICE, watchdog::Heartbeat};
+use pallet_validated_streams::payload::event_id as canonical_event_id;
+use sp_core::H256;
+use std::{
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(test)]
+pub mod tests;
+
+pub mod in_memory;
+pub use in_memory::InMemoryEventPayloads;
+
+pub mod offchain;
+pub use offchain::OffchainStorageEventPayloads;
+
+/// The default of `--streams-payload-max-size`, in bytes.
+pub const DEFAULT_MAX_PAYLOAD_SIZE: usize = 64 * 1024;
+/// How often the payloads past the retention period are deleted.
+pub const PURGE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A trait for storing the payloads of events, by event id.
+pub trait EventPayloadsTrait {
+	/// Stores the payload of an event, at the given UNIX time in seconds. Storing the payload of
+	/// an event again keeps the time it was first stored at.
+	fn add_event_payload(
+		&self,
+		event_id: &H256,
+		payload: &[u8],
+		stored_at: u64,
+	) -> Result<(), Error>;
+	/// Retrieves the payload of an event, if stored.
+	fn get_event_payload(&self, event_id: &H256) -> Result<Option<Vec<u8>>, Error>;
+	/// Deletes the payloads stored before the given UNIX time in seconds, and returns how many
+	/// were deleted.
+	fn purge_expired_payloads(&self, stored_before: u64) -> Result<usize, Error>;
+}
+
+/// The payload store of a node, along with the limits of what it stores. Cheap to clone.
+#[derive(Clone)]
+pub struct EventPayloads {
+	store: Arc<dyn EventPayloadsTrait + Send + Sync>,
+	max_size: usize,
+	retention: Duration,
+}
+
+impl EventPayloads {
+	/// Stores payloads of up to `max_size` bytes in the given store, for the `retention` period.
+	pub fn new(
+		store: Arc<dyn EventPayloadsTrait + Send + Sync>,
+		max_size: usize,
+		retention: Duration,
+	) -> Self {
+		Self { store, max_size, retention }
+	}
+
+	/// Checks that the payload would be stored for the event: that it is no larger than the
+	/// maximum size, and that the event id is its [canonical](canonical_event_id) one.
+	pub fn check(&self, event_id: &H256, payload: &[u8]) -> Result<(), Error> {
+		if payload.len() > self.max_size {
+			return Err(Error::PayloadTooLarge { size: payload.len(), max: self.max_size })
+		}
+		if canonical_event_id(payload) != *event_id {
+			return Err(Error::PayloadMismatch)
+		}
+		Ok(())
+	}
+
+	/// Checks and stores the payload of an event, as of now.
+	pub fn add(&self, event_id: &H256, payload: &[u8]) -> Result<(), Error> {
+		self.check(event_id, payload)?;
+		self.store.add_event_payload(event_id, payload, unix_now())
+	}
+
+	/// Retrieves the payload of an event, if stored.
+	pub fn get(&self, event_id: &H256) -> Result<Option<Vec<u8>>, Error> {
+		self.store.get_event_payload(event_id)
+	}
+
+	/// Deletes the payloads stored longer than the retention period as of the given UNIX time in
+	/// seconds, and returns how many were deleted.
+	pub fn purge_expired(&self, now: u64) -> Result<usize, Error> {
+		self.store.purge_expired_payloads(now.saturating_sub(self.retention.as_secs()))
+	}
+}
+
+/// The current UNIX time in seconds.
+fn unix_now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+/// Deletes the payloads past the retention period every [PURGE_INTERVAL], forever.
+pub(crate) async fn purge_expired_payloads(payloads: EventPayloads, heartbeat: Heartbeat) {
+	let mut interval = tokio::time::interval(PURGE_INTERVAL);
+	loop {
+		interval.tick().await;
+		heartbeat.bump();
+		match payloads.purge_expired(unix_now()) {
+			Ok(0) => {},
+			Ok(deleted) => tracing::debug!(target: SERVICE, deleted, "Deleted expired payloads"),
+			Err(e) => tracing::warn!(target: SERVICE, error = %e, "Failed deleting payloads"),
+		}
+	}
+}
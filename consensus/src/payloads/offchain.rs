@@ -0,0 +1,103 @@
+//! Validated streams event payload storage
+
+use super::EventPayloadsTrait;
+use crate::errors::Error;
+
+use sp_core::{offchain::OffchainStorage, H256};
+use std::sync::Mutex;
+
+/// A persistent database for storing event payloads in [OffchainStorage], apart from the proofs.
+pub struct OffchainStorageEventPayloads<Storage: OffchainStorage> {
+	// key value format:
+	// p <event id (32 bytes)> -> <stored at (u64)> <payload>
+	// l <sequence number (u64, big endian)> -> <event id>, in the order the payloads were stored
+	// in, from the `tail` to the `head` sequence numbers
+	storage: Storage,
+	/// Held while adding or deleting payloads, which move the `head` and the `tail`
+	log: Mutex<()>,
+}
+
+impl<Storage: OffchainStorage> OffchainStorageEventPayloads<Storage> {
+	/// The prefix under which data is persisted in the OffchainStorage
+	pub const OFFCHAIN_PREFIX: &[u8] = b"EventPayloads";
+	const HEAD: &[u8] = b"head";
+	const TAIL: &[u8] = b"tail";
+
+	/// Returns a OffchainStorageEventPayloads instance that persists data in the provided
+	/// [OffchainStorage]
+	pub fn new(storage: Storage) -> Self {
+		Self { storage, log: Mutex::new(()) }
+	}
+
+	fn payload_key(event_id: &[u8]) -> Vec<u8> {
+		[b"p".as_ref(), event_id].concat()
+	}
+
+	fn log_key(sequence: u64) -> Vec<u8> {
+		[b"l".as_ref(), &sequence.to_be_bytes()].concat()
+	}
+
+	fn get_sequence(&self, key: &[u8]) -> Result<u64, Error> {
+		match self.storage.get(Self::OFFCHAIN_PREFIX, key) {
+			Some(value) => Ok(bincode::deserialize(&value)?),
+			None => Ok(0),
+		}
+	}
+
+	fn set_sequence(&self, key: &[u8], sequence: u64) -> Result<(), Error> {
+		self.storage.clone().set(Self::OFFCHAIN_PREFIX, key, &bincode::serialize(&sequence)?);
+		Ok(())
+	}
+}
+
+impl<Storage: OffchainStorage> EventPayloadsTrait for OffchainStorageEventPayloads<Storage> {
+	fn add_event_payload(
+		&self,
+		event_id: &H256,
+		payload: &[u8],
+		stored_at: u64,
+	) -> Result<(), Error> {
+		let _log = self.log.lock()?;
+		let key = Self::payload_key(event_id.as_ref());
+		if self.storage.get(Self::OFFCHAIN_PREFIX, &key).is_some() {
+			return Ok(())
+		}
+		let mut storage = self.storage.clone();
+		storage.set(Self::OFFCHAIN_PREFIX, &key, &bincode::serialize(&(stored_at, payload))?);
+		let head = self.get_sequence(Self::HEAD)?;
+		storage.set(Self::OFFCHAIN_PREFIX, &Self::log_key(head), event_id.as_ref());
+		self.set_sequence(Self::HEAD, head + 1)
+	}
+
+	fn get_event_payload(&self, event_id: &H256) -> Result<Option<Vec<u8>>, Error> {
+		match self.storage.get(Self::OFFCHAIN_PREFIX, &Self::payload_key(event_id.as_ref())) {
+			Some(value) => Ok(Some(bincode::deserialize::<(u64, Vec<u8>)>(&value)?.1)),
+			None => Ok(None),
+		}
+	}
+
+	fn purge_expired_payloads(&self, stored_before: u64) -> Result<usize, Error> {
+		let _log = self.log.lock()?;
+		let mut storage = self.storage.clone();
+		let (mut tail, head) = (self.get_sequence(Self::TAIL)?, self.get_sequence(Self::HEAD)?);
+		let mut deleted = 0;
+		while tail < head {
+			let log_key = Self::log_key(tail);
+			if let Some(event_id) = storage.get(Self::OFFCHAIN_PREFIX, &log_key) {
+				let key = Self::payload_key(&event_id);
+				if let Some(value) = storage.get(Self::OFFCHAIN_PREFIX, &key) {
+					let (stored_at, _): (u64, Vec<u8>) = bincode::deserialize(&value)?;
+					if stored_at >= stored_before {
+						break
+					}
+					storage.remove(Self::OFFCHAIN_PREFIX, &key);
+					deleted += 1;
+				}
+				storage.remove(Self::OFFCHAIN_PREFIX, &log_key);
+			}
+			tail += 1;
+		}
+		self.set_sequence(Self::TAIL, tail)?;
+		Ok(deleted)
+	}
+}
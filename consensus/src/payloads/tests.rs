@@ -0,0 +1,256 @@
+use super::{EventPayloads, EventPayloadsTrait, InMemoryEventPayloads, OffchainStorageEventPayloads};
+use crate::{
+	errors::Error,
+	server::{
+		validated_streams_proto::{
+			streams_server::Streams, GetEventPayloadRequest, HashEventRequest, Hasher,
+			WitnessEventRequest,
+		},
+		ValidatedStreamsGrpc,
+	},
+	test_utils::{single_validator, SimulatedNetwork, SimulatedNode, TestGrpc},
+	tunables::{Tunables, WITNESS_MODE},
+};
+use rstest::rstest;
+use sp_core::{hashing::blake2_256, H256};
+use sp_runtime::offchain::testing::TestPersistentOffchainDB;
+use std::{sync::Arc, time::Duration};
+use tonic::{Code, Request};
+
+fn in_memory_payloads() -> InMemoryEventPayloads {
+	InMemoryEventPayloads::new()
+}
+
+fn offchain_payloads() -> OffchainStorageEventPayloads<TestPersistentOffchainDB> {
+	OffchainStorageEventPayloads::new(TestPersistentOffchainDB::new())
+}
+
+/// A payload, and the id of its event.
+fn event_with_payload(byte: u8) -> (H256, Vec<u8>) {
+	let payload = vec![byte; 100];
+	(H256(blake2_256(&payload)), payload)
+}
+
+#[rstest]
+#[case(in_memory_payloads())]
+#[case(offchain_payloads())]
+fn test_add_and_get_event_payload(#[case] payloads: impl EventPayloadsTrait) {
+	let (event_id, payload) = event_with_payload(1);
+	assert_eq!(payloads.get_event_payload(&event_id), Ok(None));
+	assert_eq!(payloads.add_event_payload(&event_id, &payload, 10), Ok(()));
+	assert_eq!(payloads.get_event_payload(&event_id), Ok(Some(payload.clone())));
+	// add again the same payload
+	assert_eq!(payloads.add_event_payload(&event_id, &payload, 20), Ok(()));
+	assert_eq!(payloads.get_event_payload(&event_id), Ok(Some(payload)));
+	assert_eq!(payloads.get_event_payload(&H256::repeat_byte(1)), Ok(None));
+}
+
+#[rstest]
+#[case(in_memory_payloads())]
+#[case(offchain_payloads())]
+fn test_purge_expired_payloads(#[case] payloads: impl EventPayloadsTrait) {
+	let events: Vec<_> = (0..4).map(event_with_payload).collect();
+	for (stored_at, (event_id, payload)) in (10..).step_by(10).zip(&events) {
+		payloads.add_event_payload(event_id, payload, stored_at).unwrap();
+	}
+	// Stored again later, which keeps it as old as it was
+	payloads.add_event_payload(&events[0].0, &events[0].1, 40).unwrap();
+
+	assert_eq!(payloads.purge_expired_payloads(10), Ok(0));
+	assert_eq!(payloads.purge_expired_payloads(21), Ok(2));
+	let kept: Vec<_> =
+		events.iter().map(|(event_id, _)| payloads.get_event_payload(event_id).unwrap()).collect();
+	assert_eq!(kept, [None, None, Some(events[2].1.clone()), Some(events[3].1.clone())]);
+	assert_eq!(payloads.purge_expired_payloads(21), Ok(0));
+
+	// Deleted payloads can be stored anew
+	payloads.add_event_payload(&events[0].0, &events[0].1, 50).unwrap();
+	assert_eq!(payloads.purge_expired_payloads(50), Ok(2));
+	assert_eq!(payloads.get_event_payload(&events[0].0), Ok(Some(events[0].1.clone())));
+	assert_eq!(payloads.purge_expired_payloads(u64::MAX), Ok(1));
+	assert_eq!(payloads.get_event_payload(&events[0].0), Ok(None));
+}
+
+#[test]
+fn test_event_payloads_checks_and_retention() {
+	let store = Arc::new(in_memory_payloads());
+	let payloads = EventPayloads::new(store.clone(), 100, Duration::from_secs(60));
+	let (event_id, payload) = event_with_payload(1);
+
+	assert_eq!(payloads.check(&event_id, &payload), Ok(()));
+	assert_eq!(payloads.check(&H256::repeat_byte(1), &payload), Err(Error::PayloadMismatch));
+	let large = vec![1; 101];
+	let large_id = H256(blake2_256(&large));
+	let too_large = Err(Error::PayloadTooLarge { size: 101, max: 100 });
+	assert_eq!(payloads.check(&large_id, &large), too_large);
+	assert_eq!(payloads.add(&large_id, &large), too_large);
+	assert_eq!(payloads.get(&large_id), Ok(None));
+
+	store.add_event_payload(&event_id, &payload, 1_000).unwrap();
+	assert_eq!(payloads.purge_expired(1_060), Ok(0));
+	assert_eq!(payloads.purge_expired(1_061), Ok(1));
+	assert_eq!(payloads.get(&event_id), Ok(None));
+}
+
+/// A gRPC service witnessing events with the key of the only validator, hashing the data of events
+/// of up to 100 bytes, and storing it as their payloads if `payloads` is set.
+fn grpc(payloads: bool) -> (TestGrpc, SimulatedNetwork<SimulatedNode>) {
+	let (witnesser, network) = single_validator();
+	let tunables = Tunables::default();
+	let witnesser = witnesser.with_tunables(tunables.clone());
+	let payloads = payloads.then(|| {
+		EventPayloads::new(Arc::new(in_memory_payloads()), 100, Duration::from_secs(60))
+	});
+	let grpc = ValidatedStreamsGrpc {
+		tunables,
+		payloads,
+		max_payload_size: 100,
+		..ValidatedStreamsGrpc::for_tests(witnesser)
+	};
+	(grpc, network)
+}
+
+async fn witness(grpc: &TestGrpc, event_id: H256, payload: &[u8]) -> Result<(), Code> {
+	let (event_id, payload) = (event_id.0.to_vec(), payload.to_vec());
+	let request = WitnessEventRequest { event_id, payload, ..Default::default() };
+	grpc.witness_event(Request::new(request)).await.map(|_| ()).map_err(|status| status.code())
+}
+
+async fn get(grpc: &TestGrpc, event_id: H256) -> Result<Vec<u8>, Code> {
+	let request = GetEventPayloadRequest { event_id: event_id.0.to_vec() };
+	let response = grpc.get_event_payload(Request::new(request)).await;
+	response.map(|response| response.into_inner().payload).map_err(|status| status.code())
+}
+
+#[tokio::test]
+async fn test_payloads_submitted_and_retrieved() {
+	let (grpc, network) = grpc(true);
+	let (event_id, payload) = event_with_payload(1);
+
+	assert_eq!(get(&grpc, event_id).await, Err(Code::NotFound));
+	assert_eq!(witness(&grpc, event_id, &payload).await, Ok(()));
+	assert_eq!(get(&grpc, event_id).await, Ok(payload.clone()));
+	// Events are still witnessed without their payloads, whose absence stores nothing
+	let (other_id, _) = event_with_payload(2);
+	assert_eq!(witness(&grpc, other_id, &[]).await, Ok(()));
+	assert_eq!(get(&grpc, other_id).await, Err(Code::NotFound));
+	assert_eq!(get(&grpc, H256::zero()).await, Err(Code::NotFound));
+
+	// Never gossiped: the witnesses are the same as those of events without payloads
+	network.run_until_idle().await;
+	let stored = network.handler(0).stored_proofs();
+	let witness = stored.iter().find(|proof| proof.event_id == event_id).unwrap();
+	assert!(!witness.to_bytes().unwrap().windows(payload.len()).any(|w| w == payload));
+}
+
+#[tokio::test]
+async fn test_bad_payloads_refused() {
+	let (grpc, network) = grpc(true);
+	let (event_id, payload) = event_with_payload(1);
+
+	// Not hashing to the event id
+	assert_eq!(witness(&grpc, H256::repeat_byte(1), &payload).await, Err(Code::InvalidArgument));
+	// Larger than the cap
+	let large = vec![1; 101];
+	let large_id = H256(blake2_256(&large));
+	assert_eq!(witness(&grpc, large_id, &large).await, Err(Code::InvalidArgument));
+	// Neither of them gets witnessed, nor stored
+	network.run_until_idle().await;
+	assert!(network.handler(0).stored_proofs().is_empty());
+	assert_eq!(get(&grpc, H256::repeat_byte(1)).await, Err(Code::NotFound));
+	assert_eq!(get(&grpc, large_id).await, Err(Code::NotFound));
+	assert_eq!(witness(&grpc, event_id, &payload).await, Ok(()));
+}
+
+#[tokio::test]
+async fn test_payloads_refused_when_disabled() {
+	let (grpc, _network) = grpc(false);
+	let (event_id, payload) = event_with_payload(1);
+
+	assert_eq!(witness(&grpc, event_id, &payload).await, Err(Code::FailedPrecondition));
+	assert_eq!(witness(&grpc, event_id, &[]).await, Ok(()));
+	assert_eq!(get(&grpc, event_id).await, Err(Code::FailedPrecondition));
+}
+
+async fn hash(grpc: &TestGrpc, payload: &[u8], and_validate: bool) -> Result<H256, Code> {
+	hash_with(grpc, payload, and_validate, Hasher::Blake2256 as i32).await
+}
+
+async fn hash_with(
+	grpc: &TestGrpc,
+	payload: &[u8],
+	and_validate: bool,
+	hasher: i32,
+) -> Result<H256, Code> {
+	let request = HashEventRequest { payload: payload.to_vec(), and_validate, hasher };
+	let response = grpc.hash_event(Request::new(request)).await.map_err(|status| status.code())?;
+	Ok(H256::from_slice(&response.into_inner().event_id))
+}
+
+#[tokio::test]
+async fn test_hash_event_matches_the_canonical_ids() {
+	let (grpc, network) = grpc(false);
+
+	// The vectors the pallet pins its event ids to
+	let vectors = [
+		(&b""[..], "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"),
+		(b"abc", "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319"),
+	];
+	for (data, expected) in vectors {
+		assert_eq!(hash(&grpc, data, false).await, Ok(expected.parse().unwrap()));
+	}
+	let (event_id, payload) = event_with_payload(1);
+	assert_eq!(hash(&grpc, &payload, false).await, Ok(event_id));
+	assert_eq!(hash(&grpc, &[1; 101], false).await, Err(Code::InvalidArgument));
+
+	// Only hashed
+	network.run_until_idle().await;
+	assert!(network.handler(0).stored_proofs().is_empty());
+}
+
+#[tokio::test]
+async fn test_hash_and_validate() {
+	for payloads in [true, false] {
+		let (grpc, network) = grpc(payloads);
+		let (event_id, payload) = event_with_payload(1);
+
+		assert_eq!(hash(&grpc, &payload, true).await, Ok(event_id));
+		network.run_until_idle().await;
+		let witnessed: Vec<_> =
+			network.handler(0).stored_proofs().iter().map(|proof| proof.event_id).collect();
+		assert_eq!(witnessed, [event_id]);
+		// Along with the payload, if the node stores payloads
+		let expected = if payloads { Ok(payload) } else { Err(Code::FailedPrecondition) };
+		assert_eq!(get(&grpc, event_id).await, expected);
+
+		// Refused as witnessing would be, after hashing
+		grpc.tunables.update([(WITNESS_MODE, "paused")]).unwrap();
+		let (other_id, other) = event_with_payload(2);
+		assert_eq!(hash(&grpc, &other, false).await, Ok(other_id));
+		assert_eq!(hash(&grpc, &other, true).await, Err(Code::FailedPrecondition));
+	}
+}
+
+#[tokio::test]
+async fn test_hash_event_with_other_hashers() {
+	let (grpc, network) = grpc(true);
+	let vectors = [
+		(Hasher::Keccak256, "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"),
+		(Hasher::Sha2256, "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+	];
+	for (hasher, expected) in vectors {
+		let hashed = hash_with(&grpc, b"", false, hasher as i32).await;
+		assert_eq!(hashed, Ok(expected.parse().unwrap()));
+	}
+	assert_eq!(hash_with(&grpc, b"", false, 9).await, Err(Code::InvalidArgument));
+
+	// Witnessed under its id, but its payload is not stored under a non-canonical one
+	let payload = vec![1; 100];
+	let event_id = hash_with(&grpc, &payload, true, Hasher::Keccak256 as i32).await.unwrap();
+	assert_ne!(event_id, event_with_payload(1).0);
+	network.run_until_idle().await;
+	let witnessed: Vec<_> =
+		network.handler(0).stored_proofs().iter().map(|proof| proof.event_id).collect();
+	assert_eq!(witnessed, [event_id]);
+	assert_eq!(get(&grpc, event_id).await, Err(Code::NotFound));
+}
@@ -1,8 +1,9 @@
 //! Validated streams event proof types and storage
 
-use super::{EventProofsTrait, WitnessedEvent};
-use crate::errors::Error;
+use super::{EventProof, EventProofsTrait, WitnessedEvent};
+use crate::{errors::Error, logging::PROOFS};
 
+use pallet_validated_streams::payload::SessionIndex;
 use sp_core::H256;
 use sp_runtime::app_crypto::CryptoTypePublicPair;
 use std::{
@@ -12,7 +13,7 @@ use std::{
 
 /// An in-memory store of event proofs.
 pub struct InMemoryEventProofs {
-	proofs: Mutex<HashMap<H256, HashMap<CryptoTypePublicPair, Vec<u8>>>>,
+	proofs: Mutex<HashMap<H256, HashMap<CryptoTypePublicPair, EventProof>>>,
 }
 impl InMemoryEventProofs {
 	/// Create an empty [InMemoryEventProofs] instances.
@@ -34,14 +35,15 @@ impl EventProofsTrait for InMemoryEventProofs {
 		let event_witnesses = proofs.entry(event_id).or_default();
 		match event_witnesses.entry(witnessed_event.pub_key.clone()) {
 			Entry::Vacant(e) => {
-				e.insert(witnessed_event.signature.clone());
+				e.insert(witnessed_event.proof());
 				Ok(())
 			},
 			witness_entry => {
-				log::info!(
-					"{:?} already sent a proof for event {:?}",
-					witness_entry.key(),
-					event_id
+				tracing::info!(
+					target: PROOFS,
+					event_id = %event_id,
+					validator = ?witness_entry.key(),
+					"Validator already sent a proof of the event"
 				);
 				Ok(())
 			},
@@ -52,7 +54,7 @@ impl EventProofsTrait for InMemoryEventProofs {
 		&self,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
-	) -> Result<HashMap<CryptoTypePublicPair, Vec<u8>>, Error> {
+	) -> Result<HashMap<CryptoTypePublicPair, EventProof>, Error> {
 		let proofs = self.proofs.lock().or(Err(Error::LockFail("InMemoryProofs".to_string())))?;
 		Ok(proofs
 			.get(event_id)
@@ -68,11 +70,14 @@ impl EventProofsTrait for InMemoryEventProofs {
 		&self,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
+		oldest_session: SessionIndex,
 	) -> Result<(), Error> {
 		let mut proofs =
 			self.proofs.lock().or(Err(Error::LockFail("InMemoryProofs".to_string())))?;
 		if let Some(event_proofs) = proofs.get_mut(event_id) {
-			event_proofs.retain(|k, _| validators.contains(k));
+			event_proofs.retain(|k, proof| {
+				validators.contains(k) && proof.session >= oldest_session
+			});
 		}
 		Ok(())
 	}
@@ -1,6 +1,8 @@
 //! Validated streams event proof types and storage
 
 use crate::errors::Error;
+use codec::{Decode, DecodeAll, Encode};
+use pallet_validated_streams::payload::SessionIndex;
 use serde::{Deserialize, Serialize};
 use sp_core::H256;
 use sp_runtime::app_crypto::CryptoTypePublicPair;
@@ -18,12 +20,12 @@ pub use offchain::OffchainStorageEventProofs;
 #[cfg(feature = "rocksdb")]
 pub mod rocksdb;
 #[cfg(feature = "rocksdb")]
-pub use self::rocksdb::RocksDbEventProofs;
+pub use self::rocksdb::{RocksDbEventProofs, ROCKSDB_PROOFS_DIR};
 
 /// Proof of event that has been witnessed; an event id and a signature
 /// Signatures do not have a defined cryptosystem, but are assumed to be sr25519 signatures by
 /// [super::services::events].
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Encode, Decode)]
 pub struct WitnessedEvent {
 	/// The signature of the event
 	pub signature: Vec<u8>,
@@ -31,6 +33,86 @@ pub struct WitnessedEvent {
 	pub pub_key: CryptoTypePublicPair,
 	/// The id/hash of the event
 	pub event_id: H256,
+	/// The session the event was witnessed in, which the signature is bound to
+	pub session: SessionIndex,
+}
+
+/// A proof of an event as stored for a validator: the signature of its [WitnessedEvent], and the
+/// session it was made in.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventProof {
+	/// The session the event was witnessed in
+	pub session: SessionIndex,
+	/// The signature of the event
+	pub signature: Vec<u8>,
+}
+
+/// The proofs of an event held from the validators of a block, along with those validators, so
+/// that whoever reads them can check that enough of the validators witnessed the event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidatorProofs {
+	/// The session of the validators
+	pub session: SessionIndex,
+	/// The validators, in the order of the chain
+	pub validators: Vec<CryptoTypePublicPair>,
+	/// How many of the validators must have witnessed the event for it to be validated
+	pub target: u16,
+	/// The proofs held, in the order of their validators
+	pub proofs: Vec<(CryptoTypePublicPair, EventProof)>,
+}
+
+/// Upper bound on the size of an encoded [WitnessedEvent]. Larger messages are rejected outright,
+/// and length fields pointing past it are never trusted.
+pub const MAX_WITNESSED_EVENT_SIZE: u64 = 1024;
+/// The version of the format [WitnessedEvent]s are gossiped in, as their first byte. Witnesses of
+/// other versions fail to decode, so that a format change never gets misread, and are ignored by
+/// the gossip; it is the major version of the [gossip protocol](crate::gossip::PROTOCOL_VERSION).
+pub const WITNESSED_EVENT_VERSION: u8 = 1;
+
+impl WitnessedEvent {
+	/// The proof of the event this witness holds.
+	pub fn proof(&self) -> EventProof {
+		EventProof { session: self.session, signature: self.signature.clone() }
+	}
+
+	/// Encodes the event in the format in which it is gossiped: the [WITNESSED_EVENT_VERSION]
+	/// byte, followed by the SCALE encoding of the event, which other Substrate tooling can decode.
+	pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+		let mut bytes = vec![WITNESSED_EVENT_VERSION];
+		self.encode_to(&mut bytes);
+		Ok(bytes)
+	}
+
+	/// Decodes an event in the format in which it is gossiped. Never panics, regardless of input.
+	/// Events of another version fail as [Error::UnsupportedVersion].
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+		if bytes.len() as u64 > MAX_WITNESSED_EVENT_SIZE {
+			return Err(Error::SerilizationFailure(format!(
+				"WitnessedEvent of {} bytes exceeds the maximum size",
+				bytes.len()
+			)))
+		}
+		match bytes.split_first() {
+			Some((&WITNESSED_EVENT_VERSION, mut encoded)) => Ok(Self::decode_all(&mut encoded)?),
+			Some((version, _)) => Err(Error::UnsupportedVersion(*version)),
+			None => Err(Error::SerilizationFailure("empty WitnessedEvent".to_string())),
+		}
+	}
+}
+
+impl ValidatorProofs {
+	/// The witnesses of the event the proofs were made of, as they were gossiped.
+	pub fn witnesses(&self, event_id: H256) -> Vec<WitnessedEvent> {
+		let proofs = self.proofs.iter().cloned();
+		proofs
+			.map(|(pub_key, proof)| WitnessedEvent {
+				signature: proof.signature,
+				pub_key,
+				event_id,
+				session: proof.session,
+			})
+			.collect()
+	}
 }
 
 /// Storage for event proofs (for [WitnessedEvent]-s)
@@ -38,13 +120,13 @@ pub trait EventProofsTrait {
 	/// Stores the provided event proof.
 	fn add_event_proof(&self, event: &WitnessedEvent) -> Result<(), Error>;
 
-	/// Returns a [HashMap] containing the public keys and their corresponding signatures for the
+	/// Returns a [HashMap] containing the public keys and their corresponding proofs for the
 	/// given event id and list of validators
 	fn get_event_proofs(
 		&self,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
-	) -> Result<HashMap<CryptoTypePublicPair, Vec<u8>>, Error>;
+	) -> Result<HashMap<CryptoTypePublicPair, EventProof>, Error>;
 
 	/// Retrieve count of proof for the given event id. Equivalent to
 	/// `self.get_event_proofs(event_id, validators)?.len()`, but possibly more optimal.
@@ -57,10 +139,18 @@ pub trait EventProofsTrait {
 	}
 
 	/// Remove proofs of the given event observed by validators not in the list of validators passed
-	/// in. Useful for maintaining the pool of event proofs whenever the validator set changes.
+	/// in, or made before the `oldest_session` still accepted. Useful for maintaining the pool of
+	/// event proofs whenever the validator set changes.
 	fn purge_event_stale_signatures(
 		&self,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
+		oldest_session: SessionIndex,
 	) -> Result<(), Error>;
+
+	/// Makes sure that every proof stored so far is persisted, e.g. before shutting down. Storages
+	/// which persist every proof as it is added need not do anything.
+	fn flush(&self) -> Result<(), Error> {
+		Ok(())
+	}
 }
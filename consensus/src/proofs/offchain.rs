@@ -1,8 +1,9 @@
 //! Validated streams event proof types and storage
 
-use super::{EventProofsTrait, WitnessedEvent};
+use super::{EventProof, EventProofsTrait, WitnessedEvent};
 use crate::errors::Error;
 
+use pallet_validated_streams::payload::SessionIndex;
 use sp_core::{offchain::OffchainStorage, H256};
 use sp_runtime::app_crypto::CryptoTypePublicPair;
 use std::collections::HashMap;
@@ -28,7 +29,7 @@ impl<Storage: OffchainStorage> EventProofsTrait for OffchainStorageEventProofs<S
 		self.storage.clone().set(
 			Self::OFFCHAIN_PREFIX,
 			&[event.event_id.as_ref(), &bincode::serialize(&event.pub_key)?].concat(),
-			&event.signature,
+			&bincode::serialize(&event.proof())?,
 		);
 
 		loop {
@@ -54,8 +55,8 @@ impl<Storage: OffchainStorage> EventProofsTrait for OffchainStorageEventProofs<S
 		&self,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
-	) -> Result<HashMap<CryptoTypePublicPair, Vec<u8>>, Error> {
-		Ok(validators
+	) -> Result<HashMap<CryptoTypePublicPair, EventProof>, Error> {
+		validators
 			.iter()
 			.flat_map(|pub_key| {
 				self.storage
@@ -63,9 +64,9 @@ impl<Storage: OffchainStorage> EventProofsTrait for OffchainStorageEventProofs<S
 						Self::OFFCHAIN_PREFIX,
 						&[event_id.as_ref(), &bincode::serialize(pub_key).unwrap()].concat(),
 					)
-					.map(|signature| (pub_key.clone(), signature))
+					.map(|proof| Ok((pub_key.clone(), bincode::deserialize(&proof)?)))
 			})
-			.collect())
+			.collect()
 	}
 
 	fn get_event_proof_count(
@@ -89,6 +90,7 @@ impl<Storage: OffchainStorage> EventProofsTrait for OffchainStorageEventProofs<S
 		&self,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
+		oldest_session: SessionIndex,
 	) -> Result<(), Error> {
 		loop {
 			let existing_bytes = self.storage.get(Self::OFFCHAIN_PREFIX, event_id.as_ref());
@@ -98,15 +100,17 @@ impl<Storage: OffchainStorage> EventProofsTrait for OffchainStorageEventProofs<S
 				.unwrap_or_else(|| Ok(vec![]))?;
 
 			signers_list.retain(|pub_key| {
-				if !validators.contains(pub_key) {
-					self.storage.clone().remove(
-						Self::OFFCHAIN_PREFIX,
-						&[event_id.as_ref(), &bincode::serialize(pub_key).unwrap()].concat(),
-					);
-					false
-				} else {
-					true
+				let key = [event_id.as_ref(), &bincode::serialize(pub_key).unwrap()].concat();
+				// Proofs which cannot be read back are as good as stale
+				let stale = !validators.contains(pub_key) ||
+					self.storage.get(Self::OFFCHAIN_PREFIX, &key).map_or(true, |proof| {
+						bincode::deserialize::<EventProof>(&proof)
+							.map_or(true, |proof| proof.session < oldest_session)
+					});
+				if stale {
+					self.storage.clone().remove(Self::OFFCHAIN_PREFIX, &key);
 				}
+				!stale
 			});
 
 			if self.storage.clone().compare_and_set(
@@ -1,23 +1,42 @@
 //! Validated streams event proof types and storage
 
-use super::{EventProofsTrait, WitnessedEvent};
+use super::{EventProof, EventProofsTrait, WitnessedEvent};
 use crate::errors::Error;
 
+use pallet_validated_streams::payload::SessionIndex;
 use sp_core::H256;
 use sp_runtime::app_crypto::CryptoTypePublicPair;
-use std::collections::HashMap;
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+};
 
-/// A persistent database for storing event proofs.
+/// The directory the proofs of a node are kept in, under its base path.
+pub const ROCKSDB_PROOFS_DIR: &str = "validated-streams-proofs";
+
+/// A persistent database for storing event proofs, so that the witnesses collected for the events
+/// in flight survive a restart of the node.
 pub struct RocksDbEventProofs {
 	// key value format:
-	// <event id (32 bytes)> <public key (serialized CryptoTypePublicPair)> -> <signature bytes>
+	// <event id (32 bytes)> <public key (serialized CryptoTypePublicPair)> ->
+	// <serialized EventProof>
 	db: rocksdb::DB,
 }
 
 impl RocksDbEventProofs {
 	/// Returns a RocksDbEventProofs instance which persists data in the provided path
 	pub fn create(path: &str) -> Self {
-		Self { db: rocksdb::DB::open_default(path).expect("open") }
+		Self::open(Path::new(path)).expect("open")
+	}
+
+	/// Opens the database at the given path, creating it if it does not exist yet.
+	pub fn open(path: &Path) -> Result<Self, Error> {
+		Ok(Self { db: rocksdb::DB::open_default(path)? })
+	}
+
+	/// Where a node keeps its proofs: in the [ROCKSDB_PROOFS_DIR] under its base path.
+	pub fn path_under(base_path: &Path) -> PathBuf {
+		base_path.join(ROCKSDB_PROOFS_DIR)
 	}
 
 	/// Clears ALL the data stored at the given path.
@@ -31,7 +50,7 @@ impl EventProofsTrait for RocksDbEventProofs {
 	fn add_event_proof(&self, event: &WitnessedEvent) -> Result<(), Error> {
 		self.db.put(
 			[event.event_id.as_ref(), &bincode::serialize(&event.pub_key)?].concat(),
-			event.signature.clone(),
+			bincode::serialize(&event.proof())?,
 		)?;
 		Ok(())
 	}
@@ -40,11 +59,11 @@ impl EventProofsTrait for RocksDbEventProofs {
 		&self,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
-	) -> Result<HashMap<CryptoTypePublicPair, Vec<u8>>, Error> {
+	) -> Result<HashMap<CryptoTypePublicPair, EventProof>, Error> {
 		// NOTE: to get all proofs, no matter who signed them:
-		// self.db.prefix_iterator(event_id).map(|r| { r.map(|(key, signature)| { let pub_key =
-		// bincode::deserialize(&key[H256::len_bytes()..]).unwrap(); (pub_key, signature.into())
-		// }).map_err(|e| e.into())}).collect()
+		// self.db.prefix_iterator(event_id).map(|r| { r.map(|(key, proof)| { let pub_key =
+		// bincode::deserialize(&key[H256::len_bytes()..]).unwrap(); (pub_key,
+		// bincode::deserialize(&proof).unwrap()) }).map_err(|e| e.into())}).collect()
 
 		let values =
 			self.db.multi_get(validators.iter().map(|pub_key| {
@@ -53,8 +72,11 @@ impl EventProofsTrait for RocksDbEventProofs {
 		validators
 			.iter()
 			.zip(values)
-			.flat_map(|(pub_key, signature_r)| match signature_r {
-				Ok(Some(signature)) => Some(Ok((pub_key.clone(), signature))),
+			.flat_map(|(pub_key, proof_r)| match proof_r {
+				Ok(Some(proof)) => Some(match bincode::deserialize(&proof) {
+					Ok(proof) => Ok((pub_key.clone(), proof)),
+					Err(e) => Err(e.into()),
+				}),
 				Ok(None) => None,
 				Err(e) => Some(Err(e.into())),
 			})
@@ -82,14 +104,22 @@ impl EventProofsTrait for RocksDbEventProofs {
 		&self,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
+		oldest_session: SessionIndex,
 	) -> Result<(), Error> {
 		for r in self.db.prefix_iterator(event_id) {
-			let (key, _signature) = r?;
+			let (key, proof) = r?;
 			let pub_key = bincode::deserialize(&key[H256::len_bytes()..])?;
-			if !validators.contains(&pub_key) {
+			// Proofs which cannot be read back are as good as stale
+			let session = bincode::deserialize::<EventProof>(&proof).map(|proof| proof.session);
+			if !validators.contains(&pub_key) || session.map_or(true, |s| s < oldest_session) {
 				self.db.delete(key)?;
 			}
 		}
 		Ok(())
 	}
+
+	fn flush(&self) -> Result<(), Error> {
+		self.db.flush()?;
+		Ok(())
+	}
 }
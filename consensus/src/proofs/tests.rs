@@ -1,11 +1,17 @@
 use super::{
-	EventProofsTrait, InMemoryEventProofs, OffchainStorageEventProofs,
-	WitnessedEvent,
+	EventProofsTrait, InMemoryEventProofs, OffchainStorageEventProofs, WitnessedEvent,
+	WITNESSED_EVENT_VERSION,
 };
 #[cfg(feature = "rocksdb")]
 use super::RocksDbEventProofs;
+use crate::{
+	errors::Error,
+	events::AuthoritiesList,
+	test_utils::{TestProofs, TestValidators},
+};
+use proptest::prelude::*;
 use rstest::rstest;
-use sp_core::{sr25519::Public, H256};
+use sp_core::{crypto::CryptoTypeId, sr25519, H256};
 use sp_runtime::{app_crypto::CryptoTypePublicPair, offchain::testing::TestPersistentOffchainDB};
 use std::{
 	collections::HashMap,
@@ -19,19 +25,28 @@ fn in_memory_proofs() -> impl EventProofsTrait {
 #[cfg(feature = "rocksdb")]
 static ROCKSDB_INSTANCE: AtomicUsize = AtomicUsize::new(1);
 #[cfg(feature = "rocksdb")]
-fn rocksdb_proofs() -> impl EventProofsTrait {
+fn rocksdb_path() -> String {
 	let path =
 		format!("/tmp/testvstreamsrocksdb{}", ROCKSDB_INSTANCE.fetch_add(1, Ordering::SeqCst));
 	let _ = RocksDbEventProofs::destroy(&path);
-	RocksDbEventProofs::create(&path)
+	path
+}
+#[cfg(feature = "rocksdb")]
+fn rocksdb_proofs() -> impl EventProofsTrait {
+	RocksDbEventProofs::create(&rocksdb_path())
 }
 
 fn offchain_proofs() -> impl EventProofsTrait {
 	OffchainStorageEventProofs::new(TestPersistentOffchainDB::new())
 }
 
+fn test_proofs() -> impl EventProofsTrait {
+	TestProofs::new()
+}
+
 #[rstest]
 #[case(in_memory_proofs())]
+#[case(test_proofs())]
 #[cfg(feature = "rocksdb")]
 #[case(rocksdb_proofs())]
 #[case(offchain_proofs())]
@@ -46,6 +61,7 @@ fn test_add_event_proof(#[case] proofs: impl EventProofsTrait) {
 
 #[rstest]
 #[case(in_memory_proofs())]
+#[case(test_proofs())]
 #[cfg(feature = "rocksdb")]
 #[case(rocksdb_proofs())]
 #[case(offchain_proofs())]
@@ -64,6 +80,7 @@ fn test_get_proof_count(#[case] proofs: impl EventProofsTrait) {
 
 #[rstest]
 #[case(in_memory_proofs())]
+#[case(test_proofs())]
 #[cfg(feature = "rocksdb")]
 #[case(rocksdb_proofs())]
 #[case(offchain_proofs())]
@@ -78,13 +95,14 @@ fn test_get_proof_proofs(#[case] proofs: impl EventProofsTrait) {
 	let _ = proofs.add_event_proof(&witnessed_event);
 	let proofmap = proofs.get_event_proofs(&event_id, &validator_list).unwrap();
 	assert_eq!(proofmap.len(), 1);
-	assert_eq!(proofmap.get(&validator_list[0]), Some(&witnessed_event.signature));
+	assert_eq!(proofmap.get(&validator_list[0]), Some(&witnessed_event.proof()));
 
 	assert_eq!(proofs.get_event_proofs(&event_id, &new_validator_list), Ok(HashMap::new()));
 }
 
 #[rstest]
 #[case(in_memory_proofs())]
+#[case(test_proofs())]
 #[cfg(feature = "rocksdb")]
 #[case(rocksdb_proofs())]
 #[case(offchain_proofs())]
@@ -96,23 +114,185 @@ fn test_remove_stale_events(#[case] proofs: impl EventProofsTrait) {
 
 	let _ = proofs.add_event_proof(&witnessed_event);
 
-	assert!(proofs.purge_event_stale_signatures(&event_id, &validator_list).is_ok());
+	assert!(proofs.purge_event_stale_signatures(&event_id, &validator_list, 0).is_ok());
 	assert_eq!(proofs.get_event_proof_count(&event_id, &validator_list), Ok(1));
 
-	assert!(proofs.purge_event_stale_signatures(&event_id, &new_validator_list).is_ok());
+	assert!(proofs.purge_event_stale_signatures(&event_id, &new_validator_list, 0).is_ok());
 	assert_eq!(proofs.get_event_proof_count(&event_id, &validator_list), Ok(0));
 }
 
+#[rstest]
+#[case(in_memory_proofs())]
+#[case(test_proofs())]
+#[cfg(feature = "rocksdb")]
+#[case(rocksdb_proofs())]
+#[case(offchain_proofs())]
+fn test_remove_stale_session_events(#[case] proofs: impl EventProofsTrait) {
+	let event_id = H256::repeat_byte(1);
+	let validators = TestValidators::new(2);
+	let _ = proofs.add_event_proof(&validators.witness(0, event_id).in_session(3).build());
+	let _ = proofs.add_event_proof(&validators.witness(1, event_id).in_session(4).build());
+
+	assert!(proofs.purge_event_stale_signatures(&event_id, &validators.pubkeys(), 3).is_ok());
+	assert_eq!(proofs.get_event_proof_count(&event_id, &validators.pubkeys()), Ok(2));
+
+	// Once session 3 falls out of the grace window
+	assert!(proofs.purge_event_stale_signatures(&event_id, &validators.pubkeys(), 4).is_ok());
+	let remaining = proofs.get_event_proofs(&event_id, &validators.pubkeys()).unwrap();
+	let sessions: Vec<_> = remaining.iter().map(|(key, proof)| (key, proof.session)).collect();
+	assert_eq!(sessions, [(&validators.pub_key(1), 4)]);
+}
+
+#[test]
+fn test_instrumented_proofs() {
+	use crate::test_utils::ProofsCall;
+
+	let event_id = H256::repeat_byte(1);
+	let witnessed_event = create_witnessed_event(event_id);
+	let validator_list = get_validator_list();
+	let proofs = TestProofs::new();
+
+	proofs.fail_next(Error::Database("injected".to_string()));
+	assert_eq!(
+		proofs.add_event_proof(&witnessed_event),
+		Err(Error::Database("injected".to_string()))
+	);
+	assert_eq!(proofs.get_event_proof_count(&event_id, &validator_list), Ok(0));
+
+	assert!(proofs.add_event_proof(&witnessed_event).is_ok());
+	assert_eq!(proofs.get_event_proof_count(&event_id, &validator_list), Ok(1));
+
+	assert_eq!(
+		proofs.calls(),
+		vec![
+			ProofsCall::AddEventProof(witnessed_event.clone()),
+			ProofsCall::GetEventProofCount(event_id),
+			ProofsCall::AddEventProof(witnessed_event),
+			ProofsCall::GetEventProofCount(event_id),
+		]
+	);
+}
+
+/// The gossiped encoding of a [WitnessedEvent], pinned byte for byte. The event is the second
+/// sr25519 golden vector of `pallet_validated_streams::payload`, witnessed in session 7.
+#[test]
+fn test_witnessed_event_golden_wire_format() {
+	let signature = hex::decode(concat!(
+		"426fc51dde66bd6db779c2d56377f2ab6545b698e52c62bdde3694c78c49614e",
+		"5105380a19c4824b8adb0ba9f8b9a58dda794ed4cffa764edcc7a93b2d45c285"
+	))
+	.unwrap();
+	let public =
+		hex::decode("44a996beb1eef7bdcab976ab6d2ca26104834164ecf28fb375600576fcc6eb0f").unwrap();
+	let event_id = "0x0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20";
+	let witnessed_event = WitnessedEvent {
+		signature: signature.clone(),
+		pub_key: CryptoTypePublicPair(sr25519::CRYPTO_ID, public.clone()),
+		event_id: event_id.parse().unwrap(),
+		session: 7,
+	};
+
+	// version
+	let mut expected = vec![WITNESSED_EVENT_VERSION];
+	// signature: compact length-prefixed bytes
+	expected.extend([0x01, 0x01]);
+	expected.extend(&signature);
+	// pub_key: crypto type id, then compact length-prefixed key bytes
+	expected.extend(b"sr25");
+	expected.push(32 << 2);
+	expected.extend(&public);
+	// event_id: raw bytes
+	expected.extend(hex::decode(&event_id[2..]).unwrap());
+	// session: little-endian u32
+	expected.extend(7u32.to_le_bytes());
+
+	assert_eq!(witnessed_event.to_bytes().unwrap(), expected);
+	assert_eq!(WitnessedEvent::from_bytes(&expected).unwrap(), witnessed_event);
+	// Other versions, and trailing bytes, are rejected
+	expected[0] = WITNESSED_EVENT_VERSION + 1;
+	let unsupported = Error::UnsupportedVersion(WITNESSED_EVENT_VERSION + 1);
+	assert_eq!(WitnessedEvent::from_bytes(&expected), Err(unsupported));
+	expected[0] = WITNESSED_EVENT_VERSION;
+	expected.push(0);
+	assert!(WitnessedEvent::from_bytes(&expected).is_err());
+
+	let authorities = AuthoritiesList::new(vec![witnessed_event.pub_key.clone()]);
+	assert!(authorities.in_session(7, []).verify_witnessed_event_origin(witnessed_event).is_ok());
+}
+
+prop_compose! {
+	fn arbitrary_witnessed_event()(
+		signature in prop::collection::vec(any::<u8>(), 0..128),
+		crypto_id in any::<[u8; 4]>(),
+		key in prop::collection::vec(any::<u8>(), 0..64),
+		event_id in any::<[u8; 32]>(),
+		session in any::<u32>(),
+	) -> WitnessedEvent {
+		WitnessedEvent {
+			signature,
+			pub_key: CryptoTypePublicPair(CryptoTypeId(crypto_id), key),
+			event_id: H256(event_id),
+			session,
+		}
+	}
+}
+
+proptest! {
+	#[test]
+	fn test_witnessed_event_round_trip(witnessed_event in arbitrary_witnessed_event()) {
+		let bytes = witnessed_event.to_bytes().unwrap();
+		prop_assert_eq!(WitnessedEvent::from_bytes(&bytes).unwrap(), witnessed_event);
+	}
+
+	#[test]
+	fn test_witnessed_event_truncated(
+		witnessed_event in arbitrary_witnessed_event(),
+		cut in any::<prop::sample::Index>(),
+	) {
+		let bytes = witnessed_event.to_bytes().unwrap();
+		let cut = cut.index(bytes.len());
+		prop_assert!(WitnessedEvent::from_bytes(&bytes[..cut]).is_err());
+	}
+
+	#[test]
+	fn test_signed_witnessed_event_round_trip(event_id in any::<[u8; 32]>()) {
+		let validators = TestValidators::new(1);
+		let witnessed_event = validators.witness(0, H256(event_id)).build();
+		let bytes = witnessed_event.to_bytes().unwrap();
+		prop_assert!(validators.authorities().decode_witnessed_event(&bytes).is_ok());
+	}
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn test_rocksdb_proofs_survive_reopening() {
+	use std::path::{Path, PathBuf};
+
+	let path = rocksdb_path();
+	let witnessed_event = create_witnessed_event(H256::repeat_byte(1));
+	let event_id = witnessed_event.event_id;
+	let proofs = RocksDbEventProofs::open(Path::new(&path)).unwrap();
+	proofs.add_event_proof(&witnessed_event).unwrap();
+	proofs.flush().unwrap();
+	// Locked by the node holding it open
+	assert!(RocksDbEventProofs::open(Path::new(&path)).is_err());
+	drop(proofs);
+
+	let proofs = RocksDbEventProofs::open(Path::new(&path)).unwrap();
+	assert_eq!(proofs.get_event_proof_count(&event_id, &get_validator_list()), Ok(1));
+	let read = proofs.get_event_proofs(&event_id, &get_validator_list()).unwrap();
+	assert_eq!(read.get(&witnessed_event.pub_key), Some(&witnessed_event.proof()));
+
+	let expected = PathBuf::from("/base/validated-streams-proofs");
+	assert_eq!(RocksDbEventProofs::path_under(Path::new("/base")), expected);
+}
+
 fn get_validator_list() -> [CryptoTypePublicPair; 1] {
-	[CryptoTypePublicPair::from(Public::from_h256(H256::repeat_byte(1)))]
+	[TestValidators::new(1).pub_key(0)]
 }
 fn get_new_validator_list() -> [CryptoTypePublicPair; 1] {
-	[CryptoTypePublicPair::from(Public::from_h256(H256::repeat_byte(2)))]
+	[TestValidators::new(2).pub_key(1)]
 }
 fn create_witnessed_event(event_id: H256) -> WitnessedEvent {
-	WitnessedEvent {
-		event_id,
-		pub_key: CryptoTypePublicPair::from(Public::from_h256(H256::repeat_byte(1))),
-		signature: vec![],
-	}
+	TestValidators::new(1).witness(0, event_id).build()
 }
@@ -0,0 +1,119 @@
+//! The role the node runs the subsystem in, as it changes with the validator set. A node started
+//! with `--validator` witnesses events only while its keystore holds a key of the latest finalized
+//! validator set: [track_role] checks the keystore against every newly finalized set, and switches
+//! the [LocalRole] to [NodeRole::Observer] as soon as the key leaves the set, and back to
+//! [NodeRole::Validator] once it is added again, without restarting anything. While observing, the
+//! node refuses to witness events as observers do, but keeps verifying and collecting the
+//! witnesses gossiped by the others. Nodes started without `--validator` observe for good.
+
+use crate::{
+	errors::Error,
+	events::{get_latest_authorities_list, ValidatorSetHandle},
+	logging::SERVICE,
+	metrics::Metrics,
+	telemetry::NodeRole,
+	traits::ChainAccess,
+	watchdog::{next_beating, Heartbeat},
+};
+use sc_client_api::BlockchainEvents;
+use sp_api::BlockT;
+use sp_keystore::CryptoStore;
+use sp_runtime::key_types::AURA;
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	Arc,
+};
+#[cfg(test)]
+pub mod tests;
+
+/// The current role of the node, shared by the parts of the subsystem which depend on it. Cheap to
+/// clone; the [Default] role is [NodeRole::Validator], for witnessers used outside of a node.
+#[derive(Clone)]
+pub struct LocalRole {
+	inner: Arc<Inner>,
+}
+
+struct Inner {
+	witnessing: AtomicBool,
+	metrics: Metrics,
+}
+
+impl Default for LocalRole {
+	fn default() -> Self {
+		Self::new(NodeRole::Validator, Metrics::default())
+	}
+}
+
+impl LocalRole {
+	/// Starts in the given role, reporting it and its changes to the metrics.
+	pub fn new(role: NodeRole, metrics: Metrics) -> Self {
+		metrics.set_node_role(role.as_str());
+		let witnessing = AtomicBool::new(role == NodeRole::Validator);
+		Self { inner: Arc::new(Inner { witnessing, metrics }) }
+	}
+
+	/// The current role.
+	pub fn get(&self) -> NodeRole {
+		if self.inner.witnessing.load(Ordering::SeqCst) {
+			NodeRole::Validator
+		} else {
+			NodeRole::Observer
+		}
+	}
+
+	/// Changes to the given role, logging the change. Returns whether the role was a different one.
+	pub(crate) fn transition(&self, to: NodeRole) -> bool {
+		let witnessing = to == NodeRole::Validator;
+		if self.inner.witnessing.swap(witnessing, Ordering::SeqCst) == witnessing {
+			return false
+		}
+		let from = if witnessing { NodeRole::Observer } else { NodeRole::Validator };
+		self.inner.metrics.set_node_role(to.as_str());
+		self.inner.metrics.on_role_transition(to.as_str());
+		tracing::info!(target: SERVICE, from = from.as_str(), to = to.as_str(), "Role changed");
+		true
+	}
+}
+
+/// Changes to the role the keystore holds a key for in the latest finalized validator set, and
+/// returns it.
+pub(crate) async fn update_role<Block, Client, AuthorityId>(
+	role: &LocalRole,
+	keystore: &dyn CryptoStore,
+	client: &Client,
+	validator_set: &ValidatorSetHandle<Block>,
+) -> Result<NodeRole, Error>
+where
+	Block: BlockT,
+	Client: ChainAccess<Block, AuthorityId>,
+{
+	let authorities = get_latest_authorities_list(validator_set, client)?;
+	let keys = keystore.supported_keys(AURA, authorities.authorities.to_vec()).await?;
+	let next = if keys.is_empty() { NodeRole::Observer } else { NodeRole::Validator };
+	role.transition(next);
+	Ok(next)
+}
+
+/// Keeps the role of a validator up to date with every newly finalized validator set, until the
+/// client stops producing finality notifications.
+pub(crate) async fn track_role<Block, Client, AuthorityId>(
+	role: LocalRole,
+	keystore: Arc<dyn CryptoStore>,
+	client: Arc<Client>,
+	validator_set: ValidatorSetHandle<Block>,
+	heartbeat: Heartbeat,
+) where
+	Block: BlockT,
+	Client: BlockchainEvents<Block> + ChainAccess<Block, AuthorityId>,
+{
+	let mut finality_notifications = client.finality_notification_stream();
+	loop {
+		let updated = update_role(&role, keystore.as_ref(), client.as_ref(), &validator_set).await;
+		if let Err(e) = updated {
+			tracing::warn!(target: SERVICE, error = %e, "Failed checking the role of the node");
+		}
+		if next_beating(&mut finality_notifications, &heartbeat).await.is_none() {
+			return
+		}
+	}
+}
@@ -0,0 +1,102 @@
+use super::{update_role, LocalRole};
+use crate::{
+	errors::Error,
+	events::{AuthoritiesList, EventWitnesser, WITNESSED_EVENTS_TOPIC},
+	gossip::GossipTrait,
+	logging::SERVICE,
+	metrics::{tests::scrape, Metrics},
+	telemetry::NodeRole,
+	test_utils::{
+		validator_set, CapturedLogs, FakeChain, SimulatedNetwork, SimulatedNode, TestBlock,
+		TestValidators,
+	},
+	traces::Traces,
+	traits::EventWitnesserTrait,
+};
+use libp2p::gossipsub::IdentTopic;
+use prometheus_endpoint::Registry;
+use sp_core::{sr25519::Public, H256};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_role_follows_the_local_key_in_the_validator_set() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let validators = TestValidators::new(4);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	// The other nodes already follow session 2, in which validator 3 is rotated back in, and still
+	// accept the witnesses of the two sessions before
+	let keys = validators.pubkeys();
+	let previous = [(0, keys.clone()), (1, keys[..3].to_vec())];
+	let authorities = AuthoritiesList::new(keys).in_session(2, previous);
+	let nodes = (0..validators.len())
+		.map(|_| Arc::new(SimulatedNode::new(authorities.clone())))
+		.collect();
+	let network = SimulatedNetwork::new(0, nodes);
+
+	// The local node is validator 3
+	let validator_set = validator_set();
+	let keystore = validators.keystore(3);
+	let role = LocalRole::new(NodeRole::Validator, metrics.clone());
+	let witnesser = EventWitnesser::<TestBlock, _, Public, _>::new(
+		chain.clone(),
+		network.gossip(3),
+		keystore.clone(),
+		validator_set.clone(),
+		metrics,
+		Traces::default(),
+	)
+	.with_role(role.clone());
+	let update =
+		|| update_role::<_, _, Public>(&role, keystore.as_ref(), chain.as_ref(), &validator_set);
+
+	assert_eq!(update().await, Ok(NodeRole::Validator));
+	assert_eq!(witnesser.witness_event(H256::repeat_byte(1)).await, Ok(()));
+	assert!(logs.find(SERVICE, "Role changed").is_empty());
+
+	// Validator 3 is rotated out, and stops witnessing
+	chain.rotate_authorities(validators.pubkeys()[..3].to_vec());
+	chain.finalize_best();
+	assert_eq!(update().await, Ok(NodeRole::Observer));
+	assert_eq!(role.get(), NodeRole::Observer);
+	assert_eq!(witnesser.witness_event(H256::repeat_byte(2)).await, Err(Error::NotAValidator));
+	// While still collecting the witnesses of the others
+	let witness = validators.witness(0, H256::repeat_byte(2)).build();
+	let topic = IdentTopic::new(WITNESSED_EVENTS_TOPIC);
+	network.gossip(0).publish(topic, witness.to_bytes().unwrap()).await.unwrap();
+	network.run_until_idle().await;
+	assert!(network.handler(3).stored_proofs().contains(&witness));
+
+	// And again once rotated back in
+	chain.rotate_authorities(validators.pubkeys());
+	chain.finalize_best();
+	assert_eq!(update().await, Ok(NodeRole::Validator));
+	assert_eq!(witnesser.witness_event(H256::repeat_byte(3)).await, Ok(()));
+	network.run_until_idle().await;
+	let own: Vec<_> = network
+		.handler(0)
+		.stored_proofs()
+		.into_iter()
+		.filter(|proof| proof.pub_key == validators.pub_key(3))
+		.map(|proof| proof.event_id)
+		.collect();
+	assert_eq!(own, [H256::repeat_byte(1), H256::repeat_byte(3)]);
+
+	let changes = logs.find(SERVICE, "Role changed");
+	let roles: Vec<_> = changes.iter().map(|line| (line.field("from"), line.field("to"))).collect();
+	assert_eq!(roles, [
+		(Some("validator"), Some("observer")),
+		(Some("observer"), Some("validator")),
+	]);
+	let series = scrape(&registry);
+	let expected = [
+		("streams_role_transitions_total{role=observer}", 1.0),
+		("streams_role_transitions_total{role=validator}", 1.0),
+		("streams_node_role{role=validator}", 1.0),
+	];
+	for (name, value) in expected {
+		assert_eq!(series.get(name), Some(&value), "{name} in {series:?}");
+	}
+	assert_eq!(series.get("streams_node_role{role=observer}"), None);
+}
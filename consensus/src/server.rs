@@ -1,60 +1,1079 @@
 //! A GRPC server for easier use of a validated streams node by external trusted clients.
 /// See <https://github.com/comrade-coop/validated-streams/blob/master/proto/streams.proto> for the protobuf file and associated documentation. (or check [self::validated_streams_proto] out)
 use crate::{
-	errors::Error,
-	traits::{EventValidatorTrait, EventWitnesserTrait},
+	auth::{
+		AdminInterceptor, ApiKeyInterceptor, ApiKeys, ClientCertificateInterceptor,
+		ClientCertificates,
+	},
+	errors::{ConfigError, Error, StartupError},
+	events::WITNESSED_EVENTS_TOPIC,
+	gossip::Gossip,
+	health::report_health,
+	index::{list_events, EventIndexTrait, PageToken, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+	limits::{incoming, ClientLimits, ClientRateLimiter},
+	logging::GRPC,
+	metrics::Metrics,
+	notifications::{EventNotification, EventNotifications, EventStage},
+	payloads::EventPayloads,
+	shutdown::{ShutdownSignal, ShutdownStage},
+	startup::{StartupSignals, StartupStep, STARTUP_WAIT},
+	status::{EventStatus, EventStatuses},
+	streams::stream_event_id,
+	traces::Traces,
+	traits::{EventProofReaderTrait, EventValidatorTrait, EventWitnesserTrait},
+	tunables::Tunables,
 };
-use futures::{future, stream, Stream};
-use sp_core::H256;
-use std::{net::SocketAddr, pin::Pin, sync::Arc};
-use tonic::{transport::Server, Request, Response, Status};
+use futures::{future, stream, Stream, StreamExt, TryFutureExt};
+use libp2p::{gossipsub::IdentTopic, Multiaddr, PeerId};
+use opentelemetry::trace::FutureExt as _;
+use pallet_validated_streams::payload::event_id as canonical_event_id;
+use sp_core::{
+	hashing::{keccak_256, sha2_256},
+	H256,
+};
+use std::{
+	collections::HashMap,
+	io::ErrorKind,
+	net::SocketAddr,
+	path::Path,
+	pin::Pin,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tokio::{
+	net::TcpListener,
+	sync::broadcast::{error::RecvError, Receiver},
+};
+use tonic::{
+	codec::CompressionEncoding,
+	codegen::http::{self, Uri},
+	metadata::MetadataMap,
+	service::{interceptor::InterceptedService, Interceptor},
+	transport::{Body, Certificate, Identity, Server, ServerTlsConfig},
+	Code, Request, Response, Status, Streaming,
+};
+use tower::util::MapRequestLayer;
+use tracing::Instrument;
 use validated_streams_proto::{
+	admin_server::{Admin, AdminServer},
+	get_event_status_response::Status as ProtoStatus,
 	streams_server::{Streams, StreamsServer},
-	ValidatedEvent, ValidatedEventsRequest, ValidatedEventsResponse, WitnessEventRequest,
-	WitnessEventResponse,
+	validated_event_notification::Stage,
+	AddPeersRequest, AddPeersResponse, CancelEventRequest, CancelEventResponse, EventConfirmation,
+	EventSignature, GetApiVersionRequest, GetApiVersionResponse, GetEventPayloadRequest,
+	GetEventPayloadResponse, GetEventProofRequest, GetEventProofResponse, GetEventStatusRequest,
+	GetEventStatusResponse, GetValidatorInfoRequest, GetValidatorInfoResponse, GossipPeer,
+	HashEventRequest, HashEventResponse, Hasher, IndexedEvent, ListEventsRequest,
+	ListEventsResponse, ListPeersRequest, ListPeersResponse, ListValidatedEventsRequest,
+	ListValidatedEventsResponse, RegossipProofsRequest, RegossipProofsResponse, RemovePeerRequest,
+	RemovePeerResponse, SubmitEventsResponse, SubscribeValidatedEventsRequest, TrackedEvent,
+	UpdateConfigRequest, UpdateConfigResponse, ValidatedEvent, ValidatedEventNotification,
+	ValidatedEventsRequest, ValidatedEventsResponse, WitnessEventRequest, WitnessEventResponse,
+	WitnessEventResult, WitnessEventsRequest, WitnessEventsResponse,
 };
 
 /// The protobuf module implemented by this server.
 pub mod validated_streams_proto {
 	#![allow(missing_docs)]
-	tonic::include_proto!("validated_streams");
+	tonic::include_proto!("validated_streams.v1");
+
+	/// The encoded descriptors of the services, served through gRPC reflection.
+	pub const FILE_DESCRIPTOR_SET: &[u8] =
+		tonic::include_file_descriptor_set!("validated_streams_descriptor");
+}
+
+/// The most events a single `WitnessEvents` request can submit.
+pub const MAX_WITNESS_BATCH: usize = 10_000;
+/// How many events of a `SubmitEvents` stream are witnessed at once.
+pub const SUBMIT_CONCURRENCY: usize = 64;
+/// How long before the deadline of a client a witnessing request gives up, so that the client gets
+/// its answer before the deadline passes on its side.
+pub const DEADLINE_MARGIN: Duration = Duration::from_millis(50);
+/// The trailing metadata of a `DEADLINE_EXCEEDED` witnessing request, holding the number of
+/// witnesses of the event the node had collected by then.
+pub const WITNESS_COUNT: &str = "witness-count";
+
+/// How long the server keeps trying to listen on an address which is in use or not available yet,
+/// as while the previous run of the node releases its port or the network interface comes up.
+pub const BIND_RETRY_DEADLINE: Duration = Duration::from_secs(30);
+/// The wait before trying to listen on an address again, doubled after every attempt.
+const BIND_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+/// The longest wait between two attempts at listening on an address.
+const BIND_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// The package of the API served, as versioned in the protobuf file.
+pub const API_PACKAGE: &str = "validated_streams.v1";
+/// The major version of the API, that of [API_PACKAGE].
+pub const API_MAJOR_VERSION: u32 = 1;
+/// The revision of the API within [API_PACKAGE], bumped with every addition to the protobuf file.
+pub const API_MINOR_VERSION: u32 = 2;
+/// The package the services were served under before it was versioned.
+pub const UNVERSIONED_PACKAGE: &str = "ValidatedStreams";
+
+/// Parameters for [run].
+pub struct ServerParams<EventWitnesser, EventValidator> {
+	/// The witnesser of the events submitted by clients.
+	pub event_witnesser: Arc<EventWitnesser>,
+	/// The validator telling which events are validated.
+	pub event_validator: Arc<EventValidator>,
+	/// The addresses to listen on.
+	pub grpc_addrs: Vec<SocketAddr>,
+	/// The TLS configuration (see [tls_config]), or [None] to serve over plaintext.
+	pub tls: Option<ServerTlsConfig>,
+	/// The API keys clients must present one of, if there are any (see [crate::auth]).
+	pub api_keys: ApiKeys,
+	/// The certificates clients must present one of, if there are any (see [crate::auth]).
+	pub client_certificates: ClientCertificates,
+	/// The origins browsers may call the streams service from over gRPC-web (see [grpc_web]).
+	pub web_origins: Vec<String>,
+	/// The limits on the clients.
+	pub limits: ClientLimits,
+	/// The metrics to report to.
+	pub metrics: Metrics,
+	/// The traces of the events.
+	pub traces: Traces,
+	/// The [startup](crate::startup), which witnessing requests are held until done.
+	pub startup: StartupSignals,
+	/// The [tunables](crate::tunables), changed through the AdminGrpc service.
+	pub tunables: Tunables,
+	/// The store of the event payloads, or [None] if the node refuses them.
+	pub payloads: Option<EventPayloads>,
+	/// The largest data of an event which is hashed, rather than stored as a payload.
+	pub max_payload_size: usize,
+	/// The index the validated events are listed from.
+	pub event_index: Arc<dyn EventIndexTrait + Send + Sync>,
+	/// The notifications of the events, sent to subscribers.
+	pub notifications: EventNotifications,
+	/// The progress of the events.
+	pub statuses: EventStatuses,
+	/// The witnesses of the events.
+	pub event_proofs: Arc<dyn EventProofReaderTrait + Send + Sync>,
+	/// The gossip whose peers the AdminGrpc service manages.
+	pub gossip: Gossip,
+	/// The shutdown of the node.
+	pub shutdown: ShutdownSignal,
 }
 
-/// Run a GRPC server with the ValidatedStreamsGrpc service on the specified listen addresses.
+/// Run a GRPC server with the ValidatedStreamsGrpc service, the AdminGrpc service changing the
+/// [tunables](crate::tunables), and the reflection service describing both, on the specified listen
+/// addresses, over TLS if `tls` is given (see
+/// [tls_config]) and plaintext otherwise, to the clients presenting one of the `api_keys` if there
+/// are any, and one of the `client_certificates` if there are any (see [crate::auth]). The
+/// ValidatedStreamsGrpc service is also served over gRPC-web to browsers from the `web_origins`, if
+/// any (see [grpc_web]).
+/// Fails with the first address which cannot be served on, e.g. because it is still in use after
+/// [BIND_RETRY_DEADLINE].
+/// Witnessing requests are held until the [startup](crate::startup) is done. Event payloads are
+/// stored and served if `payloads` are given, and refused otherwise; the data of events is hashed
+/// if no larger than `max_payload_size`. Validated events are listed from the `event_index`, and
+/// subscribers get the `notifications`; the progress of events is read from the `statuses`, and
+/// their witnesses from the `event_proofs`. The AdminGrpc service manages the peers of the
+/// `gossip`, and only serves clients connecting from the host of the node if there are no
+/// `api_keys` (see [AdminInterceptor]). Both services take gzip-compressed requests, and compress
+/// their responses to the clients which accept it. Calls of the services under
+/// [UNVERSIONED_PACKAGE] are served as those under [API_PACKAGE]. Once the shutdown reaches
+/// [ShutdownStage::DrainingRequests], stops accepting requests and returns when those in flight
+/// are done.
 pub async fn run<
 	EventWitnesser: EventWitnesserTrait + Sync + Send + 'static,
 	EventValidator: EventValidatorTrait + Sync + Send + 'static,
 >(
-	event_witnesser: Arc<EventWitnesser>,
-	event_validator: Arc<EventValidator>,
-	grpc_addrs: Vec<SocketAddr>,
-) -> Result<(), Error> {
-	log::info!(
+	params: ServerParams<EventWitnesser, EventValidator>,
+) -> Result<(), StartupError> {
+	let ServerParams {
+		event_witnesser,
+		event_validator,
+		grpc_addrs,
+		tls,
+		api_keys,
+		client_certificates,
+		web_origins,
+		limits,
+		metrics,
+		traces,
+		startup,
+		tunables,
+		payloads,
+		max_payload_size,
+		event_index,
+		notifications,
+		statuses,
+		event_proofs,
+		gossip,
+		shutdown,
+	} = params;
+	tracing::info!(
+		target: GRPC,
+		tls = tls.is_some(),
+		authenticated = api_keys.is_enabled(),
+		allowlisted_certificates = client_certificates.is_enabled(),
+		grpc_web = !web_origins.is_empty(),
+		client_rate_limit = limits.rate_limit,
+		max_connections = limits.max_connections,
+		keepalive_interval = ?limits.keepalive_interval,
+		max_connection_age = ?limits.max_connection_age,
 		"GRPC server can be reached at {}",
-		grpc_addrs.iter().fold(String::new(), |acc, &arg| format!("{acc}, {arg}"))
+		grpc_addrs.iter().map(SocketAddr::to_string).collect::<Vec<_>>().join(", ")
 	);
 
-	future::try_join_all(grpc_addrs.into_iter().map(|a| {
-		Server::builder()
-			.add_service(StreamsServer::new(ValidatedStreamsGrpc {
-				event_witnesser: event_witnesser.clone(),
-				event_validator: event_validator.clone(),
-			}))
-			.serve(a)
-	}))
-	.await
-	.map_err(|e| Error::Other(e.to_string()))?;
+	// Shared by every address, and left out of the API keys for the probes
+	let (health_reporter, health) = tonic_health::server::health_reporter();
+	// gRPC-web is served over HTTP/1.1
+	let http1 = !web_origins.is_empty();
+	// Shared by every address, so that a client cannot get around it by switching
+	let rate_limiter = ClientRateLimiter::new(limits.rate_limit);
+	let serving = future::try_join_all(grpc_addrs.into_iter().map(|a| {
+		let (shutdown, tls, health) = (shutdown.clone(), tls.clone(), health.clone());
+		let interceptor = ApiKeyInterceptor::new(api_keys.clone());
+		let certificates = ClientCertificateInterceptor::new(client_certificates.clone());
+		let grpc = ValidatedStreamsGrpc {
+			event_witnesser: event_witnesser.clone(),
+			event_validator: event_validator.clone(),
+			metrics: metrics.clone(),
+			traces: traces.clone(),
+			startup: startup.clone(),
+			tunables: tunables.clone(),
+			payloads: payloads.clone(),
+			max_payload_size,
+			event_index: event_index.clone(),
+			notifications: notifications.clone(),
+			statuses: statuses.clone(),
+			event_proofs: event_proofs.clone(),
+		};
+		// Refused requests do not count towards the rate limit
+		let (mut allowed, mut auth) = (certificates.clone(), interceptor.clone());
+		let mut rate_limiter = rate_limiter.clone();
+		let limited =
+			move |request: Request<()>| rate_limiter.call(auth.call(allowed.call(request)?)?);
+		// Compressed with the clients which accept it, as batches and lists of events add up
+		let streams = StreamsServer::new(grpc)
+			.accept_compressed(CompressionEncoding::Gzip)
+			.send_compressed(CompressionEncoding::Gzip);
+		let streams = InterceptedService::new(streams, limited);
+		let streams = grpc_web(&web_origins).enable(streams);
+		let admin = AdminGrpc {
+			tunables: tunables.clone(),
+			metrics: metrics.clone(),
+			gossip: gossip.clone(),
+			event_proofs: event_proofs.clone(),
+		};
+		let admin = AdminServer::new(admin)
+			.accept_compressed(CompressionEncoding::Gzip)
+			.send_compressed(CompressionEncoding::Gzip);
+		let (mut allowed, mut admin_auth) =
+			(certificates.clone(), AdminInterceptor::new(api_keys.clone()));
+		let admin = InterceptedService::new(admin, move |request: Request<()>| {
+			admin_auth.call(allowed.call(request)?)
+		});
+		let reflection = tonic_reflection::server::Builder::configure()
+			.register_encoded_file_descriptor_set(validated_streams_proto::FILE_DESCRIPTOR_SET)
+			.register_encoded_file_descriptor_set(tonic_reflection::pb::FILE_DESCRIPTOR_SET)
+			.build()
+			.map(|reflection| {
+				let (mut allowed, mut auth) = (certificates, interceptor);
+				let authorized = move |request: Request<()>| auth.call(allowed.call(request)?);
+				InterceptedService::new(reflection, authorized)
+			})
+			.map_err(|e| StartupError::Server(a, e.to_string()));
+		async move {
+			let reflection = reflection?;
+			let listener = bind(a).await?;
+			let connections =
+				incoming(listener, limits.max_connections, limits.max_connection_age);
+			let (keepalive_interval, keepalive_timeout) = limits.keepalive();
+			let server = Server::builder()
+				.accept_http1(http1)
+				.http2_keepalive_interval(keepalive_interval)
+				.http2_keepalive_timeout(keepalive_timeout);
+			let server = match tls {
+				Some(tls) => server.tls_config(tls).map_err(|e| server_error(a, e))?,
+				None => server,
+			};
+			server
+				.layer(MapRequestLayer::new(versioned_path))
+				.add_service(streams)
+				.add_service(admin)
+				.add_service(reflection)
+				.add_service(health)
+				.serve_with_incoming_shutdown(connections, async {
+					shutdown.reached(ShutdownStage::DrainingRequests).await
+				})
+				.await
+				.map_err(|e| server_error(a, e))
+		}
+	}));
+	let reporting = report_health(health_reporter, startup, shutdown, event_proofs);
+	match future::select(Box::pin(serving), Box::pin(reporting)).await {
+		future::Either::Left((served, _)) => served?,
+		future::Either::Right(((), serving)) => serving.await?,
+	};
 
 	Ok(())
 }
 
+/// The TLS configuration of the server, read from PEM files: the certificate chain it presents and
+/// its private key, and the CA certificates clients must present a certificate signed by, if any.
+/// Without those, any client which can reach the server can connect to it, as over plaintext.
+/// The certificates and the key are only parsed once the server starts.
+pub fn tls_config(
+	cert: &Path,
+	key: &Path,
+	client_ca: Option<&Path>,
+) -> Result<ServerTlsConfig, StartupError> {
+	let read = |path: &Path| {
+		std::fs::read(path).map_err(|e| StartupError::Tls(format!("{}: {e}", path.display())))
+	};
+	let config = ServerTlsConfig::new().identity(Identity::from_pem(read(cert)?, read(key)?));
+	Ok(match client_ca {
+		Some(client_ca) => config.client_ca_root(Certificate::from_pem(read(client_ca)?)),
+		None => config,
+	})
+}
+
+/// The gRPC-web configuration of the streams service: browsers may only call it from the given
+/// origins, or from any if one of them is `*`. With no origin, every browser is refused.
+pub fn grpc_web(origins: &[String]) -> tonic_web::Config {
+	if origins.iter().any(|origin| origin == "*") {
+		tonic_web::config().allow_all_origins()
+	} else {
+		tonic_web::config().allow_origins(origins.iter().cloned())
+	}
+}
+
+/// Routes a call of a service under [UNVERSIONED_PACKAGE] to the same service under [API_PACKAGE],
+/// as the messages did not change.
+fn versioned_path(mut request: http::Request<Body>) -> http::Request<Body> {
+	let unversioned = format!("/{UNVERSIONED_PACKAGE}.");
+	let Some(method) = request.uri().path().strip_prefix(unversioned.as_str()) else {
+		return request
+	};
+	let mut parts = request.uri().clone().into_parts();
+	parts.path_and_query = format!("/{API_PACKAGE}.{method}").parse().ok();
+	if let Ok(uri) = Uri::from_parts(parts) {
+		*request.uri_mut() = uri;
+	}
+	request
+}
+
+/// Listens on the address, retrying with an exponential backoff for up to [BIND_RETRY_DEADLINE]
+/// while it is in use or not available. Fails with the last error otherwise.
+async fn bind(address: SocketAddr) -> Result<TcpListener, StartupError> {
+	let deadline = tokio::time::Instant::now() + BIND_RETRY_DEADLINE;
+	let mut backoff = BIND_INITIAL_BACKOFF;
+	loop {
+		let e = match TcpListener::bind(address).await {
+			Ok(listener) => return Ok(listener),
+			Err(e) => e,
+		};
+		let retried = matches!(e.kind(), ErrorKind::AddrInUse | ErrorKind::AddrNotAvailable);
+		if !retried || tokio::time::Instant::now() + backoff > deadline {
+			return Err(StartupError::Server(address, e.to_string()))
+		}
+		tracing::warn!(
+			target: GRPC,
+			%address,
+			error = %e,
+			?backoff,
+			"Failed listening for GRPC clients; retrying"
+		);
+		tokio::time::sleep(backoff).await;
+		backoff = (backoff * 2).min(BIND_MAX_BACKOFF);
+	}
+}
+
+/// The error of a server which failed on an address.
+fn server_error(address: SocketAddr, e: tonic::transport::Error) -> StartupError {
+	// The transport error only says "transport error"; the reason is in its source
+	let reason = match std::error::Error::source(&e) {
+		Some(source) => format!("{e}: {source}"),
+		None => e.to_string(),
+	};
+	StartupError::Server(address, reason)
+}
+
 /// Implements a GRPC service which allows submitting event hashes from the trusted client and
-/// streaming the finalized events out to the same.
+/// streaming the finalized events out to the same, along with the payloads of the events it
+/// submitted.
 pub struct ValidatedStreamsGrpc<EventWitnesser, EventValidator> {
 	/// A [EventWitnesserTrait] instance.
 	pub event_witnesser: Arc<EventWitnesser>,
 	/// A [EventValidatorTrait] instance.
 	pub event_validator: Arc<EventValidator>,
+	/// The metrics requests are reported to.
+	pub metrics: Metrics,
+	/// The traces requests are reported to.
+	pub traces: Traces,
+	/// The startup witnessing requests wait for.
+	pub startup: StartupSignals,
+	/// The tunables whose rate limit witnessing requests are held to.
+	pub tunables: Tunables,
+	/// Where the payloads submitted along with events are stored, if they are.
+	pub payloads: Option<EventPayloads>,
+	/// The largest data of an event hashed.
+	pub max_payload_size: usize,
+	/// The index validated events are listed from.
+	pub event_index: Arc<dyn EventIndexTrait + Send + Sync>,
+	/// The notifications pushed to the subscribers.
+	pub notifications: EventNotifications,
+	/// The progress of the events.
+	pub statuses: EventStatuses,
+	/// Where the witnesses of the events are read from.
+	pub event_proofs: Arc<dyn EventProofReaderTrait + Send + Sync>,
+}
+
+// Not derived, which would require the witnesser and validator themselves to be Clone
+impl<EventWitnesser, EventValidator> Clone
+	for ValidatedStreamsGrpc<EventWitnesser, EventValidator>
+{
+	fn clone(&self) -> Self {
+		Self {
+			event_witnesser: self.event_witnesser.clone(),
+			event_validator: self.event_validator.clone(),
+			metrics: self.metrics.clone(),
+			traces: self.traces.clone(),
+			startup: self.startup.clone(),
+			tunables: self.tunables.clone(),
+			payloads: self.payloads.clone(),
+			max_payload_size: self.max_payload_size,
+			event_index: self.event_index.clone(),
+			notifications: self.notifications.clone(),
+			statuses: self.statuses.clone(),
+			event_proofs: self.event_proofs.clone(),
+		}
+	}
+}
+
+impl<EventWitnesser: EventWitnesserTrait, EventValidator>
+	ValidatedStreamsGrpc<EventWitnesser, EventValidator>
+{
+	async fn handle_witness_event(
+		&self,
+		event: WitnessEventRequest,
+	) -> Result<WitnessEventResponse, Status> {
+		let received = Instant::now();
+		let submitted_id = parse_event_id(&event.event_id)?;
+		let event_id = stream_event_id(&event.stream_id, submitted_id);
+		tracing::Span::current().record("event_id", tracing::field::display(event_id));
+		tracing::debug!(target: GRPC, event_id = %event_id, "Received event from the client");
+		self.traces.on_event_submitted(event_id);
+
+		let payloads = if event.payload.is_empty() {
+			None
+		} else {
+			let payloads = self.payloads.as_ref().ok_or_else(payloads_disabled)?;
+			// Hashing to the id the client submitted, whichever stream it is of
+			payloads.check(&submitted_id, &event.payload).map_err(|e| {
+				tracing::debug!(target: GRPC, event_id = %event_id, error = %e, "Bad payload");
+				Status::from(e)
+			})?;
+			Some(payloads)
+		};
+
+		// Answered from what the node knows, so that clients can retry without witnessing again
+		let status = self.statuses.get(&event_id);
+		if status == EventStatus::Cancelled {
+			return Err(Error::EventCancelled.into())
+		}
+		if status != EventStatus::Unknown {
+			tracing::debug!(target: GRPC, event_id = %event_id, ?status, "Event already witnessed");
+			self.store_payload(payloads, &event_id, &event.payload)?;
+			return self.witness_response(&event_id, true)
+		}
+
+		if !self.tunables.take_witness_token() {
+			let limit = self.tunables.current().witness_rate_limit;
+			tracing::debug!(target: GRPC, event_id = %event_id, limit, "Rate limited");
+			return Err(Status::resource_exhausted(format!(
+				"rate limit of {limit} witnessing requests per second exceeded"
+			)))
+		}
+
+		self.wait_started().await?;
+
+		let witnessing = self.event_witnesser.witness_stream_event(&event.stream_id, event_id);
+		witnessing.await.map_err(|e| {
+			tracing::debug!(
+				target: GRPC,
+				event_id = %event_id,
+				error = %e,
+				"Failed witnessing event"
+			);
+			Status::from(e)
+		})?;
+		self.metrics.on_event_submitted(event_id, received);
+		self.store_payload(payloads, &event_id, &event.payload)?;
+		self.witness_response(&event_id, false)
+	}
+
+	/// Stores the payload of a witnessed event, if it came with one.
+	fn store_payload(
+		&self,
+		payloads: Option<&EventPayloads>,
+		event_id: &H256,
+		payload: &[u8],
+	) -> Result<(), Status> {
+		let Some(payloads) = payloads else { return Ok(()) };
+		payloads.add(event_id, payload).map_err(|e| {
+			tracing::warn!(
+				target: GRPC,
+				event_id = %event_id,
+				error = %e,
+				"Failed storing the payload of a witnessed event"
+			);
+			Status::from(e)
+		})
+	}
+
+	/// The answer to a witnessing request, with the witnesses of the event held so far and the
+	/// block including it, if any.
+	fn witness_response(
+		&self,
+		event_id: &H256,
+		already_witnessed: bool,
+	) -> Result<WitnessEventResponse, Status> {
+		let proofs = self.event_proofs.get_latest_event_proofs(event_id).map_err(Status::from)?;
+		let status = self.statuses.get(event_id);
+		let extrinsic_submitted = matches!(
+			status,
+			EventStatus::InPool | EventStatus::InBlock { .. } | EventStatus::Finalized { .. }
+		);
+		let (block_hash, block_number) =
+			proto_status(status).1.map_or((vec![], 0), |(hash, number)| (hash.0.to_vec(), number));
+		Ok(WitnessEventResponse {
+			proof_count: proofs.proofs.len() as u32,
+			already_witnessed,
+			extrinsic_submitted,
+			block_hash,
+			block_number,
+		})
+	}
+
+	/// Waits for the startup to be done, up to [STARTUP_WAIT].
+	async fn wait_started(&self) -> Result<(), Status> {
+		let ready = self.startup.all_done(&StartupStep::ALL);
+		if tokio::time::timeout(STARTUP_WAIT, ready).await.is_err() {
+			let pending = self.startup.pending(&StartupStep::ALL);
+			let pending: Vec<_> = pending.iter().map(StartupStep::as_str).collect();
+			tracing::debug!(target: GRPC, ?pending, "Still starting up");
+			return Err(Status::unavailable(format!(
+				"still starting up, waiting for: {}",
+				pending.join(", ")
+			)))
+		}
+		Ok(())
+	}
+
+	/// The status of a witnessing request which ran out of time, stating the number of witnesses of
+	/// its event held from the latest validators, if it can be read.
+	fn deadline_exceeded(&self, event_id: &[u8], timeout: Duration) -> Status {
+		let proofs = parse_event_id(event_id).and_then(|event_id| {
+			let proofs = self.event_proofs.get_latest_event_proofs(&event_id);
+			proofs.map_err(Status::from)
+		});
+		let timeout_ms = timeout.as_millis() as u64;
+		let Ok(proofs) = proofs else {
+			return Status::deadline_exceeded(format!("deadline of {timeout_ms} ms exceeded"))
+		};
+		let (count, target) = (proofs.proofs.len(), proofs.target);
+		tracing::debug!(target: GRPC, timeout_ms, count, "Witnessing request ran out of time");
+		let mut status = Status::deadline_exceeded(format!(
+			"deadline of {timeout_ms} ms exceeded with {count} of the {target} witnesses needed"
+		));
+		status.metadata_mut().insert(WITNESS_COUNT, count.into());
+		status
+	}
+
+	async fn handle_witness_events(
+		&self,
+		request: WitnessEventsRequest,
+	) -> Result<Vec<WitnessEventResult>, Status> {
+		let count = request.events.len();
+		if count > MAX_WITNESS_BATCH {
+			return Err(Status::invalid_argument(format!(
+				"{count} events are more than the {MAX_WITNESS_BATCH} of a batch"
+			)))
+		}
+		tracing::debug!(target: GRPC, count, "Received a batch of events from the client");
+		// Once for the whole batch, so that the events are not refused one by one
+		self.wait_started().await?;
+
+		let results = request.events.into_iter().map(|event| {
+			let span = tracing::debug_span!(
+				target: GRPC,
+				"witness_batched_event",
+				event_id = tracing::field::Empty
+			);
+			self.handle_witness_event(event).instrument(span)
+		});
+		let results = future::join_all(results).await.into_iter().map(|result| match result {
+			Ok(_) => WitnessEventResult { code: Code::Ok as u32, message: String::new() },
+			Err(status) => WitnessEventResult {
+				code: status.code() as u32,
+				message: status.message().to_string(),
+			},
+		});
+		Ok(results.collect())
+	}
+
+	async fn handle_submit_events(
+		&self,
+		events: impl Stream<Item = Result<WitnessEventRequest, Status>> + Unpin,
+	) -> Result<SubmitEventsResponse, Status> {
+		// Once for the whole stream, as for a batch
+		self.wait_started().await?;
+
+		let mut results = events
+			.map(|event| async move {
+				let span = tracing::debug_span!(
+					target: GRPC,
+					"witness_streamed_event",
+					event_id = tracing::field::Empty
+				);
+				Ok::<_, Status>(self.handle_witness_event(event?).instrument(span).await)
+			})
+			.buffer_unordered(SUBMIT_CONCURRENCY);
+		let mut response = SubmitEventsResponse::default();
+		// Failing only if the stream itself does, e.g. with the client going away
+		while let Some(result) = results.next().await {
+			match result? {
+				Ok(_) => response.accepted += 1,
+				Err(status) => *response.rejected.entry(status.code() as u32).or_default() += 1,
+			}
+		}
+		let rejected: u64 = response.rejected.values().sum();
+		tracing::debug!(
+			target: GRPC,
+			accepted = response.accepted,
+			rejected,
+			"Client ended its stream of events"
+		);
+		Ok(response)
+	}
+
+	/// The confirmations of the events of the stream, as [Confirmations] tells them. Witnessing
+	/// only starts once the startup is done.
+	pub(crate) fn handle_submit_and_confirm(
+		&self,
+		events: impl Stream<Item = Result<WitnessEventRequest, Status>> + Send + 'static,
+	) -> impl Stream<Item = Result<EventConfirmation, Status>> + Send + 'static
+	where
+		EventWitnesser: Sync + Send + 'static,
+		EventValidator: Sync + Send + 'static,
+	{
+		let grpc = self.clone();
+		// Subscribed before witnessing, so that no event reaches the threshold unnoticed
+		let notifications = self.notifications.subscribe();
+		let (statuses, event_proofs) = (self.statuses.clone(), self.event_proofs.clone());
+		let confirmations = async move {
+			grpc.wait_started().await?;
+			let grpc = Arc::new(grpc);
+			let witnessing = events
+				.map(move |event| {
+					let grpc = grpc.clone();
+					async move {
+						let event = event?;
+						let span = tracing::debug_span!(
+							target: GRPC,
+							"witness_streamed_event",
+							event_id = tracing::field::Empty
+						);
+						let (event_id, stream_id) =
+							(event.event_id.clone(), event.stream_id.clone());
+						let witnessed = grpc.handle_witness_event(event).instrument(span).await;
+						Ok((event_id, stream_id, witnessed))
+					}
+				})
+				.buffer_unordered(SUBMIT_CONCURRENCY);
+			Ok(Confirmations {
+				witnessing: Box::pin(witnessing),
+				notifications,
+				statuses,
+				event_proofs,
+				pending: HashMap::new(),
+				ended: false,
+				sweeping: false,
+			})
+		};
+		stream::once(confirmations).flat_map(|confirmations| match confirmations {
+			Ok(confirmations) => stream::unfold(confirmations, Confirmations::next).left_stream(),
+			Err(status) => stream::iter([Err(status)]).right_stream(),
+		})
+	}
+
+	async fn handle_hash_event(&self, request: HashEventRequest) -> Result<H256, Status> {
+		let size = request.payload.len();
+		if size > self.max_payload_size {
+			return Err(Error::PayloadTooLarge { size, max: self.max_payload_size }.into())
+		}
+		let hasher = Hasher::from_i32(request.hasher).ok_or_else(|| {
+			Status::invalid_argument(format!("unknown hasher {}", request.hasher))
+		})?;
+		let event_id = match hasher {
+			Hasher::Blake2256 => canonical_event_id(&request.payload),
+			Hasher::Keccak256 => H256(keccak_256(&request.payload)),
+			Hasher::Sha2256 => H256(sha2_256(&request.payload)),
+		};
+		let tracked_id = stream_event_id(&request.stream_id, event_id);
+		tracing::Span::current().record("event_id", tracing::field::display(tracked_id));
+		if request.and_validate {
+			// Payloads are only stored under their canonical ids
+			let stored = self.payloads.is_some() && hasher == Hasher::Blake2256;
+			let payload = if stored { request.payload } else { vec![] };
+			let event_id = event_id.0.to_vec();
+			let event = WitnessEventRequest { event_id, payload, stream_id: request.stream_id };
+			self.handle_witness_event(event).await?;
+		}
+		Ok(tracked_id)
+	}
+
+	fn handle_get_event_payload(&self, request: GetEventPayloadRequest) -> Result<Vec<u8>, Status> {
+		let event_id = parse_event_id(&request.event_id)?;
+		let payloads = self.payloads.as_ref().ok_or_else(payloads_disabled)?;
+		match payloads.get(&event_id) {
+			Ok(Some(payload)) => Ok(payload),
+			Ok(None) => Err(Status::not_found(format!("no payload stored for event {event_id}"))),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	fn handle_get_event_status(
+		&self,
+		request: GetEventStatusRequest,
+	) -> Result<GetEventStatusResponse, Status> {
+		let event_id = parse_event_id(&request.event_id)?;
+		let (status, block) = proto_status(self.statuses.get(&event_id));
+		let (block_hash, block_number) =
+			block.map_or((vec![], 0), |(hash, number)| (hash.0.to_vec(), number));
+		Ok(GetEventStatusResponse { status: status.into(), block_hash, block_number })
+	}
+
+	fn handle_cancel_event(&self, request: CancelEventRequest) -> Result<(), Status> {
+		let event_id = parse_event_id(&request.event_id)?;
+		match self.statuses.cancel(event_id) {
+			Ok(()) => {
+				tracing::info!(target: GRPC, event_id = %event_id, "Cancelled event");
+				Ok(())
+			},
+			Err(EventStatus::Unknown) =>
+				Err(Status::not_found(format!("event {event_id} was not witnessed by this node"))),
+			Err(status) => Err(Status::failed_precondition(format!(
+				"event {event_id} is past the witnessing threshold, as {}",
+				proto_status(status).0.as_str_name()
+			))),
+		}
+	}
+
+	fn handle_get_api_version(&self) -> GetApiVersionResponse {
+		GetApiVersionResponse {
+			package: API_PACKAGE.to_string(),
+			major: API_MAJOR_VERSION,
+			minor: API_MINOR_VERSION,
+			node_version: env!("CARGO_PKG_VERSION").to_string(),
+		}
+	}
+
+	async fn handle_get_validator_info(&self) -> Result<GetValidatorInfoResponse, Status> {
+		let set = self.event_witnesser.witnessing_set().await.map_err(Status::from)?;
+		Ok(GetValidatorInfoResponse {
+			session: set.session,
+			validators: set.validators.into_iter().map(|pub_key| pub_key.1).collect(),
+			target: set.target.into(),
+			witnessing: set.witnessing,
+		})
+	}
+
+	fn handle_get_event_proof(
+		&self,
+		request: GetEventProofRequest,
+	) -> Result<GetEventProofResponse, Status> {
+		let event_id = parse_event_id(&request.event_id)?;
+		let proofs = self
+			.event_proofs
+			.get_latest_event_proofs(&event_id)
+			.map_err(Status::from)?;
+		let signatures = proofs.proofs.into_iter().map(|(pub_key, proof)| EventSignature {
+			public_key: pub_key.1,
+			signature: proof.signature,
+			session: proof.session,
+		});
+		Ok(GetEventProofResponse {
+			session: proofs.session,
+			validators: proofs.validators.into_iter().map(|pub_key| pub_key.1).collect(),
+			target: proofs.target.into(),
+			signatures: signatures.collect(),
+		})
+	}
+
+	fn handle_list_events(&self, request: ListEventsRequest) -> Result<ListEventsResponse, Status> {
+		let after = match request.page_token.len() {
+			0 => None,
+			32 => Some(H256::from_slice(&request.page_token)),
+			_ => return Err(Error::InvalidPageToken.into()),
+		};
+		let statuses = request
+			.statuses
+			.iter()
+			.map(|status| {
+				ProtoStatus::from_i32(*status)
+					.ok_or_else(|| Status::invalid_argument(format!("unknown status {status}")))
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		let page_size = match request.page_size {
+			0 => DEFAULT_PAGE_SIZE,
+			size => size.min(MAX_PAGE_SIZE),
+		} as usize;
+
+		let (listed, more) = self.statuses.list(after, page_size, |status| {
+			statuses.is_empty() || statuses.contains(&proto_status(*status).0)
+		});
+		let next_page_token = match listed.last() {
+			Some((event_id, _)) if more => event_id.0.to_vec(),
+			_ => vec![],
+		};
+		let events = listed
+			.into_iter()
+			.map(|(event_id, status)| {
+				let proofs = self
+					.event_proofs
+					.get_latest_event_proofs(&event_id)
+					.map_err(Status::from)?;
+				Ok(TrackedEvent {
+					event_id: event_id.0.to_vec(),
+					status: proto_status(status).0.into(),
+					proof_count: proofs.proofs.len() as u32,
+				})
+			})
+			.collect::<Result<_, Status>>()?;
+		Ok(ListEventsResponse { events, next_page_token })
+	}
+
+	fn handle_list_validated_events(
+		&self,
+		request: ListValidatedEventsRequest,
+	) -> Result<ListValidatedEventsResponse, Status> {
+		let (from, to) = (request.from_block, request.to_block);
+		if from > to {
+			return Err(Status::invalid_argument(format!(
+				"from_block {from} is after to_block {to}"
+			)))
+		}
+		let token = parse_page_token(&request.page_token)?;
+		let index = self.event_index.as_ref();
+		let (events, next) =
+			list_events(index, from, to, request.page_size, token).map_err(Status::from)?;
+
+		let events = events
+			.into_iter()
+			.map(|event| IndexedEvent {
+				event_id: event.event_id.0.to_vec(),
+				block_number: event.block_number,
+				extrinsic_index: event.extrinsic_index,
+			})
+			.collect();
+		let next_page_token = next
+			.map(|(block, extrinsic)| [block.to_be_bytes(), extrinsic.to_be_bytes()].concat())
+			.unwrap_or_default();
+		Ok(ListValidatedEventsResponse { events, next_page_token })
+	}
+}
+
+/// The status of an event, and the block including it if any, as sent to clients.
+fn proto_status(status: EventStatus) -> (ProtoStatus, Option<(H256, u32)>) {
+	match status {
+		EventStatus::Unknown => (ProtoStatus::Unknown, None),
+		EventStatus::WitnessedBySelf => (ProtoStatus::WitnessedBySelf, None),
+		EventStatus::Cancelled => (ProtoStatus::Cancelled, None),
+		EventStatus::ThresholdReached => (ProtoStatus::ThresholdReached, None),
+		EventStatus::InPool => (ProtoStatus::InPool, None),
+		EventStatus::InBlock { block_hash, block_number } =>
+			(ProtoStatus::InBlock, Some((block_hash, block_number))),
+		EventStatus::Finalized { block_hash, block_number } =>
+			(ProtoStatus::Finalized, Some((block_hash, block_number))),
+	}
+}
+
+fn payloads_disabled() -> Status {
+	Error::PayloadsDisabled.into()
+}
+
+/// The event id of a request, which must be exactly 32 bytes long.
+fn parse_event_id(event_id: &[u8]) -> Result<H256, Status> {
+	if event_id.len() == 32 {
+		Ok(H256::from_slice(event_id))
+	} else {
+		Err(Status::invalid_argument("invalid event_id length (expected 32 bytes)"))
+	}
+}
+
+/// The next notification of a subscription to send, skipping those filtered out; [None] once the
+/// subscription ends, after reporting any notification it missed.
+async fn next_notification(
+	receiver: &mut Receiver<EventNotification>,
+	finalized_only: bool,
+) -> Option<Result<ValidatedEventNotification, Status>> {
+	loop {
+		let notification = match receiver.recv().await {
+			Ok(notification) => notification,
+			Err(RecvError::Lagged(missed)) =>
+				return Some(Err(Status::data_loss(format!(
+					"fell behind the node and missed {missed} notifications"
+				)))),
+			Err(RecvError::Closed) => return None,
+		};
+		let stage = match notification.stage {
+			EventStage::Submitted if finalized_only => continue,
+			EventStage::Submitted => Stage::Submitted,
+			EventStage::Finalized => Stage::Finalized,
+		};
+		return Some(Ok(ValidatedEventNotification {
+			event_id: notification.event_id.0.to_vec(),
+			stage: stage.into(),
+			block_hash: notification.block_hash.map(|hash| hash.0.to_vec()).unwrap_or_default(),
+			witness_count: notification.witness_count.into(),
+		}))
+	}
+}
+
+/// The outcome of witnessing an event of a stream, along with the ID and the stream it was
+/// submitted with.
+type StreamedWitness = Result<(Vec<u8>, String, Result<WitnessEventResponse, Status>), Status>;
+
+/// The state of a `SubmitAndConfirm` call: the events of the client being witnessed, and those
+/// witnessed but yet to reach the threshold, confirmed as the notifications tell.
+struct Confirmations {
+	witnessing: Pin<Box<dyn Stream<Item = StreamedWitness> + Send>>,
+	notifications: Receiver<EventNotification>,
+	statuses: EventStatuses,
+	event_proofs: Arc<dyn EventProofReaderTrait + Send + Sync>,
+	/// The ID and the stream each pending event was submitted with, by the ID it is tracked under
+	pending: HashMap<H256, (H256, String)>,
+	/// Whether the client ended its stream of events
+	ended: bool,
+	/// Whether notifications were missed, so that the pending events are looked up instead
+	sweeping: bool,
+}
+
+impl Confirmations {
+	/// The next confirmation or failure, and the state to carry on with; [None] once the client
+	/// ended its stream and every event is answered.
+	async fn next(mut self) -> Option<(Result<EventConfirmation, Status>, Self)> {
+		loop {
+			if self.sweeping {
+				let statuses = &self.statuses;
+				let found = self.pending.keys().find(|id| statuses.get(id).reached_threshold());
+				match found.copied() {
+					Some(event_id) => return Some((Ok(self.confirmed(event_id)), self)),
+					None => self.sweeping = false,
+				}
+			}
+			if self.ended && self.pending.is_empty() {
+				return None
+			}
+			tokio::select! {
+				witnessed = self.witnessing.next(), if !self.ended => match witnessed {
+					None => self.ended = true,
+					// The stream itself failed, e.g. with the client going away
+					Some(Err(status)) => return Some((Err(status), self)),
+					Some(Ok((event_id, stream_id, Err(status)))) => {
+						let failed = EventConfirmation {
+							event_id,
+							code: status.code() as u32,
+							message: status.message().to_string(),
+							witness_count: 0,
+							stream_id,
+						};
+						return Some((Ok(failed), self))
+					},
+					Some(Ok((event_id, stream_id, Ok(_)))) => {
+						// Only witnessed with a valid ID
+						let submitted_id = H256::from_slice(&event_id);
+						let event_id = stream_event_id(&stream_id, submitted_id);
+						self.pending.insert(event_id, (submitted_id, stream_id));
+						if self.statuses.get(&event_id).reached_threshold() {
+							return Some((Ok(self.confirmed(event_id)), self))
+						}
+					},
+				},
+				notification = self.notifications.recv() => match notification {
+					Ok(notification) if self.pending.contains_key(&notification.event_id) =>
+						return Some((Ok(self.confirmed(notification.event_id)), self)),
+					Ok(_) => {},
+					Err(RecvError::Lagged(missed)) => {
+						tracing::debug!(target: GRPC, missed, "Confirmations fell behind");
+						self.sweeping = true;
+					},
+					Err(RecvError::Closed) =>
+						return Some((Err(Error::ShuttingDown.into()), self)),
+				},
+			}
+		}
+	}
+
+	/// Confirms a pending event, with the witnesses of it held.
+	fn confirmed(&mut self, event_id: H256) -> EventConfirmation {
+		let (submitted_id, stream_id) = self.pending.remove(&event_id).expect("only pending ones");
+		let proofs = self.event_proofs.get_latest_event_proofs(&event_id);
+		EventConfirmation {
+			event_id: submitted_id.0.to_vec(),
+			code: Code::Ok as u32,
+			message: String::new(),
+			witness_count: proofs.map_or(0, |proofs| proofs.proofs.len() as u32),
+			stream_id,
+		}
+	}
+}
+
+/// The page token of a request, if any: the block number and the extrinsic index of the next event,
+/// as two big endian u32s.
+fn parse_page_token(token: &[u8]) -> Result<Option<PageToken>, Status> {
+	match token.len() {
+		0 => Ok(None),
+		8 => {
+			let (block, extrinsic) = token.split_at(4);
+			let parse = |bytes: &[u8]| u32::from_be_bytes(bytes.try_into().expect("4 bytes"));
+			Ok(Some((parse(block), parse(extrinsic))))
+		},
+		_ => Err(Error::InvalidPageToken.into()),
+	}
+}
+
+/// The timeout of a request, from its `grpc-timeout` header: at most 8 digits, followed by the unit
+/// (`H`, `M`, `S`, `m`, `u` or `n`). [None] if there is none, or it cannot be parsed.
+fn grpc_timeout(metadata: &MetadataMap) -> Option<Duration> {
+	// ASCII only, once read as a string
+	let value = metadata.get("grpc-timeout")?.to_str().ok()?;
+	if value.len() < 2 || value.len() > 9 {
+		return None
+	}
+	let (amount, unit) = value.split_at(value.len() - 1);
+	let amount: u64 = amount.parse().ok()?;
+	Some(match unit {
+		"H" => Duration::from_secs(amount * 3600),
+		"M" => Duration::from_secs(amount * 60),
+		"S" => Duration::from_secs(amount),
+		"m" => Duration::from_millis(amount),
+		"u" => Duration::from_micros(amount),
+		"n" => Duration::from_nanos(amount),
+		_ => return None,
+	})
+}
+
+/// The outcome label of a client request, for [Metrics::on_client_request].
+fn outcome<T>(result: &Result<T, Status>) -> &'static str {
+	match result {
+		Ok(_) => "ok",
+		Err(status) => match status.code() {
+			Code::InvalidArgument => "invalid_argument",
+			Code::Internal => "internal",
+			Code::Unavailable => "unavailable",
+			Code::ResourceExhausted => "rate_limited",
+			Code::FailedPrecondition => "failed_precondition",
+			Code::NotFound => "not_found",
+			Code::OutOfRange => "out_of_range",
+			Code::DeadlineExceeded => "deadline_exceeded",
+			_ => "error",
+		},
+	}
 }
 
 #[tonic::async_trait]
@@ -67,19 +1086,75 @@ impl<
 		&self,
 		request: Request<WitnessEventRequest>,
 	) -> Result<Response<WitnessEventResponse>, Status> {
+		let span = tracing::debug_span!(
+			target: GRPC,
+			"handle_client_request",
+			method = "witness_event",
+			event_id = tracing::field::Empty
+		);
+		let cx = self.traces.client_request("witness_event", request.metadata());
+		let timeout = grpc_timeout(request.metadata());
 		let event = request.into_inner();
-		let event_id = if event.event_id.len() == 32 {
-			Ok(H256::from_slice(event.event_id.as_slice()))
-		} else {
-			Err(Status::invalid_argument("invalid event_id length (expected 32 bytes)"))
-		}?;
+		let event_id = event.event_id.clone();
+		let witnessing = self.handle_witness_event(event).instrument(span).with_context(cx);
+		let result = match timeout {
+			Some(timeout) => {
+				let deadline = timeout.saturating_sub(DEADLINE_MARGIN);
+				match tokio::time::timeout(deadline, witnessing).await {
+					Ok(result) => result,
+					Err(_) => Err(self.deadline_exceeded(&event_id, timeout)),
+				}
+			},
+			None => witnessing.await,
+		};
+		self.metrics.on_client_request("witness_event", outcome(&result));
+		Ok(Response::new(result?))
+	}
+
+	async fn witness_events(
+		&self,
+		request: Request<WitnessEventsRequest>,
+	) -> Result<Response<WitnessEventsResponse>, Status> {
+		let span =
+			tracing::debug_span!(target: GRPC, "handle_client_request", method = "witness_events");
+		let cx = self.traces.client_request("witness_events", request.metadata());
+		let result = self
+			.handle_witness_events(request.into_inner())
+			.instrument(span)
+			.with_context(cx)
+			.await;
+		self.metrics.on_client_request("witness_events", outcome(&result));
+
+		Ok(Response::new(WitnessEventsResponse { results: result? }))
+	}
+
+	async fn submit_events(
+		&self,
+		request: Request<Streaming<WitnessEventRequest>>,
+	) -> Result<Response<SubmitEventsResponse>, Status> {
+		let span =
+			tracing::debug_span!(target: GRPC, "handle_client_request", method = "submit_events");
+		let cx = self.traces.client_request("submit_events", request.metadata());
+		let result = self
+			.handle_submit_events(request.into_inner())
+			.instrument(span)
+			.with_context(cx)
+			.await;
+		self.metrics.on_client_request("submit_events", outcome(&result));
 
-		self.event_witnesser
-			.witness_event(event_id)
-			.await
-			.map_err(|e| Status::aborted(e.to_string()))?;
+		Ok(Response::new(result?))
+	}
+
+	type SubmitAndConfirmStream =
+		Pin<Box<dyn Stream<Item = Result<EventConfirmation, Status>> + Send>>;
+
+	async fn submit_and_confirm(
+		&self,
+		request: Request<Streaming<WitnessEventRequest>>,
+	) -> Result<Response<Self::SubmitAndConfirmStream>, Status> {
+		self.metrics.on_client_request("submit_and_confirm", "ok");
 
-		Ok(Response::new(WitnessEventResponse {}))
+		Ok(Response::new(Box::pin(self.handle_submit_and_confirm(request.into_inner()))))
 	}
 
 	// This type looks terrifying, but I'm blaming tonic; even their examples have that!
@@ -91,6 +1166,7 @@ impl<
 		request: Request<ValidatedEventsRequest>,
 	) -> Result<Response<Self::ValidatedEventsStream>, Status> {
 		let request = request.into_inner();
+		self.metrics.on_client_request("validated_events", "ok");
 
 		let mut from_block = request.from_block;
 		if from_block == 0 && request.from_latest {
@@ -106,11 +1182,7 @@ impl<
 				let next_block = block_num + 1;
 
 				let events = match event_validator.get_finalized_block_events(block_num).await {
-					Err(e) =>
-						return Some((
-							Err(Status::aborted(e.to_string())),
-							(event_validator, next_block),
-						)),
+					Err(e) => return Some((Err(Status::from(e)), (event_validator, next_block))),
 					Ok(events) => events,
 				};
 
@@ -126,4 +1198,250 @@ impl<
 			},
 		))))
 	}
+
+	async fn hash_event(
+		&self,
+		request: Request<HashEventRequest>,
+	) -> Result<Response<HashEventResponse>, Status> {
+		let span = tracing::debug_span!(
+			target: GRPC,
+			"handle_client_request",
+			method = "hash_event",
+			event_id = tracing::field::Empty
+		);
+		let cx = self.traces.client_request("hash_event", request.metadata());
+		let result =
+			self.handle_hash_event(request.into_inner()).instrument(span).with_context(cx).await;
+		self.metrics.on_client_request("hash_event", outcome(&result));
+
+		Ok(Response::new(HashEventResponse { event_id: result?.0.to_vec() }))
+	}
+
+	async fn get_event_payload(
+		&self,
+		request: Request<GetEventPayloadRequest>,
+	) -> Result<Response<GetEventPayloadResponse>, Status> {
+		let result = self.handle_get_event_payload(request.into_inner());
+		self.metrics.on_client_request("get_event_payload", outcome(&result));
+
+		Ok(Response::new(GetEventPayloadResponse { payload: result? }))
+	}
+
+	async fn list_validated_events(
+		&self,
+		request: Request<ListValidatedEventsRequest>,
+	) -> Result<Response<ListValidatedEventsResponse>, Status> {
+		let result = self.handle_list_validated_events(request.into_inner());
+		self.metrics.on_client_request("list_validated_events", outcome(&result));
+
+		Ok(Response::new(result?))
+	}
+
+	async fn get_event_status(
+		&self,
+		request: Request<GetEventStatusRequest>,
+	) -> Result<Response<GetEventStatusResponse>, Status> {
+		let result = self.handle_get_event_status(request.into_inner());
+		self.metrics.on_client_request("get_event_status", outcome(&result));
+
+		Ok(Response::new(result?))
+	}
+
+	async fn cancel_event(
+		&self,
+		request: Request<CancelEventRequest>,
+	) -> Result<Response<CancelEventResponse>, Status> {
+		let result = self.handle_cancel_event(request.into_inner());
+		self.metrics.on_client_request("cancel_event", outcome(&result));
+		result?;
+
+		Ok(Response::new(CancelEventResponse {}))
+	}
+
+	async fn get_validator_info(
+		&self,
+		_request: Request<GetValidatorInfoRequest>,
+	) -> Result<Response<GetValidatorInfoResponse>, Status> {
+		let result = self.handle_get_validator_info().await;
+		self.metrics.on_client_request("get_validator_info", outcome(&result));
+
+		Ok(Response::new(result?))
+	}
+
+	async fn get_api_version(
+		&self,
+		_request: Request<GetApiVersionRequest>,
+	) -> Result<Response<GetApiVersionResponse>, Status> {
+		self.metrics.on_client_request("get_api_version", "ok");
+
+		Ok(Response::new(self.handle_get_api_version()))
+	}
+
+	async fn get_event_proof(
+		&self,
+		request: Request<GetEventProofRequest>,
+	) -> Result<Response<GetEventProofResponse>, Status> {
+		let result = self.handle_get_event_proof(request.into_inner());
+		self.metrics.on_client_request("get_event_proof", outcome(&result));
+
+		Ok(Response::new(result?))
+	}
+
+	async fn list_events(
+		&self,
+		request: Request<ListEventsRequest>,
+	) -> Result<Response<ListEventsResponse>, Status> {
+		let result = self.handle_list_events(request.into_inner());
+		self.metrics.on_client_request("list_events", outcome(&result));
+
+		Ok(Response::new(result?))
+	}
+
+	type SubscribeValidatedEventsStream =
+		Pin<Box<dyn Stream<Item = Result<ValidatedEventNotification, Status>> + Send>>;
+
+	async fn subscribe_validated_events(
+		&self,
+		request: Request<SubscribeValidatedEventsRequest>,
+	) -> Result<Response<Self::SubscribeValidatedEventsStream>, Status> {
+		let finalized_only = request.into_inner().finalized_only;
+		self.metrics.on_client_request("subscribe_validated_events", "ok");
+
+		Ok(Response::new(Box::pin(stream::unfold(
+			Some(self.notifications.subscribe()),
+			async move |receiver| {
+				let mut receiver = receiver?;
+				let next = next_notification(&mut receiver, finalized_only).await?;
+				// A subscription which missed notifications ends after telling so
+				let receiver = next.is_ok().then_some(receiver);
+				Some((next, receiver))
+			},
+		))))
+	}
+}
+
+/// Implements the GRPC service changing the [tunables](crate::tunables) of the subsystem and
+/// managing its gossip peers, for the same trusted clients.
+pub struct AdminGrpc {
+	/// The tunables changed.
+	pub tunables: Tunables,
+	/// The metrics requests are reported to.
+	pub metrics: Metrics,
+	/// The gossip whose peers are managed, and which witnesses are gossiped again through.
+	pub gossip: Gossip,
+	/// The witnesses gossiped again.
+	pub event_proofs: Arc<dyn EventProofReaderTrait + Send + Sync>,
+}
+
+impl AdminGrpc {
+	async fn handle_add_peers(&self, request: AddPeersRequest) -> Result<(), Status> {
+		let addresses = request.addresses.iter().map(|address| {
+			address
+				.parse::<Multiaddr>()
+				.map_err(|e| Status::invalid_argument(format!("invalid address {address}: {e}")))
+		});
+		let addresses = addresses.collect::<Result<Vec<_>, _>>()?;
+		tracing::info!(target: GRPC, ?addresses, "Adding gossip peers");
+		self.gossip.clone().connect_to(addresses).await;
+		Ok(())
+	}
+
+	async fn handle_remove_peer(&self, request: RemovePeerRequest) -> Result<bool, Status> {
+		let peer = request.peer_id.parse::<PeerId>().map_err(|e| {
+			Status::invalid_argument(format!("invalid peer ID {}: {e}", request.peer_id))
+		})?;
+		Ok(self.gossip.clone().remove_peer(peer).await?)
+	}
+
+	async fn handle_list_peers(&self) -> Result<ListPeersResponse, Status> {
+		let peers = self.gossip.clone().peers().await?;
+		let peers = peers.into_iter().map(|peer| GossipPeer {
+			peer_id: peer.peer_id.to_string(),
+			addresses: peer.addresses.iter().map(Multiaddr::to_string).collect(),
+			subscribed: peer.subscribed,
+		});
+		let listen_addresses = self.gossip.clone().listen_addresses().await?;
+		Ok(ListPeersResponse {
+			peers: peers.collect(),
+			listen_addresses: listen_addresses.iter().map(Multiaddr::to_string).collect(),
+		})
+	}
+
+	async fn handle_regossip_proofs(&self, request: RegossipProofsRequest) -> Result<u32, Status> {
+		let event_id = parse_event_id(&request.event_id)?;
+		let witnesses = self.event_proofs.get_latest_event_proofs(&event_id)?.witnesses(event_id);
+		if witnesses.is_empty() {
+			return Err(Status::not_found(format!("no witnesses of event {event_id} held")))
+		}
+		let topic = IdentTopic::new(WITNESSED_EVENTS_TOPIC);
+		for witness in &witnesses {
+			self.gossip.clone().publish(topic.clone(), witness.to_bytes()?).await?;
+		}
+		let count = witnesses.len();
+		tracing::debug!(target: GRPC, event_id = %event_id, count, "Regossiped witnesses");
+		Ok(witnesses.len() as u32)
+	}
+}
+
+#[tonic::async_trait]
+impl Admin for AdminGrpc {
+	async fn update_config(
+		&self,
+		request: Request<UpdateConfigRequest>,
+	) -> Result<Response<UpdateConfigResponse>, Status> {
+		let changes = request.into_inner().changes;
+		let changes = changes.iter().map(|(name, value)| (name.as_str(), value.as_str()));
+		let result = self.tunables.update(changes).map_err(|e| {
+			tracing::debug!(target: GRPC, error = %e, "Rejected config update");
+			match e {
+				ConfigError::NotTunable(_) => Status::failed_precondition(e.to_string()),
+				_ => Status::invalid_argument(e.to_string()),
+			}
+		});
+		self.metrics.on_client_request("update_config", outcome(&result));
+		let effective = result?.entries().into_iter().map(|(name, value)| (name.into(), value));
+
+		Ok(Response::new(UpdateConfigResponse { effective: effective.collect() }))
+	}
+
+	async fn add_peers(
+		&self,
+		request: Request<AddPeersRequest>,
+	) -> Result<Response<AddPeersResponse>, Status> {
+		let result = self.handle_add_peers(request.into_inner()).await;
+		self.metrics.on_client_request("add_peers", outcome(&result));
+		result?;
+
+		Ok(Response::new(AddPeersResponse {}))
+	}
+
+	async fn remove_peer(
+		&self,
+		request: Request<RemovePeerRequest>,
+	) -> Result<Response<RemovePeerResponse>, Status> {
+		let result = self.handle_remove_peer(request.into_inner()).await;
+		self.metrics.on_client_request("remove_peer", outcome(&result));
+
+		Ok(Response::new(RemovePeerResponse { connected: result? }))
+	}
+
+	async fn list_peers(
+		&self,
+		_request: Request<ListPeersRequest>,
+	) -> Result<Response<ListPeersResponse>, Status> {
+		let result = self.handle_list_peers().await;
+		self.metrics.on_client_request("list_peers", outcome(&result));
+
+		Ok(Response::new(result?))
+	}
+
+	async fn regossip_proofs(
+		&self,
+		request: Request<RegossipProofsRequest>,
+	) -> Result<Response<RegossipProofsResponse>, Status> {
+		let result = self.handle_regossip_proofs(request.into_inner()).await;
+		self.metrics.on_client_request("regossip_proofs", outcome(&result));
+
+		Ok(Response::new(RegossipProofsResponse { witnesses: result? }))
+	}
 }
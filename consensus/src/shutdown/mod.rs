@@ -0,0 +1,156 @@
+//! Ordered shutdown of the subsystem. Once the node is told to exit, the [StreamsShutdown] returned
+//! by [crate::node::start] stops the parts of the subsystem one after the other, so that nothing
+//! gets cut off halfway: the gRPC server stops accepting requests and drains those in flight, the
+//! witnesser stops signing, the witnesses queued for gossip get handled and the proofs store gets
+//! flushed, and the gossip peers are disconnected. All of that is bounded by [SHUTDOWN_DEADLINE].
+
+use crate::{gossip::Gossip, logging::SERVICE, proofs::EventProofsTrait};
+use futures::channel::oneshot;
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::watch, time::Instant};
+#[cfg(test)]
+pub mod tests;
+
+/// How long the whole shutdown may take before the node exits regardless.
+pub const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(30);
+/// How long the gRPC requests in flight get to finish once the server stops accepting new ones.
+pub const GRPC_DRAIN_GRACE: Duration = Duration::from_secs(10);
+
+/// The stages of a shutdown, in the order they are gone through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ShutdownStage {
+	/// The subsystem runs normally
+	Running,
+	/// The gRPC server accepts no new requests, and waits for those in flight
+	DrainingRequests,
+	/// No new witnesses are signed
+	StoppingWitnessing,
+	/// The queued witnesses and the proofs store are being flushed
+	Flushing,
+	/// The gossip peers are being disconnected
+	ClosingGossip,
+	/// The subsystem has shut down, or ran out of time doing so
+	Done,
+}
+
+/// The stage the shutdown of the subsystem is at, as seen by its parts. Cheap to clone; the
+/// [Default] signal stays at [ShutdownStage::Running] unless advanced.
+#[derive(Clone)]
+pub struct ShutdownSignal {
+	stage: Arc<watch::Sender<ShutdownStage>>,
+}
+
+impl Default for ShutdownSignal {
+	fn default() -> Self {
+		Self { stage: Arc::new(watch::channel(ShutdownStage::Running).0) }
+	}
+}
+
+impl ShutdownSignal {
+	/// The current stage.
+	pub fn stage(&self) -> ShutdownStage {
+		*self.stage.borrow()
+	}
+
+	/// Whether the shutdown has reached the given stage.
+	pub fn has_reached(&self, stage: ShutdownStage) -> bool {
+		self.stage() >= stage
+	}
+
+	/// Waits for the shutdown to reach the given stage.
+	pub async fn reached(&self, stage: ShutdownStage) {
+		let mut stages = self.stage.subscribe();
+		while *stages.borrow_and_update() < stage {
+			// The sender lives as long as self does
+			stages.changed().await.expect("sender is alive");
+		}
+	}
+
+	/// Moves the shutdown on to the given stage. Stages are never gone back to.
+	pub(crate) fn advance(&self, stage: ShutdownStage) {
+		self.stage.send_if_modified(|current| {
+			let advanced = stage > *current;
+			if advanced {
+				*current = stage;
+			}
+			advanced
+		});
+	}
+}
+
+/// Shuts the subsystem down in order; see the module documentation. Returned by
+/// [crate::node::start], to be [run](StreamsShutdown::run) once the node is told to exit.
+#[must_use]
+pub struct StreamsShutdown {
+	signal: ShutdownSignal,
+	event_proofs: Arc<dyn EventProofsTrait + Send + Sync>,
+	gossip: Gossip,
+	grpc_stopped: oneshot::Receiver<()>,
+}
+
+impl StreamsShutdown {
+	/// Creates the shutdown of a subsystem whose gRPC server signals `grpc_stopped` once it has
+	/// drained its requests.
+	pub(crate) fn new(
+		signal: ShutdownSignal,
+		event_proofs: Arc<dyn EventProofsTrait + Send + Sync>,
+		gossip: Gossip,
+		grpc_stopped: oneshot::Receiver<()>,
+	) -> Self {
+		Self { signal, event_proofs, gossip, grpc_stopped }
+	}
+
+	/// The signal the parts of the subsystem follow the shutdown through.
+	pub fn signal(&self) -> ShutdownSignal {
+		self.signal.clone()
+	}
+
+	/// Runs the shutdown through all of its stages, giving up on any remaining ones once the
+	/// deadline has passed.
+	pub async fn run(self, deadline: Duration) {
+		let started = Instant::now();
+		let signal = self.signal.clone();
+		tracing::info!(target: SERVICE, "Shutting down Validated Streams");
+		match tokio::time::timeout(deadline, self.run_stages()).await {
+			Ok(()) => {
+				let elapsed_ms = started.elapsed().as_millis() as u64;
+				tracing::info!(target: SERVICE, elapsed_ms, "Validated Streams shut down");
+			},
+			Err(_) => tracing::warn!(
+				target: SERVICE,
+				stage = ?signal.stage(),
+				"Shutdown deadline passed; exiting regardless"
+			),
+		}
+		signal.advance(ShutdownStage::Done);
+	}
+
+	async fn run_stages(self) {
+		let Self { signal, event_proofs, mut gossip, grpc_stopped } = self;
+
+		signal.advance(ShutdownStage::DrainingRequests);
+		tracing::info!(target: SERVICE, "Draining gRPC requests");
+		match tokio::time::timeout(GRPC_DRAIN_GRACE, grpc_stopped).await {
+			Ok(_) => tracing::info!(target: SERVICE, "gRPC server stopped"),
+			Err(_) => tracing::warn!(
+				target: SERVICE,
+				grace_ms = GRPC_DRAIN_GRACE.as_millis() as u64,
+				"gRPC requests still in flight after the grace period; leaving them"
+			),
+		}
+
+		signal.advance(ShutdownStage::StoppingWitnessing);
+		tracing::info!(target: SERVICE, "Stopped witnessing events");
+
+		signal.advance(ShutdownStage::Flushing);
+		tracing::info!(target: SERVICE, "Flushing the queued witnesses and the proofs store");
+		gossip.flush().await;
+		if let Err(e) = event_proofs.flush() {
+			tracing::error!(target: SERVICE, error = %e, "Failed flushing the proofs store");
+		}
+
+		signal.advance(ShutdownStage::ClosingGossip);
+		tracing::info!(target: SERVICE, "Closing gossip connections");
+		gossip.close().await;
+	}
+}
@@ -0,0 +1,125 @@
+use super::{ShutdownSignal, ShutdownStage, StreamsShutdown, SHUTDOWN_DEADLINE};
+use crate::{
+	errors::Error,
+	gossip::{Gossip, GossipHandler},
+	logging::SERVICE,
+	proofs::{EventProofsTrait, WitnessedEvent},
+	test_utils::{single_validator, CapturedLogs, ProofsCall, TestProofs, TestValidators},
+	traits::EventWitnesserTrait,
+};
+use async_trait::async_trait;
+use futures::{channel::oneshot, future};
+use libp2p::gossipsub::IdentTopic;
+use sp_core::H256;
+use std::{sync::Arc, time::Duration};
+
+const TOPIC: &str = "test_shutdown";
+
+/// Stores every gossiped witness straight into the proofs.
+struct StoringHandler(Arc<TestProofs>);
+
+#[async_trait]
+impl GossipHandler for StoringHandler {
+	fn get_topics(&self) -> Vec<IdentTopic> {
+		vec![IdentTopic::new(TOPIC)]
+	}
+
+	async fn handle(&self, message: &[u8]) {
+		let witnessed_event = WitnessedEvent::from_bytes(message).unwrap();
+		self.0.add_event_proof(&witnessed_event).unwrap();
+	}
+}
+
+#[test]
+fn test_stages_only_advance() {
+	let signal = ShutdownSignal::default();
+	assert_eq!(signal.stage(), ShutdownStage::Running);
+	signal.advance(ShutdownStage::Flushing);
+	signal.advance(ShutdownStage::DrainingRequests);
+	assert_eq!(signal.stage(), ShutdownStage::Flushing);
+	assert!(signal.has_reached(ShutdownStage::StoppingWitnessing));
+	assert!(!signal.has_reached(ShutdownStage::ClosingGossip));
+}
+
+#[tokio::test]
+async fn test_shutdown_flushes_queued_witnesses_in_order() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let validators = TestValidators::new(1);
+	let proofs = Arc::new(TestProofs::new());
+	let (gossip, service) = Gossip::create();
+	let gossip_service = tokio::spawn(service.run(Arc::new(StoringHandler(proofs.clone()))));
+
+	// The gRPC server stops as soon as it is asked to drain
+	let signal = ShutdownSignal::default();
+	let (grpc_stopped, grpc_drained) = oneshot::channel();
+	let draining = signal.clone();
+	tokio::spawn(async move {
+		draining.reached(ShutdownStage::DrainingRequests).await;
+		grpc_stopped.send(()).unwrap();
+	});
+
+	// Witnesses still queued for the gossip when the shutdown starts
+	let witnesses: Vec<_> =
+		(0..3).map(|i| validators.witness(0, H256::from_low_u64_be(i)).build()).collect();
+	let publishing = future::try_join_all(witnesses.iter().map(|witness| {
+		let (mut gossip, message) = (gossip.clone(), witness.to_bytes().unwrap());
+		async move { gossip.publish(IdentTopic::new(TOPIC), message).await }
+	}));
+	let shutdown = StreamsShutdown::new(signal.clone(), proofs.clone(), gossip, grpc_drained);
+	let (published, ()) = futures::join!(publishing, shutdown.run(SHUTDOWN_DEADLINE));
+	assert!(published.is_ok());
+
+	assert_eq!(signal.stage(), ShutdownStage::Done);
+	let mut expected: Vec<_> = witnesses.into_iter().map(ProofsCall::AddEventProof).collect();
+	expected.push(ProofsCall::Flush);
+	assert_eq!(proofs.calls(), expected);
+	// The gossip service stopped once closed
+	assert_eq!(gossip_service.await.unwrap(), Ok(()));
+
+	let progress: Vec<_> = logs
+		.events()
+		.into_iter()
+		.filter(|event| event.target == SERVICE)
+		.map(|event| event.message().to_string())
+		.collect();
+	assert_eq!(progress, [
+		"Shutting down Validated Streams",
+		"Draining gRPC requests",
+		"gRPC server stopped",
+		"Stopped witnessing events",
+		"Flushing the queued witnesses and the proofs store",
+		"Closing gossip connections",
+		"Validated Streams shut down",
+	]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_shutdown_gives_up_at_the_deadline() {
+	let (logs, _guard) = CapturedLogs::capture();
+	// Neither the gRPC server nor the gossip service ever stop
+	let (gossip, _service) = Gossip::create();
+	let (_grpc_stopped, grpc_drained) = oneshot::channel();
+	let signal = ShutdownSignal::default();
+	let shutdown =
+		StreamsShutdown::new(signal.clone(), Arc::new(TestProofs::new()), gossip, grpc_drained);
+
+	let deadline = Duration::from_secs(15);
+	assert!(tokio::time::timeout(deadline * 2, shutdown.run(deadline)).await.is_ok());
+	assert_eq!(signal.stage(), ShutdownStage::Done);
+	assert_eq!(logs.find(SERVICE, "Shutdown deadline passed; exiting regardless").len(), 1);
+}
+
+#[tokio::test]
+async fn test_witnesser_stops_with_shutdown() {
+	let (witnesser, network) = single_validator();
+	let signal = ShutdownSignal::default();
+	let witnesser = witnesser.with_shutdown(signal.clone());
+
+	assert_eq!(witnesser.witness_event(H256::repeat_byte(1)).await, Ok(()));
+	signal.advance(ShutdownStage::DrainingRequests);
+	assert_eq!(witnesser.witness_event(H256::repeat_byte(2)).await, Ok(()));
+	signal.advance(ShutdownStage::StoppingWitnessing);
+	assert_eq!(witnesser.witness_event(H256::repeat_byte(3)).await, Err(Error::ShuttingDown));
+	network.run_until(network.now()).await;
+	assert_eq!(network.trace().len(), 2);
+}
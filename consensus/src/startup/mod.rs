@@ -0,0 +1,129 @@
+//! Ordered startup of the subsystem. Each part of the subsystem marks the [StartupStep] it
+//! completes through the [StartupSignals] shared by [crate::node::start], and the parts which
+//! depend on it wait for it instead of racing it: the gossip subscribes to its topics once the
+//! proofs store is open, and the gRPC server holds witnessing requests until every step is done,
+//! rejecting them if that takes longer than [STARTUP_WAIT]. Among the steps is the gossip
+//! connecting to enough peers, so that the node does not witness events no one would hear of.
+//! Requests for validated events are served straight away, as they only read the chain. Every
+//! step is logged, with the time it took the subsystem to get there, under
+//! `validated_streams::service`.
+
+use crate::logging::SERVICE;
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::watch, time::Instant};
+#[cfg(test)]
+pub mod tests;
+
+/// How long a witnessing request arriving during startup waits for the subsystem to be ready,
+/// before being rejected as unavailable.
+pub const STARTUP_WAIT: Duration = Duration::from_secs(10);
+
+/// The steps of the startup, each done by a different part of the subsystem.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StartupStep {
+	/// The proofs store is open
+	ProofsStoreOpen,
+	/// The keystore was looked up for the witnessing key, or the key found not to be needed
+	KeystoreResolved,
+	/// The gossip listens on its addresses
+	GossipListening,
+	/// The gossip has subscribed to the topics of its handler
+	TopicsSubscribed,
+	/// The gossip is connected to the minimum number of peers, so that witnesses reach someone
+	PeersConnected,
+}
+
+impl StartupStep {
+	/// Every step, in the order they are usually done in.
+	pub const ALL: [Self; 5] = [
+		Self::ProofsStoreOpen,
+		Self::KeystoreResolved,
+		Self::GossipListening,
+		Self::TopicsSubscribed,
+		Self::PeersConnected,
+	];
+
+	/// The name of the step in the logs.
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::ProofsStoreOpen => "proofs_store_open",
+			Self::KeystoreResolved => "keystore_resolved",
+			Self::GossipListening => "gossip_listening",
+			Self::TopicsSubscribed => "topics_subscribed",
+			Self::PeersConnected => "peers_connected",
+		}
+	}
+}
+
+/// Which steps of the startup are done, as seen by the parts of the subsystem. Cheap to clone; the
+/// [Default] signals have no step done, while [StartupSignals::ready] ones have them all.
+#[derive(Clone)]
+pub struct StartupSignals {
+	inner: Arc<Inner>,
+}
+
+struct Inner {
+	started: Instant,
+	/// Whether each step is done, in the order of [StartupStep::ALL]
+	steps: [watch::Sender<bool>; StartupStep::ALL.len()],
+}
+
+impl Default for StartupSignals {
+	fn default() -> Self {
+		let steps = StartupStep::ALL.map(|_| watch::channel(false).0);
+		Self { inner: Arc::new(Inner { started: Instant::now(), steps }) }
+	}
+}
+
+impl StartupSignals {
+	/// Signals with every step done already, for parts of the subsystem used outside of a node.
+	pub fn ready() -> Self {
+		let signals = Self::default();
+		for step in &signals.inner.steps {
+			step.send_replace(true);
+		}
+		signals
+	}
+
+	/// Whether the given step is done.
+	pub fn is_done(&self, step: StartupStep) -> bool {
+		*self.sender(step).borrow()
+	}
+
+	/// The given steps which are not done yet.
+	pub fn pending(&self, steps: &[StartupStep]) -> Vec<StartupStep> {
+		steps.iter().copied().filter(|step| !self.is_done(*step)).collect()
+	}
+
+	/// Waits for the given step to be done.
+	pub async fn done(&self, step: StartupStep) {
+		let mut done = self.sender(step).subscribe();
+		while !*done.borrow_and_update() {
+			// The sender lives as long as self does
+			done.changed().await.expect("sender is alive");
+		}
+	}
+
+	/// Waits for all of the given steps to be done.
+	pub async fn all_done(&self, steps: &[StartupStep]) {
+		for step in steps {
+			self.done(*step).await;
+		}
+	}
+
+	/// Marks the given step as done, logging it the first time.
+	pub(crate) fn mark_done(&self, step: StartupStep) {
+		if self.sender(step).send_replace(true) {
+			return
+		}
+		let elapsed_ms = self.inner.started.elapsed().as_millis() as u64;
+		tracing::info!(target: SERVICE, step = step.as_str(), elapsed_ms, "Startup step done");
+		if self.pending(&StartupStep::ALL).is_empty() {
+			tracing::info!(target: SERVICE, elapsed_ms, "Validated Streams started");
+		}
+	}
+
+	fn sender(&self, step: StartupStep) -> &watch::Sender<bool> {
+		&self.inner.steps[step as usize]
+	}
+}
@@ -0,0 +1,148 @@
+use super::{StartupSignals, StartupStep, STARTUP_WAIT};
+use crate::{
+	logging::SERVICE,
+	server::{
+		validated_streams_proto::{
+			streams_server::Streams, ValidatedEventsRequest, WitnessEventRequest,
+			WitnessEventsRequest,
+		},
+		ValidatedStreamsGrpc, DEADLINE_MARGIN, WITNESS_COUNT,
+	},
+	test_utils::{single_validator, CapturedLogs, SimulatedNetwork, SimulatedNode, TestGrpc},
+};
+use std::{sync::Arc, time::Duration};
+use tonic::{Code, Request, Status};
+
+/// A gRPC service witnessing events with the key of the only validator, waiting on the given
+/// startup.
+fn grpc(startup: StartupSignals) -> (Arc<TestGrpc>, SimulatedNetwork<SimulatedNode>) {
+	let (witnesser, network) = single_validator();
+	let grpc = ValidatedStreamsGrpc { startup, ..ValidatedStreamsGrpc::for_tests(witnesser) };
+	(Arc::new(grpc), network)
+}
+
+/// Sends a witnessing request for the event of the given byte in the background.
+fn witness(grpc: &Arc<TestGrpc>, byte: u8) -> tokio::task::JoinHandle<Result<(), Status>> {
+	let grpc = grpc.clone();
+	tokio::spawn(async move {
+		let request = WitnessEventRequest { event_id: vec![byte; 32], ..Default::default() };
+		grpc.witness_event(Request::new(request)).await.map(|_| ())
+	})
+}
+
+#[test]
+fn test_steps_are_logged_once() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let startup = StartupSignals::default();
+	assert_eq!(startup.pending(&StartupStep::ALL), StartupStep::ALL);
+
+	for step in StartupStep::ALL {
+		startup.mark_done(step);
+		startup.mark_done(step);
+		assert!(startup.is_done(step));
+	}
+	let steps = logs.find(SERVICE, "Startup step done");
+	let names: Vec<_> = steps.iter().map(|line| line.field("step").unwrap().to_string()).collect();
+	let expected: Vec<_> = StartupStep::ALL.iter().map(StartupStep::as_str).collect();
+	assert_eq!(names, expected);
+	assert!(steps.iter().all(|line| line.field("elapsed_ms").is_some()));
+	assert_eq!(logs.find(SERVICE, "Validated Streams started").len(), 1);
+	assert!(StartupSignals::ready().pending(&StartupStep::ALL).is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_requests_wait_for_every_startup_step() {
+	let startup = StartupSignals::default();
+	let (grpc, network) = grpc(startup.clone());
+
+	// Validated events only read the chain, and are served right away
+	let request = ValidatedEventsRequest { from_block: 0, from_latest: true };
+	assert!(grpc.validated_events(Request::new(request)).await.is_ok());
+
+	// A witnessing request arriving during each step is held until the last one is done
+	let mut requests = Vec::new();
+	for (byte, step) in StartupStep::ALL.into_iter().enumerate() {
+		requests.push(witness(&grpc, byte as u8));
+		tokio::time::sleep(Duration::from_secs(1)).await;
+		assert!(requests.iter().all(|request| !request.is_finished()));
+		startup.mark_done(step);
+	}
+	for request in requests {
+		assert!(request.await.unwrap().is_ok());
+	}
+	network.run_until(network.now()).await;
+	assert_eq!(network.trace().len(), StartupStep::ALL.len());
+
+	// And no longer held afterwards
+	assert!(witness(&grpc, 9).await.unwrap().is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_requests_rejected_when_startup_stalls() {
+	let startup = StartupSignals::default();
+	let (grpc, network) = grpc(startup.clone());
+	startup.mark_done(StartupStep::ProofsStoreOpen);
+	startup.mark_done(StartupStep::KeystoreResolved);
+
+	let started = tokio::time::Instant::now();
+	let status = witness(&grpc, 1).await.unwrap().unwrap_err();
+	assert!(started.elapsed() >= STARTUP_WAIT);
+	assert_eq!(status.code(), Code::Unavailable);
+	assert!(status.message().ends_with("topics_subscribed, peers_connected"), "{status}");
+	network.run_until(network.now()).await;
+	assert!(network.trace().is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_requests_answered_before_their_deadline() {
+	let startup = StartupSignals::default();
+	let (grpc, network) = grpc(startup.clone());
+	let request = |timeout: &str| {
+		let mut request =
+			Request::new(WitnessEventRequest { event_id: vec![1; 32], ..Default::default() });
+		request.metadata_mut().insert("grpc-timeout", timeout.parse().unwrap());
+		request
+	};
+
+	let started = tokio::time::Instant::now();
+	let status = grpc.witness_event(request("500m")).await.unwrap_err();
+	assert!(started.elapsed() >= Duration::from_millis(500) - DEADLINE_MARGIN);
+	assert!(started.elapsed() < Duration::from_millis(500));
+	assert_eq!(status.code(), Code::DeadlineExceeded);
+	assert!(status.message().contains("with 0 of the 1 witnesses needed"), "{status}");
+	assert_eq!(status.metadata().get(WITNESS_COUNT).unwrap(), "0");
+
+	// Timeouts which cannot be parsed are ignored
+	let (grpc_clone, unparsed) = (grpc.clone(), request("1x"));
+	let witnessing = tokio::spawn(async move { grpc_clone.witness_event(unparsed).await });
+	tokio::time::sleep(Duration::from_secs(1)).await;
+	StartupStep::ALL.into_iter().for_each(|step| startup.mark_done(step));
+	assert!(witnessing.await.unwrap().is_ok());
+	network.run_until(network.now()).await;
+	assert_eq!(network.trace().len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_batches_wait_for_the_startup_once() {
+	let startup = StartupSignals::default();
+	let (grpc, network) = grpc(startup.clone());
+	startup.mark_done(StartupStep::ProofsStoreOpen);
+	let event = |byte| WitnessEventRequest { event_id: vec![byte; 32], ..Default::default() };
+	let events: Vec<_> = (0..3).map(event).collect();
+
+	// Refused whole, after a single wait
+	let started = tokio::time::Instant::now();
+	let request = Request::new(WitnessEventsRequest { events: events.clone() });
+	let status = grpc.witness_events(request).await.unwrap_err();
+	assert!(started.elapsed() < STARTUP_WAIT * 2);
+	assert_eq!(status.code(), Code::Unavailable);
+
+	for step in StartupStep::ALL {
+		startup.mark_done(step);
+	}
+	let response = grpc.witness_events(Request::new(WitnessEventsRequest { events })).await;
+	let results = response.unwrap().into_inner().results;
+	assert!(results.iter().all(|result| result.code == Code::Ok as u32), "{results:?}");
+	network.run_until(network.now()).await;
+	assert_eq!(network.trace().len(), 3);
+}
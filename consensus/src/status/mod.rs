@@ -0,0 +1,197 @@
+//! The progress of the events the node came across, for the `GetEventStatus` and `ListEvents`
+//! RPCs. The witnesser records the events it witnesses, the gossip handler those which reach the
+//! witnessing threshold and get into the transaction pool, and a task following the chain those
+//! which get included in imported blocks and finalized. An event only moves forward through the
+//! [EventStatus]es, except that a later block including it again replaces the earlier one. An
+//! event witnessed by this node alone can be [cancelled](EventStatuses::cancel) by the client, and
+//! then only moves on to be included in a block, by the other validators.
+//! Statuses are kept in memory for the last [STATUS_CAPACITY] events only; others, including all
+//! of them after a restart, are [EventStatus::Unknown].
+
+use crate::{
+	metrics::block_event_ids,
+	traits::ChainAccess,
+	watchdog::{next_beating, Heartbeat},
+};
+use futures::{stream, StreamExt};
+use lru::LruCache;
+use sc_client_api::{BlockBackend, BlockchainEvents};
+use sp_api::{BlockT, HeaderT};
+use sp_core::H256;
+use std::{
+	num::NonZeroUsize,
+	sync::{Arc, Mutex},
+};
+
+#[cfg(test)]
+pub mod tests;
+
+/// How many events the statuses are kept for.
+pub const STATUS_CAPACITY: usize = 65536;
+
+/// How far an event has gone, as this node knows it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventStatus {
+	/// The node has not come across the event
+	Unknown,
+	/// Witnessed by this node
+	WitnessedBySelf,
+	/// Witnessed by this node, then cancelled by the client before reaching the threshold
+	Cancelled,
+	/// Witnessed by enough validators, as this node collected
+	ThresholdReached,
+	/// Submitted to the transaction pool of this node
+	InPool,
+	/// Included in an imported block, which is not finalized yet
+	InBlock {
+		/// The hash of the block
+		block_hash: H256,
+		/// The number of the block
+		block_number: u32,
+	},
+	/// Included in a finalized block
+	Finalized {
+		/// The hash of the block
+		block_hash: H256,
+		/// The number of the block
+		block_number: u32,
+	},
+}
+
+impl EventStatus {
+	/// How far along the status is; an event never moves back to a lower one.
+	fn rank(&self) -> u8 {
+		match self {
+			EventStatus::Unknown => 0,
+			EventStatus::WitnessedBySelf => 1,
+			// Only left for a block including the event
+			EventStatus::Cancelled => 1,
+			EventStatus::ThresholdReached => 2,
+			EventStatus::InPool => 3,
+			EventStatus::InBlock { .. } => 4,
+			EventStatus::Finalized { .. } => 5,
+		}
+	}
+
+	/// Whether the event was witnessed by enough validators, as this node knows it.
+	pub fn reached_threshold(&self) -> bool {
+		self.rank() >= EventStatus::ThresholdReached.rank()
+	}
+}
+
+/// The statuses of the recent events. Cloning it gives another handle to the same statuses.
+#[derive(Clone)]
+pub struct EventStatuses {
+	statuses: Arc<Mutex<LruCache<H256, EventStatus>>>,
+}
+
+impl Default for EventStatuses {
+	fn default() -> Self {
+		let capacity = NonZeroUsize::new(STATUS_CAPACITY).expect("capacity is not zero");
+		Self { statuses: Arc::new(Mutex::new(LruCache::new(capacity))) }
+	}
+}
+
+impl EventStatuses {
+	/// The status of an event.
+	pub fn get(&self, event_id: &H256) -> EventStatus {
+		let mut statuses = self.statuses.lock().unwrap();
+		statuses.get(event_id).copied().unwrap_or(EventStatus::Unknown)
+	}
+
+	/// Moves an event to the given status, unless it is already further along. A finalized event
+	/// stays in the block it was finalized in, and a cancelled one only moves into a block; events
+	/// are only cancelled through [EventStatuses::cancel].
+	pub fn advance(&self, event_id: H256, status: EventStatus) {
+		let mut statuses = self.statuses.lock().unwrap();
+		let current = statuses.get(&event_id).copied().unwrap_or(EventStatus::Unknown);
+		let replaced = match current {
+			_ if status == EventStatus::Cancelled => false,
+			EventStatus::Finalized { .. } => false,
+			EventStatus::Cancelled =>
+				matches!(status, EventStatus::InBlock { .. } | EventStatus::Finalized { .. }),
+			EventStatus::InBlock { .. } => status.rank() >= current.rank(),
+			_ => status.rank() > current.rank(),
+		};
+		if replaced {
+			statuses.put(event_id, status);
+		}
+	}
+
+	/// Cancels an event witnessed by this node which has not reached the threshold yet, so that
+	/// the node neither witnesses it again nor submits it. Fails with the status of the event
+	/// otherwise.
+	pub fn cancel(&self, event_id: H256) -> Result<(), EventStatus> {
+		let mut statuses = self.statuses.lock().unwrap();
+		match statuses.get(&event_id).copied().unwrap_or(EventStatus::Unknown) {
+			EventStatus::WitnessedBySelf | EventStatus::Cancelled => {
+				statuses.put(event_id, EventStatus::Cancelled);
+				Ok(())
+			},
+			status => Err(status),
+		}
+	}
+
+	/// Lists up to `page_size` of the events whose status passes the `filter`, in the order of
+	/// their ids, starting after the event `after` if given. Returns them along with whether more
+	/// are left.
+	pub fn list(
+		&self,
+		after: Option<H256>,
+		page_size: usize,
+		filter: impl Fn(&EventStatus) -> bool,
+	) -> (Vec<(H256, EventStatus)>, bool) {
+		let statuses = self.statuses.lock().unwrap();
+		// Iterating does not make the events any more recent
+		let mut listed: Vec<_> = statuses
+			.iter()
+			.filter(|(event_id, _)| after.map_or(true, |after| **event_id > after))
+			.filter(|(_, status)| filter(status))
+			.map(|(event_id, status)| (*event_id, *status))
+			.collect();
+		drop(statuses);
+		listed.sort_unstable_by_key(|(event_id, _)| *event_id);
+		let more = listed.len() > page_size;
+		listed.truncate(page_size);
+		(listed, more)
+	}
+}
+
+/// Records the events of every newly-imported block as included in it, and those of every
+/// newly-finalized block as finalized, until the client stops producing notifications.
+pub(crate) async fn track_included_events<Block, Client, AuthorityId>(
+	client: Arc<Client>,
+	statuses: EventStatuses,
+	heartbeat: Heartbeat,
+) where
+	Block: BlockT,
+	Client: BlockchainEvents<Block> + BlockBackend<Block> + ChainAccess<Block, AuthorityId>,
+	<<Block as BlockT>::Header as HeaderT>::Number: Into<u32>,
+{
+	let imported = client.import_notification_stream().map(|notification| {
+		let number: u32 = (*notification.header.number()).into();
+		(vec![(notification.hash, number)], false)
+	});
+	let finalized = client.finality_notification_stream().map(|notification| {
+		// The blocks of the route, from the previously finalized one up, are finalized along with
+		// the notified one
+		let number: u32 = (*notification.header.number()).into();
+		let route = &notification.tree_route;
+		let blocks =
+			route.iter().enumerate().map(|(i, hash)| (*hash, number - (route.len() - i) as u32));
+		(blocks.chain([(notification.hash, number)]).collect::<Vec<_>>(), true)
+	});
+	let mut notifications = stream::select(imported, finalized);
+	while let Some((blocks, finalized)) = next_beating(&mut notifications, &heartbeat).await {
+		for (hash, block_number) in blocks {
+			let Some(event_ids) = block_event_ids(client.as_ref(), hash) else { continue };
+			let block_hash = H256::from_slice(hash.as_ref());
+			let status = if finalized {
+				EventStatus::Finalized { block_hash, block_number }
+			} else {
+				EventStatus::InBlock { block_hash, block_number }
+			};
+			event_ids.into_iter().for_each(|event_id| statuses.advance(event_id, status));
+		}
+	}
+}
@@ -0,0 +1,307 @@
+use super::{EventStatus, EventStatuses};
+use crate::{
+	errors::Error,
+	events::EventGossipHandler,
+	gossip::GossipHandler,
+	metrics::Metrics,
+	proofs::{EventProof, InMemoryEventProofs, ValidatorProofs},
+	server::{
+		validated_streams_proto::{
+			get_event_status_response::Status as ProtoStatus, streams_server::Streams,
+			CancelEventRequest, GetEventStatusRequest, GetEventStatusResponse, ListEventsRequest,
+			ListEventsResponse, TrackedEvent, WitnessEventRequest, WitnessEventResponse,
+		},
+		ValidatedStreamsGrpc,
+	},
+	test_utils::{
+		single_node, single_validator, validator_set, FakeChain, TestBlock, TestGrpc, TestPool,
+		TestValidators,
+	},
+	traces::Traces,
+	traits::{EventProofReaderTrait, EventWitnesserTrait},
+};
+use sc_transaction_pool_api::error::Error as PoolError;
+use sp_core::{
+	sr25519::{self, Public},
+	H256,
+};
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::sync::Arc;
+use tonic::{Code, Request};
+
+fn in_block(byte: u8, block_number: u32) -> EventStatus {
+	EventStatus::InBlock { block_hash: H256::repeat_byte(byte), block_number }
+}
+
+fn finalized(byte: u8, block_number: u32) -> EventStatus {
+	EventStatus::Finalized { block_hash: H256::repeat_byte(byte), block_number }
+}
+
+#[test]
+fn test_statuses_only_move_forward() {
+	let statuses = EventStatuses::default();
+	let event_id = H256::repeat_byte(1);
+	assert_eq!(statuses.get(&event_id), EventStatus::Unknown);
+
+	statuses.advance(event_id, EventStatus::ThresholdReached);
+	statuses.advance(event_id, EventStatus::WitnessedBySelf);
+	assert_eq!(statuses.get(&event_id), EventStatus::ThresholdReached);
+	statuses.advance(event_id, in_block(1, 5));
+	statuses.advance(event_id, EventStatus::InPool);
+	assert_eq!(statuses.get(&event_id), in_block(1, 5));
+	// Included again on another branch
+	statuses.advance(event_id, in_block(2, 5));
+	assert_eq!(statuses.get(&event_id), in_block(2, 5));
+
+	statuses.advance(event_id, finalized(2, 5));
+	statuses.advance(event_id, in_block(3, 6));
+	statuses.advance(event_id, finalized(3, 6));
+	assert_eq!(statuses.get(&event_id), finalized(2, 5));
+	// Shared between the clones
+	assert_eq!(statuses.clone().get(&event_id), finalized(2, 5));
+	assert_eq!(statuses.get(&H256::repeat_byte(2)), EventStatus::Unknown);
+}
+
+#[test]
+fn test_only_events_witnessed_by_self_cancelled() {
+	let statuses = EventStatuses::default();
+	let (witnessed, pooled) = (H256::repeat_byte(1), H256::repeat_byte(2));
+	statuses.advance(witnessed, EventStatus::WitnessedBySelf);
+	statuses.advance(pooled, EventStatus::InPool);
+
+	assert_eq!(statuses.cancel(witnessed), Ok(()));
+	assert_eq!(statuses.cancel(witnessed), Ok(()));
+	assert_eq!(statuses.cancel(pooled), Err(EventStatus::InPool));
+	assert_eq!(statuses.cancel(H256::repeat_byte(3)), Err(EventStatus::Unknown));
+	assert_eq!(statuses.get(&H256::repeat_byte(3)), EventStatus::Unknown);
+
+	// Only moved on by the other validators getting it into a block
+	statuses.advance(witnessed, EventStatus::ThresholdReached);
+	statuses.advance(witnessed, EventStatus::InPool);
+	assert_eq!(statuses.get(&witnessed), EventStatus::Cancelled);
+	statuses.advance(witnessed, in_block(1, 5));
+	assert_eq!(statuses.get(&witnessed), in_block(1, 5));
+	statuses.advance(pooled, EventStatus::Cancelled);
+	assert_eq!(statuses.get(&pooled), EventStatus::InPool);
+}
+
+#[test]
+fn test_statuses_listed_in_pages() {
+	let statuses = EventStatuses::default();
+	for byte in [5, 1, 4, 2, 3] {
+		let status = if byte % 2 == 0 { EventStatus::InPool } else { in_block(byte, 1) };
+		statuses.advance(H256::repeat_byte(byte), status);
+	}
+	let ids = |listed: Vec<(H256, EventStatus)>| -> Vec<u8> {
+		listed.into_iter().map(|(event_id, _)| event_id.0[0]).collect()
+	};
+
+	let (listed, more) = statuses.list(None, 2, |_| true);
+	assert_eq!((ids(listed), more), (vec![1, 2], true));
+	let (listed, more) = statuses.list(Some(H256::repeat_byte(2)), 2, |_| true);
+	assert_eq!((ids(listed), more), (vec![3, 4], true));
+	let (listed, more) = statuses.list(Some(H256::repeat_byte(4)), 2, |_| true);
+	assert_eq!((ids(listed), more), (vec![5], false));
+	let in_pool = |status: &EventStatus| *status == EventStatus::InPool;
+	let (listed, more) = statuses.list(None, 2, in_pool);
+	assert_eq!((ids(listed), more), (vec![2, 4], false));
+	// Listing does not make the events more recent
+	assert_eq!(statuses.get(&H256::repeat_byte(5)), in_block(5, 1));
+}
+
+#[tokio::test]
+async fn test_witnessed_and_submitted_events_recorded() {
+	let validators = TestValidators::new(1);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let statuses = EventStatuses::default();
+	let (witnesser, _network) = single_node(&validators, chain.clone());
+	let witnesser = witnesser.with_statuses(statuses.clone());
+	witnesser.witness_event(H256::repeat_byte(1)).await.unwrap();
+	assert_eq!(statuses.get(&H256::repeat_byte(1)), EventStatus::WitnessedBySelf);
+
+	// A single validator reaches the target with its own witness
+	let pool = Arc::new(TestPool::default());
+	let handler = EventGossipHandler::<_, _, _, Public, TestBlock>::new(
+		chain,
+		Arc::new(InMemoryEventProofs::new()),
+		pool.clone(),
+		validator_set(),
+		Metrics::default(),
+		Traces::default(),
+	)
+	.with_statuses(statuses.clone());
+	let witness = |event| validators.witness(0, H256::repeat_byte(event)).build().to_bytes();
+	handler.handle(&witness(1).unwrap()).await;
+	assert_eq!(statuses.get(&H256::repeat_byte(1)), EventStatus::InPool);
+	// Not in the pool if it refused the extrinsic
+	pool.fail_next(PoolError::ImmediatelyDropped);
+	handler.handle(&witness(2).unwrap()).await;
+	assert_eq!(statuses.get(&H256::repeat_byte(2)), EventStatus::ThresholdReached);
+}
+
+/// A gRPC service reading the given statuses.
+fn grpc(statuses: EventStatuses) -> TestGrpc {
+	let (witnesser, _network) = single_validator();
+	ValidatedStreamsGrpc { statuses, ..ValidatedStreamsGrpc::for_tests(witnesser) }
+}
+
+async fn get_status(grpc: &TestGrpc, event_id: Vec<u8>) -> Result<GetEventStatusResponse, Code> {
+	let request = Request::new(GetEventStatusRequest { event_id });
+	let response = grpc.get_event_status(request).await;
+	response.map(|response| response.into_inner()).map_err(|status| status.code())
+}
+
+#[tokio::test]
+async fn test_get_event_status() {
+	let statuses = EventStatuses::default();
+	let grpc = grpc(statuses.clone());
+	let (pooled, included) = (H256::repeat_byte(1), H256::repeat_byte(2));
+	statuses.advance(pooled, EventStatus::InPool);
+	statuses.advance(included, finalized(9, 7));
+
+	let response = |status: ProtoStatus, block_hash: Vec<u8>, block_number| {
+		Ok(GetEventStatusResponse { status: status.into(), block_hash, block_number })
+	};
+	let expected = response(ProtoStatus::InPool, vec![], 0);
+	assert_eq!(get_status(&grpc, pooled.0.to_vec()).await, expected);
+	let expected = response(ProtoStatus::Finalized, vec![9; 32], 7);
+	assert_eq!(get_status(&grpc, included.0.to_vec()).await, expected);
+	let expected = response(ProtoStatus::Unknown, vec![], 0);
+	assert_eq!(get_status(&grpc, vec![3; 32]).await, expected);
+	assert_eq!(get_status(&grpc, vec![3; 31]).await, Err(Code::InvalidArgument));
+}
+
+/// As many proofs of an event as the first byte of its id.
+struct FirstByteProofs;
+
+impl EventProofReaderTrait for FirstByteProofs {
+	fn get_latest_event_proofs(&self, event_id: &H256) -> Result<ValidatorProofs, Error> {
+		let pub_key = CryptoTypePublicPair(sr25519::CRYPTO_ID, vec![]);
+		let proof = (pub_key, EventProof { session: 0, signature: vec![] });
+		let proofs = vec![proof; event_id.0[0] as usize];
+		Ok(ValidatorProofs { session: 0, validators: vec![], target: 1, proofs })
+	}
+}
+
+async fn list(
+	grpc: &TestGrpc,
+	statuses: &[ProtoStatus],
+	page_size: u32,
+	page_token: Vec<u8>,
+) -> Result<ListEventsResponse, Code> {
+	let statuses = statuses.iter().map(|status| *status as i32).collect();
+	let request = Request::new(ListEventsRequest { statuses, page_size, page_token });
+	let response = grpc.list_events(request).await;
+	response.map(|response| response.into_inner()).map_err(|status| status.code())
+}
+
+#[tokio::test]
+async fn test_list_events() {
+	let statuses = EventStatuses::default();
+	let grpc =
+		ValidatedStreamsGrpc { event_proofs: Arc::new(FirstByteProofs), ..grpc(statuses.clone()) };
+	statuses.advance(H256::repeat_byte(3), EventStatus::InPool);
+	statuses.advance(H256::repeat_byte(1), EventStatus::WitnessedBySelf);
+	statuses.advance(H256::repeat_byte(2), finalized(9, 7));
+
+	let event = |byte, status: ProtoStatus| TrackedEvent {
+		event_id: vec![byte; 32],
+		status: status.into(),
+		proof_count: byte as u32,
+	};
+	let first = list(&grpc, &[], 2, vec![]).await.unwrap();
+	let expected = [event(1, ProtoStatus::WitnessedBySelf), event(2, ProtoStatus::Finalized)];
+	assert_eq!((first.events, first.next_page_token.clone()), (expected.to_vec(), vec![2; 32]));
+	let second = list(&grpc, &[], 2, first.next_page_token).await.unwrap();
+	assert_eq!(second.events, [event(3, ProtoStatus::InPool)]);
+	assert!(second.next_page_token.is_empty());
+
+	// Filtered by status, with the default page size
+	let filtered = list(&grpc, &[ProtoStatus::InPool, ProtoStatus::Finalized], 0, vec![]).await;
+	let expected = [event(2, ProtoStatus::Finalized), event(3, ProtoStatus::InPool)];
+	assert_eq!(filtered.unwrap().events, expected);
+
+	assert_eq!(list(&grpc, &[], 0, vec![1; 31]).await, Err(Code::InvalidArgument));
+	let unknown = ListEventsRequest { statuses: vec![42], page_size: 0, page_token: vec![] };
+	let status = grpc.list_events(Request::new(unknown)).await.unwrap_err();
+	assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_known_events_not_witnessed_again() {
+	let (witnesser, network) = single_validator();
+	let statuses = EventStatuses::default();
+	let witnesser = witnesser.with_statuses(statuses.clone());
+	let grpc = ValidatedStreamsGrpc {
+		event_witnesser: Arc::new(witnesser),
+		event_proofs: Arc::new(FirstByteProofs),
+		..grpc(statuses.clone())
+	};
+	let witness = |byte| {
+		let request = WitnessEventRequest { event_id: vec![byte; 32], ..Default::default() };
+		grpc.witness_event(Request::new(request))
+	};
+	let response = |proof_count, already_witnessed, extrinsic_submitted| WitnessEventResponse {
+		proof_count,
+		already_witnessed,
+		extrinsic_submitted,
+		..Default::default()
+	};
+
+	assert_eq!(witness(2).await.unwrap().into_inner(), response(2, false, false));
+	// As when a client retries
+	assert_eq!(witness(2).await.unwrap().into_inner(), response(2, true, false));
+	// Events the node only knows of from the other validators
+	statuses.advance(H256::repeat_byte(3), EventStatus::InPool);
+	assert_eq!(witness(3).await.unwrap().into_inner(), response(3, true, true));
+	// Along with where they landed on chain
+	let (block_hash, block_number) = (H256::repeat_byte(9), 7);
+	statuses.advance(H256::repeat_byte(4), EventStatus::InBlock { block_hash, block_number });
+	let included = WitnessEventResponse {
+		block_hash: block_hash.0.to_vec(),
+		block_number,
+		..response(4, true, true)
+	};
+	assert_eq!(witness(4).await.unwrap().into_inner(), included);
+	network.run_until(network.now()).await;
+	assert_eq!(network.trace().len(), 1);
+}
+
+#[tokio::test]
+async fn test_cancelled_events_neither_witnessed_nor_submitted() {
+	let validators = TestValidators::new(1);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let statuses = EventStatuses::default();
+	let grpc = grpc(statuses.clone());
+	let cancel = |byte| {
+		let request = CancelEventRequest { event_id: vec![byte; 32] };
+		grpc.cancel_event(Request::new(request))
+	};
+	statuses.advance(H256::repeat_byte(1), EventStatus::WitnessedBySelf);
+	statuses.advance(H256::repeat_byte(2), EventStatus::ThresholdReached);
+
+	assert!(cancel(1).await.is_ok());
+	assert_eq!(cancel(2).await.unwrap_err().code(), Code::FailedPrecondition);
+	assert_eq!(cancel(3).await.unwrap_err().code(), Code::NotFound);
+	let status = get_status(&grpc, vec![1; 32]).await.unwrap().status;
+	assert_eq!(status, ProtoStatus::Cancelled as i32);
+	let request = WitnessEventRequest { event_id: vec![1; 32], ..Default::default() };
+	let status = grpc.witness_event(Request::new(request)).await.unwrap_err();
+	assert_eq!(status.code(), Code::FailedPrecondition);
+
+	// Nor submitted once it reaches the threshold
+	let pool = Arc::new(TestPool::default());
+	let handler = EventGossipHandler::<_, _, _, Public, TestBlock>::new(
+		chain,
+		Arc::new(InMemoryEventProofs::new()),
+		pool.clone(),
+		validator_set(),
+		Metrics::default(),
+		Traces::default(),
+	)
+	.with_statuses(statuses.clone());
+	let witness = validators.witness(0, H256::repeat_byte(1)).build().to_bytes().unwrap();
+	handler.handle(&witness).await;
+	assert!(pool.submitted().is_empty());
+	assert_eq!(statuses.get(&H256::repeat_byte(1)), EventStatus::Cancelled);
+}
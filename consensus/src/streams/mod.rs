@@ -0,0 +1,98 @@
+//! Independent streams of events validated by the same node, for nodes started with `--streams-id`.
+//! Each application submitting events names its stream in the `stream_id` of its requests; the
+//! events of the default stream, with an empty `stream_id`, are witnessed as they always were.
+//! Witnesses of the events of a stream are gossiped on a topic of its own, and the events are
+//! tracked under [stream_event_id]s, which keeps their proofs, statuses and payloads, and the
+//! validated events on chain, apart from those of the other streams.
+
+use crate::{errors::Error, events::WITNESSED_EVENTS_TOPIC};
+use libp2p::gossipsub::IdentTopic;
+use sp_core::{hashing::blake2_256, H256};
+use std::{fmt, str::FromStr, sync::Arc};
+
+#[cfg(test)]
+pub mod tests;
+
+/// The stream of the events submitted without a `stream_id`.
+pub const DEFAULT_STREAM: &str = "";
+/// The longest stream id, in bytes.
+pub const MAX_STREAM_ID_LENGTH: usize = 64;
+
+/// The id of a stream other than the default one: up to [MAX_STREAM_ID_LENGTH] ASCII letters,
+/// digits, `-`, `_` and `.`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId(String);
+
+impl StreamId {
+	/// The id, as clients name the stream.
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+}
+
+impl FromStr for StreamId {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+		if s.is_empty() || s.len() > MAX_STREAM_ID_LENGTH || !s.chars().all(allowed) {
+			return Err(format!(
+				"stream ids are 1 to {MAX_STREAM_ID_LENGTH} ASCII letters, digits, '-', '_' or '.'"
+			))
+		}
+		Ok(Self(s.to_string()))
+	}
+}
+
+impl fmt::Display for StreamId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+/// The id an event of the stream is tracked under: the event id itself for the default stream, and
+/// the blake2-256 hash of the stream id followed by the event id for the others.
+pub fn stream_event_id(stream_id: &str, event_id: H256) -> H256 {
+	if stream_id == DEFAULT_STREAM {
+		return event_id
+	}
+	H256(blake2_256(&[stream_id.as_bytes(), event_id.as_bytes()].concat()))
+}
+
+/// The gossip topic of the witnesses of the events of the stream.
+pub fn stream_topic(stream_id: &str) -> IdentTopic {
+	if stream_id == DEFAULT_STREAM {
+		return IdentTopic::new(WITNESSED_EVENTS_TOPIC)
+	}
+	IdentTopic::new(format!("{WITNESSED_EVENTS_TOPIC}/{stream_id}"))
+}
+
+/// The streams a node validates events of: the default one, and those of `--streams-id`. Cheap to
+/// clone.
+#[derive(Clone, Debug, Default)]
+pub struct EventStreams {
+	ids: Arc<Vec<StreamId>>,
+}
+
+impl EventStreams {
+	/// Validates the events of the given streams, along with those of the default one.
+	pub fn new(mut ids: Vec<StreamId>) -> Self {
+		ids.sort();
+		ids.dedup();
+		Self { ids: Arc::new(ids) }
+	}
+
+	/// Fails with [Error::UnknownStream] if the node does not validate the events of the stream.
+	pub fn check(&self, stream_id: &str) -> Result<(), Error> {
+		if stream_id == DEFAULT_STREAM || self.ids.iter().any(|id| id.as_str() == stream_id) {
+			return Ok(())
+		}
+		Err(Error::UnknownStream(stream_id.to_string()))
+	}
+
+	/// The gossip topics of all the streams, the default one first.
+	pub fn topics(&self) -> Vec<IdentTopic> {
+		let others = self.ids.iter().map(|id| stream_topic(id.as_str()));
+		[stream_topic(DEFAULT_STREAM)].into_iter().chain(others).collect()
+	}
+}
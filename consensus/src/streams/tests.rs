@@ -0,0 +1,93 @@
+use super::{stream_event_id, stream_topic, EventStreams, StreamId, DEFAULT_STREAM};
+use crate::{
+	errors::Error,
+	events::WITNESSED_EVENTS_TOPIC,
+	server::{
+		validated_streams_proto::{streams_server::Streams, WitnessEventRequest},
+		ValidatedStreamsGrpc,
+	},
+	status::{EventStatus, EventStatuses},
+	test_utils::{single_validator, SimulatedNetwork, SimulatedNode, TestWitnesser},
+	traits::EventWitnesserTrait,
+};
+use sp_core::{hashing::blake2_256, H256};
+use tonic::{Code, Request};
+
+fn streams(ids: &[&str]) -> EventStreams {
+	EventStreams::new(ids.iter().map(|id| id.parse().unwrap()).collect())
+}
+
+#[test]
+fn test_stream_ids_parsed() {
+	assert_eq!("app-1.v2_b".parse::<StreamId>().unwrap().as_str(), "app-1.v2_b");
+	assert!("a".repeat(64).parse::<StreamId>().is_ok());
+	for invalid in ["", "with space", "with/slash", "ünicode", &"a".repeat(65)] {
+		assert!(invalid.parse::<StreamId>().is_err(), "{invalid}");
+	}
+}
+
+#[test]
+fn test_streams_kept_apart() {
+	let event_id = H256::repeat_byte(1);
+	assert_eq!(stream_event_id(DEFAULT_STREAM, event_id), event_id);
+	let expected = H256(blake2_256(&[b"app".as_slice(), event_id.as_bytes()].concat()));
+	assert_eq!(stream_event_id("app", event_id), expected);
+	assert_ne!(stream_event_id("other", event_id), expected);
+
+	let streams = streams(&["other", "app", "app"]);
+	let topics: Vec<_> = streams.topics().iter().map(|topic| topic.to_string()).collect();
+	assert_eq!(topics, [WITNESSED_EVENTS_TOPIC, "WitnessedEvent/app", "WitnessedEvent/other"]);
+	assert_eq!(stream_topic(DEFAULT_STREAM).to_string(), WITNESSED_EVENTS_TOPIC);
+	assert_eq!(streams.check("app"), Ok(()));
+	assert_eq!(streams.check(DEFAULT_STREAM), Ok(()));
+	assert_eq!(streams.check("unknown"), Err(Error::UnknownStream("unknown".to_string())));
+}
+
+/// A witnesser of the `app` stream, and a network of a single node listening to the default one.
+fn witnesser(statuses: EventStatuses) -> (TestWitnesser, SimulatedNetwork<SimulatedNode>) {
+	let (witnesser, network) = single_validator();
+	let witnesser = witnesser.with_statuses(statuses).with_streams(streams(&["app"]));
+	(witnesser, network)
+}
+
+#[tokio::test]
+async fn test_witnesses_gossiped_on_the_topic_of_their_stream() {
+	let (witnesser, network) = witnesser(EventStatuses::default());
+	let (default_event, app_event) = (H256::repeat_byte(1), H256::repeat_byte(2));
+
+	witnesser.witness_event(default_event).await.unwrap();
+	witnesser.witness_stream_event("app", stream_event_id("app", app_event)).await.unwrap();
+	let unknown = witnesser.witness_stream_event("unknown", app_event).await;
+	assert_eq!(unknown, Err(Error::UnknownStream("unknown".to_string())));
+	network.run_until_idle().await;
+
+	// The node only listens to the topic of the default stream
+	let proofs = network.handler(0).stored_proofs();
+	let event_ids: Vec<_> = proofs.iter().map(|proof| proof.event_id).collect();
+	assert_eq!(event_ids, [default_event]);
+}
+
+#[tokio::test]
+async fn test_events_tracked_by_their_stream() {
+	let statuses = EventStatuses::default();
+	let (witnesser, _network) = witnesser(statuses.clone());
+	let grpc = ValidatedStreamsGrpc {
+		statuses: statuses.clone(),
+		..ValidatedStreamsGrpc::for_tests(witnesser)
+	};
+	let event_id = H256::repeat_byte(1);
+	let request = |stream_id: &str| {
+		let (event_id, stream_id) = (event_id.0.to_vec(), stream_id.to_string());
+		Request::new(WitnessEventRequest { event_id, stream_id, ..Default::default() })
+	};
+
+	grpc.witness_event(request("app")).await.unwrap();
+	assert_eq!(statuses.get(&stream_event_id("app", event_id)), EventStatus::WitnessedBySelf);
+	assert_eq!(statuses.get(&event_id), EventStatus::Unknown);
+	let status = grpc.witness_event(request("unknown")).await.unwrap_err();
+	assert_eq!(status.code(), Code::FailedPrecondition);
+	// Witnessed apart in the default stream
+	let response = grpc.witness_event(request(DEFAULT_STREAM)).await.unwrap().into_inner();
+	assert!(!response.already_witnessed);
+	assert_eq!(statuses.get(&event_id), EventStatus::WitnessedBySelf);
+}
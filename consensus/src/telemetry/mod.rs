@@ -0,0 +1,133 @@
+//! Messages of the subsystem on the node's Substrate telemetry stream, for networks watching the
+//! telemetry dashboard rather than Prometheus. A `validated_streams.status` message goes out every
+//! [STATUS_INTERVAL], and `validated_streams.quorum_stall` whenever the gossip mesh becomes too
+//! small for events to reach quorum.
+
+use crate::{metrics::Metrics, role::LocalRole, watchdog::Heartbeat};
+use sc_telemetry::{
+	serde_json::Value, TelemetryHandle, TelemetryPayload, VerbosityLevel, CONSENSUS_INFO,
+	CONSENSUS_WARN,
+};
+use std::{sync::Arc, time::Duration};
+#[cfg(test)]
+pub mod tests;
+
+/// How often the status is sent. Matches the interval of Substrate's own `system.interval`.
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Where telemetry messages are sent. Implemented by [TelemetryHandle]; tests substitute a sink
+/// capturing the messages.
+pub trait TelemetrySink: Send + Sync + 'static {
+	/// Sends a message with the given verbosity.
+	fn send(&self, verbosity: VerbosityLevel, payload: TelemetryPayload);
+}
+
+impl TelemetrySink for TelemetryHandle {
+	fn send(&self, verbosity: VerbosityLevel, payload: TelemetryPayload) {
+		self.send_telemetry(verbosity, payload)
+	}
+}
+
+/// The role of the node, as reported in the status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeRole {
+	/// The node witnesses events with its own validator key.
+	Validator,
+	/// The node only follows the chain and the validated events, without witnessing any.
+	Observer,
+}
+
+impl NodeRole {
+	/// The name of the role, as reported in the status and the metrics.
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Validator => "validator",
+			Self::Observer => "observer",
+		}
+	}
+}
+
+/// A handle for sending telemetry messages. Cheap to clone; the [Default] handle drops everything,
+/// for nodes with telemetry disabled and for tests.
+#[derive(Clone, Default)]
+pub struct StreamsTelemetry {
+	sink: Option<Arc<dyn TelemetrySink>>,
+}
+
+impl StreamsTelemetry {
+	/// Sends the messages to the given sink.
+	pub fn new(sink: impl TelemetrySink) -> Self {
+		Self { sink: Some(Arc::new(sink)) }
+	}
+
+	/// Sends the messages to the node's telemetry, if it is enabled.
+	pub fn from_handle(handle: Option<TelemetryHandle>) -> Self {
+		handle.map(Self::new).unwrap_or_default()
+	}
+
+	/// Whether the messages go anywhere.
+	pub fn is_enabled(&self) -> bool {
+		self.sink.is_some()
+	}
+
+	/// Sends the status of the node, from its metrics.
+	pub fn status(&self, metrics: &Metrics, role: NodeRole) {
+		if !self.is_enabled() {
+			return
+		}
+		let counts = metrics.status_counts().unwrap_or_default();
+		self.send(
+			CONSENSUS_INFO,
+			"validated_streams.status",
+			[
+				("pending", counts.pending.into()),
+				("at_quorum", counts.at_quorum.into()),
+				("finalized", counts.finalized.into()),
+				("peers", counts.peers.into()),
+				("role", role.as_str().into()),
+			],
+		);
+	}
+
+	/// Notes that there are too few gossip peers for our events to ever reach quorum.
+	pub fn on_quorum_stall(&self, connected: usize, required: usize) {
+		self.send(
+			CONSENSUS_WARN,
+			"validated_streams.quorum_stall",
+			[("connected", connected.into()), ("required", required.into())],
+		);
+	}
+
+	fn send<const N: usize>(
+		&self,
+		verbosity: VerbosityLevel,
+		message: &str,
+		fields: [(&str, Value); N],
+	) {
+		let Some(sink) = &self.sink else { return };
+		let mut payload = TelemetryPayload::new();
+		payload.insert("msg".into(), message.into());
+		payload.extend(fields.into_iter().map(|(key, value)| (key.into(), value)));
+		sink.send(verbosity, payload);
+	}
+}
+
+/// Sends the status of the node, in its current role, to the telemetry every `interval`, forever.
+/// Returns immediately if the telemetry is not enabled.
+pub async fn report_status(
+	telemetry: StreamsTelemetry,
+	metrics: Metrics,
+	role: LocalRole,
+	interval: Duration,
+	heartbeat: Heartbeat,
+) {
+	if !telemetry.is_enabled() {
+		return
+	}
+	let mut interval = tokio::time::interval(interval);
+	loop {
+		interval.tick().await;
+		heartbeat.bump();
+		telemetry.status(&metrics, role.get());
+	}
+}
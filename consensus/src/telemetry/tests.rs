@@ -0,0 +1,80 @@
+use super::{report_status, NodeRole, StreamsTelemetry, TelemetrySink, STATUS_INTERVAL};
+use crate::{metrics::Metrics, role::LocalRole, watchdog::Heartbeats};
+use prometheus_endpoint::Registry;
+use sc_telemetry::{serde_json::json, TelemetryPayload, VerbosityLevel, CONSENSUS_INFO};
+use sp_core::H256;
+use std::{
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+/// Captures the messages sent to the telemetry.
+#[derive(Clone, Default)]
+struct CapturedTelemetry(Arc<Mutex<Vec<(VerbosityLevel, TelemetryPayload)>>>);
+
+impl CapturedTelemetry {
+	fn messages(&self) -> Vec<(VerbosityLevel, TelemetryPayload)> {
+		self.0.lock().unwrap().clone()
+	}
+}
+
+impl TelemetrySink for CapturedTelemetry {
+	fn send(&self, verbosity: VerbosityLevel, payload: TelemetryPayload) {
+		self.0.lock().unwrap().push((verbosity, payload));
+	}
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_status_schema_and_cadence() {
+	let captured = CapturedTelemetry::default();
+	let metrics = Metrics::register(Some(&Registry::new())).unwrap();
+	metrics.set_pending_events(2);
+	metrics.set_gossip_peers(3);
+	metrics.on_events_finalized(&[H256::repeat_byte(1)]);
+	let telemetry = StreamsTelemetry::new(captured.clone());
+	let heartbeat = Heartbeats::default().register("telemetry");
+	let role = LocalRole::new(NodeRole::Validator, Metrics::default());
+	tokio::spawn(report_status(telemetry, metrics, role, STATUS_INTERVAL, heartbeat));
+
+	// The first status goes out right away, and then once every interval
+	tokio::time::sleep(STATUS_INTERVAL * 3 - Duration::from_millis(1)).await;
+	let messages = captured.messages();
+	assert_eq!(messages.len(), 3);
+	let expected = json!({
+		"msg": "validated_streams.status",
+		"pending": 2,
+		"at_quorum": 0,
+		"finalized": 1,
+		"peers": 3,
+		"role": "validator",
+	});
+	for (verbosity, payload) in messages {
+		assert_eq!(verbosity, CONSENSUS_INFO);
+		assert_eq!(json!(payload), expected);
+	}
+}
+
+#[test]
+fn test_quorum_stall_schema() {
+	let captured = CapturedTelemetry::default();
+	StreamsTelemetry::new(captured.clone()).on_quorum_stall(1, 2);
+
+	let messages: Vec<_> = captured.messages().into_iter().map(|(_, payload)| payload).collect();
+	let expected = json!({
+		"msg": "validated_streams.quorum_stall",
+		"connected": 1,
+		"required": 2,
+	});
+	assert_eq!(json!(messages), json!([expected]));
+}
+
+#[tokio::test]
+async fn test_disabled_telemetry_sends_nothing() {
+	let telemetry = StreamsTelemetry::from_handle(None);
+	assert!(!telemetry.is_enabled());
+	// Returns right away instead of ticking forever
+	let heartbeat = Heartbeats::default().register("telemetry");
+	let role = LocalRole::new(NodeRole::Observer, Metrics::default());
+	report_status(telemetry.clone(), Metrics::default(), role, STATUS_INTERVAL, heartbeat).await;
+	telemetry.on_quorum_stall(0, 1);
+}
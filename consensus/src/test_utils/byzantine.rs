@@ -0,0 +1,110 @@
+//! Validators for the [super::SimulatedNetwork] which can be made to misbehave
+
+use super::{SimulatedNode, TestValidators};
+use crate::{
+	events::AuthoritiesList, gossip::GossipHandler, metrics::Metrics, proofs::WitnessedEvent,
+};
+use async_trait::async_trait;
+use libp2p::gossipsub::IdentTopic;
+use pallet_validated_streams::payload::SessionIndex;
+use sp_core::H256;
+use std::{ops::RangeInclusive, sync::Mutex};
+
+/// Ways in which a [SimulatedValidator] can deviate from the protocol when witnessing events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+	/// Signs bytes other than the witness payload of the event
+	SignsGarbage,
+	/// Publishes a validly-signed proof of each event in every session still accepted, the oldest
+	/// first
+	Equivocates,
+	/// Never publishes any proofs
+	Withholds,
+	/// Along with its own proof, republishes every message it has received so far, and the proofs
+	/// in them re-targeted at the new event
+	Replays,
+}
+
+/// A validator of a simulated network. Handles gossip exactly like a [SimulatedNode], but witnesses
+/// events according to its [Fault], if any.
+pub struct SimulatedValidator {
+	index: usize,
+	fault: Option<Fault>,
+	/// The sessions whose witnesses are accepted, the last one being the current session
+	sessions: RangeInclusive<SessionIndex>,
+	node: SimulatedNode,
+	received: Mutex<Vec<Vec<u8>>>,
+}
+
+impl SimulatedValidator {
+	/// Create validator `index` of a validator set with the given authorities.
+	pub fn new(index: usize, authorities: AuthoritiesList, fault: Option<Fault>) -> Self {
+		Self::with_metrics(index, authorities, fault, Metrics::default())
+	}
+
+	/// Create validator `index` of a validator set with the given authorities, which reports to
+	/// the given metrics.
+	pub fn with_metrics(
+		index: usize,
+		authorities: AuthoritiesList,
+		fault: Option<Fault>,
+		metrics: Metrics,
+	) -> Self {
+		let sessions = authorities.oldest_session()..=authorities.session;
+		let node = SimulatedNode::with_metrics(authorities, metrics);
+		Self { index, fault, sessions, node, received: Mutex::new(Vec::new()) }
+	}
+
+	/// The fault of the validator, or None if it is honest.
+	pub fn fault(&self) -> Option<Fault> {
+		self.fault
+	}
+
+	/// The honest gossip-handling part of the validator.
+	pub fn node(&self) -> &SimulatedNode {
+		&self.node
+	}
+
+	/// The messages the validator publishes when asked to witness the event. `validators` must
+	/// hold the validator's key at its index.
+	pub fn witness(&self, validators: &TestValidators, event_id: H256) -> Vec<Vec<u8>> {
+		let session = *self.sessions.end();
+		let witness = |session| validators.witness(self.index, event_id).in_session(session);
+		let honest = || witness(session).build();
+		let events = match self.fault {
+			None => vec![honest()],
+			Some(Fault::SignsGarbage) => {
+				let garbage = [event_id.as_bytes(), b"garbage"].concat();
+				vec![witness(session).signing(&garbage).build()]
+			},
+			Some(Fault::Equivocates) =>
+				self.sessions.clone().map(|session| witness(session).build()).collect(),
+			Some(Fault::Withholds) => vec![],
+			Some(Fault::Replays) => {
+				let received = self.received.lock().unwrap().clone();
+				let retargeted = received
+					.iter()
+					.filter_map(|message| WitnessedEvent::from_bytes(message).ok())
+					.map(|old| WitnessedEvent { event_id, ..old })
+					.map(|event| event.to_bytes().unwrap());
+				let mut messages = vec![honest().to_bytes().unwrap()];
+				messages.extend(received.iter().cloned());
+				messages.extend(retargeted);
+				return messages
+			},
+		};
+		events.iter().map(|event| event.to_bytes().unwrap()).collect()
+	}
+}
+
+#[async_trait]
+impl GossipHandler for SimulatedValidator {
+	fn get_topics(&self) -> Vec<IdentTopic> {
+		self.node.get_topics()
+	}
+
+	async fn handle(&self, message: &[u8]) {
+		self.received.lock().unwrap().push(message.to_vec());
+		self.node.handle(message).await
+	}
+}
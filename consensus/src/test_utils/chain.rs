@@ -0,0 +1,214 @@
+//! An in-memory, scriptable [ChainAccess] implementation
+
+use crate::{
+	errors::Error,
+	traits::{ChainAccess, EventValidatorTrait},
+};
+use async_trait::async_trait;
+use pallet_validated_streams::payload::SessionIndex;
+use sp_core::{
+	sr25519::{Public, Signature},
+	H256,
+};
+use sp_runtime::{
+	app_crypto::CryptoTypePublicPair,
+	testing::{Block, ExtrinsicWrapper},
+};
+use std::{collections::BTreeMap, sync::Mutex};
+
+/// The extrinsics of a [TestBlock]: each one validates the event with the wrapped id.
+pub type TestExtrinsic = ExtrinsicWrapper<H256>;
+/// The block type of a [FakeChain].
+pub type TestBlock = Block<TestExtrinsic>;
+
+/// An extrinsic created through [ChainAccess::create_unsigned_extrinsic].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CreatedExtrinsic {
+	/// The block the extrinsic was created at.
+	pub at: H256,
+	/// The event validated by the extrinsic.
+	pub event_id: H256,
+	/// The proofs passed along with the event.
+	pub event_proofs: Option<BTreeMap<Public, (SessionIndex, Signature)>>,
+}
+
+/// A single chain of blocks, whose authorities, best and finalized blocks are all set by the test.
+/// Block `n` has the hash `H256::from_low_u64_be(n)`, which makes it easy to refer to from tests.
+/// Every rotation of the authorities starts a new session; by default, witnesses are only
+/// accepted from the current one.
+pub struct FakeChain {
+	state: Mutex<State>,
+}
+
+struct State {
+	/// The authorities at each block, by block number
+	authorities: Vec<Vec<CryptoTypePublicPair>>,
+	/// The session at each block, by block number
+	sessions: Vec<SessionIndex>,
+	/// How many previous sessions witnesses are accepted from
+	session_grace: u32,
+	finalized: u64,
+	created: Vec<CreatedExtrinsic>,
+	/// Whether the state of the blocks can no longer be read
+	pruned: bool,
+}
+
+impl FakeChain {
+	/// Create a chain holding only a genesis block, which is also finalized.
+	pub fn new(authorities: Vec<CryptoTypePublicPair>) -> Self {
+		Self {
+			state: Mutex::new(State {
+				authorities: vec![authorities],
+				sessions: vec![0],
+				session_grace: 0,
+				finalized: 0,
+				created: Vec::new(),
+				pruned: false,
+			}),
+		}
+	}
+
+	/// The hash of block `number`.
+	pub fn hash(number: u64) -> H256 {
+		H256::from_low_u64_be(number)
+	}
+
+	/// Add a new best block, with the same authorities as the previous one. Returns its hash.
+	pub fn advance(&self) -> H256 {
+		let mut state = self.state.lock().unwrap();
+		let authorities = state.authorities.last().expect("genesis exists").clone();
+		let session = *state.sessions.last().expect("genesis exists");
+		state.authorities.push(authorities);
+		state.sessions.push(session);
+		Self::hash(state.authorities.len() as u64 - 1)
+	}
+
+	/// Add a new best block, at which the authorities are rotated to the given ones, starting a
+	/// new session. Returns its hash.
+	pub fn rotate_authorities(&self, authorities: Vec<CryptoTypePublicPair>) -> H256 {
+		let mut state = self.state.lock().unwrap();
+		let session = state.sessions.last().expect("genesis exists") + 1;
+		state.authorities.push(authorities);
+		state.sessions.push(session);
+		Self::hash(state.authorities.len() as u64 - 1)
+	}
+
+	/// Accept witnesses from up to `grace` sessions before the current one.
+	pub fn set_session_grace(&self, grace: u32) {
+		self.state.lock().unwrap().session_grace = grace;
+	}
+
+	/// Move the finalized block to `number`. It can be moved back as well, which makes it possible
+	/// to test finality flipping between blocks with different authorities.
+	pub fn set_finalized(&self, number: u64) {
+		let mut state = self.state.lock().unwrap();
+		assert!((number as usize) < state.authorities.len(), "block {number} does not exist");
+		state.finalized = number;
+	}
+
+	/// Finalize the best block.
+	pub fn finalize_best(&self) {
+		let best = self.state.lock().unwrap().authorities.len() as u64 - 1;
+		self.set_finalized(best)
+	}
+
+	/// Makes the state of every block unreadable, as though it was pruned, or readable again.
+	pub fn set_pruned(&self, pruned: bool) {
+		self.state.lock().unwrap().pruned = pruned;
+	}
+
+	/// All extrinsics created so far, in order.
+	pub fn created_extrinsics(&self) -> Vec<CreatedExtrinsic> {
+		self.state.lock().unwrap().created.clone()
+	}
+
+	fn number(state: &State, hash: H256) -> Result<usize, Error> {
+		let number = hash.to_low_u64_be() as usize;
+		if state.pruned {
+			Err(Error::Other(format!("State of block {hash:?} is pruned")))
+		} else if hash == Self::hash(number as u64) && number < state.authorities.len() {
+			Ok(number)
+		} else {
+			Err(Error::Other(format!("Unknown block {hash:?}")))
+		}
+	}
+}
+
+impl<AuthorityId> ChainAccess<TestBlock, AuthorityId> for FakeChain {
+	fn best_block(&self) -> (u64, H256) {
+		let best = self.state.lock().unwrap().authorities.len() as u64 - 1;
+		(best, Self::hash(best))
+	}
+
+	fn finalized_block(&self) -> (u64, H256) {
+		let finalized = self.state.lock().unwrap().finalized;
+		(finalized, Self::hash(finalized))
+	}
+
+	fn authorities(&self, at: H256) -> Result<Vec<CryptoTypePublicPair>, Error> {
+		let state = self.state.lock()?;
+		Ok(state.authorities[Self::number(&state, at)?].clone())
+	}
+
+	fn session(&self, at: H256) -> Result<SessionIndex, Error> {
+		let state = self.state.lock()?;
+		Ok(state.sessions[Self::number(&state, at)?])
+	}
+
+	fn previous_sessions(
+		&self,
+		at: H256,
+	) -> Result<Vec<(SessionIndex, Vec<CryptoTypePublicPair>)>, Error> {
+		let state = self.state.lock()?;
+		let number = Self::number(&state, at)?;
+		let current = state.sessions[number];
+		let oldest = current.saturating_sub(state.session_grace);
+		// Each session starts at a rotation, and keeps its authorities throughout
+		let mut previous = BTreeMap::new();
+		for (session, authorities) in state.sessions[..=number].iter().zip(&state.authorities) {
+			if (oldest..current).contains(session) {
+				previous.entry(*session).or_insert_with(|| authorities.clone());
+			}
+		}
+		Ok(previous.into_iter().collect())
+	}
+
+	fn extrinsic_ids(&self, at: H256, extrinsics: &Vec<TestExtrinsic>) -> Result<Vec<H256>, Error> {
+		Self::number(&*self.state.lock()?, at)?;
+		Ok(extrinsics.iter().map(|extrinsic| **extrinsic).collect())
+	}
+
+	fn create_unsigned_extrinsic(
+		&self,
+		at: H256,
+		event_id: H256,
+		event_proofs: Option<BTreeMap<Public, (SessionIndex, Signature)>>,
+	) -> Result<TestExtrinsic, Error> {
+		let mut state = self.state.lock()?;
+		Self::number(&state, at)?;
+		state.created.push(CreatedExtrinsic { at, event_id, event_proofs });
+		Ok(ExtrinsicWrapper::from(event_id))
+	}
+}
+
+/// An [EventValidatorTrait] of a chain without any finalized events, for tests which only submit
+/// events.
+pub struct NoFinalizedEvents;
+
+#[async_trait]
+impl EventValidatorTrait for NoFinalizedEvents {
+	async fn get_finalized_block_events(&self, _block_num: u32) -> Result<Vec<H256>, Error> {
+		Ok(vec![])
+	}
+
+	async fn get_finalized_block_indexed_events(
+		&self,
+		_block_num: u32,
+	) -> Result<Vec<(u32, H256)>, Error> {
+		Ok(vec![])
+	}
+
+	async fn get_latest_finalized_block(&self) -> Result<u32, Error> {
+		Ok(0)
+	}
+}
@@ -0,0 +1,32 @@
+//! A [ValidatedStreamsGrpc] with defaults for everything but the witnesser
+
+use super::{NoEventProofs, NoFinalizedEvents};
+use crate::{
+	index::InMemoryEventIndex, metrics::Metrics, notifications::EventNotifications,
+	payloads::DEFAULT_MAX_PAYLOAD_SIZE, server::ValidatedStreamsGrpc, startup::StartupSignals,
+	status::EventStatuses, traces::Traces, tunables::Tunables,
+};
+use std::sync::Arc;
+
+impl<EventWitnesser> ValidatedStreamsGrpc<EventWitnesser, NoFinalizedEvents> {
+	/// A server witnessing events through the given witnesser, on a chain without any finalized
+	/// events, started up, with the default tunables and payload size, no payloads stored, an
+	/// empty index, no proofs, and metrics and traces reporting nowhere. Tests override the fields
+	/// they are about with the struct update syntax.
+	pub fn for_tests(event_witnesser: EventWitnesser) -> Self {
+		Self {
+			event_witnesser: Arc::new(event_witnesser),
+			event_validator: Arc::new(NoFinalizedEvents),
+			metrics: Metrics::default(),
+			traces: Traces::default(),
+			startup: StartupSignals::ready(),
+			tunables: Tunables::default(),
+			payloads: None,
+			max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE,
+			event_index: Arc::new(InMemoryEventIndex::new()),
+			notifications: EventNotifications::default(),
+			statuses: EventStatuses::default(),
+			event_proofs: Arc::new(NoEventProofs),
+		}
+	}
+}
@@ -0,0 +1,141 @@
+//! A [tracing] layer which captures log lines along with their fields, for asserting on them
+
+use std::{
+	collections::HashMap,
+	fmt,
+	sync::{Arc, Mutex},
+};
+use tracing::{
+	field::{Field, Visit},
+	span,
+	subscriber::DefaultGuard,
+	Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+	layer::{Context, SubscriberExt},
+	registry::LookupSpan,
+	Layer, Registry,
+};
+
+/// A log line captured by [CapturedLogs].
+#[derive(Clone, Debug)]
+pub struct CapturedEvent {
+	/// The level of the line.
+	pub level: Level,
+	/// The target of the line.
+	pub target: String,
+	/// The fields of the line, including its `message`.
+	pub fields: HashMap<String, String>,
+	/// The spans the line was logged in, innermost first, with their fields.
+	pub spans: Vec<(String, HashMap<String, String>)>,
+}
+
+impl CapturedEvent {
+	/// The message of the line.
+	pub fn message(&self) -> &str {
+		self.fields.get("message").map(String::as_str).unwrap_or_default()
+	}
+
+	/// The value of a field of the line itself, or else of the innermost span which has it.
+	pub fn field(&self, name: &str) -> Option<&str> {
+		self.fields
+			.get(name)
+			.or_else(|| self.spans.iter().find_map(|(_, fields)| fields.get(name)))
+			.map(String::as_str)
+	}
+
+	/// Whether the line was logged within a span with the given name.
+	pub fn in_span(&self, name: &str) -> bool {
+		self.spans.iter().any(|(span, _)| span == name)
+	}
+}
+
+/// Captures all log lines of the current thread while the guard returned by
+/// [CapturedLogs::capture] is alive; use with a current-thread runtime in async tests. Or, with
+/// [CapturedLogs::capture_global], those of all threads.
+#[derive(Clone, Default)]
+pub struct CapturedLogs {
+	events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+
+impl CapturedLogs {
+	/// Starts capturing the log lines of the current thread.
+	pub fn capture() -> (Self, DefaultGuard) {
+		let logs = Self::default();
+		let guard = tracing::subscriber::set_default(Registry::default().with(logs.clone()));
+		(logs, guard)
+	}
+
+	/// Starts capturing the log lines of every thread, for the rest of the process. Panics if
+	/// another subscriber was installed globally already, so use it in test binaries of their own.
+	pub fn capture_global() -> Self {
+		let logs = Self::default();
+		tracing::subscriber::set_global_default(Registry::default().with(logs.clone()))
+			.expect("no other global subscriber");
+		logs
+	}
+
+	/// All lines captured so far, in order.
+	pub fn events(&self) -> Vec<CapturedEvent> {
+		self.events.lock().unwrap().clone()
+	}
+
+	/// The lines captured so far with the given target and message.
+	pub fn find(&self, target: &str, message: &str) -> Vec<CapturedEvent> {
+		self.events()
+			.into_iter()
+			.filter(|event| event.target == target && event.message() == message)
+			.collect()
+	}
+}
+
+/// The fields of a span, stored in its extensions.
+struct SpanFields(HashMap<String, String>);
+
+struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+impl Visit for FieldVisitor<'_> {
+	fn record_str(&mut self, field: &Field, value: &str) {
+		self.0.insert(field.name().to_string(), value.to_string());
+	}
+
+	fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+		self.0.insert(field.name().to_string(), format!("{value:?}"));
+	}
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for CapturedLogs {
+	fn new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+		let mut fields = HashMap::new();
+		attrs.record(&mut FieldVisitor(&mut fields));
+		if let Some(span) = ctx.span(id) {
+			span.extensions_mut().insert(SpanFields(fields));
+		}
+	}
+
+	fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+		if let Some(span) = ctx.span(id) {
+			if let Some(SpanFields(fields)) = span.extensions_mut().get_mut::<SpanFields>() {
+				values.record(&mut FieldVisitor(fields));
+			}
+		}
+	}
+
+	fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+		let mut fields = HashMap::new();
+		event.record(&mut FieldVisitor(&mut fields));
+		let mut spans = Vec::new();
+		let mut current = ctx.lookup_current();
+		while let Some(span) = current {
+			let fields = span.extensions().get::<SpanFields>().map(|f| f.0.clone());
+			spans.push((span.name().to_string(), fields.unwrap_or_default()));
+			current = span.parent();
+		}
+		self.events.lock().unwrap().push(CapturedEvent {
+			level: *event.metadata().level(),
+			target: event.metadata().target().to_string(),
+			fields,
+			spans,
+		});
+	}
+}
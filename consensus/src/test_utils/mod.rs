@@ -0,0 +1,22 @@
+//! Shared fixtures for testing Validated Streams components.
+//! Only compiled for tests, or when the `test-utils` feature is enabled (which should only ever be
+//! done from a `[dev-dependencies]` section).
+
+pub mod byzantine;
+pub mod chain;
+pub mod grpc;
+pub mod logs;
+pub mod network;
+pub mod pool;
+pub mod proofs;
+pub mod validators;
+pub mod witnesser;
+
+pub use byzantine::{Fault, SimulatedValidator};
+pub use chain::{CreatedExtrinsic, FakeChain, NoFinalizedEvents, TestBlock, TestExtrinsic};
+pub use logs::{CapturedEvent, CapturedLogs};
+pub use network::{Delivery, LinkConfig, SimulatedGossip, SimulatedNetwork, SimulatedNode};
+pub use pool::TestPool;
+pub use proofs::{NoEventProofs, ProofsCall, TestProofs};
+pub use validators::{TestValidators, WitnessBuilder};
+pub use witnesser::{single_node, single_validator, validator_set, TestGrpc, TestWitnesser};
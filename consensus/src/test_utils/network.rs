@@ -0,0 +1,419 @@
+//! A deterministic, in-process simulation of the gossip network
+//!
+//! A [SimulatedNetwork] connects a number of [GossipHandler]-s in a full mesh, and hands out
+//! [SimulatedGossip] endpoints (implementing [GossipTrait]) through which they publish. Nothing
+//! happens until the network is run: messages are then delivered in virtual time, with per-link
+//! latency, loss and partitions, and with all randomness drawn from a seeded generator, so that the
+//! same seed always results in the same deliveries in the same order.
+//!
+//! Like gossipsub, every node forwards messages it sees for the first time to all of its peers,
+//! retransmits messages lost on a link a few times (standing in for gossipsub's IHAVE/IWANT), and
+//! hands every message to each handler at most once, including the handler of the publisher itself.
+
+use crate::{
+	errors::Error,
+	events::{AuthoritiesList, EventProofsCollector, WITNESSED_EVENTS_TOPIC},
+	gossip::{GossipHandler, GossipTrait},
+	metrics::Metrics,
+	proofs::WitnessedEvent,
+	test_utils::{ProofsCall, TestProofs},
+};
+use async_trait::async_trait;
+use libp2p::gossipsub::{IdentTopic, TopicHash};
+use sp_core::H256;
+use std::{
+	cmp::Ordering,
+	collections::{BinaryHeap, HashSet},
+	ops::RangeInclusive,
+	sync::{Arc, Mutex},
+};
+
+/// Delivery characteristics of a (directed) link between two nodes.
+#[derive(Clone, Debug)]
+pub struct LinkConfig {
+	/// Range of latencies, in virtual milliseconds; each transmission picks one uniformly
+	pub latency: RangeInclusive<u64>,
+	/// Probability of each transmission getting lost
+	pub loss: f64,
+}
+
+impl Default for LinkConfig {
+	fn default() -> Self {
+		Self { latency: 10..=50, loss: 0.0 }
+	}
+}
+
+/// A message delivered to a node's handler, as recorded in [SimulatedNetwork::trace].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delivery {
+	/// Virtual time of the delivery
+	pub time: u64,
+	/// The node the message was received from; same as `to` for the publisher's own message
+	pub from: usize,
+	/// The node whose handler received the message
+	pub to: usize,
+	/// Sequence number of the message, in publishing order
+	pub message: u64,
+}
+
+/// A simulated network of handlers of type `H`. See the module documentation.
+pub struct SimulatedNetwork<H> {
+	handlers: Vec<Arc<H>>,
+	state: Arc<Mutex<State>>,
+}
+
+impl<H: GossipHandler + Send + Sync + 'static> SimulatedNetwork<H> {
+	/// Create a network connecting the given handlers, with all randomness derived from `seed`.
+	pub fn new(seed: u64, handlers: Vec<Arc<H>>) -> Self {
+		let state = State {
+			nodes: handlers.len(),
+			rng: SplitMix64(seed),
+			now: 0,
+			sequence: 0,
+			messages: 0,
+			queue: BinaryHeap::new(),
+			default_link: LinkConfig::default(),
+			links: Vec::new(),
+			partition: None,
+			seen: HashSet::new(),
+			retransmissions: 3,
+			retransmission_interval: 100,
+			trace: Vec::new(),
+		};
+		Self { handlers, state: Arc::new(Mutex::new(state)) }
+	}
+
+	/// The number of nodes in the network.
+	pub fn len(&self) -> usize {
+		self.handlers.len()
+	}
+
+	/// Whether the network has no nodes at all.
+	pub fn is_empty(&self) -> bool {
+		self.handlers.is_empty()
+	}
+
+	/// The handler of node `i`.
+	pub fn handler(&self, i: usize) -> &Arc<H> {
+		&self.handlers[i]
+	}
+
+	/// The endpoint through which node `i` publishes messages.
+	pub fn gossip(&self, i: usize) -> SimulatedGossip {
+		SimulatedGossip { node: i, state: self.state.clone() }
+	}
+
+	/// Change the configuration of all links which were not configured individually.
+	pub fn set_default_link(&self, config: LinkConfig) {
+		self.state.lock().unwrap().default_link = config;
+	}
+
+	/// Change the configuration of the link from node `from` to node `to`.
+	pub fn set_link(&self, from: usize, to: usize, config: LinkConfig) {
+		let mut state = self.state.lock().unwrap();
+		state.links.retain(|(f, t, _)| (*f, *t) != (from, to));
+		state.links.push((from, to, config));
+	}
+
+	/// Change how many times, and how many virtual milliseconds apart, a lost transmission is
+	/// retried before giving up.
+	pub fn set_retransmissions(&self, count: u32, interval: u64) {
+		let mut state = self.state.lock().unwrap();
+		state.retransmissions = count;
+		state.retransmission_interval = interval;
+	}
+
+	/// Split the network into groups which cannot reach each other. Nodes not in any group form a
+	/// group of their own. Transmissions across groups are lost (and retransmitted as usual).
+	pub fn partition(&self, groups: &[&[usize]]) {
+		let mut state = self.state.lock().unwrap();
+		let mut group_of = vec![groups.len(); state.nodes];
+		for (group, nodes) in groups.iter().enumerate() {
+			for &node in nodes.iter() {
+				group_of[node] = group;
+			}
+		}
+		state.partition = Some(group_of);
+	}
+
+	/// Remove any partition.
+	pub fn heal(&self) {
+		self.state.lock().unwrap().partition = None;
+	}
+
+	/// The current virtual time.
+	pub fn now(&self) -> u64 {
+		self.state.lock().unwrap().now
+	}
+
+	/// All deliveries so far, in order.
+	pub fn trace(&self) -> Vec<Delivery> {
+		self.state.lock().unwrap().trace.clone()
+	}
+
+	/// Process everything that happens up to (and including) the given virtual time.
+	pub async fn run_until(&self, deadline: u64) {
+		while let Some(delivery) = self.step(deadline) {
+			if let Some((to, data)) = delivery {
+				self.handlers[to].handle(&data).await;
+			}
+		}
+		let mut state = self.state.lock().unwrap();
+		state.now = state.now.max(deadline);
+	}
+
+	/// Process everything that happens in the next `duration` virtual milliseconds.
+	pub async fn run_for(&self, duration: u64) {
+		self.run_until(self.now() + duration).await
+	}
+
+	/// Process events until there are no messages in flight.
+	pub async fn run_until_idle(&self) {
+		while let Some(delivery) = self.step(u64::MAX) {
+			if let Some((to, data)) = delivery {
+				self.handlers[to].handle(&data).await;
+			}
+		}
+	}
+
+	/// Processes the next scheduled event, if it is due by `deadline`. Returns the message to hand
+	/// to a handler, if any. The state is not kept locked while handlers run, so that they can
+	/// publish in turn.
+	fn step(&self, deadline: u64) -> Option<Option<(usize, Vec<u8>)>> {
+		let mut state = self.state.lock().unwrap();
+		if state.queue.peek()?.time > deadline {
+			return None
+		}
+		let Scheduled { time, kind, .. } = state.queue.pop()?;
+		state.now = time;
+		match kind {
+			Kind::Transmit { from, to, message, attempt } => {
+				let link = state.link(from, to);
+				let lost = state.partitioned(from, to) || state.rng.chance(link.loss);
+				if !lost {
+					let latency = state.rng.range(&link.latency);
+					state.schedule(time + latency, Kind::Arrive { from, to, message });
+				} else if attempt < state.retransmissions {
+					let retry_at = time + state.retransmission_interval;
+					state.schedule(
+						retry_at,
+						Kind::Transmit { from, to, message, attempt: attempt + 1 },
+					);
+				}
+				Some(None)
+			},
+			Kind::Arrive { from, to, message } => {
+				if !state.seen.insert((to, message.sequence)) {
+					return Some(None)
+				}
+				state.trace.push(Delivery { time, from, to, message: message.sequence });
+				for peer in (0..state.nodes).filter(|&peer| peer != to && peer != from) {
+					let transmit =
+						Kind::Transmit { from: to, to: peer, message: message.clone(), attempt: 0 };
+					state.schedule(time, transmit);
+				}
+				let topics = self.handlers[to].get_topics();
+				let subscribed = topics.iter().any(|topic| topic.hash() == message.topic);
+				Some(subscribed.then(|| (to, message.data.clone())))
+			},
+		}
+	}
+}
+
+/// An endpoint of a [SimulatedNetwork], through which one of its nodes publishes messages.
+#[derive(Clone)]
+pub struct SimulatedGossip {
+	node: usize,
+	state: Arc<Mutex<State>>,
+}
+
+#[async_trait]
+impl GossipTrait for SimulatedGossip {
+	async fn publish(&mut self, topic: IdentTopic, message: Vec<u8>) -> Result<(), Error> {
+		let mut state = self.state.lock().unwrap();
+		let message =
+			Arc::new(Message { sequence: state.messages, topic: topic.hash(), data: message });
+		state.messages += 1;
+		let now = state.now;
+		state.schedule(now, Kind::Arrive { from: self.node, to: self.node, message });
+		Ok(())
+	}
+}
+
+/// A simulated validator: collects proofs of the events gossiped to it, and records the events it
+/// would have submitted to the chain instead of submitting them.
+pub struct SimulatedNode {
+	collector: EventProofsCollector<TestProofs>,
+	proofs: Arc<TestProofs>,
+	authorities: AuthoritiesList,
+	submitted: Mutex<Vec<H256>>,
+	rejected: Mutex<Vec<Error>>,
+}
+
+impl SimulatedNode {
+	/// Create a node whose validator set is the given list of authorities.
+	pub fn new(authorities: AuthoritiesList) -> Self {
+		Self::with_metrics(authorities, Metrics::default())
+	}
+
+	/// Create a node whose validator set is the given list of authorities, and which reports to
+	/// the given metrics.
+	pub fn with_metrics(authorities: AuthoritiesList, metrics: Metrics) -> Self {
+		let proofs = Arc::new(TestProofs::new());
+		Self {
+			collector: EventProofsCollector::new(proofs.clone(), metrics),
+			proofs,
+			authorities,
+			submitted: Mutex::new(Vec::new()),
+			rejected: Mutex::new(Vec::new()),
+		}
+	}
+
+	/// Events the node would have submitted to the chain, in submission order.
+	pub fn submitted(&self) -> Vec<H256> {
+		self.submitted.lock().unwrap().clone()
+	}
+
+	/// Every proof the node has stored in its event proofs, in order.
+	pub fn stored_proofs(&self) -> Vec<WitnessedEvent> {
+		self.proofs
+			.calls()
+			.into_iter()
+			.filter_map(|call| match call {
+				ProofsCall::AddEventProof(witnessed_event) => Some(witnessed_event),
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// Takes the errors for all messages the node has rejected since the last call, in order.
+	pub fn take_rejected(&self) -> Vec<Error> {
+		std::mem::take(&mut *self.rejected.lock().unwrap())
+	}
+}
+
+#[async_trait]
+impl GossipHandler for SimulatedNode {
+	fn get_topics(&self) -> Vec<IdentTopic> {
+		vec![IdentTopic::new(WITNESSED_EVENTS_TOPIC)]
+	}
+
+	async fn handle(&self, message: &[u8]) {
+		match self.collector.collect(&self.authorities, message) {
+			Ok(Some(event_id)) => {
+				self.submitted.lock().unwrap().push(event_id);
+				self.collector.mark_submitted(event_id).unwrap();
+			},
+			Ok(None) | Err(Error::DuplicateWitness) => {},
+			Err(e) => self.rejected.lock().unwrap().push(e),
+		}
+	}
+}
+
+/// A published message.
+#[derive(Debug)]
+struct Message {
+	sequence: u64,
+	topic: TopicHash,
+	data: Vec<u8>,
+}
+
+#[derive(Debug)]
+enum Kind {
+	/// An attempt at sending a message over a link
+	Transmit { from: usize, to: usize, message: Arc<Message>, attempt: u32 },
+	/// A message reaching a node
+	Arrive { from: usize, to: usize, message: Arc<Message> },
+}
+
+/// Something which happens at a specific virtual time. Ties are broken by scheduling order.
+#[derive(Debug)]
+struct Scheduled {
+	time: u64,
+	sequence: u64,
+	kind: Kind,
+}
+
+impl PartialEq for Scheduled {
+	fn eq(&self, other: &Self) -> bool {
+		(self.time, self.sequence) == (other.time, other.sequence)
+	}
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Scheduled {
+	// Reversed, so that the BinaryHeap pops the earliest event first
+	fn cmp(&self, other: &Self) -> Ordering {
+		(other.time, other.sequence).cmp(&(self.time, self.sequence))
+	}
+}
+
+struct State {
+	nodes: usize,
+	rng: SplitMix64,
+	now: u64,
+	/// Scheduling order of the next [Scheduled] event
+	sequence: u64,
+	/// Sequence number of the next published [Message]
+	messages: u64,
+	queue: BinaryHeap<Scheduled>,
+	default_link: LinkConfig,
+	links: Vec<(usize, usize, LinkConfig)>,
+	/// The group of each node, if the network is partitioned
+	partition: Option<Vec<usize>>,
+	/// (node, message) pairs which have already been delivered
+	seen: HashSet<(usize, u64)>,
+	retransmissions: u32,
+	retransmission_interval: u64,
+	trace: Vec<Delivery>,
+}
+
+impl State {
+	fn schedule(&mut self, time: u64, kind: Kind) {
+		self.queue.push(Scheduled { time, sequence: self.sequence, kind });
+		self.sequence += 1;
+	}
+
+	fn link(&self, from: usize, to: usize) -> LinkConfig {
+		self.links
+			.iter()
+			.find(|(f, t, _)| (*f, *t) == (from, to))
+			.map(|(_, _, config)| config.clone())
+			.unwrap_or_else(|| self.default_link.clone())
+	}
+
+	fn partitioned(&self, from: usize, to: usize) -> bool {
+		self.partition.as_ref().map_or(false, |group_of| group_of[from] != group_of[to])
+	}
+}
+
+/// The SplitMix64 generator; tiny, and fully determined by its seed on every platform.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+	fn next_u64(&mut self) -> u64 {
+		self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		z ^ (z >> 31)
+	}
+
+	/// Returns true with the given probability.
+	fn chance(&mut self, probability: f64) -> bool {
+		probability > 0.0 && ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+	}
+
+	/// Returns a number in the given range, picked uniformly.
+	fn range(&mut self, range: &RangeInclusive<u64>) -> u64 {
+		let span = range.end() - range.start() + 1;
+		range.start() + self.next_u64() % span
+	}
+}
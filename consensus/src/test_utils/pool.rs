@@ -0,0 +1,47 @@
+//! A [LocalTransactionPool] which accepts everything submitted to it, unless told otherwise
+
+use crate::test_utils::{TestBlock, TestExtrinsic};
+use sc_transaction_pool_api::{error::Error as PoolError, LocalTransactionPool};
+use sp_core::H256;
+use sp_runtime::generic::BlockId;
+use std::{collections::VecDeque, sync::Mutex};
+
+/// A transaction pool for [TestBlock]-s, which records the extrinsics submitted to it.
+#[derive(Default)]
+pub struct TestPool {
+	submitted: Mutex<Vec<TestExtrinsic>>,
+	/// Errors the next submissions fail with, in order
+	errors: Mutex<VecDeque<PoolError>>,
+}
+
+impl TestPool {
+	/// The ids of the events submitted so far, in order.
+	pub fn submitted(&self) -> Vec<H256> {
+		self.submitted.lock().unwrap().iter().map(|extrinsic| **extrinsic).collect()
+	}
+
+	/// Makes the next submission not yet told to fail, fail with the given error instead of being
+	/// accepted.
+	pub fn fail_next(&self, error: PoolError) {
+		self.errors.lock().unwrap().push_back(error);
+	}
+}
+
+impl LocalTransactionPool for TestPool {
+	type Block = TestBlock;
+	type Hash = H256;
+	type Error = PoolError;
+
+	fn submit_local(
+		&self,
+		_at: &BlockId<TestBlock>,
+		extrinsic: TestExtrinsic,
+	) -> Result<H256, PoolError> {
+		if let Some(error) = self.errors.lock().unwrap().pop_front() {
+			return Err(error)
+		}
+		let event_id = *extrinsic;
+		self.submitted.lock().unwrap().push(extrinsic);
+		Ok(event_id)
+	}
+}
@@ -0,0 +1,118 @@
+//! An instrumented in-memory [EventProofsTrait] implementation
+
+use crate::{
+	errors::Error,
+	proofs::{EventProof, EventProofsTrait, InMemoryEventProofs, ValidatorProofs, WitnessedEvent},
+	traits::EventProofReaderTrait,
+};
+use pallet_validated_streams::payload::SessionIndex;
+use sp_core::H256;
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::Mutex,
+};
+
+/// A call made to a [TestProofs] instance.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProofsCall {
+	/// [EventProofsTrait::add_event_proof] was called with the given event
+	AddEventProof(WitnessedEvent),
+	/// [EventProofsTrait::get_event_proofs] was called for the given event id
+	GetEventProofs(H256),
+	/// [EventProofsTrait::get_event_proof_count] was called for the given event id
+	GetEventProofCount(H256),
+	/// [EventProofsTrait::purge_event_stale_signatures] was called for the given event id
+	PurgeEventStaleSignatures(H256),
+	/// [EventProofsTrait::flush] was called
+	Flush,
+}
+
+/// An [InMemoryEventProofs] wrapper which records every call made to it and can be told to fail
+/// upcoming calls.
+#[derive(Default)]
+pub struct TestProofs {
+	inner: InMemoryEventProofs,
+	calls: Mutex<Vec<ProofsCall>>,
+	failures: Mutex<VecDeque<Error>>,
+}
+
+impl TestProofs {
+	/// Create an empty [TestProofs] instance.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Make the next call (to any method) fail with the given error. Calling this multiple times
+	/// queues up failures for successive calls.
+	pub fn fail_next(&self, error: Error) {
+		self.failures.lock().unwrap().push_back(error);
+	}
+
+	/// Returns all calls made so far, in order.
+	pub fn calls(&self) -> Vec<ProofsCall> {
+		self.calls.lock().unwrap().clone()
+	}
+
+	/// Forget all calls recorded so far.
+	pub fn clear_calls(&self) {
+		self.calls.lock().unwrap().clear();
+	}
+
+	fn record(&self, call: ProofsCall) -> Result<(), Error> {
+		self.calls.lock().unwrap().push(call);
+		match self.failures.lock().unwrap().pop_front() {
+			Some(error) => Err(error),
+			None => Ok(()),
+		}
+	}
+}
+
+impl EventProofsTrait for TestProofs {
+	fn add_event_proof(&self, event: &WitnessedEvent) -> Result<(), Error> {
+		self.record(ProofsCall::AddEventProof(event.clone()))?;
+		self.inner.add_event_proof(event)
+	}
+
+	fn get_event_proofs(
+		&self,
+		event_id: &H256,
+		validators: &[CryptoTypePublicPair],
+	) -> Result<HashMap<CryptoTypePublicPair, EventProof>, Error> {
+		self.record(ProofsCall::GetEventProofs(*event_id))?;
+		self.inner.get_event_proofs(event_id, validators)
+	}
+
+	fn get_event_proof_count(
+		&self,
+		event_id: &H256,
+		validators: &[CryptoTypePublicPair],
+	) -> Result<u16, Error> {
+		self.record(ProofsCall::GetEventProofCount(*event_id))?;
+		self.inner.get_event_proof_count(event_id, validators)
+	}
+
+	fn purge_event_stale_signatures(
+		&self,
+		event_id: &H256,
+		validators: &[CryptoTypePublicPair],
+		oldest_session: SessionIndex,
+	) -> Result<(), Error> {
+		self.record(ProofsCall::PurgeEventStaleSignatures(*event_id))?;
+		self.inner.purge_event_stale_signatures(event_id, validators, oldest_session)
+	}
+
+	fn flush(&self) -> Result<(), Error> {
+		self.record(ProofsCall::Flush)
+	}
+}
+
+/// An [EventProofReaderTrait] of a node holding no proofs from no validators, for tests which do
+/// not read proofs.
+pub struct NoEventProofs;
+
+impl EventProofReaderTrait for NoEventProofs {
+	fn get_latest_event_proofs(&self, _event_id: &H256) -> Result<ValidatorProofs, Error> {
+		Ok(ValidatorProofs { session: 0, validators: vec![], target: 1, proofs: vec![] })
+	}
+}
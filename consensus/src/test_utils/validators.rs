@@ -0,0 +1,169 @@
+//! Validator key fixtures and a builder for signed [WitnessedEvent]-s
+
+use crate::{events::AuthoritiesList, proofs::WitnessedEvent};
+use pallet_validated_streams::payload::{witness_payload, SessionIndex};
+use sc_keystore::LocalKeystore;
+use sp_core::{
+	sr25519::{Pair, Public},
+	Pair as PairT, H256,
+};
+use sp_keystore::SyncCryptoStore;
+use sp_runtime::{app_crypto::CryptoTypePublicPair, key_types::AURA};
+use std::sync::Arc;
+
+/// A set of validators with deterministic sr25519 keys, each with its own in-memory keystore
+/// containing its (AURA) key.
+pub struct TestValidators {
+	pairs: Vec<Pair>,
+	keystores: Vec<Arc<LocalKeystore>>,
+}
+
+impl TestValidators {
+	/// Create `n` validators. The key of validator `i` is derived from the `//Validator{i}` seed, so
+	/// the same index always results in the same key.
+	pub fn new(n: usize) -> Self {
+		let (pairs, keystores) = (0..n)
+			.map(|i| {
+				let seed = format!("//Validator{i}");
+				let pair = Pair::from_string(&seed, None).expect("static values are valid; qed");
+				let keystore = Arc::new(LocalKeystore::in_memory());
+				SyncCryptoStore::sr25519_generate_new(keystore.as_ref(), AURA, Some(seed.as_str()))
+					.expect("in-memory keystore does not fail; qed");
+				(pair, keystore)
+			})
+			.unzip();
+		Self { pairs, keystores }
+	}
+
+	/// The number of validators.
+	pub fn len(&self) -> usize {
+		self.pairs.len()
+	}
+
+	/// Whether there are no validators at all.
+	pub fn is_empty(&self) -> bool {
+		self.pairs.is_empty()
+	}
+
+	/// The keypair of validator `i`.
+	pub fn pair(&self, i: usize) -> &Pair {
+		&self.pairs[i]
+	}
+
+	/// The public key of validator `i`.
+	pub fn public(&self, i: usize) -> Public {
+		self.pairs[i].public()
+	}
+
+	/// The public key of validator `i`, as stored in [WitnessedEvent]-s and event proofs.
+	pub fn pub_key(&self, i: usize) -> CryptoTypePublicPair {
+		CryptoTypePublicPair::from(self.public(i))
+	}
+
+	/// The public keys of all validators, in index order.
+	pub fn pubkeys(&self) -> Vec<CryptoTypePublicPair> {
+		(0..self.len()).map(|i| self.pub_key(i)).collect()
+	}
+
+	/// A keystore containing only the key of validator `i`.
+	pub fn keystore(&self, i: usize) -> Arc<LocalKeystore> {
+		self.keystores[i].clone()
+	}
+
+	/// An [AuthoritiesList] made out of all validators.
+	pub fn authorities(&self) -> AuthoritiesList {
+		AuthoritiesList::new(self.pubkeys())
+	}
+
+	/// Start building a [WitnessedEvent] for `event_id` as witnessed by validator `i` in session 0.
+	pub fn witness(&self, i: usize, event_id: H256) -> WitnessBuilder {
+		WitnessBuilder {
+			validators: self,
+			validator: i,
+			signer: i,
+			event_id,
+			session: 0,
+			payload: None,
+			corruption: None,
+		}
+	}
+}
+
+/// Ways in which a [WitnessBuilder] can break the signature of a [WitnessedEvent]
+#[derive(Clone, Copy, Debug)]
+enum Corruption {
+	FlipByte,
+	Truncate,
+	Empty,
+}
+
+/// A builder for [WitnessedEvent]-s, correctly signed unless told otherwise.
+/// Created through [TestValidators::witness].
+pub struct WitnessBuilder<'a> {
+	validators: &'a TestValidators,
+	validator: usize,
+	signer: usize,
+	event_id: H256,
+	session: SessionIndex,
+	payload: Option<Vec<u8>>,
+	corruption: Option<Corruption>,
+}
+
+impl<'a> WitnessBuilder<'a> {
+	/// Sign the event with the key of validator `j`, while still claiming to be validator `i`.
+	pub fn signed_by(mut self, j: usize) -> Self {
+		self.signer = j;
+		self
+	}
+
+	/// Witness the event in the given session.
+	pub fn in_session(mut self, session: SessionIndex) -> Self {
+		self.session = session;
+		self
+	}
+
+	/// Sign the given bytes instead of the witness payload of the event.
+	pub fn signing(mut self, payload: &[u8]) -> Self {
+		self.payload = Some(payload.to_vec());
+		self
+	}
+
+	/// Flip a byte in the signature, keeping its length valid.
+	pub fn corrupt_signature(mut self) -> Self {
+		self.corruption = Some(Corruption::FlipByte);
+		self
+	}
+
+	/// Cut the last byte of the signature, making it unparseable.
+	pub fn truncate_signature(mut self) -> Self {
+		self.corruption = Some(Corruption::Truncate);
+		self
+	}
+
+	/// Leave the signature empty.
+	pub fn empty_signature(mut self) -> Self {
+		self.corruption = Some(Corruption::Empty);
+		self
+	}
+
+	/// Build the [WitnessedEvent].
+	pub fn build(self) -> WitnessedEvent {
+		let payload =
+			self.payload.unwrap_or_else(|| witness_payload(&self.event_id, self.session));
+		let mut signature = self.validators.pair(self.signer).sign(&payload).0.to_vec();
+		match self.corruption {
+			Some(Corruption::FlipByte) => signature[8] ^= 0xff,
+			Some(Corruption::Truncate) => {
+				signature.pop();
+			},
+			Some(Corruption::Empty) => signature.clear(),
+			None => {},
+		}
+		WitnessedEvent {
+			signature,
+			pub_key: self.validators.pub_key(self.validator),
+			event_id: self.event_id,
+			session: self.session,
+		}
+	}
+}
@@ -0,0 +1,50 @@
+//! An [EventWitnesser] on a [SimulatedNetwork] of its node alone
+
+use super::{
+	FakeChain, NoFinalizedEvents, SimulatedGossip, SimulatedNetwork, SimulatedNode, TestBlock,
+	TestValidators,
+};
+use crate::{
+	events::{EventWitnesser, ValidatorSetHandle},
+	metrics::Metrics,
+	server::ValidatedStreamsGrpc,
+	traces::Traces,
+};
+use sp_core::sr25519::Public;
+use std::{num::NonZeroUsize, sync::Arc};
+
+/// A witnesser on a [FakeChain], gossiping over a [SimulatedNetwork].
+pub type TestWitnesser = EventWitnesser<TestBlock, FakeChain, Public, SimulatedGossip>;
+/// A gRPC service witnessing events through a [TestWitnesser].
+pub type TestGrpc = ValidatedStreamsGrpc<TestWitnesser, NoFinalizedEvents>;
+
+/// A handle on the validator sets, caching as many as the tests ever look up.
+pub fn validator_set() -> ValidatorSetHandle<TestBlock> {
+	ValidatorSetHandle::new(NonZeroUsize::new(16).unwrap())
+}
+
+/// The witnesser of the only validator, on a chain where it is the only authority, gossiping over
+/// a network of its node alone.
+pub fn single_validator() -> (TestWitnesser, SimulatedNetwork<SimulatedNode>) {
+	let validators = TestValidators::new(1);
+	single_node(&validators, Arc::new(FakeChain::new(validators.pubkeys())))
+}
+
+/// The witnesser of the first of the validators, on the given chain, gossiping over a network of
+/// its node alone.
+pub fn single_node(
+	validators: &TestValidators,
+	chain: Arc<FakeChain>,
+) -> (TestWitnesser, SimulatedNetwork<SimulatedNode>) {
+	let node = Arc::new(SimulatedNode::new(validators.authorities()));
+	let network = SimulatedNetwork::new(0, vec![node]);
+	let witnesser = EventWitnesser::new(
+		chain,
+		network.gossip(0),
+		validators.keystore(0),
+		validator_set(),
+		Metrics::default(),
+		Traces::default(),
+	);
+	(witnesser, network)
+}
@@ -0,0 +1,129 @@
+//! OpenTelemetry traces of the witnessing pipeline. A request of the trusted client starts a
+//! `witness_event` trace (continuing the W3C `traceparent` sent along with the request, if any),
+//! with `sign` and `gossip_publish` spans under it. Once the event gathers enough witnesses, the
+//! `quorum_reached` span and its `submit_extrinsic` child are linked back to the request's span.
+//!
+//! The span contexts of requests are only kept locally, and never sent over the gossip.
+
+use lru::LruCache;
+use opentelemetry::{
+	propagation::{Extractor, TextMapPropagator},
+	sdk::{propagation::TraceContextPropagator, trace::Tracer, Resource},
+	trace::{Link, SpanContext, SpanKind, TraceContextExt, TraceError, Tracer as _},
+	Context, KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use sp_core::H256;
+use std::{
+	num::NonZeroUsize,
+	sync::{Arc, Mutex},
+};
+use tonic::metadata::{KeyRef, MetadataMap};
+#[cfg(test)]
+pub mod tests;
+
+/// How many events submitted by the trusted client the span contexts are kept for.
+const ORIGINS_CAPACITY: usize = 4096;
+
+/// A handle for tracing the pipeline. Cheap to clone; the [Default] handle does not trace anything,
+/// and costs next to nothing.
+#[derive(Clone, Default)]
+pub struct Traces {
+	inner: Option<Arc<Inner>>,
+}
+
+struct Inner {
+	tracer: Tracer,
+	/// The span contexts of the client requests which submitted each event
+	origins: Mutex<LruCache<H256, SpanContext>>,
+}
+
+/// Reads the `traceparent` and `tracestate` of a request from its gRPC metadata.
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+	fn get(&self, key: &str) -> Option<&str> {
+		self.0.get(key).and_then(|value| value.to_str().ok())
+	}
+
+	fn keys(&self) -> Vec<&str> {
+		self.0
+			.keys()
+			.map(|key| match key {
+				KeyRef::Ascii(key) => key.as_str(),
+				KeyRef::Binary(key) => key.as_str(),
+			})
+			.collect()
+	}
+}
+
+impl Traces {
+	/// Traces through the given tracer.
+	pub fn new(tracer: Tracer) -> Self {
+		let capacity = NonZeroUsize::new(ORIGINS_CAPACITY).expect("capacity is not zero");
+		let origins = Mutex::new(LruCache::new(capacity));
+		Self { inner: Some(Arc::new(Inner { tracer, origins })) }
+	}
+
+	/// Traces through a batching OTLP (gRPC) exporter sending to the given collector endpoint.
+	/// Needs to be called from within a Tokio runtime.
+	pub fn otlp(endpoint: &str) -> Result<Self, TraceError> {
+		let tracer = opentelemetry_otlp::new_pipeline()
+			.tracing()
+			.with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+			.with_trace_config(opentelemetry::sdk::trace::config().with_resource(Resource::new(
+				vec![KeyValue::new("service.name", "validated-streams")],
+			)))
+			.install_batch(opentelemetry::runtime::Tokio)?;
+		Ok(Self::new(tracer))
+	}
+
+	/// Starts the span of a request of the trusted client, as a child of the trace context found
+	/// in its metadata, if any. Returns a context holding the span, which ends once the context is
+	/// dropped.
+	pub fn client_request(&self, method: &'static str, metadata: &MetadataMap) -> Context {
+		let Some(inner) = &self.inner else { return Context::new() };
+		let parent = TraceContextPropagator::new().extract(&MetadataExtractor(metadata));
+		let span = inner
+			.tracer
+			.span_builder(method)
+			.with_kind(SpanKind::Server)
+			.start_with_context(&inner.tracer, &parent);
+		parent.with_span(span)
+	}
+
+	/// Records that the client request of the current context submitted the event, so that the
+	/// spans of its quorum can be linked to it.
+	pub fn on_event_submitted(&self, event_id: H256) {
+		let Some(inner) = &self.inner else { return };
+		let cx = Context::current();
+		let span = cx.span();
+		span.set_attribute(KeyValue::new("event_id", format!("{event_id:?}")));
+		if span.span_context().is_valid() {
+			inner.origins.lock().unwrap().put(event_id, span.span_context().clone());
+		}
+	}
+
+	/// Starts a span of a pipeline stage as a child of the given context. Returns a context
+	/// holding the span, which ends once the context is dropped.
+	pub fn stage(&self, parent: &Context, name: &'static str) -> Context {
+		let Some(inner) = &self.inner else { return Context::new() };
+		let span = inner.tracer.span_builder(name).start_with_context(&inner.tracer, parent);
+		parent.with_span(span)
+	}
+
+	/// Starts the span of an event reaching the target number of witnesses, linked to the client
+	/// request which submitted the event on this node, if any. Returns a context holding the span,
+	/// which ends once the context is dropped.
+	pub fn quorum_reached(&self, event_id: H256) -> Context {
+		let Some(inner) = &self.inner else { return Context::new() };
+		let origin = inner.origins.lock().unwrap().pop(&event_id);
+		let span = inner
+			.tracer
+			.span_builder("quorum_reached")
+			.with_attributes(vec![KeyValue::new("event_id", format!("{event_id:?}"))])
+			.with_links(origin.map(|origin| Link::new(origin, vec![])).into_iter().collect())
+			.start_with_context(&inner.tracer, &Context::new());
+		Context::new().with_span(span)
+	}
+}
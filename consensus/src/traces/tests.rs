@@ -0,0 +1,163 @@
+use super::Traces;
+use crate::{
+	events::{EventGossipHandler, EventWitnesser, WITNESSED_EVENTS_TOPIC},
+	gossip::GossipTrait,
+	metrics::Metrics,
+	proofs::InMemoryEventProofs,
+	server::{
+		validated_streams_proto::{streams_server::Streams, WitnessEventRequest},
+		ValidatedStreamsGrpc,
+	},
+	test_utils::{validator_set, FakeChain, SimulatedNetwork, TestBlock, TestPool, TestValidators},
+};
+use libp2p::gossipsub::IdentTopic;
+use opentelemetry::{
+	sdk::{
+		export::trace::SpanData,
+		trace::{Span, SpanProcessor, TracerProvider},
+	},
+	trace::{SpanId, SpanKind, TraceContextExt, TraceId, TraceResult, TracerProvider as _},
+	Context, Key,
+};
+use sp_core::{sr25519::Public, H256};
+use std::sync::{Arc, Mutex};
+use tonic::{
+	metadata::{MetadataMap, MetadataValue},
+	Request,
+};
+
+/// The trace and parent span of the client's request, as in the examples of the W3C spec.
+const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+/// Records every finished span in memory.
+#[derive(Clone, Debug, Default)]
+struct InMemorySpans(Arc<Mutex<Vec<SpanData>>>);
+
+impl InMemorySpans {
+	/// The finished span with the given name. Panics unless there is exactly one of them.
+	fn get(&self, name: &str) -> SpanData {
+		let spans = self.0.lock().unwrap();
+		let mut matching = spans.iter().filter(|span| span.name == name);
+		let span = matching.next().unwrap_or_else(|| panic!("no {name} span")).clone();
+		assert!(matching.next().is_none(), "more than one {name} span");
+		span
+	}
+
+	fn names(&self) -> Vec<String> {
+		self.0.lock().unwrap().iter().map(|span| span.name.to_string()).collect()
+	}
+}
+
+impl SpanProcessor for InMemorySpans {
+	fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+	fn on_end(&self, span: SpanData) {
+		self.0.lock().unwrap().push(span);
+	}
+
+	fn force_flush(&self) -> TraceResult<()> {
+		Ok(())
+	}
+
+	fn shutdown(&mut self) -> TraceResult<()> {
+		Ok(())
+	}
+}
+
+fn event_id_attribute(span: &SpanData) -> Option<String> {
+	span.attributes.get(&Key::new("event_id")).map(|value| value.as_str().into_owned())
+}
+
+#[test]
+fn test_disabled_traces_do_nothing() {
+	let traces = Traces::default();
+	let mut metadata = MetadataMap::new();
+	metadata.insert("traceparent", MetadataValue::from_static(TRACEPARENT));
+
+	let cx = traces.client_request("witness_event", &metadata);
+	assert!(!cx.span().span_context().is_valid());
+	traces.on_event_submitted(H256::zero());
+	assert!(!traces.stage(&cx, "sign").span().span_context().is_valid());
+	assert!(!traces.quorum_reached(H256::zero()).span().span_context().is_valid());
+}
+
+#[tokio::test]
+async fn test_happy_path_span_graph() {
+	let spans = InMemorySpans::default();
+	let provider = TracerProvider::builder().with_span_processor(spans.clone()).build();
+	let traces = Traces::new(provider.tracer("validated_streams"));
+	let validators = TestValidators::new(4);
+	let chain = Arc::new(FakeChain::new(validators.pubkeys()));
+	let pool = Arc::new(TestPool::default());
+
+	let handlers = (0..validators.len())
+		.map(|i| {
+			let (pool, traces) = match i {
+				0 => (pool.clone(), traces.clone()),
+				_ => (Arc::new(TestPool::default()), Traces::default()),
+			};
+			Arc::new(EventGossipHandler::<_, _, _, Public, TestBlock>::new(
+				chain.clone(),
+				Arc::new(InMemoryEventProofs::new()),
+				pool,
+				validator_set(),
+				Metrics::default(),
+				traces,
+			))
+		})
+		.collect();
+	let network = SimulatedNetwork::new(0, handlers);
+	let witnesser = EventWitnesser::<TestBlock, _, Public, _>::new(
+		chain.clone(),
+		network.gossip(0),
+		validators.keystore(0),
+		validator_set(),
+		Metrics::default(),
+		traces.clone(),
+	);
+	let grpc = ValidatedStreamsGrpc { traces, ..ValidatedStreamsGrpc::for_tests(witnesser) };
+	let event_id = H256::repeat_byte(1);
+
+	let request = WitnessEventRequest { event_id: event_id.0.to_vec(), ..Default::default() };
+	let mut request = Request::new(request);
+	request.metadata_mut().insert("traceparent", MetadataValue::from_static(TRACEPARENT));
+	grpc.witness_event(request).await.unwrap();
+	let topic = IdentTopic::new(WITNESSED_EVENTS_TOPIC);
+	for i in 1..3 {
+		let message = validators.witness(i, event_id).build().to_bytes().unwrap();
+		network.gossip(i).publish(topic.clone(), message).await.unwrap();
+	}
+	network.run_until_idle().await;
+	assert_eq!(pool.submitted(), vec![event_id]);
+
+	let mut names = spans.names();
+	names.sort();
+	assert_eq!(
+		names,
+		["gossip_publish", "quorum_reached", "sign", "submit_extrinsic", "witness_event"]
+	);
+
+	// The request continues the client's trace...
+	let request = spans.get("witness_event");
+	let trace_id = TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap();
+	assert_eq!(request.span_context.trace_id(), trace_id);
+	assert_eq!(request.parent_span_id, SpanId::from_hex("b7ad6b7169203331").unwrap());
+	assert_eq!(request.span_kind, SpanKind::Server);
+	assert_eq!(event_id_attribute(&request), Some(format!("{event_id:?}")));
+	// ...with the stages handled within the request under it
+	for name in ["sign", "gossip_publish"] {
+		let stage = spans.get(name);
+		assert_eq!(stage.span_context.trace_id(), trace_id, "{name}");
+		assert_eq!(stage.parent_span_id, request.span_context.span_id(), "{name}");
+	}
+
+	// The quorum happens later, in the gossip, and is only linked to the request
+	let quorum = spans.get("quorum_reached");
+	assert_eq!(quorum.parent_span_id, SpanId::INVALID);
+	let links: Vec<_> = quorum.links.iter().map(|link| link.span_context.clone()).collect();
+	assert_eq!(links, vec![request.span_context.clone()]);
+	assert_eq!(event_id_attribute(&quorum), Some(format!("{event_id:?}")));
+	let submit = spans.get("submit_extrinsic");
+	assert_eq!(submit.span_context.trace_id(), quorum.span_context.trace_id());
+	assert_eq!(submit.parent_span_id, quorum.span_context.span_id());
+}
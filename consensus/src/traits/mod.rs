@@ -0,0 +1,198 @@
+//! Traits used by Validated Streams code
+
+use crate::{
+	errors::Error, events::WitnessingSet, proofs::ValidatorProofs, streams::DEFAULT_STREAM,
+};
+use async_trait::async_trait;
+use codec::Codec;
+use pallet_validated_streams::{payload::SessionIndex, ValidatedStreamsApi};
+use sc_client_api::HeaderBackend;
+use sp_api::{ApiExt, BlockT, ProvideRuntimeApi};
+use sp_consensus_aura::AuraApi;
+use sp_core::{
+	sr25519::{Public, Signature},
+	H256,
+};
+use sp_runtime::{app_crypto::CryptoTypePublicPair, traits::NumberFor};
+use std::collections::BTreeMap;
+#[cfg(test)]
+pub mod tests;
+
+/// A trait wrapping the functionality of witnessing an event that is called by the trusted client
+/// (e.g. through GRPC).
+#[async_trait]
+pub trait EventWitnesserTrait {
+	/// Witnesses an event of the default stream by signing it with the key of the current node and
+	/// gossipping the signature to all peers.
+	async fn witness_event(&self, event: H256) -> Result<(), Error> {
+		self.witness_stream_event(DEFAULT_STREAM, event).await
+	}
+
+	/// Witnesses an event of the given stream, as [Self::witness_event] does, gossipping the
+	/// signature on the topic of the stream. The event is the
+	/// [stream_event_id](crate::streams::stream_event_id) of the one submitted.
+	async fn witness_stream_event(&self, stream_id: &str, event: H256) -> Result<(), Error>;
+
+	/// The validators witnessing the events as of the last finalized block, and whether this node
+	/// is currently one of them.
+	async fn witnessing_set(&self) -> Result<WitnessingSet, Error>;
+}
+
+/// A trait responsible for getting a stream of validated/finalized events from the node to a
+/// client. Note that there is nothing this trait does which can't be done through the RPC / a light
+/// client.
+#[async_trait]
+pub trait EventValidatorTrait {
+	/// Get the list of events in a specific block. If the block is not ready yet, waits until the
+	/// block is finalized. To use as a stream of events, just query the events in successive block
+	/// numbers.
+	async fn get_finalized_block_events(&self, block_num: u32) -> Result<Vec<H256>, Error>;
+
+	/// Like [EventValidatorTrait::get_finalized_block_events], but along with the index of the
+	/// extrinsic which validated each event within the block.
+	async fn get_finalized_block_indexed_events(
+		&self,
+		block_num: u32,
+	) -> Result<Vec<(u32, H256)>, Error>;
+
+	/// Get the latest block's number.
+	async fn get_latest_finalized_block(&self) -> Result<u32, Error>;
+}
+
+/// A trait reading the witnesses of an event this node holds, so that a client can check for
+/// itself that enough validators witnessed the event (e.g. through GRPC).
+pub trait EventProofReaderTrait {
+	/// The proofs of the event held from the validators at the last finalized block.
+	fn get_latest_event_proofs(&self, event_id: &H256) -> Result<ValidatorProofs, Error>;
+}
+
+/// The operations on the chain which the event services need, so that they can be tested against
+/// a scripted chain instead of a full client. Implemented for every client reading headers and
+/// calling into a runtime which provides the Validated Streams and Aura APIs, as Substrate clients
+/// do whatever their backend and executor; see `test_utils::FakeChain` for an in-memory
+/// implementation.
+///
+/// Runtimes before version 2 of the Validated Streams API know of no sessions: at their blocks, the
+/// validators are those of session 0, with no previous sessions.
+pub trait ChainAccess<Block: BlockT, AuthorityId>: Send + Sync + 'static {
+	/// The number and hash of the best block.
+	fn best_block(&self) -> (NumberFor<Block>, Block::Hash);
+
+	/// The number and hash of the last finalized block.
+	fn finalized_block(&self) -> (NumberFor<Block>, Block::Hash);
+
+	/// The validators (Aura authorities) at the given block.
+	fn authorities(&self, at: Block::Hash) -> Result<Vec<CryptoTypePublicPair>, Error>;
+
+	/// The session of the validators at the given block.
+	fn session(&self, at: Block::Hash) -> Result<SessionIndex, Error>;
+
+	/// The previous sessions whose witnesses are still accepted at the given block, oldest first,
+	/// with their validators.
+	fn previous_sessions(
+		&self,
+		at: Block::Hash,
+	) -> Result<Vec<(SessionIndex, Vec<CryptoTypePublicPair>)>, Error>;
+
+	/// The ids of the events validated by the given extrinsics, as the runtime at the given block
+	/// sees them. Used for checking which events are (going to be) on chain.
+	#[allow(clippy::ptr_arg)]
+	fn extrinsic_ids(
+		&self,
+		at: Block::Hash,
+		extrinsics: &Vec<Block::Extrinsic>,
+	) -> Result<Vec<H256>, Error>;
+
+	/// Creates an unsigned extrinsic validating the event, using the runtime at the given block.
+	fn create_unsigned_extrinsic(
+		&self,
+		at: Block::Hash,
+		event_id: H256,
+		event_proofs: Option<BTreeMap<Public, (SessionIndex, Signature)>>,
+	) -> Result<Block::Extrinsic, Error>;
+}
+
+impl<Client, Block, AuthorityId> ChainAccess<Block, AuthorityId> for Client
+where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync + 'static,
+	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+	AuthorityId: Codec,
+	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
+{
+	fn best_block(&self) -> (NumberFor<Block>, Block::Hash) {
+		let info = self.info();
+		(info.best_number, info.best_hash)
+	}
+
+	fn finalized_block(&self) -> (NumberFor<Block>, Block::Hash) {
+		let info = self.info();
+		(info.finalized_number, info.finalized_hash)
+	}
+
+	fn authorities(&self, at: Block::Hash) -> Result<Vec<CryptoTypePublicPair>, Error> {
+		Ok(self.runtime_api().authorities(at)?.iter().map(CryptoTypePublicPair::from).collect())
+	}
+
+	fn session(&self, at: Block::Hash) -> Result<SessionIndex, Error> {
+		if !has_sessions(self, at)? {
+			return Ok(0)
+		}
+		Ok(self.runtime_api().current_session(at)?)
+	}
+
+	fn previous_sessions(
+		&self,
+		at: Block::Hash,
+	) -> Result<Vec<(SessionIndex, Vec<CryptoTypePublicPair>)>, Error> {
+		if !has_sessions(self, at)? {
+			return Ok(Vec::new())
+		}
+		let sessions = self.runtime_api().previous_sessions(at)?.into_iter();
+		Ok(sessions
+			.map(|(session, keys)| (session, keys.iter().map(CryptoTypePublicPair::from).collect()))
+			.collect())
+	}
+
+	fn extrinsic_ids(
+		&self,
+		at: Block::Hash,
+		extrinsics: &Vec<Block::Extrinsic>,
+	) -> Result<Vec<H256>, Error> {
+		Ok(self.runtime_api().get_extrinsic_ids(at, extrinsics)?)
+	}
+
+	fn create_unsigned_extrinsic(
+		&self,
+		at: Block::Hash,
+		event_id: H256,
+		event_proofs: Option<BTreeMap<Public, (SessionIndex, Signature)>>,
+	) -> Result<Block::Extrinsic, Error> {
+		if !has_sessions(self, at)? {
+			// Such runtimes take bare signatures, which they check over the bare event id
+			let event_proofs = event_proofs.map(|proofs| {
+				proofs.into_iter().map(|(key, (_, signature))| (key, signature)).collect()
+			});
+			#[allow(deprecated)]
+			let extrinsic = self.runtime_api().create_unsigned_extrinsic_before_version_2(
+				at,
+				event_id,
+				event_proofs,
+			)?;
+			return Ok(extrinsic)
+		}
+		Ok(self.runtime_api().create_unsigned_extrinsic(at, event_id, event_proofs)?)
+	}
+}
+
+/// Whether the runtime at the given block provides version 2 of the Validated Streams API, which
+/// binds witnesses to sessions.
+fn has_sessions<Block, Client>(client: &Client, at: Block::Hash) -> Result<bool, Error>
+where
+	Block: BlockT,
+	Client: ProvideRuntimeApi<Block>,
+	Client::Api: ValidatedStreamsApi<Block>,
+{
+	let api = client.runtime_api();
+	Ok(api.has_api_with::<dyn ValidatedStreamsApi<Block>, _>(at, |version| version >= 2)?)
+}
@@ -0,0 +1,120 @@
+use super::ChainAccess;
+use crate::test_utils::TestValidators;
+use pallet_validated_streams::{payload::SessionIndex, ValidatedStreamsApi};
+use sc_client_api::HeaderBackend;
+use sp_api::{ApiRef, ProvideRuntimeApi};
+use sp_blockchain::{BlockStatus, Info, Result as BlockchainResult};
+use sp_consensus_aura::{sr25519::AuthorityId, AuraApi, SlotDuration};
+use sp_core::{
+	sr25519::{Public, Signature},
+	H256,
+};
+use sp_runtime::traits::NumberFor;
+use std::collections::BTreeMap;
+use substrate_test_runtime_client::{
+	runtime::{Block, Extrinsic, Header},
+	Backend, Client,
+};
+
+/// The APIs of a runtime including the pallet, with the given authorities, in session 3.
+#[derive(Clone)]
+struct StreamsRuntime {
+	authorities: Vec<AuthorityId>,
+}
+
+sp_api::mock_impl_runtime_apis! {
+	impl ValidatedStreamsApi<Block> for StreamsRuntime {
+		fn get_extrinsic_ids(extrinsics: &Vec<Extrinsic>) -> Vec<H256> {
+			extrinsics
+				.iter()
+				.filter_map(|extrinsic| match extrinsic {
+					Extrinsic::IncludeData(event_id) => Some(H256::from_slice(event_id)),
+					_ => None,
+				})
+				.collect()
+		}
+
+		fn create_unsigned_extrinsic(
+			event_id: H256,
+			_event_proofs: Option<BTreeMap<Public, (SessionIndex, Signature)>>,
+		) -> Extrinsic {
+			Extrinsic::IncludeData(event_id.0.to_vec())
+		}
+
+		fn current_session() -> SessionIndex {
+			3
+		}
+
+		fn previous_sessions(&self) -> Vec<(SessionIndex, Vec<Public>)> {
+			vec![(2, vec![self.authorities[0].clone().into()])]
+		}
+	}
+
+	impl AuraApi<Block, AuthorityId> for StreamsRuntime {
+		fn slot_duration() -> SlotDuration {
+			SlotDuration::from_millis(6000)
+		}
+
+		fn authorities(&self) -> Vec<AuthorityId> {
+			self.authorities.clone()
+		}
+	}
+}
+
+/// A client of the Substrate test runtime, whose runtime calls go to a [StreamsRuntime] instead.
+struct StreamsClient {
+	client: Client<Backend>,
+	runtime: StreamsRuntime,
+}
+
+impl HeaderBackend<Block> for StreamsClient {
+	fn header(&self, hash: H256) -> BlockchainResult<Option<Header>> {
+		HeaderBackend::header(&self.client, hash)
+	}
+
+	fn info(&self) -> Info<Block> {
+		HeaderBackend::info(&self.client)
+	}
+
+	fn status(&self, hash: H256) -> BlockchainResult<BlockStatus> {
+		HeaderBackend::status(&self.client, hash)
+	}
+
+	fn number(&self, hash: H256) -> BlockchainResult<Option<NumberFor<Block>>> {
+		HeaderBackend::number(&self.client, hash)
+	}
+
+	fn hash(&self, number: NumberFor<Block>) -> BlockchainResult<Option<H256>> {
+		HeaderBackend::hash(&self.client, number)
+	}
+}
+
+impl ProvideRuntimeApi<Block> for StreamsClient {
+	type Api = StreamsRuntime;
+
+	fn runtime_api(&self) -> ApiRef<'_, Self::Api> {
+		self.runtime.clone().into()
+	}
+}
+
+#[test]
+fn test_clients_of_runtimes_with_the_apis_access_the_chain() {
+	let validators = TestValidators::new(2);
+	let authorities = (0..2).map(|i| validators.public(i).into()).collect();
+	let client = StreamsClient {
+		client: substrate_test_runtime_client::new(),
+		runtime: StreamsRuntime { authorities },
+	};
+	let chain: &dyn ChainAccess<Block, AuthorityId> = &client;
+
+	let (number, genesis) = chain.best_block();
+	assert_eq!(number, 0);
+	assert_eq!(chain.finalized_block(), (0, genesis));
+	assert_eq!(chain.authorities(genesis), Ok(validators.pubkeys()));
+	assert_eq!(chain.session(genesis), Ok(3));
+	let previous = vec![(2, vec![validators.pub_key(0)])];
+	assert_eq!(chain.previous_sessions(genesis), Ok(previous));
+	let event_id = H256::repeat_byte(1);
+	let extrinsic = chain.create_unsigned_extrinsic(genesis, event_id, None).unwrap();
+	assert_eq!(chain.extrinsic_ids(genesis, &vec![extrinsic]), Ok(vec![event_id]));
+}
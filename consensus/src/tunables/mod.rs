@@ -0,0 +1,295 @@
+//! Parameters of the subsystem which can be changed while the node runs, without missing blocks to
+//! a restart: whether witnessing is paused, and how many witnessing requests of the trusted client
+//! are accepted per second. They start out as given on the command line, and are changed through
+//! the `UpdateConfig` admin RPC, or by reloading the file given with `--streams-config` when the
+//! node receives SIGHUP. Both name the parameters after their command-line flags, and apply
+//! either every change they are given or, if any of them is invalid, none; the other flags only
+//! take effect at startup, and changing them is rejected. Every change is logged with the old and
+//! the new value under `validated_streams::service`.
+
+use crate::{config::ValidatedStreamsNetworkParams, errors::ConfigError, logging::SERVICE};
+use std::{
+	fmt,
+	path::Path,
+	str::FromStr,
+	sync::{Arc, Mutex},
+};
+use tokio::{sync::watch, time::Instant};
+#[cfg(test)]
+pub mod tests;
+
+/// The flag of [TunableParams::witness_mode].
+pub const WITNESS_MODE: &str = "streams-witness-mode";
+/// The flag of [TunableParams::witness_rate_limit].
+pub const WITNESS_RATE_LIMIT: &str = "streams-witness-rate-limit";
+/// The flags which only take effect at startup: the other flags of
+/// [ValidatedStreamsNetworkParams], and the base path of the node, which holds its databases.
+const STARTUP_ONLY: [&str; 50] = [
+	"grpc-addr",
+	"grpc-tls-cert",
+	"grpc-tls-key",
+	"grpc-tls-client-ca",
+	"grpc-tls-client-allow",
+	"grpc-api-key",
+	"grpc-api-keys-file",
+	"grpc-web-origin",
+	"grpc-client-rate-limit",
+	"grpc-max-connections",
+	"grpc-keepalive-interval",
+	"grpc-keepalive-timeout",
+	"grpc-max-connection-age",
+	"gossip-port",
+	"gossip-listen-addr",
+	"gossip-backend",
+	"gossip-bootnodes",
+	"gossip-compression",
+	"gossip-discovery-interval",
+	"gossip-mdns",
+	"gossip-transport-timeout",
+	"gossip-max-incoming-connections",
+	"gossip-max-connections-per-peer",
+	"gossip-quic",
+	"gossip-heartbeat-interval",
+	"gossip-mesh-n",
+	"gossip-history-length",
+	"gossip-flood-publish",
+	"gossip-max-message-size",
+	"gossip-validators-only",
+	"gossip-allowlist",
+	"gossip-ban-threshold",
+	"gossip-ban-duration",
+	"gossip-flood-limit",
+	"gossip-min-peers",
+	"gossip-order-capacity",
+	"gossip-backpressure",
+	"gossip-liveness-interval",
+	"gossip-autonat",
+	"gossip-relay",
+	"gossip-external-address",
+	"streams-node-key-file",
+	"streams-node-key-seed",
+	"otlp-endpoint",
+	"streams-allow-missing-key",
+	"streams-runtime-threads",
+	"streams-config",
+	"streams-event-payloads",
+	"streams-payload-max-size",
+	"streams-payload-retention",
+	"streams-id",
+	"base-path",
+];
+
+/// Whether a validator witnesses the events submitted by its trusted client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WitnessMode {
+	/// Events are witnessed
+	Active,
+	/// Events are refused, as during maintenance; the witnesses of the other validators are still
+	/// collected
+	Paused,
+}
+
+impl fmt::Display for WitnessMode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			Self::Active => "active",
+			Self::Paused => "paused",
+		})
+	}
+}
+
+impl FromStr for WitnessMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"active" => Ok(Self::Active),
+			"paused" => Ok(Self::Paused),
+			_ => Err(format!("expected active or paused, got {s}")),
+		}
+	}
+}
+
+/// The values of the tunable parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TunableParams {
+	/// Whether the events of the trusted client are witnessed
+	pub witness_mode: WitnessMode,
+	/// How many witnessing requests of the trusted client are accepted per second, 0 for no limit
+	pub witness_rate_limit: u32,
+}
+
+impl Default for TunableParams {
+	fn default() -> Self {
+		Self { witness_mode: WitnessMode::Active, witness_rate_limit: 0 }
+	}
+}
+
+impl TunableParams {
+	/// The values given on the command line.
+	pub fn from_params(params: &ValidatedStreamsNetworkParams) -> Self {
+		Self {
+			witness_mode: params.streams_witness_mode,
+			witness_rate_limit: params.streams_witness_rate_limit,
+		}
+	}
+
+	/// Every parameter, by name, with its value.
+	pub fn entries(&self) -> [(&'static str, String); 2] {
+		[
+			(WITNESS_MODE, self.witness_mode.to_string()),
+			(WITNESS_RATE_LIMIT, self.witness_rate_limit.to_string()),
+		]
+	}
+
+	fn set(&mut self, name: &str, value: &str) -> Result<(), ConfigError> {
+		let invalid = || ConfigError::Invalid { name: name.to_string(), value: value.to_string() };
+		match name {
+			WITNESS_MODE => self.witness_mode = value.parse().map_err(|_| invalid())?,
+			WITNESS_RATE_LIMIT => self.witness_rate_limit = value.parse().map_err(|_| invalid())?,
+			_ if STARTUP_ONLY.contains(&name) => return Err(ConfigError::NotTunable(name.into())),
+			_ => return Err(ConfigError::Unknown(name.to_string())),
+		}
+		Ok(())
+	}
+}
+
+/// The current values of the tunable parameters, read by the parts of the subsystem they tune.
+/// Cheap to clone; the [Default] ones have the [Default] values.
+#[derive(Clone)]
+pub struct Tunables {
+	inner: Arc<Inner>,
+}
+
+struct Inner {
+	params: watch::Sender<TunableParams>,
+	/// The token bucket of the witnessing rate limit
+	witness_bucket: Mutex<Option<(f64, Instant)>>,
+}
+
+impl Default for Tunables {
+	fn default() -> Self {
+		Self::new(TunableParams::default())
+	}
+}
+
+impl Tunables {
+	/// Tunables starting out with the given values.
+	pub fn new(params: TunableParams) -> Self {
+		let inner = Inner { params: watch::channel(params).0, witness_bucket: Mutex::new(None) };
+		Self { inner: Arc::new(inner) }
+	}
+
+	/// The current values.
+	pub fn current(&self) -> TunableParams {
+		*self.inner.params.borrow()
+	}
+
+	/// Notified of every change of the values.
+	pub fn subscribe(&self) -> watch::Receiver<TunableParams> {
+		self.inner.params.subscribe()
+	}
+
+	/// Applies the given changes, by parameter name, and returns the resulting values; if any of
+	/// the changes is invalid, none of them is applied.
+	pub fn update<'a>(
+		&self,
+		changes: impl IntoIterator<Item = (&'a str, &'a str)>,
+	) -> Result<TunableParams, ConfigError> {
+		let mut applied = Ok(());
+		let mut changed = None;
+		// Validated and swapped under the lock of the channel, so that no update is lost
+		self.inner.params.send_if_modified(|params| {
+			let mut next = *params;
+			applied = changes.into_iter().try_for_each(|(name, value)| next.set(name, value));
+			if applied.is_err() || next == *params {
+				return false
+			}
+			changed = Some((std::mem::replace(params, next), next));
+			true
+		});
+		applied?;
+		if let Some((previous, next)) = changed {
+			for ((name, old), (_, new)) in previous.entries().into_iter().zip(next.entries()) {
+				if old != new {
+					tracing::info!(
+						target: SERVICE,
+						name,
+						old = %old,
+						new = %new,
+						"Changed a tunable parameter"
+					);
+				}
+			}
+		}
+		Ok(self.current())
+	}
+
+	/// Applies the changes listed in the config file at the given path, as [Tunables::update].
+	pub fn reload(&self, path: &Path) -> Result<TunableParams, ConfigError> {
+		let contents =
+			std::fs::read_to_string(path).map_err(|e| ConfigError::File(e.to_string()))?;
+		let changes = parse_config(&contents)?;
+		self.update(changes.iter().map(|(name, value)| (name.as_str(), value.as_str())))
+	}
+
+	/// Takes a token of the witnessing rate limit. Returns false if the request should be refused.
+	pub(crate) fn take_witness_token(&self) -> bool {
+		let limit = self.current().witness_rate_limit as f64;
+		if limit == 0.0 {
+			return true
+		}
+		let now = Instant::now();
+		let mut bucket = self.inner.witness_bucket.lock().unwrap();
+		// Starting with a full second's worth of requests; a tightened limit caps what is left
+		let (tokens, updated) = bucket.get_or_insert((limit, now));
+		let elapsed = now.saturating_duration_since(*updated).as_secs_f64();
+		*tokens = (*tokens + elapsed * limit).min(limit);
+		*updated = now;
+		if *tokens < 1.0 {
+			return false
+		}
+		*tokens -= 1.0;
+		true
+	}
+}
+
+/// Parses the contents of a config file: a `name = value` line per parameter, named as in
+/// [Tunables::update]. Empty lines, and lines starting with `#`, are skipped.
+pub fn parse_config(contents: &str) -> Result<Vec<(String, String)>, ConfigError> {
+	let lines = contents.lines().enumerate().map(|(index, line)| (index + 1, line.trim()));
+	lines
+		.filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+		.map(|(number, line)| match line.split_once('=') {
+			Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+			None => Err(ConfigError::File(format!("line {number} is not a `name = value` pair"))),
+		})
+		.collect()
+}
+
+/// Reloads the config file at the given path every time the node receives SIGHUP, keeping the
+/// current values if it is invalid.
+#[cfg(unix)]
+pub(crate) async fn reload_on_sighup(tunables: Tunables, path: std::path::PathBuf) {
+	use tokio::signal::unix::{signal, SignalKind};
+	let mut hangups = match signal(SignalKind::hangup()) {
+		Ok(hangups) => hangups,
+		Err(e) => {
+			tracing::warn!(target: SERVICE, error = %e, "Failed listening for SIGHUP");
+			return
+		},
+	};
+	while hangups.recv().await.is_some() {
+		match tunables.reload(&path) {
+			Ok(_) => {
+				tracing::info!(target: SERVICE, path = %path.display(), "Reloaded the config file")
+			},
+			Err(e) => tracing::warn!(
+				target: SERVICE,
+				path = %path.display(),
+				error = %e,
+				"Failed reloading the config file; keeping the current values"
+			),
+		}
+	}
+}
@@ -0,0 +1,177 @@
+use super::{parse_config, TunableParams, Tunables, WitnessMode, WITNESS_MODE, WITNESS_RATE_LIMIT};
+use crate::{
+	errors::{ConfigError, Error},
+	gossip::Gossip,
+	logging::SERVICE,
+	metrics::Metrics,
+	server::{
+		validated_streams_proto::{
+			admin_server::Admin, streams_server::Streams, UpdateConfigRequest, WitnessEventRequest,
+			WitnessEventsRequest,
+		},
+		AdminGrpc, ValidatedStreamsGrpc, MAX_WITNESS_BATCH,
+	},
+	test_utils::{
+		single_validator, CapturedLogs, NoEventProofs, SimulatedNetwork, SimulatedNode, TestGrpc,
+	},
+	traits::EventWitnesserTrait,
+};
+use sp_core::H256;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tonic::{Code, Request};
+
+/// A gRPC service witnessing events with the key of the only validator, tuned by the given
+/// tunables, and the admin service changing them.
+fn grpc(tunables: &Tunables) -> (TestGrpc, AdminGrpc, SimulatedNetwork<SimulatedNode>) {
+	let (witnesser, network) = single_validator();
+	let witnesser = witnesser.with_tunables(tunables.clone());
+	let grpc = ValidatedStreamsGrpc {
+		tunables: tunables.clone(),
+		..ValidatedStreamsGrpc::for_tests(witnesser)
+	};
+	let admin = AdminGrpc {
+		tunables: tunables.clone(),
+		metrics: Metrics::default(),
+		gossip: Gossip::create().0,
+		event_proofs: Arc::new(NoEventProofs),
+	};
+	(grpc, admin, network)
+}
+
+fn changes(changes: &[(&str, &str)]) -> Request<UpdateConfigRequest> {
+	let changes = changes.iter().map(|(name, value)| (name.to_string(), value.to_string()));
+	Request::new(UpdateConfigRequest { changes: changes.collect() })
+}
+
+/// How many of `count` witnessing requests sent at once are accepted.
+async fn accepted(grpc: &TestGrpc, count: u8) -> usize {
+	let mut accepted = 0;
+	for byte in 0..count {
+		let request = WitnessEventRequest { event_id: vec![byte; 32], ..Default::default() };
+		match grpc.witness_event(Request::new(request)).await {
+			Ok(_) => accepted += 1,
+			Err(status) => assert_eq!(status.code(), Code::ResourceExhausted, "{status}"),
+		}
+	}
+	accepted
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_rate_limit_tightened_at_runtime() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let tunables = Tunables::default();
+	let (grpc, admin, _network) = grpc(&tunables);
+	assert_eq!(accepted(&grpc, 10).await, 10);
+
+	let response = admin.update_config(changes(&[(WITNESS_RATE_LIMIT, "3")])).await.unwrap();
+	let effective = response.into_inner().effective;
+	assert_eq!(effective, HashMap::from([
+		(WITNESS_MODE.to_string(), "active".to_string()),
+		(WITNESS_RATE_LIMIT.to_string(), "3".to_string()),
+	]));
+	let lines = logs.find(SERVICE, "Changed a tunable parameter");
+	assert_eq!(lines.len(), 1);
+	assert_eq!(lines[0].field("name"), Some(WITNESS_RATE_LIMIT));
+	assert_eq!((lines[0].field("old"), lines[0].field("new")), (Some("0"), Some("3")));
+
+	// Enforced from the next request on, and refilled every second
+	assert_eq!(accepted(&grpc, 10).await, 3);
+	tokio::time::sleep(Duration::from_secs(1)).await;
+	assert_eq!(accepted(&grpc, 10).await, 3);
+
+	// And lifted again
+	admin.update_config(changes(&[(WITNESS_RATE_LIMIT, "0")])).await.unwrap();
+	assert_eq!(accepted(&grpc, 10).await, 10);
+}
+
+#[tokio::test]
+async fn test_invalid_updates_change_nothing() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let tunables = Tunables::default();
+	let (_grpc, admin, _network) = grpc(&tunables);
+
+	let rejected = [
+		(("base-path", "/tmp"), Code::FailedPrecondition),
+		(("grpc-addr", "127.0.0.1:6001"), Code::FailedPrecondition),
+		(("streams-witness-speed", "1"), Code::InvalidArgument),
+		((WITNESS_RATE_LIMIT, "-1"), Code::InvalidArgument),
+		((WITNESS_MODE, "sleeping"), Code::InvalidArgument),
+	];
+	for (change, code) in rejected {
+		// Along with a valid change, which is not applied either
+		let request = changes(&[(WITNESS_MODE, "paused"), change]);
+		let status = admin.update_config(request).await.unwrap_err();
+		assert_eq!(status.code(), code, "{change:?}: {status}");
+	}
+	assert_eq!(tunables.current(), TunableParams::default());
+	assert!(logs.find(SERVICE, "Changed a tunable parameter").is_empty());
+
+	// No changes only read the values
+	let response = admin.update_config(changes(&[])).await.unwrap();
+	assert_eq!(response.into_inner().effective[WITNESS_MODE], "active");
+}
+
+#[tokio::test]
+async fn test_paused_witnessing() {
+	let tunables = Tunables::default();
+	let (grpc, _admin, _network) = grpc(&tunables);
+	let witness = |byte| grpc.event_witnesser.witness_event(H256::repeat_byte(byte));
+
+	assert_eq!(witness(1).await, Ok(()));
+	tunables.update([(WITNESS_MODE, "paused")]).unwrap();
+	assert_eq!(witness(2).await, Err(Error::WitnessingPaused));
+	let request = Request::new(WitnessEventRequest { event_id: vec![2; 32], ..Default::default() });
+	let status = grpc.witness_event(request).await.unwrap_err();
+	assert_eq!(status.code(), Code::FailedPrecondition, "{status}");
+	tunables.update([(WITNESS_MODE, "active")]).unwrap();
+	assert_eq!(witness(3).await, Ok(()));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_batch_rate_limited_per_event() {
+	let tunables = Tunables::default();
+	let (grpc, _admin, network) = grpc(&tunables);
+	tunables.update([(WITNESS_RATE_LIMIT, "3")]).unwrap();
+
+	let event = |event_id: Vec<u8>| WitnessEventRequest { event_id, ..Default::default() };
+	let mut events: Vec<_> = (0..5).map(|byte| event(vec![byte; 32])).collect();
+	events.insert(1, event(vec![1; 31]));
+	let response = grpc.witness_events(Request::new(WitnessEventsRequest { events })).await;
+	let results = response.unwrap().into_inner().results;
+	let codes: Vec<_> = results.iter().map(|result| Code::from(result.code as i32)).collect();
+	// A malformed event takes no token of the rate limit
+	assert_eq!(codes, [
+		Code::Ok,
+		Code::InvalidArgument,
+		Code::Ok,
+		Code::Ok,
+		Code::ResourceExhausted,
+		Code::ResourceExhausted,
+	]);
+	network.run_until(network.now()).await;
+	assert_eq!(network.trace().len(), 3);
+
+	// Too large batches are refused whole
+	let events = vec![event(vec![9; 32]); MAX_WITNESS_BATCH + 1];
+	let status = grpc.witness_events(Request::new(WitnessEventsRequest { events })).await;
+	assert_eq!(status.unwrap_err().code(), Code::InvalidArgument);
+	let response = grpc.witness_events(Request::new(WitnessEventsRequest { events: vec![] })).await;
+	assert!(response.unwrap().into_inner().results.is_empty());
+}
+
+#[test]
+fn test_config_file() {
+	let contents = "# Tightened during the migration\n\nstreams-witness-rate-limit = 50\n";
+	assert_eq!(parse_config(contents), Ok(vec![(WITNESS_RATE_LIMIT.into(), "50".into())]));
+	assert!(matches!(parse_config("streams-witness-mode paused"), Err(ConfigError::File(_))));
+
+	let path = std::env::temp_dir().join(format!("streams-config-{}", std::process::id()));
+	std::fs::write(&path, format!("{contents}{WITNESS_MODE} = paused\n")).unwrap();
+	let tunables = Tunables::default();
+	let reloaded = tunables.reload(&path);
+	std::fs::remove_file(&path).unwrap();
+	let expected = TunableParams { witness_mode: WitnessMode::Paused, witness_rate_limit: 50 };
+	assert_eq!(reloaded, Ok(expected));
+	assert_eq!(tunables.current(), expected);
+	assert!(matches!(tunables.reload(&path), Err(ConfigError::File(_))));
+}
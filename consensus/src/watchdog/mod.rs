@@ -0,0 +1,140 @@
+//! Heartbeats of the long-lived loops of the subsystem, and a watchdog reporting those which
+//! stopped beating. Every loop registers a [Heartbeat] under the name of its task and bumps it each
+//! time around; loops which wait on notifications use [next_beating] so that they keep beating
+//! while idle. The watchdog exports the age of every heartbeat as
+//! `streams_task_heartbeat_age_seconds{task}` and warns about those older than the threshold.
+
+use crate::{logging::SERVICE, metrics::Metrics};
+use futures::{Stream, StreamExt};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::Duration,
+};
+use tokio::time::Instant;
+#[cfg(test)]
+pub mod tests;
+
+/// How often idle loops beat, at least.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// How old a heartbeat gets before its task is considered stalled.
+pub const STALL_THRESHOLD: Duration = Duration::from_secs(60);
+/// How often the watchdog checks the heartbeats.
+pub const WATCHDOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The heartbeats of all instrumented tasks. Cheap to clone.
+#[derive(Clone)]
+pub struct Heartbeats {
+	origin: Instant,
+	tasks: Arc<Mutex<BTreeMap<&'static str, Heartbeat>>>,
+}
+
+impl Default for Heartbeats {
+	fn default() -> Self {
+		Self { origin: Instant::now(), tasks: Default::default() }
+	}
+}
+
+/// The heartbeat of one task. Cheap to clone and to bump.
+#[derive(Clone)]
+pub struct Heartbeat {
+	origin: Instant,
+	/// The time of the last beat, in milliseconds since the origin
+	last: Arc<AtomicU64>,
+}
+
+impl Heartbeat {
+	/// Records that the task is alive.
+	pub fn bump(&self) {
+		self.last.store(self.origin.elapsed().as_millis() as u64, Ordering::Relaxed);
+	}
+
+	/// How long ago the task last beat.
+	fn age(&self, now: Instant) -> Duration {
+		let last = self.origin + Duration::from_millis(self.last.load(Ordering::Relaxed));
+		now.saturating_duration_since(last)
+	}
+}
+
+impl Heartbeats {
+	/// Registers the heartbeat of a task, beating already. Registering the same task again (e.g.
+	/// after a restart) replaces its heartbeat. Once the task drops every clone of the heartbeat,
+	/// e.g. because it finished, it is no longer watched.
+	pub fn register(&self, task: &'static str) -> Heartbeat {
+		let heartbeat = Heartbeat { origin: self.origin, last: Arc::new(AtomicU64::new(0)) };
+		heartbeat.bump();
+		self.tasks.lock().unwrap().insert(task, heartbeat.clone());
+		heartbeat
+	}
+
+	/// The age of the heartbeat of every task still running, by task.
+	pub fn ages(&self, now: Instant) -> Vec<(&'static str, Duration)> {
+		let mut tasks = self.tasks.lock().unwrap();
+		tasks.retain(|_, heartbeat| Arc::strong_count(&heartbeat.last) > 1);
+		tasks.iter().map(|(task, heartbeat)| (*task, heartbeat.age(now))).collect()
+	}
+}
+
+/// Waits for the next item of the stream, bumping the heartbeat at least every
+/// [HEARTBEAT_INTERVAL] meanwhile.
+pub async fn next_beating<S: Stream + Unpin>(
+	stream: &mut S,
+	heartbeat: &Heartbeat,
+) -> Option<S::Item> {
+	loop {
+		heartbeat.bump();
+		if let Ok(item) = tokio::time::timeout(HEARTBEAT_INTERVAL, stream.next()).await {
+			heartbeat.bump();
+			return item
+		}
+	}
+}
+
+/// Reports the ages of the heartbeats and warns about stalled tasks.
+pub(crate) struct Watchdog {
+	heartbeats: Heartbeats,
+	metrics: Metrics,
+	threshold: Duration,
+	stalled: BTreeSet<&'static str>,
+}
+
+impl Watchdog {
+	pub fn new(heartbeats: Heartbeats, metrics: Metrics, threshold: Duration) -> Self {
+		Self { heartbeats, metrics, threshold, stalled: BTreeSet::new() }
+	}
+
+	/// Checks every heartbeat at `now`, returning the stalled tasks.
+	pub fn check(&mut self, now: Instant) -> Vec<&'static str> {
+		for (task, age) in self.heartbeats.ages(now) {
+			self.metrics.set_task_heartbeat_age(task, age);
+			if age >= self.threshold {
+				if self.stalled.insert(task) {
+					let seconds = age.as_secs();
+					tracing::warn!(target: SERVICE, task, seconds, "Task has not made progress");
+				}
+			} else if self.stalled.remove(task) {
+				tracing::info!(target: SERVICE, task, "Task made progress again");
+			}
+		}
+		self.stalled.iter().copied().collect()
+	}
+}
+
+/// Checks the heartbeats every `interval`, forever, flagging the tasks which have not beaten for
+/// `threshold`.
+pub async fn run_watchdog(
+	heartbeats: Heartbeats,
+	metrics: Metrics,
+	threshold: Duration,
+	interval: Duration,
+) {
+	let mut watchdog = Watchdog::new(heartbeats, metrics, threshold);
+	let mut interval = tokio::time::interval(interval);
+	loop {
+		interval.tick().await;
+		watchdog.check(Instant::now());
+	}
+}
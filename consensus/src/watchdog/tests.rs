@@ -0,0 +1,57 @@
+use super::{next_beating, Heartbeats, Watchdog, HEARTBEAT_INTERVAL, STALL_THRESHOLD};
+use crate::{logging::SERVICE, metrics::Metrics, test_utils::CapturedLogs};
+use futures::{future, stream};
+use prometheus_endpoint::Registry;
+use tokio::time::Instant;
+
+/// The heartbeat age of a task, as exported to the registry.
+fn heartbeat_age(registry: &Registry, task: &str) -> Option<f64> {
+	let families = registry.gather();
+	let family =
+		families.iter().find(|family| family.get_name() == "streams_task_heartbeat_age_seconds")?;
+	let metrics = family.get_metric();
+	let metric = metrics.iter().find(|metric| metric.get_label()[0].get_value() == task)?;
+	Some(metric.get_gauge().get_value())
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_watchdog_flags_stalled_loop() {
+	let (logs, _guard) = CapturedLogs::capture();
+	let registry = Registry::new();
+	let metrics = Metrics::register(Some(&registry)).unwrap();
+	let heartbeats = Heartbeats::default();
+
+	// A loop idly waiting on notifications which never come, and one stuck after its first round
+	let healthy = heartbeats.register("healthy");
+	tokio::spawn(async move {
+		let mut notifications = stream::pending::<()>();
+		next_beating(&mut notifications, &healthy).await;
+	});
+	let stalled = heartbeats.register("stalled");
+	let stuck = stalled.clone();
+	tokio::spawn(async move {
+		stuck.bump();
+		future::pending::<()>().await;
+		drop(stuck);
+	});
+	// A task which finished is not watched anymore
+	drop(heartbeats.register("finished"));
+
+	let mut watchdog = Watchdog::new(heartbeats, metrics, STALL_THRESHOLD);
+	tokio::time::sleep(STALL_THRESHOLD / 2).await;
+	assert!(watchdog.check(Instant::now()).is_empty());
+
+	tokio::time::sleep(STALL_THRESHOLD).await;
+	assert_eq!(watchdog.check(Instant::now()), vec!["stalled"]);
+	assert_eq!(watchdog.check(Instant::now()), vec!["stalled"]);
+	let warnings = logs.find(SERVICE, "Task has not made progress");
+	assert_eq!(warnings.len(), 1);
+	assert_eq!(warnings[0].field("task"), Some("stalled"));
+	assert!(heartbeat_age(&registry, "stalled").unwrap() >= STALL_THRESHOLD.as_secs_f64());
+	assert!(heartbeat_age(&registry, "healthy").unwrap() <= HEARTBEAT_INTERVAL.as_secs_f64());
+	assert_eq!(heartbeat_age(&registry, "finished"), None);
+
+	stalled.bump();
+	assert!(watchdog.check(Instant::now()).is_empty());
+	assert_eq!(logs.find(SERVICE, "Task made progress again").len(), 1);
+}
@@ -0,0 +1,97 @@
+//! Benchmarks of how much the streams subsystem holds up the rest of the node. A slot timer, like
+//! the one block authorship waits on, ticks on the node's runtime while a burst of gossip gets its
+//! witnesses verified, either on the same runtime or on a dedicated [StreamsRuntime]; the lateness
+//! of its ticks is what gets measured. See the Benchmarking section of the README.
+
+use consensus_validated_streams::{
+	events::{AuthoritiesList, EventProofsCollector},
+	executor::StreamsRuntime,
+	metrics::Metrics,
+	proofs::InMemoryEventProofs,
+	test_utils::TestValidators,
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use sp_core::H256;
+use std::{
+	num::NonZeroUsize,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+/// The size of the validator set used throughout.
+const VALIDATORS: usize = 10;
+/// How many distinct events the burst cycles through.
+const EVENTS: u8 = 100;
+/// Worker threads of the node's runtime, and of the dedicated one.
+const THREADS: usize = 2;
+/// How many workers verify the burst, twice as many as there are threads to keep them all busy.
+const LOAD_WORKERS: usize = THREADS * 2;
+/// How many witnesses a worker handles between yields, like a gossip worker draining its queue.
+const LOAD_BATCH: usize = 50;
+/// The period of the slot timer.
+const TICK: Duration = Duration::from_millis(1);
+
+/// Verifies and collects witnesses of the burst forever.
+async fn verify_burst(messages: Arc<Vec<Vec<u8>>>, authorities: AuthoritiesList) {
+	loop {
+		// A fresh store every round, so that the witnesses never get found to be duplicates
+		let collector =
+			EventProofsCollector::new(Arc::new(InMemoryEventProofs::new()), Metrics::default());
+		for batch in messages.chunks(LOAD_BATCH) {
+			for message in batch {
+				collector.collect(&authorities, message).unwrap();
+			}
+			tokio::task::yield_now().await;
+		}
+	}
+}
+
+fn bench_slot_timer_lateness(c: &mut Criterion) {
+	let validators = TestValidators::new(VALIDATORS);
+	let authorities = validators.authorities();
+	let messages: Vec<_> = (0..EVENTS)
+		.flat_map(|event| (0..VALIDATORS).map(move |i| (i, H256::repeat_byte(event))))
+		.map(|(i, event_id)| validators.witness(i, event_id).build().to_bytes().unwrap())
+		.collect();
+	let messages = Arc::new(messages);
+
+	let mut group = c.benchmark_group("slot_timer_lateness");
+	for (name, dedicated) in [("idle", None), ("shared", Some(false)), ("dedicated", Some(true))] {
+		let runtime = tokio::runtime::Builder::new_multi_thread()
+			.worker_threads(THREADS)
+			.enable_all()
+			.build()
+			.unwrap();
+		let streams_runtime = (dedicated == Some(true)).then(|| {
+			let threads = NonZeroUsize::new(THREADS).unwrap();
+			Arc::new(StreamsRuntime::dedicated(threads).unwrap())
+		});
+		if dedicated.is_some() {
+			for _ in 0..LOAD_WORKERS {
+				let burst = verify_burst(messages.clone(), authorities.clone());
+				match &streams_runtime {
+					Some(streams_runtime) => runtime.spawn(streams_runtime.clone().run(burst)),
+					None => runtime.spawn(burst),
+				};
+			}
+		}
+
+		group.bench_function(name, |b| {
+			b.to_async(&runtime).iter_custom(|iters| async move {
+				let mut lateness = Duration::ZERO;
+				for _ in 0..iters {
+					let started = Instant::now();
+					tokio::time::sleep(TICK).await;
+					lateness += started.elapsed().saturating_sub(TICK);
+				}
+				lateness
+			})
+		});
+		// Stops the burst, and the dedicated runtime with it
+		drop(runtime);
+	}
+	group.finish();
+}
+
+criterion_group!(benches, bench_slot_timer_lateness);
+criterion_main!(benches);
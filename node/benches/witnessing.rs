@@ -0,0 +1,206 @@
+//! Benchmarks of the witnessing hot path: everything a witnessed event goes through between being
+//! signed by one validator and being counted by the others. Runs entirely on the test fixtures, so
+//! no chain is needed. See the Benchmarking section of the README for comparing against a baseline.
+
+use consensus_validated_streams::{
+	events::{EventProofsCollector, WITNESSED_EVENTS_TOPIC},
+	gossip::GossipTrait,
+	metrics::Metrics,
+	proofs::{
+		EventProofsTrait, InMemoryEventProofs, OffchainStorageEventProofs, RocksDbEventProofs,
+		WitnessedEvent,
+	},
+	test_utils::{SimulatedNetwork, SimulatedNode, TestValidators},
+};
+use criterion::{
+	black_box, criterion_group, criterion_main, measurement::WallTime, BatchSize, BenchmarkGroup,
+	Criterion,
+};
+use libp2p::gossipsub::IdentTopic;
+use sp_core::H256;
+use sp_keystore::SyncCryptoStore;
+use sp_runtime::{
+	app_crypto::CryptoTypePublicPair, key_types::AURA, offchain::testing::TestPersistentOffchainDB,
+};
+use std::sync::Arc;
+use vstreams_node_runtime::pallet_validated_streams::payload::witness_payload;
+
+/// The size of the validator set used throughout.
+const VALIDATORS: usize = 10;
+/// How many distinct events the proofs benchmarks cycle through.
+const EVENTS: u8 = 100;
+
+/// Proofs of every validator for each of the [EVENTS] events, grouped by event.
+fn witnessed_events(validators: &TestValidators) -> Vec<WitnessedEvent> {
+	(0..EVENTS)
+		.flat_map(|event| {
+			(0..validators.len())
+				.map(move |i| validators.witness(i, H256::repeat_byte(event)).build())
+		})
+		.collect()
+}
+
+fn bench_sign(c: &mut Criterion) {
+	let validators = TestValidators::new(1);
+	let keystore = validators.keystore(0);
+	let pub_key = validators.pub_key(0);
+	let event_id = H256::repeat_byte(1);
+
+	c.bench_function("witness_payload_sign", |b| {
+		b.iter(|| {
+			let payload = witness_payload(black_box(&event_id), black_box(0));
+			SyncCryptoStore::sign_with(keystore.as_ref(), AURA, &pub_key, &payload)
+				.unwrap()
+				.unwrap()
+		})
+	});
+}
+
+fn bench_verify(c: &mut Criterion) {
+	let validators = TestValidators::new(VALIDATORS);
+	let authorities = validators.authorities();
+	let witnessed_event = validators.witness(VALIDATORS - 1, H256::repeat_byte(1)).build();
+
+	c.bench_function("witnessed_event_verify", |b| {
+		b.iter(|| authorities.verify_witnessed_event_origin(witnessed_event.clone()).unwrap())
+	});
+}
+
+fn bench_membership(c: &mut Criterion) {
+	let mut group = c.benchmark_group("validator_set_membership");
+	for size in [VALIDATORS, 100] {
+		let validators = TestValidators::new(size);
+		let authorities = validators.authorities();
+		// The last key of the list, the worst case of a scan
+		let key = validators.pub_key(size - 1);
+		group.bench_function(format!("indexed/{size}"), |b| {
+			b.iter(|| authorities.contains(black_box(&key)))
+		});
+		group.bench_function(format!("scan/{size}"), |b| {
+			b.iter(|| authorities.authorities.contains(black_box(&key)))
+		});
+	}
+	group.finish();
+}
+
+fn bench_add_and_count_backend(
+	group: &mut BenchmarkGroup<WallTime>,
+	name: &str,
+	proofs: impl EventProofsTrait,
+	events: &[WitnessedEvent],
+	authorities: &[CryptoTypePublicPair],
+) {
+	let mut events = events.iter().cycle();
+	group.bench_function(name, |b| {
+		b.iter(|| {
+			let event = events.next().unwrap();
+			proofs.add_event_proof(event).unwrap();
+			proofs.get_event_proof_count(&event.event_id, authorities).unwrap()
+		})
+	});
+}
+
+fn bench_add_and_count(c: &mut Criterion) {
+	let validators = TestValidators::new(VALIDATORS);
+	let authorities = validators.pubkeys();
+	let events = witnessed_events(&validators);
+	let rocksdb_dir = tempfile::tempdir().unwrap();
+
+	let mut group = c.benchmark_group("event_proofs_add_and_count");
+	bench_add_and_count_backend(
+		&mut group,
+		"in_memory",
+		InMemoryEventProofs::new(),
+		&events,
+		&authorities,
+	);
+	bench_add_and_count_backend(
+		&mut group,
+		"offchain",
+		OffchainStorageEventProofs::new(TestPersistentOffchainDB::new()),
+		&events,
+		&authorities,
+	);
+	bench_add_and_count_backend(
+		&mut group,
+		"rocksdb",
+		RocksDbEventProofs::create(rocksdb_dir.path().to_str().unwrap()),
+		&events,
+		&authorities,
+	);
+	group.finish();
+}
+
+fn bench_handle_witnessed_event(c: &mut Criterion) {
+	let validators = TestValidators::new(VALIDATORS);
+	let authorities = validators.authorities();
+	let messages: Vec<_> =
+		witnessed_events(&validators).iter().map(|event| event.to_bytes().unwrap()).collect();
+
+	// What EventGossipHandler does with every message, short of submitting the extrinsic. Each
+	// iteration handles every message once, on a fresh collector, as replayed ones would be
+	// dropped as copies
+	c.bench_function("handle_witnessed_event", |b| {
+		b.iter_batched(
+			|| EventProofsCollector::new(Arc::new(InMemoryEventProofs::new()), Metrics::default()),
+			|collector| {
+				for message in &messages {
+					if let Some(event_id) = collector.collect(&authorities, message).unwrap() {
+						collector.mark_submitted(event_id).unwrap();
+					}
+				}
+			},
+			BatchSize::SmallInput,
+		)
+	});
+
+	// A whole round of gossip: every validator witnesses the event and every node handles all
+	// the proofs
+	let runtime = tokio::runtime::Runtime::new().unwrap();
+	let event_id = H256::repeat_byte(1);
+	c.bench_function("witness_round_simulated_gossip", |b| {
+		b.to_async(&runtime).iter_batched(
+			|| {
+				let nodes = (0..VALIDATORS)
+					.map(|_| Arc::new(SimulatedNode::new(authorities.clone())))
+					.collect();
+				let messages = (0..VALIDATORS)
+					.map(|i| validators.witness(i, event_id).build().to_bytes().unwrap())
+					.collect::<Vec<_>>();
+				(SimulatedNetwork::new(0, nodes), messages)
+			},
+			|(network, messages)| async move {
+				let topic = IdentTopic::new(WITNESSED_EVENTS_TOPIC);
+				for (i, message) in messages.into_iter().enumerate() {
+					network.gossip(i).publish(topic.clone(), message).await.unwrap();
+				}
+				network.run_until_idle().await;
+			},
+			BatchSize::SmallInput,
+		)
+	});
+}
+
+fn bench_wire_format(c: &mut Criterion) {
+	let validators = TestValidators::new(1);
+	let witnessed_event = validators.witness(0, H256::repeat_byte(1)).build();
+	let message = witnessed_event.to_bytes().unwrap();
+
+	c.bench_function("witnessed_event_encode", |b| {
+		b.iter(|| black_box(&witnessed_event).to_bytes().unwrap())
+	});
+	c.bench_function("witnessed_event_decode", |b| {
+		b.iter(|| WitnessedEvent::from_bytes(black_box(&message)).unwrap())
+	});
+}
+
+criterion_group!(
+	benches,
+	bench_sign,
+	bench_verify,
+	bench_membership,
+	bench_add_and_count,
+	bench_handle_witnessed_event,
+	bench_wire_format
+);
+criterion_main!(benches);
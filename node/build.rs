@@ -4,5 +4,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 	generate_cargo_keys();
 
 	rerun_if_git_head_changed();
+
+	// A client generated straight from the proto file, for the gRPC end-to-end tests
+	tonic_build::configure()
+		.build_server(false)
+		.compile(&["../proto/streams.proto"], &["../proto"])?;
 	Ok(())
 }
@@ -1,6 +1,9 @@
 //! Specification for the chains used by this node
 
+use libp2p::Multiaddr;
+use sc_chain_spec::ChainSpecExtension;
 use sc_service::ChainType;
+use serde::{Deserialize, Serialize};
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
 use sp_consensus_grandpa::AuthorityId as GrandpaId;
 use sp_core::{sr25519, Pair, Public};
@@ -14,7 +17,40 @@ use vstreams_node_runtime::{
 // const STAGING_TELEMETRY_URL: &str = "wss://telemetry.polkadot.io/submit/";
 
 /// Specialized `ChainSpec`. This is a specialization of the general Substrate ChainSpec type.
-pub type ChainSpec = sc_service::GenericChainSpec<GenesisConfig>;
+pub type ChainSpec = sc_service::GenericChainSpec<GenesisConfig, Extensions>;
+
+/// The extensions of the chain spec, alongside its genesis.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ChainSpecExtension)]
+#[serde(rename_all = "camelCase")]
+pub struct Extensions {
+	/// The `validatedStreams` section, left out of the specs of networks which need none.
+	#[serde(default)]
+	pub validated_streams: ValidatedStreamsExtension,
+}
+
+impl Extensions {
+	/// The extensions of the given chain spec, if it was loaded as a [ChainSpec].
+	pub fn try_get(chain_spec: &dyn sc_service::ChainSpec) -> Option<&Self> {
+		sc_chain_spec::get_extension(chain_spec.extensions())
+	}
+}
+
+/// The Validated Streams settings shipped with the chain spec of a network.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidatedStreamsExtension {
+	/// The gossip multiaddrs of the validators of the network, dialed on startup unless
+	/// `--gossip-bootnodes` is given.
+	#[serde(default)]
+	pub peers: Vec<Multiaddr>,
+}
+
+/// The gossip peers listed in the chain spec, if any.
+pub fn gossip_peers(chain_spec: &dyn sc_service::ChainSpec) -> Vec<Multiaddr> {
+	Extensions::try_get(chain_spec)
+		.map(|extensions| extensions.validated_streams.peers.clone())
+		.unwrap_or_default()
+}
 
 /// Generate a crypto pair from seed.
 pub fn get_from_seed<TPublic: Public>(seed: &str) -> <TPublic::Pair as Pair>::Public {
@@ -75,7 +111,7 @@ pub fn development_config() -> Result<ChainSpec, String> {
 		// Properties
 		None,
 		// Extensions
-		None,
+		Extensions::default(),
 	))
 }
 
@@ -129,12 +165,12 @@ pub fn local_testnet_config() -> Result<ChainSpec, String> {
 		None,
 		None,
 		// Extensions
-		None,
+		Extensions::default(),
 	))
 }
 
 /// Configure initial storage state for FRAME modules.
-fn testnet_genesis(
+pub fn testnet_genesis(
 	wasm_binary: &[u8],
 	initial_authorities: Vec<(AuraId, GrandpaId)>,
 	root_key: AccountId,
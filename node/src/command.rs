@@ -4,10 +4,15 @@ use crate::{
 	cli::{Cli, Subcommand},
 	service::{self, ExecutorDispatch},
 };
+use consensus_validated_streams::{
+	shutdown::SHUTDOWN_DEADLINE, ValidatedStreamsNetworkConfiguration,
+};
 use frame_benchmarking_cli::{BenchmarkCmd, ExtrinsicFactory, SUBSTRATE_REFERENCE_HARDWARE};
-use sc_cli::{ChainSpec, RuntimeVersion, SubstrateCli};
-use sc_service::PartialComponents;
+use futures::future::{self, Either};
+use sc_cli::{ChainSpec, CliConfiguration, RuntimeVersion, SubstrateCli};
+use sc_service::{Configuration, PartialComponents};
 use sp_keyring::Sr25519Keyring;
+use std::time::Duration;
 #[cfg(feature = "try-runtime")]
 use try_runtime_cli::block_building_info::timestamp_with_aura_info;
 use vstreams_node_runtime::{Block, EXISTENTIAL_DEPOSIT};
@@ -206,11 +211,59 @@ pub fn run() -> sc_cli::Result<()> {
 			runner.sync_run(|config| cmd.run::<Block>(&config))
 		},
 		None => {
-			let runner = cli.create_runner(&cli.run.base)?;
-			runner.run_node_until_exit(|config| async move {
-				service::new_full(config, cli.run.validated_streams_params)
-					.map_err(sc_cli::Error::Service)
-			})
+			// What cli.create_runner does, so as to run the node without the Runner
+			let tokio_runtime = sc_cli::build_runtime()?;
+			let config = cli.run.base.create_configuration(&cli, tokio_runtime.handle().clone())?;
+			cli.run.base.init(&Cli::support_url(), &Cli::impl_version(), |_, _| {}, &config)?;
+			run_node_until_exit(tokio_runtime, config, cli.run.validated_streams_params)
 		},
 	}
 }
+
+/// How long the tasks of the node get to stop once the streams subsystem has shut down. Same as the
+/// Runner's.
+const TASKS_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Runs the node until it is told to exit or one of its essential tasks fails, like
+/// [sc_cli::Runner::run_node_until_exit] does. Unlike the Runner, which stops all tasks at once,
+/// first lets the streams subsystem shut down in order, so that no witness gets cut off halfway.
+fn run_node_until_exit(
+	tokio_runtime: tokio::runtime::Runtime,
+	config: Configuration,
+	validated_streams_config: ValidatedStreamsNetworkConfiguration,
+) -> sc_cli::Result<()> {
+	sc_cli::print_node_infos::<Cli>(&config);
+	let (mut task_manager, streams_shutdown) = tokio_runtime
+		.block_on(async { service::new_full(config, validated_streams_config) })
+		.map_err(sc_cli::Error::Service)?;
+
+	let result = tokio_runtime.block_on(async {
+		let exit = Box::pin(exit_signal());
+		let result = match future::select(task_manager.future(), exit).await {
+			Either::Left((result, _)) => result,
+			Either::Right(((), _)) => Ok(()),
+		};
+		streams_shutdown.run(SHUTDOWN_DEADLINE).await;
+		result
+	});
+
+	// Stops the remaining tasks
+	drop(task_manager);
+	tokio_runtime.shutdown_timeout(TASKS_SHUTDOWN_TIMEOUT);
+	result.map_err(sc_cli::Error::Service)
+}
+
+/// Resolves once the process receives SIGINT or SIGTERM.
+async fn exit_signal() {
+	#[cfg(unix)]
+	{
+		use tokio::signal::unix::{signal, SignalKind};
+		let mut terminate = signal(SignalKind::terminate()).expect("SIGTERM handler installs");
+		tokio::select! {
+			_ = tokio::signal::ctrl_c() => {},
+			_ = terminate.recv() => {},
+		}
+	}
+	#[cfg(not(unix))]
+	tokio::signal::ctrl_c().await.ok();
+}
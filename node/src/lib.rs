@@ -7,5 +7,6 @@
 
 #![warn(missing_docs)]
 pub mod chain_spec;
+pub mod manual_seal;
 pub mod rpc;
 pub mod service;
@@ -0,0 +1,285 @@
+//! A variant of the full node service which seals blocks on demand instead of running Aura and
+//! GRANDPA. Used by the integration tests to run several validators inside a single process.
+
+use crate::{
+	chain_spec::{self, authority_keys_from_seed, get_account_id_from_seed, ChainSpec},
+	service::{new_event_proofs, ExecutorDispatch, FullClient, FullEventProofs, CACHE_CAPACITY},
+};
+#[cfg(feature = "off-chain-proofs")]
+use consensus_validated_streams::ValidatedStreamsBlockImport;
+use consensus_validated_streams::{
+	gossip::{self, GossipBackend},
+	index::OffchainStorageEventIndex, payloads::OffchainStorageEventPayloads,
+	shutdown::StreamsShutdown, ValidatedStreamsNetworkConfiguration, ValidatorSetHandle,
+};
+use futures::channel::mpsc;
+use sc_client_api::Backend;
+use sc_consensus_manual_seal::{
+	consensus::aura::AuraConsensusDataProvider, EngineCommand, ManualSealParams,
+};
+use sc_executor::NativeElseWasmExecutor;
+#[cfg(feature = "off-chain-proofs")]
+use sc_network_sync::SyncingService;
+use sc_service::{error::Error as ServiceError, ChainType, Configuration, TaskManager};
+#[cfg(feature = "off-chain-proofs")]
+use sp_consensus_aura::sr25519::AuthorityId as AuraId;
+use sp_consensus_aura::SlotDuration;
+use sp_core::{sr25519, H256};
+use substrate_prometheus_endpoint::Registry;
+use std::{
+	num::NonZeroUsize,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::{SystemTime, UNIX_EPOCH},
+};
+use vstreams_node_runtime::{opaque::Block, RuntimeApi, WASM_BINARY};
+
+/// The transaction pool used by full nodes.
+pub type FullPool = sc_transaction_pool::FullPool<Block, FullClient>;
+
+/// A running manual-seal node, along with handles to its internals.
+pub struct ManualSealNode {
+	/// The task manager running all of the node's tasks. Dropping it stops the node.
+	pub task_manager: TaskManager,
+	/// The client.
+	pub client: Arc<FullClient>,
+	/// The transaction pool fully-witnessed events are submitted to.
+	pub transaction_pool: Arc<FullPool>,
+	/// The proofs of the events the node has seen witnessed.
+	pub event_proofs: Arc<FullEventProofs>,
+	/// Channel for ordering the node to seal or finalize blocks.
+	pub seal_commands: mpsc::Sender<EngineCommand<H256>>,
+	/// The registry the node reports metrics to, if metrics are enabled.
+	pub prometheus_registry: Option<Registry>,
+	/// The shutdown of the streams subsystem, as run by the node binary once told to exit.
+	pub streams_shutdown: StreamsShutdown,
+}
+
+/// Configuration for a local network whose authorities are derived from the given seeds (in the
+/// same way as `--alice` and co. are derived from `//Alice`).
+pub fn testnet_config(authority_seeds: Vec<String>) -> Result<ChainSpec, String> {
+	let wasm_binary = WASM_BINARY.ok_or_else(|| "Development wasm not available".to_string())?;
+
+	Ok(ChainSpec::from_genesis(
+		// Name
+		"Manual Seal Testnet",
+		// ID
+		"manual_seal_testnet",
+		ChainType::Local,
+		move || {
+			chain_spec::testnet_genesis(
+				wasm_binary,
+				authority_seeds.iter().map(|seed| authority_keys_from_seed(seed)).collect(),
+				get_account_id_from_seed::<sr25519::Public>("Alice"),
+				authority_seeds
+					.iter()
+					.map(|seed| get_account_id_from_seed::<sr25519::Public>(seed))
+					.collect(),
+				true,
+			)
+		},
+		vec![],
+		None,
+		None,
+		None,
+		None,
+		Default::default(),
+	))
+}
+
+/// Builds a new service for a full client which only produces blocks when ordered to through
+/// [ManualSealNode::seal_commands].
+pub fn new_manual_seal(
+	mut config: Configuration,
+	validated_streams_network_config: ValidatedStreamsNetworkConfiguration,
+) -> Result<ManualSealNode, ServiceError> {
+	let executor = NativeElseWasmExecutor::<ExecutorDispatch>::new(
+		config.wasm_method,
+		config.default_heap_pages,
+		config.max_runtime_instances,
+		config.runtime_cache_size,
+	);
+
+	let (client, backend, keystore_container, mut task_manager) =
+		sc_service::new_full_parts::<Block, RuntimeApi, _>(&config, None, executor)?;
+	let client = Arc::new(client);
+
+	let select_chain = sc_consensus::LongestChain::new(backend.clone());
+
+	let transaction_pool = sc_transaction_pool::BasicPool::new_full(
+		config.transaction_pool.clone(),
+		config.role.is_authority().into(),
+		config.prometheus_registry(),
+		task_manager.spawn_essential_handle(),
+		client.clone(),
+	);
+
+	let event_proofs = new_event_proofs(&config, &backend)?;
+
+	let event_payloads = Arc::new(OffchainStorageEventPayloads::new(
+		backend
+			.offchain_storage()
+			.ok_or_else(|| ServiceError::Other("Offchain storage is required.".into()))?,
+	));
+
+	let event_index = Arc::new(OffchainStorageEventIndex::new(
+		backend
+			.offchain_storage()
+			.ok_or_else(|| ServiceError::Other("Offchain storage is required.".into()))?,
+	));
+
+	let validator_set = ValidatorSetHandle::new(NonZeroUsize::new(CACHE_CAPACITY).unwrap());
+
+	#[cfg(not(feature = "off-chain-proofs"))]
+	let block_import = client.clone();
+	#[cfg(feature = "off-chain-proofs")]
+	let (block_import, provide_sync_service) = ValidatedStreamsBlockImport::<
+		Block,
+		_,
+		_,
+		_,
+		SyncingService<Block>,
+		AuraId,
+	>::new(client.clone(), client.clone(), event_proofs.clone(), validator_set.clone());
+
+	let import_queue = sc_consensus_manual_seal::import_queue(
+		Box::new(block_import.clone()),
+		&task_manager.spawn_essential_handle(),
+		config.prometheus_registry(),
+	);
+
+	let (gossip_network, provide_gossip_network) = gossip::pending_network();
+	if validated_streams_network_config.gossip_backend == GossipBackend::Network {
+		config.network.extra_sets.push(gossip::network::peers_set_config());
+	}
+	let streams_shutdown =
+		consensus_validated_streams::start(consensus_validated_streams::StartParams {
+			spawn_handle: task_manager.spawn_handle(),
+			essential_spawn_handle: task_manager.spawn_essential_handle(),
+			event_proofs: event_proofs.clone(),
+			event_payloads,
+			event_index,
+			client: client.clone(),
+			keystore: keystore_container.keystore(),
+			transaction_pool: transaction_pool.clone(),
+			validated_streams_network_config,
+			network_configuration: config.network.clone(),
+			chain_spec_peers: chain_spec::gossip_peers(&*config.chain_spec),
+			gossip_network,
+			validator_set,
+			prometheus_registry: config.prometheus_registry().cloned(),
+			telemetry: None,
+			role: config.role.clone(),
+			keystore_path: config.keystore.path().map(|path| path.to_path_buf()),
+		})?;
+	let prometheus_registry = config.prometheus_registry().cloned();
+
+	let (network, system_rpc_tx, tx_handler_controller, network_starter, sync_service) =
+		sc_service::build_network(sc_service::BuildNetworkParams {
+			config: &config,
+			client: client.clone(),
+			transaction_pool: transaction_pool.clone(),
+			spawn_handle: task_manager.spawn_handle(),
+			import_queue,
+			block_announce_validator_builder: None,
+			warp_sync_params: None,
+		})?;
+
+	#[cfg(feature = "off-chain-proofs")]
+	provide_sync_service(sync_service.clone());
+	provide_gossip_network(network.clone());
+
+	let rpc_extensions_builder = {
+		let client = client.clone();
+		let pool = transaction_pool.clone();
+
+		Box::new(move |deny_unsafe, _| {
+			let deps =
+				crate::rpc::FullDeps { client: client.clone(), pool: pool.clone(), deny_unsafe };
+			crate::rpc::create_full(deps).map_err(Into::into)
+		})
+	};
+
+	let slot_duration = sc_consensus_aura::slot_duration(&*client)?;
+
+	let proposer_factory = sc_basic_authorship::ProposerFactory::new(
+		task_manager.spawn_handle(),
+		client.clone(),
+		transaction_pool.clone(),
+		config.prometheus_registry(),
+		None,
+	);
+
+	sc_service::spawn_tasks(sc_service::SpawnTasksParams {
+		network,
+		client: client.clone(),
+		keystore: keystore_container.sync_keystore(),
+		task_manager: &mut task_manager,
+		transaction_pool: transaction_pool.clone(),
+		rpc_builder: rpc_extensions_builder,
+		backend,
+		system_rpc_tx,
+		tx_handler_controller,
+		sync_service,
+		config,
+		telemetry: None,
+	})?;
+
+	let (seal_commands, commands_stream) = mpsc::channel(16);
+
+	let manual_seal = sc_consensus_manual_seal::run_manual_seal(ManualSealParams {
+		block_import,
+		env: proposer_factory,
+		client: client.clone(),
+		pool: transaction_pool.clone(),
+		commands_stream,
+		select_chain,
+		consensus_data_provider: Some(Box::new(AuraConsensusDataProvider::new(client.clone()))),
+		create_inherent_data_providers: move |_, ()| async move {
+			let timestamp = next_timestamp(slot_duration);
+
+			let slot =
+				sp_consensus_aura::inherents::InherentDataProvider::from_timestamp_and_slot_duration(
+					*timestamp,
+					slot_duration,
+				);
+
+			Ok((slot, timestamp))
+		},
+	});
+
+	// the authoring task is considered essential, i.e. if it
+	// fails we take down the service with it.
+	task_manager.spawn_essential_handle().spawn_blocking(
+		"manual-seal",
+		Some("block-authoring"),
+		manual_seal,
+	);
+
+	network_starter.start_network();
+
+	Ok(ManualSealNode {
+		task_manager,
+		client,
+		transaction_pool,
+		event_proofs,
+		seal_commands,
+		prometheus_registry,
+		streams_shutdown,
+	})
+}
+
+/// Timestamp of the next block sealed by any manual-seal node in the process. Shared between all
+/// nodes so that blocks sealed by different nodes on top of each other still have increasing slots.
+static NEXT_TIMESTAMP: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a timestamp inherent one slot after the previous one, so that blocks can be sealed
+/// faster than the runtime's slot duration would otherwise allow.
+fn next_timestamp(slot_duration: SlotDuration) -> sp_timestamp::InherentDataProvider {
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+	let _ = NEXT_TIMESTAMP.compare_exchange(0, now, Ordering::SeqCst, Ordering::SeqCst);
+	let timestamp = NEXT_TIMESTAMP.fetch_add(slot_duration.as_millis(), Ordering::SeqCst);
+	sp_timestamp::InherentDataProvider::new(timestamp.into())
+}
@@ -1,10 +1,16 @@
 //! Service and ServiceFactory implementation. Specialized wrapper over substrate service.
+use crate::chain_spec;
 #[cfg(feature = "off-chain-proofs")]
 use consensus_validated_streams::ValidatedStreamsBlockImport;
 use consensus_validated_streams::{
-	proofs::OffchainStorageEventProofs, BlockStateCache, ValidatedStreamsNetworkConfiguration,
+	gossip::{self, GossipBackend},
+	index::OffchainStorageEventIndex, payloads::OffchainStorageEventPayloads,
+	shutdown::StreamsShutdown, ValidatedStreamsNetworkConfiguration, ValidatorSetHandle,
 };
-use lru::LruCache;
+#[cfg(not(feature = "rocksdb-proofs"))]
+use consensus_validated_streams::proofs::OffchainStorageEventProofs;
+#[cfg(feature = "rocksdb-proofs")]
+use consensus_validated_streams::proofs::RocksDbEventProofs;
 use sc_client_api::{Backend, BlockBackend};
 use sc_consensus_aura::{ImportQueueParams, SlotProportion, StartAuraParams};
 use sc_consensus_grandpa::SharedVoterState;
@@ -22,12 +28,19 @@ use sp_consensus_aura::sr25519::AuthorityPair as AuraPair;
 
 use std::{
 	num::NonZeroUsize,
-	sync::{Arc, Mutex},
+	sync::Arc,
 	time::Duration,
 };
 use vstreams_node_runtime::{self, opaque::Block, RuntimeApi};
 type FullBackend = sc_service::TFullBackend<Block>;
 type FullSelectChain = sc_consensus::LongestChain<FullBackend, Block>;
+/// The event proofs of the node, kept in a database of their own with the `rocksdb-proofs`
+/// feature, and in the offchain storage of the node otherwise; either way, on disk.
+#[cfg(feature = "rocksdb-proofs")]
+pub type FullEventProofs = RocksDbEventProofs;
+#[cfg(not(feature = "rocksdb-proofs"))]
+pub type FullEventProofs =
+	OffchainStorageEventProofs<<FullBackend as Backend<Block>>::OffchainStorage>;
 
 /// Our native executor instance.
 pub struct ExecutorDispatch;
@@ -50,7 +63,7 @@ impl sc_executor::NativeExecutionDispatch for ExecutorDispatch {
 }
 
 //LRU cache capacity
-const CACHE_CAPACITY: usize = 4;
+pub(crate) const CACHE_CAPACITY: usize = 4;
 
 /// The TFullClient type
 pub type FullClient = TFullClient<Block, RuntimeApi, NativeElseWasmExecutor<ExecutorDispatch>>;
@@ -69,8 +82,8 @@ type FullPartialComponentsOther = (
 	sc_consensus_grandpa::GrandpaBlockImport<FullBackend, Block, FullClient, FullSelectChain>,
 	sc_consensus_grandpa::LinkHalf<Block, FullClient, FullSelectChain>,
 	Option<Telemetry>,
-	Arc<OffchainStorageEventProofs<<FullBackend as Backend<Block>>::OffchainStorage>>,
-	BlockStateCache<Block>,
+	Arc<FullEventProofs>,
+	ValidatorSetHandle<Block>,
 );
 
 #[cfg(feature = "off-chain-proofs")]
@@ -79,17 +92,42 @@ type FullPartialComponentsOther = (
 		Block,
 		sc_consensus_grandpa::GrandpaBlockImport<FullBackend, Block, FullClient, FullSelectChain>,
 		FullClient,
-		OffchainStorageEventProofs<<FullBackend as Backend<Block>>::OffchainStorage>,
+		FullEventProofs,
 		SyncingService<Block>,
 		AuraId,
 	>,
 	Box<dyn FnOnce(Arc<SyncingService<Block>>)>,
 	sc_consensus_grandpa::LinkHalf<Block, FullClient, FullSelectChain>,
 	Option<Telemetry>,
-	Arc<OffchainStorageEventProofs<<FullBackend as Backend<Block>>::OffchainStorage>>,
-	BlockStateCache<Block>,
+	Arc<FullEventProofs>,
+	ValidatorSetHandle<Block>,
 );
 
+/// Opens the [FullEventProofs] of the node, in the offchain storage of its backend.
+#[cfg(not(feature = "rocksdb-proofs"))]
+pub(crate) fn new_event_proofs(
+	_config: &Configuration,
+	backend: &FullBackend,
+) -> Result<Arc<FullEventProofs>, ServiceError> {
+	Ok(Arc::new(OffchainStorageEventProofs::new(
+		backend
+			.offchain_storage()
+			.ok_or_else(|| ServiceError::Other("Offchain storage is required.".into()))?,
+	)))
+}
+
+/// Opens the [FullEventProofs] of the node, in a database of their own under its base path.
+#[cfg(feature = "rocksdb-proofs")]
+pub(crate) fn new_event_proofs(
+	config: &Configuration,
+	_backend: &FullBackend,
+) -> Result<Arc<FullEventProofs>, ServiceError> {
+	let path = RocksDbEventProofs::path_under(config.base_path.path());
+	Ok(Arc::new(RocksDbEventProofs::open(&path).map_err(|e| {
+		ServiceError::Other(format!("Failed opening the proofs at {}: {e}", path.display()))
+	})?))
+}
+
 /// Build the services a client is composed of, but don't run it yet
 pub fn new_partial(config: &Configuration) -> Result<FullPartialComponents, ServiceError> {
 	if config.keystore_remote.is_some() {
@@ -144,14 +182,9 @@ pub fn new_partial(config: &Configuration) -> Result<FullPartialComponents, Serv
 		telemetry.as_ref().map(|x| x.handle()),
 	)?;
 
-	let event_proofs = Arc::new(OffchainStorageEventProofs::new(
-		backend
-			.offchain_storage()
-			.ok_or_else(|| ServiceError::Other("Offchain storage is required.".into()))?,
-	));
+	let event_proofs = new_event_proofs(config, &backend)?;
 
-	let block_state =
-		Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(CACHE_CAPACITY).unwrap())));
+	let validator_set = ValidatorSetHandle::new(NonZeroUsize::new(CACHE_CAPACITY).unwrap());
 
 	#[cfg(not(feature = "off-chain-proofs"))]
 	let block_import = grandpa_block_import.clone();
@@ -160,7 +193,7 @@ pub fn new_partial(config: &Configuration) -> Result<FullPartialComponents, Serv
 		grandpa_block_import.clone(),
 		client.clone(),
 		event_proofs.clone(),
-		block_state.clone(),
+		validator_set.clone(),
 	);
 	let slot_duration = sc_consensus_aura::slot_duration(&*client)?;
 
@@ -196,7 +229,7 @@ pub fn new_partial(config: &Configuration) -> Result<FullPartialComponents, Serv
 		select_chain,
 		transaction_pool,
 		#[cfg(not(feature = "off-chain-proofs"))]
-		other: (block_import, grandpa_link, telemetry, event_proofs, block_state),
+		other: (block_import, grandpa_link, telemetry, event_proofs, validator_set),
 		#[cfg(feature = "off-chain-proofs")]
 		other: (
 			block_import,
@@ -204,7 +237,7 @@ pub fn new_partial(config: &Configuration) -> Result<FullPartialComponents, Serv
 			grandpa_link,
 			telemetry,
 			event_proofs,
-			block_state,
+			validator_set,
 		),
 	})
 }
@@ -216,11 +249,12 @@ fn remote_keystore(_url: &str) -> Result<Arc<LocalKeystore>, &'static str> {
 	Err("Remote Keystore not supported.")
 }
 
-/// Builds a new service for a full client.
+/// Builds a new service for a full client. Also returns the shutdown of the streams subsystem, to
+/// run once the node is told to exit.
 pub fn new_full(
 	mut config: Configuration,
 	validated_streams_network_config: ValidatedStreamsNetworkConfiguration,
-) -> Result<TaskManager, ServiceError> {
+) -> Result<(TaskManager, StreamsShutdown), ServiceError> {
 	let sc_service::PartialComponents {
 		client,
 		backend,
@@ -230,22 +264,52 @@ pub fn new_full(
 		select_chain,
 		transaction_pool,
 		#[cfg(not(feature = "off-chain-proofs"))]
-			other: (block_import, grandpa_link, mut telemetry, event_proofs, block_state),
+			other: (block_import, grandpa_link, mut telemetry, event_proofs, validator_set),
 		#[cfg(feature = "off-chain-proofs")]
-			other:
-			(block_import, provide_sync_service, grandpa_link, mut telemetry, event_proofs, block_state),
+			other: (
+				block_import,
+				provide_sync_service,
+				grandpa_link,
+				mut telemetry,
+				event_proofs,
+				validator_set,
+			),
 	} = new_partial(&config)?;
 
-	consensus_validated_streams::start(consensus_validated_streams::StartParams {
-		spawn_handle: task_manager.spawn_handle(),
-		event_proofs,
-		client: client.clone(),
-		keystore: keystore_container.keystore(),
-		transaction_pool: transaction_pool.clone(),
-		validated_streams_network_config,
-		network_configuration: config.network.clone(),
-		block_state,
-	})?;
+	let event_payloads = Arc::new(OffchainStorageEventPayloads::new(
+		backend
+			.offchain_storage()
+			.ok_or_else(|| ServiceError::Other("Offchain storage is required.".into()))?,
+	));
+	let event_index = Arc::new(OffchainStorageEventIndex::new(
+		backend
+			.offchain_storage()
+			.ok_or_else(|| ServiceError::Other("Offchain storage is required.".into()))?,
+	));
+	let (gossip_network, provide_gossip_network) = gossip::pending_network();
+	if validated_streams_network_config.gossip_backend == GossipBackend::Network {
+		config.network.extra_sets.push(gossip::network::peers_set_config());
+	}
+	let streams_shutdown =
+		consensus_validated_streams::start(consensus_validated_streams::StartParams {
+			spawn_handle: task_manager.spawn_handle(),
+			essential_spawn_handle: task_manager.spawn_essential_handle(),
+			event_proofs,
+			event_payloads,
+			event_index,
+			client: client.clone(),
+			keystore: keystore_container.keystore(),
+			transaction_pool: transaction_pool.clone(),
+			validated_streams_network_config,
+			network_configuration: config.network.clone(),
+			chain_spec_peers: chain_spec::gossip_peers(&*config.chain_spec),
+			gossip_network,
+			validator_set,
+			prometheus_registry: config.prometheus_registry().cloned(),
+			telemetry: telemetry.as_ref().map(|x| x.handle()),
+			role: config.role.clone(),
+			keystore_path: config.keystore.path().map(|path| path.to_path_buf()),
+		})?;
 
 	if let Some(url) = &config.keystore_remote {
 		match remote_keystore(url) {
@@ -284,6 +348,7 @@ pub fn new_full(
 
 	#[cfg(feature = "off-chain-proofs")]
 	provide_sync_service(sync_service.clone());
+	provide_gossip_network(network.clone());
 
 	if config.offchain_worker.enabled {
 		sc_service::build_offchain_workers(
@@ -420,5 +485,5 @@ pub fn new_full(
 	}
 
 	network_starter.start_network();
-	Ok(task_manager)
+	Ok((task_manager, streams_shutdown))
 }
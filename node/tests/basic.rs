@@ -0,0 +1,43 @@
+//! Happy-path scenarios of the basic sample, run on an in-process network.
+
+mod harness;
+
+use harness::Harness;
+use sp_core::H256;
+use std::time::Duration;
+
+const FINALIZATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_event_witnessed_by_all_is_finalized() {
+	let harness = Harness::start(3).await;
+	let event_id = H256::repeat_byte(1);
+
+	for index in 0..harness.len() {
+		harness.submit_event(index, event_id).await.unwrap();
+	}
+
+	assert!(harness.wait_finalized(event_id, FINALIZATION_TIMEOUT).await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_restarted_validator_keeps_witnessing() {
+	let mut harness = Harness::start(3).await;
+	let first_event_id = H256::repeat_byte(1);
+	let second_event_id = H256::repeat_byte(2);
+
+	for index in 0..harness.len() {
+		harness.submit_event(index, first_event_id).await.unwrap();
+	}
+	assert!(harness.wait_finalized(first_event_id, FINALIZATION_TIMEOUT).await);
+
+	harness.kill(2).await;
+	assert!(harness.submit_event(2, second_event_id).await.is_err());
+	harness.restart(2).await;
+
+	for index in 0..harness.len() {
+		harness.submit_event(index, second_event_id).await.unwrap();
+	}
+	assert!(harness.wait_finalized(second_event_id, FINALIZATION_TIMEOUT).await);
+	assert!(harness.is_finalized_on(2, first_event_id));
+}
@@ -0,0 +1,24 @@
+//! The Validated Streams section of the chain spec.
+
+use libp2p::Multiaddr;
+use sc_telemetry::serde_json::{self, json, Value};
+use vstreams_node::{
+	chain_spec::{gossip_peers, ChainSpec},
+	manual_seal,
+};
+
+#[test]
+fn test_gossip_peers_read_from_the_chain_spec() {
+	let spec = manual_seal::testnet_config(vec!["Alice".to_string()]).unwrap();
+	assert!(gossip_peers(&spec).is_empty());
+
+	let peer: Multiaddr = "/ip4/10.0.0.1/tcp/10000".parse().unwrap();
+	let mut json: Value = serde_json::from_str(&spec.as_json(false).unwrap()).unwrap();
+	json["validatedStreams"] = json!({ "peers": [peer.to_string()] });
+	let spec = ChainSpec::from_json_bytes(json.to_string().into_bytes()).unwrap();
+	assert_eq!(gossip_peers(&spec), vec![peer]);
+
+	// Specs carrying anything else in the section are rejected
+	json["validatedStreams"] = json!({ "bootnodes": [] });
+	assert!(ChainSpec::from_json_bytes(json.to_string().into_bytes()).is_err());
+}
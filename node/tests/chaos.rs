@@ -0,0 +1,107 @@
+//! A validator crashing and restarting while events are being witnessed, at randomized (but
+//! seeded) points. Set `CHAOS_SEED` to rerun a single seed reported by a failure.
+
+mod harness;
+
+use harness::Harness;
+use sp_core::H256;
+use std::{collections::HashSet, time::Duration};
+
+const FINALIZATION_TIMEOUT: Duration = Duration::from_secs(120);
+const SEEDS: [u64; 3] = [1, 2, 3];
+const EVENTS: usize = 5;
+
+/// A small, seedable pseudo-random number generator (SplitMix64).
+struct Rng(u64);
+
+impl Rng {
+	fn next(&mut self) -> u64 {
+		self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+		z ^ (z >> 31)
+	}
+
+	fn below(&mut self, bound: u64) -> u64 {
+		self.next() % bound
+	}
+}
+
+/// Prints the seed of the scenario if it panics.
+struct ReportSeed(u64);
+
+impl Drop for ReportSeed {
+	fn drop(&mut self) {
+		if std::thread::panicking() {
+			eprintln!("chaos scenario failed; rerun with CHAOS_SEED={}", self.0);
+		}
+	}
+}
+
+async fn submit_to(harness: &Harness, indices: impl IntoIterator<Item = usize>, event_id: H256) {
+	for index in indices {
+		harness.submit_event(index, event_id).await.unwrap();
+	}
+}
+
+/// Witness a batch of events on three validators, killing one of them (never the sealer) right
+/// after it witnesses one of the events, and restarting it after a while. The trusted clients
+/// resubmit all events once the validator is back, like they would after any outage.
+async fn run_scenario(seed: u64) {
+	let _report = ReportSeed(seed);
+	let mut rng = Rng(seed);
+	let mut harness = Harness::start(3).await;
+	let victim = 1 + rng.below(2) as usize;
+	let others: Vec<usize> = (0..harness.len()).filter(|&index| index != victim).collect();
+	let crash_at = rng.below(EVENTS as u64) as usize;
+	let events: Vec<H256> =
+		(0..EVENTS).map(|i| H256::from_low_u64_be(seed << 8 | i as u64)).collect();
+
+	for (i, &event_id) in events.iter().enumerate() {
+		if i < crash_at {
+			submit_to(&harness, 0..harness.len(), event_id).await;
+		} else if i == crash_at {
+			submit_to(&harness, [victim], event_id).await;
+			tokio::time::sleep(Duration::from_millis(rng.below(500))).await;
+			harness.kill(victim).await;
+			submit_to(&harness, others.iter().copied(), event_id).await;
+		} else {
+			submit_to(&harness, others.iter().copied(), event_id).await;
+		}
+		if i % 2 == 0 {
+			harness.seal_block(true).await;
+		}
+	}
+
+	tokio::time::sleep(Duration::from_millis(rng.below(2000))).await;
+	harness.restart(victim).await;
+	for &event_id in &events {
+		submit_to(&harness, 0..harness.len(), event_id).await;
+	}
+
+	for &event_id in &events {
+		assert!(harness.wait_finalized(event_id, FINALIZATION_TIMEOUT).await, "{event_id:?}");
+	}
+
+	for index in 0..harness.len() {
+		let finalized = harness.finalized_events(index);
+		let unique: HashSet<_> = finalized.iter().collect();
+		assert_eq!(unique.len(), finalized.len(), "duplicate events on node {index}");
+		assert_eq!(unique, events.iter().collect::<HashSet<_>>(), "events of node {index}");
+		for &event_id in &events {
+			assert_eq!(harness.proof_count(index, event_id), harness.len() as u16);
+		}
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_validator_crash_mid_quorum() {
+	match std::env::var("CHAOS_SEED") {
+		Ok(seed) => run_scenario(seed.parse().expect("CHAOS_SEED is a number")).await,
+		Err(_) =>
+			for seed in SEEDS {
+				run_scenario(seed).await;
+			},
+	}
+}
@@ -0,0 +1,213 @@
+//! The gRPC interface of a node, exercised through a client generated straight from the proto file
+//! (see `build.rs`) instead of the one exported by the consensus crate. Incompatible changes to the
+//! proto file or to the status codes the server answers with should make these tests fail.
+
+mod harness;
+
+/// The client generated from `proto/streams.proto`.
+mod proto {
+	tonic::include_proto!("validated_streams.v1");
+}
+
+use harness::Harness;
+use proto::{
+	get_event_status_response::Status as EventStatus, streams_client::StreamsClient,
+	GetEventProofRequest, GetEventStatusRequest, IndexedEvent, ListValidatedEventsRequest,
+	ValidatedEvent, ValidatedEventsRequest, WitnessEventRequest,
+};
+use sp_core::H256;
+use std::time::{Duration, Instant};
+use tonic::{metadata::MetadataValue, transport::Channel, Code, Request};
+
+const FINALIZATION_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the node is given to index the finalized blocks.
+const INDEXING_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the node is given to track the finalization of an event.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(30);
+
+async fn connect(harness: &Harness, index: usize) -> StreamsClient<Channel> {
+	StreamsClient::connect(format!("http://{}", harness.node(index).grpc_addr())).await.unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_witness_event_rejects_malformed_ids() {
+	let harness = Harness::start(1).await;
+	let mut client = connect(&harness, 0).await;
+
+	for event_id in [vec![], vec![1; 31], vec![1; 33]] {
+		let request = WitnessEventRequest { event_id, ..Default::default() };
+		let status = client.witness_event(request).await.unwrap_err();
+		assert_eq!(status.code(), Code::InvalidArgument);
+		assert!(status.message().contains("32 bytes"), "unexpected message: {}", status.message());
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_witnessed_event_is_streamed_once_finalized() {
+	let harness = Harness::start(3).await;
+	let event_id = H256::repeat_byte(1);
+
+	// Subscribe before the event exists, from the first block on
+	let mut events = connect(&harness, 1)
+		.await
+		.validated_events(ValidatedEventsRequest { from_block: 1, from_latest: false })
+		.await
+		.unwrap()
+		.into_inner();
+
+	for index in 0..harness.len() {
+		// Unknown metadata, such as that added by proxies, is ignored
+		let request = WitnessEventRequest { event_id: event_id.0.to_vec(), ..Default::default() };
+		let mut request = Request::new(request);
+		request.metadata_mut().insert("x-request-id", MetadataValue::from_static("e2e"));
+		let mut client = connect(&harness, index).await;
+		let response = client.witness_event(request).await.unwrap().into_inner();
+		assert!(!response.already_witnessed);
+		if index > 0 {
+			continue
+		}
+
+		// Witnessed by the first node alone so far, which holds its own witness
+		let request = GetEventStatusRequest { event_id: event_id.0.to_vec() };
+		let status = client.get_event_status(request).await.unwrap().into_inner();
+		assert_eq!(status.status(), EventStatus::WitnessedBySelf);
+		assert!(status.block_hash.is_empty());
+		let request = GetEventProofRequest { event_id: event_id.0.to_vec() };
+		let proof = client.get_event_proof(request).await.unwrap().into_inner();
+		assert_eq!(proof.validators.len(), harness.len());
+		assert!(proof.target as usize <= harness.len());
+		assert_eq!(proof.signatures.len(), response.proof_count as usize);
+		for signature in &proof.signatures {
+			assert!(proof.validators.contains(&signature.public_key));
+			assert_eq!(signature.signature.len(), 64);
+			assert_eq!(signature.session, proof.session);
+		}
+	}
+	assert!(harness.wait_finalized(event_id, FINALIZATION_TIMEOUT).await);
+
+	// Tracked as finalized, in the block the chain has it in
+	let mut client = connect(&harness, 0).await;
+	let deadline = Instant::now() + STATUS_TIMEOUT;
+	let status = loop {
+		let request = GetEventStatusRequest { event_id: event_id.0.to_vec() };
+		let status = client.get_event_status(request).await.unwrap().into_inner();
+		if status.status() == EventStatus::Finalized {
+			break status
+		}
+		assert!(Instant::now() < deadline, "not tracked as finalized: {status:?}");
+		tokio::time::sleep(Duration::from_millis(100)).await;
+	};
+	let block_hash = H256::from_slice(&status.block_hash);
+	assert_eq!(harness.block_number(0, block_hash), status.block_number);
+	assert!(harness.block_events(0, block_hash).contains(&event_id));
+	// Queries about malformed ids are refused like submissions
+	let status = client.get_event_status(GetEventStatusRequest { event_id: vec![1; 31] }).await;
+	assert_eq!(status.unwrap_err().code(), Code::InvalidArgument);
+	let proof = client.get_event_proof(GetEventProofRequest { event_id: vec![1; 31] }).await;
+	assert_eq!(proof.unwrap_err().code(), Code::InvalidArgument);
+	// Submitting it again does not witness it again
+	let request = WitnessEventRequest { event_id: event_id.0.to_vec(), ..Default::default() };
+	let response = connect(&harness, 0).await.witness_event(request).await.unwrap();
+	assert!(response.into_inner().already_witnessed);
+
+	let expected = ValidatedEvent { event_id: event_id.0.to_vec() };
+	let mut next_block = 1;
+	loop {
+		let response = events.message().await.unwrap().expect("stream is open");
+		next_block += 1;
+		assert_eq!(response.next_block, next_block);
+		if !response.events.is_empty() {
+			assert_eq!(response.events, vec![expected]);
+			break
+		}
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_requests_without_the_api_key_are_refused() {
+	let harness = Harness::start_with_args(1, &["--grpc-api-key", "e2e-key"]).await;
+	let mut client = connect(&harness, 0).await;
+	let request = |authorization: Option<&'static str>| {
+		let request = WitnessEventRequest { event_id: vec![1; 32], ..Default::default() };
+		let mut request = Request::new(request);
+		if let Some(authorization) = authorization {
+			let value = MetadataValue::from_static(authorization);
+			request.metadata_mut().insert("authorization", value);
+		}
+		request
+	};
+
+	for authorization in [None, Some("Bearer other-key"), Some("e2e-key")] {
+		let status = client.witness_event(request(authorization)).await.unwrap_err();
+		assert_eq!(status.code(), Code::Unauthenticated, "{authorization:?}");
+	}
+	let response = client.witness_event(request(Some("Bearer e2e-key"))).await.unwrap();
+	assert!(!response.into_inner().already_witnessed);
+}
+
+fn list_request(from_block: u32, to_block: u32, page_size: u32) -> ListValidatedEventsRequest {
+	ListValidatedEventsRequest { from_block, to_block, page_size, page_token: vec![] }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_list_validated_events_pages_through_finalized_blocks() {
+	let harness = Harness::start(1).await;
+	let mut client = connect(&harness, 0).await;
+
+	// An event in every other block, the others empty
+	let mut validated = vec![];
+	for round in 1..=6u8 {
+		let event_id = H256::repeat_byte(round);
+		harness.submit_event(0, event_id).await.unwrap();
+		assert!(harness.wait_ready_transactions(0, 1).await);
+		let block = harness.seal_block(true).await;
+		assert_eq!(harness.block_events(0, block), vec![event_id]);
+		validated.push((harness.block_number(0, block), event_id));
+		harness.seal_block(true).await;
+	}
+	let last = harness.block_number(0, harness.best_hash(0));
+
+	// Listed once indexed
+	let deadline = Instant::now() + INDEXING_TIMEOUT;
+	let first_page = loop {
+		match client.list_validated_events(list_request(1, last, 4)).await {
+			Ok(response) => break response.into_inner(),
+			Err(status) if status.code() == Code::OutOfRange && Instant::now() < deadline =>
+				tokio::time::sleep(Duration::from_millis(100)).await,
+			Err(status) => panic!("listing failed: {status:?}"),
+		}
+	};
+
+	let mut events: Vec<IndexedEvent> = first_page.events;
+	let (mut token, mut pages) = (first_page.next_page_token, 1);
+	while !token.is_empty() {
+		let request = ListValidatedEventsRequest { page_token: token, ..list_request(1, last, 4) };
+		let page = client.list_validated_events(request).await.unwrap().into_inner();
+		assert!(page.events.len() <= 4);
+		events.extend(page.events);
+		token = page.next_page_token;
+		pages += 1;
+	}
+	assert_eq!(pages, 2);
+	let listed: Vec<_> = events
+		.iter()
+		.map(|event| (event.block_number, H256::from_slice(&event.event_id)))
+		.collect();
+	assert_eq!(listed, validated);
+	let positions: Vec<_> =
+		events.iter().map(|event| (event.block_number, event.extrinsic_index)).collect();
+	let mut sorted = positions.clone();
+	sorted.sort();
+	sorted.dedup();
+	assert_eq!(positions, sorted);
+
+	// Ranges not wholly indexed fail, stating what is
+	let status = client.list_validated_events(list_request(1, last + 10, 4)).await.unwrap_err();
+	assert_eq!(status.code(), Code::OutOfRange);
+	assert!(status.message().contains(&format!("0 to {last}")), "{}", status.message());
+	let status = client.list_validated_events(list_request(5, 1, 4)).await.unwrap_err();
+	assert_eq!(status.code(), Code::InvalidArgument);
+	let request = ListValidatedEventsRequest { page_token: vec![1; 3], ..list_request(1, last, 4) };
+	let status = client.list_validated_events(request).await.unwrap_err();
+	assert_eq!(status.code(), Code::InvalidArgument);
+}
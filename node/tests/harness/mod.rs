@@ -0,0 +1,497 @@
+//! An in-process network of manual-seal validators, for integration tests which need several full
+//! nodes gossiping with each other.
+//!
+//! Every node gets its own temporary base path and loopback ports, and all nodes are wired to each
+//! other as (gossip) bootnodes. Block production is manual: blocks are sealed on the "sealer" node
+//! (node 0 unless changed), and finality is then applied to the rest of the nodes once they import
+//! the block. Blocks can also be sealed on top of any earlier block, so as to create forks, and
+//! finalized later on, so as to pick the winning branch.
+#![allow(dead_code)]
+
+use consensus_validated_streams::{
+	proofs::EventProofsTrait,
+	shutdown::SHUTDOWN_DEADLINE,
+	server::validated_streams_proto::{streams_client::StreamsClient, WitnessEventRequest},
+	test_utils::TestValidators,
+	ValidatedStreamsNetworkParams,
+};
+use futures::{channel::oneshot, SinkExt};
+use libp2p::{identity, PeerId};
+use sc_cli::{ChainSpec, CliConfiguration, RuntimeVersion, SubstrateCli};
+use sc_client_api::{BlockBackend, Finalizer, HeaderBackend};
+use sc_consensus_manual_seal::EngineCommand;
+use sc_transaction_pool_api::TransactionPool;
+use sp_api::ProvideRuntimeApi;
+use sp_core::H256;
+use std::{
+	net::{SocketAddr, TcpListener},
+	path::Path,
+	time::{Duration, Instant},
+};
+use tempfile::TempDir;
+use tonic::transport::Channel;
+use vstreams_node::manual_seal::{self, ManualSealNode};
+use vstreams_node_runtime::pallet_validated_streams::ValidatedStreamsApi;
+
+/// How long to wait for a starting node to open its gRPC port.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Delay between successive polls while waiting for something to happen.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The command-line arguments of a harness node. Mirrors the RunCmd of the node binary.
+#[derive(Debug, clap::Parser)]
+#[group(skip)]
+struct NodeCmd {
+	#[clap(flatten)]
+	base: sc_cli::RunCmd,
+	#[clap(flatten)]
+	validated_streams_params: ValidatedStreamsNetworkParams,
+}
+
+/// A [SubstrateCli] which always loads a chain spec with the harness's validators.
+struct HarnessCli {
+	validators: usize,
+}
+
+impl SubstrateCli for HarnessCli {
+	fn impl_name() -> String {
+		"Validated Streams Test Harness".into()
+	}
+
+	fn impl_version() -> String {
+		env!("CARGO_PKG_VERSION").into()
+	}
+
+	fn description() -> String {
+		env!("CARGO_PKG_DESCRIPTION").into()
+	}
+
+	fn author() -> String {
+		env!("CARGO_PKG_AUTHORS").into()
+	}
+
+	fn support_url() -> String {
+		"support.anonymous.an".into()
+	}
+
+	fn copyright_start_year() -> i32 {
+		2017
+	}
+
+	fn load_spec(&self, _id: &str) -> Result<Box<dyn ChainSpec>, String> {
+		Ok(Box::new(manual_seal::testnet_config(
+			(0..self.validators).map(validator_seed).collect(),
+		)?))
+	}
+
+	fn native_runtime_version(_: &Box<dyn ChainSpec>) -> &'static RuntimeVersion {
+		&vstreams_node_runtime::VERSION
+	}
+}
+
+/// The seed validator keys are derived from. Matches the keys of the `TestValidators` fixture.
+fn validator_seed(index: usize) -> String {
+	format!("Validator{index}")
+}
+
+/// Returns a currently-unused port on the loopback interface.
+fn free_port() -> u16 {
+	TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// Everything needed to (re)start a node.
+struct NodeSpec {
+	index: usize,
+	base_path: TempDir,
+	node_key: [u8; 32],
+	p2p_port: u16,
+	gossip_port: u16,
+	grpc_addr: SocketAddr,
+	prometheus_port: u16,
+	/// Passed to the node on top of the harness's own arguments
+	extra_args: Vec<String>,
+}
+
+impl NodeSpec {
+	fn new(index: usize) -> Self {
+		Self {
+			index,
+			base_path: TempDir::new().unwrap(),
+			node_key: [index as u8 + 1; 32],
+			p2p_port: free_port(),
+			gossip_port: free_port(),
+			grpc_addr: SocketAddr::from(([127, 0, 0, 1], free_port())),
+			prometheus_port: free_port(),
+			extra_args: Vec::new(),
+		}
+	}
+
+	fn peer_id(&self) -> PeerId {
+		let secret = identity::ed25519::SecretKey::from_bytes(self.node_key).unwrap();
+		identity::Keypair::Ed25519(secret.into()).public().to_peer_id()
+	}
+
+	fn node_key_hex(&self) -> String {
+		self.node_key.iter().map(|b| format!("{b:02x}")).collect()
+	}
+
+	fn p2p_addr(&self) -> String {
+		format!("/ip4/127.0.0.1/tcp/{}/p2p/{}", self.p2p_port, self.peer_id())
+	}
+
+	fn gossip_addr(&self) -> String {
+		format!("/ip4/127.0.0.1/tcp/{}", self.gossip_port)
+	}
+
+	fn args(&self, peers: &[&NodeSpec]) -> Vec<String> {
+		let base_path: &Path = self.base_path.path();
+		let mut args = vec![
+			"vstreams-node".to_string(),
+			"--validator".to_string(),
+			"--base-path".to_string(),
+			base_path.display().to_string(),
+			"--listen-addr".to_string(),
+			format!("/ip4/127.0.0.1/tcp/{}", self.p2p_port),
+			"--node-key".to_string(),
+			self.node_key_hex(),
+			"--no-mdns".to_string(),
+			"--prometheus-port".to_string(),
+			self.prometheus_port.to_string(),
+			"--no-telemetry".to_string(),
+			"--grpc-addr".to_string(),
+			self.grpc_addr.to_string(),
+			"--gossip-port".to_string(),
+			self.gossip_port.to_string(),
+		];
+		for peer in peers {
+			args.extend(["--bootnodes".to_string(), peer.p2p_addr()]);
+			args.extend(["--gossip-bootnodes".to_string(), peer.gossip_addr()]);
+		}
+		args.extend(self.extra_args.iter().cloned());
+		args
+	}
+}
+
+/// A validator managed by the [Harness].
+pub struct TestNode {
+	spec: NodeSpec,
+	running: Option<ManualSealNode>,
+}
+
+impl TestNode {
+	/// Whether the node is currently running.
+	pub fn is_running(&self) -> bool {
+		self.running.is_some()
+	}
+
+	/// The internals of the node. Panics if the node is not running.
+	pub fn internals(&self) -> &ManualSealNode {
+		self.running.as_ref().expect("node is running")
+	}
+
+	/// The address of the node's gRPC server.
+	pub fn grpc_addr(&self) -> SocketAddr {
+		self.spec.grpc_addr
+	}
+
+	/// The address the node's gossip listens on.
+	pub fn gossip_addr(&self) -> String {
+		self.spec.gossip_addr()
+	}
+
+	/// Connect a new gRPC client to the node.
+	pub async fn grpc_client(&self) -> Result<StreamsClient<Channel>, tonic::transport::Error> {
+		StreamsClient::connect(format!("http://{}", self.spec.grpc_addr)).await
+	}
+}
+
+/// An in-process network of validators. See the module documentation.
+pub struct Harness {
+	nodes: Vec<TestNode>,
+	sealer: usize,
+}
+
+impl Harness {
+	/// Start `validators` nodes, each one of them a validator, and wait for their gRPC servers to
+	/// come up.
+	pub async fn start(validators: usize) -> Self {
+		Self::start_with_args(validators, &[]).await
+	}
+
+	/// Like [Harness::start], passing the given extra arguments to every node.
+	pub async fn start_with_args(validators: usize, args: &[&str]) -> Self {
+		let nodes = (0..validators)
+			.map(|index| {
+				let mut spec = NodeSpec::new(index);
+				spec.extra_args = args.iter().map(|arg| arg.to_string()).collect();
+				TestNode { spec, running: None }
+			})
+			.collect();
+		let mut harness = Self { nodes, sealer: 0 };
+		for index in 0..validators {
+			harness.restart(index).await;
+		}
+		harness
+	}
+
+	/// The number of nodes in the harness, running or not.
+	pub fn len(&self) -> usize {
+		self.nodes.len()
+	}
+
+	/// Access a node.
+	pub fn node(&self, index: usize) -> &TestNode {
+		&self.nodes[index]
+	}
+
+	/// Change on which node [Harness::seal_block] seals blocks.
+	pub fn set_sealer(&mut self, index: usize) {
+		self.sealer = index;
+	}
+
+	/// Submit an event to a node through its gRPC interface.
+	pub async fn submit_event(&self, index: usize, event_id: H256) -> Result<(), tonic::Status> {
+		let mut client = self.nodes[index]
+			.grpc_client()
+			.await
+			.map_err(|e| tonic::Status::unavailable(e.to_string()))?;
+		let event_id = event_id.as_bytes().to_vec();
+		client.witness_event(WitnessEventRequest { event_id, ..Default::default() }).await?;
+		Ok(())
+	}
+
+	/// Seal a block on top of the best block of the sealer node. When `finalize` is set, the block
+	/// is also finalized on every running node once they import it.
+	pub async fn seal_block(&self, finalize: bool) -> H256 {
+		self.seal_on(None, finalize).await
+	}
+
+	/// Seal a block on the sealer node, on top of the given parent (or the best block). Sealing on
+	/// top of a block which already has children forks the chain; nodes switch to the new branch
+	/// once it becomes the longest one.
+	pub async fn seal_on(&self, parent_hash: Option<H256>, finalize: bool) -> H256 {
+		let sealer = self.nodes[self.sealer].internals();
+		let (sender, receiver) = oneshot::channel();
+		sealer
+			.seal_commands
+			.clone()
+			.send(EngineCommand::SealNewBlock {
+				create_empty: true,
+				finalize,
+				parent_hash,
+				sender: Some(sender),
+			})
+			.await
+			.expect("manual seal task is running");
+		let hash = receiver.await.unwrap().expect("block sealed").hash;
+
+		if finalize {
+			self.finalize_elsewhere(hash).await;
+		}
+		hash
+	}
+
+	/// Seal a branch of `length` unfinalized blocks on top of `parent_hash`. Returns the hashes of
+	/// the new blocks, in order.
+	pub async fn seal_branch(&self, parent_hash: H256, length: usize) -> Vec<H256> {
+		let mut branch: Vec<H256> = Vec::with_capacity(length);
+		for _ in 0..length {
+			let parent_hash = branch.last().copied().unwrap_or(parent_hash);
+			branch.push(self.seal_on(Some(parent_hash), false).await);
+		}
+		branch
+	}
+
+	/// Finalize an already-sealed block (and with it, the branch it is on) on every running node.
+	pub async fn finalize(&self, hash: H256) {
+		let (sender, receiver) = oneshot::channel();
+		self.nodes[self.sealer]
+			.internals()
+			.seal_commands
+			.clone()
+			.send(EngineCommand::FinalizeBlock { hash, sender: Some(sender), justification: None })
+			.await
+			.expect("manual seal task is running");
+		receiver.await.unwrap().expect("block finalized");
+
+		self.finalize_elsewhere(hash).await;
+	}
+
+	/// Finalize a block sealed on the sealer node on all other running nodes.
+	async fn finalize_elsewhere(&self, hash: H256) {
+		for (index, node) in self.nodes.iter().enumerate() {
+			if index != self.sealer && node.is_running() {
+				self.finalize_on(index, hash).await;
+			}
+		}
+	}
+
+	/// Wait for a node to import a block, and finalize it there.
+	async fn finalize_on(&self, index: usize, hash: H256) {
+		if !self.wait_imported(index, hash).await {
+			eprintln!("node {index} did not import {hash:?}; not finalizing it there");
+			return
+		}
+		let client = &self.nodes[index].internals().client;
+		if let Err(e) = client.finalize_block(hash, None, true) {
+			eprintln!("could not finalize {hash:?} on node {index}: {e:?}");
+		}
+	}
+
+	/// Wait for a node to import a block. Returns false if that doesn't happen within the timeout.
+	pub async fn wait_imported(&self, index: usize, hash: H256) -> bool {
+		let client = &self.nodes[index].internals().client;
+		let deadline = Instant::now() + STARTUP_TIMEOUT;
+		while client.header(hash).ok().flatten().is_none() {
+			if Instant::now() > deadline {
+				return false
+			}
+			tokio::time::sleep(POLL_INTERVAL).await;
+		}
+		true
+	}
+
+	/// Wait until the transaction pool of a node holds exactly `count` ready transactions. Returns
+	/// false if that doesn't happen within the timeout.
+	pub async fn wait_ready_transactions(&self, index: usize, count: usize) -> bool {
+		let pool = &self.nodes[index].internals().transaction_pool;
+		let deadline = Instant::now() + STARTUP_TIMEOUT;
+		while pool.status().ready != count {
+			if Instant::now() > deadline {
+				return false
+			}
+			tokio::time::sleep(POLL_INTERVAL).await;
+		}
+		true
+	}
+
+	/// The number of a block imported by a node.
+	pub fn block_number(&self, index: usize, hash: H256) -> u32 {
+		let client = &self.nodes[index].internals().client;
+		client.header(hash).ok().flatten().expect("block is imported").number
+	}
+
+	/// The best block of a node.
+	pub fn best_hash(&self, index: usize) -> H256 {
+		self.nodes[index].internals().client.info().best_hash
+	}
+
+	/// The events included in a block imported by a node.
+	pub fn block_events(&self, index: usize, hash: H256) -> Vec<H256> {
+		let client = &self.nodes[index].internals().client;
+		let body = client.block_body(hash).ok().flatten().unwrap_or_default();
+		client.runtime_api().get_extrinsic_ids(hash, &body).unwrap_or_default()
+	}
+
+	/// The number of proofs a node holds for the event, from any of the harness's validators.
+	pub fn proof_count(&self, index: usize, event_id: H256) -> u16 {
+		let validators = TestValidators::new(self.nodes.len());
+		let event_proofs = &self.nodes[index].internals().event_proofs;
+		event_proofs.get_event_proof_count(&event_id, &validators.pubkeys()).unwrap()
+	}
+
+	/// Whether the event is part of the finalized chain of the given node.
+	pub fn is_finalized_on(&self, index: usize, event_id: H256) -> bool {
+		let client = &self.nodes[index].internals().client;
+		let finalized_number = client.info().finalized_number;
+		(1..=finalized_number).any(|number| {
+			let Some(hash) = client.block_hash(number).ok().flatten() else { return false };
+			self.block_events(index, hash).contains(&event_id)
+		})
+	}
+
+	/// All events in the finalized chain of the given node, in order of inclusion.
+	pub fn finalized_events(&self, index: usize) -> Vec<H256> {
+		let client = &self.nodes[index].internals().client;
+		let finalized_number = client.info().finalized_number;
+		(1..=finalized_number)
+			.filter_map(|number| client.block_hash(number).ok().flatten())
+			.flat_map(|hash| self.block_events(index, hash))
+			.collect()
+	}
+
+	/// Keep sealing and finalizing blocks until the event is finalized on every running node.
+	/// Returns false if that doesn't happen within the timeout.
+	pub async fn wait_finalized(&self, event_id: H256, timeout: Duration) -> bool {
+		let deadline = Instant::now() + timeout;
+		loop {
+			let finalized_everywhere = (0..self.nodes.len())
+				.filter(|&index| self.nodes[index].is_running())
+				.all(|index| self.is_finalized_on(index, event_id));
+			if finalized_everywhere {
+				return true
+			}
+			if Instant::now() > deadline {
+				return false
+			}
+			self.seal_block(true).await;
+			tokio::time::sleep(POLL_INTERVAL).await;
+		}
+	}
+
+	/// Stop a node, waiting for all of its tasks to finish. Its database is kept for a subsequent
+	/// [Harness::restart].
+	pub async fn kill(&mut self, index: usize) {
+		if let Some(node) = self.nodes[index].running.take() {
+			Self::stop(node, false).await;
+		}
+	}
+
+	/// Stop a node the way the node binary does once told to exit: shut its streams subsystem down
+	/// in order, and then stop all of its tasks. Its database is kept for a subsequent
+	/// [Harness::restart].
+	pub async fn terminate(&mut self, index: usize) {
+		if let Some(node) = self.nodes[index].running.take() {
+			Self::stop(node, true).await;
+		}
+	}
+
+	async fn stop(node: ManualSealNode, graceful: bool) {
+		let ManualSealNode {
+			task_manager,
+			client,
+			transaction_pool,
+			event_proofs,
+			seal_commands,
+			prometheus_registry,
+			streams_shutdown,
+		} = node;
+		if graceful {
+			streams_shutdown.run(SHUTDOWN_DEADLINE).await;
+		} else {
+			drop(streams_shutdown);
+		}
+		drop((client, transaction_pool, event_proofs, seal_commands, prometheus_registry));
+		task_manager.clean_shutdown().await;
+	}
+
+	/// (Re)start a node with the same keys, ports, and base path, and wait for its gRPC server to
+	/// come up.
+	pub async fn restart(&mut self, index: usize) {
+		self.kill(index).await;
+
+		let cli = HarnessCli { validators: self.nodes.len() };
+		let peers: Vec<_> =
+			self.nodes.iter().filter(|n| n.spec.index != index).map(|n| &n.spec).collect();
+		let cmd = <NodeCmd as clap::Parser>::parse_from(self.nodes[index].spec.args(&peers));
+
+		let mut config = cmd
+			.base
+			.create_configuration(&cli, tokio::runtime::Handle::current())
+			.expect("valid configuration");
+		config.dev_key_seed = Some(format!("//{}", validator_seed(index)));
+		config.rpc_http = None;
+		config.rpc_ws = None;
+		config.rpc_ipc = None;
+
+		let node = manual_seal::new_manual_seal(config, cmd.validated_streams_params)
+			.expect("node starts");
+		self.nodes[index].running = Some(node);
+
+		let deadline = Instant::now() + STARTUP_TIMEOUT;
+		while self.nodes[index].grpc_client().await.is_err() {
+			assert!(Instant::now() < deadline, "gRPC server of node {index} did not start");
+			tokio::time::sleep(POLL_INTERVAL).await;
+		}
+	}
+}
@@ -0,0 +1,58 @@
+//! Health of the gossip mesh as validators drop out and come back.
+
+mod harness;
+
+use consensus_validated_streams::{logging::GOSSIP, test_utils::CapturedLogs};
+use harness::Harness;
+use std::time::{Duration, Instant};
+
+/// How long the mesh is given to notice a validator coming or going.
+const MESH_TIMEOUT: Duration = Duration::from_secs(30);
+const DEGRADED: &str = "Gossip mesh is degraded; events cannot reach quorum";
+const RECOVERED: &str = "Gossip mesh recovered";
+
+/// The value of one of the mesh gauges of a node.
+fn gauge(harness: &Harness, index: usize, name: &str) -> f64 {
+	let registry = harness.node(index).internals().prometheus_registry.as_ref();
+	let families = registry.expect("metrics are enabled").gather();
+	families
+		.iter()
+		.find(|family| family.get_name().ends_with(name))
+		.map(|family| family.get_metric()[0].get_gauge().get_value())
+		.unwrap_or_default()
+}
+
+async fn wait_mesh(harness: &Harness, index: usize, health: f64, degraded: bool) -> bool {
+	let deadline = Instant::now() + MESH_TIMEOUT;
+	while gauge(harness, index, "streams_mesh_health") != health ||
+		gauge(harness, index, "streams_mesh_degraded") != degraded as u8 as f64
+	{
+		if Instant::now() > deadline {
+			return false
+		}
+		tokio::time::sleep(Duration::from_millis(100)).await;
+	}
+	true
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_mesh_degrades_and_recovers() {
+	let logs = CapturedLogs::capture_global();
+	// All three validators are needed to witness an event
+	let mut harness = Harness::start(3).await;
+	assert!(wait_mesh(&harness, 0, 1.0, false).await);
+
+	harness.kill(2).await;
+	assert!(wait_mesh(&harness, 0, 0.5, true).await);
+	let missing = harness.node(2).gossip_addr();
+	let warned = logs
+		.find(GOSSIP, DEGRADED)
+		.iter()
+		.any(|line| line.field("missing").unwrap_or_default().contains(&missing));
+	assert!(warned, "no warning about {missing} missing");
+
+	let recoveries = logs.find(GOSSIP, RECOVERED).len();
+	harness.restart(2).await;
+	assert!(wait_mesh(&harness, 0, 1.0, false).await);
+	assert!(logs.find(GOSSIP, RECOVERED).len() > recoveries);
+}
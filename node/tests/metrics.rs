@@ -0,0 +1,116 @@
+//! Metrics reported by the nodes, read straight from their Prometheus registries.
+
+mod harness;
+
+use harness::Harness;
+use sp_core::H256;
+use std::time::{Duration, Instant};
+
+const FINALIZATION_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the metrics are given to catch up with a finality notification.
+const REPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The samples of a histogram of a node with the given label value, as the total count and the
+/// cumulative count of each bucket by upper bound.
+fn histogram(harness: &Harness, index: usize, name: &str, label: &str) -> (u64, Vec<(f64, u64)>) {
+	let registry = harness.node(index).internals().prometheus_registry.as_ref();
+	let families = registry.expect("metrics are enabled").gather();
+	let histogram = families
+		.iter()
+		.filter(|family| family.get_name().ends_with(name))
+		.flat_map(|family| family.get_metric())
+		.find(|metric| metric.get_label().iter().any(|label_pair| label_pair.get_value() == label))
+		.map(|metric| metric.get_histogram().clone());
+	match histogram {
+		Some(histogram) => (
+			histogram.get_sample_count(),
+			histogram
+				.get_bucket()
+				.iter()
+				.map(|bucket| (bucket.get_upper_bound(), bucket.get_cumulative_count()))
+				.collect(),
+		),
+		None => (0, Vec::new()),
+	}
+}
+
+/// The samples of the end-to-end latency histogram of a node for the given outcome.
+fn event_latency(harness: &Harness, index: usize, outcome: &str) -> (u64, Vec<(f64, u64)>) {
+	histogram(harness, index, "streams_event_latency_seconds", outcome)
+}
+
+/// The samples of the witness delay histogram of a node for the validator with the given index.
+fn witness_delay(harness: &Harness, index: usize, validator: usize) -> (u64, Vec<(f64, u64)>) {
+	histogram(harness, index, "streams_witness_delay_seconds", &validator.to_string())
+}
+
+async fn wait_latency_samples(harness: &Harness, index: usize, count: u64) -> bool {
+	let deadline = Instant::now() + REPORT_TIMEOUT;
+	while event_latency(harness, index, "finalized").0 < count {
+		if Instant::now() > deadline {
+			return false
+		}
+		tokio::time::sleep(Duration::from_millis(100)).await;
+	}
+	true
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_event_latency_observed_at_finality() {
+	let harness = Harness::start(4).await;
+	let submitted = H256::repeat_byte(1);
+	// Reaches node 0 only through gossip; three proofs are enough out of four validators
+	let gossiped = H256::repeat_byte(2);
+
+	let started = Instant::now();
+	for index in 0..harness.len() {
+		harness.submit_event(index, submitted).await.unwrap();
+	}
+	for index in 1..harness.len() {
+		harness.submit_event(index, gossiped).await.unwrap();
+	}
+	assert!(harness.wait_finalized(submitted, FINALIZATION_TIMEOUT).await);
+	assert!(harness.wait_finalized(gossiped, FINALIZATION_TIMEOUT).await);
+	let elapsed = started.elapsed().as_secs_f64();
+
+	assert!(wait_latency_samples(&harness, 1, 2).await);
+	assert!(wait_latency_samples(&harness, 0, 1).await);
+	let (count, buckets) = event_latency(&harness, 0, "finalized");
+	assert_eq!(count, 1, "only events submitted by the local client are timed");
+	for (upper_bound, cumulative_count) in buckets {
+		// The sample is somewhere between zero and the time the whole test took
+		if upper_bound >= elapsed {
+			assert_eq!(cumulative_count, 1, "bucket le={upper_bound}");
+		}
+	}
+	for index in 0..harness.len() {
+		assert_eq!(event_latency(&harness, index, "expired").0, 0);
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_delayed_validator_witness_delay() {
+	let harness = Harness::start(3).await;
+	let event_id = H256::repeat_byte(3);
+	let delay = Duration::from_secs(1);
+
+	harness.submit_event(0, event_id).await.unwrap();
+	harness.submit_event(1, event_id).await.unwrap();
+	tokio::time::sleep(delay).await;
+	harness.submit_event(2, event_id).await.unwrap();
+	assert!(harness.wait_finalized(event_id, FINALIZATION_TIMEOUT).await);
+
+	for validator in 0..harness.len() {
+		let (count, buckets) = witness_delay(&harness, 0, validator);
+		assert_eq!(count, 1, "witnesses of validator {validator}");
+		// Only the delayed validator's witness arrives later than the delay
+		let below_delay = buckets
+			.iter()
+			.filter(|(upper_bound, _)| *upper_bound < delay.as_secs_f64())
+			.map(|(_, cumulative_count)| *cumulative_count)
+			.max()
+			.unwrap_or(0);
+		let expected = if validator == 2 { 0 } else { 1 };
+		assert_eq!(below_delay, expected, "delay of validator {validator}");
+	}
+}
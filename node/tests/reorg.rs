@@ -0,0 +1,97 @@
+//! Scenarios where the chain forks, run on an in-process network.
+
+mod harness;
+
+use consensus_validated_streams::server::validated_streams_proto::{
+	ValidatedEvent, ValidatedEventsRequest,
+};
+use harness::Harness;
+use sp_core::H256;
+use std::time::Duration;
+
+const FINALIZATION_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long an unfinalized block is given to (wrongly) show up in the validated events.
+const UNFINALIZED_WAIT: Duration = Duration::from_secs(3);
+
+/// Get the event witnessed by every node and included in a block right after `fork_point`, then
+/// seal a longer branch without it on top of `fork_point` and finalize that. Returns the block on
+/// the losing branch and the blocks of the winning one.
+async fn reorg_event_away(
+	harness: &Harness,
+	fork_point: H256,
+	event_id: H256,
+) -> (H256, Vec<H256>) {
+	for index in 0..harness.len() {
+		harness.submit_event(index, event_id).await.unwrap();
+	}
+	assert!(harness.wait_ready_transactions(0, 1).await);
+
+	let losing = harness.seal_on(Some(fork_point), false).await;
+	assert_eq!(harness.block_events(0, losing), vec![event_id]);
+	assert!(harness.wait_ready_transactions(0, 0).await);
+
+	let winning = harness.seal_branch(fork_point, 2).await;
+	for hash in &winning {
+		assert_eq!(harness.block_events(0, *hash), Vec::<H256>::new());
+	}
+	harness.finalize(*winning.last().unwrap()).await;
+	assert_eq!(harness.best_hash(0), *winning.last().unwrap());
+
+	(losing, winning)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_event_on_losing_branch_lands_on_winner() {
+	let harness = Harness::start(3).await;
+	let fork_point = harness.seal_block(true).await;
+	let event_id = H256::repeat_byte(1);
+
+	let (losing, _) = reorg_event_away(&harness, fork_point, event_id).await;
+
+	// the retracted extrinsic goes back to the pool and into the next block of the winner
+	assert!(harness.wait_finalized(event_id, FINALIZATION_TIMEOUT).await);
+	for index in 0..harness.len() {
+		assert_ne!(harness.best_hash(index), losing);
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_reorg_keeps_event_proofs() {
+	let harness = Harness::start(3).await;
+	let fork_point = harness.seal_block(true).await;
+	let event_id = H256::repeat_byte(2);
+
+	reorg_event_away(&harness, fork_point, event_id).await;
+
+	for index in 0..harness.len() {
+		assert_eq!(harness.proof_count(index, event_id), harness.len() as u16);
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_validated_events_wait_for_finality() {
+	let harness = Harness::start(3).await;
+	let event_id = H256::repeat_byte(3);
+
+	for index in 0..harness.len() {
+		harness.submit_event(index, event_id).await.unwrap();
+	}
+	assert!(harness.wait_ready_transactions(0, 1).await);
+	let block = harness.seal_block(false).await;
+	assert_eq!(harness.block_events(0, block), vec![event_id]);
+	assert!(harness.wait_imported(1, block).await);
+	let number = harness.block_number(1, block);
+
+	let mut client = harness.node(1).grpc_client().await.unwrap();
+	let mut events = client
+		.validated_events(ValidatedEventsRequest { from_block: number, from_latest: false })
+		.await
+		.unwrap()
+		.into_inner();
+	assert!(tokio::time::timeout(UNFINALIZED_WAIT, events.message()).await.is_err());
+
+	harness.finalize(block).await;
+	let response = events.message().await.unwrap().expect("stream is open");
+	assert_eq!(response.next_block, number + 1);
+	assert_eq!(response.events, vec![ValidatedEvent { event_id: event_id.as_bytes().to_vec() }]);
+}
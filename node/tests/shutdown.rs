@@ -0,0 +1,157 @@
+//! The ordered shutdown of the streams subsystem: a node told to exit in the middle of a burst of
+//! witnessed events keeps every witness it acknowledged to its client, and none of the tasks of
+//! the subsystem outlives the node, nor does the runtime they run on if it is a dedicated one.
+
+mod harness;
+
+use consensus_validated_streams::{
+	executor::STREAMS_THREAD_NAME, gossip::ingress::INGRESS_WORKERS,
+	server::validated_streams_proto::WitnessEventRequest,
+};
+use harness::Harness;
+use sp_core::H256;
+use std::{
+	collections::BTreeMap,
+	time::{Duration, Instant},
+};
+use substrate_prometheus_endpoint::Registry;
+
+/// Far more events than a node witnesses before being terminated.
+const BURST: u64 = 10_000;
+const BURST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the threads of a dedicated runtime get to exit once the node is terminated.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// The tasks of the subsystem, by name.
+const STREAMS_TASKS: [&str; 10] = [
+	"validated-streams-finality-metrics",
+	"validated-streams-gossip",
+	"validated-streams-gossip-ingress",
+	"validated-streams-grpc-server",
+	"validated-streams-inclusion-metrics",
+	"validated-streams-keystore",
+	"validated-streams-mesh-quorum",
+	"validated-streams-role",
+	"validated-streams-telemetry",
+	"validated-streams-watchdog",
+];
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_terminated_node_keeps_acknowledged_witnesses() {
+	let mut harness = Harness::start(2).await;
+	let client = harness.node(0).grpc_client().await.unwrap();
+	let burst = tokio::spawn(async move {
+		let mut acknowledged = Vec::new();
+		for i in 0..BURST {
+			let event_id = H256::from_low_u64_be(i);
+			let event = event_id.as_bytes().to_vec();
+			let request = WitnessEventRequest { event_id: event, ..Default::default() };
+			match client.clone().witness_event(request).await {
+				Ok(_) => acknowledged.push(event_id),
+				// Refused or cut off by the shutdown
+				Err(_) => break,
+			}
+		}
+		acknowledged
+	});
+
+	// Mid-burst, once the node has witnessed a few events
+	let deadline = Instant::now() + BURST_TIMEOUT;
+	while harness.proof_count(0, H256::from_low_u64_be(10)) == 0 {
+		assert!(Instant::now() < deadline, "node did not witness the burst");
+		tokio::time::sleep(Duration::from_millis(10)).await;
+	}
+	harness.terminate(0).await;
+	let acknowledged = burst.await.unwrap();
+	assert!(acknowledged.len() > 10 && acknowledged.len() < BURST as usize);
+
+	// The reopened store holds the node's own proof of every event it acknowledged
+	harness.restart(0).await;
+	let missing: Vec<_> = acknowledged
+		.iter()
+		.filter(|event_id| harness.proof_count(0, **event_id) == 0)
+		.collect();
+	assert!(missing.is_empty(), "{} acknowledged witnesses were lost: {missing:?}", missing.len());
+}
+
+/// How many times each task of the subsystem was spawned and has ended, by name, as counted by
+/// the task metrics of Substrate. Also fails if any of them was spawned as a blocking task.
+fn streams_tasks(registry: &Registry) -> BTreeMap<String, (u64, u64)> {
+	let mut tasks = BTreeMap::<String, (u64, u64)>::new();
+	for family in registry.gather() {
+		let spawned = family.get_name().ends_with("tasks_spawned_total");
+		if !spawned && !family.get_name().ends_with("tasks_ended_total") {
+			continue
+		}
+		for metric in family.get_metric() {
+			let label = |name: &str| {
+				let pair = metric.get_label().iter().find(|pair| pair.get_name() == name);
+				pair.map(|pair| pair.get_value())
+			};
+			if label("task_group") != Some("validated-streams") {
+				continue
+			}
+			let name = label("task_name").expect("tasks are named").to_string();
+			assert_eq!(label("kind"), Some("async"), "{name} is not an async task");
+			let count = metric.get_counter().get_value() as u64;
+			let (spawned_count, ended_count) = tasks.entry(name).or_default();
+			*(if spawned { spawned_count } else { ended_count }) += count;
+		}
+	}
+	tasks
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_streams_tasks_are_managed_and_reaped() {
+	let mut harness = Harness::start(1).await;
+	let registry = harness.node(0).internals().prometheus_registry.clone();
+	let registry = registry.expect("metrics are enabled");
+
+	let tasks = streams_tasks(&registry);
+	let names: Vec<_> = tasks.keys().map(String::as_str).collect();
+	assert_eq!(names, STREAMS_TASKS);
+	// Every task is spawned once, but for the workers of the gossip
+	let once = |(name, (spawned, _)): (&String, &(u64, u64))| {
+		*spawned == if name.ends_with("ingress") { INGRESS_WORKERS as u64 } else { 1 }
+	};
+	assert!(tasks.iter().all(once), "{tasks:?}");
+
+	harness.terminate(0).await;
+	let tasks = streams_tasks(&registry);
+	let running: Vec<_> = tasks.iter().filter(|(_, (spawned, ended))| spawned != ended).collect();
+	assert!(running.is_empty(), "tasks still running after the shutdown: {running:?}");
+}
+
+/// How many threads of the process run a dedicated streams runtime, as told by their names. Linux
+/// truncates these to 15 bytes.
+#[cfg(target_os = "linux")]
+fn streams_threads() -> usize {
+	let prefix = &STREAMS_THREAD_NAME[..STREAMS_THREAD_NAME.len().min(15)];
+	let threads = std::fs::read_dir("/proc/self/task").unwrap().flatten();
+	let comm = |thread: std::fs::DirEntry| std::fs::read_to_string(thread.path().join("comm"));
+	let names = threads.filter_map(|thread| comm(thread).ok());
+	names.filter(|name| name.trim_end().starts_with(prefix)).count()
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_dedicated_runtime_is_shut_down_with_the_node() {
+	let mut harness = Harness::start_with_args(1, &["--streams-runtime-threads", "2"]).await;
+	let registry = harness.node(0).internals().prometheus_registry.clone();
+	let registry = registry.expect("metrics are enabled");
+
+	// The tasks are still managed by the node, while running on their own threads
+	harness.submit_event(0, H256::repeat_byte(1)).await.unwrap();
+	let tasks = streams_tasks(&registry);
+	assert_eq!(tasks.keys().map(String::as_str).collect::<Vec<_>>(), STREAMS_TASKS);
+	assert_eq!(streams_threads(), 2);
+
+	harness.terminate(0).await;
+	let tasks = streams_tasks(&registry);
+	assert!(tasks.values().all(|(spawned, ended)| spawned == ended), "{tasks:?}");
+	// The threads exit on their own once the runtime is shut down
+	let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+	while streams_threads() > 0 {
+		assert!(Instant::now() < deadline, "the streams runtime outlived the node");
+		tokio::time::sleep(Duration::from_millis(10)).await;
+	}
+}
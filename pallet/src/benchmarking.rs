@@ -2,7 +2,11 @@
 use super::*;
 use crate::{Config, Pallet as pallet_validated_streams};
 use frame_benchmarking::{benchmarks, BenchmarkError, Vec};
-use frame_support::{ensure, traits::ConstU32, BoundedBTreeMap, BoundedVec};
+use frame_support::{
+	ensure,
+	traits::{ConstU32, Get},
+	BoundedBTreeMap, BoundedVec,
+};
 use frame_system::{pallet_prelude::*, RawOrigin};
 use sp_core::{
 	crypto::key_types::AURA,
@@ -14,6 +18,8 @@ use sp_runtime::{app_crypto::RuntimePublic, RuntimeAppPublic};
 #[cfg(feature = "off-chain-proofs")]
 benchmarks! {
 	validate_event {
+		// The sessions claimed by the proofs, whose authorities are looked up with them on chain
+		let s in 0 .. T::SessionGraceWindow::get();
 		let event_id = H256::default();
 	}: _(RawOrigin::None, event_id, None)
 	verify {
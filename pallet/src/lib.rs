@@ -6,6 +6,13 @@
 //!
 //! ### Dispatchable Functions
 //! * [validate_event](pallet/struct.Pallet.html#method.validate_event)
+//!
+//! ### Sessions
+//! The pallet starts a new session whenever the authorities change, and keeps the authorities of
+//! the last [`Config::SessionGraceWindow`] sessions. Witnesses are signed over the session they are
+//! made in (see [payload]), and only accepted from the current session and those kept ones, and
+//! from authorities of the session they claim. Whatever session they claim, only the witnesses of
+//! current authorities count towards the target, as they do on the nodes.
 // Re-export pallet items so that they can be accessed from the crate namespace.
 pub use pallet::*;
 #[cfg(test)]
@@ -14,6 +21,8 @@ pub mod tests;
 #[cfg(test)]
 pub mod mock;
 
+pub mod migrations;
+pub mod payload;
 pub mod weights;
 pub use weights::*;
 
@@ -23,6 +32,7 @@ mod benchmarking;
 #[frame_support::pallet]
 pub mod pallet {
 	use super::*;
+	use crate::payload::SessionIndex;
 	use frame_support::{
 		pallet_prelude::{ValidTransaction, *},
 		BoundedBTreeMap, BoundedVec,
@@ -37,9 +47,16 @@ pub mod pallet {
 	use sp_runtime::app_crypto::RuntimePublic;
 	pub use sp_runtime::traits::Extrinsic;
 	use sp_runtime::RuntimeAppPublic;
-	use sp_std::{collections::btree_map::BTreeMap, vec::Vec};
+	use sp_std::{
+		collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+		vec::Vec,
+	};
+
+	/// The version of the storage layout, bumped by each of the [crate::migrations].
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(1);
 
 	#[pallet::pallet]
+	#[pallet::storage_version(STORAGE_VERSION)]
 	pub struct Pallet<T>(_);
 
 	#[pallet::config]
@@ -55,6 +72,11 @@ pub mod pallet {
 		#[pallet::constant]
 		type VSMaxAuthorities: Get<u32>;
 
+		/// How many sessions before the current one witnesses are still accepted from. Witnesses
+		/// of older sessions are rejected as stale.
+		#[pallet::constant]
+		type SessionGraceWindow: Get<u32>;
+
 		fn authorities() -> BoundedVec<Self::VSAuthorityId, Self::VSMaxAuthorities>;
 	}
 	#[pallet::event]
@@ -72,9 +94,23 @@ pub mod pallet {
 		NoProofs,
 		NotEnoughProofs,
 		UnrecognizedAuthority,
+		/// A proof was made in a session past the grace window, or in a future one
+		StaleSession,
 	}
 
-	type ProofsMap<T> = BoundedBTreeMap<Public, Signature, <T as Config>::VSMaxAuthorities>;
+	/// The proofs of an event: the session each authority witnessed it in, and its signature.
+	pub(crate) type ProofsMap<T> =
+		BoundedBTreeMap<Public, (SessionIndex, Signature), <T as Config>::VSMaxAuthorities>;
+
+	/// The current session, increased every time the authorities change.
+	#[pallet::storage]
+	pub type CurrentSession<T: Config> = StorageValue<_, SessionIndex, ValueQuery>;
+
+	/// The authorities of the current session and of the [Config::SessionGraceWindow] sessions
+	/// before it, as they were when each session started.
+	#[pallet::storage]
+	pub(super) type SessionAuthorities<T: Config> =
+		StorageMap<_, Twox64Concat, SessionIndex, BoundedVec<Public, T::VSMaxAuthorities>>;
 
 	#[cfg(feature = "off-chain-proofs")]
 	#[pallet::storage]
@@ -82,7 +118,29 @@ pub mod pallet {
 
 	#[cfg(not(feature = "off-chain-proofs"))]
 	#[pallet::storage]
-	pub(super) type OnStreams<T: Config> = StorageMap<_, Blake2_128Concat, H256, ProofsMap<T>>;
+	pub(crate) type OnStreams<T: Config> = StorageMap<_, Blake2_128Concat, H256, ProofsMap<T>>;
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		/// Starts a new session if the authorities differ from those of the current one, forgetting
+		/// the session which falls out of the grace window.
+		fn on_initialize(_n: BlockNumberFor<T>) -> Weight {
+			let session = CurrentSession::<T>::get();
+			let authorities = BoundedVec::truncate_from(Self::authority_keys());
+			let recorded = SessionAuthorities::<T>::get(session);
+			if recorded.as_ref() == Some(&authorities) {
+				return T::DbWeight::get().reads(3)
+			}
+			// The first block records the authorities of the genesis session
+			let next = if recorded.is_some() { session + 1 } else { session };
+			SessionAuthorities::<T>::insert(next, authorities);
+			CurrentSession::<T>::put(next);
+			if let Some(expired) = next.checked_sub(T::SessionGraceWindow::get() + 1) {
+				SessionAuthorities::<T>::remove(expired);
+			}
+			T::DbWeight::get().reads_writes(3, 3)
+		}
+	}
 
 	#[pallet::call]
 	impl<T: Config> Pallet<T> {
@@ -91,7 +149,7 @@ pub mod pallet {
 		/// If so, it raise an `AlreadyValidated` event.
 		/// If not, it inserts the event into storage and emits a `ValidatedEvent` event.
 		#[pallet::call_index(0)]
-		#[pallet::weight(T::WeightInfo::validate_event())]
+		#[pallet::weight(T::WeightInfo::validate_event(Pallet::<T>::claimed_sessions(proofs)))]
 		pub fn validate_event(
 			origin: OriginFor<T>,
 			event_id: H256,
@@ -120,14 +178,18 @@ pub mod pallet {
 			event_id: H256,
 			event_proofs: Option<ProofsMap<T>>,
 		) -> DispatchResult {
-			let authorities: Vec<Public> = T::authorities()
-				.into_iter()
-				.map(|id| Public::from_h256(H256::from_slice(id.to_raw_vec().as_slice())))
-				.collect();
+			let authorities = Self::authority_keys();
 			if let Some(proofs) = event_proofs {
 				ensure!(!OnStreams::<T>::contains_key(event_id), Error::<T>::AlreadyValidated);
-				for key in proofs.keys() {
-					if !authorities.contains(key) {
+				let current = CurrentSession::<T>::get();
+				let mut signers = BTreeMap::from([(current, authorities.clone())]);
+				for (key, (session, _)) in &proofs {
+					if !signers.contains_key(session) {
+						let session_authorities = Self::session_authorities(*session, current)
+							.ok_or(Error::<T>::StaleSession)?;
+						signers.insert(*session, session_authorities);
+					}
+					if !signers[session].contains(key) {
 						return Err(Error::<T>::UnrecognizedAuthority.into())
 					}
 				}
@@ -135,9 +197,15 @@ pub mod pallet {
 				let total = authorities.len();
 				let target = (total * 2 / 3 + 1) as u16;
 				let mut proof_count = 0;
-				for (key, signature) in &proofs {
-					ensure!(key.verify(&event_id, signature), Error::<T>::InvalidProof);
-					proof_count += 1;
+				for (key, (session, signature)) in &proofs {
+					ensure!(
+						key.verify(&payload::witness_payload(&event_id, *session), signature),
+						Error::<T>::InvalidProof
+					);
+					// Former authorities witnessed it, but are no longer counted
+					if authorities.contains(key) {
+						proof_count += 1;
+					}
 				}
 
 				if proof_count < target {
@@ -152,6 +220,57 @@ pub mod pallet {
 			}
 		}
 	}
+	impl<T: Config> Pallet<T> {
+		/// The keys of the current authorities.
+		fn authority_keys() -> Vec<Public> {
+			T::authorities()
+				.into_iter()
+				.map(|id| Public::from_h256(H256::from_slice(id.to_raw_vec().as_slice())))
+				.collect()
+		}
+
+		/// The authorities of the given session, as long as witnesses made in it are accepted in
+		/// the current one.
+		#[cfg_attr(feature = "off-chain-proofs", allow(dead_code))]
+		fn session_authorities(
+			session: SessionIndex,
+			current: SessionIndex,
+		) -> Option<Vec<Public>> {
+			if session == current {
+				Some(Self::authority_keys())
+			} else if session < current && current - session <= T::SessionGraceWindow::get() {
+				SessionAuthorities::<T>::get(session).map(BoundedVec::into_inner)
+			} else {
+				None
+			}
+		}
+
+		/// How many sessions the proofs claim, at most as many as the authorities are kept of. Each
+		/// of them may take a lookup of its authorities when validating the event.
+		fn claimed_sessions(proofs: &Option<ProofsMap<T>>) -> u32 {
+			let sessions = proofs.iter().flat_map(|proofs| proofs.values().map(|(s, _)| *s));
+			let sessions: BTreeSet<_> = sessions.collect();
+			(sessions.len() as u32).min(T::SessionGraceWindow::get())
+		}
+
+		/// The current session.
+		pub fn current_session() -> SessionIndex {
+			CurrentSession::<T>::get()
+		}
+
+		/// The previous sessions whose witnesses are still accepted, oldest first, with their
+		/// authorities.
+		pub fn previous_sessions() -> Vec<(SessionIndex, Vec<Public>)> {
+			let current = CurrentSession::<T>::get();
+			let oldest = current.saturating_sub(T::SessionGraceWindow::get());
+			(oldest..current)
+				.filter_map(|session| {
+					let authorities = SessionAuthorities::<T>::get(session)?;
+					Some((session, authorities.into_inner()))
+				})
+				.collect()
+		}
+	}
 	#[pallet::validate_unsigned]
 	impl<T: Config> ValidateUnsigned for Pallet<T> {
 		type Call = Call<T>;
@@ -205,17 +324,30 @@ pub mod pallet {
 		}
 	}
 	sp_api::decl_runtime_apis! {
+		/// Version 2 binds witnesses to the session they are made in.
+		#[api_version(2)]
 		pub trait ValidatedStreamsApi
 		{
 			/// Get event ids from a vector of extrinsics.
 			/// Meant to be used to get a list of all events present in a given block.
 			#[allow(clippy::ptr_arg)]
 			fn get_extrinsic_ids(extrinsics: &Vec<Block::Extrinsic>) -> Vec<H256>;
-			/// Create a new extrinsic for a given event id.
+			/// Create a new extrinsic for a given event id, from signatures over the bare id.
+			#[changed_in(2)]
 			fn create_unsigned_extrinsic(
 				event_id: H256,
 				event_proofs: Option<BTreeMap<Public, Signature>>,
 			) -> Block::Extrinsic;
+			/// Create a new extrinsic for a given event id.
+			fn create_unsigned_extrinsic(
+				event_id: H256,
+				event_proofs: Option<BTreeMap<Public, (SessionIndex, Signature)>>,
+			) -> Block::Extrinsic;
+			/// The current session.
+			fn current_session() -> SessionIndex;
+			/// The previous sessions whose witnesses are still accepted, oldest first, with their
+			/// authorities.
+			fn previous_sessions() -> Vec<(SessionIndex, Vec<Public>)>;
 		}
 	}
 }
@@ -0,0 +1,74 @@
+//! Storage migrations of the pallet, to be added to the `Executive` of the runtimes using it.
+
+use crate::{Config, Pallet};
+use frame_support::{
+	traits::{Get, OnRuntimeUpgrade, StorageVersion},
+	weights::Weight,
+};
+use sp_std::marker::PhantomData;
+
+/// Version 1 binds witnesses to the session they are made in, so the proofs kept on chain carry
+/// their session next to their signature.
+pub mod v1 {
+	use super::*;
+	#[cfg(not(feature = "off-chain-proofs"))]
+	use crate::pallet::{OnStreams, ProofsMap};
+	#[cfg(not(feature = "off-chain-proofs"))]
+	use frame_support::BoundedBTreeMap;
+	#[cfg(not(feature = "off-chain-proofs"))]
+	use sp_core::sr25519::{Public, Signature};
+	#[cfg(not(feature = "off-chain-proofs"))]
+	use sp_std::collections::btree_map::BTreeMap;
+
+	/// The proofs of an event before version 1: the bare signature of each authority.
+	#[cfg(not(feature = "off-chain-proofs"))]
+	type OldProofsMap<T> = BoundedBTreeMap<Public, Signature, <T as Config>::VSMaxAuthorities>;
+
+	/// Tags the proofs of the events validated before the upgrade with the genesis session, the
+	/// one the pallet starts counting from. Their signatures stay over the unversioned payload, and
+	/// are kept as a record only: the events they prove are validated already.
+	pub struct MigrateToV1<T>(PhantomData<T>);
+
+	impl<T: Config> MigrateToV1<T> {
+		/// Tags every proof of [OnStreams] with the genesis session, returning how many events
+		/// were migrated.
+		#[cfg(not(feature = "off-chain-proofs"))]
+		fn translate_proofs() -> u64 {
+			let mut translated = 0;
+			OnStreams::<T>::translate_values::<OldProofsMap<T>, _>(|proofs| {
+				translated += 1;
+				let proofs = proofs.into_inner().into_iter();
+				let proofs: BTreeMap<_, _> =
+					proofs.map(|(key, signature)| (key, (0, signature))).collect();
+				ProofsMap::<T>::try_from(proofs).ok()
+			});
+			translated
+		}
+
+		/// Events are only marked as validated on chain, without their proofs.
+		#[cfg(feature = "off-chain-proofs")]
+		fn translate_proofs() -> u64 {
+			0
+		}
+	}
+
+	impl<T: Config> OnRuntimeUpgrade for MigrateToV1<T> {
+		fn on_runtime_upgrade() -> Weight {
+			if StorageVersion::get::<Pallet<T>>() >= 1 {
+				return T::DbWeight::get().reads(1)
+			}
+			let translated = Self::translate_proofs();
+			StorageVersion::new(1).put::<Pallet<T>>();
+			T::DbWeight::get().reads_writes(translated + 1, translated + 1)
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn post_upgrade(_state: sp_std::vec::Vec<u8>) -> Result<(), &'static str> {
+			frame_support::ensure!(
+				StorageVersion::get::<Pallet<T>>() == 1,
+				"The storage version was not bumped to 1"
+			);
+			Ok(())
+		}
+	}
+}
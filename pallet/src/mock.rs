@@ -79,6 +79,8 @@ impl pallet_validated_streams::Config for Test {
 
 	type VSMaxAuthorities = ConstU32<32>;
 
+	type SessionGraceWindow = ConstU32<2>;
+
 	fn authorities() -> BoundedVec<Self::VSAuthorityId, Self::VSMaxAuthorities> {
 		get_pairs(PAIRS.lock().unwrap().as_mut(), AuthoritiesCount::get())
 			.map(|pair| AuraId::from_slice(pair.as_slice()).unwrap())
@@ -95,28 +97,37 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
 
 #[cfg(not(feature = "off-chain-proofs"))]
 pub mod onchain_mod {
-	use crate::mock::*;
+	use crate::{
+		mock::*,
+		payload::{witness_payload, SessionIndex},
+	};
 	pub use crate::Config;
 	pub use frame_support::BoundedBTreeMap;
 	pub use sp_core::{crypto::CryptoTypePublicPair, sr25519::Signature};
 	use std::collections::BTreeMap;
-	pub fn proofs(
-		event_id: &H256,
-	) -> BoundedBTreeMap<Public, Signature, <Test as Config>::VSMaxAuthorities> {
+
+	/// The proofs of an event, as passed to the pallet.
+	pub type Proofs =
+		BoundedBTreeMap<Public, (SessionIndex, Signature), <Test as Config>::VSMaxAuthorities>;
+
+	pub fn proofs(event_id: &H256) -> Proofs {
 		proofs_n(event_id, AuthoritiesCount::get())
 	}
 
-	pub fn proofs_n(
-		event_id: &H256,
-		count: u16,
-	) -> BoundedBTreeMap<Public, Signature, <Test as Config>::VSMaxAuthorities> {
+	pub fn proofs_n(event_id: &H256, count: u16) -> Proofs {
+		session_proofs(event_id, count, 0)
+	}
+
+	/// Proofs of the event by the first `count` authorities, made in the given session.
+	pub fn session_proofs(event_id: &H256, count: u16, session: SessionIndex) -> Proofs {
 		get_pairs(PAIRS.lock().unwrap().as_mut(), count)
 			.map(|key| {
+				let payload = witness_payload(event_id, session);
 				let signature = KEYSTORE
-					.sign_with(AURA, &CryptoTypePublicPair::from(key), event_id.as_bytes())
+					.sign_with(AURA, &CryptoTypePublicPair::from(key), &payload)
 					.unwrap()
 					.unwrap();
-				(*key, signature.as_slice().try_into().unwrap())
+				(*key, (session, signature.as_slice().try_into().unwrap()))
 			})
 			.collect::<BTreeMap<_, _>>()
 			.try_into()
@@ -0,0 +1,38 @@
+//! The canonical construction of the bytes validators sign when witnessing an event, and of the
+//! id of an event from its data.
+//!
+//! Both the signing side (the validators' keystores) and every verifying side (gossip,
+//! block import, and the pallet itself) must go through [witness_payload]; any change to it is a
+//! consensus-breaking change, which the golden vectors in the tests are there to catch. Likewise,
+//! the node and its clients derive event ids from their data with [event_id] only, so that the id
+//! of the same data never differs from one system to another.
+//!
+//! The payload starts with [WITNESS_PAYLOAD_VERSION], so that a signature over one layout never
+//! verifies against another. The unversioned layout, the bare event id, is no longer accepted
+//! anywhere: its signatures were valid forever, while version 1 binds every signature to the
+//! [SessionIndex] it was made in.
+
+use sp_core::H256;
+use sp_std::vec::Vec;
+
+#[cfg(test)]
+pub mod tests;
+
+/// The index of a session of the validators, increased every time the set of authorities
+/// changes. See [crate::pallet::CurrentSession].
+pub type SessionIndex = u32;
+
+/// The version of the layout of [witness_payload], its first byte.
+pub const WITNESS_PAYLOAD_VERSION: u8 = 1;
+
+/// Returns the canonical id of the event with the given data: its blake2-256 hash, with no
+/// prefix nor encoding of the data.
+pub fn event_id(data: &[u8]) -> H256 {
+	H256(sp_io::hashing::blake2_256(data))
+}
+
+/// Returns the bytes a validator signs to attest that it has witnessed the given event during the
+/// given session: the version, the event id, and the little-endian session index.
+pub fn witness_payload(event_id: &H256, session: SessionIndex) -> Vec<u8> {
+	[&[WITNESS_PAYLOAD_VERSION][..], event_id.as_bytes(), &session.to_le_bytes()].concat()
+}
@@ -0,0 +1,136 @@
+use super::{event_id, witness_payload, SessionIndex, WITNESS_PAYLOAD_VERSION};
+use hex_literal::hex;
+use proptest::prelude::*;
+use sp_core::{
+	sr25519::{Pair, Public, Signature},
+	Pair as PairT, H256,
+};
+
+/// A fixed event and session, along with the payload validators must sign for them and a signature
+/// over that payload by the [SR25519_SEED] key.
+struct GoldenVector {
+	event_id: [u8; 32],
+	session: SessionIndex,
+	payload: &'static [u8],
+	signature: [u8; 64],
+}
+
+/// Mini secret key of the sr25519 vectors. It is the first RFC 8032 test secret, whose sr25519
+/// public key is also pinned by sp-core's own tests.
+const SR25519_SEED: [u8; 32] =
+	hex!("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60");
+const SR25519_PUBLIC: [u8; 32] =
+	hex!("44a996beb1eef7bdcab976ab6d2ca26104834164ecf28fb375600576fcc6eb0f");
+
+/// sr25519 signatures are randomized, so instead of comparing against fresh signatures, the
+/// checked-in ones must keep verifying against the payloads. They were made by sp-core's
+/// `Pair::from_seed(&SR25519_SEED).sign(&witness_payload(..))`.
+const SR25519_VECTORS: [GoldenVector; 2] = [
+	GoldenVector {
+		event_id: [0; 32],
+		session: 0,
+		payload: &hex!(
+			"01"
+			"0000000000000000000000000000000000000000000000000000000000000000"
+			"00000000"
+		),
+		signature: hex!(
+			"d40373fab2780461771425111e5e3a35964d10bbcc610aa35301476b1c1fb30a"
+			"9189d41eb8f95c77085691e322a04273ab16199bbbc5361138784a302ffc0e86"
+		),
+	},
+	GoldenVector {
+		event_id: hex!("0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20"),
+		session: 7,
+		payload: &hex!(
+			"01"
+			"0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20"
+			"07000000"
+		),
+		signature: hex!(
+			"426fc51dde66bd6db779c2d56377f2ab6545b698e52c62bdde3694c78c49614e"
+			"5105380a19c4824b8adb0ba9f8b9a58dda794ed4cffa764edcc7a93b2d45c285"
+		),
+	},
+];
+
+#[test]
+fn test_sr25519_golden_key() {
+	assert_eq!(Pair::from_seed(&SR25519_SEED).public(), Public::from_raw(SR25519_PUBLIC));
+}
+
+#[test]
+fn test_sr25519_golden_payloads() {
+	let public = Public::from_raw(SR25519_PUBLIC);
+	for vector in &SR25519_VECTORS {
+		let payload = witness_payload(&H256(vector.event_id), vector.session);
+		assert_eq!(payload, vector.payload);
+		assert_eq!(payload[0], WITNESS_PAYLOAD_VERSION);
+		assert!(Pair::verify(&Signature::from_raw(vector.signature), &payload, &public));
+	}
+}
+
+#[test]
+fn test_sr25519_golden_signatures_are_payload_bound() {
+	let public = Public::from_raw(SR25519_PUBLIC);
+	let [first, second] = &SR25519_VECTORS;
+	assert!(!Pair::verify(&Signature::from_raw(first.signature), second.payload, &public));
+	assert!(!Pair::verify(&Signature::from_raw(second.signature), first.payload, &public));
+}
+
+#[test]
+fn test_sr25519_golden_signatures_are_session_bound() {
+	let public = Public::from_raw(SR25519_PUBLIC);
+	for vector in &SR25519_VECTORS {
+		let signature = Signature::from_raw(vector.signature);
+		let event_id = H256(vector.event_id);
+		let other_session = witness_payload(&event_id, vector.session + 1);
+		assert!(!Pair::verify(&signature, other_session, &public));
+		// Nor does it verify against the unversioned payload, the bare event id
+		assert!(!Pair::verify(&signature, vector.event_id, &public));
+	}
+}
+
+/// Fixed event data along with their canonical ids, which are plain blake2-256 hashes.
+const EVENT_ID_VECTORS: [(&[u8], [u8; 32]); 3] = [
+	(b"", hex!("0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8")),
+	(b"abc", hex!("bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319")),
+	(
+		b"validated streams",
+		hex!("9e4158c2bf970f070a4b2205b11de0a20a7a31308e444ec1bbddcacf5851e8d3"),
+	),
+];
+
+#[test]
+fn test_event_id_golden_vectors() {
+	for (data, expected) in EVENT_ID_VECTORS {
+		assert_eq!(event_id(data), H256(expected), "{data:?}");
+	}
+}
+
+proptest! {
+	#[test]
+	fn test_witness_payload_is_injective(
+		a in any::<([u8; 32], SessionIndex)>(),
+		b in any::<([u8; 32], SessionIndex)>(),
+	) {
+		prop_assume!(a != b);
+		prop_assert_ne!(witness_payload(&H256(a.0), a.1), witness_payload(&H256(b.0), b.1));
+	}
+
+	#[test]
+	fn test_witness_payload_sign_verify(
+		seed in any::<[u8; 32]>(),
+		event_id in any::<[u8; 32]>(),
+		other_event_id in any::<[u8; 32]>(),
+		session in any::<SessionIndex>(),
+	) {
+		let pair = Pair::from_seed(&seed);
+		let payload = |event_id| witness_payload(&H256(event_id), session);
+		let signature = pair.sign(&payload(event_id));
+		prop_assert!(Pair::verify(&signature, payload(event_id), &pair.public()));
+		if other_event_id != event_id {
+			prop_assert!(!Pair::verify(&signature, payload(other_event_id), &pair.public()));
+		}
+	}
+}
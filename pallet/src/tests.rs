@@ -65,7 +65,7 @@ fn it_adds_event() {
 #[cfg(not(feature = "off-chain-proofs"))]
 #[test]
 fn it_validates_event() {
-	use crate::mock::onchain_mod::*;
+	use crate::{mock::onchain_mod::*, payload::witness_payload};
 	new_test_ext().execute_with(|| {
 		// Go past genesis block so events get deposited
 		System::set_block_number(1);
@@ -95,6 +95,7 @@ fn it_validates_event() {
 		*proofs_map
 			.get_mut(&proofs_map.iter().next().unwrap().0.clone())
 			.unwrap()
+			.1
 			.as_mut()
 			.get_mut(0)
 			.unwrap() += 1;
@@ -111,17 +112,20 @@ fn it_validates_event() {
 		proofs_map
 			.try_insert(
 				unrecognized_authority,
-				KEYSTORE
-					.sign_with(
-						AURA,
-						&CryptoTypePublicPair::from(unrecognized_authority),
-						event_id.as_bytes(),
-					)
-					.unwrap()
-					.unwrap()
-					.as_slice()
-					.try_into()
-					.unwrap(),
+				(
+					0,
+					KEYSTORE
+						.sign_with(
+							AURA,
+							&CryptoTypePublicPair::from(unrecognized_authority),
+							&witness_payload(&event_id, 0),
+						)
+						.unwrap()
+						.unwrap()
+						.as_slice()
+						.try_into()
+						.unwrap(),
+				),
 			)
 			.unwrap();
 		assert_err!(
@@ -183,3 +187,123 @@ fn it_computes_target_correctly(#[case] total: u16, #[case] target: u16) {
 		));
 	})
 }
+
+#[cfg(not(feature = "off-chain-proofs"))]
+#[test]
+fn it_accepts_proofs_only_within_the_session_grace_window() {
+	use crate::{mock::onchain_mod::*, CurrentSession};
+	use frame_support::traits::Hooks;
+	new_test_ext().execute_with(|| {
+		// Session 0 with 4 authorities, then alternating between 5 and 4 of them up to session 5
+		for block in 1..=6 {
+			AuthoritiesCount::set(&if block % 2 == 0 { 5 } else { 4 });
+			System::set_block_number(block);
+			ValidatedStreams::on_initialize(block);
+		}
+		assert_eq!(CurrentSession::<Test>::get(), 5);
+		// Nothing changed, so neither does the session
+		ValidatedStreams::on_initialize(7);
+		assert_eq!(ValidatedStreams::current_session(), 5);
+		let kept: Vec<_> = ValidatedStreams::previous_sessions().iter().map(|(s, _)| *s).collect();
+		assert_eq!(kept, vec![3, 4]);
+
+		// Session 0 (N-5) is long past the grace window of 2, and a future session is unknown
+		for session in [0, 2, 6] {
+			let event_id = H256::repeat_byte(session as u8);
+			assert_err!(
+				ValidatedStreams::validate_event(
+					RuntimeOrigin::none(),
+					event_id,
+					Some(session_proofs(&event_id, 4, session))
+				),
+				pallet_validated_streams::Error::<Test>::StaleSession
+			);
+		}
+		// The fifth authority was not one of those of session 4
+		let event_id = H256::repeat_byte(4);
+		assert_err!(
+			ValidatedStreams::validate_event(
+				RuntimeOrigin::none(),
+				event_id,
+				Some(session_proofs(&event_id, 5, 4))
+			),
+			pallet_validated_streams::Error::<Test>::UnrecognizedAuthority
+		);
+		// While session 4 (N-1) is still within it, and its 4 authorities reach the target of 4
+		assert_ok!(ValidatedStreams::validate_event(
+			RuntimeOrigin::none(),
+			event_id,
+			Some(session_proofs(&event_id, 4, 4))
+		));
+		// And so is the current session, of course
+		let event_id = H256::repeat_byte(5);
+		assert_ok!(ValidatedStreams::validate_event(
+			RuntimeOrigin::none(),
+			event_id,
+			Some(session_proofs(&event_id, 5, 5))
+		));
+	})
+}
+
+#[cfg(not(feature = "off-chain-proofs"))]
+#[test]
+fn it_counts_only_the_proofs_of_current_authorities() {
+	use crate::mock::onchain_mod::*;
+	use frame_support::traits::Hooks;
+	new_test_ext().execute_with(|| {
+		// Session 0 with 5 authorities, then session 1 with 4 of them, whose target is 3
+		for (block, count) in [(1, 5), (2, 4)] {
+			AuthoritiesCount::set(&count);
+			System::set_block_number(block);
+			ValidatedStreams::on_initialize(block);
+		}
+		let event_id = H256::repeat_byte(1);
+		let current = session_proofs(&event_id, 4, 0);
+		let (former, proof) = session_proofs(&event_id, 5, 0)
+			.into_iter()
+			.find(|(key, _)| !current.contains_key(key))
+			.unwrap();
+		// The fifth authority witnessed the event in session 0, but is no longer one of them
+		let mut proofs = session_proofs(&event_id, 2, 1);
+		proofs.try_insert(former, proof).unwrap();
+		assert_err!(
+			ValidatedStreams::validate_event(RuntimeOrigin::none(), event_id, Some(proofs.clone())),
+			pallet_validated_streams::Error::<Test>::NotEnoughProofs
+		);
+		// While a third current authority reaches the target, whichever session it witnessed in
+		let (key, proof) = session_proofs(&event_id, 3, 0)
+			.into_iter()
+			.find(|(key, _)| !proofs.contains_key(key))
+			.unwrap();
+		proofs.try_insert(key, proof).unwrap();
+		assert_ok!(ValidatedStreams::validate_event(RuntimeOrigin::none(), event_id, Some(proofs)));
+	})
+}
+
+#[cfg(not(feature = "off-chain-proofs"))]
+#[test]
+fn it_migrates_proofs_to_sessions() {
+	use crate::{migrations::v1::MigrateToV1, mock::onchain_mod::*, pallet::OnStreams};
+	use frame_support::{
+		storage::unhashed,
+		traits::{OnRuntimeUpgrade, StorageVersion},
+	};
+	use sp_core::sr25519::Public;
+	use std::collections::BTreeMap;
+	new_test_ext().execute_with(|| {
+		StorageVersion::new(0).put::<ValidatedStreams>();
+		// Proofs of session 0, as they were kept before sessions: without their session
+		let event_id = H256::repeat_byte(0);
+		let proofs = proofs(&event_id);
+		let old: BTreeMap<Public, Signature> =
+			proofs.iter().map(|(key, (_, signature))| (*key, signature.clone())).collect();
+		unhashed::put(&OnStreams::<Test>::hashed_key_for(event_id), &old);
+
+		MigrateToV1::<Test>::on_runtime_upgrade();
+		assert_eq!(OnStreams::<Test>::get(event_id), Some(proofs.clone()));
+		assert_eq!(StorageVersion::get::<ValidatedStreams>(), 1);
+		// Migrating again does nothing
+		MigrateToV1::<Test>::on_runtime_upgrade();
+		assert_eq!(OnStreams::<Test>::get(event_id), Some(proofs));
+	})
+}
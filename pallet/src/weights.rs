@@ -31,21 +31,26 @@
 use frame_support::{traits::Get, weights::Weight};
 use sp_std::marker::PhantomData;
 pub trait WeightInfo {
-	fn validate_event() -> Weight;
+	fn validate_event(s: u32, ) -> Weight;
 }
 /// Weight functions for `pallet_validated_streams`.
 pub struct SubstrateWeight<T>(PhantomData<T>);
 impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 	/// Storage: ValidatedStreams Streams (r:1 w:1)
 	/// Proof: ValidatedStreams Streams (max_values: None, max_size: Some(52), added: 2527, mode: MaxEncodedLen)
-	fn validate_event() -> Weight {
+	/// Storage: Aura Authorities (r:1 w:0)
+	/// Storage: ValidatedStreams CurrentSession (r:1 w:0)
+	/// Storage: ValidatedStreams SessionAuthorities (r:2 w:0)
+	/// The range of component `s` is `[0, 2]`.
+	fn validate_event(s: u32, ) -> Weight {
 		// Proof Size summary in bytes:
 		//  Measured:  `6`
 		//  Estimated: `3517`
 		// Minimum execution time: 7_873_000 picoseconds.
 		Weight::from_parts(8_834_000, 0)
 			.saturating_add(Weight::from_parts(0, 3517))
-			.saturating_add(T::DbWeight::get().reads(1))
+			.saturating_add(T::DbWeight::get().reads(3))
+			.saturating_add(T::DbWeight::get().reads((1_u64).saturating_mul(s.into())))
 			.saturating_add(T::DbWeight::get().writes(1))
 	}
 }
@@ -8,6 +8,7 @@ include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
 
 use frame_support::BoundedVec;
 use pallet_grandpa::AuthorityId as GrandpaId;
+use pallet_validated_streams::payload::SessionIndex;
 use sp_api::impl_runtime_apis;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
 use sp_core::{
@@ -108,7 +109,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	//   `spec_version`, and `authoring_version` are the same between Wasm and native.
 	// This value is set to 100 to notify Polkadot-JS App (https://polkadot.js.org/apps) to use
 	//   the compatible custom types.
-	spec_version: 100,
+	spec_version: 102,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
 	transaction_version: 1,
@@ -274,6 +275,7 @@ impl pallet_validated_streams::Config for Runtime {
 	type WeightInfo = pallet_validated_streams::weights::SubstrateWeight<Runtime>;
 	type VSAuthorityId = AuraId;
 	type VSMaxAuthorities = ConstU32<32>;
+	type SessionGraceWindow = ConstU32<2>;
 	fn authorities() -> BoundedVec<Self::VSAuthorityId, Self::VSMaxAuthorities> {
 		Aura::authorities()
 	}
@@ -322,6 +324,8 @@ pub type UncheckedExtrinsic =
 	generic::UncheckedExtrinsic<Address, RuntimeCall, Signature, SignedExtra>;
 /// The payload being signed in transactions.
 pub type SignedPayload = generic::SignedPayload<RuntimeCall, SignedExtra>;
+/// The migrations to run on the next runtime upgrade.
+pub type Migrations = (pallet_validated_streams::migrations::v1::MigrateToV1<Runtime>,);
 /// Executive: handles dispatch to the various modules.
 pub type Executive = frame_executive::Executive<
 	Runtime,
@@ -329,6 +333,7 @@ pub type Executive = frame_executive::Executive<
 	frame_system::ChainContext<Runtime>,
 	Runtime,
 	AllPalletsWithSystem,
+	Migrations,
 >;
 
 #[cfg(feature = "runtime-benchmarks")]
@@ -408,7 +413,7 @@ impl_runtime_apis! {
 			event_proofs: Option<
 				BTreeMap<
 					Public,
-					SrSignature
+					(SessionIndex, SrSignature)
 				>,
 			>,
 		) -> <Block as BlockT>::Extrinsic {
@@ -422,6 +427,12 @@ impl_runtime_apis! {
 				.into(),
 			}
 		}
+		fn current_session() -> SessionIndex {
+			ValidatedStreams::current_session()
+		}
+		fn previous_sessions() -> Vec<(SessionIndex, Vec<Public>)> {
+			ValidatedStreams::previous_sessions()
+		}
 	}
 	impl sp_transaction_pool::runtime_api::TaggedTransactionQueue<Block> for Runtime {
 		fn validate_transaction(
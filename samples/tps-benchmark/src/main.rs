@@ -8,7 +8,7 @@ use std::{
 use tonic::{transport::Channel, Request};
 
 mod validated_streams {
-	tonic::include_proto!("validated_streams");
+	tonic::include_proto!("validated_streams.v1");
 }
 use validated_streams::{
 	streams_client::StreamsClient, ValidatedEventsRequest, WitnessEventRequest,
@@ -29,7 +29,7 @@ fn event_id_to_event_num(event_id: &[u8]) -> u32 {
 }
 
 async fn wait_validators(mut client: StreamsClient<Channel>) {
-	let request = WitnessEventRequest { event_id: event_num_to_event_id(0) };
+	let request = WitnessEventRequest { event_id: event_num_to_event_id(0), ..Default::default() };
 	loop {
 		let request = Request::new(request.clone());
 		if client.witness_event(request).await.is_err() {
@@ -43,7 +43,8 @@ async fn wait_validators(mut client: StreamsClient<Channel>) {
 async fn send_events(client: StreamsClient<Channel>, from_num: u32, to_num: u32) {
 	let mut events = Vec::new();
 	for i in from_num + 1..to_num + 1 {
-		events.push(WitnessEventRequest { event_id: event_num_to_event_id(i) });
+		let event_id = event_num_to_event_id(i);
+		events.push(WitnessEventRequest { event_id, ..Default::default() });
 	}
 	stream::iter(events)
 		.map(|event| {